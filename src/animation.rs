@@ -4,17 +4,54 @@ use std::time::Duration;
 use num_rational::Ratio;
 
 use crate::error::ImageResult;
-use crate::RgbaImage;
+use crate::math::Rect;
+use crate::{Rgba, RgbaImage};
 
 /// An implementation dependent iterator, reading the frames as requested
 pub struct Frames<'a> {
     iterator: Box<dyn Iterator<Item = ImageResult<Frame>> + 'a>,
+    canvas: Option<(u32, u32)>,
+    background: Option<Rgba<u8>>,
 }
 
 impl<'a> Frames<'a> {
     /// Creates a new `Frames` from an implementation specific iterator.
     pub fn new(iterator: Box<dyn Iterator<Item = ImageResult<Frame>> + 'a>) -> Self {
-        Frames { iterator }
+        Frames {
+            iterator,
+            canvas: None,
+            background: None,
+        }
+    }
+
+    /// Records the size of the canvas that the frames are placed on.
+    pub fn with_canvas(mut self, width: u32, height: u32) -> Self {
+        self.canvas = Some((width, height));
+        self
+    }
+
+    /// Records the background color that the file specifies for the canvas.
+    pub fn with_background(mut self, color: Rgba<u8>) -> Self {
+        self.background = Some(color);
+        self
+    }
+
+    /// The width and height of the canvas, if the decoder knows them.
+    ///
+    /// Frames returned by [`AnimationDecoder::into_raw_frames`] only cover part of the canvas,
+    /// at the offset given by their `left` and `top`.
+    ///
+    /// [`AnimationDecoder::into_raw_frames`]: ../trait.AnimationDecoder.html#method.into_raw_frames
+    pub fn canvas_dimensions(&self) -> Option<(u32, u32)> {
+        self.canvas
+    }
+
+    /// The background color of the canvas, if the file specifies one.
+    ///
+    /// Decoders do not fill the canvas with it when compositing frames, as browsers do not
+    /// either, but editors may want to show or preserve it.
+    pub fn background_color(&self) -> Option<Rgba<u8>> {
+        self.background
     }
 
     /// Steps through the iterator from the current frame until the end and pushes each frame into
@@ -44,6 +81,8 @@ pub struct Frame {
     /// y offset
     top: u32,
     buffer: RgbaImage,
+    /// The region of the canvas that changed since the previous frame, if known.
+    dirty: Option<Rect>,
 }
 
 /// The delay of a frame relative to the previous one.
@@ -60,6 +99,7 @@ impl Frame {
             left: 0,
             top: 0,
             buffer,
+            dirty: None,
         }
     }
 
@@ -70,9 +110,16 @@ impl Frame {
             left,
             top,
             buffer,
+            dirty: None,
         }
     }
 
+    /// Records the region of the canvas that changed since the previous frame.
+    pub fn with_dirty_rect(mut self, rect: Rect) -> Frame {
+        self.dirty = Some(rect);
+        self
+    }
+
     /// Delay of this frame
     pub fn delay(&self) -> Delay {
         self.delay
@@ -102,6 +149,115 @@ impl Frame {
     pub fn top(&self) -> u32 {
         self.top
     }
+
+    /// The region of the canvas that changed since the previous frame.
+    ///
+    /// Frames composited by [`AnimationDecoder::into_frames`] cover the whole canvas, but often
+    /// only a small part of it changes, which players can upload to a texture on its own. It
+    /// includes the area that the previous frame was disposed of. The first frame changes the
+    /// whole canvas. Frames that the decoder did not record a region for report the area they
+    /// cover at their offset.
+    ///
+    /// [`AnimationDecoder::into_frames`]: ../trait.AnimationDecoder.html#tymethod.into_frames
+    pub fn dirty_rect(&self) -> Rect {
+        self.dirty.unwrap_or(Rect {
+            x: self.left,
+            y: self.top,
+            width: self.buffer.width(),
+            height: self.buffer.height(),
+        })
+    }
+}
+
+/// Tracks the region of a composited canvas that changes from one frame to the next.
+#[allow(dead_code)]
+// When no animated formats are enabled
+pub(crate) struct DirtyRegion {
+    width: u32,
+    height: u32,
+    /// The area of the previous frame if it is disposed of, which changes again.
+    disposed: Rect,
+}
+
+#[allow(dead_code)]
+// When no animated formats are enabled
+impl DirtyRegion {
+    pub(crate) fn new(width: u32, height: u32) -> Self {
+        DirtyRegion {
+            width,
+            height,
+            // The first frame is drawn onto a cleared canvas.
+            disposed: Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            },
+        }
+    }
+
+    /// The dirty rect of the next frame, which is drawn onto `area` and cleared or restored
+    /// afterwards if `disposed`.
+    pub(crate) fn next_frame(&mut self, area: Rect, disposed: bool) -> Rect {
+        let area = area.clip(self.width, self.height);
+        let dirty = area.union(self.disposed);
+        self.disposed = if disposed {
+            area
+        } else {
+            Rect {
+                x: 0,
+                y: 0,
+                width: 0,
+                height: 0,
+            }
+        };
+        dirty
+    }
+}
+
+/// Map the frames of an animation, or anything derived from them, with `f` and pass the results
+/// to `sink` in order.
+///
+/// With the `rayon` feature, the items are taken in batches that are mapped on the threads of the
+/// global thread pool. This lets animation encoders quantize and compress several frames at once
+/// while still writing them in order. The next batch is only taken once the previous one is
+/// written, which bounds the number of frames held in memory. The first error is returned after
+/// the results of all items before it have been passed to `sink`.
+#[allow(dead_code)]
+// When no animated formats are enabled
+pub(crate) fn map_frames_in_order<I, T, U, F, S>(items: I, f: F, mut sink: S) -> ImageResult<()>
+where
+    I: IntoIterator<Item = T>,
+    T: Send,
+    U: Send,
+    F: Fn(T) -> ImageResult<U> + Sync,
+    S: FnMut(U) -> ImageResult<()>,
+{
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
+
+        let batch_size = 2 * rayon::current_num_threads();
+        let mut items = items.into_iter();
+        loop {
+            let batch: Vec<T> = items.by_ref().take(batch_size).collect();
+            if batch.is_empty() {
+                return Ok(());
+            }
+            let results: Vec<ImageResult<U>> = batch.into_par_iter().map(&f).collect();
+            for result in results {
+                sink(result?)?;
+            }
+        }
+    }
+
+    #[cfg(not(feature = "rayon"))]
+    {
+        for item in items {
+            sink(f(item)?)?;
+        }
+        Ok(())
+    }
 }
 
 impl Delay {
@@ -277,9 +433,58 @@ impl From<Delay> for Duration {
     }
 }
 
+/// Options for [`save_animation`].
+///
+/// Construct it with `Default` and set the fields of interest.
+///
+/// [`save_animation`]: fn.save_animation.html
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AnimationOptions {
+    /// How often the animation is played, `0` repeats it forever. Defaults to `0`.
+    pub loop_count: u32,
+
+    /// The speed of the color quantization of GIF frames, from 1 to 30. Lower values are slower
+    /// but produce better palettes. Defaults to 1, as `GifEncoder::new` does.
+    pub quantization_speed: Option<i32>,
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Delay, Duration, Ratio};
+    use super::{map_frames_in_order, Delay, Duration, Ratio};
+    use crate::error::{ImageError, ParameterError, ParameterErrorKind};
+
+    #[test]
+    fn maps_frames_in_order() {
+        let mut mapped = Vec::new();
+        map_frames_in_order(
+            0..100u32,
+            |i| Ok(i * 2),
+            |i| {
+                mapped.push(i);
+                Ok(())
+            },
+        )
+        .unwrap();
+        assert_eq!(mapped, (0..100).map(|i| i * 2).collect::<Vec<_>>());
+
+        let mut mapped = Vec::new();
+        let result = map_frames_in_order(
+            0..100u32,
+            |i| match i {
+                37 => Err(ImageError::Parameter(ParameterError::from_kind(
+                    ParameterErrorKind::NoMoreData,
+                ))),
+                i => Ok(i),
+            },
+            |i| {
+                mapped.push(i);
+                Ok(())
+            },
+        );
+        assert!(result.is_err());
+        assert_eq!(mapped, (0..37).collect::<Vec<_>>());
+    }
 
     #[test]
     fn simple() {
@@ -0,0 +1,201 @@
+//! Contains the `ArrayImage` struct, a small image with dimensions fixed at compile time.
+use num_traits::Zero;
+
+use crate::buffer_::ImageBuffer;
+use crate::image::{GenericImage, GenericImageView};
+use crate::traits::Pixel;
+
+/// An image of `W` by `H` pixels that is stored inline, without any heap allocation.
+///
+/// This is meant for small images whose size is known at compile time, such as cursors, icons,
+/// structuring elements for morphological operations or test fixtures. The pixels are stored
+/// row-major as `[[P; W]; H]`, so the whole image lives on the stack or inside of its owner.
+/// Large dimensions should use an [`ImageBuffer`] instead.
+///
+/// ```
+/// use image::{ArrayImage, GenericImageView, Luma};
+///
+/// // A cross shaped structuring element.
+/// let cross = ArrayImage::<Luma<u8>, 3, 3>::from_fn(|x, y| {
+///     Luma([if x == 1 || y == 1 { 255 } else { 0 }])
+/// });
+/// assert_eq!(cross.dimensions(), (3, 3));
+/// assert_eq!(cross.get_pixel(1, 0), Luma([255]));
+/// ```
+///
+/// [`ImageBuffer`]: struct.ImageBuffer.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ArrayImage<P: Pixel, const W: usize, const H: usize> {
+    pixels: [[P; W]; H],
+}
+
+impl<P: Pixel, const W: usize, const H: usize> ArrayImage<P, W, H> {
+    /// Creates a new image with all pixels set to zero.
+    pub fn new() -> Self {
+        let zero = [<P::Subpixel as Zero>::zero(); u8::MAX as usize];
+        let zero = *P::from_slice(&zero[..usize::from(P::CHANNEL_COUNT)]);
+        Self::from_pixel(zero)
+    }
+
+    /// Creates a new image with all pixels set to `pixel`.
+    pub fn from_pixel(pixel: P) -> Self {
+        ArrayImage {
+            pixels: [[pixel; W]; H],
+        }
+    }
+
+    /// Creates a new image by repeated application of the supplied function.
+    ///
+    /// The arguments to the function are the pixel's x and y coordinates.
+    pub fn from_fn<F>(mut f: F) -> Self
+    where
+        F: FnMut(u32, u32) -> P,
+    {
+        let mut image = Self::new();
+        for (y, row) in image.pixels.iter_mut().enumerate() {
+            for (x, pixel) in row.iter_mut().enumerate() {
+                *pixel = f(x as u32, y as u32);
+            }
+        }
+        image
+    }
+
+    /// Creates an image from its rows of pixels.
+    pub fn from_rows(pixels: [[P; W]; H]) -> Self {
+        ArrayImage { pixels }
+    }
+
+    /// Returns the rows of pixels of this image.
+    pub fn rows(&self) -> &[[P; W]; H] {
+        &self.pixels
+    }
+
+    /// Returns the rows of pixels of this image for modification.
+    pub fn rows_mut(&mut self) -> &mut [[P; W]; H] {
+        &mut self.pixels
+    }
+
+    /// Consumes the image and returns its rows of pixels.
+    pub fn into_rows(self) -> [[P; W]; H] {
+        self.pixels
+    }
+
+    /// Copies the pixels into a heap allocated [`ImageBuffer`].
+    ///
+    /// [`ImageBuffer`]: struct.ImageBuffer.html
+    pub fn to_image_buffer(&self) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        ImageBuffer::from_fn(W as u32, H as u32, |x, y| {
+            self.pixels[y as usize][x as usize]
+        })
+    }
+
+    fn pixel_ref(&self, x: u32, y: u32) -> &P {
+        match self
+            .pixels
+            .get(y as usize)
+            .and_then(|row| row.get(x as usize))
+        {
+            None => panic!("Image index {:?} out of bounds {:?}", (x, y), (W, H)),
+            Some(pixel) => pixel,
+        }
+    }
+
+    fn pixel_mut(&mut self, x: u32, y: u32) -> &mut P {
+        match self
+            .pixels
+            .get_mut(y as usize)
+            .and_then(|row| row.get_mut(x as usize))
+        {
+            None => panic!("Image index {:?} out of bounds {:?}", (x, y), (W, H)),
+            Some(pixel) => pixel,
+        }
+    }
+}
+
+impl<P: Pixel, const W: usize, const H: usize> Default for ArrayImage<P, W, H> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<P: Pixel, const W: usize, const H: usize> GenericImageView for ArrayImage<P, W, H> {
+    type Pixel = P;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (W as u32, H as u32)
+    }
+
+    fn bounds(&self) -> (u32, u32, u32, u32) {
+        (0, 0, W as u32, H as u32)
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> P {
+        *self.pixel_ref(x, y)
+    }
+}
+
+impl<P: Pixel, const W: usize, const H: usize> GenericImage for ArrayImage<P, W, H> {
+    fn get_pixel_mut(&mut self, x: u32, y: u32) -> &mut P {
+        self.pixel_mut(x, y)
+    }
+
+    fn put_pixel(&mut self, x: u32, y: u32, pixel: P) {
+        *self.pixel_mut(x, y) = pixel
+    }
+
+    /// Put a pixel at location (x, y), taking into account alpha channels
+    ///
+    /// DEPRECATED: This method will be removed. Blend the pixel directly instead.
+    fn blend_pixel(&mut self, x: u32, y: u32, p: P) {
+        self.pixel_mut(x, y).blend(&p)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ArrayImage;
+    use crate::color::{Luma, Rgba};
+    use crate::image::{GenericImage, GenericImageView};
+    use crate::imageops;
+
+    #[test]
+    fn new_is_zeroed() {
+        let image = ArrayImage::<Rgba<u8>, 4, 2>::new();
+        assert_eq!(image.dimensions(), (4, 2));
+        assert!(image.pixels().all(|(_, _, p)| p == Rgba([0, 0, 0, 0])));
+    }
+
+    #[test]
+    fn put_and_get() {
+        let mut image = ArrayImage::<Luma<u16>, 3, 5>::new();
+        image.put_pixel(2, 4, Luma([1000]));
+        assert_eq!(image.get_pixel(2, 4), Luma([1000]));
+        assert_eq!(image.rows()[4][2], Luma([1000]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn get_out_of_bounds() {
+        let image = ArrayImage::<Luma<u8>, 3, 3>::new();
+        image.get_pixel(3, 0);
+    }
+
+    #[test]
+    fn matches_image_buffer() {
+        let image = ArrayImage::<Luma<u8>, 4, 3>::from_fn(|x, y| Luma([(x * 10 + y) as u8]));
+        let buffer = image.to_image_buffer();
+        assert_eq!(buffer.dimensions(), (4, 3));
+        for (x, y, p) in image.pixels() {
+            assert_eq!(*buffer.get_pixel(x, y), p);
+        }
+    }
+
+    #[test]
+    fn usable_with_imageops() {
+        let mut canvas = ArrayImage::<Luma<u8>, 4, 4>::new();
+        let dot = ArrayImage::<Luma<u8>, 2, 2>::from_pixel(Luma([7]));
+        imageops::replace(&mut canvas, &dot, 1, 1);
+        assert_eq!(canvas.get_pixel(2, 2), Luma([7]));
+        assert_eq!(canvas.get_pixel(3, 3), Luma([0]));
+    }
+}
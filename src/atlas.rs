@@ -0,0 +1,737 @@
+//! Packing of many small images into a few large texture atlases (sprite sheets).
+//!
+//! [`pack`] arranges a set of images on as few atlases as possible and composites them, while
+//! [`layout`] only computes the arrangement from the image sizes. Both describe the result with an
+//! [`AtlasLayout`], which can be serialized with the `serde` feature to ship it alongside the
+//! atlases.
+//!
+//! The inverse, cutting a sprite sheet into its frames, is done by [`slice_grid`] for sheets with
+//! equally sized frames and by [`slice`] for sheets described by an [`AtlasLayout`]. Both return
+//! borrowed views, owned frames are obtained with `SubImage::to_image`.
+//!
+//! Animations, such as the frames of a GIF image, are packed together with the timing of their
+//! frames by [`pack_animation`], and turned back into frames by [`unpack_animation`].
+//!
+//! ```
+//! use image::atlas::{pack, PackOptions};
+//! use image::{Rgba, RgbaImage};
+//!
+//! let sprites = vec![
+//!     RgbaImage::from_pixel(30, 20, Rgba([255, 0, 0, 255])),
+//!     RgbaImage::from_pixel(10, 40, Rgba([0, 255, 0, 255])),
+//! ];
+//! let options = PackOptions {
+//!     padding: 1,
+//!     ..PackOptions::default()
+//! };
+//! let (atlases, layout) = pack(&sprites, &options).unwrap();
+//! assert_eq!(atlases.len(), 1);
+//!
+//! let placement = layout.placements[1];
+//! assert_eq!((placement.width, placement.height), (10, 40));
+//! assert_eq!(
+//!     atlases[placement.atlas].get_pixel(placement.x, placement.y),
+//!     &Rgba([0, 255, 0, 255])
+//! );
+//! ```
+//!
+//! [`pack`]: fn.pack.html
+//! [`layout`]: fn.layout.html
+//! [`slice_grid`]: fn.slice_grid.html
+//! [`slice`]: fn.slice.html
+//! [`AtlasLayout`]: struct.AtlasLayout.html
+//! [`pack_animation`]: fn.pack_animation.html
+//! [`unpack_animation`]: fn.unpack_animation.html
+
+use crate::animation::{Delay, Frame};
+use crate::buffer_::{ImageBuffer, RgbaImage};
+use crate::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use crate::image::{GenericImage, GenericImageView, SubImage};
+use crate::traits::Pixel;
+
+/// Options for packing images into atlases.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct PackOptions {
+    /// The maximum width of each atlas.
+    pub max_width: u32,
+    /// The maximum height of each atlas.
+    pub max_height: u32,
+    /// The number of empty pixels between two images and between the images and the border of
+    /// the atlas. This avoids bleeding of neighbouring images when sampling with filtering.
+    pub padding: u32,
+    /// Whether the dimensions of each atlas are rounded up to powers of two.
+    pub power_of_two: bool,
+}
+
+impl Default for PackOptions {
+    fn default() -> Self {
+        PackOptions {
+            max_width: 2048,
+            max_height: 2048,
+            padding: 0,
+            power_of_two: false,
+        }
+    }
+}
+
+/// The location of one packed image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Placement {
+    /// The index of the atlas that holds the image.
+    pub atlas: usize,
+    /// The x coordinate of the top left corner of the image in the atlas.
+    pub x: u32,
+    /// The y coordinate of the top left corner of the image in the atlas.
+    pub y: u32,
+    /// The width of the image.
+    pub width: u32,
+    /// The height of the image.
+    pub height: u32,
+}
+
+/// The arrangement of a set of images on one or more atlases.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AtlasLayout {
+    /// The width and height of each atlas.
+    pub atlases: Vec<(u32, u32)>,
+    /// The placement of each image, in the order in which the images were given.
+    pub placements: Vec<Placement>,
+}
+
+/// The position and timing of one frame of an animation packed by [`pack_animation`].
+///
+/// [`pack_animation`]: fn.pack_animation.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct FrameTiming {
+    /// The x coordinate of the frame on the canvas of the animation.
+    pub left: u32,
+    /// The y coordinate of the frame on the canvas of the animation.
+    pub top: u32,
+    /// How long the frame is shown, in milliseconds as a numerator and a denominator.
+    pub delay_ms: (u32, u32),
+}
+
+/// The arrangement of the frames of an animation on atlases, with their timing.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct AnimationSheet {
+    /// Where each frame is placed on the atlases.
+    pub layout: AtlasLayout,
+    /// The timing of each frame, in the order of the animation.
+    pub frames: Vec<FrameTiming>,
+}
+
+/// A row of images within an atlas that all start at the same y coordinate.
+struct Shelf {
+    y: u32,
+    height: u32,
+    /// The x coordinate where the next image on this shelf would start.
+    next_x: u32,
+}
+
+struct Bin {
+    shelves: Vec<Shelf>,
+    /// The y coordinate where the next shelf would start.
+    next_y: u32,
+}
+
+/// Compute an arrangement of images with the given `(width, height)` sizes.
+///
+/// The images are placed on shelves, the tallest ones first, and a new atlas is started whenever
+/// an image does not fit on the atlases so far. This is fast and works well for images of similar
+/// heights, as is typical for sprites, but the result is not necessarily optimal.
+///
+/// Returns an `ImageError::Parameter` if an image, including its padding, is larger than the
+/// maximum atlas size.
+pub fn layout(sizes: &[(u32, u32)], options: &PackOptions) -> ImageResult<AtlasLayout> {
+    let (max_width, max_height) = if options.power_of_two {
+        (
+            previous_power_of_two(options.max_width),
+            previous_power_of_two(options.max_height),
+        )
+    } else {
+        (options.max_width, options.max_height)
+    };
+    let padding = u64::from(options.padding);
+
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| (std::cmp::Reverse(sizes[i].1), std::cmp::Reverse(sizes[i].0)));
+
+    let mut bins: Vec<Bin> = Vec::new();
+    let mut placements = vec![None; sizes.len()];
+
+    for index in order {
+        let (width, height) = sizes[index];
+        if 2 * padding + u64::from(width) > u64::from(max_width)
+            || 2 * padding + u64::from(height) > u64::from(max_height)
+        {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!(
+                    "the image {} of size {}x{} does not fit into an atlas of size {}x{}",
+                    index, width, height, max_width, max_height
+                )),
+            )));
+        }
+
+        // Whether an image at `(x, y)` ends before the padding at the border of the atlas.
+        let fits = |x: u32, y: u32| {
+            u64::from(x) + u64::from(width) + padding <= u64::from(max_width)
+                && u64::from(y) + u64::from(height) + padding <= u64::from(max_height)
+        };
+
+        let mut placed = None;
+        for (atlas, bin) in bins.iter_mut().enumerate() {
+            if let Some(shelf) = bin
+                .shelves
+                .iter_mut()
+                .find(|shelf| shelf.height >= height && fits(shelf.next_x, shelf.y))
+            {
+                placed = Some((atlas, shelf.next_x, shelf.y));
+                shelf.next_x += width + options.padding;
+                break;
+            }
+
+            if fits(options.padding, bin.next_y) {
+                placed = Some((atlas, options.padding, bin.next_y));
+                bin.shelves.push(Shelf {
+                    y: bin.next_y,
+                    height,
+                    next_x: options.padding + width + options.padding,
+                });
+                bin.next_y += height + options.padding;
+                break;
+            }
+        }
+
+        let (atlas, x, y) = match placed {
+            Some(placed) => placed,
+            None => {
+                bins.push(Bin {
+                    shelves: vec![Shelf {
+                        y: options.padding,
+                        height,
+                        next_x: options.padding + width + options.padding,
+                    }],
+                    next_y: options.padding + height + options.padding,
+                });
+                (bins.len() - 1, options.padding, options.padding)
+            }
+        };
+
+        placements[index] = Some(Placement {
+            atlas,
+            x,
+            y,
+            width,
+            height,
+        });
+    }
+
+    let placements: Vec<Placement> = placements.into_iter().map(Option::unwrap).collect();
+
+    let mut atlases = vec![(0, 0); bins.len()];
+    for placement in &placements {
+        let size = &mut atlases[placement.atlas];
+        size.0 = size.0.max(placement.x + placement.width + options.padding);
+        size.1 = size.1.max(placement.y + placement.height + options.padding);
+    }
+    if options.power_of_two {
+        for size in &mut atlases {
+            *size = (size.0.next_power_of_two(), size.1.next_power_of_two());
+        }
+    }
+
+    Ok(AtlasLayout {
+        atlases,
+        placements,
+    })
+}
+
+/// Pack `images` into as few atlases as possible.
+///
+/// Returns the atlases together with the placement of each image, see [`layout`] for how the
+/// images are arranged. Pixels of the atlases that are not covered by an image are zero, which is
+/// fully transparent for pixels with an alpha channel.
+///
+/// [`layout`]: fn.layout.html
+#[allow(clippy::type_complexity)]
+pub fn pack<I, P>(
+    images: &[I],
+    options: &PackOptions,
+) -> ImageResult<(Vec<ImageBuffer<P, Vec<P::Subpixel>>>, AtlasLayout)>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel + 'static,
+{
+    let sizes: Vec<(u32, u32)> = images.iter().map(|image| image.dimensions()).collect();
+    let layout = layout(&sizes, options)?;
+
+    let mut atlases: Vec<ImageBuffer<P, Vec<P::Subpixel>>> = layout
+        .atlases
+        .iter()
+        .map(|&(width, height)| ImageBuffer::new(width, height))
+        .collect();
+    for (image, placement) in images.iter().zip(&layout.placements) {
+        atlases[placement.atlas].copy_from(image, placement.x, placement.y)?;
+    }
+
+    Ok((atlases, layout))
+}
+
+/// Cut a sprite sheet into `cols` by `rows` equally sized frames.
+///
+/// The frames are separated by `spacing` pixels and the grid is surrounded by a border of `margin`
+/// pixels, the size of the frames is derived from the dimensions of `image`. Frames are returned
+/// row by row, which is the usual order of animation frames on a sheet.
+///
+/// ```
+/// use image::atlas::slice_grid;
+/// use image::{Delay, Frame, Rgba, RgbaImage};
+///
+/// // Four 8x8 frames in a row, with one pixel of spacing between them.
+/// let sheet = RgbaImage::from_pixel(35, 8, Rgba([0, 0, 255, 255]));
+/// let frames: Vec<Frame> = slice_grid(&sheet, 4, 1, 0, 1)
+///     .unwrap()
+///     .iter()
+///     .map(|view| Frame::from_parts(view.to_image(), 0, 0, Delay::from_numer_denom_ms(100, 1)))
+///     .collect();
+/// assert_eq!(frames.len(), 4);
+/// assert_eq!(frames[3].buffer().dimensions(), (8, 8));
+/// ```
+///
+/// Returns an `ImageError::Parameter` if there are no rows or columns, or if the margin and spacing
+/// do not leave room for frames of at least one pixel.
+pub fn slice_grid<I: GenericImageView>(
+    image: &I,
+    cols: u32,
+    rows: u32,
+    margin: u32,
+    spacing: u32,
+) -> ImageResult<Vec<SubImage<&I>>> {
+    let frame_size = |length: u32, count: u32| {
+        let gaps = u64::from(margin) * 2 + u64::from(spacing) * u64::from(count.checked_sub(1)?);
+        let size = u64::from(length).checked_sub(gaps)? / u64::from(count);
+        if size == 0 {
+            None
+        } else {
+            Some(size as u32)
+        }
+    };
+
+    let (width, height) = image.dimensions();
+    let (frame_width, frame_height) = match (frame_size(width, cols), frame_size(height, rows)) {
+        (Some(frame_width), Some(frame_height)) => (frame_width, frame_height),
+        _ => {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!(
+                    "an image of size {}x{} can not be sliced into {}x{} frames with margin {} and spacing {}",
+                    width, height, cols, rows, margin, spacing
+                )),
+            )))
+        }
+    };
+
+    let mut frames = Vec::with_capacity(cols as usize * rows as usize);
+    for row in 0..rows {
+        for col in 0..cols {
+            let x = margin + col * (frame_width + spacing);
+            let y = margin + row * (frame_height + spacing);
+            frames.push(image.view(x, y, frame_width, frame_height));
+        }
+    }
+    Ok(frames)
+}
+
+/// Cut atlases into the images described by `layout`.
+///
+/// This is the inverse of [`pack`], the returned views are in the order of the placements.
+///
+/// Returns an `ImageError::Parameter` if a placement refers to a missing atlas or lies outside of
+/// its atlas.
+///
+/// [`pack`]: fn.pack.html
+pub fn slice<'a, I: GenericImageView>(
+    atlases: &'a [I],
+    layout: &AtlasLayout,
+) -> ImageResult<Vec<SubImage<&'a I>>> {
+    layout
+        .placements
+        .iter()
+        .map(|placement| {
+            let atlas = atlases.get(placement.atlas).filter(|atlas| {
+                let (width, height) = atlas.dimensions();
+                u64::from(placement.x) + u64::from(placement.width) <= u64::from(width)
+                    && u64::from(placement.y) + u64::from(placement.height) <= u64::from(height)
+            });
+            match atlas {
+                Some(atlas) => {
+                    Ok(atlas.view(placement.x, placement.y, placement.width, placement.height))
+                }
+                None => Err(ImageError::Parameter(ParameterError::from_kind(
+                    ParameterErrorKind::Generic(format!(
+                        "the placement {:?} lies outside of the atlases",
+                        placement
+                    )),
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// Pack the frames of an animation into sprite sheets.
+///
+/// The frames are arranged as by [`pack`], in the order of the animation. The returned
+/// [`AnimationSheet`] records where each frame is and how long it is shown, which is what game
+/// engines and CSS animations need to play the sheet. Frames decoded with
+/// `AnimationDecoder::into_frames` cover the whole canvas, so frames of the same size are laid out
+/// in rows.
+///
+/// ```no_run
+/// use image::atlas::{pack_animation, PackOptions};
+/// use image::codecs::gif::GifDecoder;
+/// use image::AnimationDecoder;
+///
+/// let file = std::io::BufReader::new(std::fs::File::open("walk.gif")?);
+/// let frames = GifDecoder::new(file)?.into_frames().collect_frames()?;
+/// let (sheets, sheet) = pack_animation(frames, &PackOptions::default())?;
+/// sheets[0].save("walk.png")?;
+/// for timing in &sheet.frames {
+///     println!("{:?}", timing.delay_ms);
+/// }
+/// # Ok::<(), image::ImageError>(())
+/// ```
+///
+/// Returns an `ImageError::Parameter` if a frame is larger than the maximum atlas size.
+///
+/// [`pack`]: fn.pack.html
+/// [`AnimationSheet`]: struct.AnimationSheet.html
+pub fn pack_animation<F>(
+    frames: F,
+    options: &PackOptions,
+) -> ImageResult<(Vec<RgbaImage>, AnimationSheet)>
+where
+    F: IntoIterator<Item = Frame>,
+{
+    let mut timings = Vec::new();
+    let mut images = Vec::new();
+    for frame in frames {
+        timings.push(FrameTiming {
+            left: frame.left(),
+            top: frame.top(),
+            delay_ms: frame.delay().numer_denom_ms(),
+        });
+        images.push(frame.into_buffer());
+    }
+
+    let (atlases, layout) = pack(&images, options)?;
+    let sheet = AnimationSheet {
+        layout,
+        frames: timings,
+    };
+    Ok((atlases, sheet))
+}
+
+/// Cut the frames of an animation out of sprite sheets packed by [`pack_animation`].
+///
+/// The frames can be encoded as an animation again, for example with
+/// `GifEncoder::encode_frames`.
+///
+/// ```no_run
+/// use image::atlas::{unpack_animation, AnimationSheet};
+/// use image::codecs::gif::GifEncoder;
+///
+/// # fn sheet() -> AnimationSheet { unimplemented!() }
+/// let sheets = vec![image::open("walk.png")?.into_rgba8()];
+/// let frames = unpack_animation(&sheets, &sheet())?;
+/// GifEncoder::new(std::fs::File::create("walk.gif")?).encode_frames(frames)?;
+/// # Ok::<(), image::ImageError>(())
+/// ```
+///
+/// Returns an `ImageError::Parameter` if the sheet does not describe as many frames as it places,
+/// if a delay has a denominator of zero, or if a placement lies outside of the atlases.
+///
+/// [`pack_animation`]: fn.pack_animation.html
+pub fn unpack_animation(atlases: &[RgbaImage], sheet: &AnimationSheet) -> ImageResult<Vec<Frame>> {
+    let invalid = |message: String| {
+        ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+            message,
+        )))
+    };
+    if sheet.frames.len() != sheet.layout.placements.len() {
+        return Err(invalid(format!(
+            "the sheet has the timing of {} frames but places {}",
+            sheet.frames.len(),
+            sheet.layout.placements.len()
+        )));
+    }
+    if let Some(timing) = sheet.frames.iter().find(|timing| timing.delay_ms.1 == 0) {
+        return Err(invalid(format!(
+            "the frame delay {:?} has a denominator of zero",
+            timing.delay_ms
+        )));
+    }
+
+    let views = slice(atlases, &sheet.layout)?;
+    let frames = views
+        .iter()
+        .zip(&sheet.frames)
+        .map(|(view, timing)| {
+            let (numerator, denominator) = timing.delay_ms;
+            let delay = Delay::from_numer_denom_ms(numerator, denominator);
+            Frame::from_parts(view.to_image(), timing.left, timing.top, delay)
+        })
+        .collect();
+    Ok(frames)
+}
+
+fn previous_power_of_two(value: u32) -> u32 {
+    match value {
+        0 => 0,
+        value => 1 << (31 - value.leading_zeros()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayImage, Luma};
+
+    fn overlaps(a: &Placement, b: &Placement, padding: u32) -> bool {
+        a.atlas == b.atlas
+            && a.x < b.x + b.width + padding
+            && b.x < a.x + a.width + padding
+            && a.y < b.y + b.height + padding
+            && b.y < a.y + a.height + padding
+    }
+
+    #[test]
+    fn placements_are_disjoint_and_padded() {
+        let sizes: Vec<(u32, u32)> = (1..40).map(|i| (i * 3 % 17 + 1, i * 5 % 13 + 1)).collect();
+        let options = PackOptions {
+            max_width: 64,
+            max_height: 64,
+            padding: 2,
+            power_of_two: false,
+        };
+        let layout = layout(&sizes, &options).unwrap();
+
+        for (i, a) in layout.placements.iter().enumerate() {
+            assert_eq!((a.width, a.height), sizes[i]);
+            let (width, height) = layout.atlases[a.atlas];
+            assert!(a.x >= 2 && a.y >= 2);
+            assert!(a.x + a.width + 2 <= width && a.y + a.height + 2 <= height);
+            for b in &layout.placements[i + 1..] {
+                assert!(!overlaps(a, b, 2));
+            }
+        }
+    }
+
+    #[test]
+    fn overflow_starts_new_atlas() {
+        let options = PackOptions {
+            max_width: 16,
+            max_height: 16,
+            ..PackOptions::default()
+        };
+        let layout = layout(&[(16, 10), (16, 10), (8, 6)], &options).unwrap();
+        assert_eq!(layout.atlases.len(), 2);
+        assert_eq!(layout.atlases[0], (16, 16));
+        assert_eq!(layout.atlases[1], (16, 10));
+    }
+
+    #[test]
+    fn power_of_two_sizes() {
+        let options = PackOptions {
+            max_width: 100,
+            power_of_two: true,
+            ..PackOptions::default()
+        };
+        let layout = layout(&[(40, 30), (20, 5)], &options).unwrap();
+        assert_eq!(layout.atlases, vec![(64, 32)]);
+        assert!(layout.placements.iter().all(|p| p.x + p.width <= 64));
+    }
+
+    #[test]
+    fn too_large_image() {
+        let options = PackOptions {
+            max_width: 16,
+            max_height: 16,
+            padding: 1,
+            power_of_two: false,
+        };
+        assert!(layout(&[(15, 4)], &options).is_err());
+    }
+
+    #[test]
+    fn composites_images() {
+        let images: Vec<GrayImage> = (1..=5)
+            .map(|i| GrayImage::from_pixel(i * 2, 3, Luma([i as u8 * 10])))
+            .collect();
+        let (atlases, layout) = pack(&images, &PackOptions::default()).unwrap();
+        for (image, placement) in images.iter().zip(&layout.placements) {
+            let view = atlases[placement.atlas].view(
+                placement.x,
+                placement.y,
+                placement.width,
+                placement.height,
+            );
+            assert!(view.pixels().all(|(x, y, p)| p == *image.get_pixel(x, y)));
+        }
+    }
+
+    #[test]
+    fn slice_inverts_pack() {
+        let images: Vec<GrayImage> = (1..=6)
+            .map(|i| GrayImage::from_fn(i, 7 - i, |x, y| Luma([(i * 10 + x + y) as u8])))
+            .collect();
+        let options = PackOptions {
+            max_width: 8,
+            max_height: 8,
+            padding: 1,
+            power_of_two: false,
+        };
+        let (atlases, layout) = pack(&images, &options).unwrap();
+        let frames = slice(&atlases, &layout).unwrap();
+        assert_eq!(frames.len(), images.len());
+        for (frame, image) in frames.iter().zip(&images) {
+            assert_eq!(&frame.to_image(), image);
+        }
+    }
+
+    #[test]
+    fn slice_out_of_bounds() {
+        let atlases = [GrayImage::new(4, 4)];
+        let mut layout = AtlasLayout {
+            atlases: vec![(4, 4)],
+            placements: vec![Placement {
+                atlas: 0,
+                x: 2,
+                y: 0,
+                width: 3,
+                height: 1,
+            }],
+        };
+        assert!(slice(&atlases, &layout).is_err());
+        layout.placements[0].x = 1;
+        assert!(slice(&atlases, &layout).is_ok());
+        layout.placements[0].atlas = 1;
+        assert!(slice(&atlases, &layout).is_err());
+    }
+
+    #[test]
+    fn grid_with_margin_and_spacing() {
+        // 3x2 frames of 4x5 pixels, with a margin of 2 and a spacing of 1.
+        let sheet = GrayImage::from_fn(2 * 2 + 3 * 4 + 2, 2 * 2 + 2 * 5 + 1, |x, y| {
+            let (x, y) = (x.wrapping_sub(2), y.wrapping_sub(2));
+            if x % 5 == 4 || y % 6 == 5 || x >= 14 || y >= 11 {
+                Luma([0])
+            } else {
+                Luma([(1 + x / 5 + 3 * (y / 6)) as u8])
+            }
+        });
+        let frames = slice_grid(&sheet, 3, 2, 2, 1).unwrap();
+        assert_eq!(frames.len(), 6);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.dimensions(), (4, 5));
+            assert!(frame.pixels().all(|(_, _, p)| p == Luma([i as u8 + 1])));
+        }
+    }
+
+    #[test]
+    fn grid_without_room() {
+        let sheet = GrayImage::new(10, 10);
+        assert!(slice_grid(&sheet, 0, 1, 0, 0).is_err());
+        assert!(slice_grid(&sheet, 2, 2, 5, 0).is_err());
+        assert!(slice_grid(&sheet, 11, 1, 0, 0).is_err());
+        assert!(slice_grid(&sheet, 2, 1, 0, 9).is_err());
+        assert_eq!(
+            slice_grid(&sheet, 2, 1, 0, 8).unwrap()[1].bounds(),
+            (9, 0, 1, 10)
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn layout_serializes() {
+        let layout = layout(&[(4, 4), (2, 8)], &PackOptions::default()).unwrap();
+        let json = serde_json::to_string(&layout).unwrap();
+        let parsed: AtlasLayout = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, layout);
+    }
+
+    fn animation() -> Vec<Frame> {
+        (0..5u8)
+            .map(|i| {
+                let buffer = RgbaImage::from_fn(6, 4, |x, y| {
+                    crate::Rgba([i * 40, x as u8 * 30, y as u8 * 50, 255])
+                });
+                let delay = Delay::from_numer_denom_ms(100 + u32::from(i), 3);
+                Frame::from_parts(buffer, 0, u32::from(i % 2), delay)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn animation_round_trip() {
+        let options = PackOptions {
+            max_width: 16,
+            max_height: 16,
+            padding: 1,
+            power_of_two: false,
+        };
+        let (atlases, sheet) = pack_animation(animation(), &options).unwrap();
+        assert_eq!(sheet.frames.len(), 5);
+        assert_eq!(sheet.frames[1].delay_ms, (101, 3));
+
+        let frames = unpack_animation(&atlases, &sheet).unwrap();
+        assert_eq!(frames.len(), 5);
+        for (unpacked, frame) in frames.iter().zip(animation()) {
+            assert_eq!(unpacked.buffer(), frame.buffer());
+            assert_eq!(
+                (unpacked.left(), unpacked.top()),
+                (frame.left(), frame.top())
+            );
+            assert_eq!(unpacked.delay(), frame.delay());
+        }
+
+        let mut broken = sheet.clone();
+        broken.frames.pop();
+        assert!(unpack_animation(&atlases, &broken).is_err());
+        let mut broken = sheet;
+        broken.frames[0].delay_ms.1 = 0;
+        assert!(unpack_animation(&atlases, &broken).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "gif")]
+    fn gif_round_trip() {
+        use crate::codecs::gif::{GifDecoder, GifEncoder};
+        use crate::AnimationDecoder;
+
+        // Colors that the palette of a GIF frame holds exactly.
+        let frames: Vec<Frame> = (0..3u8)
+            .map(|i| {
+                let buffer =
+                    RgbaImage::from_fn(5, 3, |x, _| crate::Rgba([i * 100, x as u8 * 50, 7, 255]));
+                Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(50, 1))
+            })
+            .collect();
+        let mut gif = Vec::new();
+        GifEncoder::new(&mut gif)
+            .encode_frames(frames.clone())
+            .unwrap();
+
+        let decoded = GifDecoder::new(&gif[..]).unwrap().into_frames();
+        let (atlases, sheet) =
+            pack_animation(decoded.collect_frames().unwrap(), &PackOptions::default()).unwrap();
+        assert_eq!(atlases.len(), 1);
+        let unpacked = unpack_animation(&atlases, &sheet).unwrap();
+        for (unpacked, frame) in unpacked.iter().zip(&frames) {
+            assert_eq!(unpacked.buffer(), frame.buffer());
+            assert_eq!(unpacked.delay(), frame.delay());
+        }
+    }
+}
@@ -7,10 +7,10 @@ use std::path::Path;
 use std::slice::{ChunksExact, ChunksExactMut};
 
 use crate::color::{FromColor, Luma, LumaA, Rgb, Rgba};
-use crate::dynimage::{save_buffer, save_buffer_with_format, write_buffer_with_format};
 use crate::error::ImageResult;
 use crate::flat::{FlatSamples, SampleLayout};
 use crate::image::{GenericImage, GenericImageView, ImageFormat, ImageOutputFormat};
+use crate::io::free_functions;
 use crate::math::Rect;
 use crate::traits::{EncodableLayout, Pixel, PixelWithColorType};
 use crate::utils::expand_packed;
@@ -609,6 +609,7 @@ pub struct ImageBuffer<P: Pixel, Container> {
     height: u32,
     _phantom: PhantomData<P>,
     data: Container,
+    icc_profile: Option<Vec<u8>>,
 }
 
 // generic implementation, shared along all image buffers
@@ -629,12 +630,28 @@ where
                 width,
                 height,
                 _phantom: PhantomData,
+                icc_profile: None,
             })
         } else {
             None
         }
     }
 
+    /// Returns the ICC color profile that describes the colors of this image, if it has one.
+    ///
+    /// Images decoded from a file keep the profile embedded in it, which is embedded again when
+    /// the image is saved or written in a format that can store it.
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        self.icc_profile.as_deref()
+    }
+
+    /// Set or remove the ICC color profile that describes the colors of this image.
+    ///
+    /// The profile must describe the color space of the samples. It is not validated.
+    pub fn set_icc_profile(&mut self, icc_profile: Option<Vec<u8>>) {
+        self.icc_profile = icc_profile;
+    }
+
     /// Returns the underlying raw buffer
     pub fn into_raw(self) -> Container {
         self.data
@@ -945,12 +962,14 @@ where
         P: PixelWithColorType,
     {
         // This is valid as the subpixel is u8.
-        save_buffer(
-            path,
+        free_functions::save_buffer_impl(
+            path.as_ref(),
             self.as_bytes(),
             self.width(),
             self.height(),
             <P as PixelWithColorType>::COLOR_TYPE,
+            free_functions::DEFAULT_MATTE,
+            self.icc_profile(),
         )
     }
 }
@@ -972,13 +991,15 @@ where
         P: PixelWithColorType,
     {
         // This is valid as the subpixel is u8.
-        save_buffer_with_format(
-            path,
+        free_functions::save_buffer_with_format_impl(
+            path.as_ref(),
             self.as_bytes(),
             self.width(),
             self.height(),
             <P as PixelWithColorType>::COLOR_TYPE,
             format,
+            free_functions::DEFAULT_MATTE,
+            self.icc_profile(),
         )
     }
 }
@@ -998,18 +1019,22 @@ where
     /// supported types.
     pub fn write_to<W, F>(&self, writer: &mut W, format: F) -> ImageResult<()>
     where
-        W: std::io::Write + std::io::Seek,
+        W: std::io::Write,
         F: Into<ImageOutputFormat>,
         P: PixelWithColorType,
     {
+        let format = format.into();
+        let icc_profile = free_functions::embeddable_icc_profile(self.icc_profile(), &format);
         // This is valid as the subpixel is u8.
-        write_buffer_with_format(
-            writer,
+        free_functions::write_buffer_with_icc_profile_impl(
+            &mut free_functions::Stream::new(writer),
             self.as_bytes(),
             self.width(),
             self.height(),
             <P as PixelWithColorType>::COLOR_TYPE,
             format,
+            free_functions::DEFAULT_MATTE,
+            icc_profile,
         )
     }
 }
@@ -1025,6 +1050,7 @@ where
             height: 0,
             _phantom: PhantomData,
             data: Default::default(),
+            icc_profile: None,
         }
     }
 }
@@ -1084,6 +1110,7 @@ where
             width: self.width,
             height: self.height,
             _phantom: PhantomData,
+            icc_profile: self.icc_profile.clone(),
         }
     }
 }
@@ -1201,6 +1228,7 @@ impl<P: Pixel> ImageBuffer<P, Vec<P::Subpixel>> {
             width,
             height,
             _phantom: PhantomData,
+            icc_profile: None,
         }
     }
 
@@ -3,59 +3,182 @@
 /// The [AVIF] specification defines an image derivative of the AV1 bitstream, an open video codec.
 ///
 /// [AVIF]: https://aomediacodec.github.io/av1-avif/
+use std::collections::VecDeque;
 use std::convert::TryFrom;
 use std::error::Error;
 use std::io::{self, Cursor, Read};
 use std::marker::PhantomData;
 use std::mem;
+use std::time::Duration;
 
-use crate::error::DecodingError;
-use crate::{ColorType, ImageDecoder, ImageError, ImageFormat, ImageResult};
+use crate::animation::{Delay, Frame, Frames};
+use crate::error::{DecodingError, UnsupportedError, UnsupportedErrorKind};
+use crate::io::Limits;
+use crate::{
+    AnimationDecoder, ColorType, ImageDecoder, ImageError, ImageFormat, ImageResult, RgbaImage,
+};
 
 use dav1d::{PixelLayout, PlanarImageComponent};
 use dcv_color_primitives as dcp;
 use mp4parse::{read_avif, ParseStrictness};
 
+use super::sequence::{self, Sample, Sequence};
+
 fn error_map<E: Into<Box<dyn Error + Send + Sync>>>(err: E) -> ImageError {
     ImageError::Decoding(DecodingError::new(ImageFormat::Avif.into(), err))
 }
 
 /// AVIF Decoder.
 ///
-/// Reads one image into the chosen input.
+/// Reads one image into the chosen input. The frames of image sequences, also known as animated
+/// AVIF, are read through the `AnimationDecoder` interface.
 pub struct AvifDecoder<R> {
     inner: PhantomData<R>,
     picture: dav1d::Picture,
     alpha_picture: Option<dav1d::Picture>,
+    /// The whole file and the frames within it, if it is an image sequence.
+    sequence: Option<(Vec<u8>, Sequence)>,
+    /// The limits on the frames of an image sequence.
+    limits: Limits,
 }
 
 impl<R: Read> AvifDecoder<R> {
     /// Create a new decoder that reads its input from `r`.
-    pub fn new(mut r: R) -> ImageResult<Self> {
-        let ctx = read_avif(&mut r, ParseStrictness::Normal).map_err(error_map)?;
-        let mut primary_decoder = dav1d::Decoder::new();
-        let coded = ctx.primary_item_coded_data();
-        primary_decoder
-            .send_data(coded, None, None, None)
-            .map_err(error_map)?;
-        let picture = primary_decoder.get_picture().map_err(error_map)?;
-        let alpha_item = ctx.alpha_item_coded_data();
-        let alpha_picture = if !alpha_item.is_empty() {
-            let mut alpha_decoder = dav1d::Decoder::new();
-            alpha_decoder
-                .send_data(alpha_item, None, None, None)
-                .map_err(error_map)?;
-            Some(alpha_decoder.get_picture().map_err(error_map)?)
+    ///
+    /// The still image of an image sequence is its primary item if it has one, as viewers without
+    /// animation support show, and otherwise its first frame.
+    pub fn new(r: R) -> ImageResult<Self> {
+        Self::with_limits(r, Limits::no_limits())
+    }
+
+    /// Create a new decoder that reads its input from `r` and decodes within the given limits.
+    ///
+    /// The whole file is read into memory, so the `max_alloc` limit also bounds its size. The
+    /// dimensions of the still image are checked once it is decoded.
+    pub fn with_limits(mut r: R, mut limits: Limits) -> ImageResult<Self> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let mut data = Vec::new();
+        let max_size = limits
+            .max_alloc
+            .map_or(u64::MAX, |max| max.saturating_add(1));
+        r.by_ref().take(max_size).read_to_end(&mut data)?;
+        limits.reserve(data.len() as u64)?;
+        limits.check_cancelled()?;
+        let sequence = if sequence::is_sequence(&data) {
+            sequence::parse(&data)
         } else {
             None
         };
+
+        let (picture, alpha_picture) =
+            match read_avif(&mut Cursor::new(&data), ParseStrictness::Normal) {
+                Ok(ctx) => {
+                    let mut primary_decoder = dav1d::Decoder::new();
+                    let coded = ctx.primary_item_coded_data();
+                    primary_decoder
+                        .send_data(coded, None, None, None)
+                        .map_err(error_map)?;
+                    let picture = primary_decoder.get_picture().map_err(error_map)?;
+                    let alpha_item = ctx.alpha_item_coded_data();
+                    let alpha_picture = if !alpha_item.is_empty() {
+                        let mut alpha_decoder = dav1d::Decoder::new();
+                        alpha_decoder
+                            .send_data(alpha_item, None, None, None)
+                            .map_err(error_map)?;
+                        Some(alpha_decoder.get_picture().map_err(error_map)?)
+                    } else {
+                        None
+                    };
+                    (picture, alpha_picture)
+                }
+                Err(err) => match &sequence {
+                    Some(sequence) => {
+                        let mut frames = SequenceDecoder::new(sequence.samples.clone());
+                        let (picture, _) = frames.next_picture(&data).unwrap_or_else(|| {
+                            Err(ImageError::Decoding(DecodingError::new(
+                                ImageFormat::Avif.into(),
+                                "image sequence without frames",
+                            )))
+                        })?;
+                        (picture, None)
+                    }
+                    None => return Err(error_map(err)),
+                },
+            };
         assert_eq!(picture.bit_depth(), 8);
+        limits.check_dimensions(picture.width(), picture.height())?;
+        // Only the file of an image sequence is kept, to decode its frames from.
+        let file_size = data.len() as u64;
+        let sequence = sequence.map(|sequence| (data, sequence));
+        if sequence.is_none() {
+            limits.free(file_size);
+        }
         Ok(AvifDecoder {
             inner: PhantomData,
             picture,
             alpha_picture,
+            sequence,
+            limits,
         })
     }
+
+    /// Returns if the file is an image sequence, whose frames are read with `into_frames`.
+    pub fn is_animated(&self) -> bool {
+        self.sequence.is_some()
+    }
+}
+
+/// Decodes the frames of an image sequence in order.
+struct SequenceDecoder {
+    decoder: dav1d::Decoder,
+    samples: std::vec::IntoIter<Sample>,
+    /// A frame that the decoder did not accept before it returned pending pictures.
+    pending: Option<Sample>,
+    /// The durations of the frames sent to the decoder whose pictures were not returned yet.
+    durations: VecDeque<u32>,
+}
+
+impl SequenceDecoder {
+    fn new(samples: Vec<Sample>) -> Self {
+        SequenceDecoder {
+            decoder: dav1d::Decoder::new(),
+            samples: samples.into_iter(),
+            pending: None,
+            durations: VecDeque::new(),
+        }
+    }
+
+    /// Returns the next picture and its duration, or `None` after the last one. The frames are
+    /// read from `data`, the whole file.
+    fn next_picture(&mut self, data: &[u8]) -> Option<ImageResult<(dav1d::Picture, u32)>> {
+        loop {
+            match self.decoder.get_picture() {
+                Ok(picture) => {
+                    let duration = self.durations.pop_front().unwrap_or(0);
+                    return Some(Ok((picture, duration)));
+                }
+                Err(dav1d::Error::Again) => {}
+                Err(err) => return Some(Err(error_map(err))),
+            }
+
+            let sample = self.pending.take().or_else(|| self.samples.next())?;
+            let coded = match sample.data(data) {
+                Some(coded) => coded,
+                None => {
+                    return Some(Err(ImageError::Decoding(DecodingError::new(
+                        ImageFormat::Avif.into(),
+                        "frame lies outside of the file",
+                    ))))
+                }
+            };
+            match self.decoder.send_data(coded, None, None, None) {
+                Ok(()) => self.durations.push_back(sample.duration),
+                // The decoder first needs to return pictures, send the frame again afterwards.
+                Err(dav1d::Error::Again) => self.pending = Some(sample),
+                Err(err) => return Some(Err(error_map(err))),
+            }
+        }
+    }
 }
 
 /// Wrapper struct around a `Cursor<Vec<u8>>`
@@ -93,78 +216,172 @@ impl<'a, R: 'a + Read> ImageDecoder<'a> for AvifDecoder<R> {
         ))
     }
 
+    fn set_limits(&mut self, mut limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        if let Some((data, _)) = &self.sequence {
+            limits.reserve(data.len() as u64)?;
+        }
+        self.limits = limits;
+        Ok(())
+    }
+
     fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
         assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        picture_to_rgba(&self.picture, self.alpha_picture.as_ref(), buf)
+    }
+}
 
-        dcp::initialize();
+impl<'a, R: 'a + Read> AnimationDecoder<'a> for AvifDecoder<R> {
+    /// Returns the frames of an image sequence, or the image itself as the only frame of a still
+    /// image.
+    fn into_frames(self) -> Frames<'a> {
+        let (data, sequence) = match self.sequence {
+            Some(sequence) => sequence,
+            None => {
+                let (width, height) = self.dimensions();
+                if let Err(err) = self.limits.clone().reserve(self.total_bytes()) {
+                    return Frames::new(Box::new(std::iter::once(Err(err))));
+                }
+                let mut buf = vec![0; width as usize * height as usize * 4];
+                let frame = self
+                    .read_image(&mut buf)
+                    .map(|()| Frame::new(RgbaImage::from_raw(width, height, buf).unwrap()));
+                return Frames::new(Box::new(std::iter::once(frame)));
+            }
+        };
 
-        if self.picture.pixel_layout() != PixelLayout::I400 {
-            let pixel_format = match self.picture.pixel_layout() {
-                PixelLayout::I400 => todo!(),
-                PixelLayout::I420 => dcp::PixelFormat::I420,
-                PixelLayout::I422 => dcp::PixelFormat::I422,
-                PixelLayout::I444 => dcp::PixelFormat::I444,
-                PixelLayout::Unknown => panic!("Unknown pixel layout"),
-            };
-            let src_format = dcp::ImageFormat {
-                pixel_format,
-                color_space: dcp::ColorSpace::Bt601,
-                num_planes: 3,
-            };
-            let dst_format = dcp::ImageFormat {
-                pixel_format: dcp::PixelFormat::Bgra,
-                color_space: dcp::ColorSpace::Lrgb,
-                num_planes: 1,
+        let timescale = sequence.timescale;
+        let limits = self.limits;
+        let mut frames = SequenceDecoder::new(sequence.samples);
+        let iterator = std::iter::from_fn(move || {
+            // The frames are decoded from the file in memory, without reading in between.
+            if let Err(err) = limits.check_cancelled() {
+                return Some(Err(err));
+            }
+            let (picture, duration) = match frames.next_picture(&data)? {
+                Ok(next) => next,
+                Err(err) => return Some(Err(err)),
             };
-            let (width, height) = self.dimensions();
-            let planes = &[
-                self.picture.plane(PlanarImageComponent::Y),
-                self.picture.plane(PlanarImageComponent::U),
-                self.picture.plane(PlanarImageComponent::V),
-            ];
-            let src_buffers = planes.iter().map(AsRef::as_ref).collect::<Vec<_>>();
-            let strides = &[
-                self.picture.stride(PlanarImageComponent::Y) as usize,
-                self.picture.stride(PlanarImageComponent::U) as usize,
-                self.picture.stride(PlanarImageComponent::V) as usize,
-            ];
-            let dst_buffers = &mut [&mut buf[..]];
-            dcp::convert_image(
-                width,
-                height,
-                &src_format,
-                Some(strides),
-                &src_buffers,
-                &dst_format,
-                None,
-                dst_buffers,
-            )
-            .map_err(error_map)?;
-        } else {
-            let plane = self.picture.plane(PlanarImageComponent::Y);
-            buf.copy_from_slice(plane.as_ref());
-        }
+            Some(picture_frame(&picture, duration, timescale, limits.clone()))
+        });
+        Frames::new(Box::new(iterator))
+    }
+}
 
-        if let Some(picture) = self.alpha_picture {
-            assert_eq!(picture.pixel_layout(), PixelLayout::I400);
-            let stride = picture.stride(PlanarImageComponent::Y) as usize;
-            let plane = picture.plane(PlanarImageComponent::Y);
-            let width = picture.width();
-            for (buf, slice) in Iterator::zip(
-                buf.chunks_exact_mut(width as usize * 4),
-                plane.as_ref().chunks_exact(stride),
-            ) {
-                for i in 0..width as usize {
-                    buf[3 + i * 4] = slice[i];
-                }
-            }
-        }
+/// Converts a picture of an image sequence into a frame that is shown for `duration` units of
+/// `timescale`, within the limits.
+fn picture_frame(
+    picture: &dav1d::Picture,
+    duration: u32,
+    timescale: u32,
+    mut limits: Limits,
+) -> ImageResult<Frame> {
+    if picture.bit_depth() != 8 {
+        return Err(ImageError::Unsupported(
+            UnsupportedError::from_format_and_kind(
+                ImageFormat::Avif.into(),
+                UnsupportedErrorKind::GenericFeature(format!(
+                    "{} bit image sequences",
+                    picture.bit_depth()
+                )),
+            ),
+        ));
+    }
+    let (width, height) = (picture.width(), picture.height());
+    limits.check_dimensions(width, height)?;
+    limits.reserve(u64::from(width) * u64::from(height) * 4)?;
+    let mut buf = vec![0; width as usize * height as usize * 4];
+    picture_to_rgba(picture, None, &mut buf)?;
+    let delay = if timescale == 0 {
+        Delay::from_numer_denom_ms(0, 1)
+    } else {
+        let nanos = u64::from(duration) * 1_000_000_000 / u64::from(timescale);
+        Delay::from_saturating_duration(Duration::from_nanos(nanos))
+    };
+    Ok(Frame::from_parts(
+        RgbaImage::from_raw(width, height, buf).unwrap(),
+        0,
+        0,
+        delay,
+    ))
+}
+
+/// Converts a picture, and the picture of its alpha channel, to eight bit RGBA.
+fn picture_to_rgba(
+    picture: &dav1d::Picture,
+    alpha_picture: Option<&dav1d::Picture>,
+    buf: &mut [u8],
+) -> ImageResult<()> {
+    dcp::initialize();
 
-        // Convert Bgra to Rgba
-        for chunk in buf.chunks_exact_mut(4) {
-            chunk.swap(0, 2);
+    if picture.pixel_layout() != PixelLayout::I400 {
+        let pixel_format = match picture.pixel_layout() {
+            PixelLayout::I400 => todo!(),
+            PixelLayout::I420 => dcp::PixelFormat::I420,
+            PixelLayout::I422 => dcp::PixelFormat::I422,
+            PixelLayout::I444 => dcp::PixelFormat::I444,
+            PixelLayout::Unknown => panic!("Unknown pixel layout"),
+        };
+        let src_format = dcp::ImageFormat {
+            pixel_format,
+            color_space: dcp::ColorSpace::Bt601,
+            num_planes: 3,
+        };
+        let dst_format = dcp::ImageFormat {
+            pixel_format: dcp::PixelFormat::Bgra,
+            color_space: dcp::ColorSpace::Lrgb,
+            num_planes: 1,
+        };
+        let (width, height) = (picture.width(), picture.height());
+        let planes = &[
+            picture.plane(PlanarImageComponent::Y),
+            picture.plane(PlanarImageComponent::U),
+            picture.plane(PlanarImageComponent::V),
+        ];
+        let src_buffers = planes.iter().map(AsRef::as_ref).collect::<Vec<_>>();
+        let strides = &[
+            picture.stride(PlanarImageComponent::Y) as usize,
+            picture.stride(PlanarImageComponent::U) as usize,
+            picture.stride(PlanarImageComponent::V) as usize,
+        ];
+        let dst_buffers = &mut [&mut buf[..]];
+        dcp::convert_image(
+            width,
+            height,
+            &src_format,
+            Some(strides),
+            &src_buffers,
+            &dst_format,
+            None,
+            dst_buffers,
+        )
+        .map_err(error_map)?;
+    } else {
+        let plane = picture.plane(PlanarImageComponent::Y);
+        buf.copy_from_slice(plane.as_ref());
+    }
+
+    if let Some(picture) = alpha_picture {
+        assert_eq!(picture.pixel_layout(), PixelLayout::I400);
+        let stride = picture.stride(PlanarImageComponent::Y) as usize;
+        let plane = picture.plane(PlanarImageComponent::Y);
+        let width = picture.width();
+        for (buf, slice) in Iterator::zip(
+            buf.chunks_exact_mut(width as usize * 4),
+            plane.as_ref().chunks_exact(stride),
+        ) {
+            for i in 0..width as usize {
+                buf[3 + i * 4] = slice[i];
+            }
         }
+    }
 
-        Ok(())
+    // Convert Bgra to Rgba
+    for chunk in buf.chunks_exact_mut(4) {
+        chunk.swap(0, 2);
     }
+
+    Ok(())
 }
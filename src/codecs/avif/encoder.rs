@@ -12,7 +12,7 @@ use crate::color::{FromColor, Luma, LumaA, Rgb, Rgba};
 use crate::error::{
     EncodingError, ParameterError, ParameterErrorKind, UnsupportedError, UnsupportedErrorKind,
 };
-use crate::{ColorType, ImageBuffer, ImageFormat, Pixel};
+use crate::{ColorType, ImageBuffer, ImageEncoder, ImageFormat, Pixel};
 use crate::{ImageError, ImageResult};
 
 use bytemuck::{try_cast_slice, try_cast_slice_mut, Pod, PodCastError};
@@ -22,7 +22,8 @@ use rgb::AsPixels;
 
 /// AVIF Encoder.
 ///
-/// Writes one image into the chosen output.
+/// Writes one image into the chosen output. The chroma planes are always stored at full
+/// resolution (4:4:4), the underlying encoder does not subsample them.
 pub struct AvifEncoder<W> {
     inner: W,
     fallback: Vec<u8>,
@@ -48,6 +49,74 @@ impl ColorSpace {
     }
 }
 
+/// The settings of an [`AvifEncoder`].
+///
+/// Construct it with `Default` and set the fields of interest. Pass it to
+/// [`AvifEncoder::new_with_options`] or, through `ImageOutputFormat::AvifWithOptions`, to
+/// `DynamicImage::write_to`.
+///
+/// There is no setting for chroma subsampling. The `ravif` version used here always encodes the
+/// chroma planes at full resolution (4:4:4) and has no option to subsample them. A field can be
+/// added once the encoder supports it, the struct is `#[non_exhaustive]` for such additions.
+///
+/// [`AvifEncoder`]: struct.AvifEncoder.html
+/// [`AvifEncoder::new_with_options`]: struct.AvifEncoder.html#method.new_with_options
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct AvifOptions {
+    /// The encoding effort from 0 to 10, where 0 is the slowest and 10 the fastest. Defaults to 1.
+    pub speed: u8,
+    /// The quality of the color planes from 0 to 100, where 100 is the best. Defaults to 100.
+    pub quality: u8,
+    /// The quality of the alpha plane from 0 to 100, defaults to `quality`.
+    pub alpha_quality: Option<u8>,
+    /// Premultiply the color with the alpha channel before encoding.
+    ///
+    /// This compresses better, but the color of fully transparent pixels is lost.
+    pub premultiplied_alpha: bool,
+    /// The color space of the encoded planes, defaults to `ColorSpace::Srgb`.
+    pub color_space: ColorSpace,
+    /// Encode without any loss, which overrides the qualities and the color space.
+    pub lossless: bool,
+}
+
+impl Default for AvifOptions {
+    fn default() -> Self {
+        AvifOptions {
+            speed: 1,
+            quality: 100,
+            alpha_quality: None,
+            premultiplied_alpha: false,
+            color_space: ColorSpace::Srgb,
+            lossless: false,
+        }
+    }
+}
+
+impl AvifOptions {
+    fn to_config(self) -> Config {
+        // At quality 100 the quantizer is zero, and without a conversion to YCbCr nothing else
+        // of the pixels is lost either.
+        let (quality, alpha_quality, color_space) = if self.lossless {
+            (100, 100, ColorSpace::Srgb)
+        } else {
+            let quality = min(self.quality, 100);
+            let alpha_quality = min(self.alpha_quality.unwrap_or(quality), 100);
+            (quality, alpha_quality, self.color_space)
+        };
+
+        Config {
+            quality: f32::from(quality),
+            alpha_quality: f32::from(alpha_quality),
+            speed: min(self.speed, 10),
+            premultiplied_alpha: self.premultiplied_alpha && !self.lossless,
+            color_space: color_space.to_ravif(),
+            // match core count
+            threads: 0,
+        }
+    }
+}
+
 enum RgbColor<'buf> {
     Rgb8(Img<&'buf [RGB8]>),
     Rgba8(Img<&'buf [RGBA8]>),
@@ -63,22 +132,24 @@ impl<W: Write> AvifEncoder<W> {
     /// `speed` accepts a value in the range 0-10, where 0 is the slowest and 10 is the fastest.
     /// `quality` accepts a value in the range 0-100, where 0 is the worst and 100 is the best.
     pub fn new_with_speed_quality(w: W, speed: u8, quality: u8) -> Self {
-        // Clamp quality and speed to range
-        let quality = min(quality, 100);
-        let speed = min(speed, 10);
+        AvifEncoder::new_with_options(
+            w,
+            AvifOptions {
+                speed,
+                quality,
+                ..AvifOptions::default()
+            },
+        )
+    }
 
+    /// Create a new encoder with all of the specified `options`, that writes its output to `w`.
+    ///
+    /// Out of range speeds and qualities are clamped.
+    pub fn new_with_options(w: W, options: AvifOptions) -> Self {
         AvifEncoder {
             inner: w,
             fallback: vec![],
-            config: Config {
-                quality: f32::from(quality),
-                alpha_quality: f32::from(quality),
-                speed,
-                premultiplied_alpha: false,
-                color_space: ravif::ColorSpace::RGB,
-                // match core count
-                threads: 0,
-            },
+            config: options.to_config(),
         }
     }
 
@@ -88,6 +159,29 @@ impl<W: Write> AvifEncoder<W> {
         self
     }
 
+    /// Encode the alpha plane with a `quality` from 0 to 100, independent of the color planes.
+    pub fn with_alpha_quality(mut self, quality: u8) -> Self {
+        self.config.alpha_quality = f32::from(min(quality, 100));
+        self
+    }
+
+    /// Premultiply the color with the alpha channel, see `AvifOptions::premultiplied_alpha`.
+    pub fn with_premultiplied_alpha(mut self, premultiplied: bool) -> Self {
+        self.config.premultiplied_alpha = premultiplied;
+        self
+    }
+
+    /// Encode without any loss, keeping the configured speed.
+    pub fn with_lossless(mut self) -> Self {
+        self.config = AvifOptions {
+            speed: self.config.speed,
+            lossless: true,
+            ..AvifOptions::default()
+        }
+        .to_config();
+        self
+    }
+
     /// Encode image data with the indicated color type.
     ///
     /// The encoder currently requires all data to be RGBA8, it will be converted internally if
@@ -269,3 +363,59 @@ impl<W: Write> AvifEncoder<W> {
         }
     }
 }
+
+impl<W: Write> ImageEncoder for AvifEncoder<W> {
+    fn write_image(
+        self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        AvifEncoder::write_image(self, buf, width, height, color_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn options_are_clamped_and_overridden_by_lossless() {
+        let config = AvifOptions {
+            speed: 20,
+            quality: 150,
+            alpha_quality: Some(30),
+            ..AvifOptions::default()
+        }
+        .to_config();
+        assert_eq!(config.speed, 10);
+        assert_eq!(config.quality, 100.0);
+        assert_eq!(config.alpha_quality, 30.0);
+
+        let config = AvifOptions {
+            quality: 50,
+            premultiplied_alpha: true,
+            lossless: true,
+            ..AvifOptions::default()
+        }
+        .to_config();
+        assert_eq!((config.quality, config.alpha_quality), (100.0, 100.0));
+        assert!(!config.premultiplied_alpha);
+    }
+
+    #[test]
+    fn encodes_with_options() {
+        let pixels: Vec<u8> = (0..8 * 8 * 4).map(|i| (i * 7) as u8).collect();
+        let options = AvifOptions {
+            speed: 10,
+            quality: 60,
+            ..AvifOptions::default()
+        };
+        let mut out = Vec::new();
+        AvifEncoder::new_with_options(&mut out, options)
+            .write_image(&pixels, 8, 8, ColorType::Rgba8)
+            .unwrap();
+        assert_eq!(&out[4..12], b"ftypavif");
+    }
+}
@@ -6,9 +6,11 @@
 #[cfg(feature = "avif-decoder")]
 pub use self::decoder::AvifDecoder;
 #[cfg(feature = "avif-encoder")]
-pub use self::encoder::{AvifEncoder, ColorSpace};
+pub use self::encoder::{AvifEncoder, AvifOptions, ColorSpace};
 
 #[cfg(feature = "avif-decoder")]
 mod decoder;
 #[cfg(feature = "avif-encoder")]
 mod encoder;
+#[cfg(feature = "avif-decoder")]
+mod sequence;
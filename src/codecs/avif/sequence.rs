@@ -0,0 +1,280 @@
+//! Parsing of the track of an AVIF image sequence.
+//!
+//! Animated AVIF files, of the `avis` brand, store their frames as the samples of a track in the
+//! `moov` box, like a video in an MP4 file. Only the boxes needed to locate the samples of the
+//! first color track and their durations are read.
+//!
+//! # Related Links
+//! * <https://aomediacodec.github.io/av1-avif/#image-sequences> - AVIF image sequences
+//! * <https://aomediacodec.github.io/av1-isobmff/> - Storage of AV1 in ISOBMFF
+
+use std::convert::TryFrom;
+
+/// A frame of an image sequence.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Sample {
+    /// The offset of the coded frame from the start of the file.
+    pub(crate) offset: u64,
+    /// The length of the coded frame in bytes.
+    pub(crate) size: u32,
+    /// How long the frame is shown, in units of the timescale of the sequence.
+    pub(crate) duration: u32,
+}
+
+impl Sample {
+    /// Returns the coded frame within the whole file.
+    pub(crate) fn data<'a>(&self, file: &'a [u8]) -> Option<&'a [u8]> {
+        let start = usize::try_from(self.offset).ok()?;
+        file.get(start..start.checked_add(self.size as usize)?)
+    }
+}
+
+/// The frames of an image sequence and the units of their durations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Sequence {
+    /// The number of duration units per second.
+    pub(crate) timescale: u32,
+    pub(crate) samples: Vec<Sample>,
+}
+
+/// Iterate over the boxes in `data`, yielding the type and the payload of each.
+fn boxes(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let mut position = 0;
+    std::iter::from_fn(move || {
+        let header = data.get(position..position + 8)?;
+        let kind = &header[4..8];
+        let (start, size) = match u32::from_be_bytes([header[0], header[1], header[2], header[3]]) {
+            0 => (position + 8, data.len() - position),
+            1 => {
+                let size = data.get(position + 8..position + 16)?;
+                let size = u64::from_be_bytes(<[u8; 8]>::try_from(size).unwrap());
+                (position + 16, usize::try_from(size).ok()?)
+            }
+            size => (position + 8, size as usize),
+        };
+        let end = position.checked_add(size)?;
+        let payload = data.get(start..end)?;
+        position = end;
+        Some((kind, payload))
+    })
+}
+
+/// Returns the payload of the first child box of the given type.
+fn child<'a>(data: &'a [u8], kind: &[u8]) -> Option<&'a [u8]> {
+    boxes(data)
+        .find(|&(k, _)| k == kind)
+        .map(|(_, payload)| payload)
+}
+
+/// Reads the big endian integers of a full box, after its version and flags.
+fn u32_at(payload: &[u8], index: usize) -> Option<u32> {
+    let bytes = payload.get(4 + 4 * index..8 + 4 * index)?;
+    Some(u32::from_be_bytes(<[u8; 4]>::try_from(bytes).unwrap()))
+}
+
+/// Returns whether the file type box declares an image sequence.
+pub(crate) fn is_sequence(data: &[u8]) -> bool {
+    match boxes(data).next() {
+        Some((kind, payload)) if kind == b"ftyp" => payload
+            .chunks_exact(4)
+            .enumerate()
+            // The major brand and the compatible brands, skipping the minor version.
+            .any(|(i, brand)| i != 1 && brand == b"avis"),
+        _ => false,
+    }
+}
+
+/// Locates the frames of the first color track of an image sequence.
+///
+/// Tracks that are auxiliary to another track, such as alpha planes, are skipped. Returns `None`
+/// if there is no such track or its sample tables are invalid.
+pub(crate) fn parse(data: &[u8]) -> Option<Sequence> {
+    let moov = child(data, b"moov")?;
+    boxes(moov)
+        .filter(|&(kind, _)| kind == b"trak")
+        .find_map(|(_, trak)| parse_track(trak, data.len()))
+}
+
+fn parse_track(trak: &[u8], file_len: usize) -> Option<Sequence> {
+    if let Some(tref) = child(trak, b"tref") {
+        if child(tref, b"auxl").is_some() {
+            return None;
+        }
+    }
+    let mdia = child(trak, b"mdia")?;
+    let handler = child(mdia, b"hdlr")?.get(8..12)?;
+    if handler != b"pict" && handler != b"vide" {
+        return None;
+    }
+    let mdhd = child(mdia, b"mdhd")?;
+    let timescale = match mdhd.first()? {
+        0 => u32_at(mdhd, 2)?,
+        1 => u32_at(mdhd, 4)?,
+        _ => return None,
+    };
+
+    let stbl = child(child(mdia, b"minf")?, b"stbl")?;
+    if boxes(child(stbl, b"stsd")?.get(8..)?).next()?.0 != b"av01" {
+        return None;
+    }
+
+    let stsz = child(stbl, b"stsz")?;
+    let count = u32_at(stsz, 1)? as usize;
+    // Every frame takes up at least one byte of the file.
+    if count > file_len {
+        return None;
+    }
+    let sizes = match u32_at(stsz, 0)? {
+        0 => (0..count)
+            .map(|i| u32_at(stsz, 2 + i))
+            .collect::<Option<Vec<u32>>>()?,
+        size => vec![size; count],
+    };
+
+    let mut durations = Vec::with_capacity(count);
+    let stts = child(stbl, b"stts")?;
+    for i in 0..u32_at(stts, 0)? as usize {
+        let (samples, delta) = (u32_at(stts, 1 + 2 * i)?, u32_at(stts, 2 + 2 * i)?);
+        let samples = (samples as usize).min(count - durations.len());
+        durations.extend(std::iter::repeat(delta).take(samples));
+    }
+    durations.resize(count, 0);
+
+    let chunks: Vec<u64> = if let Some(stco) = child(stbl, b"stco") {
+        (0..u32_at(stco, 0)? as usize)
+            .map(|i| u32_at(stco, 1 + i).map(u64::from))
+            .collect::<Option<_>>()?
+    } else {
+        let co64 = child(stbl, b"co64")?;
+        (0..u32_at(co64, 0)? as usize)
+            .map(|i| {
+                let high = u32_at(co64, 1 + 2 * i)?;
+                let low = u32_at(co64, 2 + 2 * i)?;
+                Some(u64::from(high) << 32 | u64::from(low))
+            })
+            .collect::<Option<_>>()?
+    };
+
+    // Runs of chunks with the same number of samples, by their first chunk counted from one.
+    let stsc = child(stbl, b"stsc")?;
+    let runs = (0..u32_at(stsc, 0)? as usize)
+        .map(|i| Some((u32_at(stsc, 1 + 3 * i)?, u32_at(stsc, 2 + 3 * i)?)))
+        .collect::<Option<Vec<_>>>()?;
+
+    // The chunks are visited in order, so the runs are walked along with them.
+    let (mut run, mut per_chunk) = (0, 0);
+    let mut samples = Vec::with_capacity(count);
+    for (index, &chunk_offset) in chunks.iter().enumerate() {
+        let chunk = index as u32 + 1;
+        while run < runs.len() && runs[run].0 <= chunk {
+            per_chunk = runs[run].1;
+            run += 1;
+        }
+        let mut offset = chunk_offset;
+        for _ in 0..per_chunk {
+            let i = samples.len();
+            if i == count {
+                break;
+            }
+            samples.push(Sample {
+                offset,
+                size: sizes[i],
+                duration: durations[i],
+            });
+            offset = offset.checked_add(u64::from(sizes[i]))?;
+        }
+    }
+    if samples.len() != count || samples.is_empty() {
+        return None;
+    }
+    Some(Sequence { timescale, samples })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_box(kind: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mut data = (payload.len() as u32 + 8).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    fn full_box(kind: &[u8], values: &[u32]) -> Vec<u8> {
+        let mut payload = vec![0; 4];
+        for value in values {
+            payload.extend_from_slice(&value.to_be_bytes());
+        }
+        make_box(kind, &payload)
+    }
+
+    fn track(handler: &[u8], auxiliary: bool) -> Vec<u8> {
+        let mut hdlr = vec![0; 8];
+        hdlr.extend_from_slice(handler);
+        hdlr.extend_from_slice(&[0; 13]);
+        let mdhd = full_box(b"mdhd", &[0, 0, 600, 0, 0]);
+        let mut stsd = vec![0, 0, 0, 0, 0, 0, 0, 1];
+        stsd.extend(make_box(b"av01", &[0; 8]));
+        let stbl = [
+            make_box(b"stsd", &stsd),
+            // Five frames, the last one shown longer.
+            full_box(b"stts", &[2, 4, 60, 1, 120]),
+            // Two chunks of two frames and then a chunk of one.
+            full_box(b"stsc", &[2, 1, 2, 1, 3, 1, 1]),
+            full_box(b"stsz", &[0, 5, 10, 11, 12, 13, 14]),
+            full_box(b"stco", &[3, 1000, 2000, 3000]),
+        ]
+        .concat();
+        let minf = make_box(b"stbl", &stbl);
+        let mdia = [mdhd, make_box(b"hdlr", &hdlr), make_box(b"minf", &minf)].concat();
+        let mut trak = Vec::new();
+        if auxiliary {
+            trak.extend(make_box(b"tref", &make_box(b"auxl", &1u32.to_be_bytes())));
+        }
+        trak.extend(make_box(b"mdia", &mdia));
+        make_box(b"trak", &trak)
+    }
+
+    #[test]
+    fn sample_table() {
+        let ftyp = make_box(b"ftyp", b"avis\0\0\0\0avifmsf1");
+        let moov = make_box(
+            b"moov",
+            &[track(b"pict", false), track(b"auxv", true)].concat(),
+        );
+        let file = [ftyp, moov].concat();
+        assert!(is_sequence(&file));
+
+        let sequence = parse(&file).unwrap();
+        assert_eq!(sequence.timescale, 600);
+        let sample = |offset, size, duration| Sample {
+            offset,
+            size,
+            duration,
+        };
+        assert_eq!(
+            sequence.samples,
+            vec![
+                sample(1000, 10, 60),
+                sample(1010, 11, 60),
+                sample(2000, 12, 60),
+                sample(2012, 13, 60),
+                sample(3000, 14, 120),
+            ]
+        );
+    }
+
+    #[test]
+    fn still_images_and_auxiliary_tracks() {
+        let ftyp = make_box(b"ftyp", b"avif\0\0\0\0mif1miaf");
+        assert!(!is_sequence(&ftyp));
+        // The minor version is not a brand.
+        assert!(!is_sequence(&make_box(b"ftyp", b"avifavis")));
+
+        let moov = make_box(b"moov", &track(b"pict", true));
+        assert_eq!(parse(&moov), None);
+        let moov = make_box(b"moov", &track(b"soun", false));
+        assert_eq!(parse(&moov), None);
+    }
+}
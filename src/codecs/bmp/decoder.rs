@@ -13,6 +13,7 @@ use crate::error::{
     DecodingError, ImageError, ImageResult, UnsupportedError, UnsupportedErrorKind,
 };
 use crate::image::{self, ImageDecoder, ImageDecoderRect, ImageFormat, Progress};
+use crate::io::Limits;
 
 const BITMAPCOREHEADER_SIZE: u32 = 12;
 const BITMAPINFOHEADER_SIZE: u32 = 40;
@@ -319,11 +320,10 @@ fn extend_buffer(buffer: &mut Vec<u8>, full_size: usize, blank: bool) -> &mut [u
 }
 
 /// Call the provided function on each row of the provided buffer, returning Err if the provided
-/// function returns an error, extends the buffer if it's not large enough.
+/// function returns an error.
 fn with_rows<F>(
-    buffer: &mut Vec<u8>,
+    buffer: &mut [u8],
     width: i32,
-    height: i32,
     channels: usize,
     top_down: bool,
     mut func: F,
@@ -331,36 +331,15 @@ fn with_rows<F>(
 where
     F: FnMut(&mut [u8]) -> io::Result<()>,
 {
-    // An overflow should already have been checked for when this is called,
-    // though we check anyhow, as it somehow seems to increase performance slightly.
-    let row_width = channels.checked_mul(width as usize).unwrap();
-    let full_image_size = row_width.checked_mul(height as usize).unwrap();
-
-    if !top_down {
-        for row in buffer.chunks_mut(row_width).rev() {
+    let row_width = channels * width as usize;
+    if top_down {
+        for row in buffer.chunks_mut(row_width) {
             func(row)?;
         }
-
-        // If we need more space, extend the buffer.
-        if buffer.len() < full_image_size {
-            let new_space = extend_buffer(buffer, full_image_size, false);
-            for row in new_space.chunks_mut(row_width).rev() {
-                func(row)?;
-            }
-        }
     } else {
-        for row in buffer.chunks_mut(row_width) {
+        for row in buffer.chunks_mut(row_width).rev() {
             func(row)?;
         }
-        if buffer.len() < full_image_size {
-            // If the image is stored in top-down order, we can simply use the extend function
-            // from vec to extend the buffer..
-            buffer.resize(full_image_size, 0xFF);
-            let len = buffer.len();
-            for row in buffer[len - row_width..].chunks_mut(row_width) {
-                func(row)?;
-            }
-        };
     }
     Ok(())
 }
@@ -561,6 +540,9 @@ pub struct BmpDecoder<R> {
     colors_used: u32,
     palette: Option<Vec<[u8; 3]>>,
     bitfields: Option<Bitfields>,
+
+    /// The limits on the run length encoded data that is collected next to the output buffer.
+    limits: Limits,
 }
 
 enum RLEInsn {
@@ -650,6 +632,8 @@ impl<R: Read + Seek> BmpDecoder<R> {
             colors_used: 0,
             palette: None,
             bitfields: None,
+
+            limits: Limits::no_limits(),
         };
 
         decoder.read_metadata()?;
@@ -677,6 +661,8 @@ impl<R: Read + Seek> BmpDecoder<R> {
             colors_used: 0,
             palette: None,
             bitfields: None,
+
+            limits: Limits::no_limits(),
         };
 
         decoder.read_metadata_in_ico_format()?;
@@ -1098,8 +1084,7 @@ impl<R: Read + Seek> BmpDecoder<R> {
         }
     }
 
-    fn read_palettized_pixel_data(&mut self) -> ImageResult<Vec<u8>> {
-        let mut pixel_data = self.create_pixel_data();
+    fn read_palettized_pixel_data(&mut self, pixel_data: &mut [u8]) -> ImageResult<()> {
         let num_channels = self.num_channels();
         let row_byte_length = ((i32::from(self.bit_count) * self.width + 31) / 32 * 4) as usize;
         let mut indices = vec![0; row_byte_length];
@@ -1111,43 +1096,39 @@ impl<R: Read + Seek> BmpDecoder<R> {
 
         reader.seek(SeekFrom::Start(self.data_offset))?;
 
-        with_rows(
-            &mut pixel_data,
-            self.width,
-            self.height,
-            num_channels,
-            self.top_down,
-            |row| {
-                reader.read_exact(&mut indices)?;
-                if skip_palette {
-                    row.clone_from_slice(&indices[0..width]);
-                } else {
-                    let mut pixel_iter = row.chunks_mut(num_channels);
-                    match bit_count {
-                        1 => {
-                            set_1bit_pixel_run(&mut pixel_iter, palette, indices.iter());
-                        }
-                        2 => {
-                            set_2bit_pixel_run(&mut pixel_iter, palette, indices.iter(), width);
-                        }
-                        4 => {
-                            set_4bit_pixel_run(&mut pixel_iter, palette, indices.iter(), width);
-                        }
-                        8 => {
-                            set_8bit_pixel_run(&mut pixel_iter, palette, indices.iter(), width);
-                        }
-                        _ => panic!(),
-                    };
-                }
-                Ok(())
-            },
-        )?;
+        with_rows(pixel_data, self.width, num_channels, self.top_down, |row| {
+            reader.read_exact(&mut indices)?;
+            if skip_palette {
+                row.clone_from_slice(&indices[0..width]);
+            } else {
+                let mut pixel_iter = row.chunks_mut(num_channels);
+                match bit_count {
+                    1 => {
+                        set_1bit_pixel_run(&mut pixel_iter, palette, indices.iter());
+                    }
+                    2 => {
+                        set_2bit_pixel_run(&mut pixel_iter, palette, indices.iter(), width);
+                    }
+                    4 => {
+                        set_4bit_pixel_run(&mut pixel_iter, palette, indices.iter(), width);
+                    }
+                    8 => {
+                        set_8bit_pixel_run(&mut pixel_iter, palette, indices.iter(), width);
+                    }
+                    _ => panic!(),
+                };
+            }
+            Ok(())
+        })?;
 
-        Ok(pixel_data)
+        Ok(())
     }
 
-    fn read_16_bit_pixel_data(&mut self, bitfields: Option<&Bitfields>) -> ImageResult<Vec<u8>> {
-        let mut pixel_data = self.create_pixel_data();
+    fn read_16_bit_pixel_data(
+        &mut self,
+        pixel_data: &mut [u8],
+        bitfields: Option<&Bitfields>,
+    ) -> ImageResult<()> {
         let num_channels = self.num_channels();
         let row_padding_len = self.width as usize % 2 * 2;
         let row_padding = &mut [0; 2][..row_padding_len];
@@ -1159,33 +1140,25 @@ impl<R: Read + Seek> BmpDecoder<R> {
 
         reader.seek(SeekFrom::Start(self.data_offset))?;
 
-        with_rows(
-            &mut pixel_data,
-            self.width,
-            self.height,
-            num_channels,
-            self.top_down,
-            |row| {
-                for pixel in row.chunks_mut(num_channels) {
-                    let data = u32::from(reader.read_u16::<LittleEndian>()?);
-
-                    pixel[0] = bitfields.r.read(data);
-                    pixel[1] = bitfields.g.read(data);
-                    pixel[2] = bitfields.b.read(data);
-                    if num_channels == 4 && bitfields.a.len != 0 {
-                        pixel[3] = bitfields.a.read(data);
-                    }
+        with_rows(pixel_data, self.width, num_channels, self.top_down, |row| {
+            for pixel in row.chunks_mut(num_channels) {
+                let data = u32::from(reader.read_u16::<LittleEndian>()?);
+
+                pixel[0] = bitfields.r.read(data);
+                pixel[1] = bitfields.g.read(data);
+                pixel[2] = bitfields.b.read(data);
+                if num_channels == 4 && bitfields.a.len != 0 {
+                    pixel[3] = bitfields.a.read(data);
                 }
-                reader.read_exact(row_padding)
-            },
-        )?;
+            }
+            reader.read_exact(row_padding)
+        })?;
 
-        Ok(pixel_data)
+        Ok(())
     }
 
     /// Read image data from a reader in 32-bit formats that use bitfields.
-    fn read_32_bit_pixel_data(&mut self) -> ImageResult<Vec<u8>> {
-        let mut pixel_data = self.create_pixel_data();
+    fn read_32_bit_pixel_data(&mut self, pixel_data: &mut [u8]) -> ImageResult<()> {
         let num_channels = self.num_channels();
 
         let bitfields = self.bitfields.as_ref().unwrap();
@@ -1193,33 +1166,29 @@ impl<R: Read + Seek> BmpDecoder<R> {
         let reader = &mut self.reader;
         reader.seek(SeekFrom::Start(self.data_offset))?;
 
-        with_rows(
-            &mut pixel_data,
-            self.width,
-            self.height,
-            num_channels,
-            self.top_down,
-            |row| {
-                for pixel in row.chunks_mut(num_channels) {
-                    let data = reader.read_u32::<LittleEndian>()?;
-
-                    pixel[0] = bitfields.r.read(data);
-                    pixel[1] = bitfields.g.read(data);
-                    pixel[2] = bitfields.b.read(data);
-                    if num_channels == 4 && bitfields.a.len != 0 {
-                        pixel[3] = bitfields.a.read(data);
-                    }
+        with_rows(pixel_data, self.width, num_channels, self.top_down, |row| {
+            for pixel in row.chunks_mut(num_channels) {
+                let data = reader.read_u32::<LittleEndian>()?;
+
+                pixel[0] = bitfields.r.read(data);
+                pixel[1] = bitfields.g.read(data);
+                pixel[2] = bitfields.b.read(data);
+                if num_channels == 4 && bitfields.a.len != 0 {
+                    pixel[3] = bitfields.a.read(data);
                 }
-                Ok(())
-            },
-        )?;
+            }
+            Ok(())
+        })?;
 
-        Ok(pixel_data)
+        Ok(())
     }
 
     /// Read image data from a reader where the colours are stored as 8-bit values (24 or 32-bit).
-    fn read_full_byte_pixel_data(&mut self, format: &FormatFullBytes) -> ImageResult<Vec<u8>> {
-        let mut pixel_data = self.create_pixel_data();
+    fn read_full_byte_pixel_data(
+        &mut self,
+        pixel_data: &mut [u8],
+        format: &FormatFullBytes,
+    ) -> ImageResult<()> {
         let num_channels = self.num_channels();
         let row_padding_len = match *format {
             FormatFullBytes::RGB24 => (4 - (self.width as usize * 3) % 4) % 4,
@@ -1231,38 +1200,31 @@ impl<R: Read + Seek> BmpDecoder<R> {
 
         let reader = &mut self.reader;
 
-        with_rows(
-            &mut pixel_data,
-            self.width,
-            self.height,
-            num_channels,
-            self.top_down,
-            |row| {
-                for pixel in row.chunks_mut(num_channels) {
-                    if *format == FormatFullBytes::Format888 {
-                        reader.read_u8()?;
-                    }
+        with_rows(pixel_data, self.width, num_channels, self.top_down, |row| {
+            for pixel in row.chunks_mut(num_channels) {
+                if *format == FormatFullBytes::Format888 {
+                    reader.read_u8()?;
+                }
 
-                    // Read the colour values (b, g, r).
-                    // Reading 3 bytes and reversing them is significantly faster than reading one
-                    // at a time.
-                    reader.read_exact(&mut pixel[0..3])?;
-                    pixel[0..3].reverse();
+                // Read the colour values (b, g, r).
+                // Reading 3 bytes and reversing them is significantly faster than reading one
+                // at a time.
+                reader.read_exact(&mut pixel[0..3])?;
+                pixel[0..3].reverse();
 
-                    if *format == FormatFullBytes::RGB32 {
-                        reader.read_u8()?;
-                    }
+                if *format == FormatFullBytes::RGB32 {
+                    reader.read_u8()?;
+                }
 
-                    // Read the alpha channel if present
-                    if *format == FormatFullBytes::RGBA32 {
-                        reader.read_exact(&mut pixel[3..4])?;
-                    }
+                // Read the alpha channel if present
+                if *format == FormatFullBytes::RGBA32 {
+                    reader.read_exact(&mut pixel[3..4])?;
                 }
-                reader.read_exact(row_padding)
-            },
-        )?;
+            }
+            reader.read_exact(row_padding)
+        })?;
 
-        Ok(pixel_data)
+        Ok(())
     }
 
     fn read_rle_data(&mut self, image_type: ImageType) -> ImageResult<Vec<u8>> {
@@ -1281,6 +1243,7 @@ impl<R: Read + Seek> BmpDecoder<R> {
                     )),
                 ))
             })?;
+        self.limits.clone().reserve(full_image_size as u64)?;
         let mut pixel_data = self.create_pixel_data();
         let (skip_pixels, skip_rows, eof_hit) =
             self.read_rle_data_step(&mut pixel_data, image_type, 0, 0)?;
@@ -1450,31 +1413,78 @@ impl<R: Read + Seek> BmpDecoder<R> {
     /// Read the actual data of the image. This function is deliberately not public because it
     /// cannot be called multiple times without seeking back the underlying reader in between.
     pub(crate) fn read_image_data(&mut self, buf: &mut [u8]) -> ImageResult<()> {
-        let data = match self.image_type {
-            ImageType::Palette => self.read_palettized_pixel_data(),
-            ImageType::RGB16 => self.read_16_bit_pixel_data(Some(&R5_G5_B5_COLOR_MASK)),
-            ImageType::RGB24 => self.read_full_byte_pixel_data(&FormatFullBytes::RGB24),
-            ImageType::RGB32 => self.read_full_byte_pixel_data(&FormatFullBytes::RGB32),
-            ImageType::RGBA32 => self.read_full_byte_pixel_data(&FormatFullBytes::RGBA32),
-            ImageType::RLE8 => self.read_rle_data(ImageType::RLE8),
-            ImageType::RLE4 => self.read_rle_data(ImageType::RLE4),
+        // Rows of a fixed size are decoded directly into `buf`, only run length encoded data is
+        // collected first. Pixels without alpha are opaque, which the ICO decoder relies on.
+        if self.num_channels() == 4 {
+            buf.fill(0xFF);
+        }
+        match self.image_type {
+            ImageType::Palette => self.read_palettized_pixel_data(buf),
+            ImageType::RGB16 => self.read_16_bit_pixel_data(buf, Some(&R5_G5_B5_COLOR_MASK)),
+            ImageType::RGB24 => self.read_full_byte_pixel_data(buf, &FormatFullBytes::RGB24),
+            ImageType::RGB32 => self.read_full_byte_pixel_data(buf, &FormatFullBytes::RGB32),
+            ImageType::RGBA32 => self.read_full_byte_pixel_data(buf, &FormatFullBytes::RGBA32),
+            ImageType::RLE8 | ImageType::RLE4 => {
+                let data = self.read_rle_data(self.image_type)?;
+                buf.copy_from_slice(&data);
+                Ok(())
+            }
             ImageType::Bitfields16 => match self.bitfields {
-                Some(_) => self.read_16_bit_pixel_data(None),
+                Some(_) => self.read_16_bit_pixel_data(buf, None),
                 None => Err(DecoderError::BitfieldMasksMissing(16).into()),
             },
             ImageType::Bitfields32 => match self.bitfields {
                 Some(R8_G8_B8_COLOR_MASK) => {
-                    self.read_full_byte_pixel_data(&FormatFullBytes::Format888)
+                    self.read_full_byte_pixel_data(buf, &FormatFullBytes::Format888)
                 }
                 Some(R8_G8_B8_A8_COLOR_MASK) => {
-                    self.read_full_byte_pixel_data(&FormatFullBytes::RGBA32)
+                    self.read_full_byte_pixel_data(buf, &FormatFullBytes::RGBA32)
                 }
-                Some(_) => self.read_32_bit_pixel_data(),
+                Some(_) => self.read_32_bit_pixel_data(buf),
                 None => Err(DecoderError::BitfieldMasksMissing(32).into()),
             },
-        }?;
+        }
+    }
 
-        buf.copy_from_slice(&data);
+    /// Decode a section of an image that is not run length encoded.
+    ///
+    /// The rows of such images have a fixed size, so the rows that overlap the section are
+    /// decoded as if they formed an image of their own, without decoding any other rows.
+    fn read_rect_rows<F: Fn(Progress)>(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        buf: &mut [u8],
+        progress_callback: F,
+    ) -> ImageResult<()> {
+        let total_bytes = image::check_rect(self, x, y, width, height, buf)?;
+        progress_callback(Progress::new(0, total_bytes as u64));
+
+        let row_stride = ((i32::from(self.bit_count) * self.width + 31) / 32 * 4) as u64;
+        let first_row = if self.top_down {
+            y
+        } else {
+            self.height as u32 - y - height
+        };
+        let num_channels = self.num_channels();
+        let mut rows = vec![0; self.width as usize * num_channels * height as usize];
+
+        let (data_offset, image_height) = (self.data_offset, self.height);
+        self.data_offset += u64::from(first_row) * row_stride;
+        self.height = height as i32;
+        let result = self.read_image_data(&mut rows);
+        self.data_offset = data_offset;
+        self.height = image_height;
+        result?;
+
+        let row_bytes = width as usize * num_channels;
+        let rows = rows.chunks(self.width as usize * num_channels);
+        for (out, row) in buf[..total_bytes].chunks_mut(row_bytes).zip(rows) {
+            out.copy_from_slice(&row[x as usize * num_channels..][..row_bytes]);
+        }
+        progress_callback(Progress::new(total_bytes as u64, total_bytes as u64));
         Ok(())
     }
 }
@@ -1512,6 +1522,14 @@ impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for BmpDecoder<R> {
         }
     }
 
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        self.limits = limits;
+        Ok(())
+    }
+
     fn into_reader(self) -> ImageResult<Self::Reader> {
         Ok(BmpReader(
             Cursor::new(image::decoder_to_vec(self)?),
@@ -1536,17 +1554,21 @@ impl<'a, R: 'a + Read + Seek> ImageDecoderRect<'a> for BmpDecoder<R> {
         progress_callback: F,
     ) -> ImageResult<()> {
         let start = self.reader.seek(SeekFrom::Current(0))?;
-        image::load_rect(
-            x,
-            y,
-            width,
-            height,
-            buf,
-            progress_callback,
-            self,
-            |_, _| Ok(()),
-            |s, buf| s.read_image_data(buf),
-        )?;
+        match self.image_type {
+            // Run length encoded rows have no fixed size, so the whole image is decoded.
+            ImageType::RLE8 | ImageType::RLE4 => image::load_rect(
+                x,
+                y,
+                width,
+                height,
+                buf,
+                progress_callback,
+                self,
+                |_, _| Ok(()),
+                |s, buf| s.read_image_data(buf),
+            )?,
+            _ => self.read_rect_rows(x, y, width, height, buf, progress_callback)?,
+        }
         self.reader.seek(SeekFrom::Start(start))?;
         Ok(())
     }
@@ -1580,6 +1602,42 @@ mod test {
         decoder.read_rect(0, 0, 8, 8, &mut *buf).unwrap();
     }
 
+    #[test]
+    fn read_rect_matches_image() {
+        for path in &[
+            "tests/images/bmp/images/Core_4_Bit.bmp",
+            "tests/images/bmp/images/Info_8_Bit_Top_Down.bmp",
+            "tests/images/bmp/images/Info_R8_G8_B8.bmp",
+            "tests/images/bmp/images/V3_R5_G6_B5.bmp",
+            "tests/images/bmp/images/V3_X8_R8_G8_B8_Top_Down.bmp",
+            "tests/images/bmp/images/pal4rle.bmp",
+        ] {
+            let data = std::fs::read(path).unwrap();
+            let decoder = BmpDecoder::new(Cursor::new(&data)).unwrap();
+            let (image_width, _) = decoder.dimensions();
+            let bytes_per_pixel = usize::from(decoder.color_type().bytes_per_pixel());
+            let mut image = vec![0; decoder.total_bytes() as usize];
+            decoder.read_image(&mut image).unwrap();
+
+            let mut decoder = BmpDecoder::new(Cursor::new(&data)).unwrap();
+            let (x, y, width, height) = (1, 2, 4, 3);
+            let mut buf = vec![0; width * height * bytes_per_pixel];
+            for _ in 0..2 {
+                decoder
+                    .read_rect(x as u32, y as u32, width as u32, height as u32, &mut buf)
+                    .unwrap();
+                for (row, out) in buf.chunks(width * bytes_per_pixel).enumerate() {
+                    let start = ((y + row) * image_width as usize + x) * bytes_per_pixel;
+                    assert_eq!(out, &image[start..][..out.len()], "{}", path);
+                }
+            }
+            let mut row = vec![0; (image_width as usize + 1) * bytes_per_pixel];
+            assert!(decoder
+                .read_rect(0, 0, image_width + 1, 1, &mut row)
+                .is_err());
+        }
+    }
+
     #[test]
     fn read_rle_too_short() {
         let data = vec![
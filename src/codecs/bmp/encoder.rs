@@ -3,7 +3,7 @@ use std::io::{self, Write};
 
 use crate::color;
 use crate::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
-use crate::image::ImageEncoder;
+use crate::image::{ImageEncoder, RowProgress, StreamingEncoder};
 
 const BITMAPFILEHEADER_SIZE: u32 = 14;
 const BITMAPINFOHEADER_SIZE: u32 = 40;
@@ -43,6 +43,50 @@ impl<'a, W: Write + 'a> BmpEncoder<'a, W> {
         c: color::ColorType,
         palette: Option<&[[u8; 3]]>,
     ) -> ImageResult<()> {
+        let row_pad_size = self.write_header(width, height, c, palette, false)?;
+
+        // write image data
+        let y_stride = width as usize * usize::from(c.bytes_per_pixel());
+        for row in (0..height as usize).rev() {
+            // from the bottom up
+            self.write_row(&image[row * y_stride..][..y_stride], c, row_pad_size)?;
+        }
+
+        Ok(())
+    }
+
+    /// Start writing an image of `width`, `height` and `ColorType` `c` row by row, see
+    /// [`StreamingEncoder`].
+    ///
+    /// The image is stored top-down, which BMP allows for uncompressed images, so that every row
+    /// is written as soon as it arrives.
+    ///
+    /// [`StreamingEncoder`]: ../../trait.StreamingEncoder.html
+    pub fn streaming(
+        mut self,
+        width: u32,
+        height: u32,
+        c: color::ColorType,
+    ) -> ImageResult<StreamingBmpEncoder<'a, W>> {
+        let progress = RowProgress::new(width, height, c)?;
+        let row_pad_size = self.write_header(width, height, c, None, true)?;
+        Ok(StreamingBmpEncoder {
+            encoder: self,
+            color: c,
+            row_pad_size,
+            progress,
+        })
+    }
+
+    /// Write the headers and the palette, and return the padding of each row.
+    fn write_header(
+        &mut self,
+        width: u32,
+        height: u32,
+        c: color::ColorType,
+        palette: Option<&[[u8; 3]]>,
+        top_down: bool,
+    ) -> ImageResult<u32> {
         if palette.is_some() && c != color::ColorType::L8 && c != color::ColorType::La8 {
             return Err(ImageError::IoError(io::Error::new(
                 io::ErrorKind::InvalidInput,
@@ -82,7 +126,12 @@ impl<'a, W: Write + 'a> BmpEncoder<'a, W> {
         // write DIB header
         self.writer.write_u32::<LittleEndian>(dib_header_size)?;
         self.writer.write_i32::<LittleEndian>(width as i32)?;
-        self.writer.write_i32::<LittleEndian>(height as i32)?;
+        if top_down {
+            // a negative height stores the rows from the top down
+            self.writer.write_i32::<LittleEndian>(-(height as i32))?;
+        } else {
+            self.writer.write_i32::<LittleEndian>(height as i32)?;
+        }
         self.writer.write_u16::<LittleEndian>(1)?; // color planes
         self.writer
             .write_u16::<LittleEndian>((written_pixel_size * 8) as u16)?; // bits per pixel
@@ -111,123 +160,50 @@ impl<'a, W: Write + 'a> BmpEncoder<'a, W> {
             }
         }
 
-        // write image data
-        match c {
-            color::ColorType::Rgb8 => self.encode_rgb(image, width, height, row_pad_size, 3)?,
-            color::ColorType::Rgba8 => self.encode_rgba(image, width, height, row_pad_size, 4)?,
-            color::ColorType::L8 => {
-                self.encode_gray(image, width, height, row_pad_size, 1, palette)?
-            }
-            color::ColorType::La8 => {
-                self.encode_gray(image, width, height, row_pad_size, 2, palette)?
-            }
-            _ => {
-                return Err(ImageError::IoError(io::Error::new(
-                    io::ErrorKind::InvalidInput,
-                    &get_unsupported_error_message(c)[..],
-                )))
-            }
-        }
-
-        Ok(())
-    }
-
-    fn encode_rgb(
-        &mut self,
-        image: &[u8],
-        width: u32,
-        height: u32,
-        row_pad_size: u32,
-        bytes_per_pixel: u32,
-    ) -> io::Result<()> {
-        let width = width as usize;
-        let height = height as usize;
-        let x_stride = bytes_per_pixel as usize;
-        let y_stride = width * x_stride;
-        for row in (0..height).rev() {
-            // from the bottom up
-            let row_start = row * y_stride;
-            for px in image[row_start..][..y_stride].chunks_exact(x_stride) {
-                let r = px[0];
-                let g = px[1];
-                let b = px[2];
-                // written as BGR
-                self.writer.write_all(&[b, g, r])?;
-            }
-            self.write_row_pad(row_pad_size)?;
-        }
-
-        Ok(())
-    }
-
-    fn encode_rgba(
-        &mut self,
-        image: &[u8],
-        width: u32,
-        height: u32,
-        row_pad_size: u32,
-        bytes_per_pixel: u32,
-    ) -> io::Result<()> {
-        let width = width as usize;
-        let height = height as usize;
-        let x_stride = bytes_per_pixel as usize;
-        let y_stride = width * x_stride;
-        for row in (0..height).rev() {
-            // from the bottom up
-            let row_start = row * y_stride;
-            for px in image[row_start..][..y_stride].chunks_exact(x_stride) {
-                let r = px[0];
-                let g = px[1];
-                let b = px[2];
-                let a = px[3];
-                // written as BGRA
-                self.writer.write_all(&[b, g, r, a])?;
+        if let color::ColorType::L8 | color::ColorType::La8 = c {
+            // write grayscale palette
+            if let Some(palette) = palette {
+                for item in palette {
+                    // each color is written as BGRA, where A is always 0
+                    self.writer.write_all(&[item[2], item[1], item[0], 0])?;
+                }
+            } else {
+                for val in 0u8..=255 {
+                    // each color is written as BGRA, where A is always 0 and since only grayscale is being written, B = G = R = index
+                    self.writer.write_all(&[val, val, val, 0])?;
+                }
             }
-            self.write_row_pad(row_pad_size)?;
         }
 
-        Ok(())
+        Ok(row_pad_size)
     }
 
-    fn encode_gray(
-        &mut self,
-        image: &[u8],
-        width: u32,
-        height: u32,
-        row_pad_size: u32,
-        bytes_per_pixel: u32,
-        palette: Option<&[[u8; 3]]>,
-    ) -> io::Result<()> {
-        // write grayscale palette
-        if let Some(palette) = palette {
-            for item in palette {
-                // each color is written as BGRA, where A is always 0
-                self.writer.write_all(&[item[2], item[1], item[0], 0])?;
+    /// Write a row of the image, which `write_header` checked the color type of.
+    fn write_row(&mut self, row: &[u8], c: color::ColorType, row_pad_size: u32) -> io::Result<()> {
+        match c {
+            color::ColorType::Rgb8 => {
+                for px in row.chunks_exact(3) {
+                    // written as BGR
+                    self.writer.write_all(&[px[2], px[1], px[0]])?;
+                }
             }
-        } else {
-            for val in 0u8..=255 {
-                // each color is written as BGRA, where A is always 0 and since only grayscale is being written, B = G = R = index
-                self.writer.write_all(&[val, val, val, 0])?;
+            color::ColorType::Rgba8 => {
+                for px in row.chunks_exact(4) {
+                    // written as BGRA
+                    self.writer.write_all(&[px[2], px[1], px[0], px[3]])?;
+                }
             }
-        }
-
-        // write image data
-        let x_stride = bytes_per_pixel;
-        let y_stride = width * x_stride;
-        for row in (0..height).rev() {
-            // from the bottom up
-            let row_start = row * y_stride;
-            for col in 0..width {
-                let pixel_start = (row_start + (col * x_stride)) as usize;
-                // color value is equal to the palette index
-                self.writer.write_u8(image[pixel_start])?;
-                // alpha is never written as it's not widely supported
+            color::ColorType::L8 | color::ColorType::La8 => {
+                for px in row.chunks_exact(usize::from(c.bytes_per_pixel())) {
+                    // color value is equal to the palette index
+                    self.writer.write_u8(px[0])?;
+                    // alpha is never written as it's not widely supported
+                }
             }
-
-            self.write_row_pad(row_pad_size)?;
+            _ => unreachable!(),
         }
 
-        Ok(())
+        self.write_row_pad(row_pad_size)
     }
 
     fn write_row_pad(&mut self, row_pad_size: u32) -> io::Result<()> {
@@ -251,6 +227,32 @@ impl<'a, W: Write> ImageEncoder for BmpEncoder<'a, W> {
     }
 }
 
+/// BMP encoder that writes an image row by row, created by [`BmpEncoder::streaming`].
+///
+/// [`BmpEncoder::streaming`]: struct.BmpEncoder.html#method.streaming
+pub struct StreamingBmpEncoder<'a, W: 'a> {
+    encoder: BmpEncoder<'a, W>,
+    color: color::ColorType,
+    row_pad_size: u32,
+    progress: RowProgress,
+}
+
+impl<'a, W: Write> StreamingEncoder for StreamingBmpEncoder<'a, W> {
+    fn write_rows(&mut self, buf: &[u8], rows: u32) -> ImageResult<()> {
+        self.progress.advance(buf, rows)?;
+        for row in buf.chunks_exact(self.progress.row_bytes().max(1)) {
+            self.encoder.write_row(row, self.color, self.row_pad_size)?;
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> ImageResult<()> {
+        self.progress.finish()?;
+        self.encoder.writer.flush()?;
+        Ok(())
+    }
+}
+
 fn get_unsupported_error_message(c: color::ColorType) -> String {
     format!(
         "Unsupported color type {:?}.  Supported types: RGB(8), RGBA(8), Gray(8), GrayA(8).",
@@ -373,4 +375,25 @@ mod tests {
         assert_eq!(2, decoded[7]);
         assert_eq!(2, decoded[8]);
     }
+
+    #[test]
+    fn streaming_rows() {
+        use crate::image::StreamingEncoder;
+
+        // 5x3 pixels, rows that need padding
+        let image: Vec<u8> = (0..5 * 3 * 3).collect();
+        let mut encoded_data = Vec::new();
+        let mut encoder = BmpEncoder::new(&mut encoded_data)
+            .streaming(5, 3, ColorType::Rgb8)
+            .unwrap();
+        encoder.write_rows(&image[..15], 1).unwrap();
+        assert!(encoder.write_rows(&image[15..], 1).is_err());
+        encoder.write_rows(&image[15..], 2).unwrap();
+        encoder.finish().unwrap();
+
+        let decoder = BmpDecoder::new(Cursor::new(&encoded_data)).expect("failed to decode");
+        let mut buf = vec![0; decoder.total_bytes() as usize];
+        decoder.read_image(&mut buf).expect("failed to decode");
+        assert_eq!(buf, image);
+    }
 }
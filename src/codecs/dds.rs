@@ -5,18 +5,23 @@
 //!  # Related Links
 //!  * <https://docs.microsoft.com/en-us/windows/win32/direct3ddds/dx-graphics-dds-pguide> - Description of the DDS format.
 
-use std::io::Read;
+use std::io::{self, Read, Seek};
 use std::{error, fmt};
 
 use byteorder::{LittleEndian, ReadBytesExt};
 
+use crate::buffer_::ConvertBuffer;
 #[allow(deprecated)]
 use crate::codecs::dxt::{DxtDecoder, DxtReader, DxtVariant};
 use crate::color::ColorType;
+use crate::cubemap::Cubemap;
 use crate::error::{
-    DecodingError, ImageError, ImageFormatHint, ImageResult, UnsupportedError, UnsupportedErrorKind,
+    DecodingError, ImageError, ImageFormatHint, ImageResult, ParameterError, ParameterErrorKind,
+    UnsupportedError, UnsupportedErrorKind,
 };
-use crate::image::{ImageDecoder, ImageFormat};
+use crate::image::{ImageDecoder, ImageDecoderRect, ImageFormat, Progress};
+use crate::io::Limits;
+use crate::{RgbImage, Rgba, RgbaImage};
 
 /// Errors that can occur during decoding and parsing a DDS image
 #[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
@@ -27,6 +32,8 @@ enum DecoderError {
     HeaderSizeInvalid(u32),
     /// Wrong DDS header flags
     HeaderFlagsInvalid(u32),
+    /// More mipmap levels than the dimensions allow
+    MipmapCountInvalid(u32),
 
     /// DDS "DDS " signature invalid or missing
     DdsSignatureInvalid,
@@ -44,6 +51,9 @@ impl fmt::Display for DecoderError {
             DecoderError::HeaderFlagsInvalid(fs) => {
                 f.write_fmt(format_args!("Invalid DDS header flags: {:#010X}", fs))
             }
+            DecoderError::MipmapCountInvalid(c) => {
+                f.write_fmt(format_args!("Invalid DDS mipmap count: {}", c))
+            }
             DecoderError::DdsSignatureInvalid => f.write_str("DDS signature not found"),
         }
     }
@@ -153,10 +163,20 @@ impl Header {
     }
 }
 
+/// `DDSD_MIPMAPCOUNT`, set if the mipmap count of the header is valid.
+const FLAG_MIPMAP_COUNT: u32 = 0x20000;
+/// `DDSCAPS2_CUBEMAP` together with the flags of all six faces.
+const CAPS2_CUBEMAP_ALL_FACES: u32 = 0xFE00;
+
 /// The representation of a DDS decoder
 pub struct DdsDecoder<R: Read> {
     #[allow(deprecated)]
     inner: DxtDecoder<R>,
+    /// The number of mipmap levels stored for each face, including the full size one.
+    mipmap_count: u32,
+    cubemap: bool,
+    /// The limits on the faces of a cubemap, which are all decoded.
+    limits: Limits,
 }
 
 impl<R: Read> DdsDecoder<R> {
@@ -207,7 +227,22 @@ impl<R: Read> DdsDecoder<R> {
 
             #[allow(deprecated)]
             let inner = DxtDecoder::new(r, header.width, header.height, variant)?;
-            Ok(Self { inner })
+            let mipmap_count = if header.flags & FLAG_MIPMAP_COUNT != 0 {
+                header.mipmap_count.max(1)
+            } else {
+                1
+            };
+            // Each level halves the dimensions until both are 1.
+            let full_chain = 32 - header.width.max(header.height).max(1).leading_zeros();
+            if mipmap_count > full_chain {
+                return Err(DecoderError::MipmapCountInvalid(mipmap_count).into());
+            }
+            Ok(Self {
+                inner,
+                mipmap_count,
+                cubemap: header.caps2 & CAPS2_CUBEMAP_ALL_FACES == CAPS2_CUBEMAP_ALL_FACES,
+                limits: Limits::no_limits(),
+            })
         } else {
             // For now, supports only DXT variants
             Err(ImageError::Unsupported(
@@ -220,6 +255,68 @@ impl<R: Read> DdsDecoder<R> {
     }
 }
 
+impl<R: Read> DdsDecoder<R> {
+    /// Whether the file holds all six faces of a cubemap.
+    ///
+    /// Decoding the file as a single image only returns the positive X face.
+    pub fn is_cubemap(&self) -> bool {
+        self.cubemap
+    }
+
+    /// Decode all six faces of a cubemap.
+    ///
+    /// Smaller mipmap levels of the faces are skipped. Returns an `ImageError::Parameter` if the
+    /// file does not hold a cubemap with all six faces.
+    #[allow(deprecated)]
+    pub fn read_cubemap(self) -> ImageResult<Cubemap<Rgba<u8>>> {
+        if !self.cubemap {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic("the DDS file does not hold a cubemap".to_string()),
+            )));
+        }
+
+        let (width, height) = self.inner.dimensions();
+        let mipmap_count = self.mipmap_count;
+        let variant = self.inner.variant();
+        let mut limits = self.limits;
+        let mut r = self.inner.into_inner();
+
+        let mut read_face = || -> ImageResult<RgbaImage> {
+            let decoder = DxtDecoder::new(&mut r, width, height, variant)?;
+            // DXT1 faces are decoded to RGB and converted to RGBA.
+            limits.reserve(decoder.total_bytes())?;
+            if variant == DxtVariant::DXT1 {
+                limits.reserve(u64::from(width) * u64::from(height) * 4)?;
+            }
+            let mut buf = vec![0; decoder.total_bytes() as usize];
+            decoder.read_image(&mut buf)?;
+
+            for level in 1..mipmap_count {
+                let blocks = |length: u32| u64::from(((length >> level).max(1) + 3) / 4);
+                let len = blocks(width) * blocks(height) * variant.encoded_bytes_per_block() as u64;
+                io::copy(&mut (&mut r).take(len), &mut io::sink())?;
+            }
+
+            let face = if variant == DxtVariant::DXT1 {
+                RgbImage::from_raw(width, height, buf).map(|face| face.convert())
+            } else {
+                RgbaImage::from_raw(width, height, buf)
+            };
+            Ok(face.unwrap())
+        };
+
+        let faces = [
+            read_face()?,
+            read_face()?,
+            read_face()?,
+            read_face()?,
+            read_face()?,
+            read_face()?,
+        ];
+        Cubemap::from_faces(faces)
+    }
+}
+
 impl<'a, R: 'a + Read> ImageDecoder<'a> for DdsDecoder<R> {
     #[allow(deprecated)]
     type Reader = DxtReader<R>;
@@ -240,14 +337,38 @@ impl<'a, R: 'a + Read> ImageDecoder<'a> for DdsDecoder<R> {
         self.inner.into_reader()
     }
 
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        self.limits = limits;
+        Ok(())
+    }
+
     fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
         self.inner.read_image(buf)
     }
 }
 
+impl<'a, R: 'a + Read + Seek> ImageDecoderRect<'a> for DdsDecoder<R> {
+    fn read_rect_with_progress<F: Fn(Progress)>(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        buf: &mut [u8],
+        progress_callback: F,
+    ) -> ImageResult<()> {
+        self.inner
+            .read_rect_with_progress(x, y, width, height, buf, progress_callback)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::cubemap::CubeFace;
 
     #[test]
     fn dimension_overflow() {
@@ -265,4 +386,91 @@ mod test {
 
         assert!(DdsDecoder::new(&header[..]).is_err());
     }
+
+    /// A 4x4 DXT1 cubemap with three mipmap levels, whose header claims `mipmap_count` levels.
+    fn cubemap_file(mipmap_count: u32) -> Vec<u8> {
+        let mut file = b"DDS ".to_vec();
+        let mut push = |value: u32| file.extend_from_slice(&value.to_le_bytes());
+        // Size, flags with the mipmap count, height, width, linear size, depth, mipmap count.
+        for &value in &[124, 0x2_1007, 4, 4, 8, 0, mipmap_count] {
+            push(value);
+        }
+        for _ in 0..11 {
+            push(0);
+        }
+        // The pixel format, DXT1.
+        for &value in &[32, 0x4, u32::from_le_bytes(*b"DXT1"), 0, 0, 0, 0, 0] {
+            push(value);
+        }
+        // Caps and caps2 with all cubemap faces, followed by reserved fields.
+        for &value in &[0x40_1008, 0xFE00, 0, 0, 0] {
+            push(value);
+        }
+        for face in 0..6u8 {
+            // A single block of the face color, with the color of the next face in the smaller
+            // mipmap levels.
+            for level in 0..3u8 {
+                let color: u16 = if face + level.min(1) == 2 { 0xF800 } else { 0 };
+                file.extend_from_slice(&color.to_le_bytes());
+                file.extend_from_slice(&[0; 6]);
+            }
+        }
+        file
+    }
+
+    #[test]
+    fn cubemap_faces() {
+        let file = cubemap_file(3);
+        let decoder = DdsDecoder::new(&file[..]).unwrap();
+        assert!(decoder.is_cubemap());
+        let cubemap = decoder.read_cubemap().unwrap();
+        assert_eq!(cubemap.size(), 4);
+        for (face, image) in cubemap.faces() {
+            let red = if face == CubeFace::PositiveY { 255 } else { 0 };
+            assert!(image.pixels().all(|p| *p == Rgba([red, 0, 0, 255])));
+        }
+    }
+
+    #[test]
+    fn read_rect_matches_image() {
+        // An 8x8 DXT1 image of four blocks in red, green, blue and white.
+        let mut file = b"DDS ".to_vec();
+        let mut push = |value: u32| file.extend_from_slice(&value.to_le_bytes());
+        for &value in &[124, 0x1007, 8, 8, 32, 0, 0] {
+            push(value);
+        }
+        for _ in 0..11 {
+            push(0);
+        }
+        for &value in &[32, 0x4, u32::from_le_bytes(*b"DXT1"), 0, 0, 0, 0, 0] {
+            push(value);
+        }
+        for &value in &[0x1000, 0, 0, 0, 0] {
+            push(value);
+        }
+        for &color in &[0xF800u16, 0x07E0, 0x001F, 0xFFFF] {
+            file.extend_from_slice(&color.to_le_bytes());
+            file.extend_from_slice(&[0; 6]);
+        }
+
+        let decoder = DdsDecoder::new(io::Cursor::new(&file)).unwrap();
+        let mut image = vec![0; decoder.total_bytes() as usize];
+        decoder.read_image(&mut image).unwrap();
+
+        let mut decoder = DdsDecoder::new(io::Cursor::new(&file)).unwrap();
+        let mut rect = vec![0; 4 * 3 * 3];
+        decoder.read_rect(2, 3, 4, 3, &mut rect).unwrap();
+        for (row, line) in rect.chunks(4 * 3).enumerate() {
+            let start = ((3 + row) * 8 + 2) * 3;
+            assert_eq!(line, &image[start..start + 4 * 3]);
+        }
+        assert_eq!(&rect[..3], &[255, 0, 0]);
+        assert_eq!(&rect[rect.len() - 3..], &[255, 255, 255]);
+    }
+
+    #[test]
+    fn mipmap_count_overflow() {
+        assert!(DdsDecoder::new(&cubemap_file(4)[..]).is_err());
+        assert!(DdsDecoder::new(&cubemap_file(u32::MAX)[..]).is_err());
+    }
 }
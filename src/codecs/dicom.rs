@@ -0,0 +1,1002 @@
+//! Decoding of DICOM pixel data
+//!
+//! DICOM is the standard format of medical imaging. A file starts with a preamble of 128 bytes
+//! and the prefix `DICM`, followed by a data set of tagged elements, such as the patient's name,
+//! the dimensions of the image and its pixel data.
+//!
+//! This decoder extracts the pixel data of single and multi-frame images, uncompressed in the
+//! implicit or explicit little endian transfer syntaxes, or encapsulated in the JPEG transfer
+//! syntaxes, which are decoded with the JPEG codec of this crate. Monochrome images decode to
+//! `L8` or `L16` and color images to `Rgb8` or `Rgb16`. The samples keep the values stored in the
+//! file, so 12 bit images use only the low 12 bits. Signed samples are offset by half of their
+//! range, 128 or 32768, to fit the unsigned color types; [`DicomDecoder::rescale`] accounts for
+//! this offset.
+//!
+//! Medical images have a much larger range of values than displays. Viewers map them to eight
+//! bits with a window of interest, as [`apply_window`] does.
+//!
+//! # Related Links
+//! * <https://www.dicomstandard.org/current> - the DICOM standard
+//!
+//! [`DicomDecoder::rescale`]: struct.DicomDecoder.html#method.rescale
+//! [`apply_window`]: fn.apply_window.html
+
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read};
+use std::marker::PhantomData;
+use std::mem;
+use std::ops::Range;
+
+use byteorder::{ByteOrder, LittleEndian, NativeEndian};
+
+use crate::codecs::jpeg::JpegDecoder;
+use crate::color::ColorType;
+use crate::dynimage::DynamicImage;
+use crate::error::{
+    DecodingError, ImageError, ImageResult, ParameterError, ParameterErrorKind, UnsupportedError,
+    UnsupportedErrorKind,
+};
+use crate::image::{self, ImageDecoder, ImageFormat};
+use crate::imageops::window::{apply_lut, window_lut};
+use crate::io::Limits;
+use crate::{GrayImage, ImageBuffer, Luma};
+
+const PREAMBLE_SIZE: usize = 128;
+
+type Tag = (u16, u16);
+
+const TRANSFER_SYNTAX: Tag = (0x0002, 0x0010);
+const SAMPLES_PER_PIXEL: Tag = (0x0028, 0x0002);
+const PHOTOMETRIC_INTERPRETATION: Tag = (0x0028, 0x0004);
+const PLANAR_CONFIGURATION: Tag = (0x0028, 0x0006);
+const NUMBER_OF_FRAMES: Tag = (0x0028, 0x0008);
+const ROWS: Tag = (0x0028, 0x0010);
+const COLUMNS: Tag = (0x0028, 0x0011);
+const BITS_ALLOCATED: Tag = (0x0028, 0x0100);
+const BITS_STORED: Tag = (0x0028, 0x0101);
+const HIGH_BIT: Tag = (0x0028, 0x0102);
+const PIXEL_REPRESENTATION: Tag = (0x0028, 0x0103);
+const WINDOW_CENTER: Tag = (0x0028, 0x1050);
+const WINDOW_WIDTH: Tag = (0x0028, 0x1051);
+const RESCALE_INTERCEPT: Tag = (0x0028, 0x1052);
+const RESCALE_SLOPE: Tag = (0x0028, 0x1053);
+const PIXEL_DATA: Tag = (0x7FE0, 0x0010);
+const ITEM: Tag = (0xFFFE, 0xE000);
+const ITEM_DELIMITATION: Tag = (0xFFFE, 0xE00D);
+const SEQUENCE_DELIMITATION: Tag = (0xFFFE, 0xE0DD);
+
+const UNDEFINED_LENGTH: u32 = 0xFFFF_FFFF;
+
+/// A linear mapping of stored sample values to the values of the modality, such as the
+/// Hounsfield units of computed tomography.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Rescale {
+    /// The factor of the sample values.
+    pub slope: f64,
+    /// The value of a sample of zero.
+    pub intercept: f64,
+}
+
+impl Rescale {
+    /// The modality value of a sample.
+    pub fn apply(&self, sample: u16) -> f64 {
+        self.slope * f64::from(sample) + self.intercept
+    }
+}
+
+impl Default for Rescale {
+    fn default() -> Self {
+        Rescale {
+            slope: 1.0,
+            intercept: 0.0,
+        }
+    }
+}
+
+/// A window of modality values that is spread over the displayed gray levels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Window {
+    /// The center of the window, also called the level.
+    pub center: f64,
+    /// The width of the window, at least 1.
+    pub width: f64,
+}
+
+impl Window {
+    /// The window that spans all values of an image.
+    pub fn covering(image: &ImageBuffer<Luma<u16>, Vec<u16>>, rescale: Rescale) -> Window {
+        let (min, max) = image
+            .pixels()
+            .map(|pixel| rescale.apply(pixel[0]))
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+                (min.min(value), max.max(value))
+            });
+        if min > max {
+            return Window {
+                center: 0.5,
+                width: 1.0,
+            };
+        }
+        Window {
+            center: (min + max) / 2.0 + 0.5,
+            width: max - min + 1.0,
+        }
+    }
+}
+
+/// Map the samples of a monochrome image to eight bit gray levels for display.
+///
+/// The samples are rescaled to modality values, which are then mapped linearly from the lower
+/// edge of the window to its upper edge, as the DICOM standard specifies. Values outside of the
+/// window are black or white. If `invert` is set, as for `MONOCHROME1` images, low values are
+/// white instead.
+///
+/// ```
+/// use image::codecs::dicom::{apply_window, Rescale, Window};
+/// use image::{ImageBuffer, Luma};
+///
+/// // Stored values of a CT scan, with an intercept to Hounsfield units.
+/// let scan = ImageBuffer::from_raw(3, 1, vec![0u16, 1024, 1124]).unwrap();
+/// let rescale = Rescale { slope: 1.0, intercept: -1024.0 };
+/// // A window from -100 to 300 HU, for soft tissue.
+/// let window = Window { center: 100.0, width: 400.0 };
+/// let gray = apply_window(&scan, rescale, window, false);
+/// assert_eq!(gray.into_raw(), vec![0, 64, 128]);
+/// ```
+pub fn apply_window(
+    image: &ImageBuffer<Luma<u16>, Vec<u16>>,
+    rescale: Rescale,
+    window: Window,
+    invert: bool,
+) -> GrayImage {
+    let mut lut = window_lut(window.center, window.width, |sample| rescale.apply(sample));
+    if invert {
+        for level in &mut lut {
+            *level = 255 - *level;
+        }
+    }
+    apply_lut(image, &lut)
+}
+
+/// The reader returned by `DicomDecoder::into_reader`.
+pub struct DicomReader<R>(Cursor<Vec<u8>>, PhantomData<R>);
+impl<R> Read for DicomReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        if self.0.position() == 0 && buf.is_empty() {
+            mem::swap(buf, self.0.get_mut());
+            Ok(buf.len())
+        } else {
+            self.0.read_to_end(buf)
+        }
+    }
+}
+
+/// The pixel data of a data set.
+enum PixelData {
+    /// The samples of all frames, one after the other.
+    Native(Range<usize>),
+    /// The basic offset table and the fragments of encapsulated frames.
+    Encapsulated(Range<usize>, Vec<Range<usize>>),
+}
+
+/// DICOM decoder
+pub struct DicomDecoder<R> {
+    data: Vec<u8>,
+    pixel_data: PixelData,
+    width: u32,
+    height: u32,
+    samples: usize,
+    bits_allocated: u16,
+    bits_stored: u16,
+    high_bit: u16,
+    signed: bool,
+    planar: bool,
+    photometric: String,
+    frames: u32,
+    frame: u32,
+    rescale: Rescale,
+    windows: Vec<Window>,
+    color_type: ColorType,
+    /// The limits on the buffers of encapsulated frames, next to the file that is kept in memory.
+    limits: Limits,
+    phantom: PhantomData<R>,
+}
+
+impl<R: Read> DicomDecoder<R> {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new(r: R) -> ImageResult<DicomDecoder<R>> {
+        Self::with_limits(r, Limits::no_limits())
+    }
+
+    /// Create a new decoder that decodes from the stream ```r``` within the given limits.
+    ///
+    /// The whole file is read into memory, so the `max_alloc` limit also bounds its size.
+    pub fn with_limits(mut r: R, mut limits: Limits) -> ImageResult<DicomDecoder<R>> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let mut data = Vec::new();
+        let max_size = limits
+            .max_alloc
+            .map_or(u64::MAX, |max| max.saturating_add(1));
+        r.by_ref().take(max_size).read_to_end(&mut data)?;
+        limits.reserve(data.len() as u64)?;
+        if data.get(PREAMBLE_SIZE..PREAMBLE_SIZE + 4) != Some(&b"DICM"[..]) {
+            return Err(decoding_error("missing DICM prefix"));
+        }
+
+        // The file meta information is always in the explicit little endian syntax.
+        let mut parser = Parser {
+            data: &data,
+            pos: PREAMBLE_SIZE + 4,
+            explicit: true,
+        };
+        let mut transfer_syntax = String::new();
+        while parser.peek_group() == Some(0x0002) {
+            let (tag, value) = parser.next_element()?;
+            if tag == TRANSFER_SYNTAX {
+                transfer_syntax = text(&data, &value);
+            }
+        }
+        let encapsulated = match transfer_syntax.as_str() {
+            "1.2.840.10008.1.2" => {
+                parser.explicit = false;
+                false
+            }
+            "1.2.840.10008.1.2.1" => false,
+            // Baseline, extended and lossless JPEG.
+            "1.2.840.10008.1.2.4.50"
+            | "1.2.840.10008.1.2.4.51"
+            | "1.2.840.10008.1.2.4.57"
+            | "1.2.840.10008.1.2.4.70" => true,
+            syntax => {
+                return Err(unsupported_error(format!("transfer syntax {}", syntax)));
+            }
+        };
+
+        let mut elements = Vec::new();
+        let mut pixel_data = None;
+        while parser.peek_group().is_some() {
+            let (tag, value) = parser.next_element()?;
+            if tag == PIXEL_DATA {
+                pixel_data = Some(value);
+                break;
+            }
+            elements.push((tag, value));
+        }
+
+        let find = |tag: Tag| {
+            elements
+                .iter()
+                .find(|&&(t, _)| t == tag)
+                .map(|(_, value)| value)
+        };
+        let unsigned = |tag: Tag| {
+            find(tag)
+                .and_then(|value| match value {
+                    Value::Bytes(range) if range.len() >= 2 => {
+                        Some(LittleEndian::read_u16(&data[range.start..]))
+                    }
+                    _ => None,
+                })
+                .ok_or_else(|| decoding_error(format!("missing element {:04X?}", tag)))
+        };
+        let numbers = |tag: Tag| -> Vec<f64> {
+            find(tag).map_or_else(Vec::new, |value| {
+                text(&data, value)
+                    .split('\\')
+                    .filter_map(|number| number.trim().parse().ok())
+                    .collect()
+            })
+        };
+
+        let samples = usize::from(unsigned(SAMPLES_PER_PIXEL).unwrap_or(1));
+        let photometric = find(PHOTOMETRIC_INTERPRETATION)
+            .map(|value| text(&data, value))
+            .unwrap_or_default();
+        let planar = unsigned(PLANAR_CONFIGURATION).unwrap_or(0) == 1;
+        let frames = match numbers(NUMBER_OF_FRAMES).first() {
+            None => 1,
+            Some(&frames) if frames >= 1.0 && frames <= f64::from(u32::MAX) => frames as u32,
+            Some(frames) => {
+                return Err(decoding_error(format!(
+                    "invalid number of frames {}",
+                    frames
+                )));
+            }
+        };
+        let height = u32::from(unsigned(ROWS)?);
+        let width = u32::from(unsigned(COLUMNS)?);
+        let bits_allocated = unsigned(BITS_ALLOCATED)?;
+        let bits_stored = unsigned(BITS_STORED).unwrap_or(bits_allocated);
+        let high_bit = unsigned(HIGH_BIT).unwrap_or(bits_stored.saturating_sub(1));
+        let signed = unsigned(PIXEL_REPRESENTATION).unwrap_or(0) == 1;
+        if bits_stored == 0
+            || bits_stored > bits_allocated
+            || high_bit >= bits_allocated
+            || high_bit + 1 < bits_stored
+        {
+            return Err(decoding_error(format!(
+                "invalid sample layout of {} stored bits",
+                bits_stored
+            )));
+        }
+
+        let color_type = match (photometric.as_str(), samples, bits_allocated) {
+            ("MONOCHROME1", 1, 8) | ("MONOCHROME2", 1, 8) => ColorType::L8,
+            ("MONOCHROME1", 1, 16) | ("MONOCHROME2", 1, 16) => ColorType::L16,
+            ("RGB", 3, 8) | ("YBR_FULL", 3, 8) => ColorType::Rgb8,
+            ("RGB", 3, 16) => ColorType::Rgb16,
+            // The JPEG decoder converts these to RGB.
+            ("YBR_FULL_422", 3, 8) if encapsulated => ColorType::Rgb8,
+            (photometric, samples, bits) => {
+                return Err(unsupported_error(format!(
+                    "{} pixel data with {} samples of {} bits",
+                    photometric, samples, bits
+                )));
+            }
+        };
+
+        let pixel_data = match pixel_data {
+            Some(Value::Bytes(range)) if !encapsulated => {
+                let size = (width as usize)
+                    .checked_mul(height as usize)
+                    .and_then(|size| size.checked_mul(samples))
+                    .and_then(|size| size.checked_mul(usize::from(bits_allocated / 8)))
+                    .and_then(|size| size.checked_mul(frames as usize));
+                if size.map_or(true, |size| range.len() < size) {
+                    return Err(decoding_error("pixel data is shorter than its frames"));
+                }
+                PixelData::Native(range)
+            }
+            Some(Value::Fragments(mut fragments)) if encapsulated && !fragments.is_empty() => {
+                let offsets = fragments.remove(0);
+                PixelData::Encapsulated(offsets, fragments)
+            }
+            _ => return Err(decoding_error("missing or invalid pixel data")),
+        };
+
+        // Signed samples are offset to fit unsigned color types, the rescale undoes this.
+        let offset = if signed {
+            f64::from(1u32 << (bits_allocated - 1))
+        } else {
+            0.0
+        };
+        let slope = numbers(RESCALE_SLOPE).first().copied().unwrap_or(1.0);
+        let intercept = numbers(RESCALE_INTERCEPT).first().copied().unwrap_or(0.0);
+        let rescale = Rescale {
+            slope,
+            intercept: intercept - offset * slope,
+        };
+        let windows = numbers(WINDOW_CENTER)
+            .into_iter()
+            .zip(numbers(WINDOW_WIDTH))
+            .map(|(center, width)| Window { center, width })
+            .collect();
+        limits.check_dimensions(width, height)?;
+
+        Ok(DicomDecoder {
+            data,
+            pixel_data,
+            width,
+            height,
+            samples,
+            bits_allocated,
+            bits_stored,
+            high_bit,
+            signed,
+            planar,
+            photometric,
+            frames,
+            frame: 0,
+            rescale,
+            windows,
+            color_type,
+            limits,
+            phantom: PhantomData,
+        })
+    }
+
+    /// The number of frames, such as the slices of a volume or the images of a cine loop.
+    pub fn frame_count(&self) -> u32 {
+        self.frames
+    }
+
+    /// Choose the frame to decode. The first frame is decoded by default.
+    ///
+    /// Returns an `ImageError::Parameter` if there is no such frame.
+    pub fn select_frame(&mut self, index: u32) -> ImageResult<()> {
+        if index >= self.frames {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!(
+                    "frame {} of an image with {} frames",
+                    index, self.frames
+                )),
+            )));
+        }
+        self.frame = index;
+        Ok(())
+    }
+
+    /// The photometric interpretation, such as `MONOCHROME2` or `RGB`.
+    pub fn photometric_interpretation(&self) -> &str {
+        &self.photometric
+    }
+
+    /// Whether low values are brighter, as for `MONOCHROME1` images.
+    pub fn is_inverted(&self) -> bool {
+        self.photometric == "MONOCHROME1"
+    }
+
+    /// The mapping of decoded samples to the values of the modality.
+    pub fn rescale(&self) -> Rescale {
+        self.rescale
+    }
+
+    /// The windows that the file suggests for display, in modality values. The first is usually
+    /// the preferred one.
+    pub fn windows(&self) -> &[Window] {
+        &self.windows
+    }
+
+    /// Turn a stored sample into the value of its stored bits, offset if it is signed.
+    fn normalize(&self, sample: u16, shift: u16) -> u16 {
+        let bits = u32::from(self.bits_stored);
+        let mask = ((1u32 << bits) - 1) as u16;
+        let value = (sample >> shift) & mask;
+        if self.signed {
+            let extended = i32::from(value) << (32 - bits) >> (32 - bits);
+            (extended + (1 << (self.bits_allocated - 1))) as u16
+        } else {
+            value
+        }
+    }
+
+    /// The bytes of an encapsulated frame, from the basic offset table or one fragment a frame.
+    fn encapsulated_frame(
+        &self,
+        offsets: &Range<usize>,
+        fragments: &[Range<usize>],
+    ) -> ImageResult<Vec<u8>> {
+        let frame = self.frame as usize;
+        let selected: Vec<&Range<usize>> = if self.frames == 1 {
+            fragments.iter().collect()
+        } else if offsets.is_empty() {
+            if fragments.len() != self.frames as usize {
+                return Err(unsupported_error(
+                    "multiple fragments per frame without an offset table".to_string(),
+                ));
+            }
+            vec![&fragments[frame]]
+        } else {
+            // Offsets are counted from the first fragment, including the headers of the items.
+            let table: Vec<usize> = self.data[offsets.clone()]
+                .chunks_exact(4)
+                .map(|offset| LittleEndian::read_u32(offset) as usize)
+                .collect();
+            let first = fragments.first().map_or(0, |fragment| fragment.start - 8);
+            let start = table.get(frame).copied().unwrap_or(usize::MAX);
+            let end = table.get(frame + 1).copied().unwrap_or(usize::MAX);
+            fragments
+                .iter()
+                .filter(|fragment| {
+                    let position = fragment.start - 8 - first;
+                    position >= start && position < end
+                })
+                .collect()
+        };
+        if selected.is_empty() {
+            return Err(decoding_error("missing fragments of a frame"));
+        }
+        Ok(selected
+            .into_iter()
+            .flat_map(|fragment| self.data[fragment.clone()].iter().copied())
+            .collect())
+    }
+
+    fn read_native(&self, range: &Range<usize>, buf: &mut [u8]) {
+        let pixels = self.width as usize * self.height as usize;
+        let sample_size = usize::from(self.bits_allocated / 8);
+        let frame_size = pixels * self.samples * sample_size;
+        let start = range.start + self.frame as usize * frame_size;
+        let frame = &self.data[start..start + frame_size];
+        // Planar samples are stored plane by plane, the output interleaves them.
+        let index = |i: usize| {
+            if self.planar {
+                (i % pixels) * self.samples + i / pixels
+            } else {
+                i
+            }
+        };
+        let shift = self.high_bit + 1 - self.bits_stored;
+
+        if sample_size == 1 {
+            for (i, &sample) in frame.iter().enumerate() {
+                buf[index(i)] = self.normalize(u16::from(sample), shift) as u8;
+            }
+            if self.photometric == "YBR_FULL" {
+                for pixel in buf.chunks_exact_mut(3) {
+                    ycbcr_to_rgb(pixel);
+                }
+            }
+        } else {
+            for (i, sample) in frame.chunks_exact(2).enumerate() {
+                let value = self.normalize(LittleEndian::read_u16(sample), shift);
+                NativeEndian::write_u16(&mut buf[index(i) * 2..][..2], value);
+            }
+        }
+    }
+
+    fn read_encapsulated(&self, frame: &[u8], buf: &mut [u8]) -> ImageResult<()> {
+        let mut limits = self.limits.clone();
+        limits.reserve(frame.len() as u64)?;
+        let mut decoder = JpegDecoder::new(Cursor::new(frame))?;
+        limits.reserve(decoder.total_bytes())?;
+        decoder.set_limits(limits)?;
+        let image = DynamicImage::from_decoder(decoder)?;
+        if image.width() != self.width || image.height() != self.height {
+            return Err(decoding_error("JPEG frame differs in size from the image"));
+        }
+        match self.color_type {
+            ColorType::L8 => {
+                for (out, &sample) in buf.iter_mut().zip(image.into_luma8().as_raw()) {
+                    *out = self.normalize(u16::from(sample), 0) as u8;
+                }
+            }
+            ColorType::L16 => {
+                for (out, &sample) in buf.chunks_exact_mut(2).zip(image.into_luma16().as_raw()) {
+                    NativeEndian::write_u16(out, self.normalize(sample, 0));
+                }
+            }
+            _ => buf.copy_from_slice(image.into_rgb8().as_raw()),
+        }
+        Ok(())
+    }
+}
+
+impl<'a, R: 'a + Read> ImageDecoder<'a> for DicomDecoder<R> {
+    type Reader = DicomReader<R>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        Ok(DicomReader(
+            Cursor::new(image::decoder_to_vec(self)?),
+            PhantomData,
+        ))
+    }
+
+    fn set_limits(&mut self, mut limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        limits.reserve(self.data.len() as u64)?;
+        self.limits = limits;
+        Ok(())
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        // The file was read when the decoder was created.
+        self.limits.check_cancelled()?;
+        match &self.pixel_data {
+            PixelData::Native(range) => {
+                self.read_native(range, buf);
+                Ok(())
+            }
+            PixelData::Encapsulated(offsets, fragments) => {
+                let frame = self.encapsulated_frame(offsets, fragments)?;
+                self.read_encapsulated(&frame, buf)
+            }
+        }
+    }
+}
+
+/// The value of an element.
+enum Value {
+    Bytes(Range<usize>),
+    /// The items of encapsulated pixel data.
+    Fragments(Vec<Range<usize>>),
+    /// A sequence of undefined length, which is skipped.
+    Sequence,
+}
+
+/// Reads the elements of a little endian data set.
+struct Parser<'a> {
+    data: &'a [u8],
+    pos: usize,
+    explicit: bool,
+}
+
+impl Parser<'_> {
+    fn peek_group(&self) -> Option<u16> {
+        self.data
+            .get(self.pos..self.pos + 2)
+            .map(LittleEndian::read_u16)
+    }
+
+    fn take(&mut self, len: usize) -> ImageResult<Range<usize>> {
+        let end = self
+            .pos
+            .checked_add(len)
+            .filter(|&end| end <= self.data.len())
+            .ok_or_else(|| decoding_error("unexpected end of data"))?;
+        let range = self.pos..end;
+        self.pos = end;
+        Ok(range)
+    }
+
+    fn read_u16(&mut self) -> ImageResult<u16> {
+        let range = self.take(2)?;
+        Ok(LittleEndian::read_u16(&self.data[range]))
+    }
+
+    fn read_u32(&mut self) -> ImageResult<u32> {
+        let range = self.take(4)?;
+        Ok(LittleEndian::read_u32(&self.data[range]))
+    }
+
+    fn read_tag(&mut self) -> ImageResult<Tag> {
+        Ok((self.read_u16()?, self.read_u16()?))
+    }
+
+    fn next_element(&mut self) -> ImageResult<(Tag, Value)> {
+        let (tag, len) = self.read_header()?;
+        if len != UNDEFINED_LENGTH {
+            return Ok((tag, Value::Bytes(self.take(len as usize)?)));
+        }
+        if tag == PIXEL_DATA {
+            let mut fragments = Vec::new();
+            loop {
+                match self.read_tag()? {
+                    ITEM => {
+                        let len = self.read_u32()?;
+                        fragments.push(self.take(len as usize)?);
+                    }
+                    SEQUENCE_DELIMITATION => {
+                        self.read_u32()?;
+                        return Ok((tag, Value::Fragments(fragments)));
+                    }
+                    _ => return Err(decoding_error("invalid item in pixel data")),
+                }
+            }
+        }
+
+        // Skip the nested items and sequences with a stack of the delimiters that end them, as
+        // recursing would overflow the stack on deeply nested data.
+        let mut delimiters = vec![delimiter(tag)];
+        while let Some(&delimiter_tag) = delimiters.last() {
+            let (tag, len) = self.read_header()?;
+            if len == UNDEFINED_LENGTH {
+                delimiters.push(delimiter(tag));
+            } else {
+                self.take(len as usize)?;
+                if tag == delimiter_tag {
+                    delimiters.pop();
+                }
+            }
+        }
+        Ok((tag, Value::Sequence))
+    }
+
+    /// Read the tag and value length of an element.
+    fn read_header(&mut self) -> ImageResult<(Tag, u32)> {
+        let tag = self.read_tag()?;
+        // Items and delimiters have no value representation, even in explicit syntaxes.
+        let len = if !self.explicit || tag.0 == 0xFFFE {
+            self.read_u32()?
+        } else {
+            let vr = self.take(2)?;
+            match &self.data[vr] {
+                b"OB" | b"OD" | b"OF" | b"OL" | b"OV" | b"OW" | b"SQ" | b"SV" | b"UC" | b"UN"
+                | b"UR" | b"UT" | b"UV" => {
+                    self.take(2)?;
+                    self.read_u32()?
+                }
+                _ => u32::from(self.read_u16()?),
+            }
+        };
+        Ok((tag, len))
+    }
+}
+
+/// The tag that ends an element of undefined length.
+fn delimiter(tag: Tag) -> Tag {
+    if tag == ITEM {
+        ITEM_DELIMITATION
+    } else {
+        SEQUENCE_DELIMITATION
+    }
+}
+
+/// The text of a string value, without padding.
+fn text(data: &[u8], value: &Value) -> String {
+    match value {
+        Value::Bytes(range) => String::from_utf8_lossy(&data[range.clone()])
+            .trim_matches(|c: char| c == ' ' || c == '\0')
+            .to_string(),
+        _ => String::new(),
+    }
+}
+
+/// Convert full range YCbCr, as JPEG uses it, to RGB in place.
+fn ycbcr_to_rgb(pixel: &mut [u8]) {
+    let y = f32::from(pixel[0]);
+    let cb = f32::from(pixel[1]) - 128.0;
+    let cr = f32::from(pixel[2]) - 128.0;
+    let clamp = |value: f32| value.round().clamp(0.0, 255.0) as u8;
+    pixel[0] = clamp(y + 1.402 * cr);
+    pixel[1] = clamp(y - 0.344_136 * cb - 0.714_136 * cr);
+    pixel[2] = clamp(y + 1.772 * cb);
+}
+
+fn decoding_error<M: Into<String>>(message: M) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormat::Dicom.into(),
+        message.into(),
+    ))
+}
+
+fn unsupported_error(feature: String) -> ImageError {
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        ImageFormat::Dicom.into(),
+        UnsupportedErrorKind::GenericFeature(feature),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codecs::jpeg::JpegEncoder;
+
+    const EXPLICIT: &str = "1.2.840.10008.1.2.1";
+    const IMPLICIT: &str = "1.2.840.10008.1.2";
+    const JPEG_BASELINE: &str = "1.2.840.10008.1.2.4.50";
+
+    /// Builds a DICOM file from the elements of its data set.
+    struct File {
+        bytes: Vec<u8>,
+        explicit: bool,
+    }
+
+    impl File {
+        fn new(transfer_syntax: &str) -> File {
+            let mut file = File {
+                bytes: vec![0; PREAMBLE_SIZE],
+                explicit: true,
+            };
+            file.bytes.extend_from_slice(b"DICM");
+            file.string(TRANSFER_SYNTAX, b"UI", transfer_syntax);
+            file.explicit = transfer_syntax != IMPLICIT;
+            file
+        }
+
+        fn element(&mut self, tag: Tag, vr: &[u8; 2], value: &[u8]) -> &mut File {
+            self.header(tag, vr, value.len() as u32);
+            self.bytes.extend_from_slice(value);
+            self
+        }
+
+        fn header(&mut self, tag: Tag, vr: &[u8; 2], len: u32) {
+            self.bytes.extend_from_slice(&tag.0.to_le_bytes());
+            self.bytes.extend_from_slice(&tag.1.to_le_bytes());
+            if !self.explicit || tag.0 == 0xFFFE {
+                self.bytes.extend_from_slice(&len.to_le_bytes());
+            } else if [b"OB", b"OW", b"SQ"].contains(&vr) {
+                self.bytes.extend_from_slice(vr);
+                self.bytes.extend_from_slice(&[0, 0]);
+                self.bytes.extend_from_slice(&len.to_le_bytes());
+            } else {
+                self.bytes.extend_from_slice(vr);
+                self.bytes.extend_from_slice(&(len as u16).to_le_bytes());
+            }
+        }
+
+        fn string(&mut self, tag: Tag, vr: &[u8; 2], value: &str) -> &mut File {
+            let mut value = value.as_bytes().to_vec();
+            if value.len() % 2 == 1 {
+                value.push(if vr == b"UI" { 0 } else { b' ' });
+            }
+            self.element(tag, vr, &value)
+        }
+
+        fn unsigned(&mut self, tag: Tag, value: u16) -> &mut File {
+            self.element(tag, b"US", &value.to_le_bytes())
+        }
+
+        fn image(&mut self, photometric: &str, width: u16, height: u16, bits: u16) -> &mut File {
+            self.unsigned(
+                SAMPLES_PER_PIXEL,
+                if photometric.starts_with("MONO") {
+                    1
+                } else {
+                    3
+                },
+            )
+            .string(PHOTOMETRIC_INTERPRETATION, b"CS", photometric)
+            .unsigned(ROWS, height)
+            .unsigned(COLUMNS, width)
+            .unsigned(BITS_ALLOCATED, bits)
+        }
+
+        fn decoder(&self) -> DicomDecoder<Cursor<&[u8]>> {
+            DicomDecoder::new(Cursor::new(&self.bytes[..])).unwrap()
+        }
+    }
+
+    fn decode(decoder: DicomDecoder<Cursor<&[u8]>>) -> Vec<u8> {
+        image::decoder_to_vec(decoder).unwrap()
+    }
+
+    #[test]
+    fn twelve_bit_monochrome() {
+        let samples: Vec<u8> = [0u16, 0x0FFF, 0xF000, 0x0400]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes().to_vec())
+            .collect();
+        let mut file = File::new(EXPLICIT);
+        file.image("MONOCHROME2", 2, 2, 16)
+            .unsigned(BITS_STORED, 12)
+            .unsigned(HIGH_BIT, 11)
+            .string(WINDOW_CENTER, b"DS", "40\\400")
+            .string(WINDOW_WIDTH, b"DS", "400\\2000")
+            .string(RESCALE_INTERCEPT, b"DS", "-1024")
+            .element(PIXEL_DATA, b"OW", &samples);
+        let decoder = file.decoder();
+        assert_eq!(decoder.color_type(), ColorType::L16);
+        assert!(!decoder.is_inverted());
+        assert_eq!(decoder.windows().len(), 2);
+        assert_eq!(
+            decoder.windows()[1],
+            Window {
+                center: 400.0,
+                width: 2000.0
+            }
+        );
+        assert_eq!(decoder.rescale().apply(1024), 0.0);
+        let data = decode(decoder);
+        let values: Vec<u16> = data.chunks(2).map(NativeEndian::read_u16).collect();
+        // Bits above the stored ones are ignored.
+        assert_eq!(values, [0, 0x0FFF, 0, 0x0400]);
+    }
+
+    #[test]
+    fn signed_implicit_with_sequence() {
+        let samples: Vec<u8> = [-1000i16, 0, 1000]
+            .iter()
+            .flat_map(|sample| sample.to_le_bytes().to_vec())
+            .collect();
+        let mut file = File::new(IMPLICIT);
+        // A sequence of undefined length, with an item of undefined length.
+        file.header((0x0008, 0x1140), b"SQ", UNDEFINED_LENGTH);
+        file.header(ITEM, b"  ", UNDEFINED_LENGTH);
+        file.string((0x0008, 0x1150), b"UI", "1.2.3");
+        file.header(ITEM_DELIMITATION, b"  ", 0);
+        file.header(SEQUENCE_DELIMITATION, b"  ", 0);
+        file.image("MONOCHROME1", 3, 1, 16)
+            .unsigned(PIXEL_REPRESENTATION, 1)
+            .element(PIXEL_DATA, b"OW", &samples);
+        let decoder = file.decoder();
+        assert!(decoder.is_inverted());
+        let rescale = decoder.rescale();
+        let data = decode(decoder);
+        let values: Vec<f64> = data
+            .chunks(2)
+            .map(|sample| rescale.apply(NativeEndian::read_u16(sample)))
+            .collect();
+        assert_eq!(values, [-1000.0, 0.0, 1000.0]);
+    }
+
+    #[test]
+    fn planar_rgb() {
+        let mut file = File::new(EXPLICIT);
+        file.image("RGB", 2, 1, 8)
+            .unsigned(PLANAR_CONFIGURATION, 1)
+            .element(PIXEL_DATA, b"OB", &[1, 2, 3, 4, 5, 6]);
+        let decoder = file.decoder();
+        assert_eq!(decoder.color_type(), ColorType::Rgb8);
+        assert_eq!(decode(decoder), [1, 3, 5, 2, 4, 6]);
+    }
+
+    #[test]
+    fn frames() {
+        let mut file = File::new(EXPLICIT);
+        file.image("MONOCHROME2", 2, 1, 8)
+            .string(NUMBER_OF_FRAMES, b"IS", "3")
+            .element(PIXEL_DATA, b"OB", &[1, 2, 3, 4, 5, 6]);
+        let mut decoder = file.decoder();
+        assert_eq!(decoder.frame_count(), 3);
+        assert!(decoder.select_frame(3).is_err());
+        decoder.select_frame(2).unwrap();
+        assert_eq!(decode(decoder), [5, 6]);
+    }
+
+    #[test]
+    fn invalid_frame_counts() {
+        for frames in &["0", "-2", "NaN", "inf", "1e20"] {
+            let mut file = File::new(EXPLICIT);
+            file.image("MONOCHROME2", 2, 1, 8)
+                .string(NUMBER_OF_FRAMES, b"IS", frames)
+                .element(PIXEL_DATA, b"OB", &[1, 2]);
+            let cursor = Cursor::new(&file.bytes[..]);
+            assert!(DicomDecoder::new(cursor).is_err(), "{}", frames);
+        }
+    }
+
+    #[test]
+    fn deeply_nested_sequences() {
+        let mut file = File::new(EXPLICIT);
+        file.image("MONOCHROME2", 2, 1, 8);
+        let depth = 100_000;
+        for _ in 0..depth {
+            file.header((0x0008, 0x1140), b"SQ", UNDEFINED_LENGTH);
+            file.header(ITEM, b"  ", UNDEFINED_LENGTH);
+        }
+        for _ in 0..depth {
+            file.header(ITEM_DELIMITATION, b"  ", 0);
+            file.header(SEQUENCE_DELIMITATION, b"  ", 0);
+        }
+        file.element(PIXEL_DATA, b"OB", &[1, 2]);
+        assert_eq!(decode(file.decoder()), [1, 2]);
+    }
+
+    #[test]
+    fn jpeg_frames() {
+        let mut frames = Vec::new();
+        for &value in &[60, 200] {
+            let mut jpeg = Vec::new();
+            JpegEncoder::new_with_quality(&mut jpeg, 100)
+                .encode(&[value; 64], 8, 8, ColorType::L8)
+                .unwrap();
+            frames.push(jpeg);
+        }
+
+        let mut file = File::new(JPEG_BASELINE);
+        file.image("MONOCHROME2", 8, 8, 8)
+            .string(NUMBER_OF_FRAMES, b"IS", "2");
+        file.header(PIXEL_DATA, b"OB", UNDEFINED_LENGTH);
+        file.header(ITEM, b"  ", 0);
+        for frame in &frames {
+            file.element(ITEM, b"  ", frame);
+        }
+        file.header(SEQUENCE_DELIMITATION, b"  ", 0);
+
+        let mut decoder = file.decoder();
+        decoder.select_frame(1).unwrap();
+        assert_eq!(decoder.color_type(), ColorType::L8);
+        let data = decode(decoder);
+        assert!(data.iter().all(|&sample| (sample as i32 - 200).abs() <= 1));
+    }
+
+    #[test]
+    fn unsupported_files() {
+        let mut file = File::new("1.2.840.10008.1.2.4.90");
+        file.image("MONOCHROME2", 1, 1, 8)
+            .element(PIXEL_DATA, b"OB", &[0, 0]);
+        assert!(DicomDecoder::new(Cursor::new(&file.bytes)).is_err());
+
+        let mut file = File::new(EXPLICIT);
+        file.image("PALETTE COLOR", 1, 1, 8)
+            .element(PIXEL_DATA, b"OB", &[0, 0]);
+        assert!(DicomDecoder::new(Cursor::new(&file.bytes)).is_err());
+
+        assert!(DicomDecoder::new(Cursor::new(vec![0; 200])).is_err());
+    }
+
+    #[test]
+    fn windows() {
+        let image = ImageBuffer::from_raw(4, 1, vec![100u16, 150, 199, 200]).unwrap();
+        let window = Window::covering(&image, Rescale::default());
+        let gray = apply_window(&image, Rescale::default(), window, false);
+        assert_eq!(gray.as_raw(), &[0, 128, 252, 255]);
+        let gray = apply_window(&image, Rescale::default(), window, true);
+        assert_eq!(gray.as_raw(), &[255, 127, 3, 0]);
+    }
+}
@@ -41,7 +41,7 @@ impl DxtVariant {
     }
 
     /// Returns the amount of bytes per block of encoded DXTn data
-    fn encoded_bytes_per_block(self) -> usize {
+    pub(crate) fn encoded_bytes_per_block(self) -> usize {
         match self {
             DxtVariant::DXT1 => 8,
             DxtVariant::DXT3 | DxtVariant::DXT5 => 16,
@@ -99,6 +99,16 @@ impl<R: Read> DxtDecoder<R> {
         })
     }
 
+    /// The DXT variant that is decoded.
+    pub(crate) fn variant(&self) -> DxtVariant {
+        self.variant
+    }
+
+    /// Returns the stream, positioned after the scanlines read so far.
+    pub(crate) fn into_inner(self) -> R {
+        self.inner
+    }
+
     fn read_scanline(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         assert_eq!(u64::try_from(buf.len()), Ok(self.scanline_bytes()));
 
@@ -0,0 +1,745 @@
+//! Decoding and encoding of FITS images
+//!
+//! The Flexible Image Transport System is the standard file format of astronomy. A file is a
+//! sequence of header and data units. A header is made of 80 character ASCII cards of the form
+//! `KEYWORD = value / comment`, padded to blocks of 2880 bytes, and is followed by the big endian
+//! samples of an array, again padded to whole blocks.
+//!
+//! Only the image of the primary unit is supported, with two axes, or with three axes of which the
+//! third holds one or three color planes. Unsigned 8 bit samples, and unsigned 16 bit samples
+//! which FITS stores as signed ones with an offset of `BZERO = 32768`, decode to `L8` and `L16`,
+//! or `Rgb8` and `Rgb16`. All other samples decode to `Rgb32F` holding the physical values
+//! `BZERO + BSCALE * sample`, with undefined (`BLANK`) samples as NaN. Gray images repeat their
+//! values in all three channels in this case.
+//!
+//! The first row of the array is the first row of the image. Viewers that follow the convention of
+//! putting the first row at the bottom show it flipped vertically.
+//!
+//! # Related Links
+//! * <https://fits.gsfc.nasa.gov/fits_standard.html> - the FITS standard
+
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read, Write};
+use std::marker::PhantomData;
+use std::mem;
+
+use byteorder::{BigEndian, ByteOrder, NativeEndian};
+
+use crate::color::ColorType;
+use crate::error::{
+    DecodingError, ImageError, ImageResult, ParameterError, ParameterErrorKind, UnsupportedError,
+    UnsupportedErrorKind,
+};
+use crate::image::{self, ImageDecoder, ImageEncoder, ImageFormat};
+use crate::io::Limits;
+
+/// The size of the blocks that headers and data are padded to.
+const BLOCK_SIZE: usize = 2880;
+/// The size of a header card.
+const CARD_SIZE: usize = 80;
+
+/// The value of a header keyword.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeaderValue {
+    /// A logical value, written as `T` or `F`.
+    Logical(bool),
+    /// An integer.
+    Integer(i64),
+    /// A floating point number.
+    Real(f64),
+    /// A character string, without its quotes and trailing spaces.
+    String(String),
+    /// A value of another kind, such as a complex number, as it is written.
+    Other(String),
+}
+
+impl HeaderValue {
+    /// The value as a logical, if it is one.
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            HeaderValue::Logical(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value as an integer, if it is one.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            HeaderValue::Integer(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value as a floating point number, if it is an integer or a real number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            HeaderValue::Integer(value) => Some(value as f64),
+            HeaderValue::Real(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The value as a string, if it is a character string.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            HeaderValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+/// A card of a header, a keyword with an optional value and comment.
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeaderCard {
+    /// The keyword, such as `EXPTIME`. It is empty for blank cards.
+    pub keyword: String,
+    /// The value, if the card has one.
+    pub value: Option<HeaderValue>,
+    /// The comment, or the text of commentary cards such as `COMMENT` and `HISTORY`.
+    pub comment: Option<String>,
+}
+
+impl HeaderCard {
+    fn parse(card: &[u8]) -> HeaderCard {
+        let text = String::from_utf8_lossy(card);
+        let (keyword, rest) = match (text.get(..8), text.get(8..)) {
+            (Some(keyword), Some(rest)) => (keyword, rest),
+            _ => (&*text, ""),
+        };
+        let keyword = keyword.trim_end().to_string();
+
+        // Cards without the value indicator are commentary.
+        if !rest.starts_with("= ") {
+            return HeaderCard {
+                keyword,
+                value: None,
+                comment: Some(rest.trim().to_string()).filter(|c| !c.is_empty()),
+            };
+        }
+
+        let field = rest[2..].trim_start();
+        let (value, comment) = if let Some(string) = field.strip_prefix('\'') {
+            // Quotes within strings are doubled.
+            let mut value = String::new();
+            let mut end = string.len();
+            let mut chars = string.char_indices().peekable();
+            while let Some((i, c)) = chars.next() {
+                if c != '\'' {
+                    value.push(c);
+                } else if chars.peek().map(|&(_, c)| c) == Some('\'') {
+                    chars.next();
+                    value.push(c);
+                } else {
+                    end = i + 1;
+                    break;
+                }
+            }
+            let value = HeaderValue::String(value.trim_end().to_string());
+            (Some(value), parse_comment(&string[end..]))
+        } else {
+            let (value, comment) = match field.find('/') {
+                Some(slash) => (&field[..slash], parse_comment(&field[slash..])),
+                None => (field, None),
+            };
+            let value = value.trim();
+            if value.is_empty() {
+                (None, comment)
+            } else {
+                (Some(parse_value(value)), comment)
+            }
+        };
+
+        HeaderCard {
+            keyword,
+            value,
+            comment,
+        }
+    }
+
+    /// Write the card, padded to 80 characters. Values are right aligned in columns 11 to 30.
+    fn write<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        let mut card = format!("{:<8}", self.keyword);
+        if let Some(value) = &self.value {
+            let value = match value {
+                HeaderValue::Logical(value) => format!("{:>20}", if *value { 'T' } else { 'F' }),
+                HeaderValue::Integer(value) => format!("{:>20}", value),
+                HeaderValue::Real(value) => format!("{:>20?}", value),
+                HeaderValue::String(value) => format!("'{:<8}'", value.replace('\'', "''")),
+                HeaderValue::Other(value) => format!("{:>20}", value),
+            };
+            card.push_str("= ");
+            card.push_str(&value);
+        }
+        if let Some(comment) = &self.comment {
+            if self.value.is_some() {
+                card.push_str(" / ");
+            }
+            card.push_str(comment);
+        }
+        let mut bytes = card.into_bytes();
+        bytes.resize(CARD_SIZE, b' ');
+        w.write_all(&bytes[..CARD_SIZE])
+    }
+}
+
+fn parse_comment(rest: &str) -> Option<String> {
+    rest.trim_start()
+        .strip_prefix('/')
+        .map(|comment| comment.trim().to_string())
+        .filter(|comment| !comment.is_empty())
+}
+
+fn parse_value(value: &str) -> HeaderValue {
+    match value {
+        "T" => HeaderValue::Logical(true),
+        "F" => HeaderValue::Logical(false),
+        _ => {
+            if let Ok(value) = value.parse() {
+                HeaderValue::Integer(value)
+            } else if let Ok(value) = value.replace(|c: char| c == 'D' || c == 'd', "E").parse() {
+                // Fortran style exponents are allowed.
+                HeaderValue::Real(value)
+            } else {
+                HeaderValue::Other(value.to_string())
+            }
+        }
+    }
+}
+
+/// The reader returned by `FitsDecoder::into_reader`.
+pub struct FitsReader<R>(Cursor<Vec<u8>>, PhantomData<R>);
+impl<R> Read for FitsReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        if self.0.position() == 0 && buf.is_empty() {
+            mem::swap(buf, self.0.get_mut());
+            Ok(buf.len())
+        } else {
+            self.0.read_to_end(buf)
+        }
+    }
+}
+
+/// FITS decoder
+pub struct FitsDecoder<R: Read> {
+    r: R,
+    cards: Vec<HeaderCard>,
+    width: u32,
+    height: u32,
+    planes: usize,
+    bitpix: i64,
+    bzero: f64,
+    bscale: f64,
+    blank: Option<i64>,
+    color_type: ColorType,
+    /// The limits on the big endian samples that are read before they are interleaved.
+    limits: Limits,
+}
+
+impl<R: Read> FitsDecoder<R> {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new(mut r: R) -> ImageResult<FitsDecoder<R>> {
+        let cards = read_header(&mut r)?;
+        let value = |keyword: &str| {
+            cards
+                .iter()
+                .find(|card| card.keyword == keyword)
+                .and_then(|card| card.value.as_ref())
+        };
+        let integer = |keyword: &str| {
+            value(keyword)
+                .and_then(HeaderValue::as_i64)
+                .ok_or_else(|| decoding_error(format!("missing or invalid keyword {}", keyword)))
+        };
+
+        let simple = cards.first().map_or(false, |card| {
+            card.keyword == "SIMPLE" && card.value == Some(HeaderValue::Logical(true))
+        });
+        if !simple {
+            return Err(decoding_error("not a standard FITS file".to_string()));
+        }
+
+        let bitpix = integer("BITPIX")?;
+        if ![8, 16, 32, 64, -32, -64].contains(&bitpix) {
+            return Err(decoding_error(format!("invalid BITPIX {}", bitpix)));
+        }
+        let axes = match integer("NAXIS")? {
+            2 => [integer("NAXIS1")?, integer("NAXIS2")?, 1],
+            3 => [integer("NAXIS1")?, integer("NAXIS2")?, integer("NAXIS3")?],
+            naxis => {
+                return Err(unsupported_error(format!(
+                    "primary array with {} axes",
+                    naxis
+                )))
+            }
+        };
+        let dimension = |length: i64| {
+            u32::try_from(length).map_err(|_| decoding_error(format!("invalid axis {}", length)))
+        };
+        let (width, height) = (dimension(axes[0])?, dimension(axes[1])?);
+        let planes = match axes[2] {
+            1 => 1,
+            3 => 3,
+            planes => return Err(unsupported_error(format!("{} image planes", planes))),
+        };
+
+        let bzero = value("BZERO").and_then(HeaderValue::as_f64).unwrap_or(0.0);
+        let bscale = value("BSCALE").and_then(HeaderValue::as_f64).unwrap_or(1.0);
+        let blank = value("BLANK").and_then(HeaderValue::as_i64);
+        let color_type = match (bitpix, bzero, bscale, planes) {
+            (8, z, s, 1) if z == 0.0 && s == 1.0 => ColorType::L8,
+            (8, z, s, _) if z == 0.0 && s == 1.0 => ColorType::Rgb8,
+            (16, z, s, 1) if z == 32768.0 && s == 1.0 => ColorType::L16,
+            (16, z, s, _) if z == 32768.0 && s == 1.0 => ColorType::Rgb16,
+            _ => ColorType::Rgb32F,
+        };
+
+        if crate::utils::check_dimension_overflow(width, height, color_type.bytes_per_pixel()) {
+            return Err(unsupported_error(format!(
+                "Image dimensions ({}x{}) are too large",
+                width, height
+            )));
+        }
+
+        Ok(FitsDecoder {
+            r,
+            cards,
+            width,
+            height,
+            planes,
+            bitpix,
+            bzero,
+            bscale,
+            blank,
+            color_type,
+            limits: Limits::no_limits(),
+        })
+    }
+
+    /// The cards of the primary header, in the order of the file.
+    pub fn header(&self) -> &[HeaderCard] {
+        &self.cards
+    }
+
+    /// The value of the first card with a keyword, such as `EXPTIME` or `OBJECT`.
+    pub fn keyword(&self, keyword: &str) -> Option<&HeaderValue> {
+        self.cards
+            .iter()
+            .filter(|card| card.keyword == keyword)
+            .find_map(|card| card.value.as_ref())
+    }
+
+    /// The physical value of a big endian sample.
+    fn physical_value(&self, sample: &[u8]) -> f32 {
+        let raw = match self.bitpix {
+            8 => i64::from(sample[0]),
+            16 => i64::from(BigEndian::read_i16(sample)),
+            32 => i64::from(BigEndian::read_i32(sample)),
+            64 => BigEndian::read_i64(sample),
+            -32 => {
+                return (self.bzero + self.bscale * f64::from(BigEndian::read_f32(sample))) as f32
+            }
+            _ => return (self.bzero + self.bscale * BigEndian::read_f64(sample)) as f32,
+        };
+        if Some(raw) == self.blank {
+            f32::NAN
+        } else {
+            (self.bzero + self.bscale * raw as f64) as f32
+        }
+    }
+}
+
+impl<'a, R: 'a + Read> ImageDecoder<'a> for FitsDecoder<R> {
+    type Reader = FitsReader<R>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        Ok(FitsReader(
+            Cursor::new(image::decoder_to_vec(self)?),
+            PhantomData,
+        ))
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        self.limits = limits;
+        Ok(())
+    }
+
+    fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+
+        let pixels = self.width as usize * self.height as usize;
+        let sample_size = self.bitpix.abs() as usize / 8;
+        let data_size = pixels * self.planes * sample_size;
+        self.limits.reserve(data_size as u64)?;
+        let mut data = vec![0; data_size];
+        self.r.read_exact(&mut data)?;
+
+        // Samples are stored plane by plane, the output interleaves them.
+        let planes = self.planes;
+        let index = |i: usize| (i % pixels) * planes + i / pixels;
+        match self.color_type {
+            ColorType::L8 | ColorType::Rgb8 => {
+                for (i, &sample) in data.iter().enumerate() {
+                    buf[index(i)] = sample;
+                }
+            }
+            ColorType::L16 | ColorType::Rgb16 => {
+                for (i, sample) in data.chunks_exact(2).enumerate() {
+                    // Adding the offset of 32768 flips the sign bit.
+                    let value = BigEndian::read_u16(sample) ^ 0x8000;
+                    NativeEndian::write_u16(&mut buf[index(i) * 2..][..2], value);
+                }
+            }
+            _ => {
+                for (i, sample) in data.chunks_exact(sample_size).enumerate() {
+                    let value = self.physical_value(sample);
+                    if planes == 1 {
+                        for channel in buf[i * 12..][..12].chunks_exact_mut(4) {
+                            NativeEndian::write_f32(channel, value);
+                        }
+                    } else {
+                        NativeEndian::write_f32(&mut buf[index(i) * 4..][..4], value);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Read the cards of a header up to its `END` card, and the rest of its last block.
+fn read_header<R: Read>(r: &mut R) -> ImageResult<Vec<HeaderCard>> {
+    let mut cards = Vec::new();
+    let mut block = [0; BLOCK_SIZE];
+    loop {
+        r.read_exact(&mut block)?;
+        for card in block.chunks_exact(CARD_SIZE) {
+            let card = HeaderCard::parse(card);
+            if card.keyword == "END" {
+                return Ok(cards);
+            }
+            cards.push(card);
+        }
+    }
+}
+
+fn decoding_error(message: String) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormat::Fits.into(), message))
+}
+
+fn unsupported_error(feature: String) -> ImageError {
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        ImageFormat::Fits.into(),
+        UnsupportedErrorKind::GenericFeature(feature),
+    ))
+}
+
+/// FITS encoder
+///
+/// Writes `L8` and `Rgb8` images as unsigned 8 bit samples, `L16` and `Rgb16` images as 16 bit
+/// samples with an offset of 32768, and `Rgb32F` images as 32 bit floating point samples. Color
+/// images are written as three planes.
+pub struct FitsEncoder<W: Write> {
+    w: W,
+    cards: Vec<HeaderCard>,
+}
+
+impl<W: Write> FitsEncoder<W> {
+    /// Create a new encoder that writes its output to ```w```. The writer should be buffered.
+    pub fn new(w: W) -> FitsEncoder<W> {
+        FitsEncoder {
+            w,
+            cards: Vec::new(),
+        }
+    }
+
+    /// Add a card to the header, after the cards that describe the image.
+    ///
+    /// Keywords are truncated to eight characters.
+    pub fn add_card(&mut self, card: HeaderCard) {
+        self.cards.push(card);
+    }
+
+    /// Encode an image with the given dimensions and color type.
+    pub fn encode(
+        mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        let (bitpix, planes) = match color_type {
+            ColorType::L8 => (8, 1),
+            ColorType::Rgb8 => (8, 3),
+            ColorType::L16 => (16, 1),
+            ColorType::Rgb16 => (16, 3),
+            ColorType::Rgb32F => (-32, 3),
+            _ => {
+                return Err(ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(
+                        ImageFormat::Fits.into(),
+                        UnsupportedErrorKind::Color(color_type.into()),
+                    ),
+                ))
+            }
+        };
+        let pixels = width as usize * height as usize;
+        let sample_size = usize::from(color_type.bytes_per_pixel()) / planes;
+        if data.len() != pixels * planes * sample_size {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+
+        let card = |keyword: &str, value| HeaderCard {
+            keyword: keyword.to_string(),
+            value: Some(value),
+            comment: None,
+        };
+        let mut header = vec![
+            card("SIMPLE", HeaderValue::Logical(true)),
+            card("BITPIX", HeaderValue::Integer(bitpix)),
+            card(
+                "NAXIS",
+                HeaderValue::Integer(if planes == 1 { 2 } else { 3 }),
+            ),
+            card("NAXIS1", HeaderValue::Integer(i64::from(width))),
+            card("NAXIS2", HeaderValue::Integer(i64::from(height))),
+        ];
+        if planes == 3 {
+            header.push(card("NAXIS3", HeaderValue::Integer(3)));
+        }
+        if bitpix == 16 {
+            header.push(card("BZERO", HeaderValue::Integer(32768)));
+            header.push(card("BSCALE", HeaderValue::Integer(1)));
+        }
+        header.append(&mut self.cards);
+        header.push(HeaderCard {
+            keyword: "END".to_string(),
+            value: None,
+            comment: None,
+        });
+
+        let mut bytes = Vec::new();
+        for card in &header {
+            card.write(&mut bytes)?;
+        }
+        pad(&mut bytes, b' ');
+        self.w.write_all(&bytes)?;
+
+        let mut bytes = Vec::with_capacity(data.len() + BLOCK_SIZE);
+        for plane in 0..planes {
+            for pixel in 0..pixels {
+                let offset = (pixel * planes + plane) * sample_size;
+                let sample = &data[offset..][..sample_size];
+                match bitpix {
+                    8 => bytes.push(sample[0]),
+                    16 => bytes.extend_from_slice(
+                        &(NativeEndian::read_u16(sample) ^ 0x8000).to_be_bytes(),
+                    ),
+                    _ => bytes.extend_from_slice(&NativeEndian::read_f32(sample).to_be_bytes()),
+                }
+            }
+        }
+        pad(&mut bytes, 0);
+        self.w.write_all(&bytes)?;
+        Ok(())
+    }
+}
+
+/// Pad a header or data unit to a whole number of blocks.
+fn pad(bytes: &mut Vec<u8>, fill: u8) {
+    let len = (bytes.len() + BLOCK_SIZE - 1) / BLOCK_SIZE * BLOCK_SIZE;
+    bytes.resize(len, fill);
+}
+
+impl<W: Write> ImageEncoder for FitsEncoder<W> {
+    fn write_image(
+        self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        self.encode(buf, width, height, color_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(cards: &[&str], data: &[u8]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for card in cards.iter().chain(&["END"]) {
+            bytes.extend_from_slice(format!("{:<80}", card).as_bytes());
+        }
+        pad(&mut bytes, b' ');
+        bytes.extend_from_slice(data);
+        pad(&mut bytes, 0);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> (ColorType, Vec<u8>) {
+        let decoder = FitsDecoder::new(Cursor::new(bytes)).unwrap();
+        let color_type = decoder.color_type();
+        (color_type, image::decoder_to_vec(decoder).unwrap())
+    }
+
+    #[test]
+    fn header_cards() {
+        let bytes = file(
+            &[
+                "SIMPLE  =                    T / conforms to FITS standard",
+                "BITPIX  =                    8",
+                "NAXIS   =                    2",
+                "NAXIS1  =                    1",
+                "NAXIS2  =                    1",
+                "OBJECT  = 'M 31''s core'       / target",
+                "EXPTIME =              1.5D+02",
+                "COMMENT   taken with a small refractor",
+                "DATE-OBS= '2021-10-02T21:30:00'",
+            ],
+            &[7],
+        );
+        let decoder = FitsDecoder::new(Cursor::new(&bytes)).unwrap();
+        assert_eq!(decoder.header().len(), 9);
+        assert_eq!(
+            decoder.header()[0].comment.as_deref(),
+            Some("conforms to FITS standard")
+        );
+        assert_eq!(
+            decoder.keyword("OBJECT").and_then(HeaderValue::as_str),
+            Some("M 31's core")
+        );
+        assert_eq!(
+            decoder.keyword("EXPTIME").and_then(HeaderValue::as_f64),
+            Some(150.0)
+        );
+        assert_eq!(
+            decoder.header()[7].comment.as_deref(),
+            Some("taken with a small refractor")
+        );
+        assert_eq!(decoder.keyword("COMMENT"), None);
+        assert_eq!(
+            decoder.keyword("DATE-OBS").and_then(HeaderValue::as_str),
+            Some("2021-10-02T21:30:00")
+        );
+    }
+
+    #[test]
+    fn unsigned_16_bit() {
+        let bytes = file(
+            &[
+                "SIMPLE  = T",
+                "BITPIX  = 16",
+                "NAXIS   = 2",
+                "NAXIS1  = 2",
+                "NAXIS2  = 1",
+                "BZERO   = 32768",
+            ],
+            &[0x80, 0x00, 0x7F, 0xFF],
+        );
+        let (color_type, data) = decode(&bytes);
+        assert_eq!(color_type, ColorType::L16);
+        let samples: Vec<u16> = data.chunks(2).map(NativeEndian::read_u16).collect();
+        assert_eq!(samples, [0, 65535]);
+    }
+
+    #[test]
+    fn scaled_samples_and_blanks() {
+        let bytes = file(
+            &[
+                "SIMPLE  = T",
+                "BITPIX  = 16",
+                "NAXIS   = 2",
+                "NAXIS1  = 2",
+                "NAXIS2  = 1",
+                "BSCALE  = 0.5",
+                "BZERO   = 10.0",
+                "BLANK   = -1",
+            ],
+            &[0x00, 0x04, 0xFF, 0xFF],
+        );
+        let (color_type, data) = decode(&bytes);
+        assert_eq!(color_type, ColorType::Rgb32F);
+        let samples: Vec<f32> = data.chunks(4).map(NativeEndian::read_f32).collect();
+        assert_eq!(samples[..3], [12.0; 3]);
+        assert!(samples[3..].iter().all(|sample| sample.is_nan()));
+    }
+
+    #[test]
+    fn color_planes() {
+        let bytes = file(
+            &[
+                "SIMPLE  = T",
+                "BITPIX  = 8",
+                "NAXIS   = 3",
+                "NAXIS1  = 2",
+                "NAXIS2  = 1",
+                "NAXIS3  = 3",
+            ],
+            &[1, 2, 3, 4, 5, 6],
+        );
+        assert_eq!(decode(&bytes), (ColorType::Rgb8, vec![1, 3, 5, 2, 4, 6]));
+    }
+
+    #[test]
+    fn round_trips() {
+        let floats: Vec<u8> = [0.25f32, -1.0, 1e6, 2.0, 3.0, 4.0]
+            .iter()
+            .flat_map(|value| value.to_ne_bytes().to_vec())
+            .collect();
+        let sixteen: Vec<u8> = [0u16, 1, 32767, 65535]
+            .iter()
+            .flat_map(|value| value.to_ne_bytes().to_vec())
+            .collect();
+        let images = [
+            (ColorType::L8, 3, vec![0, 128, 255]),
+            (ColorType::Rgb8, 1, vec![10, 20, 30]),
+            (ColorType::L16, 4, sixteen),
+            (ColorType::Rgb32F, 2, floats),
+        ];
+        for (color_type, width, data) in images.iter().cloned() {
+            let mut encoded = Vec::new();
+            let mut encoder = FitsEncoder::new(&mut encoded);
+            encoder.add_card(HeaderCard {
+                keyword: "OBJECT".to_string(),
+                value: Some(HeaderValue::String("it's".to_string())),
+                comment: Some("test".to_string()),
+            });
+            encoder.encode(&data, width, 1, color_type).unwrap();
+            assert_eq!(encoded.len() % BLOCK_SIZE, 0);
+            assert_eq!(decode(&encoded), (color_type, data));
+
+            let decoder = FitsDecoder::new(Cursor::new(&encoded)).unwrap();
+            assert_eq!(
+                decoder.keyword("OBJECT").and_then(HeaderValue::as_str),
+                Some("it's")
+            );
+        }
+    }
+
+    #[test]
+    fn unsupported_files() {
+        let no_image = file(&["SIMPLE  = T", "BITPIX  = 8", "NAXIS   = 0"], &[]);
+        assert!(FitsDecoder::new(Cursor::new(no_image)).is_err());
+        let not_simple = file(&["SIMPLE  = F", "BITPIX  = 8", "NAXIS   = 0"], &[]);
+        assert!(FitsDecoder::new(Cursor::new(not_simple)).is_err());
+        let encoder = FitsEncoder::new(Vec::new());
+        assert!(encoder.encode(&[0; 4], 1, 1, ColorType::Rgba8).is_err());
+    }
+}
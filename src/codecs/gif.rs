@@ -26,9 +26,12 @@
 //! ```
 #![allow(clippy::while_let_loop)]
 
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::convert::TryInto;
 use std::io::{self, Cursor, Read, Write};
+use std::iter;
 use std::marker::PhantomData;
 use std::mem;
 
@@ -37,18 +40,26 @@ use gif::{DisposalMethod, Frame};
 use num_rational::Ratio;
 
 use crate::animation;
-use crate::color::{ColorType, Rgba};
+use crate::animation::DirtyRegion;
+use crate::color::{ColorType, Rgb, Rgba};
 use crate::error::{
     DecodingError, EncodingError, ImageError, ImageResult, ParameterError, ParameterErrorKind,
     UnsupportedError, UnsupportedErrorKind,
 };
-use crate::image::{self, AnimationDecoder, ImageDecoder, ImageFormat};
+use crate::image::{self, AnimationDecoder, ImageDecoder, ImageEncoder, ImageFormat};
+use crate::imageops::colorops::ColorMap;
+use crate::imageops::dither::{dither_with, DitherAlgorithm};
+use crate::io::Limits;
+use crate::math::Rect;
+use crate::palette::Palette;
 use crate::traits::Pixel;
 use crate::ImageBuffer;
 
 /// GIF decoder
 pub struct GifDecoder<R: Read> {
     reader: gif::Decoder<R>,
+    /// The limits on the frame buffers that are allocated next to the output buffer.
+    limits: Limits,
 }
 
 impl<R: Read> GifDecoder<R> {
@@ -59,8 +70,68 @@ impl<R: Read> GifDecoder<R> {
 
         Ok(GifDecoder {
             reader: decoder.read_info(r).map_err(ImageError::from_decoding)?,
+            limits: Limits::no_limits(),
         })
     }
+
+    /// Creates a new decoder that decodes the input stream ```r``` within the given limits.
+    ///
+    /// The `max_alloc` limit bounds the buffer of each frame and, when the frames are composited,
+    /// the canvases that they are drawn on.
+    pub fn with_limits(r: R, limits: Limits) -> ImageResult<GifDecoder<R>> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let mut decoder = gif::DecodeOptions::new();
+        decoder.set_color_output(ColorOutput::RGBA);
+        // A memory limit of zero disables the limit of the gif crate.
+        let memory_limit = u32::try_from(limits.max_alloc_usize()).unwrap_or(u32::MAX);
+        decoder.set_memory_limit(gif::MemoryLimit(memory_limit.max(1)));
+
+        let reader = decoder.read_info(r).map_err(ImageError::from_decoding)?;
+        limits.check_dimensions(u32::from(reader.width()), u32::from(reader.height()))?;
+        Ok(GifDecoder { reader, limits })
+    }
+
+    /// Returns the global color table of the image, if it has one.
+    ///
+    /// Frames may additionally define local color tables which take precedence over the global
+    /// one. Use [`replace_global_palette`] to write a recolored version of the image.
+    ///
+    /// [`replace_global_palette`]: fn.replace_global_palette.html
+    pub fn global_palette(&self) -> Option<Vec<Rgb<u8>>> {
+        let palette = self.reader.global_palette()?;
+        let entries = palette
+            .chunks_exact(3)
+            .map(|rgb| Rgb([rgb[0], rgb[1], rgb[2]]))
+            .collect();
+        Some(entries)
+    }
+
+    /// The global color table entry that the logical screen descriptor names as background.
+    fn background_color(&self) -> Option<Rgba<u8>> {
+        let palette = self.global_palette()?;
+        let Rgb([r, g, b]) = *palette.get(self.reader.bg_color()?)?;
+        Some(Rgba([r, g, b, 255]))
+    }
+
+    fn frames<'a>(self, composite: bool) -> animation::Frames<'a>
+    where
+        R: 'a,
+    {
+        let (width, height) = (
+            u32::from(self.reader.width()),
+            u32::from(self.reader.height()),
+        );
+        let background = self.background_color();
+        let frames = match GifFrameIterator::new(self, composite) {
+            Ok(iterator) => animation::Frames::new(Box::new(iterator)),
+            Err(err) => animation::Frames::new(Box::new(iter::once(Err(err)))),
+        }
+        .with_canvas(width, height);
+        match background {
+            Some(color) => frames.with_background(color),
+            None => frames,
+        }
+    }
 }
 
 /// Wrapper struct around a `Cursor<Vec<u8>>`
@@ -93,6 +164,14 @@ impl<'a, R: 'a + Read> ImageDecoder<'a> for GifDecoder<R> {
         ColorType::Rgba8
     }
 
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        self.limits = limits;
+        Ok(())
+    }
+
     fn into_reader(self) -> ImageResult<Self::Reader> {
         Ok(GifReader(
             Cursor::new(image::decoder_to_vec(self)?),
@@ -102,6 +181,7 @@ impl<'a, R: 'a + Read> ImageDecoder<'a> for GifDecoder<R> {
 
     fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
         assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        self.limits.check_cancelled()?;
 
         let frame = match self
             .reader
@@ -154,6 +234,9 @@ impl<'a, R: 'a + Read> ImageDecoder<'a> for GifDecoder<R> {
         } else {
             // If the frame does not match the logical screen, read into an extra buffer
             // and 'insert' the frame from left/top to logical screen width/height.
+            self.limits
+                .clone()
+                .reserve(self.reader.buffer_size() as u64)?;
             let mut frame_buffer = vec![0; self.reader.buffer_size()];
             self.reader
                 .read_into_buffer(&mut frame_buffer[..])
@@ -204,10 +287,14 @@ struct GifFrameIterator<R: Read> {
     height: u32,
 
     non_disposed_frame: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    dirty: DirtyRegion,
+    composite: bool,
+    /// The limits that remain for the buffers of each frame.
+    limits: Limits,
 }
 
 impl<R: Read> GifFrameIterator<R> {
-    fn new(decoder: GifDecoder<R>) -> GifFrameIterator<R> {
+    fn new(decoder: GifDecoder<R>, composite: bool) -> ImageResult<GifFrameIterator<R>> {
         let (width, height) = decoder.dimensions();
 
         // TODO: Avoid this cast
@@ -216,14 +303,19 @@ impl<R: Read> GifFrameIterator<R> {
         // intentionally ignore the background color for web compatibility
 
         // create the first non disposed frame
+        let mut limits = decoder.limits;
+        limits.reserve(u64::from(width) * u64::from(height) * 4)?;
         let non_disposed_frame = ImageBuffer::from_pixel(width, height, Rgba([0, 0, 0, 0]));
 
-        GifFrameIterator {
+        Ok(GifFrameIterator {
             reader: decoder.reader,
             width,
             height,
             non_disposed_frame,
-        }
+            dirty: DirtyRegion::new(width, height),
+            composite,
+            limits,
+        })
     }
 }
 
@@ -231,6 +323,11 @@ impl<R: Read> Iterator for GifFrameIterator<R> {
     type Item = ImageResult<animation::Frame>;
 
     fn next(&mut self) -> Option<ImageResult<animation::Frame>> {
+        // Frames are decoded without reading in between, check for cancellation before each.
+        if let Err(err) = self.limits.check_cancelled() {
+            return Some(Err(err));
+        }
+
         // begin looping over each frame
 
         let frame = match self.reader.next_frame_info() {
@@ -245,6 +342,19 @@ impl<R: Read> Iterator for GifFrameIterator<R> {
             Err(err) => return Some(Err(ImageError::from_decoding(err))),
         };
 
+        // The frame and, when it does not cover the screen, the canvas it is composited on.
+        let covers_screen =
+            (frame.left, frame.top, frame.width, frame.height) == (0, 0, self.width, self.height);
+        let canvas_bytes = if self.composite && !covers_screen {
+            u64::from(self.width) * u64::from(self.height) * 4
+        } else {
+            0
+        };
+        let frame_bytes = self.reader.buffer_size() as u64;
+        if let Err(err) = self.limits.clone().reserve(frame_bytes + canvas_bytes) {
+            return Some(Err(err));
+        }
+
         let mut vec = vec![0; self.reader.buffer_size()];
         if let Err(err) = self.reader.read_into_buffer(&mut vec) {
             return Some(Err(ImageError::from_decoding(err)));
@@ -270,6 +380,15 @@ impl<R: Read> Iterator for GifFrameIterator<R> {
             }
         };
 
+        if !self.composite {
+            return Some(Ok(animation::Frame::from_parts(
+                frame_buffer,
+                frame.left,
+                frame.top,
+                frame.delay,
+            )));
+        }
+
         // blend the current frame with the non-disposed frame, then update
         // the non-disposed frame according to the disposal method.
         fn blend_and_dispose_pixel(
@@ -330,18 +449,30 @@ impl<R: Read> Iterator for GifFrameIterator<R> {
             })
         };
 
-        Some(Ok(animation::Frame::from_parts(
-            image_buffer,
-            0,
-            0,
-            frame.delay,
-        )))
+        let area = Rect {
+            x: frame.left,
+            y: frame.top,
+            width: frame.width,
+            height: frame.height,
+        };
+        let disposed = matches!(
+            frame.disposal_method,
+            DisposalMethod::Background | DisposalMethod::Previous
+        );
+        let dirty = self.dirty.next_frame(area, disposed);
+
+        let composited = animation::Frame::from_parts(image_buffer, 0, 0, frame.delay);
+        Some(Ok(composited.with_dirty_rect(dirty)))
     }
 }
 
 impl<'a, R: Read + 'a> AnimationDecoder<'a> for GifDecoder<R> {
     fn into_frames(self) -> animation::Frames<'a> {
-        animation::Frames::new(Box::new(GifFrameIterator::new(self)))
+        self.frames(true)
+    }
+
+    fn into_raw_frames(self) -> animation::Frames<'a> {
+        self.frames(false)
     }
 }
 
@@ -386,12 +517,43 @@ impl Repeat {
     }
 }
 
+/// How the GIF encoder reduces the colors of a frame to the at most 256 colors of its palette.
+///
+/// Frames with few enough colors keep their exact colors, except with a fixed `Palette`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Quantizer {
+    /// The NeuQuant neural network, sampling pixels with a factor from 1 to 30. A factor of 1
+    /// learns from every pixel and finds the best colors, higher factors are faster. This is what
+    /// the encoder uses unless told otherwise, with the speed it was created with.
+    NeuQuant(i32),
+    /// Median cut, which splits the colors into boxes of about as many pixels each. It is faster
+    /// than NeuQuant and divides gradients evenly, but spends few colors on small details.
+    MedianCut,
+    /// A palette chosen by the caller, such as the colors of a brand or a retro platform. It
+    /// holds at most 256 colors, or 255 if a frame has transparent pixels.
+    Palette(Palette),
+}
+
+/// How the GIF encoder hides the steps between the colors of the palette.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Dithering {
+    /// Map each pixel to the nearest color, which shows bands in gradients.
+    None,
+    /// Floyd-Steinberg error diffusion, which gives the smoothest result but a noisy pattern
+    /// that changes between the frames of an animation and compresses poorly.
+    FloydSteinberg,
+    /// Ordered dithering with a 4x4 Bayer matrix, whose regular pattern stays in place between
+    /// frames and compresses better.
+    Ordered,
+}
+
 /// GIF encoder.
 pub struct GifEncoder<W: Write> {
     w: Option<W>,
     gif_encoder: Option<gif::Encoder<W>>,
     speed: i32,
     repeat: Option<Repeat>,
+    quantization: Option<(Quantizer, Dithering)>,
 }
 
 impl<W: Write> GifEncoder<W> {
@@ -413,7 +575,32 @@ impl<W: Write> GifEncoder<W> {
             gif_encoder: None,
             speed,
             repeat: None,
+            quantization: None,
+        }
+    }
+
+    /// Reduce the colors of frames with `quantizer`, and dither them with `dithering`.
+    ///
+    /// ```no_run
+    /// use image::codecs::gif::{Dithering, GifEncoder, Quantizer};
+    ///
+    /// let file = std::fs::File::create("gradient.gif")?;
+    /// let encoder = GifEncoder::new(file).with_quantizer(Quantizer::MedianCut, Dithering::Ordered);
+    /// # Ok::<(), image::ImageError>(())
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the sampling factor of `Quantizer::NeuQuant` is not in the range [1, 30].
+    pub fn with_quantizer(mut self, quantizer: Quantizer, dithering: Dithering) -> Self {
+        if let Quantizer::NeuQuant(factor) = quantizer {
+            assert!(
+                (1..=30).contains(&factor),
+                "sampling factor needs to be in the range [1, 30]"
+            );
         }
+        self.quantization = Some((quantizer, dithering));
+        self
     }
 
     /// Set the repeat behaviour of the encoded GIF
@@ -435,12 +622,24 @@ impl<W: Write> GifEncoder<W> {
         height: u32,
         color: ColorType,
     ) -> ImageResult<()> {
-        let (width, height) = self.gif_dimensions(width, height)?;
-        match color {
-            ColorType::Rgb8 => self.encode_gif(Frame::from_rgb(width, height, data)),
-            ColorType::Rgba8 => {
+        let (width, height) = gif_dimensions(width, height)?;
+        match (color, &self.quantization) {
+            (ColorType::Rgb8, None) => self.encode_gif(Frame::from_rgb(width, height, data)),
+            (ColorType::Rgba8, None) => {
                 self.encode_gif(Frame::from_rgba(width, height, &mut data.to_owned()))
             }
+            (ColorType::Rgb8, Some((quantizer, dithering))) => {
+                let rgba: Vec<u8> = data
+                    .chunks_exact(3)
+                    .flat_map(|p| vec![p[0], p[1], p[2], 0xFF])
+                    .collect();
+                let frame = quantize_frame(width, height, &rgba, quantizer, *dithering)?;
+                self.encode_gif(frame)
+            }
+            (ColorType::Rgba8, Some((quantizer, dithering))) => {
+                let frame = quantize_frame(width, height, data, quantizer, *dithering)?;
+                self.encode_gif(frame)
+            }
             _ => Err(ImageError::Unsupported(
                 UnsupportedError::from_format_and_kind(
                     ImageFormat::Gif.into(),
@@ -452,69 +651,37 @@ impl<W: Write> GifEncoder<W> {
 
     /// Encode one frame of animation.
     pub fn encode_frame(&mut self, img_frame: animation::Frame) -> ImageResult<()> {
-        let frame = self.convert_frame(img_frame)?;
+        let frame = convert_frame(img_frame, self.speed, self.quantization.as_ref())?;
         self.encode_gif(frame)
     }
 
     /// Encodes Frames.
     /// Consider using `try_encode_frames` instead to encode an `animation::Frames` like iterator.
+    ///
+    /// With the `rayon` feature, the colors of several frames are quantized in parallel.
     pub fn encode_frames<F>(&mut self, frames: F) -> ImageResult<()>
     where
         F: IntoIterator<Item = animation::Frame>,
     {
-        for img_frame in frames {
-            self.encode_frame(img_frame)?;
-        }
-        Ok(())
+        self.try_encode_frames(frames.into_iter().map(Ok))
     }
 
     /// Try to encode a collection of `ImageResult<animation::Frame>` objects.
     /// Use this function to encode an `animation::Frames` like iterator.
     /// Whenever an `Err` item is encountered, that value is returned without further actions.
+    ///
+    /// With the `rayon` feature, the colors of several frames are quantized in parallel.
     pub fn try_encode_frames<F>(&mut self, frames: F) -> ImageResult<()>
     where
         F: IntoIterator<Item = ImageResult<animation::Frame>>,
     {
-        for img_frame in frames {
-            self.encode_frame(img_frame?)?;
-        }
-        Ok(())
-    }
-
-    pub(crate) fn convert_frame(
-        &mut self,
-        img_frame: animation::Frame,
-    ) -> ImageResult<Frame<'static>> {
-        // get the delay before converting img_frame
-        let frame_delay = img_frame.delay().into_ratio().to_integer();
-        // convert img_frame into RgbaImage
-        let mut rbga_frame = img_frame.into_buffer();
-        let (width, height) = self.gif_dimensions(rbga_frame.width(), rbga_frame.height())?;
-
-        // Create the gif::Frame from the animation::Frame
-        let mut frame = Frame::from_rgba_speed(width, height, &mut *rbga_frame, self.speed);
-        // Saturate the conversion to u16::MAX instead of returning an error as that
-        // would require a new special cased variant in ParameterErrorKind which most
-        // likely couldn't be reused for other cases. This isn't a bad trade-off given
-        // that the current algorithm is already lossy.
-        frame.delay = (frame_delay / 10).try_into().unwrap_or(std::u16::MAX);
-
-        Ok(frame)
-    }
-
-    fn gif_dimensions(&self, width: u32, height: u32) -> ImageResult<(u16, u16)> {
-        fn inner_dimensions(width: u32, height: u32) -> Option<(u16, u16)> {
-            let width = u16::try_from(width).ok()?;
-            let height = u16::try_from(height).ok()?;
-            Some((width, height))
-        }
-
-        // TODO: this is not very idiomatic yet. Should return an EncodingError.
-        inner_dimensions(width, height).ok_or_else(|| {
-            ImageError::Parameter(ParameterError::from_kind(
-                ParameterErrorKind::DimensionMismatch,
-            ))
-        })
+        let speed = self.speed;
+        let quantization = self.quantization.clone();
+        animation::map_frames_in_order(
+            frames,
+            |img_frame| convert_frame(img_frame?, speed, quantization.as_ref()),
+            |frame| self.encode_gif(frame),
+        )
     }
 
     pub(crate) fn encode_gif(&mut self, mut frame: Frame) -> ImageResult<()> {
@@ -542,6 +709,212 @@ impl<W: Write> GifEncoder<W> {
     }
 }
 
+/// Quantize the colors of a frame of animation with the given speed, or with the quantizer if
+/// there is one.
+fn convert_frame(
+    img_frame: animation::Frame,
+    speed: i32,
+    quantization: Option<&(Quantizer, Dithering)>,
+) -> ImageResult<Frame<'static>> {
+    // get the delay before converting img_frame
+    let frame_delay = img_frame.delay().into_ratio().to_integer();
+    // convert img_frame into RgbaImage
+    let mut rbga_frame = img_frame.into_buffer();
+    let (width, height) = gif_dimensions(rbga_frame.width(), rbga_frame.height())?;
+
+    // Create the gif::Frame from the animation::Frame
+    let mut frame = match quantization {
+        Some((quantizer, dithering)) => {
+            quantize_frame(width, height, rbga_frame.as_raw(), quantizer, *dithering)?
+        }
+        None => Frame::from_rgba_speed(width, height, &mut *rbga_frame, speed),
+    };
+    // Saturate the conversion to u16::MAX instead of returning an error as that
+    // would require a new special cased variant in ParameterErrorKind which most
+    // likely couldn't be reused for other cases. This isn't a bad trade-off given
+    // that the current algorithm is already lossy.
+    frame.delay = (frame_delay / 10).try_into().unwrap_or(std::u16::MAX);
+
+    Ok(frame)
+}
+
+/// Reduce the colors of RGBA pixels to a palette, where transparent pixels take an entry of
+/// their own.
+fn quantize_frame(
+    width: u16,
+    height: u16,
+    rgba: &[u8],
+    quantizer: &Quantizer,
+    dithering: Dithering,
+) -> ImageResult<Frame<'static>> {
+    let opaque = rgba
+        .chunks_exact(4)
+        .filter(|p| p[3] != 0)
+        .map(|p| Rgb([p[0], p[1], p[2]]));
+    let transparent = rgba.chunks_exact(4).any(|p| p[3] == 0);
+    let max_colors = if transparent { 255 } else { 256 };
+
+    let mut exact: Vec<Rgb<u8>> = opaque.clone().collect::<HashSet<_>>().into_iter().collect();
+    let palette = match quantizer {
+        Quantizer::Palette(palette) if palette.is_empty() || palette.len() > max_colors => {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!(
+                    "the palette of a GIF frame holds 1 to {} colors, found {}",
+                    max_colors,
+                    palette.len()
+                )),
+            )));
+        }
+        Quantizer::Palette(palette) => palette.clone(),
+        _ if exact.len() <= max_colors => {
+            exact.sort_unstable_by_key(|color| color.0);
+            Palette::new(exact)
+        }
+        Quantizer::NeuQuant(factor) => {
+            let pixels: Vec<u8> = opaque.flat_map(|p| vec![p[0], p[1], p[2], 0xFF]).collect();
+            let quantizer = color_quant::NeuQuant::new(*factor, max_colors, &pixels);
+            let colors = quantizer
+                .color_map_rgb()
+                .chunks_exact(3)
+                .map(|c| Rgb([c[0], c[1], c[2]]))
+                .collect();
+            Palette::new(colors)
+        }
+        Quantizer::MedianCut => Palette::median_cut_of(opaque, max_colors),
+    };
+
+    let (w, h) = (u32::from(width), u32::from(height));
+    let mut image = ImageBuffer::from_fn(w, h, |x, y| {
+        let i = 4 * (y as usize * usize::from(width) + x as usize);
+        Rgb([rgba[i], rgba[i + 1], rgba[i + 2]])
+    });
+    match dithering {
+        Dithering::None => {}
+        Dithering::FloydSteinberg => {
+            dither_with(&mut image, &palette, DitherAlgorithm::FloydSteinberg)
+        }
+        Dithering::Ordered => {
+            // Thresholds spaced like a palette of as many colors spread evenly over the cube.
+            let spread = 256.0 / (palette.len().max(2) as f32).cbrt();
+            let algorithm = DitherAlgorithm::Bayer { size: 4, spread };
+            dither_with(&mut image, &palette, algorithm)
+        }
+    }
+
+    let transparent_index = palette.len() as u8;
+    let mut indices_of: HashMap<Rgb<u8>, u8> = HashMap::new();
+    let indices = image
+        .pixels()
+        .zip(rgba.chunks_exact(4))
+        .map(|(pixel, original)| match original[3] {
+            0 => transparent_index,
+            _ => *indices_of
+                .entry(*pixel)
+                .or_insert_with(|| palette.index_of(pixel) as u8),
+        })
+        .collect();
+
+    let mut table: Vec<u8> = palette.colors().iter().flat_map(|c| c.0.to_vec()).collect();
+    if transparent {
+        table.extend_from_slice(&[0, 0, 0]);
+    }
+    Ok(Frame {
+        width,
+        height,
+        buffer: Cow::Owned(indices),
+        palette: Some(table),
+        transparent: if transparent {
+            Some(transparent_index)
+        } else {
+            None
+        },
+        ..Frame::default()
+    })
+}
+
+fn gif_dimensions(width: u32, height: u32) -> ImageResult<(u16, u16)> {
+    fn inner_dimensions(width: u32, height: u32) -> Option<(u16, u16)> {
+        let width = u16::try_from(width).ok()?;
+        let height = u16::try_from(height).ok()?;
+        Some((width, height))
+    }
+
+    // TODO: this is not very idiomatic yet. Should return an EncodingError.
+    inner_dimensions(width, height).ok_or_else(|| {
+        ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::DimensionMismatch,
+        ))
+    })
+}
+
+impl<W: Write> ImageEncoder for GifEncoder<W> {
+    fn write_image(
+        mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        self.encode(buf, width, height, color_type)
+    }
+}
+
+/// Replace the global color table of a GIF image without decoding it.
+///
+/// The global color table of the image read from `r` is substituted by `palette`, all following
+/// blocks, including local color tables and the compressed index data of every frame, are copied
+/// to `w` unchanged. This allows recoloring an image, for example swapping or remapping the
+/// colors of a sprite, without touching its pixels.
+///
+/// The new palette must have at least as many entries as the original table so that every index
+/// remains valid, and at most 256 entries. It is padded with black to the next power of two.
+pub fn replace_global_palette<R: Read, W: Write>(
+    mut r: R,
+    mut w: W,
+    palette: &[Rgb<u8>],
+) -> ImageResult<()> {
+    // The header followed by the logical screen descriptor.
+    let mut header = [0; 13];
+    r.read_exact(&mut header)?;
+    if &header[..6] != b"GIF87a" && &header[..6] != b"GIF89a" {
+        return Err(ImageError::Decoding(DecodingError::new(
+            ImageFormat::Gif.into(),
+            "invalid GIF signature",
+        )));
+    }
+
+    let flags = header[10];
+    if flags & 0x80 == 0 {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::Generic("GIF image has no global color table".into()),
+        )));
+    }
+
+    let entries = 2usize << (flags & 0x07);
+    if palette.len() < entries || palette.len() > 256 {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::Generic(format!(
+                "palette must have between {} and 256 entries, found {}",
+                entries,
+                palette.len()
+            )),
+        )));
+    }
+    io::copy(&mut r.by_ref().take(3 * entries as u64), &mut io::sink())?;
+
+    // The size field encodes a table of `2^(size + 1)` entries.
+    let size = palette.len().next_power_of_two().max(2).trailing_zeros() - 1;
+    header[10] = (flags & !0x07) | size as u8;
+    w.write_all(&header)?;
+
+    let mut table: Vec<u8> = palette.iter().flat_map(|p| p.0.iter().copied()).collect();
+    table.resize(3 << (size + 1), 0);
+    w.write_all(&table)?;
+
+    io::copy(&mut r, &mut w)?;
+    Ok(())
+}
+
 impl ImageError {
     fn from_decoding(err: gif::DecodingError) -> ImageError {
         use gif::DecodingError::*;
@@ -568,21 +941,191 @@ impl ImageError {
 mod test {
     use super::*;
 
-    #[test]
-    fn frames_exceeding_logical_screen_size() {
-        // This is a gif with 10x10 logical screen, but a 16x16 frame + 6px offset inside.
-        let data = vec![
+    /// A gif with 10x10 logical screen, but a 16x16 frame + 6px offset inside.
+    fn exceeding_logical_screen() -> Vec<u8> {
+        vec![
             0x47, 0x49, 0x46, 0x38, 0x39, 0x61, 0x0A, 0x00, 0x0A, 0x00, 0xF0, 0x00, 0x00, 0x00,
             0x00, 0x00, 0x0E, 0xFF, 0x1F, 0x21, 0xF9, 0x04, 0x09, 0x64, 0x00, 0x00, 0x00, 0x2C,
             0x06, 0x00, 0x06, 0x00, 0x10, 0x00, 0x10, 0x00, 0x00, 0x02, 0x23, 0x84, 0x8F, 0xA9,
             0xBB, 0xE1, 0xE8, 0x42, 0x8A, 0x0F, 0x50, 0x79, 0xAE, 0xD1, 0xF9, 0x7A, 0xE8, 0x71,
             0x5B, 0x48, 0x81, 0x64, 0xD5, 0x91, 0xCA, 0x89, 0x4D, 0x21, 0x63, 0x89, 0x4C, 0x09,
             0x77, 0xF5, 0x6D, 0x14, 0x00, 0x3B,
-        ];
+        ]
+    }
 
-        let decoder = GifDecoder::new(Cursor::new(data)).unwrap();
+    #[test]
+    fn frames_exceeding_logical_screen_size() {
+        let decoder = GifDecoder::new(Cursor::new(exceeding_logical_screen())).unwrap();
         let mut buf = vec![0u8; decoder.total_bytes() as usize];
 
         assert!(decoder.read_image(&mut buf).is_ok());
     }
+
+    #[test]
+    fn frames_describe_canvas() {
+        let decoder = GifDecoder::new(Cursor::new(exceeding_logical_screen())).unwrap();
+        let frames = decoder.into_raw_frames();
+        assert_eq!(frames.canvas_dimensions(), Some((10, 10)));
+        assert_eq!(frames.background_color(), Some(Rgba([0, 0, 0, 255])));
+        let frames = frames.collect_frames().unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!((frames[0].left(), frames[0].top()), (6, 6));
+        assert_eq!(frames[0].buffer().dimensions(), (16, 16));
+        assert_eq!(
+            frames[0].dirty_rect(),
+            Rect {
+                x: 6,
+                y: 6,
+                width: 16,
+                height: 16
+            }
+        );
+
+        let decoder = GifDecoder::new(Cursor::new(exceeding_logical_screen())).unwrap();
+        let frames = decoder.into_frames().collect_frames().unwrap();
+        assert_eq!((frames[0].left(), frames[0].top()), (0, 0));
+        assert_eq!(frames[0].buffer().dimensions(), (10, 10));
+        assert_eq!(
+            frames[0].dirty_rect(),
+            Rect {
+                x: 0,
+                y: 0,
+                width: 10,
+                height: 10
+            }
+        );
+    }
+
+    #[test]
+    fn frames_within_limits() {
+        // The canvas takes 10x10 and the frame 16x16 pixels of four bytes.
+        let mut limits = Limits::default();
+        limits.max_alloc = Some(400 + 1024);
+        let decoder =
+            GifDecoder::with_limits(Cursor::new(exceeding_logical_screen()), limits.clone())
+                .unwrap();
+        assert!(decoder.into_raw_frames().collect_frames().is_ok());
+
+        // Compositing the frame needs a second canvas.
+        let decoder =
+            GifDecoder::with_limits(Cursor::new(exceeding_logical_screen()), limits).unwrap();
+        let result = decoder.into_frames().collect_frames();
+        assert!(matches!(result, Err(ImageError::Limits(_))));
+
+        let mut reader = crate::io::Reader::new(Cursor::new(exceeding_logical_screen()));
+        reader.set_format(ImageFormat::Gif);
+        reader.limits({
+            let mut limits = Limits::default();
+            limits.max_alloc = Some(400 + 1023);
+            limits
+        });
+        assert!(matches!(reader.decode(), Err(ImageError::Limits(_))));
+    }
+
+    #[test]
+    fn replace_global_palette_keeps_indices() {
+        let original = std::fs::read("tests/images/gif/simple/sample_1.gif").unwrap();
+        let palette = GifDecoder::new(&original[..])
+            .unwrap()
+            .global_palette()
+            .expect("Image has a global color table");
+        let inverted: Vec<_> = palette
+            .iter()
+            .map(|p| Rgb([!p.0[0], !p.0[1], !p.0[2]]))
+            .collect();
+
+        let mut recolored = Vec::new();
+        replace_global_palette(&original[..], &mut recolored, &inverted).unwrap();
+        assert_eq!(
+            GifDecoder::new(&recolored[..]).unwrap().global_palette(),
+            Some(inverted)
+        );
+
+        let before = crate::load_from_memory(&original).unwrap().into_rgba8();
+        let after = crate::load_from_memory(&recolored).unwrap().into_rgba8();
+        for (b, a) in before.pixels().zip(after.pixels()) {
+            if b.0[3] != 0 {
+                assert_eq!(Rgba([!b.0[0], !b.0[1], !b.0[2], b.0[3]]), *a);
+            }
+        }
+    }
+
+    /// A gradient with more colors than a GIF frame can hold.
+    fn gradient() -> Vec<u8> {
+        (0..64u32 * 16)
+            .flat_map(|i| {
+                let (x, y) = (i % 64, i / 64);
+                vec![(x * 4) as u8, (y * 16) as u8, 128]
+            })
+            .collect()
+    }
+
+    fn encode_with(
+        data: &[u8],
+        color: ColorType,
+        quantizer: Quantizer,
+        dithering: Dithering,
+    ) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        let bytes_per_pixel = usize::from(color.bytes_per_pixel());
+        let height = (data.len() / bytes_per_pixel / 64) as u32;
+        GifEncoder::new(&mut encoded)
+            .with_quantizer(quantizer, dithering)
+            .encode(data, 64, height, color)
+            .unwrap();
+        encoded
+    }
+
+    #[test]
+    fn quantizers_keep_colors_close() {
+        let data = gradient();
+        for quantizer in &[Quantizer::NeuQuant(10), Quantizer::MedianCut] {
+            for &dithering in &[
+                Dithering::None,
+                Dithering::FloydSteinberg,
+                Dithering::Ordered,
+            ] {
+                let encoded = encode_with(&data, ColorType::Rgb8, quantizer.clone(), dithering);
+                let decoded = crate::load_from_memory(&encoded).unwrap().into_rgb8();
+                let error = decoded
+                    .as_raw()
+                    .iter()
+                    .zip(&data)
+                    .map(|(&a, &b)| (i32::from(a) - i32::from(b)).abs())
+                    .sum::<i32>() as usize
+                    / data.len();
+                assert!(error < 12, "{:?} {:?}: {}", quantizer, dithering, error);
+            }
+        }
+    }
+
+    #[test]
+    fn fixed_palette() {
+        let colors = vec![Rgb([0, 0, 0]), Rgb([255, 255, 255]), Rgb([255, 0, 0])];
+        let palette = Palette::new(colors.clone());
+        let mut data: Vec<u8> = gradient()
+            .chunks_exact(3)
+            .flat_map(|p| vec![p[0], p[1], p[2], 0xFF])
+            .collect();
+        data[3] = 0;
+
+        let quantizer = Quantizer::Palette(palette);
+        let encoded = encode_with(
+            &data,
+            ColorType::Rgba8,
+            quantizer,
+            Dithering::FloydSteinberg,
+        );
+        let decoded = crate::load_from_memory(&encoded).unwrap().into_rgba8();
+        assert_eq!(decoded.get_pixel(0, 0)[3], 0);
+        for pixel in decoded.pixels().skip(1) {
+            assert!(colors.contains(&pixel.to_rgb()), "{:?}", pixel);
+        }
+
+        let full = Quantizer::Palette(Palette::new(vec![Rgb([0, 0, 0]); 256]));
+        let result = GifEncoder::new(Vec::new())
+            .with_quantizer(full, Dithering::None)
+            .encode(&data, 64, 16, ColorType::Rgba8);
+        assert!(matches!(result, Err(ImageError::Parameter(_))));
+    }
 }
@@ -11,12 +11,14 @@ use std::num::{ParseFloatError, ParseIntError};
 use std::path::Path;
 use std::{error, fmt, mem};
 
+use crate::buffer_::Rgb32FImage;
 use crate::color::{ColorType, Rgb};
 use crate::error::{
     DecodingError, ImageError, ImageFormatHint, ImageResult, ParameterError, ParameterErrorKind,
     UnsupportedError, UnsupportedErrorKind,
 };
 use crate::image::{self, ImageDecoder, ImageDecoderRect, ImageFormat, Progress};
+use crate::io::Limits;
 
 /// Errors that can occur during decoding and parsing of a HDR image
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -130,6 +132,8 @@ pub struct HdrAdapter<R: Read> {
     inner: Option<HdrDecoder<R>>,
     // data: Option<Vec<u8>>,
     meta: HdrMetadata,
+    /// The limits on the pixels that are decoded before they are copied to the output buffer.
+    limits: Limits,
 }
 
 impl<R: BufRead> HdrAdapter<R> {
@@ -140,6 +144,7 @@ impl<R: BufRead> HdrAdapter<R> {
         Ok(HdrAdapter {
             inner: Some(decoder),
             meta,
+            limits: Limits::no_limits(),
         })
     }
 
@@ -150,17 +155,40 @@ impl<R: BufRead> HdrAdapter<R> {
         Ok(HdrAdapter {
             inner: Some(decoder),
             meta,
+            limits: Limits::no_limits(),
         })
     }
 
+    /// Returns file metadata. Refer to ```HdrMetadata``` for details.
+    pub fn metadata(&self) -> HdrMetadata {
+        self.meta.clone()
+    }
+
+    /// Decodes the image with its exposure changed by `ev` stops.
+    /// Refer to ```HdrDecoder::decode_with_exposure``` for details.
+    pub fn decode_with_exposure(mut self, ev: f32) -> ImageResult<Rgb32FImage> {
+        match self.inner.take() {
+            Some(decoder) => decoder.decode_with_exposure(ev),
+            None => Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::NoMoreData,
+            ))),
+        }
+    }
+
     /// Read the actual data of the image, and store it in Self::data.
     fn read_image_data(&mut self, buf: &mut [u8]) -> ImageResult<()> {
         assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
         match self.inner.take() {
             Some(decoder) => {
-                let img: Vec<Rgb<u8>> = decoder.read_image_ldr()?;
-                for (i, Rgb(data)) in img.into_iter().enumerate() {
-                    buf[(i * 3)..][..3].copy_from_slice(&data);
+                // The pixels are decoded next to the output buffer, from scanlines of four bytes a
+                // pixel that are read ahead of the threads that convert them.
+                let mut limits = self.limits.clone();
+                limits.reserve(self.total_bytes())?;
+                limits.reserve(u64::from(self.meta.width) * u64::from(self.meta.height) * 4)?;
+                let img: Vec<Rgb<f32>> = decoder.read_image_hdr()?;
+                let samples = img.iter().flat_map(|Rgb(data)| data.iter());
+                for (chunk, sample) in buf.chunks_exact_mut(4).zip(samples) {
+                    chunk.copy_from_slice(&sample.to_ne_bytes());
                 }
 
                 Ok(())
@@ -196,7 +224,7 @@ impl<'a, R: 'a + BufRead> ImageDecoder<'a> for HdrAdapter<R> {
     }
 
     fn color_type(&self) -> ColorType {
-        ColorType::Rgb8
+        ColorType::Rgb32F
     }
 
     fn into_reader(self) -> ImageResult<Self::Reader> {
@@ -206,6 +234,14 @@ impl<'a, R: 'a + BufRead> ImageDecoder<'a> for HdrAdapter<R> {
         ))
     }
 
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        self.limits = limits;
+        Ok(())
+    }
+
     fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
         self.read_image_data(buf)
     }
@@ -267,11 +303,19 @@ impl Rgbe8Pixel {
     /// Converts ```Rgbe8Pixel``` into ```Rgb<f32>``` linearly
     #[inline]
     pub fn to_hdr(self) -> Rgb<f32> {
+        self.to_hdr_with_exposure(0.0)
+    }
+
+    /// Converts ```Rgbe8Pixel``` into ```Rgb<f32>``` linearly, multiplied by 2<sup>ev</sup>
+    ///
+    /// The factor is added to the exponent, so it costs nothing on top of the conversion.
+    #[inline]
+    pub fn to_hdr_with_exposure(self, ev: f32) -> Rgb<f32> {
         if self.e == 0 {
             Rgb([0.0, 0.0, 0.0])
         } else {
             //            let exp = f32::ldexp(1., self.e as isize - (128 + 8)); // unstable
-            let exp = f32::exp2(<f32 as From<_>>::from(self.e) - (128.0 + 8.0));
+            let exp = f32::exp2(<f32 as From<_>>::from(self.e) - (128.0 + 8.0) + ev);
             Rgb([
                 exp * <f32 as From<_>>::from(self.c[0]),
                 exp * <f32 as From<_>>::from(self.c[1]),
@@ -396,9 +440,12 @@ impl<R: BufRead> HdrDecoder<R> {
             }
         };
 
-        // color type is always rgb8
-        if crate::utils::check_dimension_overflow(width, height, ColorType::Rgb8.bytes_per_pixel())
-        {
+        // color type is always rgb32f
+        if crate::utils::check_dimension_overflow(
+            width,
+            height,
+            ColorType::Rgb32F.bytes_per_pixel(),
+        ) {
             return Err(ImageError::Unsupported(
                 UnsupportedError::from_format_and_kind(
                     ImageFormat::Hdr.into(),
@@ -493,6 +540,29 @@ impl<R: BufRead> HdrDecoder<R> {
         self.read_image_transform(|pix| pix.to_hdr(), &mut ret[..])?;
         Ok(ret)
     }
+
+    /// Consumes decoder and returns the image with its exposure changed by `ev` stops, that is
+    /// with every color value multiplied by 2<sup>ev</sup>.
+    ///
+    /// The exposure is applied while the pixels are decoded, instead of in another pass over
+    /// the decoded image. To get physical radiance from an image whose metadata has an
+    /// `exposure`, pass `-exposure.log2()`.
+    pub fn decode_with_exposure(mut self, ev: f32) -> ImageResult<Rgb32FImage> {
+        let mut image = Rgb32FImage::new(self.width, self.height);
+        // Don't read anything if image is empty
+        if self.width == 0 || self.height == 0 {
+            return Ok(image);
+        }
+
+        let mut scanline = vec![Default::default(); self.width as usize];
+        for row in image.chunks_mut(self.width as usize * 3) {
+            read_scanline(&mut self.r, &mut scanline)?;
+            for (dst, pix) in row.chunks_exact_mut(3).zip(&scanline) {
+                dst.copy_from_slice(&pix.to_hdr_with_exposure(ev).0);
+            }
+        }
+        Ok(image)
+    }
 }
 
 impl<R: Read> IntoIterator for HdrDecoder<R> {
@@ -1029,6 +1099,34 @@ mod test {
     use super::*;
     use std::io::Cursor;
 
+    #[test]
+    fn exposure() {
+        let data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\nEXPOSURE=4\n\n-Y 1 +X 2\n\x80\x40\x20\x81\x00\x00\x00\x00";
+        let decoder = HdrAdapter::new(Cursor::new(&data[..])).unwrap();
+        assert_eq!(decoder.metadata().exposure, Some(4.0));
+
+        let image = decoder.decode_with_exposure(-2.0).unwrap();
+        assert_eq!(image.dimensions(), (2, 1));
+        assert_eq!(image.get_pixel(0, 0), &Rgb([0.25, 0.125, 0.0625]));
+        assert_eq!(image.get_pixel(1, 0), &Rgb([0.0, 0.0, 0.0]));
+
+        let plain = HdrDecoder::new(Cursor::new(&data[..]))
+            .unwrap()
+            .read_image_hdr()
+            .unwrap();
+        assert_eq!(plain[0], Rgb([1.0, 0.5, 0.25]));
+    }
+
+    #[test]
+    fn adapter_keeps_hdr_values() {
+        let data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 1\n\x80\x40\x20\x82";
+        let decoder = HdrAdapter::new(Cursor::new(&data[..])).unwrap();
+        assert_eq!(decoder.color_type(), ColorType::Rgb32F);
+
+        let image = crate::DynamicImage::from_decoder(decoder).unwrap();
+        assert_eq!(image.to_rgb32f().as_raw(), &[2.0, 1.0, 0.5]);
+    }
+
     #[test]
     fn dimension_overflow() {
         let data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n -Y 4294967295 +X 4294967295";
@@ -0,0 +1,152 @@
+//! Decoding of HEIF images through an external decoder.
+//!
+//! HEIF, and its HEVC coded flavour HEIC, is the container that phone cameras store photos in
+//! by default. Its codecs are patent encumbered, so this crate does not implement them, but it
+//! recognizes HEIF files and routes them to a decoder that the application registers, typically
+//! a binding to `libheif`. Once one is registered, `image::open`, `Reader` and
+//! `load_from_memory` decode HEIF files like those of any other format.
+//!
+//! ```no_run
+//! use image::codecs::heif;
+//!
+//! # fn native_decode(_: &[u8]) -> image::ImageResult<image::DynamicImage> { unimplemented!() }
+//! heif::register_decoder(|reader| {
+//!     let mut data = Vec::new();
+//!     reader.read_to_end(&mut data)?;
+//!     native_decode(&data)
+//! });
+//! let image = image::io::Reader::open("photo.heic")?.decode()?;
+//! # Ok::<(), image::ImageError>(())
+//! ```
+//!
+//! # Related Links
+//! * <https://nokiatech.github.io/heif/technical.html> - The HEIF standard, ISO/IEC 23008-12
+
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read};
+use std::marker::PhantomData;
+use std::mem;
+
+use crate::color::ColorType;
+use crate::dynimage::DynamicImage;
+use crate::error::{ImageError, ImageResult, UnsupportedError, UnsupportedErrorKind};
+use crate::image::{ImageDecoder, ImageFormat};
+
+use super::registry::Registry;
+
+/// A function that decodes a whole HEIF file.
+pub type DecodeFn = Box<dyn Fn(&mut dyn Read) -> ImageResult<DynamicImage> + Send + Sync>;
+
+/// The registered decoder.
+static DECODER: Registry<DecodeFn> = Registry::new();
+
+/// Registers the function that decodes HEIF files, replacing any previously registered one.
+///
+/// The function is given a reader positioned at the start of the file and returns the decoded
+/// image. It is called from whatever thread decodes a HEIF file.
+pub fn register_decoder<F>(decoder: F)
+where
+    F: Fn(&mut dyn Read) -> ImageResult<DynamicImage> + Send + Sync + 'static,
+{
+    DECODER.register(Box::new(decoder));
+}
+
+/// Returns if a HEIF decoder is registered.
+pub fn has_decoder() -> bool {
+    DECODER.is_registered()
+}
+
+/// HEIF decoder, which decodes with the registered decoder.
+pub struct HeifDecoder<R> {
+    image: DynamicImage,
+    marker: PhantomData<R>,
+}
+
+impl<R: Read> HeifDecoder<R> {
+    /// Decodes the image read from `r` with the registered decoder.
+    ///
+    /// Returns an `ImageError::Unsupported` if no decoder is registered.
+    pub fn new(mut r: R) -> ImageResult<HeifDecoder<R>> {
+        let decoder = DECODER.get().ok_or_else(|| {
+            ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+                ImageFormat::Heif.into(),
+                UnsupportedErrorKind::GenericFeature("no HEIF decoder is registered".into()),
+            ))
+        })?;
+        Ok(HeifDecoder {
+            image: decoder(&mut r)?,
+            marker: PhantomData,
+        })
+    }
+}
+
+/// The reader returned by `HeifDecoder::into_reader`.
+pub struct HeifReader<R>(Cursor<Vec<u8>>, PhantomData<R>);
+impl<R> Read for HeifReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        if self.0.position() == 0 && buf.is_empty() {
+            mem::swap(buf, self.0.get_mut());
+            Ok(buf.len())
+        } else {
+            self.0.read_to_end(buf)
+        }
+    }
+}
+
+impl<'a, R: 'a + Read> ImageDecoder<'a> for HeifDecoder<R> {
+    type Reader = HeifReader<R>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.image.width(), self.image.height())
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.image.color()
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        Ok(HeifReader(
+            Cursor::new(self.image.as_bytes().to_vec()),
+            PhantomData,
+        ))
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        buf.copy_from_slice(self.image.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayImage, Luma};
+
+    #[test]
+    fn registered_decoder() {
+        // A HEIF file starts with a file type box naming the brands it conforms to.
+        let file = b"\0\0\0\x18ftypheic\0\0\0\0mif1heic\x07";
+        assert_eq!(crate::guess_format(file).unwrap(), ImageFormat::Heif);
+
+        register_decoder(|reader| {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            let value = data[data.len() - 1];
+            Ok(DynamicImage::ImageLuma8(GrayImage::from_pixel(
+                3,
+                2,
+                Luma([value]),
+            )))
+        });
+        assert!(has_decoder());
+        assert!(ImageFormat::Heif.can_read());
+
+        let image = crate::load_from_memory(file).unwrap();
+        assert_eq!(image.color(), ColorType::L8);
+        assert_eq!(image.as_bytes(), &[7; 6]);
+    }
+}
@@ -0,0 +1,416 @@
+//!  Decoding and Encoding of ICNS files
+//!
+//!  ICNS is the icon format of macOS. A file holds the same icon at several sizes, each in an
+//!  entry whose four character type tells its size. Entries of modern icons are PNG images, which
+//!  this module reads and writes. The older entries of run length encoded RGB with separate masks,
+//!  and those in JPEG 2000, are skipped when reading.
+//!
+//!  # Related Links
+//!  * <https://en.wikipedia.org/wiki/Apple_Icon_Image_format> - The entry types of the format.
+
+use std::convert::TryFrom;
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::{error, fmt, mem};
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::codecs::png::{PngDecoder, PngEncoder, PngReader, PNG_SIGNATURE};
+use crate::color::ColorType;
+use crate::error::{
+    DecodingError, ImageError, ImageResult, ParameterError, ParameterErrorKind, UnsupportedError,
+    UnsupportedErrorKind,
+};
+use crate::image::{ImageDecoder, ImageEncoder, ImageFormat, MultiImageDecoder, MultiImageEncoder};
+use crate::io::Limits;
+
+/// The magic bytes at the start of every ICNS file.
+const MAGIC: &[u8; 4] = b"icns";
+/// The length of the type and the length of a file or an entry, in bytes.
+const HEADER_SIZE: u32 = 8;
+/// The types of the PNG entries of each size, from 16x16 to 1024x1024 pixels.
+const PNG_TYPES: [(u32, &[u8; 4]); 7] = [
+    (16, b"icp4"),
+    (32, b"icp5"),
+    (64, b"icp6"),
+    (128, b"ic07"),
+    (256, b"ic08"),
+    (512, b"ic09"),
+    (1024, b"ic10"),
+];
+
+/// Errors that can occur during decoding and parsing an ICNS file
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+enum DecoderError {
+    /// ICNS "icns" magic invalid or missing
+    MagicInvalid,
+    /// The length of an entry is shorter than its header
+    EntryLengthInvalid(u32),
+}
+
+impl fmt::Display for DecoderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecoderError::MagicInvalid => f.write_str("ICNS magic not found"),
+            DecoderError::EntryLengthInvalid(length) => {
+                f.write_fmt(format_args!("Invalid ICNS entry length: {}", length))
+            }
+        }
+    }
+}
+
+impl From<DecoderError> for ImageError {
+    fn from(e: DecoderError) -> ImageError {
+        ImageError::Decoding(DecodingError::new(ImageFormat::Icns.into(), e))
+    }
+}
+
+impl error::Error for DecoderError {}
+
+/// An entry of the file that holds a PNG image.
+#[derive(Clone, Copy)]
+struct Entry {
+    /// The position of the image data in the file.
+    offset: u64,
+    length: u64,
+    /// The width of the image, read from the header of the PNG.
+    width: u32,
+}
+
+impl Entry {
+    /// Create a decoder for the image of this entry, which is decoded in place from `r`.
+    fn decoder<R: Read + Seek>(
+        &self,
+        mut r: R,
+        limits: &Limits,
+    ) -> ImageResult<PngDecoder<io::Take<R>>> {
+        limits.check_cancelled()?;
+        r.seek(SeekFrom::Start(self.offset))?;
+        PngDecoder::with_limits(r.take(self.length), limits.clone())
+    }
+}
+
+/// An ICNS decoder
+///
+/// The largest PNG entry is selected initially, other entries can be selected and read through
+/// the [`MultiImageDecoder`] implementation.
+///
+/// [`MultiImageDecoder`]: ../../trait.MultiImageDecoder.html
+pub struct IcnsDecoder<R: Read> {
+    reader: R,
+    entries: Vec<Entry>,
+    selected: usize,
+    /// The dimensions of the selected entry.
+    dimensions: (u32, u32),
+    /// The color type of the selected entry.
+    color_type: ColorType,
+    /// The limits on the decoders of the entries.
+    limits: Limits,
+}
+
+impl<R: Read + Seek> IcnsDecoder<R> {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new(r: R) -> ImageResult<IcnsDecoder<R>> {
+        Self::with_limits(r, Limits::no_limits())
+    }
+
+    /// Create a new decoder that decodes from the stream ```r``` within the given limits.
+    ///
+    /// Returns an `ImageError::Unsupported` if the file has no PNG entries.
+    pub fn with_limits(mut r: R, limits: Limits) -> ImageResult<IcnsDecoder<R>> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let entries = read_entries(&mut r)?;
+        let selected = (0..entries.len())
+            .max_by_key(|&index| entries[index].width)
+            .ok_or_else(|| {
+                ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+                    ImageFormat::Icns.into(),
+                    UnsupportedErrorKind::GenericFeature("ICNS files without PNG entries".into()),
+                ))
+            })?;
+        let decoder = entries[selected].decoder(&mut r, &limits)?;
+
+        Ok(IcnsDecoder {
+            dimensions: decoder.dimensions(),
+            color_type: decoder.color_type(),
+            reader: r,
+            entries,
+            selected,
+            limits,
+        })
+    }
+}
+
+/// Read the types and lengths of the entries, and keep those that hold PNG images.
+fn read_entries<R: Read + Seek>(r: &mut R) -> ImageResult<Vec<Entry>> {
+    let mut magic = [0; 4];
+    r.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        return Err(DecoderError::MagicInvalid.into());
+    }
+    let file_length = u64::from(r.read_u32::<BigEndian>()?);
+
+    let mut entries = Vec::new();
+    let mut position = u64::from(HEADER_SIZE);
+    while position + u64::from(HEADER_SIZE) <= file_length {
+        r.seek(SeekFrom::Start(position))?;
+        let _icon_type = r.read_u32::<BigEndian>()?;
+        let length = r.read_u32::<BigEndian>()?;
+        if length < HEADER_SIZE {
+            return Err(DecoderError::EntryLengthInvalid(length).into());
+        }
+
+        // The signature, then the length and type of the IHDR chunk, which starts with the width.
+        let mut header = Vec::with_capacity(24);
+        r.by_ref()
+            .take(u64::from(length - HEADER_SIZE).min(24))
+            .read_to_end(&mut header)?;
+        if header.len() == 24 && header[..PNG_SIGNATURE.len()] == PNG_SIGNATURE {
+            entries.push(Entry {
+                offset: position + u64::from(HEADER_SIZE),
+                length: u64::from(length - HEADER_SIZE),
+                width: u32::from_be_bytes([header[16], header[17], header[18], header[19]]),
+            });
+        }
+        position += u64::from(length);
+    }
+    Ok(entries)
+}
+
+impl<'a, R: 'a + Read + Seek> MultiImageDecoder<'a> for IcnsDecoder<R> {
+    fn image_count(&mut self) -> ImageResult<usize> {
+        Ok(self.entries.len())
+    }
+
+    fn select_image(&mut self, index: usize) -> ImageResult<()> {
+        if index == self.selected {
+            return Ok(());
+        }
+        let entry = *self.entries.get(index).ok_or_else(|| {
+            ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                format!("the file has no image at index {}", index),
+            )))
+        })?;
+        let decoder = entry.decoder(&mut self.reader, &self.limits)?;
+        self.dimensions = decoder.dimensions();
+        self.color_type = decoder.color_type();
+        self.selected = index;
+        Ok(())
+    }
+
+    fn read_page(&mut self, index: usize, buf: &mut [u8]) -> ImageResult<()> {
+        self.select_image(index)?;
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        self.entries[self.selected]
+            .decoder(&mut self.reader, &self.limits)?
+            .read_image(buf)
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for IcnsDecoder<R> {
+    type Reader = PngReader<io::Take<R>>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        self.entries[self.selected]
+            .decoder(self.reader, &self.limits)?
+            .into_reader()
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        self.limits = limits;
+        Ok(())
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        self.entries[self.selected]
+            .decoder(self.reader, &self.limits)?
+            .read_image(buf)
+    }
+}
+
+/// ICNS encoder
+///
+/// Each image is written as a PNG entry. An icon with several sizes is written, one image at a
+/// time, with the [`MultiImageEncoder`] implementation.
+///
+/// [`MultiImageEncoder`]: ../../trait.MultiImageEncoder.html
+pub struct IcnsEncoder<W: Write> {
+    w: W,
+    /// The types and PNG images of the entries appended so far.
+    entries: Vec<(&'static [u8; 4], Vec<u8>)>,
+}
+
+impl<W: Write> IcnsEncoder<W> {
+    /// Create a new encoder that writes its output to ```w```.
+    pub fn new(w: W) -> IcnsEncoder<W> {
+        IcnsEncoder {
+            w,
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write> ImageEncoder for IcnsEncoder<W> {
+    /// Write an icon of a single size.
+    ///
+    /// The image must be a square of 16, 32, 64, 128, 256, 512 or 1024 pixels.
+    fn write_image(
+        mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        self.append_image(buf, width, height, color_type)?;
+        self.finish()
+    }
+}
+
+impl<W: Write> MultiImageEncoder for IcnsEncoder<W> {
+    /// Add an entry to the icon, encoded as PNG.
+    ///
+    /// The image must be a square of 16, 32, 64, 128, 256, 512 or 1024 pixels, and of a size that
+    /// was not appended before. The icon is only written by `finish`.
+    fn append_image(
+        &mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        let icon_type = PNG_TYPES
+            .iter()
+            .find(|&&(size, _)| (width, height) == (size, size))
+            .map(|&(_, icon_type)| icon_type)
+            .filter(|icon_type| self.entries.iter().all(|(other, _)| other != icon_type))
+            .ok_or_else(|| {
+                ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                    format!(
+                        "the icon has no entry for an image of {}x{} pixels, the entries are \
+                         squares of 16 to 1024 pixels of distinct powers of two",
+                        width, height,
+                    ),
+                )))
+            })?;
+
+        let mut image_data = Vec::new();
+        PngEncoder::new(&mut image_data).write_image(buf, width, height, color_type)?;
+        self.entries.push((icon_type, image_data));
+        Ok(())
+    }
+
+    fn finish(mut self) -> ImageResult<()> {
+        let entries = mem::take(&mut self.entries);
+        if entries.is_empty() {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic("the icon has no images".to_string()),
+            )));
+        }
+
+        let length = |data: &Vec<u8>| u64::from(HEADER_SIZE) + data.len() as u64;
+        let file_length =
+            u64::from(HEADER_SIZE) + entries.iter().map(|(_, data)| length(data)).sum::<u64>();
+        // Icons of all sizes together are far smaller than 4 GiB.
+        let file_length = u32::try_from(file_length).unwrap();
+
+        self.w.write_all(MAGIC)?;
+        self.w.write_u32::<BigEndian>(file_length)?;
+        for (icon_type, data) in &entries {
+            self.w.write_all(*icon_type)?;
+            self.w.write_u32::<BigEndian>(length(data) as u32)?;
+            self.w.write_all(data)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{guess_format, load_from_memory, Rgba, RgbaImage};
+
+    fn icon(size: u32) -> RgbaImage {
+        RgbaImage::from_fn(size, size, |x, y| Rgba([x as u8, y as u8, size as u8, 255]))
+    }
+
+    fn encode(sizes: &[u32]) -> Vec<u8> {
+        let mut file = Vec::new();
+        let mut encoder = IcnsEncoder::new(&mut file);
+        for &size in sizes {
+            let image = icon(size);
+            encoder
+                .append_image(&image, size, size, ColorType::Rgba8)
+                .unwrap();
+        }
+        encoder.finish().unwrap();
+        file
+    }
+
+    #[test]
+    fn select_each_entry() {
+        let file = encode(&[16, 64, 32]);
+        assert_eq!(&file[8..12], b"icp4");
+        assert_eq!(guess_format(&file).unwrap(), ImageFormat::Icns);
+
+        let mut decoder = IcnsDecoder::new(io::Cursor::new(&file)).unwrap();
+        assert_eq!(decoder.image_count().unwrap(), 3);
+        // The largest entry is selected initially.
+        assert_eq!(decoder.dimensions(), (64, 64));
+        for (index, &size) in [16, 64, 32].iter().enumerate() {
+            decoder.select_image(index).unwrap();
+            assert_eq!(decoder.dimensions(), (size, size));
+            let mut buf = vec![0; decoder.total_bytes() as usize];
+            decoder.read_page(index, &mut buf).unwrap();
+            assert_eq!(buf, icon(size).into_raw());
+        }
+        assert!(decoder.select_image(3).is_err());
+
+        let image = load_from_memory(&file).unwrap();
+        assert_eq!(image.to_rgba8(), icon(64));
+    }
+
+    #[test]
+    fn skips_other_entries() {
+        let png = encode(&[16]);
+        let mut file = b"icns".to_vec();
+        file.extend_from_slice(&(8 + 12 + png.len() as u32 - 8).to_be_bytes());
+        // A run length encoded entry, which is not read.
+        file.extend_from_slice(b"is32\0\0\0\x0c\x01\x02\x03\x04");
+        file.extend_from_slice(&png[8..]);
+
+        let mut decoder = IcnsDecoder::new(io::Cursor::new(&file)).unwrap();
+        assert_eq!(decoder.image_count().unwrap(), 1);
+        assert_eq!(decoder.dimensions(), (16, 16));
+
+        let truncated = &file[..8 + 12];
+        assert!(IcnsDecoder::new(io::Cursor::new(truncated)).is_err());
+    }
+
+    #[test]
+    fn invalid_sizes() {
+        let mut encoder = IcnsEncoder::new(Vec::new());
+        let image = icon(48);
+        assert!(encoder
+            .append_image(&image, 48, 48, ColorType::Rgba8)
+            .is_err());
+        let image = icon(32);
+        encoder
+            .append_image(&image, 32, 32, ColorType::Rgba8)
+            .unwrap();
+        assert!(encoder
+            .append_image(&image, 32, 32, ColorType::Rgba8)
+            .is_err());
+        assert!(IcnsEncoder::new(Vec::new()).finish().is_err());
+    }
+}
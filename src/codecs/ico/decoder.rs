@@ -6,9 +6,11 @@ use std::{error, fmt, mem};
 
 use crate::color::ColorType;
 use crate::error::{
-    DecodingError, ImageError, ImageResult, UnsupportedError, UnsupportedErrorKind,
+    DecodingError, ImageError, ImageResult, ParameterError, ParameterErrorKind, UnsupportedError,
+    UnsupportedErrorKind,
 };
-use crate::image::{self, ImageDecoder, ImageFormat};
+use crate::image::{self, ImageDecoder, ImageFormat, MultiImageDecoder};
+use crate::io::Limits;
 
 use self::InnerDecoder::*;
 use crate::codecs::bmp::BmpDecoder;
@@ -108,9 +110,22 @@ impl Into<ImageFormat> for IcoEntryImageFormat {
 }
 
 /// An ico decoder
+///
+/// The entry with the highest color depth and size is selected initially, other entries can be
+/// selected and read through the [`MultiImageDecoder`] implementation.
+///
+/// [`MultiImageDecoder`]: ../../trait.MultiImageDecoder.html
 pub struct IcoDecoder<R: Read> {
+    reader: R,
+    entries: Vec<DirEntry>,
+    selected: usize,
     selected_entry: DirEntry,
-    inner_decoder: InnerDecoder<R>,
+    /// The dimensions of the selected entry, read from the header of its image.
+    dimensions: (u32, u32),
+    /// The color type of the selected entry, read from the header of its image.
+    color_type: ColorType,
+    /// The limits on the decoders of the entries.
+    limits: Limits,
 }
 
 enum InnerDecoder<R: Read> {
@@ -118,6 +133,22 @@ enum InnerDecoder<R: Read> {
     Png(PngDecoder<R>),
 }
 
+impl<R: Read + Seek> InnerDecoder<R> {
+    fn dimensions(&self) -> (u32, u32) {
+        match self {
+            Bmp(decoder) => decoder.dimensions(),
+            Png(decoder) => decoder.dimensions(),
+        }
+    }
+
+    fn color_type(&self) -> ColorType {
+        match self {
+            Bmp(decoder) => decoder.color_type(),
+            Png(decoder) => decoder.color_type(),
+        }
+    }
+}
+
 #[derive(Clone, Copy, Default)]
 struct DirEntry {
     width: u8,
@@ -145,18 +176,60 @@ struct DirEntry {
 
 impl<R: Read + Seek> IcoDecoder<R> {
     /// Create a new decoder that decodes from the stream ```r```
-    pub fn new(mut r: R) -> ImageResult<IcoDecoder<R>> {
+    pub fn new(r: R) -> ImageResult<IcoDecoder<R>> {
+        Self::with_limits(r, Limits::no_limits())
+    }
+
+    /// Create a new decoder that decodes from the stream ```r``` within the given limits.
+    pub fn with_limits(mut r: R, limits: Limits) -> ImageResult<IcoDecoder<R>> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
         let entries = read_entries(&mut r)?;
-        let entry = best_entry(entries)?;
-        let decoder = entry.decoder(r)?;
+        let selected = best_entry(&entries)?;
+        let entry = entries[selected];
+        let decoder = entry.decoder(&mut r, &limits)?;
 
         Ok(IcoDecoder {
+            dimensions: decoder.dimensions(),
+            color_type: decoder.color_type(),
+            reader: r,
+            entries,
+            selected,
             selected_entry: entry,
-            inner_decoder: decoder,
+            limits,
         })
     }
 }
 
+impl<'a, R: 'a + Read + Seek> MultiImageDecoder<'a> for IcoDecoder<R> {
+    fn image_count(&mut self) -> ImageResult<usize> {
+        Ok(self.entries.len())
+    }
+
+    fn select_image(&mut self, index: usize) -> ImageResult<()> {
+        if index == self.selected {
+            return Ok(());
+        }
+        let entry = *self.entries.get(index).ok_or_else(|| {
+            ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                format!("the file has no image at index {}", index),
+            )))
+        })?;
+        let decoder = entry.decoder(&mut self.reader, &self.limits)?;
+        self.dimensions = decoder.dimensions();
+        self.color_type = decoder.color_type();
+        self.selected = index;
+        self.selected_entry = entry;
+        Ok(())
+    }
+
+    fn read_page(&mut self, index: usize, buf: &mut [u8]) -> ImageResult<()> {
+        self.select_image(index)?;
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        self.selected_entry
+            .read_image(&mut self.reader, &self.limits, buf)
+    }
+}
+
 fn read_entries<R: Read>(r: &mut R) -> ImageResult<Vec<DirEntry>> {
     let _reserved = r.read_u16::<LittleEndian>()?;
     let _type = r.read_u16::<LittleEndian>()?;
@@ -193,23 +266,22 @@ fn read_entry<R: Read>(r: &mut R) -> ImageResult<DirEntry> {
     })
 }
 
-/// Find the entry with the highest (color depth, size).
-fn best_entry(mut entries: Vec<DirEntry>) -> ImageResult<DirEntry> {
-    let mut best = entries.pop().ok_or(DecoderError::NoEntries)?;
-
-    let mut best_score = (
-        best.bits_per_pixel,
-        u32::from(best.real_width()) * u32::from(best.real_height()),
-    );
-
-    for entry in entries {
-        let score = (
+/// Find the index of the entry with the highest (color depth, size).
+fn best_entry(entries: &[DirEntry]) -> ImageResult<usize> {
+    let score = |entry: &DirEntry| {
+        (
             entry.bits_per_pixel,
             u32::from(entry.real_width()) * u32::from(entry.real_height()),
-        );
-        if score > best_score {
-            best = entry;
-            best_score = score;
+        )
+    };
+    // The last of the entries with the highest score is chosen.
+    let mut best = entries
+        .len()
+        .checked_sub(1)
+        .ok_or(DecoderError::NoEntries)?;
+    for (index, entry) in entries.iter().enumerate() {
+        if score(entry) > score(&entries[best]) {
+            best = index;
         }
     }
     Ok(best)
@@ -234,90 +306,45 @@ impl DirEntry {
         u32::from(self.real_width()) == width && u32::from(self.real_height()) == height
     }
 
-    fn seek_to_start<R: Read + Seek>(&self, r: &mut R) -> ImageResult<()> {
-        r.seek(SeekFrom::Start(u64::from(self.image_offset)))?;
-        Ok(())
-    }
-
-    fn is_png<R: Read + Seek>(&self, r: &mut R) -> ImageResult<bool> {
-        self.seek_to_start(r)?;
-
-        // Read the first 8 bytes to sniff the image.
-        let mut signature = [0u8; 8];
-        r.read_exact(&mut signature)?;
-
-        Ok(signature == PNG_SIGNATURE)
-    }
-
-    fn decoder<R: Read + Seek>(&self, mut r: R) -> ImageResult<InnerDecoder<R>> {
-        let is_png = self.is_png(&mut r)?;
-        self.seek_to_start(&mut r)?;
-
-        if is_png {
-            Ok(Png(PngDecoder::new(r)?))
-        } else {
-            Ok(Bmp(BmpDecoder::new_with_ico_format(r)?))
-        }
-    }
-}
-
-/// Wrapper struct around a `Cursor<Vec<u8>>`
-pub struct IcoReader<R>(Cursor<Vec<u8>>, PhantomData<R>);
-impl<R> Read for IcoReader<R> {
-    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
-        self.0.read(buf)
-    }
-    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
-        if self.0.position() == 0 && buf.is_empty() {
-            mem::swap(buf, self.0.get_mut());
-            Ok(buf.len())
+    /// Create a decoder for the image of this entry, within the limits.
+    ///
+    /// The image is decoded in place from `r`, which only has to be positioned on the entry.
+    fn decoder<R: Read + Seek>(
+        &self,
+        r: R,
+        limits: &Limits,
+    ) -> ImageResult<InnerDecoder<EntryReader<R>>> {
+        let mut r = EntryReader::new(r, self)?;
+        let mut signature = Vec::with_capacity(PNG_SIGNATURE.len());
+        (&mut r)
+            .take(PNG_SIGNATURE.len() as u64)
+            .read_to_end(&mut signature)?;
+        r.seek(SeekFrom::Start(0))?;
+
+        if signature == PNG_SIGNATURE {
+            Ok(Png(PngDecoder::with_limits(r, limits.clone())?))
         } else {
-            self.0.read_to_end(buf)
-        }
-    }
-}
-
-impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for IcoDecoder<R> {
-    type Reader = IcoReader<R>;
-
-    fn dimensions(&self) -> (u32, u32) {
-        match self.inner_decoder {
-            Bmp(ref decoder) => decoder.dimensions(),
-            Png(ref decoder) => decoder.dimensions(),
+            let mut decoder = BmpDecoder::new_with_ico_format(r)?;
+            decoder.set_limits(limits.clone())?;
+            Ok(Bmp(decoder))
         }
     }
 
-    fn color_type(&self) -> ColorType {
-        match self.inner_decoder {
-            Bmp(ref decoder) => decoder.color_type(),
-            Png(ref decoder) => decoder.color_type(),
-        }
-    }
-
-    fn into_reader(self) -> ImageResult<Self::Reader> {
-        Ok(IcoReader(
-            Cursor::new(image::decoder_to_vec(self)?),
-            PhantomData,
-        ))
-    }
-
-    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
-        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
-        match self.inner_decoder {
+    /// Decode the image of this entry into `buf`, applying the AND mask of BMP images.
+    fn read_image<R: Read + Seek>(&self, r: R, limits: &Limits, buf: &mut [u8]) -> ImageResult<()> {
+        limits.check_cancelled()?;
+        match self.decoder(r, limits)? {
             Png(decoder) => {
-                if self.selected_entry.image_length < PNG_SIGNATURE.len() as u32 {
+                if self.image_length < PNG_SIGNATURE.len() as u32 {
                     return Err(DecoderError::PngShorterThanHeader.into());
                 }
 
                 // Check if the image dimensions match the ones in the image data.
                 let (width, height) = decoder.dimensions();
-                if !self.selected_entry.matches_dimensions(width, height) {
+                if !self.matches_dimensions(width, height) {
                     return Err(DecoderError::ImageEntryDimensionMismatch {
                         format: IcoEntryImageFormat::Png,
-                        entry: (
-                            self.selected_entry.real_width(),
-                            self.selected_entry.real_height(),
-                        ),
+                        entry: (self.real_width(), self.real_height()),
                         image: (width, height),
                     }
                     .into());
@@ -333,13 +360,10 @@ impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for IcoDecoder<R> {
             }
             Bmp(mut decoder) => {
                 let (width, height) = decoder.dimensions();
-                if !self.selected_entry.matches_dimensions(width, height) {
+                if !self.matches_dimensions(width, height) {
                     return Err(DecoderError::ImageEntryDimensionMismatch {
                         format: IcoEntryImageFormat::Bmp,
-                        entry: (
-                            self.selected_entry.real_width(),
-                            self.selected_entry.real_height(),
-                        ),
+                        entry: (self.real_width(), self.real_height()),
                         image: (width, height),
                     }
                     .into());
@@ -355,12 +379,18 @@ impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for IcoDecoder<R> {
                     ));
                 }
 
-                decoder.read_image_data(buf)?;
+                match decoder.read_image_data(buf) {
+                    // The pixels extend past the data of the entry.
+                    Err(ImageError::IoError(err)) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                        return Err(DecoderError::InvalidDataSize.into())
+                    }
+                    result => result?,
+                }
 
                 let r = decoder.reader();
                 let image_end = r.seek(SeekFrom::Current(0))?;
-                let data_end =
-                    u64::from(self.selected_entry.image_offset + self.selected_entry.image_length);
+                // The reader only holds the data of the entry.
+                let data_end = u64::from(self.image_length);
 
                 let mask_row_bytes = ((width + 31) / 32) * 4;
                 let mask_length = u64::from(mask_row_bytes) * u64::from(height);
@@ -404,6 +434,120 @@ impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for IcoDecoder<R> {
     }
 }
 
+/// The data of a single entry, read in place from the reader of the whole file.
+///
+/// Positions are relative to the start of the entry, and reads end at its end.
+struct EntryReader<R> {
+    inner: R,
+    start: u64,
+    len: u64,
+    position: u64,
+}
+
+impl<R: Seek> EntryReader<R> {
+    fn new(mut inner: R, entry: &DirEntry) -> io::Result<Self> {
+        let start = u64::from(entry.image_offset);
+        inner.seek(SeekFrom::Start(start))?;
+        Ok(EntryReader {
+            inner,
+            start,
+            len: u64::from(entry.image_length),
+            position: 0,
+        })
+    }
+}
+
+impl<R: Read> Read for EntryReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let remaining = self.len.saturating_sub(self.position);
+        let len = buf
+            .len()
+            .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+        let read = self.inner.read(&mut buf[..len])?;
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for EntryReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let offset = |base: u64, delta: i64| {
+            if delta >= 0 {
+                base.checked_add(delta as u64)
+            } else {
+                base.checked_sub(delta.unsigned_abs())
+            }
+        };
+        let position = match pos {
+            SeekFrom::Start(position) => Some(position),
+            SeekFrom::Current(delta) => offset(self.position, delta),
+            SeekFrom::End(delta) => offset(self.len, delta),
+        };
+        let absolute = position.and_then(|position| self.start.checked_add(position));
+        let (position, absolute) = match (position, absolute) {
+            (Some(position), Some(absolute)) => (position, absolute),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "invalid seek to a negative or overflowing position",
+                ))
+            }
+        };
+        self.inner.seek(SeekFrom::Start(absolute))?;
+        self.position = position;
+        Ok(position)
+    }
+}
+
+/// Wrapper struct around a `Cursor<Vec<u8>>`
+pub struct IcoReader<R>(Cursor<Vec<u8>>, PhantomData<R>);
+impl<R> Read for IcoReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        if self.0.position() == 0 && buf.is_empty() {
+            mem::swap(buf, self.0.get_mut());
+            Ok(buf.len())
+        } else {
+            self.0.read_to_end(buf)
+        }
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for IcoDecoder<R> {
+    type Reader = IcoReader<R>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.dimensions
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        Ok(IcoReader(
+            Cursor::new(image::decoder_to_vec(self)?),
+            PhantomData,
+        ))
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        self.limits = limits;
+        Ok(())
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        self.selected_entry
+            .read_image(self.reader, &self.limits, buf)
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -466,4 +610,39 @@ mod test {
         let mut buf = vec![0; usize::try_from(decoder.total_bytes()).unwrap()];
         assert!(decoder.read_image(&mut buf).is_err());
     }
+
+    #[test]
+    fn select_each_entry() {
+        use crate::codecs::ico::IcoEncoder;
+        use crate::image::MultiImageEncoder;
+
+        let mut encoded = Vec::new();
+        let mut encoder = IcoEncoder::new(&mut encoded);
+        for &size in &[16u32, 48, 32] {
+            let pixels = vec![size as u8; (size * size * 4) as usize];
+            encoder
+                .append_image(&pixels, size, size, ColorType::Rgba8)
+                .unwrap();
+        }
+        encoder.finish().unwrap();
+
+        let mut decoder = IcoDecoder::new(Cursor::new(&encoded)).unwrap();
+        assert_eq!(decoder.dimensions(), (48, 48));
+        assert_eq!(decoder.image_count().unwrap(), 3);
+        decoder.select_image(2).unwrap();
+        assert_eq!(decoder.dimensions(), (32, 32));
+        assert!(decoder.select_image(3).is_err());
+
+        // Every entry is decoded in place, without consuming the decoder.
+        for (index, &size) in [16u32, 48, 32].iter().enumerate() {
+            let mut buf = vec![0; (size * size * 4) as usize];
+            decoder.read_page(index, &mut buf).unwrap();
+            assert_eq!(decoder.dimensions(), (size, size));
+            assert!(buf.iter().all(|&b| b == size as u8));
+        }
+
+        let mut buf = vec![0; usize::try_from(decoder.total_bytes()).unwrap()];
+        decoder.read_image(&mut buf).unwrap();
+        assert!(buf.iter().all(|&b| b == 32));
+    }
 }
@@ -1,10 +1,11 @@
 use byteorder::{LittleEndian, WriteBytesExt};
 use std::borrow::Cow;
 use std::io::{self, Write};
+use std::mem;
 
 use crate::color::ColorType;
 use crate::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
-use crate::image::ImageEncoder;
+use crate::image::{ImageEncoder, MultiImageEncoder};
 
 use crate::codecs::png::PngEncoder;
 
@@ -16,8 +17,16 @@ const ICO_ICONDIR_SIZE: u32 = 6;
 const ICO_DIRENTRY_SIZE: u32 = 16;
 
 /// ICO encoder
+///
+/// An icon with several entries can be written with [`encode_images`] or, one image at a time,
+/// with the [`MultiImageEncoder`] implementation.
+///
+/// [`encode_images`]: #method.encode_images
+/// [`MultiImageEncoder`]: ../../trait.MultiImageEncoder.html
 pub struct IcoEncoder<W: Write> {
     w: W,
+    // Images added through `MultiImageEncoder::append_image`.
+    frames: Vec<IcoFrame<'static>>,
 }
 
 /// An ICO image entry
@@ -84,7 +93,10 @@ impl<'a> IcoFrame<'a> {
 impl<W: Write> IcoEncoder<W> {
     /// Create a new encoder that writes its output to ```w```.
     pub fn new(w: W) -> IcoEncoder<W> {
-        IcoEncoder { w }
+        IcoEncoder {
+            w,
+            frames: Vec::new(),
+        }
     }
 
     /// Encodes the image ```image``` that has dimensions ```width``` and
@@ -157,6 +169,29 @@ impl<W: Write> ImageEncoder for IcoEncoder<W> {
     }
 }
 
+impl<W: Write> MultiImageEncoder for IcoEncoder<W> {
+    /// Add an entry to the icon, encoded as PNG.
+    ///
+    /// The dimensions of the image must be between 1 and 256 (inclusive). The icon is only written
+    /// by `finish`.
+    fn append_image(
+        &mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        let frame = IcoFrame::as_png(buf, width, height, color_type)?;
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    fn finish(mut self) -> ImageResult<()> {
+        let frames = mem::take(&mut self.frames);
+        self.encode_images(&frames)
+    }
+}
+
 fn write_icondir<W: Write>(w: &mut W, num_images: u16) -> io::Result<()> {
     // Reserved field (must be zero):
     w.write_u16::<LittleEndian>(0)?;
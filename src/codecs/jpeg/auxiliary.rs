@@ -0,0 +1,219 @@
+//! Auxiliary images, such as depth maps and portrait mattes, that phones store in JPEG files.
+//!
+//! These images follow the primary image in the file, so decoders without support for them only
+//! see the primary image. Two ways of locating them are supported:
+//!
+//! * The container directory in the XMP metadata of the primary image, used by the Google Dynamic
+//!   Depth and Ultra HDR formats. It lists the semantic and length of each appended image.
+//! * The Multi-Picture index, used by Apple. The type of each image is given by its own XMP
+//!   metadata.
+//!
+//! # Related Links
+//! * <https://developer.android.com/media/camera/camera2/dynamic-depth> - Dynamic Depth
+//! * <https://developer.apple.com/documentation/avfoundation/avportraiteffectsmatte> - Apple
+//!   portrait effects mattes
+
+use std::io::Read;
+
+use crate::dynimage::DynamicImage;
+use crate::error::ImageResult;
+
+use super::mpf::{self, MpType};
+use super::segments;
+
+/// The kind of an auxiliary image.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum AuxiliaryImageKind {
+    /// A depth or disparity map of the scene.
+    Depth,
+    /// A matte that separates the subject of a portrait from the background.
+    PortraitMatte,
+    /// A segmentation matte of the skin, hair, teeth or glasses of people in a portrait, named by
+    /// the last part of its type, for example `skinmatte`.
+    SegmentationMatte(String),
+    /// A gain map to reconstruct a HDR rendition, see [`read_gain_map`].
+    ///
+    /// [`read_gain_map`]: fn.read_gain_map.html
+    GainMap,
+    /// An image of a type not covered above, with the semantic or type given in the file.
+    Other(String),
+}
+
+/// An image stored alongside the primary image of a file.
+#[derive(Clone, Debug)]
+pub struct AuxiliaryImage {
+    /// What the image holds.
+    pub kind: AuxiliaryImageKind,
+    /// The image itself, usually smaller than the primary image.
+    pub image: DynamicImage,
+}
+
+/// Reads the auxiliary images of a JPEG file.
+///
+/// The whole file is read into memory. Returns an empty list for files without any.
+///
+/// ```no_run
+/// # fn main() -> image::ImageResult<()> {
+/// use image::codecs::jpeg::{read_auxiliary_images, AuxiliaryImageKind};
+///
+/// let file = std::io::BufReader::new(std::fs::File::open("portrait.jpg")?);
+/// let depth = read_auxiliary_images(file)?
+///     .into_iter()
+///     .find(|auxiliary| auxiliary.kind == AuxiliaryImageKind::Depth);
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_auxiliary_images<R: Read>(mut r: R) -> ImageResult<Vec<AuxiliaryImage>> {
+    let mut data = Vec::new();
+    r.read_to_end(&mut data)?;
+    auxiliary_images(&data)
+}
+
+/// Decodes the auxiliary images of a whole JPEG file.
+pub(crate) fn auxiliary_images(data: &[u8]) -> ImageResult<Vec<AuxiliaryImage>> {
+    let mut images = Vec::new();
+    for (kind, image) in container_items(data)
+        .or_else(|| indexed_items(data))
+        .unwrap_or_default()
+    {
+        images.push(AuxiliaryImage {
+            kind,
+            image: crate::load_from_memory(image)?,
+        });
+    }
+    Ok(images)
+}
+
+/// Locate auxiliary images through the container directory of the primary image.
+fn container_items(data: &[u8]) -> Option<Vec<(AuxiliaryImageKind, &[u8])>> {
+    let xmp = mpf::xmp(data)?;
+    let directory = xmp.find("<Container:Directory")?;
+    let mut position = segments::image_length(data)?;
+    let mut items = Vec::new();
+    // The first item describes the primary image, the others follow it in the file.
+    for item in xmp[directory..].split("<Container:Item").skip(2) {
+        let item = &item[..item.find('>')?];
+        let length: usize = attribute(item, "Item:Length")?.parse().ok()?;
+        let image = data.get(position..position.checked_add(length)?)?;
+        position += length;
+
+        let kind = match attribute(item, "Item:Semantic")? {
+            "Depth" => AuxiliaryImageKind::Depth,
+            "GainMap" => AuxiliaryImageKind::GainMap,
+            other => AuxiliaryImageKind::Other(other.to_owned()),
+        };
+        items.push((kind, image));
+    }
+    Some(items)
+}
+
+/// Locate auxiliary images through the Multi-Picture index.
+fn indexed_items(data: &[u8]) -> Option<Vec<(AuxiliaryImageKind, &[u8])>> {
+    let items = mpf::entries(data)?
+        .iter()
+        .filter(|entry| entry.kind == MpType::Undefined)
+        .filter_map(|entry| {
+            let image = entry.data(data)?;
+            let xmp = mpf::xmp(image)?;
+            let kind = if xmp.contains("http://ns.apple.com/depthData/1.0/") {
+                AuxiliaryImageKind::Depth
+            } else if xmp.contains("hdrgm:Version") {
+                AuxiliaryImageKind::GainMap
+            } else {
+                match attribute(xmp, "apdi:AuxiliaryImageType")?
+                    .rsplit(':')
+                    .next()?
+                {
+                    "portraiteffectsmatte" => AuxiliaryImageKind::PortraitMatte,
+                    "hdrgainmap" => AuxiliaryImageKind::GainMap,
+                    matte if matte.ends_with("matte") => {
+                        AuxiliaryImageKind::SegmentationMatte(matte.to_owned())
+                    }
+                    other => AuxiliaryImageKind::Other(other.to_owned()),
+                }
+            };
+            Some((kind, image))
+        })
+        .collect();
+    Some(items)
+}
+
+/// Returns the value of an XML attribute in `element`.
+fn attribute<'a>(element: &'a str, name: &str) -> Option<&'a str> {
+    let start = element.find(&format!("{}=\"", name))? + name.len() + 2;
+    let value = &element[start..];
+    Some(&value[..value.find('"')?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mpf::test_utils::{multi_picture, with_xmp};
+    use super::*;
+    use crate::codecs::jpeg::JpegEncoder;
+    use crate::image::GenericImageView;
+    use crate::GrayImage;
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        JpegEncoder::new(&mut encoded)
+            .encode_image(&GrayImage::new(width, height))
+            .unwrap();
+        encoded
+    }
+
+    #[test]
+    fn image_length_skips_entropy_coded_data() {
+        let primary = jpeg(16, 16);
+        let mut file = primary.clone();
+        file.extend_from_slice(&jpeg(4, 4));
+        assert_eq!(segments::image_length(&file), Some(primary.len()));
+    }
+
+    #[test]
+    fn container_directory() {
+        let depth = jpeg(8, 6);
+        let xmp = format!(
+            r#"<x:xmpmeta><rdf:RDF><rdf:Description><Container:Directory><rdf:Seq>
+              <rdf:li rdf:parseType="Resource"><Container:Item Item:Semantic="Primary" Item:Mime="image/jpeg"/></rdf:li>
+              <rdf:li rdf:parseType="Resource"><Container:Item Item:Semantic="Depth" Item:Mime="image/jpeg" Item:Length="{}"/></rdf:li>
+            </rdf:Seq></Container:Directory></rdf:Description></rdf:RDF></x:xmpmeta>"#,
+            depth.len()
+        );
+        let mut file = with_xmp(&jpeg(16, 12), &xmp);
+        file.extend_from_slice(&depth);
+
+        let images = read_auxiliary_images(&file[..]).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].kind, AuxiliaryImageKind::Depth);
+        assert_eq!(images[0].image.dimensions(), (8, 6));
+    }
+
+    #[test]
+    fn apple_portrait_mattes() {
+        let matte = with_xmp(
+            &jpeg(8, 8),
+            r#"<rdf:Description xmlns:apdi="http://ns.apple.com/pixeldatainfo/1.0/"
+                apdi:AuxiliaryImageType="urn:com:apple:photo:2018:aux:portraiteffectsmatte"/>"#,
+        );
+        let hair = with_xmp(
+            &jpeg(4, 4),
+            r#"<rdf:Description apdi:AuxiliaryImageType="urn:com:apple:photo:2019:aux:hairmatte"/>"#,
+        );
+        let file = multi_picture(&[(&jpeg(16, 16), 0x2003_0000), (&matte, 0), (&hair, 0)]);
+
+        let images = read_auxiliary_images(&file[..]).unwrap();
+        assert_eq!(images.len(), 2);
+        assert_eq!(images[0].kind, AuxiliaryImageKind::PortraitMatte);
+        assert_eq!(
+            images[1].kind,
+            AuxiliaryImageKind::SegmentationMatte("hairmatte".to_owned())
+        );
+        assert_eq!(images[1].image.dimensions(), (4, 4));
+    }
+
+    #[test]
+    fn plain_jpeg() {
+        assert!(read_auxiliary_images(&jpeg(8, 8)[..]).unwrap().is_empty());
+    }
+}
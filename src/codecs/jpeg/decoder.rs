@@ -3,16 +3,20 @@ use std::io::{self, Cursor, Read};
 use std::marker::PhantomData;
 use std::mem;
 
-use crate::color::ColorType;
+use crate::color::{self, ColorType, ExtendedColorType};
 use crate::error::{
     DecodingError, ImageError, ImageResult, UnsupportedError, UnsupportedErrorKind,
 };
-use crate::image::{ImageDecoder, ImageFormat};
+use crate::image::{self, ImageDecoder, ImageFormat};
+use crate::io::Limits;
+use crate::metadata::Exif;
 
 /// JPEG decoder
 pub struct JpegDecoder<R> {
     decoder: jpeg::Decoder<R>,
     metadata: jpeg::ImageInfo,
+    /// The color type that the decoded samples are converted to.
+    color_type: ColorType,
 }
 
 impl<R: Read> JpegDecoder<R> {
@@ -30,7 +34,12 @@ impl<R: Read> JpegDecoder<R> {
             metadata.pixel_format = jpeg::PixelFormat::RGB24;
         }
 
-        Ok(JpegDecoder { decoder, metadata })
+        let color_type = ColorType::from_jpeg(metadata.pixel_format);
+        Ok(JpegDecoder {
+            decoder,
+            metadata,
+            color_type,
+        })
     }
 
     /// Configure the decoder to scale the image during decoding.
@@ -61,6 +70,15 @@ impl<R: Read> JpegDecoder<R> {
 
         Ok(result)
     }
+
+    /// Decode the samples of the image, in the color type of the file.
+    fn decode(&mut self) -> ImageResult<Vec<u8>> {
+        let data = self.decoder.decode().map_err(ImageError::from_jpeg)?;
+        Ok(match self.decoder.info().unwrap().pixel_format {
+            jpeg::PixelFormat::CMYK32 => cmyk_to_rgb(&data),
+            _ => data,
+        })
+    }
 }
 
 /// Wrapper struct around a `Cursor<Vec<u8>>`
@@ -90,15 +108,73 @@ impl<'a, R: 'a + Read> ImageDecoder<'a> for JpegDecoder<R> {
     }
 
     fn color_type(&self) -> ColorType {
-        ColorType::from_jpeg(self.metadata.pixel_format)
+        self.color_type
+    }
+
+    fn original_color_type(&self) -> ExtendedColorType {
+        ColorType::from_jpeg(self.metadata.pixel_format).into()
+    }
+
+    fn icc_profile(&mut self) -> Option<Vec<u8>> {
+        self.decoder.icc_profile()
+    }
+
+    fn exif_metadata(&mut self) -> Option<Exif> {
+        Exif::from_bytes(self.decoder.exif_data()?).ok()
+    }
+
+    fn request_color_type(&mut self, color_type: ColorType) -> bool {
+        let decoded = ColorType::from_jpeg(self.metadata.pixel_format);
+        if color_type != decoded && !color::can_convert_samples(decoded, color_type) {
+            return false;
+        }
+        self.color_type = color_type;
+        true
+    }
+
+    /// Scales the IDCT by 1/2, 1/4 or 1/8, the smallest that keeps the requested dimensions.
+    fn request_dimensions(&mut self, width: u32, height: u32) -> bool {
+        let full = self.dimensions();
+        let requested = (
+            u16::try_from(width).unwrap_or(u16::MAX),
+            u16::try_from(height).unwrap_or(u16::MAX),
+        );
+        match self.scale(requested.0, requested.1) {
+            Ok(_) => self.dimensions() != full,
+            Err(_) => false,
+        }
+    }
+
+    /// The samples are decoded into a buffer of their own, and CMYK samples into a second one
+    /// for their RGB conversion, before they are copied out.
+    fn set_limits(&mut self, mut limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+
+        let pixels = u64::from(width) * u64::from(height);
+        let max_decoding_buffer_size = limits.max_alloc_usize();
+        let decoded = self
+            .decoder
+            .info()
+            .map_or(self.metadata.pixel_format, |info| info.pixel_format);
+        limits.reserve(pixels * decoded.pixel_bytes() as u64)?;
+        if decoded == jpeg::PixelFormat::CMYK32 {
+            limits.reserve(pixels * 3)?;
+        }
+        self.decoder
+            .set_max_decoding_buffer_size(max_decoding_buffer_size);
+        Ok(())
     }
 
     fn into_reader(mut self) -> ImageResult<Self::Reader> {
-        let mut data = self.decoder.decode().map_err(ImageError::from_jpeg)?;
-        data = match self.decoder.info().unwrap().pixel_format {
-            jpeg::PixelFormat::CMYK32 => cmyk_to_rgb(&data),
-            _ => data,
-        };
+        let mut data = self.decode()?;
+        let decoded = ColorType::from_jpeg(self.metadata.pixel_format);
+        if decoded != self.color_type {
+            let mut converted = vec![0; self.total_bytes() as usize];
+            color::convert_samples(decoded, self.color_type, &data, &mut converted);
+            data = converted;
+        }
 
         Ok(JpegReader(Cursor::new(data), PhantomData))
     }
@@ -106,13 +182,24 @@ impl<'a, R: 'a + Read> ImageDecoder<'a> for JpegDecoder<R> {
     fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
         assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
 
-        let mut data = self.decoder.decode().map_err(ImageError::from_jpeg)?;
-        data = match self.decoder.info().unwrap().pixel_format {
-            jpeg::PixelFormat::CMYK32 => cmyk_to_rgb(&data),
-            _ => data,
-        };
+        let data = self.decode()?;
+        // Samples of another color type are converted while they are copied out.
+        let decoded = ColorType::from_jpeg(self.metadata.pixel_format);
+        color::convert_samples(decoded, self.color_type, &data, buf);
+        Ok(())
+    }
 
-        buf.copy_from_slice(&data);
+    fn read_image_into_uninit(mut self, buf: &mut [mem::MaybeUninit<u8>]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+
+        let data = self.decode()?;
+        let decoded = ColorType::from_jpeg(self.metadata.pixel_format);
+        if decoded == self.color_type && data.len() == buf.len() {
+            // The decoder holds the image already, it only needs to be copied out.
+            image::copy_to_uninit(buf, &data);
+        } else {
+            color::convert_samples(decoded, self.color_type, &data, image::zero_uninit(buf));
+        }
         Ok(())
     }
 }
@@ -187,6 +274,68 @@ mod tests {
     #[cfg(feature = "benchmarks")]
     const H: usize = 256;
 
+    #[test]
+    fn read_image_into_uninit() {
+        use super::JpegDecoder;
+        use crate::color::ColorType;
+        use crate::image::ImageDecoder;
+        use std::mem::MaybeUninit;
+
+        let data = std::fs::read("tests/images/jpg/progressive/cat.jpg").unwrap();
+        let decoder = JpegDecoder::new(&data[..]).unwrap();
+        let mut expected = vec![0; decoder.total_bytes() as usize];
+        decoder.read_image(&mut expected).unwrap();
+
+        let decoder = JpegDecoder::new(&data[..]).unwrap();
+        let mut buf = vec![MaybeUninit::uninit(); expected.len()];
+        decoder.read_image_into_uninit(&mut buf).unwrap();
+        // Safety: the decoder initialized the whole buffer.
+        let buf: Vec<u8> = buf
+            .iter()
+            .map(|byte| unsafe { byte.assume_init() })
+            .collect();
+        assert_eq!(buf, expected);
+
+        // Converted samples are written too.
+        let mut decoder = JpegDecoder::new(&data[..]).unwrap();
+        assert!(decoder.request_color_type(ColorType::L8));
+        let mut buf = vec![MaybeUninit::uninit(); decoder.total_bytes() as usize];
+        decoder.read_image_into_uninit(&mut buf).unwrap();
+    }
+
+    #[test]
+    fn request_color_type() {
+        use super::JpegDecoder;
+        use crate::color::{ColorType, ExtendedColorType};
+        use crate::image::ImageDecoder;
+
+        let data = std::fs::read("tests/images/jpg/progressive/cat.jpg").unwrap();
+        let image = crate::load_from_memory(&data).unwrap();
+        let mut decoder = JpegDecoder::new(&data[..]).unwrap();
+        assert!(!decoder.request_color_type(ColorType::Rgba16));
+        assert!(decoder.request_color_type(ColorType::L8));
+        assert_eq!(decoder.color_type(), ColorType::L8);
+        assert_eq!(decoder.original_color_type(), ExtendedColorType::Rgb8);
+        let mut buf = vec![0; decoder.total_bytes() as usize];
+        decoder.read_image(&mut buf).unwrap();
+        assert!(buf == image.to_luma8().into_raw());
+    }
+
+    #[test]
+    fn request_dimensions() {
+        use super::JpegDecoder;
+        use crate::image::ImageDecoder;
+
+        let data = std::fs::read("tests/images/jpg/progressive/cat.jpg").unwrap();
+        let mut decoder = JpegDecoder::new(&data[..]).unwrap();
+        let (width, height) = decoder.dimensions();
+        assert!(!decoder.request_dimensions(width, height));
+        assert!(decoder.request_dimensions(width / 3, height / 3));
+        assert_eq!(decoder.dimensions(), ((width + 1) / 2, (height + 1) / 2));
+        let mut buf = vec![0; decoder.total_bytes() as usize];
+        decoder.read_image(&mut buf).unwrap();
+    }
+
     #[test]
     fn cmyk_to_rgb_correct() {
         for c in 0..=255 {
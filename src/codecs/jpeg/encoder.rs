@@ -7,14 +7,17 @@ use std::io::{self, Write};
 use num_iter::range_step;
 
 use crate::error::{
-    ImageError, ImageResult, ParameterError, ParameterErrorKind, UnsupportedError,
+    EncodingError, ImageError, ImageResult, ParameterError, ParameterErrorKind, UnsupportedError,
     UnsupportedErrorKind,
 };
 use crate::image::{ImageEncoder, ImageFormat};
+use crate::metadata::{Exif, MetadataMap, EXIF_IDENTIFIER};
 use crate::utils::clamp;
 use crate::{ColorType, GenericImageView, ImageBuffer, Luma, LumaA, Pixel, Rgb, Rgba};
 
 use super::entropy::build_huff_lut_const;
+use super::mpf::{APP1, APP2, ICC_IDENTIFIER, XMP_IDENTIFIER};
+use super::panorama::PanoramaMetadata;
 use super::transform;
 use crate::traits::PixelWithColorType;
 
@@ -34,6 +37,16 @@ static DQT: u8 = 0xDB;
 // Application segments start and end
 static APP0: u8 = 0xE0;
 
+/// The largest part of an ICC profile that fits into one APP2 segment, after the length of the
+/// segment, the identifier and the sequence numbers.
+const MAX_ICC_CHUNK_LEN: usize = 0xFFFF - 2 - 14;
+/// The longest EXIF metadata that fits into its APP1 segment, after the length of the segment
+/// and the identifier.
+const MAX_EXIF_LEN: usize = 0xFFFF - 2 - 6;
+/// The longest XMP packet that fits into its APP1 segment, after the length of the segment and
+/// the identifier.
+const MAX_XMP_LEN: usize = 0xFFFF - 2 - 29;
+
 // section K.1
 // table K.1
 #[rustfmt::skip]
@@ -63,11 +76,11 @@ static STD_CHROMA_QTABLE: [u8; 64] = [
 
 // section K.3
 // Code lengths and values for table K.3
-static STD_LUMA_DC_CODE_LENGTHS: [u8; 16] = [
+pub(super) static STD_LUMA_DC_CODE_LENGTHS: [u8; 16] = [
     0x00, 0x01, 0x05, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
 ];
 
-static STD_LUMA_DC_VALUES: [u8; 12] = [
+pub(super) static STD_LUMA_DC_VALUES: [u8; 12] = [
     0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B,
 ];
 
@@ -75,11 +88,11 @@ static STD_LUMA_DC_HUFF_LUT: [(u8, u16); 256] =
     build_huff_lut_const(&STD_LUMA_DC_CODE_LENGTHS, &STD_LUMA_DC_VALUES);
 
 // Code lengths and values for table K.4
-static STD_CHROMA_DC_CODE_LENGTHS: [u8; 16] = [
+pub(super) static STD_CHROMA_DC_CODE_LENGTHS: [u8; 16] = [
     0x00, 0x03, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00,
 ];
 
-static STD_CHROMA_DC_VALUES: [u8; 12] = [
+pub(super) static STD_CHROMA_DC_VALUES: [u8; 12] = [
     0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0A, 0x0B,
 ];
 
@@ -87,11 +100,11 @@ static STD_CHROMA_DC_HUFF_LUT: [(u8, u16); 256] =
     build_huff_lut_const(&STD_CHROMA_DC_CODE_LENGTHS, &STD_CHROMA_DC_VALUES);
 
 // Code lengths and values for table k.5
-static STD_LUMA_AC_CODE_LENGTHS: [u8; 16] = [
+pub(super) static STD_LUMA_AC_CODE_LENGTHS: [u8; 16] = [
     0x00, 0x02, 0x01, 0x03, 0x03, 0x02, 0x04, 0x03, 0x05, 0x05, 0x04, 0x04, 0x00, 0x00, 0x01, 0x7D,
 ];
 
-static STD_LUMA_AC_VALUES: [u8; 162] = [
+pub(super) static STD_LUMA_AC_VALUES: [u8; 162] = [
     0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
     0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xA1, 0x08, 0x23, 0x42, 0xB1, 0xC1, 0x15, 0x52, 0xD1, 0xF0,
     0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0A, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x25, 0x26, 0x27, 0x28,
@@ -109,10 +122,10 @@ static STD_LUMA_AC_HUFF_LUT: [(u8, u16); 256] =
     build_huff_lut_const(&STD_LUMA_AC_CODE_LENGTHS, &STD_LUMA_AC_VALUES);
 
 // Code lengths and values for table k.6
-static STD_CHROMA_AC_CODE_LENGTHS: [u8; 16] = [
+pub(super) static STD_CHROMA_AC_CODE_LENGTHS: [u8; 16] = [
     0x00, 0x02, 0x01, 0x02, 0x04, 0x04, 0x03, 0x04, 0x07, 0x05, 0x04, 0x04, 0x00, 0x01, 0x02, 0x77,
 ];
-static STD_CHROMA_AC_VALUES: [u8; 162] = [
+pub(super) static STD_CHROMA_AC_VALUES: [u8; 162] = [
     0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
     0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xA1, 0xB1, 0xC1, 0x09, 0x23, 0x33, 0x52, 0xF0,
     0x15, 0x62, 0x72, 0xD1, 0x0A, 0x16, 0x24, 0x34, 0xE1, 0x25, 0xF1, 0x17, 0x18, 0x19, 0x1A, 0x26,
@@ -141,7 +154,7 @@ static CHROMAREDID: u8 = 3;
 
 /// The permutation of dct coefficients.
 #[rustfmt::skip]
-static UNZIGZAG: [u8; 64] = [
+pub(super) static UNZIGZAG: [u8; 64] = [
      0,  1,  8, 16,  9,  2,  3, 10,
     17, 24, 32, 25, 18, 11,  4,  5,
     12, 19, 26, 33, 40, 48, 41, 34,
@@ -184,7 +197,7 @@ pub(crate) struct BitWriter<W> {
 }
 
 impl<W: Write> BitWriter<W> {
-    fn new(w: W) -> Self {
+    pub(super) fn new(w: W) -> Self {
         BitWriter {
             w,
             accumulator: 0,
@@ -215,8 +228,12 @@ impl<W: Write> BitWriter<W> {
         Ok(())
     }
 
-    fn pad_byte(&mut self) -> io::Result<()> {
-        self.write_bits(0x7F, 7)
+    pub(super) fn pad_byte(&mut self) -> io::Result<()> {
+        self.write_bits(0x7F, 7)?;
+        // Drop the padding that does not complete a byte, the next bits start a new one.
+        self.accumulator = 0;
+        self.nbits = 0;
+        Ok(())
     }
 
     fn huffman_encode(&mut self, val: u8, table: &[(u8, u16); 256]) -> io::Result<()> {
@@ -229,7 +246,7 @@ impl<W: Write> BitWriter<W> {
         self.write_bits(code, size)
     }
 
-    fn write_block(
+    pub(super) fn write_block(
         &mut self,
         block: &[i32; 64],
         prevdc: i32,
@@ -273,7 +290,7 @@ impl<W: Write> BitWriter<W> {
         Ok(dcval)
     }
 
-    fn write_marker(&mut self, marker: u8) -> io::Result<()> {
+    pub(super) fn write_marker(&mut self, marker: u8) -> io::Result<()> {
         self.w.write_all(&[0xFF, marker])
     }
 
@@ -350,6 +367,10 @@ pub struct JpegEncoder<W> {
     chroma_actable: Cow<'static, [(u8, u16); 256]>,
 
     pixel_density: PixelDensity,
+    panorama: Option<PanoramaMetadata>,
+    icc_profile: Option<Vec<u8>>,
+    exif: Option<Vec<u8>>,
+    xmp: Option<String>,
 }
 
 impl<W: Write> JpegEncoder<W> {
@@ -392,30 +413,11 @@ impl<W: Write> JpegEncoder<W> {
             },
         ];
 
-        // Derive our quantization table scaling value using the libjpeg algorithm
-        let scale = u32::from(clamp(quality, 1, 100));
-        let scale = if scale < 50 {
-            5000 / scale
-        } else {
-            200 - scale * 2
-        };
-
-        let mut tables = vec![STD_LUMA_QTABLE, STD_CHROMA_QTABLE];
-        tables.iter_mut().for_each(|t| {
-            t.iter_mut().for_each(|v| {
-                *v = clamp(
-                    (u32::from(*v) * scale + 50) / 100,
-                    1,
-                    u32::from(u8::max_value()),
-                ) as u8;
-            })
-        });
-
         JpegEncoder {
             writer: BitWriter::new(w),
 
             components,
-            tables,
+            tables: quantization_tables(quality),
 
             luma_dctable: Cow::Borrowed(&STD_LUMA_DC_HUFF_LUT),
             luma_actable: Cow::Borrowed(&STD_LUMA_AC_HUFF_LUT),
@@ -423,6 +425,10 @@ impl<W: Write> JpegEncoder<W> {
             chroma_actable: Cow::Borrowed(&STD_CHROMA_AC_HUFF_LUT),
 
             pixel_density: PixelDensity::default(),
+            panorama: None,
+            icc_profile: None,
+            exif: None,
+            xmp: None,
         }
     }
 
@@ -433,6 +439,12 @@ impl<W: Write> JpegEncoder<W> {
         self.pixel_density = pixel_density;
     }
 
+    /// Mark the images the encoder will encode as 360° panoramas, by writing the given metadata
+    /// into the XMP packet of the file.
+    pub fn set_panorama_metadata(&mut self, metadata: PanoramaMetadata) {
+        self.panorama = Some(metadata);
+    }
+
     /// Encodes the image stored in the raw byte buffer ```image```
     /// that has dimensions ```width``` and ```height```
     /// and ```ColorType``` ```c```
@@ -488,10 +500,83 @@ impl<W: Write> JpegEncoder<W> {
     where
         I::Pixel: PixelWithColorType,
     {
-        let n = I::Pixel::CHANNEL_COUNT;
         let color_type = I::Pixel::COLOR_TYPE;
-        let num_components = if n == 1 || n == 2 { 1 } else { 3 };
+        let num_components = num_components::<I::Pixel>();
+
+        self.write_headers(image.width(), image.height(), num_components)?;
+
+        if color_type.has_color() {
+            self.encode_rgb(image)
+        } else {
+            self.encode_gray(image)
+        }?;
+
+        self.write_trailer()
+    }
 
+    /// Encodes the given image with the highest quality whose result fits into `max_bytes`.
+    ///
+    /// The quality is found with a binary search over the range 1-100, the quality this encoder
+    /// was constructed with is ignored. The forward DCT of the image is computed only once and
+    /// each candidate quality only repeats the quantization and entropy coding. The size of the
+    /// output grows with the quality for practically all images, but this is not strictly
+    /// guaranteed, so a slightly higher quality might also have fit in rare cases.
+    ///
+    /// Returns the chosen quality. If the image does not fit into `max_bytes` even at quality 1
+    /// then an `ImageError::Encoding` is returned and nothing is written.
+    pub fn encode_to_target_size<I: GenericImageView>(
+        &mut self,
+        image: &I,
+        max_bytes: usize,
+    ) -> ImageResult<u8>
+    where
+        I::Pixel: PixelWithColorType,
+    {
+        let num_components = num_components::<I::Pixel>();
+        let blocks = dct_blocks(image, num_components);
+
+        let encode_with_quality = |quality: u8| -> ImageResult<Vec<u8>> {
+            let mut trial = JpegEncoder::new_with_quality(Vec::new(), quality);
+            trial.pixel_density = self.pixel_density;
+            trial.panorama = self.panorama;
+            trial.icc_profile = self.icc_profile.clone();
+            trial.exif = self.exif.clone();
+            trial.xmp = self.xmp.clone();
+            trial.write_headers(image.width(), image.height(), num_components)?;
+            trial.encode_dct_blocks(&blocks, num_components)?;
+            trial.write_trailer()?;
+            Ok(trial.writer.w)
+        };
+
+        let mut best = None;
+        let (mut low, mut high) = (1u8, 100u8);
+        while low <= high {
+            let quality = low + (high - low) / 2;
+            let encoded = encode_with_quality(quality)?;
+            if encoded.len() <= max_bytes {
+                best = Some((quality, encoded));
+                low = quality + 1;
+            } else if quality == 1 {
+                break;
+            } else {
+                high = quality - 1;
+            }
+        }
+
+        match best {
+            Some((quality, encoded)) => {
+                self.writer.w.write_all(&encoded)?;
+                Ok(quality)
+            }
+            None => Err(ImageError::Encoding(EncodingError::new(
+                ImageFormat::Jpeg.into(),
+                format!("the image does not fit into {} bytes", max_bytes),
+            ))),
+        }
+    }
+
+    /// Writes all markers and segments preceding the entropy coded data.
+    fn write_headers(&mut self, width: u32, height: u32, num_components: usize) -> ImageResult<()> {
         self.writer.write_marker(SOI)?;
 
         let mut buf = Vec::new();
@@ -499,17 +584,48 @@ impl<W: Write> JpegEncoder<W> {
         build_jfif_header(&mut buf, self.pixel_density);
         self.writer.write_segment(APP0, &buf)?;
 
+        if let Some(exif) = &self.exif {
+            let mut segment = EXIF_IDENTIFIER.to_vec();
+            segment.extend_from_slice(exif);
+            self.writer.write_segment(APP1, &segment)?;
+        }
+
+        if let Some(xmp) = &self.xmp {
+            let mut segment = XMP_IDENTIFIER.to_vec();
+            segment.extend_from_slice(xmp.as_bytes());
+            self.writer.write_segment(APP1, &segment)?;
+        }
+
+        if let Some(panorama) = self.panorama {
+            let mut xmp = XMP_IDENTIFIER.to_vec();
+            xmp.extend_from_slice(panorama.to_xmp().as_bytes());
+            self.writer.write_segment(APP1, &xmp)?;
+        }
+
+        if let Some(icc_profile) = &self.icc_profile {
+            // The profile is split over as many segments as needed, each starting with the
+            // identifier, its 1-based sequence number and the number of segments.
+            let chunks = icc_profile.chunks(MAX_ICC_CHUNK_LEN);
+            let count = chunks.len();
+            for (i, chunk) in chunks.enumerate() {
+                let mut segment = ICC_IDENTIFIER.to_vec();
+                segment.extend_from_slice(&[i as u8 + 1, count as u8]);
+                segment.extend_from_slice(chunk);
+                self.writer.write_segment(APP2, &segment)?;
+            }
+        }
+
         build_frame_header(
             &mut buf,
             8,
             // TODO: not idiomatic yet. Should be an EncodingError and mention jpg. Further it
             // should check dimensions prior to writing.
-            u16::try_from(image.width()).map_err(|_| {
+            u16::try_from(width).map_err(|_| {
                 ImageError::Parameter(ParameterError::from_kind(
                     ParameterErrorKind::DimensionMismatch,
                 ))
             })?,
-            u16::try_from(image.height()).map_err(|_| {
+            u16::try_from(height).map_err(|_| {
                 ImageError::Parameter(ParameterError::from_kind(
                     ParameterErrorKind::DimensionMismatch,
                 ))
@@ -567,12 +683,10 @@ impl<W: Write> JpegEncoder<W> {
         build_scan_header(&mut buf, &self.components[..num_components]);
         self.writer.write_segment(SOS, &buf)?;
 
-        if color_type.has_color() {
-            self.encode_rgb(image)
-        } else {
-            self.encode_gray(image)
-        }?;
+        Ok(())
+    }
 
+    fn write_trailer(&mut self) -> ImageResult<()> {
         self.writer.pad_byte()?;
         self.writer.write_marker(EOI)?;
         Ok(())
@@ -653,6 +767,33 @@ impl<W: Write> JpegEncoder<W> {
 
         Ok(())
     }
+
+    /// Quantizes and writes blocks produced by [`dct_blocks`].
+    fn encode_dct_blocks(&mut self, blocks: &[[i32; 64]], num_components: usize) -> io::Result<()> {
+        let mut dcprev = [0i32; 3];
+        let mut quantized = [0i32; 64];
+
+        for mcu in blocks.chunks(num_components) {
+            for (component, block) in mcu.iter().enumerate() {
+                let table = &self.tables[component.min(1)];
+                for (i, dct) in quantized.iter_mut().enumerate() {
+                    *dct = ((block[i] / 8) as f32 / f32::from(table[i])).round() as i32;
+                }
+
+                let (dctable, actable) = if component == 0 {
+                    (&*self.luma_dctable, &*self.luma_actable)
+                } else {
+                    (&*self.chroma_dctable, &*self.chroma_actable)
+                };
+
+                dcprev[component] =
+                    self.writer
+                        .write_block(&quantized, dcprev[component], dctable, actable)?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 impl<W: Write> ImageEncoder for JpegEncoder<W> {
@@ -665,6 +806,123 @@ impl<W: Write> ImageEncoder for JpegEncoder<W> {
     ) -> ImageResult<()> {
         self.encode(buf, width, height, color_type)
     }
+
+    /// Embed the profile in `APP2` segments.
+    ///
+    /// Returns an error if the profile needs more than 255 segments, which is the case beyond
+    /// about 16 MiB.
+    fn set_icc_profile(&mut self, icc_profile: Vec<u8>) -> ImageResult<()> {
+        if icc_profile.len() > MAX_ICC_CHUNK_LEN * 255 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(
+                    "the ICC profile is too large for a JPEG file".to_string(),
+                ),
+            )));
+        }
+        self.icc_profile = Some(icc_profile);
+        Ok(())
+    }
+
+    /// Embed the metadata in an `APP1` segment.
+    ///
+    /// Returns an error if the encoded metadata exceeds the 64 KiB of a segment.
+    fn set_exif_metadata(&mut self, exif: &Exif) -> ImageResult<()> {
+        let exif = exif.to_bytes();
+        if exif.len() > MAX_EXIF_LEN {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(
+                    "the EXIF metadata is too large for a JPEG file".to_string(),
+                ),
+            )));
+        }
+        self.exif = Some(exif);
+        Ok(())
+    }
+
+    /// Embed the XMP packet in an `APP1` segment.
+    ///
+    /// JPEG files have no place for other text entries, which return an
+    /// `ImageError::Unsupported`. Returns an error if the packet exceeds the 64 KiB of a segment.
+    fn set_text_metadata(&mut self, metadata: &MetadataMap) -> ImageResult<()> {
+        if metadata.iter().next().is_some() {
+            return Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    ImageFormat::Jpeg.into(),
+                    UnsupportedErrorKind::GenericFeature("text metadata entries".to_string()),
+                ),
+            ));
+        }
+        if metadata.xmp().map_or(0, str::len) > MAX_XMP_LEN {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(
+                    "the XMP packet is too large for a JPEG file".to_string(),
+                ),
+            )));
+        }
+        self.xmp = metadata.xmp().map(str::to_string);
+        Ok(())
+    }
+}
+
+/// Derive the quantization tables for a quality using the libjpeg algorithm.
+fn quantization_tables(quality: u8) -> Vec<[u8; 64]> {
+    let scale = u32::from(clamp(quality, 1, 100));
+    let scale = if scale < 50 {
+        5000 / scale
+    } else {
+        200 - scale * 2
+    };
+
+    let mut tables = vec![STD_LUMA_QTABLE, STD_CHROMA_QTABLE];
+    tables.iter_mut().for_each(|t| {
+        t.iter_mut().for_each(|v| {
+            *v = clamp(
+                (u32::from(*v) * scale + 50) / 100,
+                1,
+                u32::from(u8::MAX),
+            ) as u8;
+        })
+    });
+    tables
+}
+
+fn num_components<P: Pixel>() -> usize {
+    let n = P::CHANNEL_COUNT;
+    if n == 1 || n == 2 {
+        1
+    } else {
+        3
+    }
+}
+
+/// Computes the unquantized DCT coefficients of all blocks of the image.
+///
+/// The blocks are returned in scan order, with the `num_components` blocks of each MCU adjacent.
+fn dct_blocks<I: GenericImageView>(image: &I, num_components: usize) -> Vec<[i32; 64]> {
+    let mut blocks = Vec::new();
+    let mut yblock = [0u8; 64];
+    let mut cb_block = [0u8; 64];
+    let mut cr_block = [0u8; 64];
+
+    for y in range_step(0, image.height(), 8) {
+        for x in range_step(0, image.width(), 8) {
+            if num_components == 1 {
+                copy_blocks_gray(image, x, y, &mut yblock);
+            } else {
+                copy_blocks_ycbcr(image, x, y, &mut yblock, &mut cb_block, &mut cr_block);
+            }
+
+            for block in [&yblock, &cb_block, &cr_block].iter().take(num_components) {
+                // Level shift and fdct
+                // Coeffs are scaled by 8
+                let mut dct = [0i32; 64];
+                transform::fdct(block, &mut dct);
+                blocks.push(dct);
+            }
+        }
+    }
+
+    blocks
 }
 
 fn build_jfif_header(m: &mut Vec<u8>, density: PixelDensity) {
@@ -753,7 +1011,7 @@ fn build_quantization_segment(m: &mut Vec<u8>, precision: u8, identifier: u8, qt
     }
 }
 
-fn encode_coefficient(coefficient: i32) -> (u8, u16) {
+pub(super) fn encode_coefficient(coefficient: i32) -> (u8, u16) {
     let mut magnitude = coefficient.abs() as u16;
     let mut num_bits = 0u8;
 
@@ -846,6 +1104,7 @@ mod tests {
     use crate::color::ColorType;
     use crate::error::ParameterErrorKind::DimensionMismatch;
     use crate::image::ImageDecoder;
+    use crate::metadata::{Exif, MetadataMap, Orientation};
     use crate::{ImageEncoder, ImageError};
 
     use super::super::JpegDecoder;
@@ -918,6 +1177,62 @@ mod tests {
         }
     }
 
+    #[test]
+    fn icc_profile_roundtrip() {
+        // Large enough to be split over two segments.
+        let icc_profile: Vec<u8> = (0..70_000).map(|i| (i % 251) as u8).collect();
+        let mut encoded = Vec::new();
+        let mut encoder = JpegEncoder::new(&mut encoded);
+        encoder.set_icc_profile(icc_profile.clone()).unwrap();
+        encoder.write_image(&[0; 3], 1, 1, ColorType::Rgb8).unwrap();
+
+        let mut decoder = JpegDecoder::new(Cursor::new(&encoded)).unwrap();
+        assert_eq!(decoder.icc_profile(), Some(icc_profile));
+        assert_eq!(decode(&encoded).len(), 3);
+
+        let mut encoder = JpegEncoder::new(Vec::new());
+        assert!(encoder.set_icc_profile(vec![0; 17 << 20]).is_err());
+    }
+
+    #[test]
+    fn exif_roundtrip() {
+        let exif = Exif {
+            orientation: Some(Orientation::Rotate270),
+            date_time_original: Some("2020:01:02 03:04:05".to_string()),
+            ..Exif::default()
+        };
+        let mut encoded = Vec::new();
+        let mut encoder = JpegEncoder::new(&mut encoded);
+        encoder.set_exif_metadata(&exif).unwrap();
+        encoder.write_image(&[0; 3], 1, 1, ColorType::Rgb8).unwrap();
+
+        let mut decoder = JpegDecoder::new(Cursor::new(&encoded)).unwrap();
+        assert_eq!(decoder.exif_metadata(), Some(exif));
+
+        let exif = Exif {
+            software: Some("x".repeat(70_000)),
+            ..Exif::default()
+        };
+        let mut encoder = JpegEncoder::new(Vec::new());
+        assert!(encoder.set_exif_metadata(&exif).is_err());
+    }
+
+    #[test]
+    fn xmp_roundtrip() {
+        let xmp = "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/>";
+        let mut metadata = MetadataMap::new();
+        metadata.set_xmp(Some(xmp.to_string()));
+        let mut encoded = Vec::new();
+        let mut encoder = JpegEncoder::new(&mut encoded);
+        encoder.set_text_metadata(&metadata).unwrap();
+        encoder.write_image(&[0; 3], 1, 1, ColorType::Rgb8).unwrap();
+        assert_eq!(super::super::mpf::xmp(&encoded), Some(xmp));
+
+        metadata.insert("Copyright", "Someone");
+        let mut encoder = JpegEncoder::new(Vec::new());
+        assert!(encoder.set_text_metadata(&metadata).is_err());
+    }
+
     #[test]
     fn jfif_header_density_check() {
         let mut buffer = Vec::new();
@@ -1065,6 +1380,42 @@ mod tests {
         assert_eq!(buf, expected)
     }
 
+    fn gradient() -> crate::RgbImage {
+        crate::RgbImage::from_fn(64, 48, |x, y| {
+            crate::Rgb([(x * 4) as u8, (y * 5) as u8, ((x ^ y) * 3) as u8])
+        })
+    }
+
+    #[test]
+    fn target_size_matches_plain_encoding() {
+        let image = gradient();
+        let mut sized = vec![];
+        let quality = JpegEncoder::new(&mut sized)
+            .encode_to_target_size(&image, 2000)
+            .unwrap();
+        assert!(sized.len() <= 2000);
+
+        let mut plain = vec![];
+        JpegEncoder::new_with_quality(&mut plain, quality)
+            .encode_image(&image)
+            .unwrap();
+        assert_eq!(sized, plain);
+
+        let mut larger = vec![];
+        JpegEncoder::new_with_quality(&mut larger, quality + 1)
+            .encode_image(&image)
+            .unwrap();
+        assert!(larger.len() > 2000);
+    }
+
+    #[test]
+    fn target_size_too_small() {
+        let mut encoded = vec![];
+        let result = JpegEncoder::new(&mut encoded).encode_to_target_size(&gradient(), 100);
+        assert!(matches!(result, Err(ImageError::Encoding(_))));
+        assert!(encoded.is_empty());
+    }
+
     #[cfg(feature = "benchmarks")]
     #[bench]
     fn bench_jpeg_encoder_new(b: &mut Bencher) {
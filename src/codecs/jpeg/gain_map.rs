@@ -0,0 +1,321 @@
+//! Gain maps, which store the difference of a HDR rendition to the SDR image it accompanies.
+//!
+//! Phones commonly save photos as JPEG files whose primary image is a regular SDR rendition,
+//! followed by a gain map in a secondary image of a multi-picture file. Decoders without gain map
+//! support only see the SDR image. This module reads gain maps that are described by the Adobe
+//! and Google `hdrgm` XMP metadata, as written by Ultra HDR and many camera apps.
+//!
+//! # Related Links
+//! * <https://developer.android.com/media/platform/hdr-image-format> - The Ultra HDR format
+//! * <https://helpx.adobe.com/camera-raw/using/gain-map.html> - The Adobe gain map specification
+
+use std::io::{Cursor, Read};
+
+use crate::buffer_::Rgb32FImage;
+use crate::color::transfer::srgb_to_linear;
+use crate::dynimage::DynamicImage;
+use crate::error::{DecodingError, ImageError, ImageResult};
+use crate::image::{GenericImageView, ImageFormat};
+use crate::imageops::{self, FilterType};
+
+use super::mpf::{self, MpType};
+use super::JpegDecoder;
+
+/// The metadata that describes how to apply a gain map.
+///
+/// Boosts are given as base 2 logarithms. All per channel values hold the same value three times
+/// if the gain map has only a single channel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GainMapMetadata {
+    /// The boost encoded by a gain map value of zero.
+    pub gain_map_min: [f32; 3],
+    /// The boost encoded by a gain map value of one.
+    pub gain_map_max: [f32; 3],
+    /// The gamma that was applied to the gain map values.
+    pub gamma: [f32; 3],
+    /// The offset added to the SDR values before applying the gain.
+    pub offset_sdr: [f32; 3],
+    /// The offset added to the HDR values before computing the gain.
+    pub offset_hdr: [f32; 3],
+    /// The display boost below which the gain map is not applied at all.
+    pub hdr_capacity_min: f32,
+    /// The display boost at which the gain map is applied fully.
+    pub hdr_capacity_max: f32,
+    /// Whether the primary image is the HDR rendition and the gain map produces the SDR one.
+    pub base_rendition_is_hdr: bool,
+}
+
+/// A gain map and its metadata.
+#[derive(Clone, Debug)]
+pub struct GainMap {
+    /// How to apply the gain map.
+    pub metadata: GainMapMetadata,
+    /// The gain map itself, usually grayscale and smaller than the primary image.
+    pub image: DynamicImage,
+}
+
+/// Reads the gain map of a JPEG file, if it has one.
+///
+/// The whole file is read into memory. Returns `Ok(None)` if the file has no multi-picture index
+/// or none of its secondary images is a gain map.
+///
+/// ```no_run
+/// # fn main() -> image::ImageResult<()> {
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use image::codecs::jpeg::read_gain_map;
+///
+/// let sdr = image::open("photo.jpg")?;
+/// if let Some(gain_map) = read_gain_map(BufReader::new(File::open("photo.jpg")?))? {
+///     // Render for a display that can show highlights at four times the SDR white.
+///     let hdr = gain_map.apply(&sdr, 4.0);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_gain_map<R: Read>(mut r: R) -> ImageResult<Option<GainMap>> {
+    let mut data = Vec::new();
+    r.read_to_end(&mut data)?;
+
+    let entries = match mpf::entries(&data) {
+        Some(entries) => entries,
+        None => return Ok(None),
+    };
+    for entry in entries {
+        if entry.kind == MpType::BaselinePrimary {
+            continue;
+        }
+        let image = entry.data(&data).ok_or_else(|| {
+            ImageError::Decoding(DecodingError::new(
+                ImageFormat::Jpeg.into(),
+                "multi-picture entry lies outside of the file",
+            ))
+        })?;
+        let metadata = match mpf::xmp(image).and_then(parse_metadata) {
+            Some(metadata) => metadata,
+            None => continue,
+        };
+        let image = DynamicImage::from_decoder(JpegDecoder::new(Cursor::new(image))?)?;
+        return Ok(Some(GainMap { metadata, image }));
+    }
+    Ok(None)
+}
+
+impl GainMap {
+    /// Reconstructs the rendition for a display with the given boost from the primary image.
+    ///
+    /// The `display_boost` is the ratio of the brightest white the display can show to the SDR
+    /// white. A boost of at least `2^hdr_capacity_max` yields the full HDR rendition, a boost of
+    /// one yields the SDR rendition. The result holds linear light relative to the SDR white, so
+    /// values above one are brighter than SDR white.
+    ///
+    /// The gain map always encodes the boost from the SDR to the HDR rendition. If the primary
+    /// image is the HDR rendition, the boost is inverted, and the roles of the offsets swapped.
+    pub fn apply(&self, base: &DynamicImage, display_boost: f32) -> Rgb32FImage {
+        let metadata = &self.metadata;
+        let capacity = metadata.hdr_capacity_max - metadata.hdr_capacity_min;
+        let mut weight = if capacity > 0.0 {
+            ((display_boost.max(1.0).log2() - metadata.hdr_capacity_min) / capacity).clamp(0.0, 1.0)
+        } else {
+            1.0
+        };
+        let (offset_base, offset_alternate) = if metadata.base_rendition_is_hdr {
+            weight = -(1.0 - weight);
+            (metadata.offset_hdr, metadata.offset_sdr)
+        } else {
+            (metadata.offset_sdr, metadata.offset_hdr)
+        };
+
+        let (width, height) = base.dimensions();
+        let mut gain = self.image.to_rgb32f();
+        if gain.dimensions() != (width, height) {
+            gain = imageops::resize(&gain, width, height, FilterType::Triangle);
+        }
+
+        let mut image = base.to_rgb32f();
+        for (pixel, gain) in image.pixels_mut().zip(gain.pixels()) {
+            for c in 0..3 {
+                let recovery = gain[c].clamp(0.0, 1.0).powf(1.0 / metadata.gamma[c]);
+                let log_boost = metadata.gain_map_min[c] * (1.0 - recovery)
+                    + metadata.gain_map_max[c] * recovery;
+                let linear = srgb_to_linear(f64::from(pixel[c])) as f32;
+                pixel[c] =
+                    (linear + offset_base[c]) * (log_boost * weight).exp2() - offset_alternate[c];
+            }
+        }
+        image
+    }
+}
+
+/// Parses the `hdrgm` metadata of a gain map, or `None` if the XMP does not describe one.
+fn parse_metadata(xmp: &str) -> Option<GainMapMetadata> {
+    xmp_value(xmp, "Version")?;
+    let channels = |name: &str, default: Option<f32>| -> Option<[f32; 3]> {
+        let values = match xmp_value(xmp, name) {
+            Some(values) => values
+                .iter()
+                .map(|value| value.trim().parse().ok())
+                .collect::<Option<Vec<f32>>>()?,
+            None => vec![default?],
+        };
+        match values[..] {
+            [value] => Some([value; 3]),
+            [r, g, b] => Some([r, g, b]),
+            _ => None,
+        }
+    };
+    let scalar = |name: &str, default: Option<f32>| -> Option<f32> {
+        match xmp_value(xmp, name) {
+            Some(values) => values.first()?.trim().parse().ok(),
+            None => default,
+        }
+    };
+
+    Some(GainMapMetadata {
+        gain_map_min: channels("GainMapMin", Some(0.0))?,
+        gain_map_max: channels("GainMapMax", None)?,
+        gamma: channels("Gamma", Some(1.0))?,
+        offset_sdr: channels("OffsetSDR", Some(1.0 / 64.0))?,
+        offset_hdr: channels("OffsetHDR", Some(1.0 / 64.0))?,
+        hdr_capacity_min: scalar("HDRCapacityMin", Some(0.0))?,
+        hdr_capacity_max: scalar("HDRCapacityMax", None)?,
+        base_rendition_is_hdr: xmp_value(xmp, "BaseRenditionIsHDR").map_or(false, |values| {
+            values.first().map(|value| value.trim()) == Some("True")
+        }),
+    })
+}
+
+/// Returns the values of a property in the `hdrgm` namespace.
+///
+/// The property is either an attribute, `hdrgm:Name="1.0"`, or an element holding a value or an
+/// ordered sequence of values, `<hdrgm:Name><rdf:Seq><rdf:li>1.0</rdf:li>...`.
+fn xmp_value<'a>(xmp: &'a str, name: &str) -> Option<Vec<&'a str>> {
+    let attribute = format!("hdrgm:{}=\"", name);
+    if let Some(start) = xmp.find(&attribute) {
+        let value = &xmp[start + attribute.len()..];
+        return Some(vec![&value[..value.find('"')?]]);
+    }
+
+    let open = format!("<hdrgm:{}>", name);
+    let close = format!("</hdrgm:{}>", name);
+    let start = xmp.find(&open)? + open.len();
+    let content = &xmp[start..start + xmp[start..].find(&close)?];
+    if !content.contains("<rdf:li") {
+        return Some(vec![content]);
+    }
+    Some(
+        content
+            .split("<rdf:li>")
+            .skip(1)
+            .filter_map(|item| item.split("</rdf:li>").next())
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mpf::test_utils::{multi_picture, with_xmp};
+    use super::*;
+    use crate::codecs::jpeg::JpegEncoder;
+    use crate::color::ColorType;
+    use std::io::Cursor;
+
+    fn jpeg(value: u8, width: u32, height: u32) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        JpegEncoder::new_with_quality(&mut encoded, 100)
+            .encode(
+                &vec![value; (width * height) as usize],
+                width,
+                height,
+                ColorType::L8,
+            )
+            .unwrap();
+        encoded
+    }
+
+    const XMP: &str = r#"<x:xmpmeta xmlns:x="adobe:ns:meta/">
+  <rdf:RDF xmlns:rdf="http://www.w3.org/1999/02/22-rdf-syntax-ns#">
+    <rdf:Description xmlns:hdrgm="http://ns.adobe.com/hdr-gain-map/1.0/"
+      hdrgm:Version="1.0"
+      hdrgm:GainMapMax="2.0"
+      hdrgm:HDRCapacityMax="2.0"/>
+  </rdf:RDF>
+</x:xmpmeta>"#;
+
+    #[test]
+    fn attribute_and_sequence_metadata() {
+        let metadata = parse_metadata(XMP).unwrap();
+        assert_eq!(metadata.gain_map_min, [0.0; 3]);
+        assert_eq!(metadata.gain_map_max, [2.0; 3]);
+        assert_eq!(metadata.offset_sdr, [1.0 / 64.0; 3]);
+        assert!(!metadata.base_rendition_is_hdr);
+
+        let xmp = r#"<rdf:Description hdrgm:Version="1.0" hdrgm:HDRCapacityMax="3">
+            <hdrgm:GainMapMax><rdf:Seq>
+              <rdf:li>1.5</rdf:li><rdf:li>2.5</rdf:li><rdf:li>3.5</rdf:li>
+            </rdf:Seq></hdrgm:GainMapMax>
+            <hdrgm:BaseRenditionIsHDR>True</hdrgm:BaseRenditionIsHDR>
+        </rdf:Description>"#;
+        let metadata = parse_metadata(xmp).unwrap();
+        assert_eq!(metadata.gain_map_max, [1.5, 2.5, 3.5]);
+        assert_eq!(metadata.hdr_capacity_max, 3.0);
+        assert!(metadata.base_rendition_is_hdr);
+
+        // The maximum boost is required.
+        assert!(parse_metadata(r#"hdrgm:Version="1.0" hdrgm:HDRCapacityMax="3""#).is_none());
+    }
+
+    #[test]
+    fn reconstruct_hdr() {
+        let base = jpeg(128, 16, 16);
+        let gain_map = with_xmp(&jpeg(255, 4, 4), XMP);
+        let file = multi_picture(&[(&base, 0x2003_0000), (&gain_map, 0)]);
+
+        let sdr = crate::load_from_memory(&file).unwrap();
+        let gain_map = read_gain_map(Cursor::new(&file)).unwrap().unwrap();
+        assert_eq!(gain_map.image.dimensions(), (4, 4));
+
+        let linear = srgb_to_linear(128.0 / 255.0) as f32;
+        let offset = 1.0 / 64.0;
+        let hdr = gain_map.apply(&sdr, 4.0);
+        let expected = (linear + offset) * 4.0 - offset;
+        assert!(hdr.pixels().all(|p| (p[0] - expected).abs() < 0.02));
+
+        // Displays without headroom show the SDR rendition.
+        let sdr = gain_map.apply(&sdr, 1.0);
+        assert!(sdr.pixels().all(|p| (p[1] - linear).abs() < 0.01));
+    }
+
+    #[test]
+    fn reconstruct_sdr_from_hdr_base() {
+        let metadata = GainMapMetadata {
+            base_rendition_is_hdr: true,
+            offset_hdr: [1.0 / 32.0; 3],
+            ..parse_metadata(XMP).unwrap()
+        };
+        let image =
+            DynamicImage::ImageLuma8(crate::GrayImage::from_pixel(4, 4, crate::Luma([255])));
+        let gain_map = GainMap { metadata, image };
+        let hdr = DynamicImage::ImageRgb8(crate::RgbImage::from_pixel(8, 8, crate::Rgb([200; 3])));
+        let linear = srgb_to_linear(200.0 / 255.0) as f32;
+
+        // Displays with enough headroom show the HDR base, apart from the offsets.
+        let full = gain_map.apply(&hdr, 4.0);
+        let expected = linear + 1.0 / 32.0 - 1.0 / 64.0;
+        assert!(full.pixels().all(|p| (p[0] - expected).abs() < 1e-5));
+
+        // Otherwise the boost is removed, from the HDR offset to the SDR one.
+        let sdr = gain_map.apply(&hdr, 1.0);
+        let expected = (linear + 1.0 / 32.0) / 4.0 - 1.0 / 64.0;
+        assert!(sdr.pixels().all(|p| (p[2] - expected).abs() < 1e-5));
+        let half = gain_map.apply(&hdr, 2.0);
+        let expected = (linear + 1.0 / 32.0) / 2.0 - 1.0 / 64.0;
+        assert!(half.pixels().all(|p| (p[1] - expected).abs() < 1e-5));
+    }
+
+    #[test]
+    fn plain_jpeg_has_no_gain_map() {
+        let base = jpeg(0, 8, 8);
+        assert!(read_gain_map(Cursor::new(&base)).unwrap().is_none());
+    }
+}
@@ -0,0 +1,794 @@
+//! Lossless transformations of baseline JPEG images.
+//!
+//! The entropy coded data is decoded into the quantized DCT coefficients of each block, which are
+//! rearranged and coded again. The coefficients are never dequantized, so the pixels of the
+//! result are exactly those of the source.
+use std::io::{Read, Write};
+
+use crate::error::{
+    DecodingError, ImageError, ImageResult, ParameterError, ParameterErrorKind, UnsupportedError,
+    UnsupportedErrorKind,
+};
+use crate::image::ImageFormat;
+use crate::math::Rect;
+
+use super::encoder::{
+    encode_coefficient, BitWriter, STD_CHROMA_AC_CODE_LENGTHS, STD_CHROMA_AC_VALUES,
+    STD_CHROMA_DC_CODE_LENGTHS, STD_CHROMA_DC_VALUES, STD_LUMA_AC_CODE_LENGTHS, STD_LUMA_AC_VALUES,
+    STD_LUMA_DC_CODE_LENGTHS, STD_LUMA_DC_VALUES, UNZIGZAG,
+};
+use super::entropy::build_huff_lut_const;
+use super::mpf::{APP2, MPF_IDENTIFIER};
+use super::segments::{JpegSegment, JpegSegmentReader, JpegSegmentWriter};
+
+const SOF0: u8 = 0xc0;
+const SOF1: u8 = 0xc1;
+const DHT: u8 = 0xc4;
+const EOI: u8 = 0xd9;
+const SOS: u8 = 0xda;
+const DRI: u8 = 0xdd;
+
+/// Crop a baseline JPEG image without decoding it to pixels.
+///
+/// The part of the image read from `r` within `rect` is written to `w`. Its coded blocks are
+/// copied bit for bit, so it holds exactly the same data as that part of the source, and decodes
+/// to the same pixels but for the rounding of decoders that smooth subsampled colors across the
+/// edges of the crop. The crop must start at a multiple of the
+/// size of the minimum coded unit, the blocks of pixels the image is coded in. That is 8 pixels
+/// for images without chroma subsampling, and 16 pixels in the subsampled directions of the
+/// common 4:2:0 and 4:2:2 images. Its width and height are not restricted, and the crop is
+/// decoded only as far as it reaches down the image.
+///
+/// The metadata of the source is copied, except for the index of a multi-picture file, whose
+/// further images are not copied. The Huffman tables are kept if they can code the crop, and
+/// the standard tables are used otherwise. Restart markers are not written.
+///
+/// Returns an `ImageError::Parameter` if `rect` is empty, does not lie within the image or does
+/// not start on the boundary of a minimum coded unit, and an `ImageError::Unsupported` for
+/// progressive, arithmetic coded and 12-bit images.
+///
+/// # Examples
+///
+/// Cut the second tile of 256 by 256 pixels out of the top row of an image.
+///
+/// ```no_run
+/// # fn main() -> image::ImageResult<()> {
+/// use image::codecs::jpeg::crop_lossless;
+/// use image::math::Rect;
+///
+/// let input = std::io::BufReader::new(std::fs::File::open("map.jpg")?);
+/// let output = std::fs::File::create("tile.jpg")?;
+/// let tile = Rect {
+///     x: 256,
+///     y: 0,
+///     width: 256,
+///     height: 256,
+/// };
+/// crop_lossless(input, output, tile)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn crop_lossless<R: Read, W: Write>(r: R, w: W, rect: Rect) -> ImageResult<()> {
+    let mut reader = JpegSegmentReader::new(r)?;
+    let mut segments = Vec::new();
+    for segment in reader.by_ref() {
+        segments.push(segment?);
+    }
+    let mut data = Vec::new();
+    reader.into_inner().read_to_end(&mut data)?;
+
+    let image = CodedImage::parse(&segments)?;
+    let crop = image.mcu_crop(rect)?;
+    let blocks = image.decode_blocks(&data, &crop)?;
+    let tables = image.output_tables(&crop, &blocks);
+
+    let mut writer = JpegSegmentWriter::new(w)?;
+    for segment in &segments {
+        match segment.marker {
+            SOF0 | SOF1 => {
+                let mut frame = segment.clone();
+                frame.data[1..3].copy_from_slice(&(rect.height as u16).to_be_bytes());
+                frame.data[3..5].copy_from_slice(&(rect.width as u16).to_be_bytes());
+                writer.write_segment(&frame)?;
+            }
+            // The tables are written in front of the scan, restart markers are not.
+            DHT | DRI => {}
+            APP2 if segment.has_identifier(MPF_IDENTIFIER) => {}
+            SOS => {
+                writer.write_segment(&huffman_segment(&tables))?;
+                writer.write_segment(segment)?;
+            }
+            _ => writer.write_segment(segment)?,
+        }
+    }
+
+    let mut bits = BitWriter::new(writer.get_mut());
+    let luts: Vec<_> = tables
+        .iter()
+        .map(|table| table.as_ref().map(HuffmanTable::lut))
+        .collect();
+    let mut predictions = vec![0; image.components.len()];
+    for (c, component, index) in image.crop_blocks(&crop) {
+        let dc = luts[component.dc_table].as_ref().unwrap();
+        let ac = luts[4 + component.ac_table].as_ref().unwrap();
+        predictions[c] = bits.write_block(&blocks[c][index], predictions[c], dc, ac)?;
+    }
+    bits.pad_byte()?;
+    bits.write_marker(EOI)?;
+    writer.into_inner().flush()?;
+    Ok(())
+}
+
+fn decoding_error(message: &'static str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormat::Jpeg.into(), message))
+}
+
+fn unsupported(feature: &str) -> ImageError {
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        ImageFormat::Jpeg.into(),
+        UnsupportedErrorKind::GenericFeature(feature.to_string()),
+    ))
+}
+
+/// A Huffman table as stored in a `DHT` segment.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct HuffmanTable {
+    /// The number of codes of each length from 1 to 16 bits.
+    bits: [u8; 16],
+    /// The symbols in the order of their codes.
+    values: Vec<u8>,
+}
+
+impl HuffmanTable {
+    fn new(bits: &[u8; 16], values: &[u8]) -> Self {
+        HuffmanTable {
+            bits: *bits,
+            values: values.to_vec(),
+        }
+    }
+
+    /// The length and code of each symbol, with a length over 16 for missing symbols.
+    fn lut(&self) -> [(u8, u16); 256] {
+        build_huff_lut_const(&self.bits, &self.values)
+    }
+
+    /// Whether the table has a code for each of the symbols.
+    fn codes(&self, symbols: &[bool; 256]) -> bool {
+        let lut = self.lut();
+        symbols
+            .iter()
+            .zip(lut.iter())
+            .all(|(&used, &(size, _))| !used || size <= 16)
+    }
+}
+
+/// Decodes the symbols of a Huffman table, as in section F.2.2.3 of the specification.
+struct HuffmanDecoder<'t> {
+    table: &'t HuffmanTable,
+    /// The first code of each length.
+    min_code: [i32; 16],
+    /// The last code of each length, or -1 if there are none.
+    max_code: [i32; 16],
+    /// The index of the symbol of the first code of each length.
+    first_value: [usize; 16],
+}
+
+impl<'t> HuffmanDecoder<'t> {
+    fn new(table: &'t HuffmanTable) -> Self {
+        let mut decoder = HuffmanDecoder {
+            table,
+            min_code: [0; 16],
+            max_code: [-1; 16],
+            first_value: [0; 16],
+        };
+        let (mut code, mut index) = (0, 0);
+        for (length, &count) in table.bits.iter().enumerate() {
+            decoder.min_code[length] = code;
+            decoder.first_value[length] = index;
+            code += i32::from(count);
+            index += usize::from(count);
+            if count > 0 {
+                decoder.max_code[length] = code - 1;
+            }
+            code <<= 1;
+        }
+        decoder
+    }
+
+    fn decode(&self, bits: &mut BitReader<'_>) -> ImageResult<u8> {
+        let mut code = 0;
+        for length in 0..16 {
+            code = (code << 1) | bits.bit() as i32;
+            if code <= self.max_code[length] {
+                let index = self.first_value[length] + (code - self.min_code[length]) as usize;
+                return Ok(self.table.values[index]);
+            }
+        }
+        Err(decoding_error("invalid Huffman code"))
+    }
+}
+
+/// Reads the bits of entropy coded data, skipping the stuffed zero bytes.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    bits: u32,
+    count: u8,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        BitReader {
+            data,
+            position: 0,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    fn bit(&mut self) -> u32 {
+        if self.count == 0 {
+            let byte = match self.data.get(self.position..self.position + 2) {
+                Some([0xff, 0x00]) => {
+                    self.position += 2;
+                    0xff
+                }
+                // A marker ends the data, which is padded with zeros like a truncated file.
+                Some([0xff, _]) => 0,
+                _ => match self.data.get(self.position) {
+                    Some(&byte) => {
+                        self.position += 1;
+                        byte
+                    }
+                    None => 0,
+                },
+            };
+            self.bits = u32::from(byte);
+            self.count = 8;
+        }
+        self.count -= 1;
+        (self.bits >> self.count) & 1
+    }
+
+    /// Read a coefficient of `size` bits, as in section F.2.2.1 of the specification.
+    fn coefficient(&mut self, size: u8) -> ImageResult<i32> {
+        if size > 11 {
+            return Err(decoding_error("invalid coefficient size"));
+        }
+        let value = (0..size).fold(0, |value, _| (value << 1) | self.bit() as i32);
+        if size > 0 && value < 1 << (size - 1) {
+            Ok(value - (1 << size) + 1)
+        } else {
+            Ok(value)
+        }
+    }
+
+    /// Skip the rest of the current byte and the restart marker that follows.
+    fn restart(&mut self) -> ImageResult<()> {
+        self.count = 0;
+        match self.data.get(self.position..self.position + 2) {
+            Some([0xff, 0xd0..=0xd7]) => {
+                self.position += 2;
+                Ok(())
+            }
+            _ => Err(decoding_error("missing JPEG restart marker")),
+        }
+    }
+}
+
+/// A component of the frame, with the tables selected for it by the scan.
+#[derive(Clone, Copy, Debug)]
+struct Component {
+    h: usize,
+    v: usize,
+    dc_table: usize,
+    ac_table: usize,
+}
+
+/// The range of minimum coded units of a crop.
+struct McuCrop {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
+
+/// The headers of a baseline JPEG image with a single scan.
+struct CodedImage {
+    width: usize,
+    height: usize,
+    components: Vec<Component>,
+    /// The DC tables followed by the AC tables, by their destination.
+    tables: Vec<Option<HuffmanTable>>,
+    restart_interval: usize,
+}
+
+impl CodedImage {
+    fn parse(segments: &[JpegSegment]) -> ImageResult<Self> {
+        let mut frame = None;
+        let mut tables = vec![None; 8];
+        let mut restart_interval = 0;
+        for segment in segments {
+            let data = &segment.data[..];
+            match segment.marker {
+                SOF0 | SOF1 => frame = Some(data),
+                0xc2 | 0xc6 | 0xca | 0xce => return Err(unsupported("progressive JPEG images")),
+                0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => {
+                    return Err(unsupported("lossless and arithmetic coded JPEG images"))
+                }
+                DHT => {
+                    let mut data = data;
+                    while let [class_destination, rest @ ..] = data {
+                        let (class, destination) = (class_destination >> 4, class_destination & 15);
+                        if class > 1 || destination > 3 || rest.len() < 16 {
+                            return Err(decoding_error("invalid JPEG Huffman table"));
+                        }
+                        let mut bits = [0; 16];
+                        bits.copy_from_slice(&rest[..16]);
+                        let count = bits.iter().map(|&count| usize::from(count)).sum::<usize>();
+                        let values = rest
+                            .get(16..16 + count)
+                            .filter(|_| count < 256)
+                            .ok_or_else(|| decoding_error("invalid JPEG Huffman table"))?;
+                        tables[usize::from(class * 4 + destination)] =
+                            Some(HuffmanTable::new(&bits, values));
+                        data = &rest[16 + count..];
+                    }
+                }
+                DRI => {
+                    let interval = data
+                        .get(..2)
+                        .ok_or_else(|| decoding_error("invalid JPEG restart interval"))?;
+                    restart_interval = usize::from(u16::from_be_bytes([interval[0], interval[1]]));
+                }
+                SOS => {
+                    let frame = frame.ok_or_else(|| decoding_error("JPEG scan without frame"))?;
+                    return CodedImage::from_headers(frame, data, tables, restart_interval);
+                }
+                _ => {}
+            }
+        }
+        Err(decoding_error("JPEG image without scan"))
+    }
+
+    fn from_headers(
+        frame: &[u8],
+        scan: &[u8],
+        tables: Vec<Option<HuffmanTable>>,
+        restart_interval: usize,
+    ) -> ImageResult<Self> {
+        let (precision, height, width, count) = match *frame {
+            [precision, h0, h1, w0, w1, count, ..] => (
+                precision,
+                usize::from(u16::from_be_bytes([h0, h1])),
+                usize::from(u16::from_be_bytes([w0, w1])),
+                usize::from(count),
+            ),
+            _ => return Err(decoding_error("invalid JPEG frame header")),
+        };
+        if precision != 8 {
+            return Err(unsupported("JPEG images with 12-bit samples"));
+        }
+        if height == 0 {
+            return Err(unsupported("JPEG images whose height follows the scan"));
+        }
+        if width == 0 || count == 0 || frame.len() < 6 + 3 * count {
+            return Err(decoding_error("invalid JPEG frame header"));
+        }
+        if scan.len() != 4 + 2 * count || usize::from(scan[0]) != count {
+            return Err(unsupported("JPEG images with several scans"));
+        }
+        if scan[scan.len() - 3..] != [0, 63, 0] {
+            return Err(decoding_error("invalid JPEG scan header"));
+        }
+
+        let mut components = Vec::with_capacity(count);
+        for (i, sampling) in frame[6..6 + 3 * count].chunks(3).enumerate() {
+            let selection = &scan[1 + 2 * i..3 + 2 * i];
+            let component = Component {
+                h: usize::from(sampling[1] >> 4),
+                v: usize::from(sampling[1] & 15),
+                dc_table: usize::from(selection[1] >> 4),
+                ac_table: usize::from(selection[1] & 15),
+            };
+            let sampling_valid = (1..=4).contains(&component.h) && (1..=4).contains(&component.v);
+            let tables_defined = component.dc_table < 4
+                && component.ac_table < 4
+                && tables[component.dc_table].is_some()
+                && tables[4 + component.ac_table].is_some();
+            if selection[0] != sampling[0] || !sampling_valid || !tables_defined {
+                return Err(decoding_error("invalid JPEG scan header"));
+            }
+            components.push(component);
+        }
+
+        Ok(CodedImage {
+            width,
+            height,
+            components,
+            tables,
+            restart_interval,
+        })
+    }
+
+    /// The size of a minimum coded unit in pixels.
+    fn mcu_size(&self) -> (usize, usize) {
+        // A scan of a single component codes each block on its own.
+        if self.components.len() == 1 {
+            return (8, 8);
+        }
+        let h = self.components.iter().map(|c| c.h).max().unwrap();
+        let v = self.components.iter().map(|c| c.v).max().unwrap();
+        (8 * h, 8 * v)
+    }
+
+    /// The number of blocks of a component in each minimum coded unit.
+    fn blocks_per_mcu(&self, component: &Component) -> (usize, usize) {
+        if self.components.len() == 1 {
+            (1, 1)
+        } else {
+            (component.h, component.v)
+        }
+    }
+
+    fn mcu_crop(&self, rect: Rect) -> ImageResult<McuCrop> {
+        let invalid = |message: String| {
+            ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                message,
+            )))
+        };
+        let (x, y) = (rect.x as usize, rect.y as usize);
+        let (width, height) = (rect.width as usize, rect.height as usize);
+        if width == 0 || height == 0 || x + width > self.width || y + height > self.height {
+            return Err(invalid(format!(
+                "the crop does not lie within the {}x{} image",
+                self.width, self.height
+            )));
+        }
+        let (mcu_width, mcu_height) = self.mcu_size();
+        if x % mcu_width != 0 || y % mcu_height != 0 {
+            return Err(invalid(format!(
+                "the crop does not start on the {}x{} pixel grid of the JPEG image",
+                mcu_width, mcu_height
+            )));
+        }
+        Ok(McuCrop {
+            x: x / mcu_width,
+            y: y / mcu_height,
+            width: (width + mcu_width - 1) / mcu_width,
+            height: (height + mcu_height - 1) / mcu_height,
+        })
+    }
+
+    /// Decode the blocks of each component within the crop, in natural order.
+    fn decode_blocks(&self, data: &[u8], crop: &McuCrop) -> ImageResult<Vec<Vec<[i32; 64]>>> {
+        let (mcu_width, _) = self.mcu_size();
+        let mcus_x = (self.width + mcu_width - 1) / mcu_width;
+        let decoders: Vec<_> = self
+            .tables
+            .iter()
+            .map(|table| table.as_ref().map(HuffmanDecoder::new))
+            .collect();
+        let mut blocks: Vec<_> = self
+            .components
+            .iter()
+            .map(|component| {
+                let (h, v) = self.blocks_per_mcu(component);
+                vec![[0; 64]; crop.width * h * crop.height * v]
+            })
+            .collect();
+
+        let mut bits = BitReader::new(data);
+        let mut predictions = vec![0; self.components.len()];
+        let mut block = [0; 64];
+        // Units below the crop need not be decoded.
+        for mcu_y in 0..crop.y + crop.height {
+            for mcu_x in 0..mcus_x {
+                let index = mcu_y * mcus_x + mcu_x;
+                if self.restart_interval > 0 && index > 0 && index % self.restart_interval == 0 {
+                    bits.restart()?;
+                    predictions
+                        .iter_mut()
+                        .for_each(|prediction| *prediction = 0);
+                }
+                let within = (crop.x..crop.x + crop.width).contains(&mcu_x) && mcu_y >= crop.y;
+                for (c, component) in self.components.iter().enumerate() {
+                    let dc = decoders[component.dc_table].as_ref().unwrap();
+                    let ac = decoders[4 + component.ac_table].as_ref().unwrap();
+                    let (h, v) = self.blocks_per_mcu(component);
+                    for j in 0..v {
+                        for i in 0..h {
+                            decode_block(&mut bits, dc, ac, &mut predictions[c], &mut block)?;
+                            if within {
+                                let row = (mcu_y - crop.y) * v + j;
+                                let column = (mcu_x - crop.x) * h + i;
+                                blocks[c][row * crop.width * h + column] = block;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// The component, its index and the index of its block within the crop, in coding order.
+    fn crop_blocks<'s>(
+        &'s self,
+        crop: &'s McuCrop,
+    ) -> impl Iterator<Item = (usize, &'s Component, usize)> + 's {
+        (0..crop.height * crop.width).flat_map(move |mcu| {
+            let (mcu_x, mcu_y) = (mcu % crop.width, mcu / crop.width);
+            self.components
+                .iter()
+                .enumerate()
+                .flat_map(move |(c, component)| {
+                    let (h, v) = self.blocks_per_mcu(component);
+                    (0..h * v).map(move |block| {
+                        let row = mcu_y * v + block / h;
+                        let column = mcu_x * h + block % h;
+                        (c, component, row * crop.width * h + column)
+                    })
+                })
+        })
+    }
+
+    /// The Huffman tables to code the crop with, which are those of the image if they have a
+    /// code for each symbol the crop needs.
+    fn output_tables(
+        &self,
+        crop: &McuCrop,
+        blocks: &[Vec<[i32; 64]>],
+    ) -> Vec<Option<HuffmanTable>> {
+        let mut symbols = vec![[false; 256]; 8];
+        let mut predictions = vec![0; self.components.len()];
+        for (c, component, index) in self.crop_blocks(crop) {
+            let block = &blocks[c][index];
+            let (dc, ac) = symbols.split_at_mut(4);
+            block_symbols(
+                block,
+                predictions[c],
+                &mut dc[component.dc_table],
+                &mut ac[component.ac_table],
+            );
+            predictions[c] = block[0];
+        }
+
+        let mut tables = vec![None; 8];
+        for component in &self.components {
+            for &table in &[component.dc_table, 4 + component.ac_table] {
+                let source = self.tables[table].as_ref().unwrap();
+                tables[table] = Some(if source.codes(&symbols[table]) {
+                    source.clone()
+                } else {
+                    standard_table(table)
+                });
+            }
+        }
+        tables
+    }
+}
+
+/// Decode the coefficients of a block, as in section F.2.2 of the specification.
+fn decode_block(
+    bits: &mut BitReader<'_>,
+    dc: &HuffmanDecoder<'_>,
+    ac: &HuffmanDecoder<'_>,
+    prediction: &mut i32,
+    block: &mut [i32; 64],
+) -> ImageResult<()> {
+    *block = [0; 64];
+    let size = dc.decode(bits)?;
+    *prediction += bits.coefficient(size)?;
+    block[0] = *prediction;
+
+    let mut k = 1;
+    while k < 64 {
+        let symbol = ac.decode(bits)?;
+        let (run, size) = (usize::from(symbol >> 4), symbol & 15);
+        if size == 0 {
+            if run != 15 {
+                break;
+            }
+            k += 16;
+            continue;
+        }
+        k += run;
+        if k > 63 {
+            return Err(decoding_error("invalid JPEG coefficient index"));
+        }
+        block[usize::from(UNZIGZAG[k])] = bits.coefficient(size)?;
+        k += 1;
+    }
+    Ok(())
+}
+
+/// Mark the DC and AC symbols that coding a block emits, as `BitWriter::write_block` does.
+fn block_symbols(block: &[i32; 64], prediction: i32, dc: &mut [bool; 256], ac: &mut [bool; 256]) {
+    dc[usize::from(encode_coefficient(block[0] - prediction).0)] = true;
+    let mut zero_run = 0;
+    for &k in &UNZIGZAG[1..] {
+        if block[usize::from(k)] == 0 {
+            zero_run += 1;
+        } else {
+            if zero_run > 15 {
+                ac[0xf0] = true;
+                zero_run %= 16;
+            }
+            let (size, _) = encode_coefficient(block[usize::from(k)]);
+            ac[usize::from((zero_run << 4) | size)] = true;
+            zero_run = 0;
+        }
+    }
+    if block[usize::from(UNZIGZAG[63])] == 0 {
+        ac[0x00] = true;
+    }
+}
+
+/// The standard table for a table index, which codes all symbols of baseline images.
+fn standard_table(table: usize) -> HuffmanTable {
+    match table {
+        0 => HuffmanTable::new(&STD_LUMA_DC_CODE_LENGTHS, &STD_LUMA_DC_VALUES),
+        1..=3 => HuffmanTable::new(&STD_CHROMA_DC_CODE_LENGTHS, &STD_CHROMA_DC_VALUES),
+        4 => HuffmanTable::new(&STD_LUMA_AC_CODE_LENGTHS, &STD_LUMA_AC_VALUES),
+        _ => HuffmanTable::new(&STD_CHROMA_AC_CODE_LENGTHS, &STD_CHROMA_AC_VALUES),
+    }
+}
+
+/// A `DHT` segment holding the given tables.
+fn huffman_segment(tables: &[Option<HuffmanTable>]) -> JpegSegment {
+    let mut data = Vec::new();
+    for (index, table) in tables.iter().enumerate() {
+        if let Some(table) = table {
+            data.push((((index / 4) << 4) | (index % 4)) as u8);
+            data.extend_from_slice(&table.bits);
+            data.extend_from_slice(&table.values);
+        }
+    }
+    JpegSegment::new(DHT, data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codecs::jpeg::JpegEncoder;
+    use crate::image::ImageEncoder;
+    use crate::{ColorType, DynamicImage, GenericImageView};
+
+    fn decode(data: &[u8]) -> DynamicImage {
+        crate::load_from_memory_with_format(data, ImageFormat::Jpeg).unwrap()
+    }
+
+    fn crop(data: &[u8], x: u32, y: u32, width: u32, height: u32) -> ImageResult<Vec<u8>> {
+        let mut cropped = Vec::new();
+        let rect = Rect {
+            x,
+            y,
+            width,
+            height,
+        };
+        crop_lossless(data, &mut cropped, rect)?;
+        Ok(cropped)
+    }
+
+    fn assert_crop_matches(original: &[u8], x: u32, y: u32, width: u32, height: u32) {
+        let cropped = decode(&crop(original, x, y, width, height).unwrap());
+        let expected = decode(original).crop_imm(x, y, width, height);
+        assert_eq!(cropped.dimensions(), (width, height));
+        assert_eq!(cropped.as_bytes(), expected.as_bytes());
+    }
+
+    #[test]
+    fn crops_full_resolution_images() {
+        let (width, height) = (37, 29);
+        let pixels: Vec<u8> = (0..width * height * 3)
+            .map(|i| (i * 7919 % 251) as u8)
+            .collect();
+        let mut original = Vec::new();
+        JpegEncoder::new_with_quality(&mut original, 90)
+            .write_image(&pixels, width, height, ColorType::Rgb8)
+            .unwrap();
+
+        // Decoders convert the colors of the start of a row differently from its end, so the
+        // crops reach the right edge of the image for their pixels to be compared.
+        assert_crop_matches(&original, 16, 16, 21, 9);
+        assert_crop_matches(&original, 0, 0, 37, 29);
+        assert_crop_matches(&original, 32, 24, 5, 5);
+
+        let mut gray = Vec::new();
+        JpegEncoder::new(&mut gray)
+            .write_image(
+                &pixels[..(width * height) as usize],
+                width,
+                height,
+                ColorType::L8,
+            )
+            .unwrap();
+        assert_crop_matches(&gray, 16, 8, 21, 13);
+    }
+
+    /// Code a 4:2:0 image of 40 by 24 pixels with a restart marker after each unit, whose luma
+    /// blocks are flat and distinct, and whose chroma is neutral.
+    fn subsampled_with_restarts() -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut writer = JpegSegmentWriter::new(&mut data).unwrap();
+        let mut quantization = vec![0];
+        quantization.extend_from_slice(&[1; 64]);
+        writer
+            .write_segment(&JpegSegment::new(0xdb, quantization))
+            .unwrap();
+        let frame = vec![8, 0, 24, 0, 40, 3, 1, 0x22, 0, 2, 0x11, 0, 3, 0x11, 0];
+        writer
+            .write_segment(&JpegSegment::new(SOF0, frame))
+            .unwrap();
+        let mut tables = vec![None; 8];
+        tables[0] = Some(standard_table(0));
+        tables[4] = Some(standard_table(4));
+        writer.write_segment(&huffman_segment(&tables)).unwrap();
+        writer
+            .write_segment(&JpegSegment::new(DRI, vec![0, 1]))
+            .unwrap();
+        let scan = vec![3, 1, 0, 2, 0, 3, 0, 0, 63, 0];
+        writer.write_segment(&JpegSegment::new(SOS, scan)).unwrap();
+
+        let (dc, ac) = (standard_table(0).lut(), standard_table(4).lut());
+        let mut bits = BitWriter::new(writer.get_mut());
+        for mcu in 0..6 {
+            if mcu > 0 {
+                bits.pad_byte().unwrap();
+                bits.write_marker(0xd0 + (mcu - 1) % 8).unwrap();
+            }
+            let mut prediction = 0;
+            for block in 0..4 {
+                let mut coefficients = [0; 64];
+                coefficients[0] = i32::from(mcu) * 80 + block * 20 - 300;
+                prediction = bits
+                    .write_block(&coefficients, prediction, &dc, &ac)
+                    .unwrap();
+            }
+            for _ in 0..2 {
+                bits.write_block(&[0; 64], 0, &dc, &ac).unwrap();
+            }
+        }
+        bits.pad_byte().unwrap();
+        bits.write_marker(EOI).unwrap();
+        data
+    }
+
+    #[test]
+    fn crops_subsampled_images_with_restarts() {
+        let original = subsampled_with_restarts();
+        assert_crop_matches(&original, 16, 0, 24, 24);
+        assert_crop_matches(&original, 0, 16, 19, 8);
+        assert!(matches!(
+            crop(&original, 8, 0, 8, 8),
+            Err(ImageError::Parameter(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_invalid_crops() {
+        let mut original = Vec::new();
+        JpegEncoder::new(&mut original)
+            .write_image(&[0x80; 16 * 16], 16, 16, ColorType::L8)
+            .unwrap();
+        assert!(matches!(
+            crop(&original, 0, 0, 17, 8),
+            Err(ImageError::Parameter(_))
+        ));
+        assert!(matches!(
+            crop(&original, 8, 8, 0, 8),
+            Err(ImageError::Parameter(_))
+        ));
+        assert!(matches!(
+            crop(&original, 4, 0, 8, 8),
+            Err(ImageError::Parameter(_))
+        ));
+
+        let progressive = std::fs::read("tests/images/jpg/progressive/cat.jpg").unwrap();
+        assert!(matches!(
+            crop(&progressive, 0, 0, 16, 16),
+            Err(ImageError::Unsupported(_))
+        ));
+    }
+}
@@ -7,10 +7,29 @@
 //! * <http://www.w3.org/Graphics/JPEG/itu-t81.pdf> - The JPEG specification
 //!
 
+pub use self::auxiliary::{read_auxiliary_images, AuxiliaryImage, AuxiliaryImageKind};
 pub use self::decoder::JpegDecoder;
 pub use self::encoder::{JpegEncoder, PixelDensity, PixelDensityUnit};
+pub use self::gain_map::{read_gain_map, GainMap, GainMapMetadata};
+pub use self::lossless::crop_lossless;
+pub use self::panorama::{read_panorama_metadata, PanoramaMetadata, PanoramaProjection};
+pub use self::progressive::ProgressiveJpegDecoder;
+pub use self::segments::{
+    edit_segments, JpegSegment, JpegSegmentReader, JpegSegmentWriter, SegmentEdit,
+};
+pub use self::stereo::{JpsDecoder, MpoDecoder};
 
+pub(crate) use self::segments::strip_with_preset;
+
+mod auxiliary;
 mod decoder;
 mod encoder;
 mod entropy;
+mod gain_map;
+mod lossless;
+mod mpf;
+mod panorama;
+mod progressive;
+mod segments;
+mod stereo;
 mod transform;
@@ -0,0 +1,222 @@
+//! Parsing of the CIPA DC-007 Multi-Picture Format.
+//!
+//! A multi-picture file is a sequence of complete JPEG images. The first one carries an APP2
+//! segment with an index of all images, which is used to locate the secondary images for depth
+//! maps, stereo pairs and gain maps.
+//!
+//! # Related Links
+//! * <https://www.cipa.jp/std/documents/e/DC-X007-KEY_E.pdf> - The Multi-Picture Format
+
+pub(crate) use crate::metadata::XMP_IDENTIFIER;
+
+use super::segments::segments;
+
+/// The marker of the APP1 segment, which holds EXIF and XMP metadata.
+pub(crate) const APP1: u8 = 0xe1;
+/// The marker of the APP2 segment, which holds ICC profiles and the Multi-Picture index.
+pub(crate) const APP2: u8 = 0xe2;
+
+/// The identifier at the start of an APP2 segment holding a part of an ICC profile.
+pub(crate) const ICC_IDENTIFIER: &[u8] = b"ICC_PROFILE\0";
+
+/// The identifier at the start of the APP2 segment holding the Multi-Picture index.
+pub(crate) const MPF_IDENTIFIER: &[u8] = b"MPF\0";
+/// The tag of the MP Entry in the MP Index IFD.
+const MP_ENTRY: u16 = 0xb002;
+
+/// Returns the XMP packet of a JPEG file, if it has one.
+pub(crate) fn xmp(data: &[u8]) -> Option<&str> {
+    segments(data)
+        .filter(|&(marker, _, _)| marker == APP1)
+        .find_map(|(_, _, payload)| payload.strip_prefix(XMP_IDENTIFIER))
+        .and_then(|xmp| std::str::from_utf8(xmp).ok())
+}
+
+/// The type of an image in a multi-picture file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum MpType {
+    /// The primary image, which is also what decoders without multi-picture support show.
+    BaselinePrimary,
+    /// A large thumbnail of the primary image.
+    LargeThumbnail,
+    /// One view of a stereoscopic image.
+    Disparity,
+    /// One view of a series taken from different angles.
+    MultiAngle,
+    /// An image of a type not covered by the specification, such as a gain map.
+    Undefined,
+}
+
+/// An image listed in the Multi-Picture index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct MpEntry {
+    pub(crate) kind: MpType,
+    /// The offset of the image from the start of the file.
+    pub(crate) offset: usize,
+    /// The length of the image in bytes.
+    pub(crate) size: usize,
+}
+
+impl MpEntry {
+    /// Returns the bytes of this image within the whole file.
+    pub(crate) fn data<'a>(&self, file: &'a [u8]) -> Option<&'a [u8]> {
+        file.get(self.offset..self.offset.checked_add(self.size)?)
+    }
+}
+
+/// Returns the images listed in the Multi-Picture index of a JPEG file.
+///
+/// Returns `None` if the file has no valid index.
+pub(crate) fn entries(data: &[u8]) -> Option<Vec<MpEntry>> {
+    let start = segments(data)
+        .filter(|&(marker, _, _)| marker == APP2)
+        .find(|&(_, _, payload)| payload.starts_with(MPF_IDENTIFIER))
+        .map(|(_, offset, _)| offset)?;
+    // Offsets in the index, except for the primary image, are relative to its TIFF header.
+    let base = start + MPF_IDENTIFIER.len();
+    let tiff = &data[base..];
+
+    let big_endian = match tiff.get(..4)? {
+        b"II*\0" => false,
+        b"MM\0*" => true,
+        _ => return None,
+    };
+    let u16_at = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 2)?;
+        let bytes = [bytes[0], bytes[1]];
+        Some(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    };
+    let u32_at = |offset: usize| {
+        let bytes = tiff.get(offset..offset + 4)?;
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    };
+
+    let ifd = u32_at(4)? as usize;
+    let count = usize::from(u16_at(ifd)?);
+    let (length, offset) = (0..count)
+        .map(|i| ifd + 2 + 12 * i)
+        .find(|&entry| u16_at(entry) == Some(MP_ENTRY))
+        .and_then(|entry| Some((u32_at(entry + 4)? as usize, u32_at(entry + 8)? as usize)))?;
+
+    (0..length / 16)
+        .map(|i| {
+            let entry = offset + 16 * i;
+            let attribute = u32_at(entry)?;
+            let size = u32_at(entry + 4)? as usize;
+            let offset = match u32_at(entry + 8)? {
+                0 => 0,
+                offset => base + offset as usize,
+            };
+            let kind = match attribute & 0x00ff_ffff {
+                0x03_0000 => MpType::BaselinePrimary,
+                0x01_0001 | 0x01_0002 => MpType::LargeThumbnail,
+                0x02_0002 => MpType::Disparity,
+                0x02_0003 => MpType::MultiAngle,
+                _ => MpType::Undefined,
+            };
+            Some(MpEntry { kind, offset, size })
+        })
+        .collect()
+}
+
+/// Helpers to assemble multi-picture files in tests.
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use super::{APP1, APP2, XMP_IDENTIFIER};
+
+    /// Insert a segment right after the start of image marker.
+    pub(crate) fn with_segment(jpeg: &[u8], marker: u8, payload: &[u8]) -> Vec<u8> {
+        let mut data = jpeg[..2].to_vec();
+        data.extend_from_slice(&[0xff, marker]);
+        data.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        data.extend_from_slice(payload);
+        data.extend_from_slice(&jpeg[2..]);
+        data
+    }
+
+    /// Insert an XMP packet.
+    pub(crate) fn with_xmp(jpeg: &[u8], xmp: &str) -> Vec<u8> {
+        let mut payload = XMP_IDENTIFIER.to_vec();
+        payload.extend_from_slice(xmp.as_bytes());
+        with_segment(jpeg, APP1, &payload)
+    }
+
+    /// Concatenate JPEG images and index them in the first one, with the given attributes.
+    pub(crate) fn multi_picture(images: &[(&[u8], u32)]) -> Vec<u8> {
+        const IFD_LENGTH: u32 = 2 + 3 * 12 + 4;
+        let entries_offset = 8 + IFD_LENGTH;
+        let payload_length = 4 + entries_offset as usize + 16 * images.len();
+        // The primary image grows by the segment, which is inserted right after its SOI.
+        let base = 2 + 4 + 4;
+        let primary_length = images[0].0.len() + 4 + payload_length;
+
+        let mut payload = b"MPF\0II*\0\x08\0\0\0".to_vec();
+        payload.extend_from_slice(&3u16.to_le_bytes());
+        payload.extend_from_slice(&[0x00, 0xb0, 7, 0, 4, 0, 0, 0, b'0', b'1', b'0', b'0']);
+        payload.extend_from_slice(&[0x01, 0xb0, 4, 0, 1, 0, 0, 0]);
+        payload.extend_from_slice(&(images.len() as u32).to_le_bytes());
+        payload.extend_from_slice(&[0x02, 0xb0, 7, 0]);
+        payload.extend_from_slice(&(16 * images.len() as u32).to_le_bytes());
+        payload.extend_from_slice(&entries_offset.to_le_bytes());
+        payload.extend_from_slice(&0u32.to_le_bytes());
+
+        let mut offset = primary_length;
+        for (i, &(image, attribute)) in images.iter().enumerate() {
+            let (size, relative) = if i == 0 {
+                (primary_length, 0)
+            } else {
+                let relative = offset - base;
+                offset += image.len();
+                (image.len(), relative)
+            };
+            payload.extend_from_slice(&attribute.to_le_bytes());
+            payload.extend_from_slice(&(size as u32).to_le_bytes());
+            payload.extend_from_slice(&(relative as u32).to_le_bytes());
+            payload.extend_from_slice(&[0; 4]);
+        }
+        assert_eq!(payload.len(), payload_length);
+
+        let mut data = with_segment(images[0].0, APP2, &payload);
+        for &(image, _) in &images[1..] {
+            data.extend_from_slice(image);
+        }
+        data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_utils::*;
+    use super::*;
+
+    #[test]
+    fn index_of_multi_picture_file() {
+        let first = [0xff, 0xd8, 0xff, 0xd9];
+        let second = [0xff, 0xd8, 0xff, 0xe0, 0, 2, 0xff, 0xd9];
+        let data = multi_picture(&[(&first, 0x2003_0000), (&second, 0x0002_0002)]);
+
+        let entries = entries(&data).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].kind, MpType::BaselinePrimary);
+        assert_eq!(entries[0].offset, 0);
+        assert_eq!(entries[0].size, data.len() - second.len());
+        assert_eq!(entries[1].kind, MpType::Disparity);
+        assert_eq!(entries[1].data(&data), Some(&second[..]));
+    }
+
+    #[test]
+    fn xmp_packet() {
+        let data = with_xmp(&[0xff, 0xd8, 0xff, 0xd9], "<x:xmpmeta/>");
+        assert_eq!(xmp(&data), Some("<x:xmpmeta/>"));
+        assert_eq!(entries(&data), None);
+    }
+}
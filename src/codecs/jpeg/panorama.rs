@@ -0,0 +1,270 @@
+//! The Google Photo Sphere `GPano` XMP metadata of 360° panoramas.
+//!
+//! Viewers only show a JPEG file as a sphere the user can look around in if its XMP metadata
+//! describes the projection and how much of the sphere the image covers. This metadata is lost
+//! when an image is decoded and encoded again, unless it is carried over explicitly with
+//! [`read_panorama_metadata`] and [`JpegEncoder::set_panorama_metadata`].
+//!
+//! # Related Links
+//! * <https://developers.google.com/streetview/spherical-metadata> - The Photo Sphere XMP metadata
+//!
+//! [`read_panorama_metadata`]: fn.read_panorama_metadata.html
+//! [`JpegEncoder::set_panorama_metadata`]: struct.JpegEncoder.html#method.set_panorama_metadata
+
+use std::fmt::Write as _;
+use std::io::Read;
+
+use crate::error::ImageResult;
+
+use super::mpf;
+
+/// The projection of a panorama.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PanoramaProjection {
+    /// Longitude and latitude map linearly to x and y, as produced by the functions of
+    /// `imageops::projection`.
+    Equirectangular,
+    /// Longitude maps linearly to x, and the tangent of the latitude to y.
+    Cylindrical,
+}
+
+impl PanoramaProjection {
+    fn name(self) -> &'static str {
+        match self {
+            PanoramaProjection::Equirectangular => "equirectangular",
+            PanoramaProjection::Cylindrical => "cylindrical",
+        }
+    }
+}
+
+/// The part of a sphere that a panorama covers, and how it is oriented.
+///
+/// The image is the cropped area of a full panorama of `full_width` by `full_height` pixels, the
+/// rest of which was not captured. The pose angles are given in degrees.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PanoramaMetadata {
+    /// The projection of the panorama.
+    pub projection: PanoramaProjection,
+    /// The width of the full panorama, which spans 360° of longitude.
+    pub full_width: u32,
+    /// The height of the full panorama, which spans 180° of latitude.
+    pub full_height: u32,
+    /// The column of the full panorama at which the image starts.
+    pub cropped_left: u32,
+    /// The row of the full panorama at which the image starts.
+    pub cropped_top: u32,
+    /// The width of the image within the full panorama.
+    pub cropped_width: u32,
+    /// The height of the image within the full panorama.
+    pub cropped_height: u32,
+    /// The compass heading of the center of the full panorama.
+    pub pose_heading: Option<f32>,
+    /// The pitch of the camera, positive when the center of the panorama is above the horizon.
+    pub pose_pitch: Option<f32>,
+    /// The roll of the camera, positive when it is rotated clockwise.
+    pub pose_roll: Option<f32>,
+}
+
+impl PanoramaMetadata {
+    /// The metadata of an equirectangular panorama of the whole sphere, such as the results of
+    /// `imageops::cubemap_to_equirectangular`.
+    pub fn full_sphere(width: u32, height: u32) -> PanoramaMetadata {
+        PanoramaMetadata {
+            projection: PanoramaProjection::Equirectangular,
+            full_width: width,
+            full_height: height,
+            cropped_left: 0,
+            cropped_top: 0,
+            cropped_width: width,
+            cropped_height: height,
+            pose_heading: None,
+            pose_pitch: None,
+            pose_roll: None,
+        }
+    }
+
+    /// Parses the `GPano` properties of an XMP packet.
+    ///
+    /// Returns `None` if the packet does not describe a panorama of a known projection. A missing
+    /// cropped area is taken to be the full panorama.
+    pub fn from_xmp(xmp: &str) -> Option<PanoramaMetadata> {
+        let projection = match xmp_value(xmp, "ProjectionType")?.trim() {
+            "equirectangular" => PanoramaProjection::Equirectangular,
+            "cylindrical" => PanoramaProjection::Cylindrical,
+            _ => return None,
+        };
+        let integer = |name: &str| -> Option<Option<u32>> {
+            match xmp_value(xmp, name) {
+                Some(value) => value.trim().parse().ok().map(Some),
+                None => Some(None),
+            }
+        };
+        let angle = |name: &str| xmp_value(xmp, name).and_then(|value| value.trim().parse().ok());
+
+        let full_width = integer("FullPanoWidthPixels")??;
+        let full_height = integer("FullPanoHeightPixels")??;
+        Some(PanoramaMetadata {
+            projection,
+            full_width,
+            full_height,
+            cropped_left: integer("CroppedAreaLeftPixels")?.unwrap_or(0),
+            cropped_top: integer("CroppedAreaTopPixels")?.unwrap_or(0),
+            cropped_width: integer("CroppedAreaImageWidthPixels")?.unwrap_or(full_width),
+            cropped_height: integer("CroppedAreaImageHeightPixels")?.unwrap_or(full_height),
+            pose_heading: angle("PoseHeadingDegrees"),
+            pose_pitch: angle("PosePitchDegrees"),
+            pose_roll: angle("PoseRollDegrees"),
+        })
+    }
+
+    /// Writes an XMP packet holding this metadata.
+    pub fn to_xmp(&self) -> String {
+        let mut properties = format!(
+            "GPano:UsePanoramaViewer=\"True\" GPano:ProjectionType=\"{}\" \
+             GPano:FullPanoWidthPixels=\"{}\" GPano:FullPanoHeightPixels=\"{}\" \
+             GPano:CroppedAreaLeftPixels=\"{}\" GPano:CroppedAreaTopPixels=\"{}\" \
+             GPano:CroppedAreaImageWidthPixels=\"{}\" GPano:CroppedAreaImageHeightPixels=\"{}\"",
+            self.projection.name(),
+            self.full_width,
+            self.full_height,
+            self.cropped_left,
+            self.cropped_top,
+            self.cropped_width,
+            self.cropped_height,
+        );
+        let angles = [
+            ("PoseHeadingDegrees", self.pose_heading),
+            ("PosePitchDegrees", self.pose_pitch),
+            ("PoseRollDegrees", self.pose_roll),
+        ];
+        for &(name, angle) in &angles {
+            if let Some(angle) = angle {
+                write!(properties, " GPano:{}=\"{}\"", name, angle).unwrap();
+            }
+        }
+        format!(
+            "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\">\
+             <rdf:RDF xmlns:rdf=\"http://www.w3.org/1999/02/22-rdf-syntax-ns#\">\
+             <rdf:Description rdf:about=\"\" \
+             xmlns:GPano=\"http://ns.google.com/photos/1.0/panorama/\" {}/>\
+             </rdf:RDF></x:xmpmeta>",
+            properties
+        )
+    }
+}
+
+/// Reads the panorama metadata of a JPEG file, if it has any.
+///
+/// The whole file is read into memory. Returns `Ok(None)` if the file has no XMP metadata or it
+/// does not describe a panorama.
+///
+/// ```no_run
+/// # fn main() -> image::ImageResult<()> {
+/// use std::fs::File;
+/// use std::io::BufReader;
+/// use image::codecs::jpeg::{read_panorama_metadata, JpegEncoder};
+///
+/// let panorama = image::open("sphere.jpg")?.to_rgb8();
+/// let metadata = read_panorama_metadata(BufReader::new(File::open("sphere.jpg")?))?;
+///
+/// let mut encoder = JpegEncoder::new(File::create("copy.jpg")?);
+/// if let Some(metadata) = metadata {
+///     encoder.set_panorama_metadata(metadata);
+/// }
+/// encoder.encode_image(&panorama)?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn read_panorama_metadata<R: Read>(mut r: R) -> ImageResult<Option<PanoramaMetadata>> {
+    let mut data = Vec::new();
+    r.read_to_end(&mut data)?;
+    Ok(mpf::xmp(&data).and_then(PanoramaMetadata::from_xmp))
+}
+
+/// Returns the value of a property in the `GPano` namespace, which is either an attribute,
+/// `GPano:Name="value"`, or an element, `<GPano:Name>value</GPano:Name>`.
+fn xmp_value<'a>(xmp: &'a str, name: &str) -> Option<&'a str> {
+    let attribute = format!("GPano:{}=\"", name);
+    if let Some(start) = xmp.find(&attribute) {
+        let value = &xmp[start + attribute.len()..];
+        return Some(&value[..value.find('"')?]);
+    }
+
+    let open = format!("<GPano:{}>", name);
+    let close = format!("</GPano:{}>", name);
+    let start = xmp.find(&open)? + open.len();
+    Some(&xmp[start..start + xmp[start..].find(&close)?])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mpf::test_utils::with_xmp;
+    use super::*;
+    use crate::codecs::jpeg::JpegEncoder;
+    use crate::{GenericImageView, RgbImage};
+    use std::io::Cursor;
+
+    #[test]
+    fn attributes_and_elements() {
+        let xmp = r#"<rdf:Description GPano:ProjectionType="equirectangular"
+            GPano:FullPanoWidthPixels="8000" GPano:FullPanoHeightPixels="4000"
+            GPano:CroppedAreaTopPixels="1000" GPano:CroppedAreaImageHeightPixels="2000">
+            <GPano:PosePitchDegrees>-2.5</GPano:PosePitchDegrees>
+        </rdf:Description>"#;
+        let metadata = PanoramaMetadata::from_xmp(xmp).unwrap();
+        assert_eq!(metadata.projection, PanoramaProjection::Equirectangular);
+        assert_eq!((metadata.full_width, metadata.full_height), (8000, 4000));
+        assert_eq!((metadata.cropped_left, metadata.cropped_top), (0, 1000));
+        assert_eq!(
+            (metadata.cropped_width, metadata.cropped_height),
+            (8000, 2000)
+        );
+        assert_eq!(metadata.pose_pitch, Some(-2.5));
+        assert_eq!(metadata.pose_heading, None);
+
+        assert!(PanoramaMetadata::from_xmp(r#"GPano:ProjectionType="equirectangular""#).is_none());
+        let unknown = r#"GPano:ProjectionType="rectilinear" GPano:FullPanoWidthPixels="2"
+            GPano:FullPanoHeightPixels="1""#;
+        assert!(PanoramaMetadata::from_xmp(unknown).is_none());
+    }
+
+    #[test]
+    fn xmp_round_trip() {
+        let mut metadata = PanoramaMetadata::full_sphere(4096, 2048);
+        assert_eq!(
+            PanoramaMetadata::from_xmp(&metadata.to_xmp()),
+            Some(metadata)
+        );
+        metadata.projection = PanoramaProjection::Cylindrical;
+        metadata.cropped_top = 24;
+        metadata.pose_heading = Some(271.5);
+        metadata.pose_roll = Some(-0.75);
+        assert_eq!(
+            PanoramaMetadata::from_xmp(&metadata.to_xmp()),
+            Some(metadata)
+        );
+    }
+
+    #[test]
+    fn encode_and_decode() {
+        let image = RgbImage::from_pixel(32, 16, crate::Rgb([20, 120, 220]));
+        let metadata = PanoramaMetadata::full_sphere(32, 16);
+        let mut encoded = Vec::new();
+        let mut encoder = JpegEncoder::new(&mut encoded);
+        encoder.set_panorama_metadata(metadata);
+        encoder.encode_image(&image).unwrap();
+
+        assert_eq!(
+            read_panorama_metadata(Cursor::new(&encoded)).unwrap(),
+            Some(metadata)
+        );
+        let decoded = crate::load_from_memory(&encoded).unwrap();
+        assert_eq!(decoded.dimensions(), (32, 16));
+
+        let mut plain = Vec::new();
+        JpegEncoder::new(&mut plain).encode_image(&image).unwrap();
+        assert_eq!(read_panorama_metadata(Cursor::new(&plain)).unwrap(), None);
+        let other = with_xmp(&plain, r#"<x:xmpmeta xmp:Rating="5"/>"#);
+        assert_eq!(read_panorama_metadata(Cursor::new(&other)).unwrap(), None);
+    }
+}
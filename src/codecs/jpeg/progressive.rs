@@ -0,0 +1,190 @@
+//! Decoding of JPEG images while their data arrives.
+use std::io::Cursor;
+
+use crate::dynimage::DynamicImage;
+use crate::error::{DecodingError, ImageError, ImageResult};
+use crate::image::{ImageFormat, ProgressiveDecoder};
+
+use super::segments::{scan_length, segment_at};
+use super::JpegDecoder;
+
+/// The part of the file the parser is in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum State {
+    /// Before the start of image marker.
+    Start,
+    /// Between segments, at the next marker.
+    Markers,
+    /// In the entropy coded data of a scan.
+    Scan,
+    /// After the end of image marker.
+    End,
+}
+
+/// JPEG decoder that returns a refined image after each scan of a progressive JPEG.
+///
+/// The first scans of a progressive JPEG describe a coarse version of the whole image, which the
+/// following scans refine. This decoder finds the ends of scans in the data as it arrives, and
+/// decodes the image as far as it is known whenever a scan is complete. Baseline JPEG images are
+/// only returned once complete.
+///
+/// See [`ProgressiveDecoder`] for its use.
+///
+/// [`ProgressiveDecoder`]: ../../trait.ProgressiveDecoder.html
+#[derive(Debug, Default)]
+pub struct ProgressiveJpegDecoder {
+    /// The data received so far.
+    data: Vec<u8>,
+    /// The position up to which the data has been parsed.
+    position: usize,
+    state: State,
+    /// Whether the frame header announced a progressive image.
+    progressive: bool,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State::Start
+    }
+}
+
+impl ProgressiveJpegDecoder {
+    /// Creates a decoder that has not received any data yet.
+    pub fn new() -> Self {
+        ProgressiveJpegDecoder::default()
+    }
+
+    /// Parses the data received since the last call.
+    ///
+    /// Returns the end of the last scan that was completed by the new data.
+    fn parse(&mut self) -> ImageResult<Option<usize>> {
+        let mut scan_end = None;
+        loop {
+            match self.state {
+                State::Start => match self.data.get(..2) {
+                    Some([0xff, 0xd8]) => {
+                        self.position = 2;
+                        self.state = State::Markers;
+                    }
+                    Some(_) => {
+                        return Err(format_error("The file does not start with a JPEG marker"))
+                    }
+                    None => return Ok(scan_end),
+                },
+                State::Markers => {
+                    let (marker, payload) = match segment_at(&self.data, self.position)? {
+                        Some(segment) => segment,
+                        None => return Ok(scan_end),
+                    };
+                    self.position = payload.end;
+                    match marker {
+                        // End of image
+                        0xd9 => self.state = State::End,
+                        // Start of frame, progressive with Huffman or arithmetic coding
+                        0xc2 | 0xc6 | 0xca | 0xce => self.progressive = true,
+                        // Start of scan
+                        0xda => self.state = State::Scan,
+                        _ => {}
+                    }
+                }
+                State::Scan => match scan_length(&self.data[self.position..]) {
+                    Some(length) => {
+                        self.position += length;
+                        scan_end = Some(self.position);
+                        self.state = State::Markers;
+                    }
+                    None => {
+                        // Only the last byte may start a marker, the data before it is parsed.
+                        self.position = self.position.max(self.data.len().saturating_sub(1));
+                        return Ok(scan_end);
+                    }
+                },
+                State::End => return Ok(scan_end),
+            }
+        }
+    }
+}
+
+impl ProgressiveDecoder for ProgressiveJpegDecoder {
+    fn feed(&mut self, data: &[u8]) -> ImageResult<Option<DynamicImage>> {
+        if self.state == State::End {
+            return Ok(None);
+        }
+        self.data.extend_from_slice(data);
+        let scan_end = self.parse()?;
+
+        if self.state == State::End {
+            self.data.truncate(self.position);
+            return decode(&self.data).map(Some);
+        }
+        match scan_end {
+            Some(end) if self.progressive => {
+                // Ending the data after the scan makes the decoder render the coefficients it
+                // has received so far.
+                let mut partial = Vec::with_capacity(end + 2);
+                partial.extend_from_slice(&self.data[..end]);
+                partial.extend_from_slice(&[0xff, 0xd9]);
+                decode(&partial).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.state == State::End
+    }
+}
+
+fn decode(data: &[u8]) -> ImageResult<DynamicImage> {
+    DynamicImage::from_decoder(JpegDecoder::new(Cursor::new(data))?)
+}
+
+fn format_error(message: &'static str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormat::Jpeg.into(), message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refines_progressive_scans() {
+        let data = std::fs::read("tests/images/jpg/progressive/cat.jpg").unwrap();
+        let complete = crate::load_from_memory(&data).unwrap();
+
+        let mut decoder = ProgressiveJpegDecoder::new();
+        let mut refinements = Vec::new();
+        for chunk in data.chunks(1000) {
+            assert!(!decoder.is_complete());
+            refinements.extend(decoder.feed(chunk).unwrap());
+        }
+        assert!(decoder.is_complete());
+        assert!(refinements.len() > 2, "{} refinements", refinements.len());
+        let last = refinements.pop().unwrap();
+        assert_eq!(last.as_bytes(), complete.as_bytes());
+        assert!(refinements
+            .iter()
+            .all(|preview| preview.as_bytes().len() == complete.as_bytes().len()));
+        assert_ne!(refinements[0].as_bytes(), complete.as_bytes());
+        assert!(decoder.feed(b"trailing").unwrap().is_none());
+    }
+
+    #[test]
+    fn baseline_only_when_complete() {
+        let data = std::fs::read("tests/images/jpg/progressive/cat.jpg").unwrap();
+        let baseline = crate::load_from_memory(&data).unwrap();
+        let mut encoded = Vec::new();
+        baseline
+            .write_to(&mut encoded, crate::ImageOutputFormat::Jpeg(80))
+            .unwrap();
+
+        let mut decoder = ProgressiveJpegDecoder::new();
+        let (head, tail) = encoded.split_at(encoded.len() - 1);
+        assert!(decoder.feed(head).unwrap().is_none());
+        assert!(decoder.feed(tail).unwrap().is_some());
+        assert!(decoder.is_complete());
+
+        let mut decoder = ProgressiveJpegDecoder::new();
+        assert!(decoder.feed(b"\x89PNG").is_err());
+    }
+}
@@ -0,0 +1,689 @@
+//! Reading and writing the marker segments of a JPEG file without decoding its image data.
+//!
+//! A JPEG file is a sequence of segments, each introduced by a marker. The segments in front of
+//! the first start of scan hold the metadata and the tables needed for decoding, and are followed
+//! by the entropy coded image data. Editing the segments in front of the image data and copying
+//! everything after them unchanged is lossless and runs at the speed of copying the file.
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+use crate::error::{DecodingError, ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use crate::image::ImageFormat;
+use crate::metadata::{self, StripPreset, EXIF_IDENTIFIER};
+
+use super::mpf::{APP1, APP2, ICC_IDENTIFIER};
+
+/// The marker of the APP0 segment, which holds the JFIF header and its extensions.
+const APP0: u8 = 0xe0;
+/// The marker of the APP14 segment, whose Adobe variant specifies the color transform.
+const APP14: u8 = 0xee;
+/// The marker of a comment segment.
+const COM: u8 = 0xfe;
+/// The marker of the start of scan, after which the image data follows.
+const SOS: u8 = 0xda;
+/// The marker of the end of image.
+const EOI: u8 = 0xd9;
+
+/// The identifier of the APP0 segment extending the JFIF header, which holds a thumbnail.
+const JFXX_IDENTIFIER: &[u8] = b"JFXX\0";
+/// The identifier of the APP14 segment written by Adobe.
+const ADOBE_IDENTIFIER: &[u8] = b"Adobe";
+
+/// The longest payload of a segment, whose length field counts itself.
+const MAX_SEGMENT_LENGTH: usize = u16::MAX as usize - 2;
+
+/// A marker segment of a JPEG file, as read by [`JpegSegmentReader`] and written by
+/// [`JpegSegmentWriter`].
+///
+/// [`JpegSegmentReader`]: struct.JpegSegmentReader.html
+/// [`JpegSegmentWriter`]: struct.JpegSegmentWriter.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JpegSegment {
+    /// The marker of the segment, the byte following `0xFF`, such as `0xE1` for APP1.
+    pub marker: u8,
+    /// The payload of the segment, without its marker and length.
+    pub data: Vec<u8>,
+}
+
+impl JpegSegment {
+    /// Create a segment with the given marker holding `data`.
+    pub fn new(marker: u8, data: Vec<u8>) -> Self {
+        JpegSegment { marker, data }
+    }
+
+    /// Whether the segment is an application segment APP0 to APP15 or a comment.
+    ///
+    /// Only these segments hold metadata, all others are needed to decode the image.
+    pub fn is_metadata(&self) -> bool {
+        matches!(self.marker, APP0..=0xef | COM)
+    }
+
+    /// Whether the payload starts with `identifier`, such as `b"Exif\0\0"` or `b"ICC_PROFILE\0"`.
+    pub fn has_identifier(&self, identifier: &[u8]) -> bool {
+        self.data.starts_with(identifier)
+    }
+
+    /// Whether the segment is a JFIF extension holding a thumbnail.
+    pub fn is_thumbnail(&self) -> bool {
+        self.marker == APP0 && self.has_identifier(JFXX_IDENTIFIER)
+    }
+
+    /// Remove the thumbnail from an APP1 segment holding EXIF metadata.
+    ///
+    /// The second directory, which describes the thumbnail, is unlinked, and the thumbnail itself
+    /// is cut off if it is stored at the end of the segment as usual. Returns whether the segment
+    /// had a thumbnail.
+    pub fn remove_exif_thumbnail(&mut self) -> bool {
+        if self.marker != APP1 || !self.has_identifier(EXIF_IDENTIFIER) {
+            return false;
+        }
+        let tiff = &mut self.data[EXIF_IDENTIFIER.len()..];
+        let end = match remove_ifd1(tiff) {
+            Some(end) => end,
+            None => return false,
+        };
+        if let Some(end) = end {
+            self.data.truncate(EXIF_IDENTIFIER.len() + end);
+        }
+        true
+    }
+
+    fn has_length(&self) -> bool {
+        has_length(self.marker)
+    }
+}
+
+/// Unlink the thumbnail directory of a TIFF structure.
+///
+/// Returns `None` if there is none, and otherwise the offset the structure may be cut off at to
+/// drop the thumbnail data.
+fn remove_ifd1(tiff: &mut [u8]) -> Option<Option<usize>> {
+    let big_endian = match tiff.get(..4)? {
+        b"II*\0" => false,
+        b"MM\0*" => true,
+        _ => return None,
+    };
+    let u16_at = |tiff: &[u8], offset: usize| {
+        let bytes = [*tiff.get(offset)?, *tiff.get(offset + 1)?];
+        Some(usize::from(if big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }))
+    };
+    let u32_at = |tiff: &[u8], offset: usize| {
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(tiff.get(offset..offset + 4)?);
+        Some(if big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        } as usize)
+    };
+
+    let ifd0 = u32_at(tiff, 4)?;
+    let next = ifd0 + 2 + 12 * u16_at(tiff, ifd0)?;
+    let ifd1 = u32_at(tiff, next)?;
+    if ifd1 == 0 {
+        return None;
+    }
+
+    let (mut start, mut length) = (None, None);
+    if let Some(count) = u16_at(tiff, ifd1) {
+        for entry in (0..count).map(|i| ifd1 + 2 + 12 * i) {
+            match u16_at(tiff, entry) {
+                Some(0x0201) => start = u32_at(tiff, entry + 8),
+                Some(0x0202) => length = u32_at(tiff, entry + 8),
+                _ => {}
+            }
+        }
+    }
+    tiff[next..next + 4].copy_from_slice(&[0; 4]);
+
+    // Nothing else refers to the thumbnail data, but other data may follow it.
+    let end = match (start, length) {
+        (Some(start), Some(length))
+            if start > next && start.checked_add(length) == Some(tiff.len()) =>
+        {
+            Some(start)
+        }
+        _ => None,
+    };
+    Some(end)
+}
+
+/// Whether segments with the marker have a length and payload, which all but the start and end
+/// of image, the restart markers and `TEM` have.
+fn has_length(marker: u8) -> bool {
+    !matches!(marker, 0x01 | 0xd0..=0xd9)
+}
+
+/// Find the segment whose marker is at `position` in `data`, skipping fill bytes.
+///
+/// Returns the marker and the range of its payload, which is empty for markers without a
+/// segment, or `None` if `data` ends before the segment does.
+pub(crate) fn segment_at(
+    data: &[u8],
+    mut position: usize,
+) -> ImageResult<Option<(u8, Range<usize>)>> {
+    while data.get(position..position + 2) == Some(&[0xff, 0xff]) {
+        position += 1;
+    }
+    let marker = match data.get(position..position + 2) {
+        Some(&[0xff, marker]) => marker,
+        Some(_) => return Err(expected_marker()),
+        None => return Ok(None),
+    };
+    if !has_length(marker) {
+        return Ok(Some((marker, position + 2..position + 2)));
+    }
+    let length = match data.get(position + 2..position + 4) {
+        Some(length) => u16::from_be_bytes([length[0], length[1]]),
+        None => return Ok(None),
+    };
+    if length < 2 {
+        return Err(invalid_length(marker));
+    }
+    let end = position + 2 + usize::from(length);
+    if end > data.len() {
+        return Ok(None);
+    }
+    Ok(Some((marker, position + 4..end)))
+}
+
+/// Returns the length of the entropy coded data at the start of `data`, up to the next marker
+/// other than a restart marker, or `None` if `data` ends before it.
+pub(crate) fn scan_length(data: &[u8]) -> Option<usize> {
+    let mut position = 0;
+    loop {
+        let marker = position + data[position..].iter().position(|&b| b == 0xff)?;
+        match *data.get(marker + 1)? {
+            // A stuffed zero or a restart marker within the data, or a fill byte.
+            0x00 | 0xd0..=0xd7 => position = marker + 2,
+            0xff => position = marker + 1,
+            _ => return Some(marker),
+        }
+    }
+}
+
+/// Iterate over the segments in front of the image data of a JPEG file in memory.
+///
+/// Yields the marker of each segment, and the offset of its payload together with the payload.
+pub(crate) fn segments(data: &[u8]) -> impl Iterator<Item = (u8, usize, &[u8])> {
+    let mut position = if data.starts_with(&[0xff, 0xd8]) {
+        Some(2)
+    } else {
+        None
+    };
+    std::iter::from_fn(move || {
+        let (marker, payload) = segment_at(data, position?).ok()??;
+        // Metadata must precede the image data.
+        if matches!(marker, SOS | EOI) {
+            return None;
+        }
+        position = Some(payload.end);
+        Some((marker, payload.start, &data[payload]))
+    })
+}
+
+/// Returns the length of the JPEG image at the start of `data`, up to and including its end of
+/// image marker.
+///
+/// Files can carry further images after the first one, which are only found this way if they are
+/// not listed in a Multi-Picture index.
+pub(crate) fn image_length(data: &[u8]) -> Option<usize> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    end_of_image(data, 2)
+}
+
+/// Returns the end of the end of image marker of the JPEG image whose segments continue at
+/// `position`.
+fn end_of_image(data: &[u8], mut position: usize) -> Option<usize> {
+    loop {
+        let (marker, payload) = segment_at(data, position).ok()??;
+        position = payload.end;
+        match marker {
+            EOI => return Some(position),
+            SOS => position += scan_length(&data[position..])?,
+            _ => {}
+        }
+    }
+}
+
+fn expected_marker() -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormat::Jpeg.into(),
+        "expected a JPEG marker",
+    ))
+}
+
+fn invalid_length(marker: u8) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormat::Jpeg.into(),
+        format!("invalid length of segment {:#04X}", marker),
+    ))
+}
+
+/// Reads the segments of a JPEG file up to the image data without decoding them.
+///
+/// The reader is an iterator over the segments after the start of image marker. It ends after
+/// the first start of scan segment, or after the end of image marker of a file without image
+/// data, and [`into_inner`] then returns the underlying reader positioned at the entropy coded
+/// data. Together with [`JpegSegmentWriter`] or [`edit_segments`] this allows tools to inspect
+/// and edit the metadata of an image, or drop segments to make it smaller, without decoding it.
+///
+/// [`into_inner`]: #method.into_inner
+/// [`JpegSegmentWriter`]: struct.JpegSegmentWriter.html
+/// [`edit_segments`]: fn.edit_segments.html
+pub struct JpegSegmentReader<R: Read> {
+    r: R,
+    done: bool,
+}
+
+impl<R: Read> JpegSegmentReader<R> {
+    /// Create a reader of the segments of the JPEG file read from `r`, checking its start of
+    /// image marker.
+    pub fn new(mut r: R) -> ImageResult<Self> {
+        let mut soi = [0; 2];
+        r.read_exact(&mut soi)?;
+        if soi != [0xff, 0xd8] {
+            return Err(ImageError::Decoding(DecodingError::new(
+                ImageFormat::Jpeg.into(),
+                "invalid JPEG start of image marker",
+            )));
+        }
+        Ok(JpegSegmentReader { r, done: false })
+    }
+
+    /// Unwrap the reader, which is positioned after the last segment that was read.
+    pub fn into_inner(self) -> R {
+        self.r
+    }
+
+    fn read_byte(&mut self) -> io::Result<u8> {
+        let mut byte = [0];
+        self.r.read_exact(&mut byte)?;
+        Ok(byte[0])
+    }
+
+    fn read_segment(&mut self) -> ImageResult<JpegSegment> {
+        if self.read_byte()? != 0xff {
+            return Err(expected_marker());
+        }
+        let mut marker = self.read_byte()?;
+        // Any number of fill bytes may precede a marker.
+        while marker == 0xff {
+            marker = self.read_byte()?;
+        }
+        if !has_length(marker) {
+            return Ok(JpegSegment::new(marker, Vec::new()));
+        }
+
+        let mut length = [0; 2];
+        self.r.read_exact(&mut length)?;
+        let length = u16::from_be_bytes(length);
+        if length < 2 {
+            return Err(invalid_length(marker));
+        }
+        let mut data = Vec::new();
+        self.r
+            .by_ref()
+            .take(u64::from(length - 2))
+            .read_to_end(&mut data)?;
+        if data.len() != usize::from(length - 2) {
+            return Err(ImageError::IoError(io::ErrorKind::UnexpectedEof.into()));
+        }
+        Ok(JpegSegment { marker, data })
+    }
+}
+
+impl<R: Read> Iterator for JpegSegmentReader<R> {
+    type Item = ImageResult<JpegSegment>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let segment = self.read_segment();
+        // Stop at the image data, or at the first error since the position is lost.
+        self.done = match &segment {
+            Ok(segment) => matches!(segment.marker, SOS | EOI),
+            Err(_) => true,
+        };
+        Some(segment)
+    }
+}
+
+/// Writes a JPEG file segment by segment.
+///
+/// The segments are written as given, it is up to the caller to write them in a valid order, such
+/// as the order they were read by a [`JpegSegmentReader`], and to write the image data after the
+/// start of scan segment through [`get_mut`].
+///
+/// [`JpegSegmentReader`]: struct.JpegSegmentReader.html
+/// [`get_mut`]: #method.get_mut
+pub struct JpegSegmentWriter<W: Write> {
+    w: W,
+}
+
+impl<W: Write> JpegSegmentWriter<W> {
+    /// Create a writer that writes the start of image marker to `w`.
+    pub fn new(mut w: W) -> ImageResult<Self> {
+        w.write_all(&[0xff, 0xd8])?;
+        Ok(JpegSegmentWriter { w })
+    }
+
+    /// Write `segment` with its marker and length.
+    pub fn write_segment(&mut self, segment: &JpegSegment) -> ImageResult<()> {
+        if !segment.has_length() && !segment.data.is_empty() {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!(
+                    "JPEG marker {:#04X} can not have data",
+                    segment.marker
+                )),
+            )));
+        }
+        if segment.data.len() > MAX_SEGMENT_LENGTH {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic("JPEG segment is longer than 65533 bytes".into()),
+            )));
+        }
+        self.w.write_all(&[0xff, segment.marker])?;
+        if segment.has_length() {
+            self.w
+                .write_all(&(segment.data.len() as u16 + 2).to_be_bytes())?;
+            self.w.write_all(&segment.data)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the underlying writer, to write the image data.
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.w
+    }
+
+    /// Unwrap the writer.
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+}
+
+/// What [`edit_segments`] does with a segment.
+///
+/// [`edit_segments`]: fn.edit_segments.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SegmentEdit {
+    /// Copy the segment unchanged.
+    Keep,
+    /// Drop the segment, which must hold metadata.
+    Remove,
+    /// Write the given metadata segments in place of the segment, which must hold metadata.
+    Replace(Vec<JpegSegment>),
+    /// Write the given metadata segments in front of the segment, which is kept.
+    InsertBefore(Vec<JpegSegment>),
+}
+
+/// Edit the metadata segments of a JPEG image without decoding it.
+///
+/// Each segment in front of the image data read from `r` is passed to `edit`, and written to `w`
+/// as the returned [`SegmentEdit`] says. The start of scan segment is passed last, and it and
+/// everything after it, in particular the entropy coded image data and any images appended to
+/// the file, are copied unchanged. Edits are thus fast and lossless even for large images.
+///
+/// Returns an `ImageError::Parameter` if an edit would remove or replace a segment needed for
+/// decoding, or write a segment that is not an application segment or a comment. See
+/// [`JpegSegment::is_metadata`].
+///
+/// # Examples
+///
+/// Strip the EXIF and XMP metadata and add a comment in front of the image data.
+///
+/// ```no_run
+/// # fn main() -> image::ImageResult<()> {
+/// use image::codecs::jpeg::{edit_segments, JpegSegment, SegmentEdit};
+///
+/// let input = std::fs::File::open("in.jpg")?;
+/// let output = std::fs::File::create("out.jpg")?;
+/// edit_segments(input, output, |segment| match segment.marker {
+///     0xe1 => SegmentEdit::Remove,
+///     0xda => SegmentEdit::InsertBefore(vec![JpegSegment::new(0xfe, b"Edited".to_vec())]),
+///     _ => SegmentEdit::Keep,
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`SegmentEdit`]: enum.SegmentEdit.html
+/// [`JpegSegment::is_metadata`]: struct.JpegSegment.html#method.is_metadata
+pub fn edit_segments<R, W, F>(r: R, w: W, edit: F) -> ImageResult<()>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&JpegSegment) -> SegmentEdit,
+{
+    edit_segments_impl(r, w, edit, false)
+}
+
+/// Edit the metadata segments, and copy the images appended to the file only if
+/// `keep_appended` is set.
+fn edit_segments_impl<R, W, F>(r: R, w: W, mut edit: F, keep_appended: bool) -> ImageResult<()>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&JpegSegment) -> SegmentEdit,
+{
+    fn invalid_edit(message: String) -> ImageError {
+        ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+            message,
+        )))
+    }
+    fn check_metadata(segments: &[JpegSegment]) -> ImageResult<()> {
+        match segments.iter().find(|segment| !segment.is_metadata()) {
+            Some(segment) => Err(invalid_edit(format!(
+                "can not write the JPEG segment {:#04X}, which is not metadata",
+                segment.marker
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    let mut writer = JpegSegmentWriter::new(w)?;
+    let mut reader = JpegSegmentReader::new(r)?;
+    for segment in reader.by_ref() {
+        let segment = segment?;
+        let edit = edit(&segment);
+        if !segment.is_metadata() && matches!(edit, SegmentEdit::Remove | SegmentEdit::Replace(_)) {
+            return Err(invalid_edit(format!(
+                "can not remove the JPEG segment {:#04X}, which is needed for decoding",
+                segment.marker
+            )));
+        }
+        match edit {
+            SegmentEdit::Keep => writer.write_segment(&segment)?,
+            SegmentEdit::Remove => {}
+            SegmentEdit::Replace(segments) => {
+                check_metadata(&segments)?;
+                for segment in &segments {
+                    writer.write_segment(segment)?;
+                }
+            }
+            SegmentEdit::InsertBefore(segments) => {
+                check_metadata(&segments)?;
+                for segment in &segments {
+                    writer.write_segment(segment)?;
+                }
+                writer.write_segment(&segment)?;
+            }
+        }
+    }
+    if keep_appended {
+        io::copy(&mut reader.into_inner(), writer.get_mut())?;
+    } else {
+        let mut rest = Vec::new();
+        reader.into_inner().read_to_end(&mut rest)?;
+        // Copy a truncated image as it is.
+        let end = scan_length(&rest)
+            .and_then(|length| end_of_image(&rest, length))
+            .unwrap_or(rest.len());
+        writer.get_mut().write_all(&rest[..end])?;
+    }
+    writer.into_inner().flush()?;
+    Ok(())
+}
+
+/// Strip the metadata of a JPEG image without decoding it, keeping what `preset` asks for.
+///
+/// Removes comments, thumbnails and all application segments except for the JFIF header, the
+/// ICC profile and the Adobe segment, which affect how the image is displayed. In particular the
+/// EXIF and XMP metadata and the index of a multi-picture file are removed. Images appended to the
+/// file, such as the second view of a stereo pair or the gain map of an HDR photo, are dropped
+/// along with the index that locates them, since they carry metadata of their own.
+pub(crate) fn strip_with_preset<R: Read, W: Write>(
+    r: R,
+    w: W,
+    preset: StripPreset,
+) -> ImageResult<()> {
+    let edit = |segment: &JpegSegment| {
+        let keep = match segment.marker {
+            APP0 => !segment.is_thumbnail(),
+            APP1 if preset == StripPreset::KeepOrientation
+                && segment.has_identifier(EXIF_IDENTIFIER) =>
+            {
+                return match metadata::orientation_only(&segment.data) {
+                    Some(exif) => {
+                        let mut data = EXIF_IDENTIFIER.to_vec();
+                        data.extend_from_slice(&exif);
+                        SegmentEdit::Replace(vec![JpegSegment::new(APP1, data)])
+                    }
+                    None => SegmentEdit::Remove,
+                };
+            }
+            APP2 => preset != StripPreset::All && segment.has_identifier(ICC_IDENTIFIER),
+            APP14 => segment.has_identifier(ADOBE_IDENTIFIER),
+            _ => !segment.is_metadata(),
+        };
+        if keep {
+            SegmentEdit::Keep
+        } else {
+            SegmentEdit::Remove
+        }
+    };
+    // Every preset removes the Multi-Picture index.
+    edit_segments_impl(r, w, edit, false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn markers(jpeg: &[u8]) -> Vec<u8> {
+        JpegSegmentReader::new(jpeg)
+            .unwrap()
+            .map(|segment| segment.unwrap().marker)
+            .collect()
+    }
+
+    #[test]
+    fn edit_segments_keeps_image_data() {
+        let original = std::fs::read("tests/images/jpg/progressive/cat.jpg").unwrap();
+        assert_eq!(markers(&original).last(), Some(&SOS));
+
+        let mut edited = Vec::new();
+        edit_segments(&original[..], &mut edited, |segment| match segment.marker {
+            COM => SegmentEdit::Remove,
+            SOS => SegmentEdit::InsertBefore(vec![JpegSegment::new(0xe5, b"test".to_vec())]),
+            _ => SegmentEdit::Keep,
+        })
+        .unwrap();
+
+        let before = markers(&original);
+        let after = markers(&edited);
+        assert!(before.contains(&COM) && !after.contains(&COM));
+        assert_eq!(after[after.len() - 2], 0xe5);
+        let before = crate::load_from_memory(&original).unwrap();
+        let after = crate::load_from_memory(&edited).unwrap();
+        assert_eq!(before.as_bytes(), after.as_bytes());
+
+        let mut stripped = Vec::new();
+        strip_with_preset(&edited[..], &mut stripped, StripPreset::KeepColorProfile).unwrap();
+        let markers = markers(&stripped);
+        assert!(!markers
+            .iter()
+            .any(|&marker| marker == APP1 || marker == 0xe5));
+        assert!(markers.contains(&APP0) && markers.contains(&APP2));
+        assert!(stripped.len() < original.len());
+    }
+
+    #[test]
+    fn edit_segments_rejects_decoding_segments() {
+        let original = std::fs::read("tests/images/jpg/progressive/cat.jpg").unwrap();
+        let remove_tables =
+            edit_segments(&original[..], Vec::new(), |segment| match segment.marker {
+                0xdb => SegmentEdit::Remove,
+                _ => SegmentEdit::Keep,
+            });
+        assert!(matches!(remove_tables, Err(ImageError::Parameter(_))));
+
+        let insert_sos = edit_segments(&original[..], Vec::new(), |segment| match segment.marker {
+            COM => SegmentEdit::Replace(vec![JpegSegment::new(SOS, Vec::new())]),
+            _ => SegmentEdit::Keep,
+        });
+        assert!(matches!(insert_sos, Err(ImageError::Parameter(_))));
+    }
+
+    #[test]
+    fn strip_drops_appended_images() {
+        use super::super::mpf::test_utils::{multi_picture, with_segment};
+        use crate::codecs::jpeg::JpegEncoder;
+        use crate::{GrayImage, Luma};
+
+        let jpeg = |value: u8| {
+            let mut encoded = Vec::new();
+            JpegEncoder::new(&mut encoded)
+                .encode_image(&GrayImage::from_pixel(8, 8, Luma([value])))
+                .unwrap();
+            encoded
+        };
+        // EXIF metadata with a GPS directory holding the latitude reference.
+        let mut exif = EXIF_IDENTIFIER.to_vec();
+        exif.extend_from_slice(b"II*\0\x08\0\0\0\x01\0\x25\x88\x04\0\x01\0\0\0\x1a\0\0\0\0\0\0\0");
+        exif.extend_from_slice(b"\x01\0\x01\0\x02\0\x02\0\0\0N\0\0\0\0\0\0\0");
+        let left = jpeg(40);
+        let right = with_segment(&jpeg(200), APP1, &exif);
+        let file = multi_picture(&[(&left, 0x2002_0002), (&right, 0x0002_0002)]);
+        assert!(crate::codecs::jpeg::MpoDecoder::new(&file[..]).is_ok());
+
+        for &preset in &[
+            StripPreset::All,
+            StripPreset::KeepColorProfile,
+            StripPreset::KeepOrientation,
+        ] {
+            let mut stripped = Vec::new();
+            strip_with_preset(&file[..], &mut stripped, preset).unwrap();
+            assert_eq!(image_length(&stripped), Some(stripped.len()));
+            assert!(!stripped.windows(4).any(|bytes| bytes == b"Exif"));
+            let image = crate::load_from_memory(&stripped).unwrap().to_luma8();
+            assert!((i32::from(image.get_pixel(4, 4)[0]) - 40).abs() <= 2);
+        }
+    }
+
+    #[test]
+    fn remove_exif_thumbnail() {
+        // A little endian structure with an empty first directory, and a second one pointing to
+        // four bytes of thumbnail data at the end.
+        let mut data = EXIF_IDENTIFIER.to_vec();
+        data.extend_from_slice(b"II*\0\x08\0\0\0\0\0\x0e\0\0\0\x02\0");
+        data.extend_from_slice(b"\x01\x02\x04\0\x01\0\0\0\x2c\0\0\0");
+        data.extend_from_slice(b"\x02\x02\x04\0\x01\0\0\0\x04\0\0\0");
+        data.extend_from_slice(b"\0\0\0\0thmb");
+        let mut segment = JpegSegment::new(APP1, data);
+
+        assert!(segment.remove_exif_thumbnail());
+        let tiff = &segment.data[EXIF_IDENTIFIER.len()..];
+        assert_eq!(tiff.len(), 44);
+        assert_eq!(&tiff[10..14], &[0; 4]);
+        assert!(!segment.remove_exif_thumbnail());
+    }
+}
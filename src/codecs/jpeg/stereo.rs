@@ -0,0 +1,414 @@
+//! Decoding of stereoscopic and multi-view JPEG files.
+//!
+//! MPO files store every view as a complete JPEG image in a multi-picture file, JPS files place
+//! both views of a stereo pair next to each other in a single JPEG image. Both decoders implement
+//! [`MultiImageDecoder`] to select a view, the first view is the left one.
+//!
+//! [`MultiImageDecoder`]: ../../trait.MultiImageDecoder.html
+
+use std::convert::TryFrom;
+use std::io::{Cursor, Read};
+
+use crate::color::ColorType;
+use crate::dynimage::DynamicImage;
+use crate::error::{DecodingError, ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use crate::image::{ImageDecoder, ImageFormat, MultiImageDecoder};
+
+use super::auxiliary::{self, AuxiliaryImage};
+use super::decoder::JpegReader;
+use super::mpf::{self, MpEntry, MpType};
+use super::segments;
+use super::JpegDecoder;
+
+/// Decoder for MPO (Multi-Picture Object) files, as written by stereo and multi-angle cameras.
+///
+/// Each view is a separate image, in the order of the multi-picture index. A file without an
+/// index is treated as a single view.
+///
+/// ```no_run
+/// # fn main() -> image::ImageResult<()> {
+/// use image::codecs::jpeg::MpoDecoder;
+///
+/// let file = std::io::BufReader::new(std::fs::File::open("photo.mpo")?);
+/// let (left, right) = MpoDecoder::new(file)?.into_stereo_pair()?;
+/// # Ok(())
+/// # }
+/// ```
+pub struct MpoDecoder {
+    data: Vec<u8>,
+    entries: Vec<MpEntry>,
+    decoder: JpegDecoder<Cursor<Vec<u8>>>,
+}
+
+impl MpoDecoder {
+    /// Create a new decoder that reads the whole file from `r`, with the first view selected.
+    pub fn new<R: Read>(mut r: R) -> ImageResult<MpoDecoder> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+
+        let entries = mpf::entries(&data)
+            .filter(|entries| !entries.is_empty())
+            .unwrap_or_else(|| {
+                vec![MpEntry {
+                    kind: MpType::BaselinePrimary,
+                    offset: 0,
+                    size: data.len(),
+                }]
+            });
+        // Views of a stereo or multi-angle capture, leaving out thumbnails and gain maps.
+        let views: Vec<MpEntry> = entries
+            .iter()
+            .copied()
+            .filter(|entry| matches!(entry.kind, MpType::Disparity | MpType::MultiAngle))
+            .collect();
+        let entries = if views.is_empty() { entries } else { views };
+
+        let decoder = Self::entry_decoder(&data, &entries[0])?;
+        Ok(MpoDecoder {
+            data,
+            entries,
+            decoder,
+        })
+    }
+
+    /// Decode the first two views, the left and the right view of a stereo pair.
+    ///
+    /// Returns an `ImageError::Parameter` if the file holds fewer than two views.
+    pub fn into_stereo_pair(mut self) -> ImageResult<(DynamicImage, DynamicImage)> {
+        self.select_image(1)?;
+        let right = DynamicImage::from_decoder(self.decoder)?;
+        let left = Self::entry_decoder(&self.data, &self.entries[0])?;
+        Ok((DynamicImage::from_decoder(left)?, right))
+    }
+
+    /// Decode the auxiliary images of the file, such as depth maps and portrait mattes.
+    ///
+    /// See [`read_auxiliary_images`] for the supported kinds of images.
+    ///
+    /// [`read_auxiliary_images`]: fn.read_auxiliary_images.html
+    pub fn auxiliary_images(&self) -> ImageResult<Vec<AuxiliaryImage>> {
+        auxiliary::auxiliary_images(&self.data)
+    }
+
+    fn entry_decoder(data: &[u8], entry: &MpEntry) -> ImageResult<JpegDecoder<Cursor<Vec<u8>>>> {
+        JpegDecoder::new(Cursor::new(entry_data(data, entry)?.to_vec()))
+    }
+}
+
+impl<'a> ImageDecoder<'a> for MpoDecoder {
+    type Reader = JpegReader<Cursor<Vec<u8>>>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.decoder.dimensions()
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.decoder.color_type()
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        self.decoder.into_reader()
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        self.decoder.read_image(buf)
+    }
+}
+
+impl<'a> MultiImageDecoder<'a> for MpoDecoder {
+    fn image_count(&mut self) -> ImageResult<usize> {
+        Ok(self.entries.len())
+    }
+
+    fn select_image(&mut self, index: usize) -> ImageResult<()> {
+        let entry = self.entries.get(index).ok_or_else(|| no_image(index))?;
+        self.decoder = Self::entry_decoder(&self.data, entry)?;
+        Ok(())
+    }
+
+    fn read_page(&mut self, index: usize, buf: &mut [u8]) -> ImageResult<()> {
+        self.select_image(index)?;
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        let entry = &self.entries[index];
+        JpegDecoder::new(Cursor::new(entry_data(&self.data, entry)?))?.read_image(buf)
+    }
+}
+
+/// The arrangement of the two views in a JPS image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum JpsLayout {
+    /// A single view.
+    Mono,
+    /// Alternating rows, starting with the first view.
+    Interleaved,
+    /// Left and right halves.
+    SideBySide,
+    /// Top and bottom halves.
+    OverUnder,
+}
+
+/// Decoder for JPS (JPEG Stereo) files, which hold both views of a stereo pair in one image.
+///
+/// The layout of the views is read from the stereoscopic descriptor of the file. Without one the
+/// views are side by side in cross-eyed order, which puts the right view into the left half.
+#[derive(Clone)]
+pub struct JpsDecoder {
+    data: Vec<u8>,
+    layout: JpsLayout,
+    /// Whether the left view comes first, in the left or top half or in the even rows.
+    left_first: bool,
+    /// The dimensions of the whole image.
+    dimensions: (u32, u32),
+    color_type: ColorType,
+    view: usize,
+}
+
+impl JpsDecoder {
+    /// Create a new decoder that reads the whole file from `r`, with the left view selected.
+    pub fn new<R: Read>(mut r: R) -> ImageResult<JpsDecoder> {
+        let mut data = Vec::new();
+        r.read_to_end(&mut data)?;
+
+        let decoder = JpegDecoder::new(Cursor::new(&data[..]))?;
+        let dimensions = decoder.dimensions();
+        let color_type = decoder.color_type();
+
+        // The stereoscopic descriptor in an APP3 segment, following the identifier and length.
+        let descriptor = segments::segments(&data)
+            .filter(|&(marker, _, _)| marker == 0xe3)
+            .find_map(|(_, _, payload)| payload.strip_prefix(&b"_JPSJPS_"[..]))
+            .and_then(|descriptor| descriptor.get(2..6))
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]));
+        let (layout, left_first) = match descriptor {
+            None => (JpsLayout::SideBySide, false),
+            Some(descriptor) => {
+                let layout = match (descriptor & 0xff, (descriptor >> 8) & 0xff) {
+                    (0x01, 0x01) => JpsLayout::Interleaved,
+                    (0x01, 0x02) => JpsLayout::SideBySide,
+                    (0x01, 0x03) => JpsLayout::OverUnder,
+                    // Monoscopic images and anaglyphs hold a single image for both eyes.
+                    _ => JpsLayout::Mono,
+                };
+                (layout, descriptor & 0x04_0000 != 0)
+            }
+        };
+
+        Ok(JpsDecoder {
+            data,
+            layout,
+            left_first,
+            dimensions,
+            color_type,
+            view: 0,
+        })
+    }
+
+    /// Decode the left and the right view.
+    ///
+    /// For monoscopic files both images are the same.
+    pub fn into_stereo_pair(mut self) -> ImageResult<(DynamicImage, DynamicImage)> {
+        let left = DynamicImage::from_decoder(self.clone())?;
+        self.view = 1;
+        let right = DynamicImage::from_decoder(self)?;
+        Ok((left, right))
+    }
+
+    /// Returns the offset of the selected view into the rows, the index of its first row and the
+    /// distance between its rows.
+    fn view_origin(&self) -> (u32, u32, u32) {
+        let (width, height) = self.dimensions;
+        let second = self.layout != JpsLayout::Mono && (self.view == 0) != self.left_first;
+        match (self.layout, second) {
+            (JpsLayout::SideBySide, true) => (width / 2, 0, 1),
+            (JpsLayout::OverUnder, true) => (0, height / 2, 1),
+            (JpsLayout::Interleaved, second) => (0, u32::from(second), 2),
+            _ => (0, 0, 1),
+        }
+    }
+}
+
+impl<'a> ImageDecoder<'a> for JpsDecoder {
+    type Reader = Cursor<Vec<u8>>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        let (width, height) = self.dimensions;
+        match self.layout {
+            JpsLayout::Mono => (width, height),
+            JpsLayout::SideBySide => (width / 2, height),
+            JpsLayout::OverUnder | JpsLayout::Interleaved => (width, height / 2),
+        }
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        let mut buf = vec![0; self.total_bytes() as usize];
+        self.read_image(&mut buf)?;
+        Ok(Cursor::new(buf))
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        self.read_view(buf)
+    }
+}
+
+impl JpsDecoder {
+    /// Decode the selected view into `buf`.
+    fn read_view(&self, buf: &mut [u8]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+
+        let decoder = JpegDecoder::new(Cursor::new(&self.data[..]))?;
+        let mut image = vec![0; decoder.total_bytes() as usize];
+        decoder.read_image(&mut image)?;
+
+        let bytes_per_pixel = usize::from(self.color_type.bytes_per_pixel());
+        let image_row = self.dimensions.0 as usize * bytes_per_pixel;
+        let view_row = self.dimensions().0 as usize * bytes_per_pixel;
+        let (x, y, step) = self.view_origin();
+        for (row, output) in buf.chunks_exact_mut(view_row).enumerate() {
+            let start = (y + step * row as u32) as usize * image_row + x as usize * bytes_per_pixel;
+            output.copy_from_slice(&image[start..start + view_row]);
+        }
+        Ok(())
+    }
+}
+
+impl<'a> MultiImageDecoder<'a> for JpsDecoder {
+    fn image_count(&mut self) -> ImageResult<usize> {
+        Ok(if self.layout == JpsLayout::Mono { 1 } else { 2 })
+    }
+
+    fn select_image(&mut self, index: usize) -> ImageResult<()> {
+        if index >= self.image_count()? {
+            return Err(no_image(index));
+        }
+        self.view = index;
+        Ok(())
+    }
+
+    fn read_page(&mut self, index: usize, buf: &mut [u8]) -> ImageResult<()> {
+        self.select_image(index)?;
+        self.read_view(buf)
+    }
+}
+
+/// The data of a multi-picture entry within the file.
+fn entry_data<'d>(data: &'d [u8], entry: &MpEntry) -> ImageResult<&'d [u8]> {
+    entry.data(data).ok_or_else(|| {
+        ImageError::Decoding(DecodingError::new(
+            ImageFormat::Jpeg.into(),
+            "multi-picture entry lies outside of the file",
+        ))
+    })
+}
+
+fn no_image(index: usize) -> ImageError {
+    ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+        format!("the file has no image at index {}", index),
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mpf::test_utils::{multi_picture, with_segment};
+    use super::*;
+    use crate::codecs::jpeg::JpegEncoder;
+    use crate::image::GenericImageView;
+    use crate::{GrayImage, Luma};
+
+    fn jpeg(image: &GrayImage) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        JpegEncoder::new_with_quality(&mut encoded, 100)
+            .encode_image(image)
+            .unwrap();
+        encoded
+    }
+
+    /// The average of the pixels, to compare images after lossy compression.
+    fn mean(image: &DynamicImage) -> u32 {
+        let (width, height) = image.dimensions();
+        let sum: u32 = image.to_luma8().pixels().map(|p| u32::from(p[0])).sum();
+        sum / (width * height)
+    }
+
+    #[test]
+    fn mpo_views() {
+        let left = jpeg(&GrayImage::from_pixel(16, 8, Luma([40])));
+        let right = jpeg(&GrayImage::from_pixel(16, 8, Luma([200])));
+        let thumbnail = jpeg(&GrayImage::new(8, 8));
+        let file = multi_picture(&[
+            (&left, 0x2002_0002),
+            (&thumbnail, 0x0001_0001),
+            (&right, 0x0002_0002),
+        ]);
+
+        let mut decoder = MpoDecoder::new(Cursor::new(&file)).unwrap();
+        assert_eq!(decoder.image_count().unwrap(), 2);
+        assert_eq!(decoder.dimensions(), (16, 8));
+        assert!(decoder.select_image(2).is_err());
+
+        let (left, right) = decoder.into_stereo_pair().unwrap();
+        assert!((mean(&left) as i32 - 40).abs() <= 1);
+        assert!((mean(&right) as i32 - 200).abs() <= 1);
+    }
+
+    #[test]
+    fn mpo_without_index() {
+        let file = jpeg(&GrayImage::new(8, 8));
+        let mut decoder = MpoDecoder::new(Cursor::new(&file)).unwrap();
+        assert_eq!(decoder.image_count().unwrap(), 1);
+        assert!(decoder.into_stereo_pair().is_err());
+    }
+
+    fn stereo_image(width: u32, height: u32, first: impl Fn(u32, u32) -> bool) -> Vec<u8> {
+        jpeg(&GrayImage::from_fn(width, height, |x, y| {
+            Luma([if first(x, y) { 40 } else { 200 }])
+        }))
+    }
+
+    #[test]
+    fn jps_cross_eyed_by_default() {
+        let file = stereo_image(32, 8, |x, _| x < 16);
+        let mut decoder = JpsDecoder::new(Cursor::new(&file)).unwrap();
+        assert_eq!(decoder.image_count().unwrap(), 2);
+        assert_eq!(decoder.dimensions(), (16, 8));
+
+        let (left, right) = decoder.into_stereo_pair().unwrap();
+        assert!((mean(&left) as i32 - 200).abs() <= 1);
+        assert!((mean(&right) as i32 - 40).abs() <= 1);
+    }
+
+    #[test]
+    fn jps_descriptor() {
+        let descriptor = |layout: u32, flags: u32| {
+            let mut payload = b"_JPSJPS_\0\x04".to_vec();
+            payload.extend_from_slice(&(0x01 | layout << 8 | flags << 16).to_be_bytes());
+            payload
+        };
+
+        // Over and under, left view first.
+        let file = stereo_image(16, 16, |_, y| y < 8);
+        let file = with_segment(&file, 0xe3, &descriptor(0x03, 0x04));
+        let decoder = JpsDecoder::new(Cursor::new(&file)).unwrap();
+        assert_eq!(decoder.dimensions(), (16, 8));
+        let (left, right) = decoder.into_stereo_pair().unwrap();
+        assert!((mean(&left) as i32 - 40).abs() <= 1);
+        assert!((mean(&right) as i32 - 200).abs() <= 1);
+
+        // Interleaved rows, left view first.
+        let image = GrayImage::from_fn(8, 8, |_, y| Luma([if y % 2 == 0 { 0 } else { 255 }]));
+        let mut file = Vec::new();
+        JpegEncoder::new_with_quality(&mut file, 100)
+            .encode_image(&image)
+            .unwrap();
+        let file = with_segment(&file, 0xe3, &descriptor(0x01, 0x04));
+        let (left, right) = JpsDecoder::new(Cursor::new(&file))
+            .unwrap()
+            .into_stereo_pair()
+            .unwrap();
+        assert_eq!(left.dimensions(), (8, 4));
+        assert!(mean(&left) < mean(&right));
+    }
+}
@@ -0,0 +1,152 @@
+//! Decoding of JPEG XS images through an external decoder.
+//!
+//! JPEG XS is a low latency, visually lossless mezzanine codec for broadcast and professional
+//! video links. This crate does not implement it, but it recognizes JPEG XS files and routes
+//! them to a decoder that the application registers, typically a binding to a native library.
+//! Once one is registered, `image::open`, `Reader` and `load_from_memory` decode JPEG XS files
+//! like those of any other format.
+//!
+//! ```no_run
+//! use image::codecs::jpegxs;
+//!
+//! # fn native_decode(_: &[u8]) -> image::ImageResult<image::DynamicImage> { unimplemented!() }
+//! jpegxs::register_decoder(|reader| {
+//!     let mut data = Vec::new();
+//!     reader.read_to_end(&mut data)?;
+//!     native_decode(&data)
+//! });
+//! let image = image::open("frame.jxs")?;
+//! # Ok::<(), image::ImageError>(())
+//! ```
+//!
+//! # Related Links
+//! * <https://jpeg.org/jpegxs/> - The JPEG XS standard, ISO/IEC 21122
+
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read};
+use std::marker::PhantomData;
+use std::mem;
+
+use crate::color::ColorType;
+use crate::dynimage::DynamicImage;
+use crate::error::{ImageError, ImageResult, UnsupportedError, UnsupportedErrorKind};
+use crate::image::{ImageDecoder, ImageFormat};
+
+use super::registry::Registry;
+
+/// A function that decodes a whole JPEG XS file.
+pub type DecodeFn = Box<dyn Fn(&mut dyn Read) -> ImageResult<DynamicImage> + Send + Sync>;
+
+/// The registered decoder.
+static DECODER: Registry<DecodeFn> = Registry::new();
+
+/// Registers the function that decodes JPEG XS files, replacing any previously registered one.
+///
+/// The function is given a reader positioned at the start of the file and returns the decoded
+/// image. It is called from whatever thread decodes a JPEG XS file.
+pub fn register_decoder<F>(decoder: F)
+where
+    F: Fn(&mut dyn Read) -> ImageResult<DynamicImage> + Send + Sync + 'static,
+{
+    DECODER.register(Box::new(decoder));
+}
+
+/// Returns if a JPEG XS decoder is registered.
+pub fn has_decoder() -> bool {
+    DECODER.is_registered()
+}
+
+/// JPEG XS decoder, which decodes with the registered decoder.
+pub struct JpegXsDecoder<R> {
+    image: DynamicImage,
+    marker: PhantomData<R>,
+}
+
+impl<R: Read> JpegXsDecoder<R> {
+    /// Decodes the image read from `r` with the registered decoder.
+    ///
+    /// Returns an `ImageError::Unsupported` if no decoder is registered.
+    pub fn new(mut r: R) -> ImageResult<JpegXsDecoder<R>> {
+        let decoder = DECODER.get().ok_or_else(|| {
+            ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+                ImageFormat::JpegXs.into(),
+                UnsupportedErrorKind::GenericFeature("no JPEG XS decoder is registered".into()),
+            ))
+        })?;
+        Ok(JpegXsDecoder {
+            image: decoder(&mut r)?,
+            marker: PhantomData,
+        })
+    }
+}
+
+/// The reader returned by `JpegXsDecoder::into_reader`.
+pub struct JpegXsReader<R>(Cursor<Vec<u8>>, PhantomData<R>);
+impl<R> Read for JpegXsReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        if self.0.position() == 0 && buf.is_empty() {
+            mem::swap(buf, self.0.get_mut());
+            Ok(buf.len())
+        } else {
+            self.0.read_to_end(buf)
+        }
+    }
+}
+
+impl<'a, R: 'a + Read> ImageDecoder<'a> for JpegXsDecoder<R> {
+    type Reader = JpegXsReader<R>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.image.width(), self.image.height())
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.image.color()
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        Ok(JpegXsReader(
+            Cursor::new(self.image.as_bytes().to_vec()),
+            PhantomData,
+        ))
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        buf.copy_from_slice(self.image.as_bytes());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayImage, Luma};
+
+    #[test]
+    fn registered_decoder() {
+        // The codestream of a JPEG XS image starts with the SOC and CAP markers.
+        let file = [0xff, 0x10, 0xff, 0x50, 0, 2, 7];
+        assert_eq!(crate::guess_format(&file).unwrap(), ImageFormat::JpegXs);
+
+        register_decoder(|reader| {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            let value = data[data.len() - 1];
+            Ok(DynamicImage::ImageLuma8(GrayImage::from_pixel(
+                3,
+                2,
+                Luma([value]),
+            )))
+        });
+        assert!(has_decoder());
+        assert!(ImageFormat::JpegXs.can_read());
+
+        let image = crate::load_from_memory(&file).unwrap();
+        assert_eq!(image.color(), ColorType::L8);
+        assert_eq!(image.as_bytes(), &[7; 6]);
+    }
+}
@@ -0,0 +1,229 @@
+//! Decoding and encoding of JPEG XL images through an external codec.
+//!
+//! JPEG XL is a royalty free successor of JPEG, with lossless recompression of existing JPEG
+//! files, high bit depths and wide gamut, which makes it popular for archiving photographs. This
+//! crate does not implement the codec, but it recognizes JPEG XL files and routes them to a
+//! decoder and an encoder that the application registers, typically bindings to `libjxl`. Once
+//! they are registered, `image::open`, `Reader`, `save` and `encoder_for` handle JPEG XL files
+//! like those of any other format.
+//!
+//! ```no_run
+//! use image::codecs::jxl;
+//!
+//! # fn native_decode(_: &[u8]) -> image::ImageResult<image::DynamicImage> { unimplemented!() }
+//! # fn native_encode(_: &[u8], _: u32, _: u32, _: image::ColorType) -> image::ImageResult<Vec<u8>> { unimplemented!() }
+//! jxl::register_decoder(|reader| {
+//!     let mut data = Vec::new();
+//!     reader.read_to_end(&mut data)?;
+//!     native_decode(&data)
+//! });
+//! jxl::register_encoder(|writer, buf, width, height, color| {
+//!     writer.write_all(&native_encode(buf, width, height, color)?)?;
+//!     Ok(())
+//! });
+//! let image = image::open("photo.jxl")?;
+//! image.save("copy.jxl")?;
+//! # Ok::<(), image::ImageError>(())
+//! ```
+//!
+//! # Related Links
+//! * <https://jpeg.org/jpegxl/> - The JPEG XL standard, ISO/IEC 18181
+
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read, Write};
+use std::marker::PhantomData;
+use std::mem;
+
+use crate::color::ColorType;
+use crate::dynimage::DynamicImage;
+use crate::error::{ImageError, ImageResult, UnsupportedError, UnsupportedErrorKind};
+use crate::image::{ImageDecoder, ImageEncoder, ImageFormat};
+
+use super::registry::Registry;
+
+/// A function that decodes a whole JPEG XL file.
+pub type DecodeFn = Box<dyn Fn(&mut dyn Read) -> ImageResult<DynamicImage> + Send + Sync>;
+
+/// A function that encodes an image as a JPEG XL file.
+///
+/// It is given the pixel data in native endian, as `ImageEncoder::write_image` is.
+pub type EncodeFn =
+    Box<dyn Fn(&mut dyn Write, &[u8], u32, u32, ColorType) -> ImageResult<()> + Send + Sync>;
+
+/// The registered codec.
+static DECODER: Registry<DecodeFn> = Registry::new();
+static ENCODER: Registry<EncodeFn> = Registry::new();
+
+/// Registers the function that decodes JPEG XL files, replacing any previously registered one.
+///
+/// The function is given a reader positioned at the start of the file and returns the decoded
+/// image. It is called from whatever thread decodes a JPEG XL file.
+pub fn register_decoder<F>(decoder: F)
+where
+    F: Fn(&mut dyn Read) -> ImageResult<DynamicImage> + Send + Sync + 'static,
+{
+    DECODER.register(Box::new(decoder));
+}
+
+/// Registers the function that encodes JPEG XL files, replacing any previously registered one.
+///
+/// The function should reject color types it can not encode with an `ImageError::Unsupported`.
+/// It is called from whatever thread encodes a JPEG XL file.
+pub fn register_encoder<F>(encoder: F)
+where
+    F: Fn(&mut dyn Write, &[u8], u32, u32, ColorType) -> ImageResult<()> + Send + Sync + 'static,
+{
+    ENCODER.register(Box::new(encoder));
+}
+
+/// Returns if a JPEG XL decoder is registered.
+pub fn has_decoder() -> bool {
+    DECODER.is_registered()
+}
+
+/// Returns if a JPEG XL encoder is registered.
+pub fn has_encoder() -> bool {
+    ENCODER.is_registered()
+}
+
+fn unregistered(what: &str) -> ImageError {
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        ImageFormat::Jxl.into(),
+        UnsupportedErrorKind::GenericFeature(format!("no JPEG XL {} is registered", what)),
+    ))
+}
+
+/// JPEG XL decoder, which decodes with the registered decoder.
+pub struct JxlDecoder<R> {
+    image: DynamicImage,
+    marker: PhantomData<R>,
+}
+
+impl<R: Read> JxlDecoder<R> {
+    /// Decodes the image read from `r` with the registered decoder.
+    ///
+    /// Returns an `ImageError::Unsupported` if no decoder is registered.
+    pub fn new(mut r: R) -> ImageResult<JxlDecoder<R>> {
+        let decoder = DECODER.get().ok_or_else(|| unregistered("decoder"))?;
+        Ok(JxlDecoder {
+            image: decoder(&mut r)?,
+            marker: PhantomData,
+        })
+    }
+}
+
+/// The reader returned by `JxlDecoder::into_reader`.
+pub struct JxlReader<R>(Cursor<Vec<u8>>, PhantomData<R>);
+impl<R> Read for JxlReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        if self.0.position() == 0 && buf.is_empty() {
+            mem::swap(buf, self.0.get_mut());
+            Ok(buf.len())
+        } else {
+            self.0.read_to_end(buf)
+        }
+    }
+}
+
+impl<'a, R: 'a + Read> ImageDecoder<'a> for JxlDecoder<R> {
+    type Reader = JxlReader<R>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.image.width(), self.image.height())
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.image.color()
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        Ok(JxlReader(
+            Cursor::new(self.image.as_bytes().to_vec()),
+            PhantomData,
+        ))
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        buf.copy_from_slice(self.image.as_bytes());
+        Ok(())
+    }
+}
+
+/// JPEG XL encoder, which encodes with the registered encoder.
+pub struct JxlEncoder<W> {
+    w: W,
+}
+
+impl<W: Write> JxlEncoder<W> {
+    /// Create a new encoder that writes its output to `w`.
+    pub fn new(w: W) -> JxlEncoder<W> {
+        JxlEncoder { w }
+    }
+}
+
+impl<W: Write> ImageEncoder for JxlEncoder<W> {
+    /// Encodes the image with the registered encoder.
+    ///
+    /// Returns an `ImageError::Unsupported` if no encoder is registered.
+    fn write_image(
+        mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        let encoder = ENCODER.get().ok_or_else(|| unregistered("encoder"))?;
+        encoder(&mut self.w, buf, width, height, color_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayImage, ImageOutputFormat};
+
+    #[test]
+    fn registered_codec() {
+        register_encoder(|writer, buf, width, height, color| {
+            if color != ColorType::L8 {
+                return Err(ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(
+                        ImageFormat::Jxl.into(),
+                        UnsupportedErrorKind::Color(color.into()),
+                    ),
+                ));
+            }
+            // A bare codestream starts with 0xff 0x0a, followed by the size header.
+            writer.write_all(&[0xff, 0x0a])?;
+            writer.write_all(&[width as u8, height as u8])?;
+            writer.write_all(buf)?;
+            Ok(())
+        });
+        register_decoder(|reader| {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data)?;
+            let (width, height) = (u32::from(data[2]), u32::from(data[3]));
+            Ok(DynamicImage::ImageLuma8(
+                GrayImage::from_raw(width, height, data[4..].to_vec()).unwrap(),
+            ))
+        });
+        assert!(has_decoder() && has_encoder());
+        assert!(ImageFormat::Jxl.can_read() && ImageFormat::Jxl.can_write());
+
+        let image = DynamicImage::ImageLuma8(GrayImage::from_fn(3, 2, |x, y| {
+            crate::Luma([(x + 3 * y) as u8])
+        }));
+        let mut encoded = Vec::new();
+        image
+            .write_to(&mut encoded, ImageOutputFormat::Jxl)
+            .unwrap();
+        assert_eq!(crate::guess_format(&encoded).unwrap(), ImageFormat::Jxl);
+
+        let decoded = crate::load_from_memory(&encoded).unwrap();
+        assert_eq!(decoded.as_bytes(), image.as_bytes());
+    }
+}
@@ -0,0 +1,408 @@
+//!  Loading and saving cubemaps in KTX files
+//!
+//!  KTX is a container for OpenGL textures. This module reads and writes the cubemaps it holds,
+//!  with uncompressed 8-bit RGB or RGBA texels. Only the full size level of the faces is read, and
+//!  a single level is written. KTX files of other textures, and compressed texels, are not
+//!  supported, and KTX is not one of the [`ImageFormat`]s that are detected when opening a file.
+//!
+//!  # Related Links
+//!  * <https://registry.khronos.org/KTX/specs/1.0/ktxspec.v1.html> - The KTX file format, version 1.
+//!
+//!  [`ImageFormat`]: ../../enum.ImageFormat.html
+
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::{error, fmt};
+
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::cubemap::Cubemap;
+use crate::error::{
+    DecodingError, ImageError, ImageFormatHint, ImageResult, ParameterError, ParameterErrorKind,
+    UnsupportedError, UnsupportedErrorKind,
+};
+use crate::io::Limits;
+use crate::{Rgba, RgbaImage};
+
+/// The identifier at the start of every KTX file.
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+/// The endianness field as read from a file written in the byte order of the reader.
+const ENDIANNESS: u32 = 0x0403_0201;
+
+/// `GL_UNSIGNED_BYTE`
+const GL_UNSIGNED_BYTE: u32 = 0x1401;
+/// `GL_RGB`
+const GL_RGB: u32 = 0x1907;
+/// `GL_RGBA`
+const GL_RGBA: u32 = 0x1908;
+/// `GL_RGBA8`
+const GL_RGBA8: u32 = 0x8058;
+
+/// Errors that can occur during decoding and parsing a KTX file
+#[derive(Debug, Copy, Clone, Hash, PartialEq, Eq, PartialOrd, Ord)]
+enum DecoderError {
+    /// KTX identifier invalid or missing
+    IdentifierInvalid,
+    /// Neither byte order
+    EndiannessInvalid(u32),
+    /// The faces of a cubemap are not square
+    FacesNotSquare(u32, u32),
+    /// The size of a face does not match its dimensions
+    FaceSizeInvalid(u32),
+}
+
+impl fmt::Display for DecoderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecoderError::IdentifierInvalid => f.write_str("KTX identifier not found"),
+            DecoderError::EndiannessInvalid(e) => {
+                f.write_fmt(format_args!("Invalid KTX endianness: {:#010X}", e))
+            }
+            DecoderError::FacesNotSquare(w, h) => f.write_fmt(format_args!(
+                "KTX cubemap faces of {}x{} are not square",
+                w, h
+            )),
+            DecoderError::FaceSizeInvalid(s) => {
+                f.write_fmt(format_args!("Invalid KTX image size: {}", s))
+            }
+        }
+    }
+}
+
+impl From<DecoderError> for ImageError {
+    fn from(e: DecoderError) -> ImageError {
+        ImageError::Decoding(DecodingError::new(format_hint(), e))
+    }
+}
+
+impl error::Error for DecoderError {}
+
+fn format_hint() -> ImageFormatHint {
+    ImageFormatHint::Name("KTX".to_string())
+}
+
+fn unsupported(feature: String) -> ImageError {
+    ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+        format_hint(),
+        UnsupportedErrorKind::GenericFeature(feature),
+    ))
+}
+
+/// Header used by KTX files, after the identifier
+#[derive(Debug)]
+struct Header {
+    /// Whether the file was written in the other byte order.
+    swap: bool,
+    gl_type: u32,
+    gl_format: u32,
+    pixel_width: u32,
+    pixel_height: u32,
+    pixel_depth: u32,
+    number_of_array_elements: u32,
+    number_of_faces: u32,
+    bytes_of_key_value_data: u32,
+}
+
+impl Header {
+    fn from_reader(r: &mut dyn Read) -> ImageResult<Self> {
+        let endianness = r.read_u32::<LittleEndian>()?;
+        let swap = match endianness {
+            ENDIANNESS => false,
+            e if e == ENDIANNESS.swap_bytes() => true,
+            e => return Err(DecoderError::EndiannessInvalid(e).into()),
+        };
+        let mut fields = [0; 12];
+        for field in fields.iter_mut() {
+            *field = read_u32(r, swap)?;
+        }
+
+        // The type size, internal formats and mipmap level count are not needed to read the
+        // first level of uncompressed texels.
+        Ok(Self {
+            swap,
+            gl_type: fields[0],
+            gl_format: fields[2],
+            pixel_width: fields[5],
+            pixel_height: fields[6],
+            pixel_depth: fields[7],
+            number_of_array_elements: fields[8],
+            number_of_faces: fields[9],
+            bytes_of_key_value_data: fields[11],
+        })
+    }
+
+    /// The number of bytes of a texel, or `None` for unsupported formats.
+    fn bytes_per_texel(&self) -> Option<u32> {
+        match (self.gl_type, self.gl_format) {
+            (GL_UNSIGNED_BYTE, GL_RGB) => Some(3),
+            (GL_UNSIGNED_BYTE, GL_RGBA) => Some(4),
+            _ => None,
+        }
+    }
+}
+
+fn read_u32(r: &mut dyn Read, swap: bool) -> ImageResult<u32> {
+    let value = r.read_u32::<LittleEndian>()?;
+    Ok(if swap { value.swap_bytes() } else { value })
+}
+
+/// The number of bytes of a row, which are padded to a multiple of four.
+fn row_bytes(width: u32, bytes_per_texel: u32) -> u64 {
+    (u64::from(width) * u64::from(bytes_per_texel) + 3) / 4 * 4
+}
+
+/// The representation of a KTX decoder
+pub struct KtxDecoder<R: Read> {
+    r: R,
+    header: Header,
+    limits: Limits,
+}
+
+impl<R: Read> KtxDecoder<R> {
+    /// Create a new decoder that decodes from the stream `r`
+    pub fn new(mut r: R) -> ImageResult<Self> {
+        let mut identifier = [0; 12];
+        r.read_exact(&mut identifier)?;
+        if identifier != IDENTIFIER {
+            return Err(DecoderError::IdentifierInvalid.into());
+        }
+
+        let header = Header::from_reader(&mut r)?;
+        Ok(Self {
+            r,
+            header,
+            limits: Limits::no_limits(),
+        })
+    }
+
+    /// The width and height of the texture, or of each face of a cubemap.
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.header.pixel_width, self.header.pixel_height)
+    }
+
+    /// Whether the file holds the six faces of a single cubemap.
+    pub fn is_cubemap(&self) -> bool {
+        self.header.number_of_faces == 6
+            && self.header.number_of_array_elements == 0
+            && self.header.pixel_depth == 0
+    }
+
+    /// Set the limits on the faces of a cubemap, which are all decoded.
+    pub fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        self.limits = limits;
+        Ok(())
+    }
+
+    /// Decode all six faces of a cubemap.
+    ///
+    /// RGB faces are converted to RGBA. Smaller mipmap levels of the faces are not read. Returns
+    /// an `ImageError::Parameter` if the file does not hold a cubemap, and an
+    /// `ImageError::Unsupported` if its texels are not 8-bit RGB or RGBA.
+    pub fn read_cubemap(self) -> ImageResult<Cubemap<Rgba<u8>>> {
+        if !self.is_cubemap() {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic("the KTX file does not hold a cubemap".to_string()),
+            )));
+        }
+        let header = self.header;
+        let bytes_per_texel = header.bytes_per_texel().ok_or_else(|| {
+            unsupported(format!(
+                "KTX texels of type {:#06X} and format {:#06X}",
+                header.gl_type, header.gl_format
+            ))
+        })?;
+        let (width, height) = (header.pixel_width, header.pixel_height);
+        if width != height {
+            return Err(DecoderError::FacesNotSquare(width, height).into());
+        }
+        let mut limits = self.limits;
+        limits.check_dimensions(width, height)?;
+        let mut r = self.r;
+
+        let mut key_value_data = (&mut r).take(header.bytes_of_key_value_data.into());
+        std::io::copy(&mut key_value_data, &mut std::io::sink())?;
+
+        // The size of one face of the first level.
+        let image_size = read_u32(&mut r, header.swap)?;
+        let row = row_bytes(width, bytes_per_texel);
+        let face_size = row * u64::from(height);
+        if u64::from(image_size) != face_size {
+            return Err(DecoderError::FaceSizeInvalid(image_size).into());
+        }
+
+        let mut read_face = || -> ImageResult<RgbaImage> {
+            limits.reserve(face_size + u64::from(width) * u64::from(height) * 4)?;
+            let mut data = vec![0; face_size as usize];
+            r.read_exact(&mut data)?;
+            // Faces are a multiple of four bytes, so no padding follows them.
+            let mut face = RgbaImage::new(width, height);
+            for (y, line) in data.chunks_exact(row as usize).enumerate() {
+                let texels = line.chunks_exact(bytes_per_texel as usize);
+                for (x, texel) in texels.take(width as usize).enumerate() {
+                    let alpha = if bytes_per_texel == 4 { texel[3] } else { 0xFF };
+                    let pixel = Rgba([texel[0], texel[1], texel[2], alpha]);
+                    face.put_pixel(x as u32, y as u32, pixel);
+                }
+            }
+            Ok(face)
+        };
+
+        let faces = [
+            read_face()?,
+            read_face()?,
+            read_face()?,
+            read_face()?,
+            read_face()?,
+            read_face()?,
+        ];
+        Cubemap::from_faces(faces)
+    }
+}
+
+/// Encoder for cubemaps in KTX files
+pub struct KtxEncoder<W: Write> {
+    w: W,
+}
+
+impl<W: Write> KtxEncoder<W> {
+    /// Create a new encoder that writes its output to `w`
+    pub fn new(w: W) -> Self {
+        KtxEncoder { w }
+    }
+
+    /// Write the faces of a cubemap as a single level of 8-bit RGBA texels.
+    ///
+    /// The faces are stored in the order of [`CubeFace::ALL`], with the first row of each face at
+    /// the start of its data.
+    ///
+    /// [`CubeFace::ALL`]: ../../imageops/projection/enum.CubeFace.html#associatedconstant.ALL
+    pub fn write_cubemap(mut self, cubemap: &Cubemap<Rgba<u8>>) -> ImageResult<()> {
+        let size = cubemap.size();
+        let face_size = u32::try_from(row_bytes(size, 4) * u64::from(size))
+            .map_err(|_| unsupported(format!("KTX cubemap faces of {}x{}", size, size)))?;
+
+        self.w.write_all(&IDENTIFIER)?;
+        // The endianness, then the type, type size, format, internal format and base internal
+        // format, the dimensions, no array elements, six faces, one mipmap level and no key value
+        // data.
+        for &field in &[
+            ENDIANNESS,
+            GL_UNSIGNED_BYTE,
+            1,
+            GL_RGBA,
+            GL_RGBA8,
+            GL_RGBA,
+            size,
+            size,
+            0,
+            0,
+            6,
+            1,
+            0,
+        ] {
+            self.w.write_u32::<LittleEndian>(field)?;
+        }
+
+        self.w.write_u32::<LittleEndian>(face_size)?;
+        for (_, face) in cubemap.faces() {
+            // Rows of four byte texels need no padding.
+            self.w.write_all(face.as_raw())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cubemap::CubeFace;
+
+    fn numbered() -> Cubemap<Rgba<u8>> {
+        let face = |i: u8| RgbaImage::from_fn(3, 3, |x, y| Rgba([i, x as u8, y as u8, 200]));
+        Cubemap::from_faces([face(0), face(1), face(2), face(3), face(4), face(5)]).unwrap()
+    }
+
+    #[test]
+    fn cubemap_round_trip() {
+        let cubemap = numbered();
+        let mut file = Vec::new();
+        KtxEncoder::new(&mut file).write_cubemap(&cubemap).unwrap();
+        let decoder = KtxDecoder::new(&file[..]).unwrap();
+        assert!(decoder.is_cubemap());
+        assert_eq!(decoder.dimensions(), (3, 3));
+        assert_eq!(decoder.read_cubemap().unwrap(), cubemap);
+    }
+
+    /// A big endian cubemap of 3x3 RGB faces with padded rows and a key value pair.
+    fn rgb_file() -> Vec<u8> {
+        let mut file = IDENTIFIER.to_vec();
+        let push = |file: &mut Vec<u8>, value: u32| file.extend_from_slice(&value.to_be_bytes());
+        for &value in &[
+            ENDIANNESS,
+            GL_UNSIGNED_BYTE,
+            1,
+            GL_RGB,
+            0x8051,
+            GL_RGB,
+            3,
+            3,
+            0,
+            0,
+            6,
+            2,
+            8,
+        ] {
+            push(&mut file, value);
+        }
+        push(&mut file, 4);
+        file.extend_from_slice(b"a\0b\0");
+        // Rows of nine bytes are padded to twelve.
+        push(&mut file, 36);
+        for face in 0..6 {
+            for _ in 0..3 {
+                file.extend_from_slice(&[face, 1, 2, face, 1, 2, face, 1, 2, 0, 0, 0]);
+            }
+        }
+        // The second mipmap level, which is not read.
+        push(&mut file, 4);
+        file.extend_from_slice(&[0; 6 * 4]);
+        file
+    }
+
+    #[test]
+    fn rgb_faces() {
+        let file = rgb_file();
+        let cubemap = KtxDecoder::new(&file[..]).unwrap().read_cubemap().unwrap();
+        assert_eq!(cubemap.size(), 3);
+        for (face, image) in cubemap.faces() {
+            assert!(image.pixels().all(|p| *p == Rgba([face as u8, 1, 2, 255])));
+        }
+        assert_eq!(
+            cubemap.face(CubeFace::NegativeZ)[(2, 2)],
+            Rgba([5, 1, 2, 255])
+        );
+    }
+
+    #[test]
+    fn unsupported_files() {
+        assert!(KtxDecoder::new(&b"DDS "[..]).is_err());
+
+        let mut file = rgb_file();
+        // A single face is a texture, not a cubemap.
+        file[12 + 4 * 10 + 3] = 1;
+        let decoder = KtxDecoder::new(&file[..]).unwrap();
+        assert!(!decoder.is_cubemap());
+        assert!(decoder.read_cubemap().is_err());
+
+        let mut file = rgb_file();
+        // Texels of type GL_FLOAT.
+        file[12 + 4 + 2..12 + 8].copy_from_slice(&[0x14, 0x06]);
+        let result = KtxDecoder::new(&file[..]).unwrap().read_cubemap();
+        assert!(matches!(result, Err(ImageError::Unsupported(_))));
+    }
+}
@@ -26,9 +26,10 @@ use exr::prelude::*;
 
 use crate::error::{DecodingError, EncodingError, ImageFormatHint};
 use crate::image::decoder_to_vec;
+use crate::io::Limits;
 use crate::{
-    ColorType, ExtendedColorType, ImageDecoder, ImageEncoder, ImageError, ImageFormat, ImageResult,
-    Progress,
+    ColorType, DynamicImage, ExtendedColorType, ImageBuffer, ImageDecoder, ImageEncoder,
+    ImageError, ImageFormat, ImageResult, Progress,
 };
 use std::convert::TryInto;
 use std::io::{Cursor, Read, Seek, Write};
@@ -47,6 +48,9 @@ pub struct OpenExrDecoder<R> {
     alpha_preference: Option<bool>,
 
     alpha_present_in_file: bool,
+
+    /// The limits on the pixels that are decoded before they are copied to the output buffer.
+    limits: Limits,
 }
 
 impl<R: Read + Seek> OpenExrDecoder<R> {
@@ -99,6 +103,33 @@ impl<R: Read + Seek> OpenExrDecoder<R> {
             exr_reader,
             header_index,
             alpha_present_in_file: has_alpha,
+            limits: Limits::no_limits(),
+        })
+    }
+
+    /// The exposure time of the image in seconds, if the file records it.
+    pub fn exposure_time(&self) -> Option<f32> {
+        self.selected_exr_header().own_attributes.exposure
+    }
+
+    /// Decodes the image with its exposure changed by `ev` stops, that is with every color value
+    /// multiplied by 2<sup>ev</sup>. Alpha values are not changed.
+    ///
+    /// The exposure is applied while the pixels are decoded, instead of in another pass over
+    /// the decoded image. The result is an `ImageRgb32F` or `ImageRgba32F`, depending on the
+    /// alpha preference of the decoder.
+    pub fn decode_with_exposure(self, ev: f32) -> ImageResult<DynamicImage> {
+        let (width, height) = self.dimensions();
+        let color_type = self.color_type();
+        let channels = usize::from(color_type.channel_count());
+        self.limits.clone().reserve(self.total_bytes())?;
+        let mut buffer = vec![0_f32; width as usize * height as usize * channels];
+        self.read_scaled(bytemuck::cast_slice_mut(&mut buffer), ev.exp2(), |_| {})?;
+
+        Ok(if color_type == ColorType::Rgba32F {
+            DynamicImage::ImageRgba32F(ImageBuffer::from_raw(width, height, buffer).unwrap())
+        } else {
+            DynamicImage::ImageRgb32F(ImageBuffer::from_raw(width, height, buffer).unwrap())
         })
     }
 
@@ -143,6 +174,14 @@ impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for OpenExrDecoder<R> {
         Ok(Cursor::new(decoder_to_vec(self)?))
     }
 
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        self.limits = limits;
+        Ok(())
+    }
+
     fn scanline_bytes(&self) -> u64 {
         // we cannot always read individual scan lines for every file,
         // as the tiles or lines in the file could be in random or reversed order.
@@ -156,6 +195,18 @@ impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for OpenExrDecoder<R> {
         self,
         unaligned_bytes: &mut [u8],
         progress_callback: F,
+    ) -> ImageResult<()> {
+        self.read_scaled(unaligned_bytes, 1.0, progress_callback)
+    }
+}
+
+impl<R: Read + Seek> OpenExrDecoder<R> {
+    // reads the image with the color values multiplied by `scale`
+    fn read_scaled<F: Fn(Progress)>(
+        self,
+        unaligned_bytes: &mut [u8],
+        scale: f32,
+        progress_callback: F,
     ) -> ImageResult<()> {
         let blocks_in_header = self.selected_exr_header().chunk_count as u64;
         let channel_count = self.color_type().channel_count() as usize;
@@ -183,6 +234,8 @@ impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for OpenExrDecoder<R> {
                 panic!("byte buffer not large enough for the specified dimensions and f32 pixels");
             }
         }
+        // The pixels are decoded into a buffer of their own, which is copied to the output.
+        self.limits.clone().reserve(unaligned_bytes.len() as u64)?;
 
         let result = read()
             .no_deep_data()
@@ -207,7 +260,9 @@ impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for OpenExrDecoder<R> {
 
                         buffer[first_f32_index * channel_count
                             ..(first_f32_index + 1) * channel_count]
-                            .copy_from_slice(&[r, g, b, a_or_1][0..channel_count]);
+                            .copy_from_slice(
+                                &[r * scale, g * scale, b * scale, a_or_1][0..channel_count],
+                            );
 
                         // TODO white point chromaticities + srgb/linear conversion?
                     }
@@ -595,4 +650,29 @@ mod test {
         // the whole image would be printed to the console, which takes forever
         assert!(original.pixels().zip(cropped.pixels()).all(|(a, b)| a == b));
     }
+
+    #[test]
+    fn exposure() {
+        let image: Rgba32FImage =
+            ImageBuffer::from_fn(5, 3, |x, y| Rgba([x as f32, y as f32 * 0.5, -1.25, 0.75]));
+        let mut bytes = vec![];
+        write_rgba_image(Cursor::new(&mut bytes), &image).unwrap();
+
+        let decoder = OpenExrDecoder::new(Cursor::new(&bytes)).unwrap();
+        assert_eq!(decoder.exposure_time(), None);
+        let decoded = decoder.decode_with_exposure(1.0).unwrap().into_rgba32f();
+        for (original, decoded) in image.pixels().zip(decoded.pixels()) {
+            let Rgba([r, g, b, a]) = *original;
+            assert_eq!(*decoded, Rgba([2.0 * r, 2.0 * g, 2.0 * b, a]));
+        }
+
+        let decoder =
+            OpenExrDecoder::with_alpha_preference(Cursor::new(&bytes), Some(false)).unwrap();
+        let decoded = decoder.decode_with_exposure(-2.0).unwrap();
+        assert_eq!(decoded.color(), ColorType::Rgb32F);
+        assert_eq!(
+            decoded.into_rgb32f().get_pixel(4, 2),
+            &Rgb([1.0, 0.25, -0.3125])
+        );
+    }
 }
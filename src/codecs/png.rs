@@ -6,21 +6,29 @@
 //! * <http://www.w3.org/TR/PNG/> - The PNG Specification
 //!
 
+use std::borrow::Cow;
 use std::convert::TryFrom;
 use std::io::{self, Read, Write};
+use std::mem::MaybeUninit;
 
 use num_rational::Ratio;
-use png::{BlendOp, DisposeOp};
 
-use crate::animation::{Delay, Frame, Frames};
-use crate::color::{Blend, ColorType, ExtendedColorType};
+use crate::animation::{map_frames_in_order, Delay, DirtyRegion, Frame, Frames};
+use crate::color::{self, Blend, ColorType, ExtendedColorType};
 use crate::error::{
     DecodingError, ImageError, ImageResult, LimitError, LimitErrorKind, ParameterError,
     ParameterErrorKind, UnsupportedError, UnsupportedErrorKind,
 };
-use crate::image::{AnimationDecoder, ImageDecoder, ImageEncoder, ImageFormat};
+use crate::image::{
+    self, AnimationDecoder, ImageDecoder, ImageDecoderRect, ImageEncoder, ImageFormat, Progress,
+    ProgressiveDecoder, RowProgress, StreamingEncoder,
+};
+use crate::imageops::colorops::ColorMap;
+use crate::io::Limits;
+use crate::math::Rect;
+use crate::metadata::{self, Exif, MetadataMap, StripPreset, XMP_KEYWORD};
+use crate::palette::Palette;
 use crate::{DynamicImage, GenericImage, ImageBuffer, Luma, LumaA, Rgb, Rgba, RgbaImage};
-use crate::utils::ReaderUnion;
 
 // http://www.w3.org/TR/PNG-Structure.html
 // The first eight bytes of a PNG file always contain the following (decimal) values:
@@ -31,14 +39,19 @@ pub(crate) const PNG_SIGNATURE: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 /// This reader will try to read the png one row at a time,
 /// however for interlaced png files this is not possible and
 /// these are therefore read at once.
-pub struct PngReader {
-    reader: png::Reader<ReaderUnion>,
+pub struct PngReader<R: Read> {
+    reader: png::Reader<R>,
     buffer: Vec<u8>,
     index: usize,
+    /// The decoded and the requested color type, if the rows are converted.
+    conversion: Option<(ColorType, ColorType)>,
 }
 
-impl<R: Read> PngReader {
-    fn new(mut reader: png::Reader<R>) -> ImageResult<PngReader> {
+impl<R: Read> PngReader<R> {
+    fn new(
+        mut reader: png::Reader<R>,
+        conversion: Option<(ColorType, ColorType)>,
+    ) -> ImageResult<PngReader<R>> {
         let len = reader.output_buffer_size();
         // Since interlaced images do not come in
         // scanline order it is almost impossible to
@@ -50,7 +63,7 @@ impl<R: Read> PngReader {
             reader
                 .next_frame(&mut buffer)
                 .map_err(ImageError::from_png)?;
-            buffer
+            convert_row(conversion, &buffer).into_owned()
         } else {
             Vec::new()
         };
@@ -59,11 +72,12 @@ impl<R: Read> PngReader {
             reader,
             buffer,
             index: 0,
+            conversion,
         })
     }
 }
 
-impl<R: Read> Read for PngReader {
+impl<R: Read> Read for PngReader<R> {
     fn read(&mut self, mut buf: &mut [u8]) -> io::Result<usize> {
         // io::Write::write for slice cannot fail
         let readed = buf.write(&self.buffer[self.index..]).unwrap();
@@ -74,11 +88,12 @@ impl<R: Read> Read for PngReader {
         while self.index >= self.buffer.len() {
             match self.reader.next_row()? {
                 Some(row) => {
+                    let data = convert_row(self.conversion, row.data());
                     // Faster to copy directly to external buffer
-                    let readed = buf.write(row.data()).unwrap();
+                    let readed = buf.write(&data).unwrap();
                     bytes += readed;
 
-                    self.buffer = (&row.data()[readed..]).to_owned();
+                    self.buffer = (&data[readed..]).to_owned();
                     self.index = 0;
                 }
                 None => return Ok(bytes),
@@ -100,27 +115,54 @@ impl<R: Read> Read for PngReader {
         self.index = 0;
 
         while let Some(row) = self.reader.next_row()? {
-            buf.extend_from_slice(row.data());
-            bytes += row.data().len();
+            let data = convert_row(self.conversion, row.data());
+            buf.extend_from_slice(&data);
+            bytes += data.len();
         }
 
         Ok(bytes)
     }
 }
 
+/// Convert the samples of decoded rows to the requested color type, if one was requested.
+fn convert_row(conversion: Option<(ColorType, ColorType)>, data: &[u8]) -> Cow<'_, [u8]> {
+    match conversion {
+        Some((from, to)) => {
+            let pixels = data.len() / usize::from(from.bytes_per_pixel());
+            let mut converted = vec![0; pixels * usize::from(to.bytes_per_pixel())];
+            color::convert_samples(from, to, data, &mut converted);
+            Cow::Owned(converted)
+        }
+        None => Cow::Borrowed(data),
+    }
+}
+
 /// PNG decoder
 pub struct PngDecoder<R: Read> {
     color_type: ColorType,
+    /// The color type of the decoded rows, which are converted to `color_type`.
+    decoded_color_type: ColorType,
     reader: png::Reader<R>,
+    /// The limits on the buffers that are allocated next to the output buffer.
+    limits: Limits,
 }
 
 impl<R: Read> PngDecoder<R> {
     /// Creates a new decoder that decodes from the stream ```r```
     pub fn new(r: R) -> ImageResult<PngDecoder<R>> {
-        let limits = png::Limits {
-            bytes: usize::max_value(),
+        Self::with_limits(r, Limits::no_limits())
+    }
+
+    /// Creates a new decoder that decodes from the stream ```r``` within the given limits.
+    ///
+    /// The `max_alloc` limit also bounds the memory that inflating the metadata chunks and the
+    /// image data takes, which can not be limited after the header was read.
+    pub fn with_limits(r: R, limits: Limits) -> ImageResult<PngDecoder<R>> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let png_limits = png::Limits {
+            bytes: limits.max_alloc_usize(),
         };
-        let mut decoder = png::Decoder::new_with_limits(r, limits);
+        let mut decoder = png::Decoder::new_with_limits(r, png_limits);
         // By default the PNG decoder will scale 16 bpc to 8 bpc, so custom
         // transformations must be set. EXPAND preserves the default behavior
         // expanding bpc < 8 to 8 bpc.
@@ -181,7 +223,15 @@ impl<R: Read> PngDecoder<R> {
             }
         };
 
-        Ok(PngDecoder { color_type, reader })
+        let (width, height) = reader.info().size();
+        limits.check_dimensions(width, height)?;
+
+        Ok(PngDecoder {
+            color_type,
+            decoded_color_type: color_type,
+            reader,
+            limits,
+        })
     }
 
     /// Turn this into an iterator over the animation frames.
@@ -198,6 +248,20 @@ impl<R: Read> PngDecoder<R> {
         ApngDecoder::new(self)
     }
 
+    /// The decoded and the requested color type, if the decoded rows have to be converted.
+    fn conversion(&self) -> Option<(ColorType, ColorType)> {
+        if self.color_type == self.decoded_color_type {
+            None
+        } else {
+            Some((self.decoded_color_type, self.color_type))
+        }
+    }
+
+    /// Checks that the limits leave room for a buffer of `bytes` next to the output buffer.
+    fn reserve_intermediate(&self, bytes: usize) -> ImageResult<()> {
+        self.limits.clone().reserve(bytes as u64)
+    }
+
     /// Returns if the image contains an animation.
     ///
     /// Note that the file itself decides if the default image is considered to be part of the
@@ -207,105 +271,35 @@ impl<R: Read> PngDecoder<R> {
     pub fn is_apng(&self) -> bool {
         self.reader.info().animation_control.is_some()
     }
-}
-
-#[cfg(feature = "async")]
-impl<R: tokio::io::AsyncRead> PngDecoder<R> {
-    /// Creates a new decoder that decodes from the stream ```r```
-    pub async fn new(r: R) -> ImageResult<PngDecoder<R>> {
-        let limits = png::Limits {
-            bytes: usize::max_value(),
-        };
-        let mut decoder = png::Decoder::new_with_limits(r, limits);
-        // By default the PNG decoder will scale 16 bpc to 8 bpc, so custom
-        // transformations must be set. EXPAND preserves the default behavior
-        // expanding bpc < 8 to 8 bpc.
-        decoder.set_transformations(png::Transformations::EXPAND);
-        let reader = decoder.read_info().map_err(ImageError::from_png)?;
-        let (color_type, bits) = reader.output_color_type();
-        let color_type = match (color_type, bits) {
-            (png::ColorType::Grayscale, png::BitDepth::Eight) => ColorType::L8,
-            (png::ColorType::Grayscale, png::BitDepth::Sixteen) => ColorType::L16,
-            (png::ColorType::GrayscaleAlpha, png::BitDepth::Eight) => ColorType::La8,
-            (png::ColorType::GrayscaleAlpha, png::BitDepth::Sixteen) => ColorType::La16,
-            (png::ColorType::Rgb, png::BitDepth::Eight) => ColorType::Rgb8,
-            (png::ColorType::Rgb, png::BitDepth::Sixteen) => ColorType::Rgb16,
-            (png::ColorType::Rgba, png::BitDepth::Eight) => ColorType::Rgba8,
-            (png::ColorType::Rgba, png::BitDepth::Sixteen) => ColorType::Rgba16,
-
-            (png::ColorType::Grayscale, png::BitDepth::One) => {
-                return Err(unsupported_color(ExtendedColorType::L1))
-            }
-            (png::ColorType::GrayscaleAlpha, png::BitDepth::One) => {
-                return Err(unsupported_color(ExtendedColorType::La1))
-            }
-            (png::ColorType::Rgb, png::BitDepth::One) => {
-                return Err(unsupported_color(ExtendedColorType::Rgb1))
-            }
-            (png::ColorType::Rgba, png::BitDepth::One) => {
-                return Err(unsupported_color(ExtendedColorType::Rgba1))
-            }
-
-            (png::ColorType::Grayscale, png::BitDepth::Two) => {
-                return Err(unsupported_color(ExtendedColorType::L2))
-            }
-            (png::ColorType::GrayscaleAlpha, png::BitDepth::Two) => {
-                return Err(unsupported_color(ExtendedColorType::La2))
-            }
-            (png::ColorType::Rgb, png::BitDepth::Two) => {
-                return Err(unsupported_color(ExtendedColorType::Rgb2))
-            }
-            (png::ColorType::Rgba, png::BitDepth::Two) => {
-                return Err(unsupported_color(ExtendedColorType::Rgba2))
-            }
-
-            (png::ColorType::Grayscale, png::BitDepth::Four) => {
-                return Err(unsupported_color(ExtendedColorType::L4))
-            }
-            (png::ColorType::GrayscaleAlpha, png::BitDepth::Four) => {
-                return Err(unsupported_color(ExtendedColorType::La4))
-            }
-            (png::ColorType::Rgb, png::BitDepth::Four) => {
-                return Err(unsupported_color(ExtendedColorType::Rgb4))
-            }
-            (png::ColorType::Rgba, png::BitDepth::Four) => {
-                return Err(unsupported_color(ExtendedColorType::Rgba4))
-            }
-
-            (png::ColorType::Indexed, bits) => {
-                return Err(unsupported_color(ExtendedColorType::Unknown(bits as u8)))
-            }
-        };
-
-        Ok(PngDecoder { color_type, reader })
-    }
 
-    /// Turn this into an iterator over the animation frames.
-    ///
-    /// Reading the complete animation requires more memory than reading the data from the IDAT
-    /// frame–multiple frame buffers need to be reserved at the same time. We further do not
-    /// support compositing 16-bit colors. In any case this would be lossy as the interface of
-    /// animation decoders does not support 16-bit colors.
+    /// Returns the palette of an indexed image.
     ///
-    /// If something is not supported or a limit is violated then the decoding step that requires
-    /// them will fail and an error will be returned instead of the frame. No further frames will
-    /// be returned.
-    pub fn apng(self) -> ApngDecoder<R> {
-        ApngDecoder::new(self)
-    }
-
-    /// Returns if the image contains an animation.
+    /// The colors of the `PLTE` chunk are combined with the alpha values of the `tRNS` chunk,
+    /// entries without an alpha value are fully opaque. Returns `None` if the image is not indexed.
     ///
-    /// Note that the file itself decides if the default image is considered to be part of the
-    /// animation. When it is not the common interpretation is to use it as a thumbnail.
+    /// Use [`replace_palette`] to write a recolored version of the image.
     ///
-    /// If a non-animated image is converted into an `ApngDecoder` then its iterator is empty.
-    pub fn is_apng(&self) -> bool {
-        self.reader.info().animation_control.is_some()
+    /// [`replace_palette`]: fn.replace_palette.html
+    pub fn palette(&self) -> Option<Vec<Rgba<u8>>> {
+        let info = self.reader.info();
+        if info.color_type != png::ColorType::Indexed {
+            return None;
+        }
+
+        let palette = info.palette.as_ref()?;
+        let trns = info.trns.as_deref().unwrap_or(&[]);
+        let entries = palette
+            .chunks_exact(3)
+            .enumerate()
+            .map(|(i, rgb)| {
+                let alpha = trns.get(i).copied().unwrap_or(0xff);
+                Rgba([rgb[0], rgb[1], rgb[2], alpha])
+            })
+            .collect();
+        Some(entries)
     }
 }
 
-
 fn unsupported_color(ect: ExtendedColorType) -> ImageError {
     ImageError::Unsupported(UnsupportedError::from_format_and_kind(
         ImageFormat::Png.into(),
@@ -314,7 +308,7 @@ fn unsupported_color(ect: ExtendedColorType) -> ImageError {
 }
 
 impl<'a, R: 'a + Read> ImageDecoder<'a> for PngDecoder<R> {
-    type Reader = PngReader;
+    type Reader = PngReader<R>;
 
     fn dimensions(&self) -> (u32, u32) {
         self.reader.info().size()
@@ -324,28 +318,113 @@ impl<'a, R: 'a + Read> ImageDecoder<'a> for PngDecoder<R> {
         self.color_type
     }
 
+    fn original_color_type(&self) -> ExtendedColorType {
+        self.decoded_color_type.into()
+    }
+
+    fn icc_profile(&mut self) -> Option<Vec<u8>> {
+        self.reader.info().icc_profile.as_ref().map(|profile| profile.to_vec())
+    }
+
+    /// Returns the entries of the text chunks, and the XMP packet of the `iTXt` chunk with the
+    /// keyword `XML:com.adobe.xmp`. Only the chunks in front of the image data are read.
+    fn text_metadata(&mut self) -> MetadataMap {
+        let info = self.reader.info();
+        let mut metadata = MetadataMap::new();
+        for chunk in &info.uncompressed_latin1_text {
+            metadata.insert(chunk.keyword.clone(), chunk.text.clone());
+        }
+        for chunk in &info.compressed_latin1_text {
+            if let Ok(text) = chunk.get_text() {
+                metadata.insert(chunk.keyword.clone(), text);
+            }
+        }
+        for chunk in &info.utf8_text {
+            match chunk.get_text() {
+                Ok(text) if chunk.keyword == XMP_KEYWORD => metadata.set_xmp(Some(text)),
+                Ok(text) => {
+                    metadata.insert(chunk.keyword.clone(), text);
+                }
+                Err(_) => (),
+            }
+        }
+        metadata
+    }
+
+    fn request_color_type(&mut self, color_type: ColorType) -> bool {
+        let decoded = self.decoded_color_type;
+        if color_type != decoded && !color::can_convert_samples(decoded, color_type) {
+            return false;
+        }
+        self.color_type = color_type;
+        true
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        self.limits = limits;
+        Ok(())
+    }
+
     fn into_reader(self) -> ImageResult<Self::Reader> {
-        PngReader::new(self.reader)
+        if self.reader.info().interlaced {
+            self.reserve_intermediate(self.reader.output_buffer_size())?;
+        }
+        let conversion = self.conversion();
+        PngReader::new(self.reader, conversion)
     }
 
     fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
-        use byteorder::{BigEndian, ByteOrder, NativeEndian};
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        if let Some((from, to)) = self.conversion() {
+            if self.reader.info().interlaced {
+                self.reserve_intermediate(self.reader.output_buffer_size())?;
+            }
+            return read_converted(&mut self.reader, from, to, buf, &self.limits);
+        }
+        if self.limits.is_cancellable() {
+            // Decode row by row, to check for cancellation in between.
+            let (width, height) = self.dimensions();
+            let rect = (0, 0, width, height);
+            read_rect_rows(
+                &mut self.reader,
+                self.color_type,
+                rect,
+                buf,
+                |_| {},
+                &self.limits,
+            )?;
+        } else {
+            self.reader.next_frame(buf).map_err(ImageError::from_png)?;
+        }
+        to_native_endian(self.color_type, buf);
+        Ok(())
+    }
 
+    fn read_image_into_uninit(mut self, buf: &mut [MaybeUninit<u8>]) -> ImageResult<()> {
         assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
-        self.reader.next_frame(buf).map_err(ImageError::from_png)?;
-        // PNG images are big endian. For 16 bit per channel and larger types,
-        // the buffer may need to be reordered to native endianness per the
-        // contract of `read_image`.
-        // TODO: assumes equal channel bit depth.
-        let bpc = self.color_type().bytes_per_pixel() / self.color_type().channel_count();
-        match bpc {
-            1 => (), // No reodering necessary for u8
-            2 => buf.chunks_mut(2).for_each(|c| {
-                let v = BigEndian::read_u16(c);
-                NativeEndian::write_u16(c, v)
-            }),
-            _ => unreachable!(),
+        if self.conversion().is_some() {
+            return self.read_image(image::zero_uninit(buf));
         }
+        // The rows are copied out of the decoder, so the buffer needs not be initialized first.
+        let (width, height) = self.dimensions();
+        let total_bytes = buf.len() as u64;
+        let copy = |offset: usize, data: &[u8]| image::copy_to_uninit(&mut buf[offset..], data);
+        let rect = (0, 0, width, height);
+        copy_rect_rows(
+            &mut self.reader,
+            self.color_type,
+            rect,
+            total_bytes,
+            copy,
+            |_| {},
+            &self.limits,
+        )?;
+        // Safety: `copy_rect_rows` wrote every byte of the image.
+        let buf = unsafe { image::assume_init(buf) };
+        to_native_endian(self.color_type, buf);
         Ok(())
     }
 
@@ -355,6 +434,202 @@ impl<'a, R: 'a + Read> ImageDecoder<'a> for PngDecoder<R> {
     }
 }
 
+impl<'a, R: 'a + Read> ImageDecoderRect<'a> for PngDecoder<R> {
+    /// Decode a rectangular section of the image.
+    ///
+    /// The rows are decoded one at a time and only the rows up to the end of the section are
+    /// decoded. As the stream can not be rewound, the image data can only be read once: decoding
+    /// another section requires a new decoder.
+    fn read_rect_with_progress<F: Fn(Progress)>(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        buf: &mut [u8],
+        progress_callback: F,
+    ) -> ImageResult<()> {
+        let total_bytes = image::check_rect(self, x, y, width, height, buf)?;
+        let buf = &mut buf[..total_bytes];
+        if let Some((from, to)) = self.conversion() {
+            let pixels = width as usize * height as usize;
+            let decoded_bytes = pixels * usize::from(from.bytes_per_pixel());
+            self.reserve_intermediate(decoded_bytes)?;
+            let mut decoded = vec![0; decoded_bytes];
+            let rect = (x, y, width, height);
+            read_rect_rows(
+                &mut self.reader,
+                from,
+                rect,
+                &mut decoded,
+                progress_callback,
+                &self.limits,
+            )?;
+            color::convert_samples(from, to, &decoded, buf);
+            return Ok(());
+        }
+        read_rect_rows(
+            &mut self.reader,
+            self.color_type,
+            (x, y, width, height),
+            buf,
+            progress_callback,
+            &self.limits,
+        )?;
+        to_native_endian(self.color_type, buf);
+        Ok(())
+    }
+}
+
+/// Decode an image of color type `from` into `buf`, converting its samples to color type `to`.
+///
+/// The rows of images that are not interlaced are converted as they are decoded. Decoding is
+/// aborted between rows once it was cancelled with the limits.
+fn read_converted<R: Read>(
+    reader: &mut png::Reader<R>,
+    from: ColorType,
+    to: ColorType,
+    buf: &mut [u8],
+    limits: &Limits,
+) -> ImageResult<()> {
+    if reader.info().interlaced {
+        let mut decoded = vec![0; reader.output_buffer_size()];
+        let (width, height) = reader.info().size();
+        let rect = (0, 0, width, height);
+        read_rect_rows(reader, from, rect, &mut decoded, |_| {}, limits)?;
+        color::convert_samples(from, to, &decoded, buf);
+        return Ok(());
+    }
+
+    let no_more_data =
+        || ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::NoMoreData));
+    let width = reader.info().width as usize;
+    let row_bytes = width * usize::from(to.bytes_per_pixel());
+    for converted in buf.chunks_exact_mut(row_bytes) {
+        limits.check_cancelled()?;
+        let row = reader
+            .next_row()
+            .map_err(ImageError::from_png)?
+            .ok_or_else(no_more_data)?;
+        color::convert_samples(from, to, row.data(), converted);
+    }
+    Ok(())
+}
+
+/// Decode the rows of an image up to the end of a section, copying the section into `buf`.
+///
+/// The rows of an interlaced image are spread over all passes, so every pass has to be decoded
+/// but only the pixels within the section are kept. Decoding is aborted between rows once it was
+/// cancelled with the limits.
+fn read_rect_rows<R: Read, F: Fn(Progress)>(
+    reader: &mut png::Reader<R>,
+    color_type: ColorType,
+    rect: (u32, u32, u32, u32),
+    buf: &mut [u8],
+    progress_callback: F,
+    limits: &Limits,
+) -> ImageResult<()> {
+    let total_bytes = buf.len() as u64;
+    let copy = |offset: usize, data: &[u8]| buf[offset..][..data.len()].copy_from_slice(data);
+    copy_rect_rows(
+        reader,
+        color_type,
+        rect,
+        total_bytes,
+        copy,
+        progress_callback,
+        limits,
+    )
+}
+
+/// Decode the rows of an image up to the end of a section like `read_rect_rows`, passing the
+/// pixels of the section to `copy` together with their offset in the output.
+///
+/// Every byte of the section is passed once decoding succeeded, including for interlaced images.
+fn copy_rect_rows<R: Read, F: Fn(Progress)>(
+    reader: &mut png::Reader<R>,
+    color_type: ColorType,
+    (x, y, width, height): (u32, u32, u32, u32),
+    total_bytes: u64,
+    mut copy: impl FnMut(usize, &[u8]),
+    progress_callback: F,
+    limits: &Limits,
+) -> ImageResult<()> {
+    let bytes_per_pixel = usize::from(color_type.bytes_per_pixel());
+    let row_bytes = width as usize * bytes_per_pixel;
+    let no_more_data =
+        || ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::NoMoreData));
+    progress_callback(Progress::new(0, total_bytes));
+
+    let info = reader.info();
+    let (image_width, image_height) = (info.width, info.height);
+    if !info.interlaced {
+        for row in 0..y + height {
+            limits.check_cancelled()?;
+            let data = reader
+                .next_row()
+                .map_err(ImageError::from_png)?
+                .ok_or_else(no_more_data)?;
+            if row < y {
+                continue;
+            }
+            let offset = (row - y) as usize * row_bytes;
+            copy(
+                offset,
+                &data.data()[x as usize * bytes_per_pixel..][..row_bytes],
+            );
+            progress_callback(Progress::new((offset + row_bytes) as u64, total_bytes));
+        }
+        return Ok(());
+    }
+
+    for (pass, &(x0, y0, dx, dy, _, _)) in ADAM7_PASSES.iter().enumerate() {
+        // Empty passes are skipped by the decoder.
+        if x0 >= image_width || y0 >= image_height {
+            continue;
+        }
+        for row in (y0..image_height).step_by(dy as usize) {
+            limits.check_cancelled()?;
+            let data = reader
+                .next_interlaced_row()
+                .map_err(ImageError::from_png)?
+                .ok_or_else(no_more_data)?;
+            if row < y || row >= y + height {
+                continue;
+            }
+            let offset = (row - y) as usize * row_bytes;
+            let pixels = data.data().chunks_exact(bytes_per_pixel);
+            for (column, pixel) in (x0..).step_by(dx as usize).zip(pixels) {
+                if column >= x && column < x + width {
+                    let start = offset + (column - x) as usize * bytes_per_pixel;
+                    copy(start, pixel);
+                }
+            }
+        }
+        progress_callback(Progress::new(
+            total_bytes * (pass as u64 + 1) / ADAM7_PASSES.len() as u64,
+            total_bytes,
+        ));
+    }
+    Ok(())
+}
+
+/// Reorder the samples of a decoded image from the big endian order of PNG to native endianness.
+fn to_native_endian(color_type: ColorType, buf: &mut [u8]) {
+    use byteorder::{BigEndian, ByteOrder, NativeEndian};
+
+    // TODO: assumes equal channel bit depth.
+    let bpc = color_type.bytes_per_pixel() / color_type.channel_count();
+    match bpc {
+        1 => (), // No reodering necessary for u8
+        2 => buf.chunks_mut(2).for_each(|c| {
+            let v = BigEndian::read_u16(c);
+            NativeEndian::write_u16(c, v)
+        }),
+        _ => unreachable!(),
+    }
+}
+
 /// An [`AnimationDecoder`] adapter of [`PngDecoder`].
 ///
 /// See [`PngDecoder::apng`] for more information.
@@ -369,7 +644,11 @@ pub struct ApngDecoder<R: Read> {
     /// The previous output buffer, used for dispose op previous.
     previous: RgbaImage,
     /// The dispose op of the current frame.
-    dispose: DisposeOp,
+    dispose: png::DisposeOp,
+    /// The area of the current frame, which dispose op background clears.
+    dispose_region: (u32, u32, u32, u32),
+    /// The region of the canvas that each frame changes.
+    dirty: DirtyRegion,
     /// The number of image still expected to be able to load.
     remaining: u32,
     /// The next (first) image is the thumbnail.
@@ -393,7 +672,9 @@ impl<R: Read> ApngDecoder<R> {
             // TODO: should we delay this allocation? At least if we support limits we should.
             current: RgbaImage::new(width, height),
             previous: RgbaImage::new(width, height),
-            dispose: DisposeOp::Background,
+            dispose: png::DisposeOp::Background,
+            dispose_region: (0, 0, width, height),
+            dirty: DirtyRegion::new(width, height),
             remaining,
             has_thumbnail,
         }
@@ -402,7 +683,62 @@ impl<R: Read> ApngDecoder<R> {
     // TODO: thumbnail(&mut self) -> Option<impl ImageDecoder<'_>>
 
     /// Decode one subframe and overlay it on the canvas.
-    fn mix_next_frame(&mut self) -> Result<Option<&RgbaImage>, ImageError> {
+    fn mix_next_frame(&mut self) -> Result<Option<(&RgbaImage, Rect)>, ImageError> {
+        // The dispose op of the previous frame, before reading replaces it.
+        let dispose = self.dispose;
+        let (left, top, width, height) = self.dispose_region;
+        let (source, px, py, blend) = match self.next_subframe()? {
+            Some(subframe) => subframe,
+            None => return Ok(None),
+        };
+
+        // Dispose of the previous frame.
+        match dispose {
+            png::DisposeOp::None => {
+                self.previous.clone_from(&self.current);
+            }
+            png::DisposeOp::Background => {
+                self.previous.clone_from(&self.current);
+                for y in top..top + height {
+                    for x in left..left + width {
+                        self.current.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+                    }
+                }
+            }
+            png::DisposeOp::Previous => {
+                self.current.clone_from(&self.previous);
+            }
+        }
+
+        match blend {
+            png::BlendOp::Source => {
+                self.current
+                    .copy_from(&source, px, py)
+                    .expect("Invalid png image not detected in png");
+            }
+            png::BlendOp::Over => {
+                // TODO: investigate speed, speed-ups, and bounds-checks.
+                for (x, y, p) in source.enumerate_pixels() {
+                    self.current.get_pixel_mut(x + px, y + py).blend(p);
+                }
+            }
+        }
+        self.dispose_region = (px, py, source.width(), source.height());
+        let area = Rect {
+            x: px,
+            y: py,
+            width: source.width(),
+            height: source.height(),
+        };
+        let disposed = self.dispose != png::DisposeOp::None;
+        let dirty = self.dirty.next_frame(area, disposed);
+
+        // Return composited output buffer.
+        Ok(Some((&self.current, dirty)))
+    }
+
+    /// Decode one subframe, returning it with its offset on the canvas and its blend op.
+    fn next_subframe(&mut self) -> ImageResult<Option<(RgbaImage, u32, u32, png::BlendOp)>> {
         // Remove this image from remaining.
         self.remaining = match self.remaining.checked_sub(1) {
             None => return Ok(None),
@@ -425,28 +761,22 @@ impl<R: Read> ApngDecoder<R> {
 
         self.animatable_color_type()?;
 
-        // Dispose of the previous frame.
-        match self.dispose {
-            DisposeOp::None => {
-                self.previous.clone_from(&self.current);
-            }
-            DisposeOp::Background => {
-                self.previous.clone_from(&self.current);
-                self.current
-                    .pixels_mut()
-                    .for_each(|pixel| *pixel = Rgba([0, 0, 0, 0]));
-            }
-            DisposeOp::Previous => {
-                self.current.clone_from(&self.previous);
-            }
-        }
+        // The frame is decoded next to the current and the previous canvas.
+        let canvas_bytes = self.current.as_raw().len() as u64;
+        let mut limits = self.inner.limits.clone();
+        limits.reserve(2 * canvas_bytes)?;
+        limits.reserve(self.inner.reader.output_buffer_size() as u64)?;
+        limits.check_cancelled()?;
 
         // Read next frame data.
         let mut buffer = vec![0; self.inner.reader.output_buffer_size()];
-        self.inner
+        let output = self
+            .inner
             .reader
             .next_frame(&mut buffer)
             .map_err(ImageError::from_png)?;
+        // The buffer fits the whole canvas, keep only the samples of this frame.
+        buffer.truncate(output.buffer_size());
         let info = self.inner.reader.info();
 
         // Find out how to interpret the decoded frame.
@@ -457,7 +787,7 @@ impl<R: Read> ApngDecoder<R> {
                 height = info.height;
                 px = 0;
                 py = 0;
-                blend = BlendOp::Source;
+                blend = png::BlendOp::Source;
             }
             Some(fc) => {
                 width = fc.width;
@@ -470,7 +800,7 @@ impl<R: Read> ApngDecoder<R> {
         };
 
         // Turn the data into an rgba image proper.
-        let source = match self.inner.color_type {
+        let source = match self.inner.decoded_color_type {
             ColorType::L8 => {
                 let image = ImageBuffer::<Luma<_>, _>::from_raw(width, height, buffer).unwrap();
                 DynamicImage::ImageLuma8(image).into_rgba8()
@@ -491,34 +821,68 @@ impl<R: Read> ApngDecoder<R> {
             _ => unreachable!("Invalid png color"),
         };
 
-        match blend {
-            BlendOp::Source => {
-                self.current
-                    .copy_from(&source, px, py)
-                    .expect("Invalid png image not detected in png");
-            }
-            BlendOp::Over => {
-                // TODO: investigate speed, speed-ups, and bounds-checks.
-                for (x, y, p) in source.enumerate_pixels() {
-                    self.current.get_pixel_mut(x + px, y + py).blend(p);
-                }
-            }
-        }
-
         // Ok, we can proceed with actually remaining images.
         self.remaining = remaining;
-        // Return composited output buffer.
-        Ok(Some(&self.current))
+        Ok(Some((source, px, py, blend)))
+    }
+
+    /// The delay of the frame read last.
+    fn delay(&self) -> Delay {
+        let info = self.inner.reader.info();
+        let fc = info.frame_control().unwrap();
+        // PNG delays are rations in seconds.
+        let num = u32::from(fc.delay_num) * 1_000u32;
+        let denom = match fc.delay_den {
+            // The standard dictates to replace by 100 when the denominator is 0.
+            0 => 100,
+            d => u32::from(d),
+        };
+        Delay::from_ratio(Ratio::new(num, denom))
+    }
+
+    /// Describes the canvas of the frames that `frames` makes of this decoder.
+    fn canvas<'a>(self, frames: impl FnOnce(Self) -> Frames<'a>) -> Frames<'a> {
+        let (width, height) = self.current.dimensions();
+        let background = self.background_color();
+        let frames = frames(self).with_canvas(width, height);
+        match background {
+            Some(color) => frames.with_background(color),
+            None => frames,
+        }
+    }
+
+    /// The color of the `bKGD` chunk, scaled to 8 bits per sample.
+    fn background_color(&self) -> Option<Rgba<u8>> {
+        let info = self.inner.reader.info();
+        let bkgd = info.bkgd.as_deref()?;
+        if info.color_type == png::ColorType::Indexed {
+            let index = usize::from(*bkgd.first()?) * 3;
+            let rgb = info.palette.as_deref()?.get(index..index + 3)?;
+            return Some(Rgba([rgb[0], rgb[1], rgb[2], 255]));
+        }
+
+        let max = (1u32 << info.bit_depth as u8) - 1;
+        let sample = |i: usize| -> Option<u8> {
+            let value = u32::from(u16::from_be_bytes([
+                *bkgd.get(2 * i)?,
+                *bkgd.get(2 * i + 1)?,
+            ]));
+            Some(((value.min(max) * 255 + max / 2) / max) as u8)
+        };
+        match bkgd.len() {
+            2 => sample(0).map(|l| Rgba([l, l, l, 255])),
+            _ => Some(Rgba([sample(0)?, sample(1)?, sample(2)?, 255])),
+        }
     }
 
     fn animatable_color_type(&self) -> Result<(), ImageError> {
-        match self.inner.color_type {
+        match self.inner.decoded_color_type {
             ColorType::L8 | ColorType::Rgb8 | ColorType::La8 | ColorType::Rgba8 => Ok(()),
             // TODO: do not handle multi-byte colors. Remember to implement it in `mix_next_frame`.
             ColorType::L16 | ColorType::Rgb16 | ColorType::La16 | ColorType::Rgba16 => {
-                Err(unsupported_color(self.inner.color_type.into()))
+                Err(unsupported_color(self.inner.decoded_color_type.into()))
             }
-            _ => unreachable!("{:?} not a valid png color", self.inner.color_type),
+            _ => unreachable!("{:?} not a valid png color", self.inner.decoded_color_type),
         }
     }
 }
@@ -531,38 +895,349 @@ impl<'a, R: Read + 'a> AnimationDecoder<'a> for ApngDecoder<R> {
             type Item = ImageResult<Frame>;
 
             fn next(&mut self) -> Option<Self::Item> {
-                let image = match self.0.mix_next_frame() {
-                    Ok(Some(image)) => image.clone(),
+                let (image, dirty) = match self.0.mix_next_frame() {
+                    Ok(Some((image, dirty))) => (image.clone(), dirty),
                     Ok(None) => return None,
                     Err(err) => return Some(Err(err)),
                 };
+                let frame = Frame::from_parts(image, 0, 0, self.0.delay());
+                Some(Ok(frame.with_dirty_rect(dirty)))
+            }
+        }
+
+        self.canvas(|decoder| Frames::new(Box::new(FrameIterator(decoder))))
+    }
+
+    fn into_raw_frames(self) -> Frames<'a> {
+        struct RawFrameIterator<R: Read>(ApngDecoder<R>);
+
+        impl<R: Read> Iterator for RawFrameIterator<R> {
+            type Item = ImageResult<Frame>;
 
-                let info = self.0.inner.reader.info();
-                let fc = info.frame_control().unwrap();
-                // PNG delays are rations in seconds.
-                let num = u32::from(fc.delay_num) * 1_000u32;
-                let denom = match fc.delay_den {
-                    // The standard dictates to replace by 100 when the denominator is 0.
-                    0 => 100,
-                    d => u32::from(d),
+            fn next(&mut self) -> Option<Self::Item> {
+                let (image, px, py) = match self.0.next_subframe() {
+                    Ok(Some((image, px, py, _))) => (image, px, py),
+                    Ok(None) => return None,
+                    Err(err) => return Some(Err(err)),
                 };
-                let delay = Delay::from_ratio(Ratio::new(num, denom));
-                Some(Ok(Frame::from_parts(image, 0, 0, delay)))
+                Some(Ok(Frame::from_parts(image, px, py, self.0.delay())))
             }
         }
 
-        Frames::new(Box::new(FrameIterator(self)))
+        self.canvas(|decoder| Frames::new(Box::new(RawFrameIterator(decoder))))
     }
 }
 
-/// PNG encoder
-pub struct PngEncoder<W: Write> {
-    w: W,
-    compression: CompressionType,
-    filter: FilterType,
+/// The origin and spacing of the pixels of the Adam7 passes, and the size of the block each pixel
+/// covers until the following passes arrive.
+const ADAM7_PASSES: [(u32, u32, u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8, 8, 8),
+    (4, 0, 8, 8, 4, 8),
+    (0, 4, 4, 8, 4, 4),
+    (2, 0, 4, 4, 2, 4),
+    (0, 2, 2, 4, 2, 2),
+    (1, 0, 2, 2, 1, 2),
+    (0, 1, 1, 2, 1, 1),
+];
+
+/// PNG decoder that returns a refined image after each pass of an interlaced PNG.
+///
+/// Interlaced PNG images are stored in seven passes, the first of which holds every eighth pixel
+/// of every eighth row. This decoder decodes the passes as their data arrives and returns a
+/// preview in which the pixels received so far fill the blocks around them. The previews have 8
+/// bits per channel, only the complete image has the full precision of the file. Images that are
+/// not interlaced are only returned once complete.
+///
+/// See [`ProgressiveDecoder`] for its use.
+///
+/// [`ProgressiveDecoder`]: ../../trait.ProgressiveDecoder.html
+#[derive(Debug, Default)]
+pub struct ProgressivePngDecoder {
+    /// The data received so far.
+    data: Vec<u8>,
+    /// The start of the first chunk that has not been received completely.
+    chunk: usize,
+    /// The length of the data when the last preview was decoded.
+    decoded: usize,
+    /// The number of passes in the last returned preview.
+    passes: usize,
+    complete: bool,
 }
 
-/// Compression level of a PNG encoder. The default setting is `Fast`.
+impl ProgressivePngDecoder {
+    /// Creates a decoder that has not received any data yet.
+    pub fn new() -> Self {
+        ProgressivePngDecoder::default()
+    }
+
+    /// Skips the chunks received completely since the last call, up to the end of the file.
+    fn skip_chunks(&mut self) -> ImageResult<()> {
+        if self.chunk == 0 {
+            match self.data.get(..PNG_SIGNATURE.len()) {
+                Some(signature) if signature == PNG_SIGNATURE => self.chunk = PNG_SIGNATURE.len(),
+                Some(_) => {
+                    return Err(ImageError::Decoding(DecodingError::new(
+                        ImageFormat::Png.into(),
+                        "The file does not start with the PNG signature",
+                    )))
+                }
+                None => return Ok(()),
+            }
+        }
+
+        while let Some(header) = self.data.get(self.chunk..self.chunk + 8) {
+            let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+            // The length, type, data and checksum of the chunk.
+            let end = self.chunk as u64 + 12 + u64::from(length);
+            if end > self.data.len() as u64 {
+                break;
+            }
+            self.chunk = end as usize;
+            if &header[4..] == b"IEND" {
+                self.complete = true;
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Decodes the rows received so far into a preview, unless it would have no more passes than
+    /// the last one.
+    fn preview(&mut self) -> ImageResult<Option<DynamicImage>> {
+        let mut decoder = png::Decoder::new(&self.data[..]);
+        decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+        let mut reader = match decoder.read_info() {
+            Ok(reader) => reader,
+            Err(png::DecodingError::IoError(ref err))
+                if err.kind() == io::ErrorKind::UnexpectedEof =>
+            {
+                return Ok(None)
+            }
+            Err(err) => return Err(ImageError::from_png(err)),
+        };
+        let (width, height) = (reader.info().width, reader.info().height);
+        if !reader.info().interlaced {
+            return Ok(None);
+        }
+
+        let channels = match reader.output_color_type().0 {
+            png::ColorType::Grayscale => 1,
+            png::ColorType::GrayscaleAlpha => 2,
+            png::ColorType::Rgb => 3,
+            png::ColorType::Rgba => 4,
+            // Palettes are expanded.
+            png::ColorType::Indexed => unreachable!(),
+        };
+        let mut buffer = vec![0; width as usize * height as usize * channels];
+        let mut passes = 0;
+        'passes: for (pass, &(x0, y0, dx, dy, block_width, block_height)) in
+            ADAM7_PASSES.iter().enumerate()
+        {
+            // Empty passes are skipped by the decoder.
+            if x0 >= width || y0 >= height {
+                passes = pass + 1;
+                continue;
+            }
+            for y in (y0..height).step_by(dy as usize) {
+                let row = match reader.next_row() {
+                    Ok(Some(row)) => row,
+                    Ok(None) => break 'passes,
+                    Err(png::DecodingError::IoError(ref err))
+                        if err.kind() == io::ErrorKind::UnexpectedEof =>
+                    {
+                        break 'passes
+                    }
+                    Err(err) => return Err(ImageError::from_png(err)),
+                };
+                let pixels = row.data().chunks_exact(channels);
+                for (x, pixel) in (x0..width).step_by(dx as usize).zip(pixels) {
+                    for by in y..(y + block_height).min(height) {
+                        for bx in x..(x + block_width).min(width) {
+                            let index = (by as usize * width as usize + bx as usize) * channels;
+                            buffer[index..index + channels].copy_from_slice(pixel);
+                        }
+                    }
+                }
+            }
+            passes = pass + 1;
+        }
+        if passes <= self.passes {
+            return Ok(None);
+        }
+        self.passes = passes;
+
+        let image = match channels {
+            1 => ImageBuffer::from_raw(width, height, buffer).map(DynamicImage::ImageLuma8),
+            2 => ImageBuffer::from_raw(width, height, buffer).map(DynamicImage::ImageLumaA8),
+            3 => ImageBuffer::from_raw(width, height, buffer).map(DynamicImage::ImageRgb8),
+            _ => ImageBuffer::from_raw(width, height, buffer).map(DynamicImage::ImageRgba8),
+        };
+        Ok(image)
+    }
+}
+
+impl ProgressiveDecoder for ProgressivePngDecoder {
+    fn feed(&mut self, data: &[u8]) -> ImageResult<Option<DynamicImage>> {
+        if self.complete {
+            return Ok(None);
+        }
+        self.data.extend_from_slice(data);
+        self.skip_chunks()?;
+        if self.complete {
+            self.data.truncate(self.chunk);
+            let decoder = PngDecoder::new(io::Cursor::new(&self.data[..]))?;
+            return DynamicImage::from_decoder(decoder).map(Some);
+        }
+
+        // Every preview decodes the data from the start, so the data has to grow by an eighth
+        // before the next one to keep the total work linear in the size of the file.
+        if self.data.len() < self.decoded + self.decoded / 8 {
+            return Ok(None);
+        }
+        self.decoded = self.data.len();
+        self.preview()
+    }
+
+    fn is_complete(&self) -> bool {
+        self.complete
+    }
+}
+
+/// PNG encoder
+pub struct PngEncoder<W: Write> {
+    w: W,
+    compression: CompressionType,
+    filter: FilterType,
+    palette: Option<PaletteQuantization>,
+    icc_profile: Option<Vec<u8>>,
+    exif: Option<Vec<u8>>,
+    text: MetadataMap,
+}
+
+/// How colors are reduced when writing an indexed PNG, see [`PngEncoder::set_palette_quantization`].
+///
+/// [`PngEncoder::set_palette_quantization`]: struct.PngEncoder.html#method.set_palette_quantization
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PaletteQuantization {
+    /// Map each pixel to the closest palette entry.
+    Nearest,
+    /// Diffuse the quantization error with Floyd-Steinberg dithering.
+    Dither,
+}
+
+/// The palette of an indexed image with transparency.
+///
+/// The colors are quantized separately for every level of alpha, rounded to sixteen levels, so
+/// that antialiased edges keep their transparency. Fully transparent pixels share a single color.
+struct AlphaPalette {
+    /// The alpha of a level, its colors, and the index of its first color in the whole palette.
+    levels: Vec<(u8, Palette, usize)>,
+    /// The index of the fully transparent color, if any pixel is fully transparent.
+    transparent: Option<usize>,
+}
+
+impl AlphaPalette {
+    fn new(image: &RgbaImage) -> ImageResult<Self> {
+        use crate::imageops::{quantize, QuantizeAlgorithm};
+
+        // The colors of the pixels of each alpha level.
+        let mut colors: Vec<Vec<u8>> = vec![Vec::new(); 16];
+        let mut transparent = false;
+        for pixel in image.pixels() {
+            match alpha_level(pixel[3]) {
+                0 => transparent = true,
+                level => colors[usize::from(level / 17)].extend_from_slice(&pixel.0[..3]),
+            }
+        }
+
+        // Every level gets a color, and the rest of the palette is shared by the number of pixels.
+        let budget = 256 - usize::from(transparent);
+        let used = colors.iter().filter(|colors| !colors.is_empty()).count();
+        let pixels: usize = colors.iter().map(|colors| colors.len() / 3).sum();
+        let mut levels = Vec::new();
+        let mut next = usize::from(transparent);
+        for (level, colors) in colors.into_iter().enumerate() {
+            if colors.is_empty() {
+                continue;
+            }
+            let max_colors = 1 + (budget - used) * (colors.len() / 3) / pixels;
+            let width = (colors.len() / 3) as u32;
+            let colors = ImageBuffer::from_raw(width, 1, colors).unwrap();
+            let (palette, _) = quantize(&colors, max_colors, QuantizeAlgorithm::MedianCut)?;
+            let len = palette.len();
+            levels.push((level as u8 * 17, palette, next));
+            next += len;
+        }
+
+        Ok(AlphaPalette {
+            levels,
+            transparent: if transparent { Some(0) } else { None },
+        })
+    }
+
+    /// The colors of the whole palette.
+    fn colors(&self) -> Vec<Rgba<u8>> {
+        let mut colors = Vec::new();
+        if self.transparent.is_some() {
+            colors.push(Rgba([0, 0, 0, 0]));
+        }
+        for (alpha, palette, _) in &self.levels {
+            colors.extend(
+                palette
+                    .colors()
+                    .iter()
+                    .map(|c| Rgba([c[0], c[1], c[2], *alpha])),
+            );
+        }
+        colors
+    }
+
+    /// The level with the alpha nearest to that of `color`, unless it is fully transparent.
+    fn level(&self, color: &Rgba<u8>) -> Option<&(u8, Palette, usize)> {
+        let alpha = alpha_level(color[3]);
+        if alpha == 0 && self.transparent.is_some() {
+            return None;
+        }
+        self.levels
+            .iter()
+            .min_by_key(|(level, _, _)| (i16::from(*level) - i16::from(alpha)).abs())
+    }
+}
+
+fn rgb(color: &Rgba<u8>) -> Rgb<u8> {
+    Rgb([color[0], color[1], color[2]])
+}
+
+/// Round alpha to the nearest of sixteen levels from 0 to 255.
+fn alpha_level(alpha: u8) -> u8 {
+    ((u16::from(alpha) + 8) / 17 * 17) as u8
+}
+
+impl ColorMap for AlphaPalette {
+    type Color = Rgba<u8>;
+
+    fn index_of(&self, color: &Rgba<u8>) -> usize {
+        match self.level(color) {
+            Some((_, palette, start)) => start + palette.index_of(&rgb(color)),
+            None => self.transparent.unwrap_or(0),
+        }
+    }
+
+    /// Maps the color to the nearest color of its alpha level. Fully transparent colors keep
+    /// their color channels, so that dithering does not spread them to the visible pixels.
+    fn map_color(&self, color: &mut Rgba<u8>) {
+        match self.level(color) {
+            Some((alpha, palette, _)) => {
+                let mut mapped = rgb(color);
+                palette.map_color(&mut mapped);
+                *color = Rgba([mapped[0], mapped[1], mapped[2], *alpha]);
+            }
+            None => color[3] = 0,
+        }
+    }
+}
+
+/// Compression level of a PNG encoder. The default setting is `Fast`.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 #[non_exhaustive]
 pub enum CompressionType {
@@ -607,6 +1282,10 @@ impl<W: Write> PngEncoder<W> {
             w,
             compression: CompressionType::default(),
             filter: FilterType::default(),
+            palette: None,
+            icc_profile: None,
+            exif: None,
+            text: MetadataMap::new(),
         }
     }
 
@@ -631,66 +1310,309 @@ impl<W: Write> PngEncoder<W> {
             w,
             compression,
             filter,
+            palette: None,
+            icc_profile: None,
+            exif: None,
+            text: MetadataMap::new(),
         }
     }
 
+    /// Write 8-bit RGB and RGBA images as an indexed PNG with at most 256 colors.
+    ///
+    /// An image with no more than 256 distinct colors is stored without any loss. Otherwise its
+    /// colors are reduced with [`imageops::quantize`], separately for the pixels of each level of
+    /// alpha, using `quantization` to map pixels onto the resulting palette. Transparency is kept
+    /// as a `tRNS` chunk. Images of other color types are written as usual. This often shrinks
+    /// the output considerably, at the cost of color fidelity for images with many colors.
+    ///
+    /// [`imageops::quantize`]: ../../imageops/fn.quantize.html
+    pub fn set_palette_quantization(&mut self, quantization: PaletteQuantization) {
+        self.palette = Some(quantization);
+    }
+
     /// Encodes the image `data` that has dimensions `width` and `height` and `ColorType` `c`.
     ///
     /// Expects data in big endian.
     #[deprecated = "Use `PngEncoder::write_image` instead. Beware that `write_image` has a different endianness convention"]
     pub fn encode(self, data: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<()> {
-        let (ct, bits) = match color {
-            ColorType::L8 => (png::ColorType::Grayscale, png::BitDepth::Eight),
-            ColorType::L16 => (png::ColorType::Grayscale, png::BitDepth::Sixteen),
-            ColorType::La8 => (png::ColorType::GrayscaleAlpha, png::BitDepth::Eight),
-            ColorType::La16 => (png::ColorType::GrayscaleAlpha, png::BitDepth::Sixteen),
-            ColorType::Rgb8 => (png::ColorType::Rgb, png::BitDepth::Eight),
-            ColorType::Rgb16 => (png::ColorType::Rgb, png::BitDepth::Sixteen),
-            ColorType::Rgba8 => (png::ColorType::Rgba, png::BitDepth::Eight),
-            ColorType::Rgba16 => (png::ColorType::Rgba, png::BitDepth::Sixteen),
+        let (ct, bits) = png_color_type(color)?;
+        self.write_png(data, width, height, ct, bits, None)
+    }
+
+    /// Start writing an image of `width`, `height` and `color_type` row by row, see
+    /// [`StreamingEncoder`].
+    ///
+    /// The rows are filtered and compressed as they are written, so only the current and the
+    /// previous row are kept. Palette quantization needs all colors of the image and is not
+    /// applied. The `png` crate can only stream into writers that it owns, such as files.
+    ///
+    /// [`StreamingEncoder`]: ../../trait.StreamingEncoder.html
+    pub fn streaming(
+        self,
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<StreamingPngEncoder<W>>
+    where
+        W: 'static,
+    {
+        let (ct, bits) = png_color_type(color_type)?;
+        let progress = RowProgress::new(width, height, color_type)?;
+        let writer = self
+            .write_header(width, height, ct, bits, None)?
+            .into_stream_writer()
+            .map_err(|e| ImageError::IoError(e.into()))?;
+        Ok(StreamingPngEncoder {
+            writer,
+            progress,
+            sixteen_bit: bits == png::BitDepth::Sixteen,
+        })
+    }
+
+    /// Quantize an 8-bit RGB or RGBA image to a palette and write it as an indexed PNG.
+    fn encode_indexed(
+        self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        quantization: PaletteQuantization,
+    ) -> ImageResult<()> {
+        use crate::buffer_::ConvertBuffer;
+        use crate::imageops::dither::{dither_with, DitherAlgorithm};
+        use crate::imageops::index_colors;
+        use std::collections::HashMap;
+
+        let image: Option<RgbaImage> = match color_type {
+            ColorType::Rgb8 => {
+                ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, buf).map(|image| image.convert())
+            }
+            ColorType::Rgba8 => ImageBuffer::from_raw(width, height, buf.to_vec()),
+            _ => unreachable!(),
+        };
+        let mut image = image.ok_or_else(|| {
+            ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            ))
+        })?;
+
+        // Keep the exact colors if there are few enough of them.
+        let mut exact: HashMap<Rgba<u8>, u8> = HashMap::new();
+        let mut palette = Vec::new();
+        for pixel in image.pixels() {
+            if palette.len() > 256 {
+                break;
+            }
+            if !exact.contains_key(pixel) {
+                exact.insert(*pixel, palette.len() as u8);
+                palette.push(*pixel);
+            }
+        }
+
+        let indices = if palette.len() <= 256 {
+            image.pixels().map(|pixel| exact[pixel]).collect()
+        } else {
+            let color_map = AlphaPalette::new(&image)?;
+            if quantization == PaletteQuantization::Dither {
+                dither_with(&mut image, &color_map, DitherAlgorithm::FloydSteinberg);
+            }
+            palette = color_map.colors();
+            index_colors(&image, &color_map).into_raw()
+        };
+
+        self.write_indexed(&indices, width, height, &palette)
+    }
+
+    /// Write an 8-bit RGB or RGBA image as an indexed PNG with exactly the colors of `palette`,
+    /// in their order.
+    ///
+    /// Every pixel must be one of the at most 256 colors of the palette.
+    pub(crate) fn encode_with_palette(
+        self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+        palette: &[Rgba<u8>],
+    ) -> ImageResult<()> {
+        use std::collections::HashMap;
+
+        let channels = match color_type {
+            ColorType::Rgb8 => 3,
+            ColorType::Rgba8 => 4,
             _ => {
                 return Err(ImageError::Unsupported(
                     UnsupportedError::from_format_and_kind(
                         ImageFormat::Png.into(),
-                        UnsupportedErrorKind::Color(color.into()),
+                        UnsupportedErrorKind::Color(color_type.into()),
                     ),
                 ))
             }
         };
-        let comp = match self.compression {
-            CompressionType::Default => png::Compression::Default,
-            CompressionType::Fast => png::Compression::Fast,
-            CompressionType::Best => png::Compression::Best,
-            CompressionType::Huffman => png::Compression::Huffman,
-            CompressionType::Rle => png::Compression::Rle,
-        };
-        let (filter, adaptive_filter) = match self.filter {
-            FilterType::NoFilter => (
-                png::FilterType::NoFilter,
-                png::AdaptiveFilterType::NonAdaptive,
-            ),
-            FilterType::Sub => (png::FilterType::Sub, png::AdaptiveFilterType::NonAdaptive),
-            FilterType::Up => (png::FilterType::Up, png::AdaptiveFilterType::NonAdaptive),
-            FilterType::Avg => (png::FilterType::Avg, png::AdaptiveFilterType::NonAdaptive),
-            FilterType::Paeth => (png::FilterType::Paeth, png::AdaptiveFilterType::NonAdaptive),
-            FilterType::Adaptive => (png::FilterType::Sub, png::AdaptiveFilterType::Adaptive),
-        };
+        if palette.is_empty() || palette.len() > 256 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!(
+                    "the palette of a PNG image holds 1 to 256 colors, found {}",
+                    palette.len()
+                )),
+            )));
+        }
+
+        let mut index_of: HashMap<Rgba<u8>, u8> = HashMap::new();
+        for (i, color) in palette.iter().enumerate() {
+            index_of.entry(*color).or_insert(i as u8);
+        }
+        let indices = buf
+            .chunks_exact(channels)
+            .map(|p| {
+                let alpha = if channels == 4 { p[3] } else { 0xFF };
+                index_of.get(&Rgba([p[0], p[1], p[2], alpha])).copied()
+            })
+            .collect::<Option<Vec<u8>>>()
+            .ok_or_else(|| {
+                ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                    "the image has colors that are not in the palette".to_string(),
+                )))
+            })?;
+
+        self.write_indexed(&indices, width, height, palette)
+    }
+
+    /// Write palette indices as an indexed PNG, with a `tRNS` chunk for translucent entries.
+    fn write_indexed(
+        self,
+        indices: &[u8],
+        width: u32,
+        height: u32,
+        palette: &[Rgba<u8>],
+    ) -> ImageResult<()> {
+        let plte = palette.iter().flat_map(|p| p.0[..3].to_vec()).collect();
+        let mut trns: Vec<u8> = palette.iter().map(|p| p.0[3]).collect();
+        while trns.last() == Some(&0xFF) {
+            trns.pop();
+        }
+
+        self.write_png(
+            indices,
+            width,
+            height,
+            png::ColorType::Indexed,
+            png::BitDepth::Eight,
+            Some((plte, trns)),
+        )
+    }
 
-        let mut encoder = png::Encoder::new(self.w, width, height);
+    fn write_png(
+        self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        ct: png::ColorType,
+        bits: png::BitDepth,
+        palette: Option<(Vec<u8>, Vec<u8>)>,
+    ) -> ImageResult<()> {
+        self.write_header(width, height, ct, bits, palette)?
+            .write_image_data(data)
+            .map_err(|e| ImageError::IoError(e.into()))
+    }
+
+    /// Write the chunks before the image data, and return the writer for the image data.
+    fn write_header(
+        self,
+        width: u32,
+        height: u32,
+        ct: png::ColorType,
+        bits: png::BitDepth,
+        palette: Option<(Vec<u8>, Vec<u8>)>,
+    ) -> ImageResult<png::Writer<W>> {
+        let (comp, filter, adaptive_filter) = png_quality(self.compression, self.filter);
+
+        let mut info = png::Info::with_size(width, height);
+        info.icc_profile = self.icc_profile.map(Cow::Owned);
+        info.exif_metadata = self.exif.map(Cow::Owned);
+        for (keyword, text) in self.text.iter() {
+            // Text that Latin-1 can not represent is stored as UTF-8.
+            if text.chars().all(|c| u32::from(c) <= 0xff) {
+                let chunk = png::text_metadata::TEXtChunk::new(keyword, text);
+                info.uncompressed_latin1_text.push(chunk);
+            } else {
+                let chunk = png::text_metadata::ITXtChunk::new(keyword, text);
+                info.utf8_text.push(chunk);
+            }
+        }
+        if let Some(xmp) = self.text.xmp() {
+            let chunk = png::text_metadata::ITXtChunk::new(XMP_KEYWORD, xmp);
+            info.utf8_text.push(chunk);
+        }
+        let mut encoder =
+            png::Encoder::with_info(self.w, info).map_err(|e| ImageError::IoError(e.into()))?;
         encoder.set_color(ct);
         encoder.set_depth(bits);
         encoder.set_compression(comp);
         encoder.set_filter(filter);
         encoder.set_adaptive_filter(adaptive_filter);
-        let mut writer = encoder
+        if let Some((plte, trns)) = palette {
+            encoder.set_palette(plte);
+            if !trns.is_empty() {
+                encoder.set_trns(trns);
+            }
+        }
+        encoder
             .write_header()
-            .map_err(|e| ImageError::IoError(e.into()))?;
-        writer
-            .write_image_data(data)
             .map_err(|e| ImageError::IoError(e.into()))
     }
 }
 
+/// The PNG color type and bit depth that samples of `color` are written as.
+fn png_color_type(color: ColorType) -> ImageResult<(png::ColorType, png::BitDepth)> {
+    Ok(match color {
+        ColorType::L8 => (png::ColorType::Grayscale, png::BitDepth::Eight),
+        ColorType::L16 => (png::ColorType::Grayscale, png::BitDepth::Sixteen),
+        ColorType::La8 => (png::ColorType::GrayscaleAlpha, png::BitDepth::Eight),
+        ColorType::La16 => (png::ColorType::GrayscaleAlpha, png::BitDepth::Sixteen),
+        ColorType::Rgb8 => (png::ColorType::Rgb, png::BitDepth::Eight),
+        ColorType::Rgb16 => (png::ColorType::Rgb, png::BitDepth::Sixteen),
+        ColorType::Rgba8 => (png::ColorType::Rgba, png::BitDepth::Eight),
+        ColorType::Rgba16 => (png::ColorType::Rgba, png::BitDepth::Sixteen),
+        _ => {
+            return Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    ImageFormat::Png.into(),
+                    UnsupportedErrorKind::Color(color.into()),
+                ),
+            ))
+        }
+    })
+}
+
+/// The settings of the `png` encoder for a compression and filter type.
+fn png_quality(
+    compression: CompressionType,
+    filter: FilterType,
+) -> (png::Compression, png::FilterType, png::AdaptiveFilterType) {
+    let compression = match compression {
+        CompressionType::Default => png::Compression::Default,
+        CompressionType::Fast => png::Compression::Fast,
+        CompressionType::Best => png::Compression::Best,
+        #[allow(deprecated)]
+        CompressionType::Huffman => png::Compression::Huffman,
+        #[allow(deprecated)]
+        CompressionType::Rle => png::Compression::Rle,
+    };
+    let (filter, adaptive_filter) = match filter {
+        FilterType::NoFilter => (
+            png::FilterType::NoFilter,
+            png::AdaptiveFilterType::NonAdaptive,
+        ),
+        FilterType::Sub => (png::FilterType::Sub, png::AdaptiveFilterType::NonAdaptive),
+        FilterType::Up => (png::FilterType::Up, png::AdaptiveFilterType::NonAdaptive),
+        FilterType::Avg => (png::FilterType::Avg, png::AdaptiveFilterType::NonAdaptive),
+        FilterType::Paeth => (png::FilterType::Paeth, png::AdaptiveFilterType::NonAdaptive),
+        FilterType::Adaptive => (png::FilterType::Sub, png::AdaptiveFilterType::Adaptive),
+    };
+    (compression, filter, adaptive_filter)
+}
+
 impl<W: Write> ImageEncoder for PngEncoder<W> {
     /// Write a PNG image with the specified width, height, and color type.
     ///
@@ -706,6 +1628,12 @@ impl<W: Write> ImageEncoder for PngEncoder<W> {
     ) -> ImageResult<()> {
         use byteorder::{BigEndian, ByteOrder, NativeEndian};
 
+        if let Some(quantization) = self.palette {
+            if let ColorType::Rgb8 | ColorType::Rgba8 = color_type {
+                return self.encode_indexed(buf, width, height, color_type, quantization);
+            }
+        }
+
         // PNG images are big endian. For 16 bit per channel and larger types,
         // the buffer may need to be reordered to big endian per the
         // contract of `write_image`.
@@ -713,7 +1641,6 @@ impl<W: Write> ImageEncoder for PngEncoder<W> {
         let bpc = color_type.bytes_per_pixel() / color_type.channel_count();
         #[allow(deprecated)]
         match bpc {
-            1 => self.encode(buf, width, height, color_type), // No reodering necessary for u8
             2 => {
                 // Because the buffer is immutable and the PNG encoder does not
                 // yet take Write/Read traits, create a temporary buffer for
@@ -724,9 +1651,712 @@ impl<W: Write> ImageEncoder for PngEncoder<W> {
                     .for_each(|(b, r)| BigEndian::write_u16(r, NativeEndian::read_u16(b)));
                 self.encode(&reordered, width, height, color_type)
             }
-            _ => unreachable!(),
+            // No reodering necessary for u8, and `encode` rejects the float types.
+            _ => self.encode(buf, width, height, color_type),
+        }
+    }
+
+    /// Embed the profile in an `iCCP` chunk.
+    fn set_icc_profile(&mut self, icc_profile: Vec<u8>) -> ImageResult<()> {
+        self.icc_profile = Some(icc_profile);
+        Ok(())
+    }
+
+    /// Embed the metadata in an `eXIf` chunk.
+    fn set_exif_metadata(&mut self, exif: &Exif) -> ImageResult<()> {
+        self.exif = Some(exif.to_bytes());
+        Ok(())
+    }
+
+    /// Embed the entries in `tEXt` chunks, or in `iTXt` chunks if their text is not Latin-1, and
+    /// the XMP packet in an `iTXt` chunk.
+    ///
+    /// Returns an error if a keyword is not 1 to 79 Latin-1 characters long.
+    fn set_text_metadata(&mut self, metadata: &MetadataMap) -> ImageResult<()> {
+        for (keyword, _) in metadata.iter() {
+            let latin1 = keyword.chars().all(|c| u32::from(c) <= 0xff);
+            if !latin1 || !(1..=79).contains(&keyword.chars().count()) {
+                return Err(ImageError::Parameter(ParameterError::from_kind(
+                    ParameterErrorKind::Generic(format!("invalid PNG keyword {:?}", keyword)),
+                )));
+            }
+        }
+        self.text = metadata.clone();
+        Ok(())
+    }
+}
+
+/// PNG encoder that writes an image row by row, created by [`PngEncoder::streaming`].
+///
+/// [`PngEncoder::streaming`]: struct.PngEncoder.html#method.streaming
+pub struct StreamingPngEncoder<W: Write + 'static> {
+    writer: png::StreamWriter<'static, W>,
+    progress: RowProgress,
+    sixteen_bit: bool,
+}
+
+impl<W: Write + 'static> StreamingEncoder for StreamingPngEncoder<W> {
+    fn write_rows(&mut self, buf: &[u8], rows: u32) -> ImageResult<()> {
+        self.progress.advance(buf, rows)?;
+        let result = if self.sixteen_bit {
+            // PNG images are big endian, reorder the samples a row at a time.
+            let mut reordered = vec![0; self.progress.row_bytes()];
+            buf.chunks_exact(reordered.len()).try_for_each(|row| {
+                for (sample, reordered) in row.chunks_exact(2).zip(reordered.chunks_exact_mut(2)) {
+                    let sample = u16::from_ne_bytes([sample[0], sample[1]]);
+                    reordered.copy_from_slice(&sample.to_be_bytes());
+                }
+                self.writer.write_all(&reordered)
+            })
+        } else {
+            self.writer.write_all(buf)
+        };
+        result.map_err(ImageError::IoError)
+    }
+
+    fn finish(self) -> ImageResult<()> {
+        self.progress.finish()?;
+        self.writer
+            .finish()
+            .map_err(|e| ImageError::IoError(e.into()))
+    }
+}
+
+/// How the area of an APNG frame is treated before the next frame is rendered.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DisposeOp {
+    /// Leave the canvas as it is.
+    None,
+    /// Clear the area of the frame to transparent black.
+    Background,
+    /// Restore the area of the frame to what it was before the frame was rendered.
+    Previous,
+}
+
+/// How an APNG frame is combined with the canvas.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BlendOp {
+    /// Replace the pixels of the canvas, including their alpha.
+    Source,
+    /// Composite the frame over the canvas, according to its alpha.
+    Over,
+}
+
+/// APNG encoder, writing animations as 8-bit RGBA.
+///
+/// The first frame defines the size of the canvas and must be placed at its origin, all following
+/// frames must fit onto it. Frames are written when the animation is finished, as the number of
+/// frames is part of the header.
+pub struct ApngEncoder<W: Write> {
+    w: W,
+    compression: CompressionType,
+    filter: FilterType,
+    loop_count: u32,
+    frames: Vec<(Frame, DisposeOp, BlendOp)>,
+}
+
+impl<W: Write> ApngEncoder<W> {
+    /// Create a new encoder that writes its output to ```w```.
+    ///
+    /// The animation loops forever unless set otherwise with [`set_loop_count`].
+    ///
+    /// [`set_loop_count`]: #method.set_loop_count
+    pub fn new(w: W) -> ApngEncoder<W> {
+        Self::new_with_quality(w, CompressionType::default(), FilterType::default())
+    }
+
+    /// Create a new encoder that writes its output to `w` with `CompressionType` `compression` and
+    /// `FilterType` `filter`. See [`PngEncoder::new_with_quality`] for their meaning.
+    ///
+    /// [`PngEncoder::new_with_quality`]: struct.PngEncoder.html#method.new_with_quality
+    pub fn new_with_quality(
+        w: W,
+        compression: CompressionType,
+        filter: FilterType,
+    ) -> ApngEncoder<W> {
+        ApngEncoder {
+            w,
+            compression,
+            filter,
+            loop_count: 0,
+            frames: Vec::new(),
         }
     }
+
+    /// Set how often the animation is played, where `0` means that it loops forever.
+    pub fn set_loop_count(&mut self, loop_count: u32) {
+        self.loop_count = loop_count;
+    }
+
+    /// Encode one frame of animation, which replaces the area of the canvas it covers and is
+    /// left on the canvas afterwards.
+    pub fn encode_frame(&mut self, frame: Frame) -> ImageResult<()> {
+        self.encode_frame_with_ops(frame, DisposeOp::None, BlendOp::Source)
+    }
+
+    /// Encode one frame of animation with the given disposal and blending.
+    pub fn encode_frame_with_ops(
+        &mut self,
+        frame: Frame,
+        dispose: DisposeOp,
+        blend: BlendOp,
+    ) -> ImageResult<()> {
+        let (width, height) = frame.buffer().dimensions();
+        let fits = match self.frames.first() {
+            None => frame.left() == 0 && frame.top() == 0,
+            Some((first, _, _)) => {
+                let (canvas_width, canvas_height) = first.buffer().dimensions();
+                u64::from(frame.left()) + u64::from(width) <= u64::from(canvas_width)
+                    && u64::from(frame.top()) + u64::from(height) <= u64::from(canvas_height)
+            }
+        };
+        if !fits || width == 0 || height == 0 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+
+        self.frames.push((frame, dispose, blend));
+        Ok(())
+    }
+
+    /// Encodes Frames.
+    /// Consider using `try_encode_frames` instead to encode an `animation::Frames` like iterator.
+    pub fn encode_frames<F>(&mut self, frames: F) -> ImageResult<()>
+    where
+        F: IntoIterator<Item = Frame>,
+    {
+        for frame in frames {
+            self.encode_frame(frame)?;
+        }
+        Ok(())
+    }
+
+    /// Try to encode a collection of `ImageResult<animation::Frame>` objects.
+    /// Use this function to encode an `animation::Frames` like iterator.
+    /// Whenever an `Err` item is encountered, that value is returned without further actions.
+    pub fn try_encode_frames<F>(&mut self, frames: F) -> ImageResult<()>
+    where
+        F: IntoIterator<Item = ImageResult<Frame>>,
+    {
+        for frame in frames {
+            self.encode_frame(frame?)?;
+        }
+        Ok(())
+    }
+
+    /// Write the animation.
+    ///
+    /// With the `rayon` feature, several frames are compressed in parallel.
+    ///
+    /// Returns an error if no frame was encoded.
+    pub fn finish(self) -> ImageResult<()> {
+        use byteorder::{BigEndian, WriteBytesExt};
+
+        let (canvas_width, canvas_height) = match self.frames.first() {
+            Some((first, _, _)) => first.buffer().dimensions(),
+            None => {
+                return Err(ImageError::Parameter(ParameterError::from_kind(
+                    ParameterErrorKind::NoMoreData,
+                )))
+            }
+        };
+        let num_frames = u32::try_from(self.frames.len()).map_err(|_| {
+            ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                "an APNG image can not have more than u32::MAX frames".into(),
+            )))
+        })?;
+
+        let mut w = self.w;
+        w.write_all(&PNG_SIGNATURE)?;
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.write_u32::<BigEndian>(canvas_width)?;
+        ihdr.write_u32::<BigEndian>(canvas_height)?;
+        // 8-bit RGBA, deflate compressed, filtered per row and not interlaced.
+        ihdr.extend_from_slice(&[8, 6, 0, 0, 0]);
+        write_chunk(&mut w, b"IHDR", &ihdr)?;
+        let mut actl = Vec::with_capacity(8);
+        actl.write_u32::<BigEndian>(num_frames)?;
+        actl.write_u32::<BigEndian>(self.loop_count)?;
+        write_chunk(&mut w, b"acTL", &actl)?;
+
+        // The frame control chunks and the data chunks of later frames share one sequence.
+        let mut sequence_number = 0u32;
+        let (compression, filter) = (self.compression, self.filter);
+        map_frames_in_order(
+            self.frames.iter().enumerate(),
+            |(index, frame)| Ok((index, frame, compress_rgba(&frame.0, compression, filter)?)),
+            |(index, (frame, dispose, blend), data)| {
+                let (width, height) = frame.buffer().dimensions();
+                let (numerator, denominator) = apng_delay(frame.delay());
+                let mut fctl = Vec::with_capacity(26);
+                fctl.write_u32::<BigEndian>(sequence_number)?;
+                fctl.write_u32::<BigEndian>(width)?;
+                fctl.write_u32::<BigEndian>(height)?;
+                fctl.write_u32::<BigEndian>(frame.left())?;
+                fctl.write_u32::<BigEndian>(frame.top())?;
+                fctl.write_u16::<BigEndian>(numerator)?;
+                fctl.write_u16::<BigEndian>(denominator)?;
+                fctl.push(match dispose {
+                    DisposeOp::None => 0,
+                    DisposeOp::Background => 1,
+                    DisposeOp::Previous => 2,
+                });
+                fctl.push(match blend {
+                    BlendOp::Source => 0,
+                    BlendOp::Over => 1,
+                });
+                write_chunk(&mut w, b"fcTL", &fctl)?;
+                sequence_number += 1;
+
+                for part in data.chunks(MAX_DATA_CHUNK_LEN) {
+                    if index == 0 {
+                        write_chunk(&mut w, b"IDAT", part)?;
+                    } else {
+                        let mut fdat = Vec::with_capacity(4 + part.len());
+                        fdat.write_u32::<BigEndian>(sequence_number)?;
+                        fdat.extend_from_slice(part);
+                        write_chunk(&mut w, b"fdAT", &fdat)?;
+                        sequence_number += 1;
+                    }
+                }
+                Ok(())
+            },
+        )?;
+        write_chunk(&mut w, b"IEND", &[])?;
+        Ok(())
+    }
+}
+
+/// The largest part of the compressed data of a frame that is written into one chunk.
+const MAX_DATA_CHUNK_LEN: usize = 1 << 20;
+
+/// Compress the rows of an 8-bit RGBA frame into the zlib stream of a PNG image.
+///
+/// The frame is encoded as an image of its own, whose data chunks are then joined, so that frames
+/// can be compressed independently of each other.
+fn compress_rgba(
+    frame: &Frame,
+    compression: CompressionType,
+    filter: FilterType,
+) -> ImageResult<Vec<u8>> {
+    let png_error = |err: png::EncodingError| ImageError::IoError(err.into());
+    let (width, height) = frame.buffer().dimensions();
+    let mut encoded = Vec::new();
+    let mut encoder = png::Encoder::new(&mut encoded, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    let (compression, filter, adaptive_filter) = png_quality(compression, filter);
+    encoder.set_compression(compression);
+    encoder.set_filter(filter);
+    encoder.set_adaptive_filter(adaptive_filter);
+    let mut writer = encoder.write_header().map_err(png_error)?;
+    writer
+        .write_image_data(frame.buffer().as_raw())
+        .map_err(png_error)?;
+    writer.finish().map_err(png_error)?;
+
+    let mut data = Vec::new();
+    let mut chunks = &encoded[PNG_SIGNATURE.len()..];
+    while chunks.len() >= 12 {
+        let length = u32::from_be_bytes([chunks[0], chunks[1], chunks[2], chunks[3]]) as usize;
+        let chunk = chunks.get(8..8 + length).unwrap_or_default();
+        if &chunks[4..8] == b"IDAT" {
+            data.extend_from_slice(chunk);
+        }
+        chunks = chunks.get(12 + length..).unwrap_or_default();
+    }
+    Ok(data)
+}
+
+/// The delay of an APNG frame as a fraction of seconds.
+fn apng_delay(delay: Delay) -> (u16, u16) {
+    let (numer, denom) = delay.numer_denom_ms();
+    let seconds = Ratio::new(u64::from(numer), u64::from(denom) * 1000);
+    if let (Ok(numer), Ok(denom)) = (
+        u16::try_from(*seconds.numer()),
+        u16::try_from(*seconds.denom()),
+    ) {
+        return (numer, denom);
+    }
+
+    // Round to milliseconds, or to whole seconds if that is too long.
+    let millis = (u64::from(numer) + u64::from(denom) / 2) / u64::from(denom);
+    match u16::try_from(millis) {
+        Ok(millis) => (millis, 1000),
+        Err(_) => (u16::try_from((millis + 500) / 1000).unwrap_or(u16::MAX), 1),
+    }
+}
+
+/// Replace the palette of an indexed PNG image without decoding it.
+///
+/// The `PLTE` chunk of the image read from `r` is substituted by `palette` and the `tRNS` chunk is
+/// rewritten to hold the alpha values of the new entries. All other chunks, in particular the
+/// compressed index data, are copied to `w` unchanged. This allows recoloring an indexed image,
+/// for example swapping or remapping the colors of a sprite, without touching its pixels.
+///
+/// The new palette must have at least as many entries as the original one so that every index
+/// remains valid, and no more than the bit depth of the image can address.
+pub fn replace_palette<R: Read, W: Write>(r: R, w: W, palette: &[Rgba<u8>]) -> ImageResult<()> {
+    let mut writer = PngChunkWriter::new(w)?;
+    let mut bit_depth = None;
+    for chunk in PngChunkReader::new(r)? {
+        let chunk = chunk?;
+        match &chunk.kind {
+            b"IHDR" if chunk.data.len() == 13 => {
+                if chunk.data[9] != 3 {
+                    return Err(ImageError::Parameter(ParameterError::from_kind(
+                        ParameterErrorKind::Generic("PNG image is not indexed".into()),
+                    )));
+                }
+                bit_depth = Some(chunk.data[8]);
+            }
+            b"PLTE" => {
+                let max_entries = match bit_depth {
+                    Some(bits) if bits <= 8 => 1usize << bits,
+                    _ => {
+                        return Err(ImageError::Decoding(DecodingError::new(
+                            ImageFormat::Png.into(),
+                            "PLTE chunk without valid IHDR chunk",
+                        )))
+                    }
+                };
+                let entries = chunk.data.len() / 3;
+                if palette.len() < entries || palette.len() > max_entries {
+                    return Err(ImageError::Parameter(ParameterError::from_kind(
+                        ParameterErrorKind::Generic(format!(
+                            "palette must have between {} and {} entries, found {}",
+                            entries,
+                            max_entries,
+                            palette.len()
+                        )),
+                    )));
+                }
+
+                let colors = palette
+                    .iter()
+                    .flat_map(|p| p.0[..3].iter().copied())
+                    .collect();
+                writer.write_chunk(&PngChunk::new(*b"PLTE", colors))?;
+
+                // Trailing opaque entries may be omitted from the tRNS chunk.
+                let translucent = palette
+                    .iter()
+                    .rposition(|p| p.0[3] != 0xff)
+                    .map_or(0, |i| i + 1);
+                if translucent > 0 {
+                    let alpha = palette[..translucent].iter().map(|p| p.0[3]).collect();
+                    writer.write_chunk(&PngChunk::new(*b"tRNS", alpha))?;
+                }
+                continue;
+            }
+            // Replaced together with the PLTE chunk.
+            b"tRNS" => continue,
+            _ => {}
+        }
+        writer.write_chunk(&chunk)?;
+    }
+    Ok(())
+}
+
+/// A chunk of a PNG file, as read by [`PngChunkReader`] and written by [`PngChunkWriter`].
+///
+/// [`PngChunkReader`]: struct.PngChunkReader.html
+/// [`PngChunkWriter`]: struct.PngChunkWriter.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PngChunk {
+    /// The type of the chunk, such as `IHDR` or `tEXt`.
+    pub kind: [u8; 4],
+    /// The data of the chunk, without its length and checksum.
+    pub data: Vec<u8>,
+}
+
+impl PngChunk {
+    /// Create a chunk of type `kind` holding `data`.
+    pub fn new(kind: [u8; 4], data: Vec<u8>) -> Self {
+        PngChunk { kind, data }
+    }
+
+    /// Whether the chunk is needed to display the image, which is the case for `IHDR`, `PLTE`,
+    /// `IDAT` and `IEND`. Decoders may ignore all other chunks.
+    pub fn is_critical(&self) -> bool {
+        self.kind[0] & 0x20 == 0
+    }
+
+    /// Whether editors that do not know the chunk may copy it to an image whose critical chunks
+    /// they changed.
+    pub fn is_safe_to_copy(&self) -> bool {
+        self.kind[3] & 0x20 != 0
+    }
+}
+
+/// Reads the chunks of a PNG file without decoding them.
+///
+/// The reader is an iterator over the chunks, which ends after the `IEND` chunk. The checksum of
+/// each chunk is verified. Together with [`PngChunkWriter`] or [`edit_chunks`] this allows tools
+/// to inspect and edit the metadata of an image, or drop chunks to make it smaller, without
+/// decompressing its image data.
+///
+/// [`PngChunkWriter`]: struct.PngChunkWriter.html
+/// [`edit_chunks`]: fn.edit_chunks.html
+pub struct PngChunkReader<R: Read> {
+    r: R,
+    done: bool,
+}
+
+impl<R: Read> PngChunkReader<R> {
+    /// Create a reader of the chunks of the PNG file read from `r`, checking its signature.
+    pub fn new(mut r: R) -> ImageResult<Self> {
+        let mut signature = [0; 8];
+        r.read_exact(&mut signature)?;
+        if signature != PNG_SIGNATURE {
+            return Err(ImageError::Decoding(DecodingError::new(
+                ImageFormat::Png.into(),
+                "invalid PNG signature",
+            )));
+        }
+        Ok(PngChunkReader { r, done: false })
+    }
+
+    fn read_chunk(&mut self) -> ImageResult<PngChunk> {
+        use byteorder::{BigEndian, ReadBytesExt};
+
+        let length = self.r.read_u32::<BigEndian>()?;
+        let mut kind = [0; 4];
+        self.r.read_exact(&mut kind)?;
+        let mut data = Vec::new();
+        self.r
+            .by_ref()
+            .take(u64::from(length))
+            .read_to_end(&mut data)?;
+        if data.len() != length as usize {
+            return Err(ImageError::IoError(io::ErrorKind::UnexpectedEof.into()));
+        }
+        if self.r.read_u32::<BigEndian>()? != chunk_crc(&kind, &data) {
+            return Err(ImageError::Decoding(DecodingError::new(
+                ImageFormat::Png.into(),
+                format!(
+                    "CRC mismatch in {} chunk",
+                    String::from_utf8_lossy(&kind[..])
+                ),
+            )));
+        }
+        Ok(PngChunk { kind, data })
+    }
+}
+
+impl<R: Read> Iterator for PngChunkReader<R> {
+    type Item = ImageResult<PngChunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let chunk = self.read_chunk();
+        // Stop at the end of the file, or at the first error since the position is lost.
+        self.done = match &chunk {
+            Ok(chunk) => &chunk.kind == b"IEND",
+            Err(_) => true,
+        };
+        Some(chunk)
+    }
+}
+
+/// Writes a PNG file chunk by chunk, computing the checksum of each chunk.
+///
+/// The chunks are written as given, it is up to the caller to write them in a valid order, such
+/// as the order they were read by a [`PngChunkReader`].
+///
+/// [`PngChunkReader`]: struct.PngChunkReader.html
+pub struct PngChunkWriter<W: Write> {
+    w: W,
+}
+
+impl<W: Write> PngChunkWriter<W> {
+    /// Create a writer that writes the PNG signature to `w`.
+    pub fn new(mut w: W) -> ImageResult<Self> {
+        w.write_all(&PNG_SIGNATURE)?;
+        Ok(PngChunkWriter { w })
+    }
+
+    /// Write `chunk` with its length and checksum.
+    pub fn write_chunk(&mut self, chunk: &PngChunk) -> ImageResult<()> {
+        if u32::try_from(chunk.data.len()).map_or(true, |len| len > i32::MAX as u32) {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic("PNG chunk is longer than 2^31 - 1 bytes".into()),
+            )));
+        }
+        write_chunk(&mut self.w, &chunk.kind, &chunk.data)?;
+        Ok(())
+    }
+
+    /// Unwrap the writer.
+    pub fn into_inner(self) -> W {
+        self.w
+    }
+}
+
+/// What [`edit_chunks`] does with a chunk.
+///
+/// [`edit_chunks`]: fn.edit_chunks.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ChunkEdit {
+    /// Copy the chunk unchanged.
+    Keep,
+    /// Drop the chunk, which must be ancillary.
+    Remove,
+    /// Write the given ancillary chunks in place of the chunk, which must be ancillary.
+    Replace(Vec<PngChunk>),
+    /// Write the given ancillary chunks in front of the chunk, which is kept.
+    InsertBefore(Vec<PngChunk>),
+}
+
+/// Edit the ancillary chunks of a PNG image without decoding it.
+///
+/// Each chunk of the image read from `r` is passed to `edit`, and written to `w` as the returned
+/// [`ChunkEdit`] says. The critical chunks, in particular the compressed image data, are always
+/// copied unchanged, so edits are fast and lossless even for large images.
+///
+/// Returns an `ImageError::Parameter` if an edit would remove or replace a critical chunk, write
+/// a critical chunk, or insert chunks between two `IDAT` chunks, which must be consecutive.
+///
+/// # Examples
+///
+/// Strip all text chunks and add a new one in front of the image data.
+///
+/// ```no_run
+/// # fn main() -> image::ImageResult<()> {
+/// use image::codecs::png::{edit_chunks, ChunkEdit, PngChunk};
+///
+/// let input = std::fs::File::open("in.png")?;
+/// let output = std::fs::File::create("out.png")?;
+/// let mut inserted = false;
+/// edit_chunks(input, output, |chunk| match &chunk.kind {
+///     b"tEXt" | b"zTXt" | b"iTXt" => ChunkEdit::Remove,
+///     b"IDAT" if !inserted => {
+///         inserted = true;
+///         ChunkEdit::InsertBefore(vec![PngChunk::new(*b"tEXt", b"Author\0Me".to_vec())])
+///     }
+///     _ => ChunkEdit::Keep,
+/// })?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// [`ChunkEdit`]: enum.ChunkEdit.html
+pub fn edit_chunks<R, W, F>(r: R, w: W, mut edit: F) -> ImageResult<()>
+where
+    R: Read,
+    W: Write,
+    F: FnMut(&PngChunk) -> ChunkEdit,
+{
+    fn invalid_edit(message: String) -> ImageError {
+        ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+            message,
+        )))
+    }
+    fn check_ancillary(chunks: &[PngChunk]) -> ImageResult<()> {
+        match chunks.iter().find(|chunk| chunk.is_critical()) {
+            Some(chunk) => Err(invalid_edit(format!(
+                "can not write a critical {} chunk",
+                String::from_utf8_lossy(&chunk.kind[..])
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    let mut writer = PngChunkWriter::new(w)?;
+    let mut previous_idat = false;
+    for chunk in PngChunkReader::new(r)? {
+        let chunk = chunk?;
+        let is_idat = &chunk.kind == b"IDAT";
+        let edit = edit(&chunk);
+        if chunk.is_critical() && matches!(edit, ChunkEdit::Remove | ChunkEdit::Replace(_)) {
+            return Err(invalid_edit(format!(
+                "can not remove the critical {} chunk",
+                String::from_utf8_lossy(&chunk.kind[..])
+            )));
+        }
+        match edit {
+            ChunkEdit::Keep => writer.write_chunk(&chunk)?,
+            ChunkEdit::Remove => {}
+            ChunkEdit::Replace(chunks) => {
+                check_ancillary(&chunks)?;
+                for chunk in &chunks {
+                    writer.write_chunk(chunk)?;
+                }
+            }
+            ChunkEdit::InsertBefore(chunks) => {
+                check_ancillary(&chunks)?;
+                if is_idat && previous_idat && !chunks.is_empty() {
+                    return Err(invalid_edit(
+                        "can not insert chunks between IDAT chunks".to_string(),
+                    ));
+                }
+                for chunk in &chunks {
+                    writer.write_chunk(chunk)?;
+                }
+                writer.write_chunk(&chunk)?;
+            }
+        }
+        previous_idat = is_idat;
+    }
+    writer.into_inner().flush()?;
+    Ok(())
+}
+
+/// Strip the ancillary chunks of a PNG image, keeping what `preset` asks for.
+///
+/// The transparency and the chunks of an animation are always kept, as are the chunks describing
+/// the colors of the image unless all metadata is removed.
+pub(crate) fn strip_with_preset<R: Read, W: Write>(
+    r: R,
+    w: W,
+    preset: StripPreset,
+) -> ImageResult<()> {
+    edit_chunks(r, w, |chunk| {
+        let keep = match &chunk.kind {
+            b"tRNS" | b"acTL" | b"fcTL" | b"fdAT" => true,
+            b"iCCP" | b"sRGB" | b"gAMA" | b"cHRM" | b"cICP" => preset != StripPreset::All,
+            b"eXIf" if preset == StripPreset::KeepOrientation => {
+                return match metadata::orientation_only(&chunk.data) {
+                    Some(exif) => ChunkEdit::Replace(vec![PngChunk::new(*b"eXIf", exif)]),
+                    None => ChunkEdit::Remove,
+                };
+            }
+            _ => chunk.is_critical(),
+        };
+        if keep {
+            ChunkEdit::Keep
+        } else {
+            ChunkEdit::Remove
+        }
+    })
+}
+
+fn write_chunk<W: Write>(w: &mut W, kind: &[u8; 4], data: &[u8]) -> io::Result<()> {
+    use byteorder::{BigEndian, WriteBytesExt};
+
+    w.write_u32::<BigEndian>(data.len() as u32)?;
+    w.write_all(kind)?;
+    w.write_all(data)?;
+    w.write_u32::<BigEndian>(chunk_crc(kind, data))
+}
+
+/// The CRC-32 of a chunk, computed over its type and data.
+fn chunk_crc(kind: &[u8; 4], data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in kind.iter().chain(data) {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xedb8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
 }
 
 impl ImageError {
@@ -770,6 +2400,167 @@ mod tests {
     use crate::image::ImageDecoder;
     use std::io::Read;
 
+    #[test]
+    fn read_rect_matches_image() {
+        for path in &[
+            "tests/images/png/interlaced/basi2c08.png",
+            "tests/images/png/16bpc/basn6a16.png",
+        ] {
+            let data = std::fs::read(path).unwrap();
+            let image = crate::load_from_memory(&data).unwrap();
+            let bytes_per_pixel = usize::from(image.color().bytes_per_pixel());
+            let row_bytes = image.width() as usize * bytes_per_pixel;
+
+            let (x, y, width, height) = (5, 7, 11, 13);
+            let mut decoder = PngDecoder::new(&data[..]).unwrap();
+            let mut buf = vec![0; width * height * bytes_per_pixel];
+            decoder
+                .read_rect(x as u32, y as u32, width as u32, height as u32, &mut buf)
+                .unwrap();
+            for (row, out) in buf.chunks(width * bytes_per_pixel).enumerate() {
+                let start = (y + row) * row_bytes + x * bytes_per_pixel;
+                assert_eq!(out, &image.as_bytes()[start..][..out.len()], "{}", path);
+            }
+        }
+    }
+
+    #[test]
+    fn with_limits() {
+        let data = std::fs::read("tests/images/png/interlaced/basi2c08.png").unwrap();
+        let mut limits = Limits::default();
+        limits.max_image_width = Some(31);
+        let result = PngDecoder::with_limits(&data[..], limits);
+        assert!(matches!(result.err(), Some(ImageError::Limits(_))));
+
+        // The rows of interlaced images are converted after the whole image was decoded.
+        let mut decoder = PngDecoder::new(&data[..]).unwrap();
+        assert!(decoder.request_color_type(ColorType::Rgba8));
+        let mut limits = Limits::default();
+        limits.max_alloc = Some(32 * 32 * 3 - 1);
+        decoder.set_limits(limits).unwrap();
+        let mut buf = vec![0; decoder.total_bytes() as usize];
+        let result = decoder.read_image(&mut buf);
+        assert!(matches!(result, Err(ImageError::Limits(_))));
+    }
+
+    #[test]
+    fn read_image_into_uninit() {
+        for path in &[
+            "tests/images/png/16bpc/basn6a16.png",
+            "tests/images/png/interlaced/basi2c08.png",
+        ] {
+            let data = std::fs::read(path).unwrap();
+            let decoder = PngDecoder::new(&data[..]).unwrap();
+            let mut expected = vec![0; decoder.total_bytes() as usize];
+            decoder.read_image(&mut expected).unwrap();
+
+            let decoder = PngDecoder::new(&data[..]).unwrap();
+            let mut buf = vec![MaybeUninit::uninit(); expected.len()];
+            decoder.read_image_into_uninit(&mut buf).unwrap();
+            // Safety: the decoder initialized the whole buffer.
+            let buf: Vec<u8> = buf
+                .iter()
+                .map(|byte| unsafe { byte.assume_init() })
+                .collect();
+            assert_eq!(buf, expected, "{}", path);
+        }
+    }
+
+    #[test]
+    fn cancelled_between_rows() {
+        use crate::error::LimitErrorKind;
+        use crate::io::CancelToken;
+        use std::cell::Cell;
+
+        // The input is in memory, so nothing but the decoder checks for cancellation.
+        let data = std::fs::read("tests/images/png/16bpc/basn6a16.png").unwrap();
+        let token = CancelToken::new();
+        let mut limits = Limits::no_limits();
+        limits.cancel_token = Some(token.clone());
+        let mut decoder = PngDecoder::with_limits(&data[..], limits).unwrap();
+        let rows = Cell::new(0);
+        let mut buf = vec![0; decoder.total_bytes() as usize];
+        let result = decoder.read_rect_with_progress(0, 0, 32, 32, &mut buf, |progress| {
+            if progress.current() > 0 {
+                rows.set(rows.get() + 1);
+                token.cancel();
+            }
+        });
+        match result {
+            Err(ImageError::Limits(err)) => assert_eq!(err.kind(), LimitErrorKind::Cancelled),
+            _ => panic!("decoding was cancelled after the first row"),
+        }
+        assert_eq!(rows.get(), 1);
+
+        let decoder = PngDecoder::with_limits(&data[..], {
+            let mut limits = Limits::no_limits();
+            limits.cancel_token = Some(token.clone());
+            limits
+        })
+        .unwrap();
+        let result = decoder.read_image(&mut buf);
+        assert!(matches!(result, Err(ImageError::Limits(_))));
+    }
+
+    #[test]
+    fn request_color_type() {
+        for path in &[
+            "tests/images/png/interlaced/basi2c08.png",
+            "tests/images/png/bugfixes/debug_triangle_corners_widescreen.png",
+        ] {
+            let data = std::fs::read(path).unwrap();
+            let image = crate::load_from_memory(&data).unwrap();
+            let expected = image.to_luma_alpha8().into_raw();
+
+            let mut decoder = PngDecoder::new(&data[..]).unwrap();
+            assert!(!decoder.request_color_type(ColorType::Rgb16));
+            assert!(decoder.request_color_type(ColorType::La8));
+            assert_eq!(decoder.color_type(), ColorType::La8);
+            assert_eq!(decoder.original_color_type(), ExtendedColorType::Rgb8);
+            let mut buf = vec![0; decoder.total_bytes() as usize];
+            decoder.read_image(&mut buf).unwrap();
+            assert!(buf == expected, "{}", path);
+
+            let mut decoder = PngDecoder::new(&data[..]).unwrap();
+            decoder.request_color_type(ColorType::La8);
+            let mut buf = Vec::new();
+            decoder
+                .into_reader()
+                .unwrap()
+                .read_to_end(&mut buf)
+                .unwrap();
+            assert!(buf == expected, "{}", path);
+
+            let mut decoder = PngDecoder::new(&data[..]).unwrap();
+            decoder.request_color_type(ColorType::La8);
+            let mut buf = vec![0; 2 * 3 * 2];
+            decoder.read_rect(5, 7, 3, 2, &mut buf).unwrap();
+            let row_bytes = image.width() as usize * 2;
+            assert_eq!(&buf[..6], &expected[7 * row_bytes + 10..][..6], "{}", path);
+        }
+    }
+
+    #[test]
+    fn text_metadata_roundtrip() {
+        let mut metadata = MetadataMap::new();
+        metadata.insert("Copyright", "Someone");
+        metadata.insert("Description", "\u{65e5}\u{672c}");
+        metadata.set_xmp(Some("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/>".to_string()));
+        let mut encoded = Vec::new();
+        let mut encoder = PngEncoder::new(&mut encoded);
+        encoder.set_text_metadata(&metadata).unwrap();
+        encoder.write_image(&[7], 1, 1, ColorType::L8).unwrap();
+
+        let mut decoder = PngDecoder::new(&encoded[..]).unwrap();
+        assert_eq!(decoder.text_metadata(), metadata);
+
+        let mut invalid = MetadataMap::new();
+        invalid.insert("", "empty keyword");
+        assert!(PngEncoder::new(Vec::new())
+            .set_text_metadata(&invalid)
+            .is_err());
+    }
+
     #[test]
     fn ensure_no_decoder_off_by_one() {
         let dec = PngDecoder::new(
@@ -812,4 +2603,358 @@ mod tests {
             .downcast_ref::<png::DecodingError>()
             .expect("Caused by a png error");
     }
+
+    #[test]
+    fn progressive_interlaced() {
+        use crate::GenericImageView;
+
+        let data = std::fs::read("tests/images/png/interlaced/basi2c08.png").unwrap();
+        let complete = crate::load_from_memory(&data).unwrap();
+
+        let mut decoder = ProgressivePngDecoder::new();
+        let mut refinements = Vec::new();
+        for chunk in data.chunks(100) {
+            assert!(!decoder.is_complete());
+            refinements.extend(decoder.feed(chunk).unwrap());
+        }
+        assert!(decoder.is_complete());
+        assert!(refinements.len() > 2, "{} refinements", refinements.len());
+        assert_eq!(refinements.pop().unwrap().as_bytes(), complete.as_bytes());
+        assert!(refinements
+            .iter()
+            .all(|preview| preview.dimensions() == complete.dimensions()));
+        assert_ne!(refinements[0].as_bytes(), complete.as_bytes());
+        assert!(decoder.feed(b"trailing").unwrap().is_none());
+
+        let mut decoder = ProgressivePngDecoder::new();
+        assert!(decoder.feed(b"GIF89a").is_err());
+    }
+
+    #[test]
+    fn streaming_rows() {
+        let image = ImageBuffer::from_fn(7, 10, |x, y| Luma([(x * 5000 + y * 300) as u16]));
+        let path = std::env::temp_dir().join("image-streaming-rows.png");
+        let file = std::fs::File::create(&path).unwrap();
+        let mut encoder = PngEncoder::new(file)
+            .streaming(7, 10, ColorType::L16)
+            .unwrap();
+        let bytes: &[u8] = bytemuck::cast_slice(image.as_raw());
+        let (first, rest) = bytes.split_at(7 * 2 * 4);
+        encoder.write_rows(first, 4).unwrap();
+        assert!(encoder.write_rows(&rest[1..], 6).is_err());
+        encoder.write_rows(rest, 6).unwrap();
+        assert!(encoder.write_rows(&[], 1).is_err());
+        encoder.finish().unwrap();
+
+        let decoded = crate::open(&path).unwrap();
+        assert_eq!(decoded.into_luma16(), image);
+        std::fs::remove_file(path).unwrap();
+
+        let encoder = PngEncoder::new(Vec::new())
+            .streaming(7, 10, ColorType::Rgb8)
+            .unwrap();
+        assert!(encoder.finish().is_err());
+    }
+
+    fn encode_with_palette(image: &RgbaImage, quantization: PaletteQuantization) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        let mut encoder = PngEncoder::new(&mut encoded);
+        encoder.set_palette_quantization(quantization);
+        encoder
+            .write_image(image, image.width(), image.height(), ColorType::Rgba8)
+            .unwrap();
+        encoded
+    }
+
+    #[test]
+    fn palette_quantization_is_exact_for_few_colors() {
+        let image = RgbaImage::from_fn(16, 16, |x, y| Rgba([x as u8 * 16, y as u8, 0, x as u8]));
+        let encoded = encode_with_palette(&image, PaletteQuantization::Nearest);
+
+        let decoder = PngDecoder::new(&encoded[..]).unwrap();
+        assert!(decoder.palette().is_some());
+        let decoded = DynamicImage::from_decoder(decoder).unwrap().into_rgba8();
+        assert_eq!(decoded, image);
+    }
+
+    #[test]
+    fn palette_quantization_limits_colors() {
+        let image = RgbaImage::from_fn(64, 64, |x, y| {
+            Rgba([x as u8 * 4, y as u8 * 4, (x + y) as u8, 255 - x as u8])
+        });
+        for &quantization in &[PaletteQuantization::Nearest, PaletteQuantization::Dither] {
+            let encoded = encode_with_palette(&image, quantization);
+            let decoder = PngDecoder::new(&encoded[..]).unwrap();
+            let palette = decoder.palette().unwrap();
+            assert!(palette.len() > 128 && palette.len() <= 256);
+            assert!(palette.iter().any(|p| p[3] != 255));
+        }
+    }
+
+    #[test]
+    fn palette_quantization_keeps_alpha_levels() {
+        // Opaque and half transparent gradients next to fully transparent pixels of many colors.
+        let image = RgbaImage::from_fn(48, 48, |x, y| {
+            let alpha = [255, 128, 0][x as usize / 16];
+            Rgba([x as u8 * 5, y as u8 * 5, (x * y) as u8, alpha])
+        });
+        for &quantization in &[PaletteQuantization::Nearest, PaletteQuantization::Dither] {
+            let encoded = encode_with_palette(&image, quantization);
+            let decoder = PngDecoder::new(&encoded[..]).unwrap();
+            let decoded = DynamicImage::from_decoder(decoder).unwrap().into_rgba8();
+            for (original, decoded) in image.pixels().zip(decoded.pixels()) {
+                assert_eq!(decoded[3], alpha_level(original[3]));
+            }
+        }
+    }
+
+    #[test]
+    fn replace_palette_keeps_indices() {
+        let original = std::fs::read("tests/images/png/transparency/tbbn3p08.png").unwrap();
+        let palette = PngDecoder::new(&original[..])
+            .unwrap()
+            .palette()
+            .expect("Image is indexed");
+        let inverted: Vec<_> = palette
+            .iter()
+            .map(|p| Rgba([!p.0[0], !p.0[1], !p.0[2], p.0[3]]))
+            .collect();
+
+        let mut recolored = Vec::new();
+        replace_palette(&original[..], &mut recolored, &inverted).unwrap();
+        assert_eq!(
+            PngDecoder::new(&recolored[..]).unwrap().palette(),
+            Some(inverted)
+        );
+
+        let before = crate::load_from_memory(&original).unwrap().into_rgba8();
+        let after = crate::load_from_memory(&recolored).unwrap().into_rgba8();
+        for (b, a) in before.pixels().zip(after.pixels()) {
+            assert_eq!(Rgba([!b.0[0], !b.0[1], !b.0[2], b.0[3]]), *a);
+        }
+    }
+
+    #[test]
+    fn replace_palette_rejects_short_palette() {
+        let original = std::fs::read("tests/images/png/transparency/tbbn3p08.png").unwrap();
+        let result = replace_palette(&original[..], Vec::new(), &[Rgba([0, 0, 0, 255])]);
+        assert!(matches!(result, Err(ImageError::Parameter(_))));
+    }
+
+    #[test]
+    fn edit_chunks_keeps_image_data() {
+        let original = std::fs::read("tests/images/png/transparency/tbbn3p08.png").unwrap();
+        let kinds = |png: &[u8]| -> Vec<[u8; 4]> {
+            PngChunkReader::new(png)
+                .unwrap()
+                .map(|chunk| chunk.unwrap().kind)
+                .collect()
+        };
+        assert_eq!(kinds(&original).last(), Some(b"IEND"));
+
+        let mut edited = Vec::new();
+        let mut inserted = false;
+        edit_chunks(&original[..], &mut edited, |chunk| match &chunk.kind {
+            b"gAMA" => ChunkEdit::Remove,
+            b"IDAT" if !inserted => {
+                inserted = true;
+                ChunkEdit::InsertBefore(vec![PngChunk::new(*b"tEXt", b"Title\0Box".to_vec())])
+            }
+            _ => ChunkEdit::Keep,
+        })
+        .unwrap();
+
+        let before = kinds(&original);
+        let after = kinds(&edited);
+        assert!(before.contains(b"gAMA") && !after.contains(b"gAMA"));
+        assert_eq!(
+            after
+                .iter()
+                .filter(|kind| kind.iter().all(u8::is_ascii_uppercase))
+                .count(),
+            before
+                .iter()
+                .filter(|kind| kind.iter().all(u8::is_ascii_uppercase))
+                .count(),
+        );
+        let mut decoder = PngDecoder::new(&edited[..]).unwrap();
+        assert_eq!(decoder.text_metadata().get("Title"), Some("Box"));
+        let before = crate::load_from_memory(&original).unwrap();
+        let after = crate::load_from_memory(&edited).unwrap();
+        assert_eq!(before.as_bytes(), after.as_bytes());
+    }
+
+    #[test]
+    fn edit_chunks_rejects_critical_edits() {
+        let original = std::fs::read("tests/images/png/transparency/tbbn3p08.png").unwrap();
+        let remove_plte = edit_chunks(&original[..], Vec::new(), |chunk| match &chunk.kind {
+            b"PLTE" => ChunkEdit::Remove,
+            _ => ChunkEdit::Keep,
+        });
+        assert!(matches!(remove_plte, Err(ImageError::Parameter(_))));
+
+        let insert_idat = edit_chunks(&original[..], Vec::new(), |chunk| match &chunk.kind {
+            b"IEND" => ChunkEdit::InsertBefore(vec![PngChunk::new(*b"IDAT", Vec::new())]),
+            _ => ChunkEdit::Keep,
+        });
+        assert!(matches!(insert_idat, Err(ImageError::Parameter(_))));
+
+        let mut corrupted = original.clone();
+        let last = corrupted.len() - 1;
+        corrupted[last] ^= 1;
+        let chunks: Vec<_> = PngChunkReader::new(&corrupted[..]).unwrap().collect();
+        assert!(chunks.last().unwrap().is_err());
+    }
+
+    #[test]
+    fn icc_profile_roundtrip() {
+        let icc_profile = b"not really a profile".to_vec();
+        let mut encoded = Vec::new();
+        let mut encoder = PngEncoder::new(&mut encoded);
+        encoder.set_icc_profile(icc_profile.clone()).unwrap();
+        encoder
+            .write_image(&[0, 1, 2], 1, 1, ColorType::Rgb8)
+            .unwrap();
+        let mut decoder = PngDecoder::new(&encoded[..]).unwrap();
+        assert_eq!(decoder.icc_profile(), Some(icc_profile));
+
+        let mut encoded = Vec::new();
+        PngEncoder::new(&mut encoded)
+            .write_image(&[0, 1, 2], 1, 1, ColorType::Rgb8)
+            .unwrap();
+        assert_eq!(PngDecoder::new(&encoded[..]).unwrap().icc_profile(), None);
+    }
+
+    #[test]
+    fn apng_roundtrip() {
+        let red = RgbaImage::from_pixel(4, 3, Rgba([255, 0, 0, 255]));
+        let blue = Rgba([0, 0, 255, 128]);
+        let mut encoded = Vec::new();
+        let mut encoder = ApngEncoder::new(&mut encoded);
+        encoder.set_loop_count(2);
+        let delay = Delay::from_numer_denom_ms(100, 1);
+        encoder
+            .encode_frame(Frame::from_parts(red.clone(), 0, 0, delay))
+            .unwrap();
+        encoder
+            .encode_frame_with_ops(
+                Frame::from_parts(RgbaImage::from_pixel(2, 1, blue), 1, 2, delay),
+                DisposeOp::Background,
+                BlendOp::Over,
+            )
+            .unwrap();
+        let third = Delay::from_numer_denom_ms(40, 3);
+        encoder
+            .encode_frame(Frame::from_parts(RgbaImage::new(1, 1), 3, 0, third))
+            .unwrap();
+        encoder.finish().unwrap();
+
+        let decoder = PngDecoder::new(&encoded[..]).unwrap();
+        assert!(decoder.is_apng());
+        let frames = decoder.apng().into_frames().collect_frames().unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].buffer(), &red);
+        assert_eq!(frames[0].delay(), delay);
+
+        let mut blended = Rgba([255, 0, 0, 255]);
+        blended.blend(&blue);
+        assert_eq!(frames[1].buffer().get_pixel(2, 2), &blended);
+        assert_eq!(frames[1].buffer().get_pixel(0, 2), &Rgba([255, 0, 0, 255]));
+
+        // The second frame is cleared, the third one replaces a single pixel.
+        let third_frame = frames[2].buffer();
+        assert_eq!(third_frame.get_pixel(2, 2), &Rgba([0, 0, 0, 0]));
+        assert_eq!(third_frame.get_pixel(3, 0), &Rgba([0, 0, 0, 0]));
+        assert_eq!(third_frame.get_pixel(0, 2), &Rgba([255, 0, 0, 255]));
+        assert_eq!(frames[2].delay(), third);
+    }
+
+    #[test]
+    fn apng_raw_frames() {
+        let delay = Delay::from_numer_denom_ms(10, 1);
+        let mut encoded = Vec::new();
+        let mut encoder = ApngEncoder::new(&mut encoded);
+        let first = RgbaImage::from_pixel(5, 4, Rgba([0, 255, 0, 255]));
+        let second = RgbaImage::from_pixel(2, 3, Rgba([0, 0, 255, 255]));
+        encoder
+            .encode_frame(Frame::from_parts(first.clone(), 0, 0, delay))
+            .unwrap();
+        encoder
+            .encode_frame(Frame::from_parts(second.clone(), 3, 1, delay))
+            .unwrap();
+        encoder.finish().unwrap();
+
+        let frames = PngDecoder::new(&encoded[..])
+            .unwrap()
+            .apng()
+            .into_raw_frames();
+        assert_eq!(frames.canvas_dimensions(), Some((5, 4)));
+        assert_eq!(frames.background_color(), None);
+        let frames = frames.collect_frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].buffer(), &first);
+        assert_eq!((frames[1].left(), frames[1].top()), (3, 1));
+        assert_eq!(frames[1].buffer(), &second);
+    }
+
+    #[test]
+    fn apng_dirty_rects() {
+        let delay = Delay::from_numer_denom_ms(10, 1);
+        let frame = |width, height, left, top| {
+            let image = RgbaImage::from_pixel(width, height, Rgba([0, 255, 0, 255]));
+            Frame::from_parts(image, left, top, delay)
+        };
+        let mut encoded = Vec::new();
+        let mut encoder = ApngEncoder::new(&mut encoded);
+        encoder.encode_frame(frame(5, 4, 0, 0)).unwrap();
+        encoder
+            .encode_frame_with_ops(frame(2, 3, 3, 1), DisposeOp::Background, BlendOp::Over)
+            .unwrap();
+        encoder.encode_frame(frame(1, 1, 0, 0)).unwrap();
+        encoder.encode_frame(frame(1, 1, 1, 1)).unwrap();
+        encoder.finish().unwrap();
+
+        let frames = PngDecoder::new(&encoded[..])
+            .unwrap()
+            .apng()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        let rects: Vec<_> = frames
+            .iter()
+            .map(|frame| {
+                let rect = frame.dirty_rect();
+                (rect.x, rect.y, rect.width, rect.height)
+            })
+            .collect();
+        // The third frame also covers the area the second one was cleared from.
+        assert_eq!(
+            rects,
+            [(0, 0, 5, 4), (3, 1, 2, 3), (0, 0, 5, 4), (1, 1, 1, 1)]
+        );
+    }
+
+    #[test]
+    fn apng_rejects_frames_off_the_canvas() {
+        let delay = Delay::from_numer_denom_ms(10, 1);
+        let mut encoder = ApngEncoder::new(Vec::new());
+        let offset = Frame::from_parts(RgbaImage::new(2, 2), 2, 0, delay);
+        assert!(encoder.encode_frame(offset).is_err());
+        encoder
+            .encode_frame(Frame::from_parts(RgbaImage::new(2, 2), 0, 0, delay))
+            .unwrap();
+        let outside = Frame::from_parts(RgbaImage::new(2, 2), 1, 1, delay);
+        assert!(encoder.encode_frame(outside).is_err());
+
+        assert!(ApngEncoder::new(Vec::new()).finish().is_err());
+    }
+
+    #[test]
+    fn apng_delays() {
+        let delay = |numer, denom| apng_delay(Delay::from_numer_denom_ms(numer, denom));
+        assert_eq!(delay(100, 1), (1, 10));
+        assert_eq!(delay(1, 3), (1, 3000));
+        assert_eq!(delay(1, 70), (0, 1000));
+        assert_eq!(delay(100_000_000, 1), (u16::MAX, 1));
+    }
 }
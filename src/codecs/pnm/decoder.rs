@@ -14,6 +14,7 @@ use crate::error::{
     DecodingError, ImageError, ImageResult, UnsupportedError, UnsupportedErrorKind,
 };
 use crate::image::{self, ImageDecoder, ImageFormat};
+use crate::io::Limits;
 use crate::utils;
 
 use byteorder::{BigEndian, ByteOrder, NativeEndian};
@@ -246,6 +247,8 @@ pub struct PnmDecoder<R> {
     reader: R,
     header: PnmHeader,
     tuple: TupleType,
+    /// The limits on the binary samples that are read before they are converted.
+    limits: Limits,
 }
 
 impl<R: BufRead> PnmDecoder<R> {
@@ -305,6 +308,7 @@ impl<R: BufRead> PnmDecoder<R> {
                 decoded: HeaderRecord::Bitmap(header),
                 encoded: None,
             },
+            limits: Limits::no_limits(),
         })
     }
 
@@ -318,6 +322,7 @@ impl<R: BufRead> PnmDecoder<R> {
                 decoded: HeaderRecord::Graymap(header),
                 encoded: None,
             },
+            limits: Limits::no_limits(),
         })
     }
 
@@ -331,6 +336,7 @@ impl<R: BufRead> PnmDecoder<R> {
                 decoded: HeaderRecord::Pixmap(header),
                 encoded: None,
             },
+            limits: Limits::no_limits(),
         })
     }
 
@@ -344,6 +350,7 @@ impl<R: BufRead> PnmDecoder<R> {
                 decoded: HeaderRecord::Arbitrary(header),
                 encoded: None,
             },
+            limits: Limits::no_limits(),
         })
     }
 }
@@ -615,6 +622,14 @@ impl<'a, R: 'a + Read> ImageDecoder<'a> for PnmDecoder<R> {
         ))
     }
 
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        self.limits = limits;
+        Ok(())
+    }
+
     fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
         assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
         match self.tuple {
@@ -635,6 +650,7 @@ impl<R: Read> PnmDecoder<R> {
                 let width = self.header.width();
                 let height = self.header.height();
                 let bytecount = S::bytelen(width, height, components)?;
+                self.limits.reserve(bytecount as u64)?;
                 let mut bytes = vec![];
 
                 self.reader
@@ -0,0 +1,360 @@
+//! Decoding and encoding of QOI images
+//!
+//! The Quite OK Image format stores 8-bit RGB and RGBA images losslessly. Every pixel is written
+//! as a run of the previous pixel, an index into a table of recently seen pixels, a small
+//! difference to the previous pixel, or in full. It compresses about as well as PNG at a fraction
+//! of the time, which suits archives and texture pipelines.
+//!
+//! The color space byte of the header is read but not interpreted.
+//!
+//! # Related Links
+//! * <https://qoiformat.org/qoi-specification.pdf> - The QOI specification
+
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read, Write};
+use std::marker::PhantomData;
+use std::mem;
+
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use crate::color::ColorType;
+use crate::error::{
+    DecodingError, ImageError, ImageResult, ParameterError, ParameterErrorKind, UnsupportedError,
+    UnsupportedErrorKind,
+};
+use crate::image::{self, ImageDecoder, ImageEncoder, ImageFormat};
+use crate::io::Limits;
+
+/// The magic bytes at the start of every QOI file.
+const MAGIC: &[u8; 4] = b"qoif";
+/// The bytes that end the stream of pixels.
+const END_MARKER: [u8; 8] = [0, 0, 0, 0, 0, 0, 0, 1];
+
+const OP_INDEX: u8 = 0x00;
+const OP_DIFF: u8 = 0x40;
+const OP_LUMA: u8 = 0x80;
+const OP_RUN: u8 = 0xC0;
+const OP_RGB: u8 = 0xFE;
+const OP_RGBA: u8 = 0xFF;
+/// The two bits that tell the operations other than `OP_RGB` and `OP_RGBA` apart.
+const OP_MASK: u8 = 0xC0;
+/// The longest run of a single operation, as the lengths of 63 and 64 are `OP_RGB` and `OP_RGBA`.
+const MAX_RUN: u8 = 62;
+
+/// The position of a pixel in the table of recently seen pixels.
+fn hash([r, g, b, a]: [u8; 4]) -> usize {
+    (usize::from(r) * 3 + usize::from(g) * 5 + usize::from(b) * 7 + usize::from(a) * 11) % 64
+}
+
+/// The reader returned by `QoiDecoder::into_reader`.
+pub struct QoiReader<R>(Cursor<Vec<u8>>, PhantomData<R>);
+impl<R> Read for QoiReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        if self.0.position() == 0 && buf.is_empty() {
+            mem::swap(buf, self.0.get_mut());
+            Ok(buf.len())
+        } else {
+            self.0.read_to_end(buf)
+        }
+    }
+}
+
+/// QOI decoder
+pub struct QoiDecoder<R: Read> {
+    r: R,
+    width: u32,
+    height: u32,
+    color_type: ColorType,
+}
+
+impl<R: Read> QoiDecoder<R> {
+    /// Create a new decoder that decodes from the stream ```r```
+    pub fn new(mut r: R) -> ImageResult<QoiDecoder<R>> {
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(decoding_error("QOI magic not found".to_string()));
+        }
+        let width = r.read_u32::<BigEndian>()?;
+        let height = r.read_u32::<BigEndian>()?;
+        let color_type = match r.read_u8()? {
+            3 => ColorType::Rgb8,
+            4 => ColorType::Rgba8,
+            channels => {
+                return Err(decoding_error(format!(
+                    "invalid number of channels: {}",
+                    channels
+                )))
+            }
+        };
+        let _color_space = r.read_u8()?;
+        if crate::utils::check_dimension_overflow(width, height, color_type.bytes_per_pixel()) {
+            return Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    ImageFormat::Qoi.into(),
+                    UnsupportedErrorKind::GenericFeature(format!(
+                        "Image dimensions ({}x{}) are too large",
+                        width, height
+                    )),
+                ),
+            ));
+        }
+
+        Ok(QoiDecoder {
+            r,
+            width,
+            height,
+            color_type,
+        })
+    }
+}
+
+impl<'a, R: 'a + Read> ImageDecoder<'a> for QoiDecoder<R> {
+    type Reader = QoiReader<R>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        Ok(QoiReader(
+            Cursor::new(image::decoder_to_vec(self)?),
+            PhantomData,
+        ))
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        Ok(())
+    }
+
+    fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+
+        let channels = usize::from(self.color_type.bytes_per_pixel());
+        let mut index = [[0u8; 4]; 64];
+        let mut pixel = [0, 0, 0, 255];
+        let mut run = 0;
+        for output in buf.chunks_exact_mut(channels) {
+            if run > 0 {
+                run -= 1;
+            } else {
+                let op = self.r.read_u8()?;
+                match op {
+                    OP_RGB => self.r.read_exact(&mut pixel[..3])?,
+                    OP_RGBA => self.r.read_exact(&mut pixel)?,
+                    _ => match op & OP_MASK {
+                        OP_INDEX => pixel = index[usize::from(op)],
+                        OP_DIFF => {
+                            for (channel, shift) in pixel.iter_mut().zip(&[4, 2, 0]) {
+                                let difference = (op >> shift) & 0x03;
+                                *channel = channel.wrapping_add(difference).wrapping_sub(2);
+                            }
+                        }
+                        OP_LUMA => {
+                            let byte = self.r.read_u8()?;
+                            let green = (op & 0x3F).wrapping_sub(32);
+                            let red = green.wrapping_sub(8).wrapping_add(byte >> 4);
+                            let blue = green.wrapping_sub(8).wrapping_add(byte & 0x0F);
+                            pixel[0] = pixel[0].wrapping_add(red);
+                            pixel[1] = pixel[1].wrapping_add(green);
+                            pixel[2] = pixel[2].wrapping_add(blue);
+                        }
+                        // The run includes this pixel.
+                        _ => run = op & 0x3F,
+                    },
+                }
+                index[hash(pixel)] = pixel;
+            }
+            output.copy_from_slice(&pixel[..channels]);
+        }
+        Ok(())
+    }
+}
+
+fn decoding_error(message: String) -> ImageError {
+    ImageError::Decoding(DecodingError::new(ImageFormat::Qoi.into(), message))
+}
+
+/// QOI encoder
+///
+/// Writes `Rgb8` and `Rgba8` images, marked as sRGB with linear alpha.
+pub struct QoiEncoder<W: Write> {
+    w: W,
+}
+
+impl<W: Write> QoiEncoder<W> {
+    /// Create a new encoder that writes its output to ```w```
+    pub fn new(w: W) -> QoiEncoder<W> {
+        QoiEncoder { w }
+    }
+
+    /// Encode an image with the given dimensions and color type.
+    pub fn encode(
+        mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        let channels = match color_type {
+            ColorType::Rgb8 => 3,
+            ColorType::Rgba8 => 4,
+            _ => {
+                return Err(ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(
+                        ImageFormat::Qoi.into(),
+                        UnsupportedErrorKind::Color(color_type.into()),
+                    ),
+                ))
+            }
+        };
+        let expected = u64::from(width) * u64::from(height) * channels as u64;
+        if u64::try_from(data.len()) != Ok(expected) {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+
+        let mut encoded = Vec::new();
+        encoded.extend_from_slice(MAGIC);
+        encoded.write_u32::<BigEndian>(width)?;
+        encoded.write_u32::<BigEndian>(height)?;
+        encoded.extend_from_slice(&[channels as u8, 0]);
+
+        let mut index = [[0u8; 4]; 64];
+        let mut previous = [0, 0, 0, 255];
+        let mut run = 0;
+        let pixels = data.chunks_exact(channels);
+        let last = pixels.len().saturating_sub(1);
+        for (i, input) in pixels.enumerate() {
+            let mut pixel = previous;
+            pixel[..channels].copy_from_slice(input);
+
+            if pixel == previous {
+                run += 1;
+                if run == MAX_RUN || i == last {
+                    encoded.push(OP_RUN | (run - 1));
+                    run = 0;
+                }
+                continue;
+            }
+            if run > 0 {
+                encoded.push(OP_RUN | (run - 1));
+                run = 0;
+            }
+
+            let position = hash(pixel);
+            if index[position] == pixel {
+                encoded.push(OP_INDEX | position as u8);
+            } else if pixel[3] != previous[3] {
+                encoded.push(OP_RGBA);
+                encoded.extend_from_slice(&pixel);
+            } else {
+                let difference =
+                    |channel: usize| pixel[channel].wrapping_sub(previous[channel]) as i8;
+                let (red, green, blue) = (difference(0), difference(1), difference(2));
+                let (red_green, blue_green) = (red.wrapping_sub(green), blue.wrapping_sub(green));
+                if [red, green, blue].iter().all(|d| (-2..=1).contains(d)) {
+                    let bias = |d: i8| (d + 2) as u8;
+                    encoded.push(OP_DIFF | bias(red) << 4 | bias(green) << 2 | bias(blue));
+                } else if (-32..=31).contains(&green)
+                    && (-8..=7).contains(&red_green)
+                    && (-8..=7).contains(&blue_green)
+                {
+                    encoded.push(OP_LUMA | (green + 32) as u8);
+                    encoded.push(((red_green + 8) as u8) << 4 | (blue_green + 8) as u8);
+                } else {
+                    encoded.push(OP_RGB);
+                    encoded.extend_from_slice(&pixel[..3]);
+                }
+            }
+            index[position] = pixel;
+            previous = pixel;
+        }
+        encoded.extend_from_slice(&END_MARKER);
+
+        self.w.write_all(&encoded)?;
+        Ok(())
+    }
+}
+
+impl<W: Write> ImageEncoder for QoiEncoder<W> {
+    fn write_image(
+        self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        self.encode(buf, width, height, color_type)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DynamicImage, Rgba, RgbaImage};
+
+    fn round_trip(image: &[u8], width: u32, height: u32, color_type: ColorType) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        QoiEncoder::new(&mut encoded)
+            .encode(image, width, height, color_type)
+            .unwrap();
+        let decoder = QoiDecoder::new(&encoded[..]).unwrap();
+        assert_eq!(decoder.color_type(), color_type);
+        let mut decoded = vec![0; decoder.total_bytes() as usize];
+        decoder.read_image(&mut decoded).unwrap();
+        assert_eq!(decoded, image);
+        encoded
+    }
+
+    #[test]
+    fn all_operations() {
+        // Runs, repeated colors, small and larger differences, and alpha changes.
+        let image = RgbaImage::from_fn(67, 13, |x, y| match (x / 10, y % 3) {
+            (0, _) => Rgba([10, 20, 30, 255]),
+            (1, 0) => Rgba([x as u8, x as u8, x as u8, 255]),
+            (1, _) => Rgba([x as u8 * 3, x as u8 * 4, x as u8 * 5, 255]),
+            (2, _) => Rgba([(x * y) as u8, (x * 7) as u8, (y * 90) as u8, 255]),
+            (3, _) => Rgba([x as u8, y as u8, 0, (x * y) as u8]),
+            _ => Rgba([10, 20, 30, 255]),
+        });
+        let encoded = round_trip(&image, 67, 13, ColorType::Rgba8);
+        assert_eq!(&encoded[..4], b"qoif");
+        assert_eq!(&encoded[encoded.len() - 8..], &END_MARKER);
+        assert!(encoded.len() < image.len());
+
+        let rgb = DynamicImage::ImageRgba8(image).into_rgb8();
+        round_trip(&rgb, 67, 13, ColorType::Rgb8);
+    }
+
+    #[test]
+    fn long_runs() {
+        round_trip(&[7; 3 * 200], 20, 10, ColorType::Rgb8);
+        // The initial pixel is opaque black, which the first pixel may repeat.
+        round_trip(&[0; 3 * 5], 5, 1, ColorType::Rgb8);
+    }
+
+    #[test]
+    fn invalid_files() {
+        assert!(QoiDecoder::new(&b"qoif\0\0\0\x01\0\0\0\x01\x02\0"[..]).is_err());
+        assert!(QoiDecoder::new(&b"qoix\0\0\0\x01\0\0\0\x01\x03\0"[..]).is_err());
+        // The pixels end early.
+        let decoder = QoiDecoder::new(&b"qoif\0\0\0\x02\0\0\0\x01\x03\0\xfe\x01"[..]).unwrap();
+        assert!(decoder.read_image(&mut [0; 6]).is_err());
+
+        let mut encoded = Vec::new();
+        let result = QoiEncoder::new(&mut encoded).encode(&[0; 2], 1, 1, ColorType::La8);
+        assert!(matches!(result, Err(ImageError::Unsupported(_))));
+    }
+}
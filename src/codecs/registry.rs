@@ -0,0 +1,65 @@
+//! Storage for the codecs that applications register for formats this crate does not implement.
+use std::sync::{Arc, PoisonError, RwLock};
+
+use once_cell::sync::OnceCell;
+
+/// A codec that the application can register, and replace, at any time from any thread.
+///
+/// Callers get a shared handle to the codec, so a replaced codec is dropped once the last call to
+/// it returns.
+pub(crate) struct Registry<T> {
+    codec: OnceCell<RwLock<Option<Arc<T>>>>,
+}
+
+impl<T> Registry<T> {
+    pub(crate) const fn new() -> Self {
+        Registry {
+            codec: OnceCell::new(),
+        }
+    }
+
+    fn lock(&self) -> &RwLock<Option<Arc<T>>> {
+        self.codec.get_or_init(|| RwLock::new(None))
+    }
+
+    /// Registers `codec`, replacing any previously registered one.
+    pub(crate) fn register(&self, codec: T) {
+        // The lock is only held to swap the handle, so a panic can not leave it inconsistent.
+        *self.lock().write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(codec));
+    }
+
+    /// Returns the registered codec.
+    pub(crate) fn get(&self) -> Option<Arc<T>> {
+        self.lock()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Returns if a codec is registered.
+    pub(crate) fn is_registered(&self) -> bool {
+        self.lock()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaced_codecs_are_dropped() {
+        static CODEC: Registry<Box<dyn Fn() -> u8 + Send + Sync>> = Registry::new();
+        assert!(!CODEC.is_registered() && CODEC.get().is_none());
+
+        CODEC.register(Box::new(|| 1));
+        let first = CODEC.get().unwrap();
+        CODEC.register(Box::new(|| 2));
+        assert_eq!((first(), CODEC.get().unwrap()()), (1, 2));
+
+        // Only the handle taken before the replacement keeps the first codec alive.
+        assert_eq!(Arc::strong_count(&first), 1);
+    }
+}
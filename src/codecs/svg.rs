@@ -0,0 +1,227 @@
+//! Rasterizing of SVG images.
+//!
+//! SVG is a vector format, so its images have no inherent pixel size. This decoder renders them
+//! with [`resvg`] at the size they declare, where a CSS pixel is 1/96 of an inch, or at a size or
+//! resolution chosen by the caller. Gzip compressed files (`.svgz`) are decompressed first. Text
+//! is not rendered, files should convert it to paths.
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::io::BufReader;
+//! use image::codecs::svg::SvgDecoder;
+//! use image::DynamicImage;
+//!
+//! // An icon rendered at 256 by 256 pixels, and a drawing at print resolution.
+//! let icon = SvgDecoder::with_size(BufReader::new(File::open("icon.svg")?), 256, 256)?;
+//! let icon = DynamicImage::from_decoder(icon)?;
+//! let drawing = SvgDecoder::with_dpi(BufReader::new(File::open("drawing.svg")?), 300.0)?;
+//! let drawing = DynamicImage::from_decoder(drawing)?;
+//! # Ok::<(), image::ImageError>(())
+//! ```
+//!
+//! [`resvg`]: https://github.com/RazrFalcon/resvg
+//!
+//! # Related Links
+//! * <https://www.w3.org/TR/SVG11/> - The SVG 1.1 specification
+
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read};
+use std::marker::PhantomData;
+use std::mem;
+
+use crate::color::ColorType;
+use crate::error::{DecodingError, ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use crate::image::{ImageDecoder, ImageFormat};
+
+/// The resolution that SVG lengths in pixels refer to.
+const CSS_DPI: f64 = 96.0;
+
+/// SVG decoder, which rasterizes the image to 8-bit RGBA.
+pub struct SvgDecoder<R> {
+    tree: usvg::Tree,
+    width: u32,
+    height: u32,
+    marker: PhantomData<R>,
+}
+
+impl<R: Read> SvgDecoder<R> {
+    /// Parse the image read from `r`, to be rendered at the size it declares.
+    pub fn new(r: R) -> ImageResult<SvgDecoder<R>> {
+        SvgDecoder::with_dpi(r, CSS_DPI)
+    }
+
+    /// Parse the image read from `r`, to be rendered at a resolution of `dpi` dots per inch.
+    ///
+    /// At 96 DPI an image is rendered at the size it declares, at 192 DPI at twice that size.
+    pub fn with_dpi(r: R, dpi: f64) -> ImageResult<SvgDecoder<R>> {
+        let tree = parse(r)?;
+        let size = tree.svg_node().size;
+        let scale = dpi / CSS_DPI;
+        let width = pixels(size.width() * scale)?;
+        let height = pixels(size.height() * scale)?;
+        Ok(SvgDecoder {
+            tree,
+            width,
+            height,
+            marker: PhantomData,
+        })
+    }
+
+    /// Parse the image read from `r`, to be rendered at exactly `width` by `height` pixels.
+    ///
+    /// The image is stretched if it has a different aspect ratio.
+    pub fn with_size(r: R, width: u32, height: u32) -> ImageResult<SvgDecoder<R>> {
+        if width == 0 || height == 0 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+        Ok(SvgDecoder {
+            tree: parse(r)?,
+            width,
+            height,
+            marker: PhantomData,
+        })
+    }
+
+    /// Render the image into samples that are not premultiplied with their alpha.
+    fn render(&self) -> ImageResult<Vec<u8>> {
+        let mut pixmap = tiny_skia::Pixmap::new(self.width, self.height).ok_or_else(|| {
+            ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            ))
+        })?;
+        let size = self.tree.svg_node().size;
+        let transform = tiny_skia::Transform::from_scale(
+            (f64::from(self.width) / size.width()) as f32,
+            (f64::from(self.height) / size.height()) as f32,
+        );
+        resvg::render(
+            &self.tree,
+            usvg::FitTo::Original,
+            transform,
+            pixmap.as_mut(),
+        )
+        .ok_or_else(|| {
+            ImageError::Decoding(DecodingError::new(
+                ImageFormat::Svg.into(),
+                "the image could not be rendered",
+            ))
+        })?;
+
+        let mut data = pixmap.take();
+        for pixel in data.chunks_exact_mut(4) {
+            let alpha = u16::from(pixel[3]);
+            if alpha != 0 && alpha != 255 {
+                for channel in &mut pixel[..3] {
+                    let value = (u16::from(*channel) * 255 + alpha / 2) / alpha;
+                    *channel = value.min(255) as u8;
+                }
+            }
+        }
+        Ok(data)
+    }
+}
+
+fn parse<R: Read>(mut r: R) -> ImageResult<usvg::Tree> {
+    let mut data = Vec::new();
+    r.read_to_end(&mut data)?;
+    let options = usvg::Options::default();
+    usvg::Tree::from_data(&data, &options.to_ref())
+        .map_err(|err| ImageError::Decoding(DecodingError::new(ImageFormat::Svg.into(), err)))
+}
+
+/// The number of pixels that a length in pixels is rendered to.
+fn pixels(length: f64) -> ImageResult<u32> {
+    let pixels = length.ceil();
+    if pixels >= 1.0 && pixels <= f64::from(u32::MAX) {
+        Ok(pixels as u32)
+    } else {
+        Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::DimensionMismatch,
+        )))
+    }
+}
+
+/// The reader returned by `SvgDecoder::into_reader`.
+pub struct SvgReader<R>(Cursor<Vec<u8>>, PhantomData<R>);
+impl<R> Read for SvgReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> io::Result<usize> {
+        if self.0.position() == 0 && buf.is_empty() {
+            mem::swap(buf, self.0.get_mut());
+            Ok(buf.len())
+        } else {
+            self.0.read_to_end(buf)
+        }
+    }
+}
+
+impl<'a, R: 'a + Read> ImageDecoder<'a> for SvgDecoder<R> {
+    type Reader = SvgReader<R>;
+
+    fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn color_type(&self) -> ColorType {
+        ColorType::Rgba8
+    }
+
+    fn into_reader(self) -> ImageResult<Self::Reader> {
+        Ok(SvgReader(Cursor::new(self.render()?), PhantomData))
+    }
+
+    fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        buf.copy_from_slice(&self.render()?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 4 by 2 image whose left half is red.
+    const HALF_RED: &[u8] = br##"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="2">
+        <rect width="2" height="2" fill="#ff0000"/>
+    </svg>"##;
+
+    fn render(decoder: SvgDecoder<&[u8]>) -> Vec<u8> {
+        let mut buf = vec![0; decoder.total_bytes() as usize];
+        decoder.read_image(&mut buf).unwrap();
+        buf
+    }
+
+    #[test]
+    fn renders_at_declared_size() {
+        let decoder = SvgDecoder::new(HALF_RED).unwrap();
+        assert_eq!(decoder.dimensions(), (4, 2));
+        assert_eq!(decoder.color_type(), ColorType::Rgba8);
+        let pixels = render(decoder);
+        assert_eq!(&pixels[..4], &[255, 0, 0, 255]);
+        assert_eq!(&pixels[12..16], &[0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn renders_at_chosen_resolution() {
+        let decoder = SvgDecoder::with_dpi(HALF_RED, 2.0 * CSS_DPI).unwrap();
+        assert_eq!(decoder.dimensions(), (8, 4));
+
+        let decoder = SvgDecoder::with_size(HALF_RED, 8, 8).unwrap();
+        assert_eq!(decoder.dimensions(), (8, 8));
+        let pixels = render(decoder);
+        assert_eq!(&pixels[..4], &[255, 0, 0, 255]);
+        assert_eq!(&pixels[7 * 4..8 * 4], &[0, 0, 0, 0]);
+
+        assert!(SvgDecoder::with_size(HALF_RED, 0, 8).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        assert!(SvgDecoder::new(&b"<svg"[..]).is_err());
+    }
+}
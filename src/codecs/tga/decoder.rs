@@ -4,12 +4,13 @@ use crate::{
     error::{
         ImageError, ImageResult, LimitError, LimitErrorKind, UnsupportedError, UnsupportedErrorKind,
     },
-    image::{ImageDecoder, ImageFormat, ImageReadBuffer},
+    image::{self, ImageDecoder, ImageDecoderRect, ImageFormat, ImageReadBuffer, Progress},
+    io::Limits,
 };
 use byteorder::ReadBytesExt;
 use std::{
     convert::TryFrom,
-    io::{self, Read, Seek},
+    io::{self, Read, Seek, SeekFrom},
     mem,
 };
 
@@ -65,6 +66,9 @@ pub struct TgaDecoder<R> {
     // Used in read_scanline
     line_read: Option<usize>,
     line_remain_buff: Vec<u8>,
+
+    /// The limits on the pixel data that is collected next to the output buffer.
+    limits: Limits,
 }
 
 impl<R: Read + Seek> TgaDecoder<R> {
@@ -87,6 +91,8 @@ impl<R: Read + Seek> TgaDecoder<R> {
 
             line_read: None,
             line_remain_buff: Vec::new(),
+
+            limits: Limits::no_limits(),
         };
         decoder.read_metadata()?;
         Ok(decoder)
@@ -237,6 +243,8 @@ impl<R: Read + Seek> TgaDecoder<R> {
     }
 
     /// Reads a run length encoded data for given number of bytes
+    ///
+    /// The last packet may hold more data than requested, which is returned as well.
     fn read_encoded_data(&mut self, num_bytes: usize) -> io::Result<Vec<u8>> {
         let mut pixel_data = Vec::with_capacity(num_bytes);
         let mut repeat_buf = Vec::with_capacity(self.bytes_per_pixel);
@@ -272,6 +280,14 @@ impl<R: Read + Seek> TgaDecoder<R> {
             }
         }
 
+        Ok(pixel_data)
+    }
+
+    /// Reads a run length encoded packet
+    fn read_all_encoded_data(&mut self) -> ImageResult<Vec<u8>> {
+        let num_bytes = self.width * self.height * self.bytes_per_pixel;
+
+        let mut pixel_data = self.read_encoded_data(num_bytes)?;
         if pixel_data.len() > num_bytes {
             // FIXME: the last packet contained more data than we asked for!
             // This is at least a warning. We truncate the data since some methods rely on the
@@ -282,13 +298,6 @@ impl<R: Read + Seek> TgaDecoder<R> {
         Ok(pixel_data)
     }
 
-    /// Reads a run length encoded packet
-    fn read_all_encoded_data(&mut self) -> ImageResult<Vec<u8>> {
-        let num_bytes = self.width * self.height * self.bytes_per_pixel;
-
-        Ok(self.read_encoded_data(num_bytes)?)
-    }
-
     /// Reads a run length encoded line
     fn read_encoded_line(&mut self) -> io::Result<Vec<u8>> {
         let line_num_bytes = self.width * self.bytes_per_pixel;
@@ -409,6 +418,84 @@ impl<R: Read + Seek> TgaDecoder<R> {
 
         Ok(pixel_data.len())
     }
+
+    /// Decode the rows of the section of the image starting at `x` and `y` into `buf`.
+    ///
+    /// Expects the reader to be at the start of the pixel data, `start`.
+    fn read_rect_rows<F: Fn(Progress)>(
+        &mut self,
+        start: u64,
+        (x, y, width, height): (usize, usize, usize, usize),
+        buf: &mut [u8],
+        progress: F,
+    ) -> ImageResult<()> {
+        let row_bytes = width * usize::from(self.color_type.bytes_per_pixel());
+        let total_bytes = (row_bytes * height) as u64;
+        let section = x * self.bytes_per_pixel..(x + width) * self.bytes_per_pixel;
+        let (flipped, image_height) = (self.is_flipped_vertically(), self.height);
+        let stored_row = |row: usize| {
+            if flipped {
+                image_height - 1 - row
+            } else {
+                row
+            }
+        };
+
+        let mut bytes_read = 0;
+        if self.image_type.is_encoded() {
+            // Run length encoded rows can only be found by decoding all rows before them.
+            let (first, last) = if flipped {
+                (stored_row(y + height - 1), stored_row(y))
+            } else {
+                (y, y + height - 1)
+            };
+            for stored in 0..=last {
+                let line = self.read_encoded_line()?;
+                if stored < first {
+                    continue;
+                }
+                let row = stored_row(stored) - y;
+                self.decode_row_section(
+                    &line[section.clone()],
+                    &mut buf[row * row_bytes..][..row_bytes],
+                )?;
+                bytes_read += row_bytes as u64;
+                progress(Progress::new(bytes_read, total_bytes));
+            }
+        } else {
+            let mut line = vec![0; section.len()];
+            for row in 0..height {
+                let offset = (stored_row(y + row) * self.width + x) * self.bytes_per_pixel;
+                self.r.seek(SeekFrom::Start(start + offset as u64))?;
+                self.r.read_exact(&mut line)?;
+                self.decode_row_section(&line, &mut buf[row * row_bytes..][..row_bytes])?;
+                bytes_read += row_bytes as u64;
+                progress(Progress::new(bytes_read, total_bytes));
+            }
+        }
+        Ok(())
+    }
+
+    /// Convert a section of a row of pixel data into the output color type.
+    fn decode_row_section(&mut self, data: &[u8], out: &mut [u8]) -> ImageResult<()> {
+        if self.image_type.is_color_mapped() {
+            let pixels = self.expand_color_map(data)?;
+            if pixels.len() != out.len() {
+                return Err(ImageError::Limits(LimitError::from_kind(
+                    LimitErrorKind::DimensionError,
+                )));
+            }
+            out.copy_from_slice(&pixels);
+        } else if data.len() == out.len() {
+            out.copy_from_slice(data);
+        } else {
+            return Err(ImageError::Limits(LimitError::from_kind(
+                LimitErrorKind::DimensionError,
+            )));
+        }
+        self.reverse_encoding_in_output(out);
+        Ok(())
+    }
 }
 
 impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for TgaDecoder<R> {
@@ -433,6 +520,14 @@ impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for TgaDecoder<R> {
         u64::from(self.color_type.bytes_per_pixel()) * self.width as u64
     }
 
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        self.limits = limits;
+        Ok(())
+    }
+
     fn into_reader(self) -> ImageResult<Self::Reader> {
         Ok(TGAReader {
             buffer: ImageReadBuffer::new(self.scanline_bytes(), self.total_bytes()),
@@ -443,31 +538,40 @@ impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for TgaDecoder<R> {
     fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
         assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
 
+        // Encoded data is collected before it is copied out, and indices are expanded into a
+        // buffer of their own.
+        let num_raw_bytes = self.width * self.height * self.bytes_per_pixel;
+        let raw_fits = self.bytes_per_pixel <= usize::from(self.color_type.bytes_per_pixel());
+        let mut limits = self.limits.clone();
+        if self.image_type.is_encoded() || !raw_fits {
+            limits.reserve(num_raw_bytes as u64)?;
+        }
+        if self.image_type.is_color_mapped() {
+            limits.reserve(buf.len() as u64)?;
+        }
+
         // In indexed images, we might need more bytes than pixels to read them. That's nonsensical
         // to encode but we'll not want to crash.
         let mut fallback_buf = vec![];
         // read the pixels from the data region
         let rawbuf = if self.image_type.is_encoded() {
             let pixel_data = self.read_all_encoded_data()?;
-            if self.bytes_per_pixel <= usize::from(self.color_type.bytes_per_pixel()) {
+            if raw_fits {
                 buf[..pixel_data.len()].copy_from_slice(&pixel_data);
                 &buf[..pixel_data.len()]
             } else {
                 fallback_buf = pixel_data;
                 &fallback_buf[..]
             }
+        } else if raw_fits {
+            self.r.by_ref().read_exact(&mut buf[..num_raw_bytes])?;
+            &buf[..num_raw_bytes]
         } else {
-            let num_raw_bytes = self.width * self.height * self.bytes_per_pixel;
-            if self.bytes_per_pixel <= usize::from(self.color_type.bytes_per_pixel()) {
-                self.r.by_ref().read_exact(&mut buf[..num_raw_bytes])?;
-                &buf[..num_raw_bytes]
-            } else {
-                fallback_buf.resize(num_raw_bytes, 0u8);
-                self.r
-                    .by_ref()
-                    .read_exact(&mut fallback_buf[..num_raw_bytes])?;
-                &fallback_buf[..num_raw_bytes]
-            }
+            fallback_buf.resize(num_raw_bytes, 0u8);
+            self.r
+                .by_ref()
+                .read_exact(&mut fallback_buf[..num_raw_bytes])?;
+            &fallback_buf[..num_raw_bytes]
         };
 
         // expand the indices using the color map if necessary
@@ -490,6 +594,31 @@ impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for TgaDecoder<R> {
     }
 }
 
+impl<'a, R: 'a + Read + Seek> ImageDecoderRect<'a> for TgaDecoder<R> {
+    fn read_rect_with_progress<F: Fn(Progress)>(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        buf: &mut [u8],
+        progress_callback: F,
+    ) -> ImageResult<()> {
+        let total_bytes = image::check_rect(self, x, y, width, height, buf)?;
+        progress_callback(Progress::new(0, total_bytes as u64));
+
+        // Rows are decoded one at a time. Afterwards the decoder is reset to the start of the
+        // pixel data, so the image can be read again.
+        let start = self.r.stream_position()?;
+        let remain = mem::take(&mut self.line_remain_buff);
+        let section = (x as usize, y as usize, width as usize, height as usize);
+        let result = self.read_rect_rows(start, section, buf, progress_callback);
+        self.r.seek(SeekFrom::Start(start))?;
+        self.line_remain_buff = remain;
+        result
+    }
+}
+
 pub struct TGAReader<R> {
     buffer: ImageReadBuffer,
     decoder: TgaDecoder<R>,
@@ -500,3 +629,47 @@ impl<R: Read + Seek> Read for TGAReader<R> {
         self.buffer.read(buf, |buf| decoder.read_scanline(buf))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codecs::tga::TgaEncoder;
+    use std::io::Cursor;
+
+    #[test]
+    fn read_rect_matches_image() {
+        // Uncompressed and run length encoded images stored bottom-up, and an image stored
+        // top-down.
+        let mut files: Vec<Vec<u8>> = ["utc24", "utc32", "ctc24", "ubw8", "cbw8"]
+            .iter()
+            .map(|name| std::fs::read(format!("tests/images/tga/testsuite/{}.tga", name)).unwrap())
+            .collect();
+        let pixels: Vec<u8> = (0..40 * 30 * 3).map(|i| (i % 253) as u8).collect();
+        let mut top_down = Vec::new();
+        TgaEncoder::new(&mut top_down)
+            .encode(&pixels, 40, 30, ColorType::Rgb8)
+            .unwrap();
+        files.push(top_down);
+
+        for data in &files {
+            let decoder = TgaDecoder::new(Cursor::new(data)).unwrap();
+            let (image_width, _) = decoder.dimensions();
+            let bytes_per_pixel = usize::from(decoder.color_type().bytes_per_pixel());
+            let mut image = vec![0; decoder.total_bytes() as usize];
+            decoder.read_image(&mut image).unwrap();
+
+            let mut decoder = TgaDecoder::new(Cursor::new(data)).unwrap();
+            let (x, y, width, height) = (11, 17, 23, 9);
+            let mut buf = vec![0; width * height * bytes_per_pixel];
+            for _ in 0..2 {
+                decoder
+                    .read_rect(x as u32, y as u32, width as u32, height as u32, &mut buf)
+                    .unwrap();
+                for (row, out) in buf.chunks(width * bytes_per_pixel).enumerate() {
+                    let start = ((y + row) * image_width as usize + x) * bytes_per_pixel;
+                    assert_eq!(out, &image[start..][..out.len()]);
+                }
+            }
+        }
+    }
+}
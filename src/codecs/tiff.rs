@@ -9,7 +9,7 @@
 extern crate tiff;
 
 use std::convert::TryFrom;
-use std::io::{self, Cursor, Read, Seek, Write};
+use std::io::{self, Cursor, Read, Seek, SeekFrom, Write};
 use std::marker::PhantomData;
 use std::mem;
 
@@ -18,9 +18,19 @@ use crate::error::{
     DecodingError, EncodingError, ImageError, ImageResult, LimitError, LimitErrorKind,
     ParameterError, ParameterErrorKind, UnsupportedError, UnsupportedErrorKind,
 };
-use crate::image::{ImageDecoder, ImageEncoder, ImageFormat};
+use crate::image::{
+    self, ImageDecoder, ImageDecoderRect, ImageEncoder, ImageFormat, MultiImageDecoder,
+    MultiImageEncoder, Progress, RowProgress, StreamingEncoder,
+};
+use crate::io::Limits;
+use crate::metadata::{self, Exif, MetadataMap};
 use crate::utils;
 
+/// The tag of the ICC profile, which the `tiff` crate does not name.
+const ICC_PROFILE_TAG: u16 = 34675;
+/// The tag of the XMP packet, which the `tiff` crate does not name either.
+const XMP_TAG: u16 = 700;
+
 /// Decoder for TIFF images.
 pub struct TiffDecoder<R>
 where
@@ -28,7 +38,13 @@ where
 {
     dimensions: (u32, u32),
     color_type: ColorType,
+    image_index: usize,
+    image_count: Option<usize>,
+    /// The EXIF metadata of the first image, read before the `tiff` crate takes the reader.
+    exif: Option<Exif>,
     inner: tiff::decoder::Decoder<R>,
+    /// The limits on the strip or tile that is decoded next to the output buffer.
+    limits: Limits,
 }
 
 impl<R> TiffDecoder<R>
@@ -37,48 +53,97 @@ where
 {
     /// Create a new TiffDecoder.
     pub fn new(r: R) -> Result<TiffDecoder<R>, ImageError> {
-        let mut inner = tiff::decoder::Decoder::new(r).map_err(ImageError::from_tiff_decode)?;
+        Self::open(r, None)
+    }
 
-        let dimensions = inner.dimensions().map_err(ImageError::from_tiff_decode)?;
-        let color_type = inner.colortype().map_err(ImageError::from_tiff_decode)?;
-        match inner.find_tag_unsigned_vec::<u16>(tiff::tags::Tag::SampleFormat) {
-            Ok(Some(sample_formats)) => {
-                for format in sample_formats {
-                    check_sample_format(format)?;
-                }
-            }
-            Ok(None) => { /* assume UInt format */ }
-            Err(other) => return Err(ImageError::from_tiff_decode(other)),
-        };
+    /// Create a new TiffDecoder that decodes within the given limits.
+    ///
+    /// The `max_alloc` limit also bounds the buffers of the `tiff` crate, such as the values of
+    /// the tags of each directory and the compressed data of each strip or tile.
+    pub fn with_limits(r: R, limits: Limits) -> Result<TiffDecoder<R>, ImageError> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        Self::open(r, Some(limits))
+    }
 
-        let color_type = match color_type {
-            tiff::ColorType::Gray(8) => ColorType::L8,
-            tiff::ColorType::Gray(16) => ColorType::L16,
-            tiff::ColorType::GrayA(8) => ColorType::La8,
-            tiff::ColorType::GrayA(16) => ColorType::La16,
-            tiff::ColorType::RGB(8) => ColorType::Rgb8,
-            tiff::ColorType::RGB(16) => ColorType::Rgb16,
-            tiff::ColorType::RGBA(8) => ColorType::Rgba8,
-            tiff::ColorType::RGBA(16) => ColorType::Rgba16,
-
-            tiff::ColorType::Palette(n) | tiff::ColorType::Gray(n) => {
-                return Err(err_unknown_color_type(n))
-            }
-            tiff::ColorType::GrayA(n) => return Err(err_unknown_color_type(n * 2)),
-            tiff::ColorType::RGB(n) => return Err(err_unknown_color_type(n * 3)),
-            tiff::ColorType::RGBA(n) | tiff::ColorType::CMYK(n) => {
-                return Err(err_unknown_color_type(n * 4))
+    fn open(mut r: R, limits: Option<Limits>) -> Result<TiffDecoder<R>, ImageError> {
+        let start = r.stream_position()?;
+        let exif = metadata::read_exif(&mut r, start).ok();
+        r.seek(SeekFrom::Start(start))?;
+
+        let mut inner = tiff::decoder::Decoder::new(r).map_err(ImageError::from_tiff_decode)?;
+        if let Some(limits) = &limits {
+            let max_alloc = limits.max_alloc_usize();
+            let mut tiff_limits = tiff::decoder::Limits::default();
+            tiff_limits.decoding_buffer_size = max_alloc;
+            tiff_limits.intermediate_buffer_size = max_alloc;
+            tiff_limits.ifd_value_size = tiff_limits.ifd_value_size.min(max_alloc);
+            // The `tiff` crate reads the first directory before it accepts limits, so read it
+            // again to load its tag values and chunk tables within them.
+            inner = inner.with_limits(tiff_limits);
+            inner
+                .seek_to_image(0)
+                .map_err(ImageError::from_tiff_decode)?;
+        }
+        let (dimensions, color_type) = read_image_info(&mut inner)?;
+        let limits = match limits {
+            Some(limits) => {
+                limits.check_dimensions(dimensions.0, dimensions.1)?;
+                limits
             }
+            None => Limits::no_limits(),
         };
 
         Ok(TiffDecoder {
             dimensions,
             color_type,
+            image_index: 0,
+            image_count: None,
+            exif,
             inner,
+            limits,
         })
     }
 }
 
+/// Read the dimensions and color type of the current image of the decoder.
+fn read_image_info<R: Read + Seek>(
+    inner: &mut tiff::decoder::Decoder<R>,
+) -> ImageResult<((u32, u32), ColorType)> {
+    let dimensions = inner.dimensions().map_err(ImageError::from_tiff_decode)?;
+    let color_type = inner.colortype().map_err(ImageError::from_tiff_decode)?;
+    match inner.find_tag_unsigned_vec::<u16>(tiff::tags::Tag::SampleFormat) {
+        Ok(Some(sample_formats)) => {
+            for format in sample_formats {
+                check_sample_format(format)?;
+            }
+        }
+        Ok(None) => { /* assume UInt format */ }
+        Err(other) => return Err(ImageError::from_tiff_decode(other)),
+    };
+
+    let color_type = match color_type {
+        tiff::ColorType::Gray(8) => ColorType::L8,
+        tiff::ColorType::Gray(16) => ColorType::L16,
+        tiff::ColorType::GrayA(8) => ColorType::La8,
+        tiff::ColorType::GrayA(16) => ColorType::La16,
+        tiff::ColorType::RGB(8) => ColorType::Rgb8,
+        tiff::ColorType::RGB(16) => ColorType::Rgb16,
+        tiff::ColorType::RGBA(8) => ColorType::Rgba8,
+        tiff::ColorType::RGBA(16) => ColorType::Rgba16,
+
+        tiff::ColorType::Palette(n) | tiff::ColorType::Gray(n) => {
+            return Err(err_unknown_color_type(n))
+        }
+        tiff::ColorType::GrayA(n) => return Err(err_unknown_color_type(n * 2)),
+        tiff::ColorType::RGB(n) => return Err(err_unknown_color_type(n * 3)),
+        tiff::ColorType::RGBA(n) | tiff::ColorType::CMYK(n) => {
+            return Err(err_unknown_color_type(n * 4))
+        }
+    };
+
+    Ok((dimensions, color_type))
+}
+
 fn check_sample_format(sample_format: u16) -> Result<(), ImageError> {
     match tiff::tags::SampleFormat::from_u16(sample_format) {
         Some(tiff::tags::SampleFormat::Uint) => Ok(()),
@@ -173,72 +238,248 @@ impl<'a, R: 'a + Read + Seek> ImageDecoder<'a> for TiffDecoder<R> {
         self.color_type
     }
 
-    fn into_reader(mut self) -> ImageResult<Self::Reader> {
-        let buf = match self
+    fn icc_profile(&mut self) -> Option<Vec<u8>> {
+        self.inner
+            .get_tag_u8_vec(tiff::tags::Tag::Unknown(ICC_PROFILE_TAG))
+            .ok()
+    }
+
+    fn exif_metadata(&mut self) -> Option<Exif> {
+        self.exif.clone()
+    }
+
+    /// Returns the XMP packet of the image. TIFF images have no other textual metadata.
+    fn text_metadata(&mut self) -> MetadataMap {
+        let xmp = self
             .inner
-            .read_image()
-            .map_err(ImageError::from_tiff_decode)?
-        {
-            tiff::decoder::DecodingResult::U8(v) => v,
-            tiff::decoder::DecodingResult::U16(v) => utils::vec_copy_to_u8(&v),
-            tiff::decoder::DecodingResult::U32(v) => utils::vec_copy_to_u8(&v),
-            tiff::decoder::DecodingResult::U64(v) => utils::vec_copy_to_u8(&v),
-            tiff::decoder::DecodingResult::I8(v) => utils::vec_copy_to_u8(&v),
-            tiff::decoder::DecodingResult::I16(v) => utils::vec_copy_to_u8(&v),
-            tiff::decoder::DecodingResult::I32(v) => utils::vec_copy_to_u8(&v),
-            tiff::decoder::DecodingResult::I64(v) => utils::vec_copy_to_u8(&v),
-            tiff::decoder::DecodingResult::F32(v) => utils::vec_copy_to_u8(&v),
-            tiff::decoder::DecodingResult::F64(v) => utils::vec_copy_to_u8(&v),
+            .get_tag_u8_vec(tiff::tags::Tag::Unknown(XMP_TAG))
+            .ok()
+            .and_then(|xmp| String::from_utf8(xmp).ok());
+        let mut metadata = MetadataMap::new();
+        metadata.set_xmp(xmp);
+        metadata
+    }
+
+    /// Selects the smallest of the reduced-resolution pages that follow the image and keep the
+    /// requested dimensions, like the levels of a pyramidal TIFF.
+    fn request_dimensions(&mut self, width: u32, height: u32) -> bool {
+        let (index, count) = match self.image_count() {
+            Ok(count) => (self.image_index, count),
+            Err(_) => return false,
         };
 
+        let mut best: Option<(usize, u64)> = None;
+        for page in index + 1..count {
+            if self.inner.seek_to_image(page).is_err() {
+                break;
+            }
+            // Bit 0 of the subfile type marks a reduced resolution of the previous full image.
+            let subfile_type = self
+                .inner
+                .find_tag_unsigned::<u32>(tiff::tags::Tag::NewSubfileType);
+            if !matches!(subfile_type, Ok(Some(subfile_type)) if subfile_type & 1 != 0) {
+                break;
+            }
+            if let Ok(((page_width, page_height), _)) = read_image_info(&mut self.inner) {
+                let pixels = u64::from(page_width) * u64::from(page_height);
+                let fits = page_width >= width && page_height >= height;
+                if fits && best.map_or(true, |(_, best_pixels)| pixels < best_pixels) {
+                    best = Some((page, pixels));
+                }
+            }
+        }
+
+        let selected = best.map_or(index, |(page, _)| page);
+        self.select_image(selected).is_ok() && selected != index
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions;
+        limits.check_dimensions(width, height)?;
+        self.limits = limits;
+        Ok(())
+    }
+
+    fn into_reader(mut self) -> ImageResult<Self::Reader> {
+        let buf = decoding_result_bytes(
+            self.inner
+                .read_image()
+                .map_err(ImageError::from_tiff_decode)?,
+        );
+
         Ok(TiffReader(Cursor::new(buf), PhantomData))
     }
 
     fn read_image(mut self, buf: &mut [u8]) -> ImageResult<()> {
         assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
-        match self
-            .inner
-            .read_image()
-            .map_err(ImageError::from_tiff_decode)?
-        {
-            tiff::decoder::DecodingResult::U8(v) => {
-                buf.copy_from_slice(&v);
-            }
-            tiff::decoder::DecodingResult::U16(v) => {
-                buf.copy_from_slice(bytemuck::cast_slice(&v));
-            }
-            tiff::decoder::DecodingResult::U32(v) => {
-                buf.copy_from_slice(bytemuck::cast_slice(&v));
-            }
-            tiff::decoder::DecodingResult::U64(v) => {
-                buf.copy_from_slice(bytemuck::cast_slice(&v));
-            }
-            tiff::decoder::DecodingResult::I8(v) => {
-                buf.copy_from_slice(bytemuck::cast_slice(&v));
-            }
-            tiff::decoder::DecodingResult::I16(v) => {
-                buf.copy_from_slice(bytemuck::cast_slice(&v));
-            }
-            tiff::decoder::DecodingResult::I32(v) => {
-                buf.copy_from_slice(bytemuck::cast_slice(&v));
-            }
-            tiff::decoder::DecodingResult::I64(v) => {
-                buf.copy_from_slice(bytemuck::cast_slice(&v));
-            }
-            tiff::decoder::DecodingResult::F32(v) => {
-                buf.copy_from_slice(bytemuck::cast_slice(&v));
+        // Decoding one strip or tile at a time into `buf` avoids holding a second copy of the
+        // whole image.
+        let (width, height) = self.dimensions;
+        self.read_rect(0, 0, width, height, buf)
+    }
+
+    fn read_image_into_uninit(mut self, buf: &mut [mem::MaybeUninit<u8>]) -> ImageResult<()> {
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        let (width, height) = self.dimensions;
+        // The chunks cover the whole image, so every byte is written.
+        let total_bytes = buf.len();
+        let copy = |offset: usize, data: &[u8]| image::copy_to_uninit(&mut buf[offset..], data);
+        self.read_chunks((0, 0, width, height), total_bytes, copy, |_| {})
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> ImageDecoderRect<'a> for TiffDecoder<R> {
+    /// Decode a rectangular section of the image.
+    ///
+    /// Only the strips or tiles that overlap the section are decoded, one at a time, so the
+    /// memory needed is bounded by the section and a single chunk instead of the whole image.
+    fn read_rect_with_progress<F: Fn(Progress)>(
+        &mut self,
+        x: u32,
+        y: u32,
+        width: u32,
+        height: u32,
+        buf: &mut [u8],
+        progress_callback: F,
+    ) -> ImageResult<()> {
+        let total_bytes = image::check_rect(self, x, y, width, height, buf)?;
+        let copy = |offset: usize, data: &[u8]| buf[offset..][..data.len()].copy_from_slice(data);
+        self.read_chunks((x, y, width, height), total_bytes, copy, progress_callback)
+    }
+}
+
+impl<R: Read + Seek> TiffDecoder<R> {
+    /// Decode the chunks that overlap a section of the image, passing the rows of the section
+    /// they hold to `copy` together with their offset in the output.
+    fn read_chunks(
+        &mut self,
+        (x, y, width, height): (u32, u32, u32, u32),
+        total_bytes: usize,
+        mut copy: impl FnMut(usize, &[u8]),
+        progress_callback: impl Fn(Progress),
+    ) -> ImageResult<()> {
+        let bytes_per_pixel = usize::from(self.color_type.bytes_per_pixel());
+        let row_bytes = width as usize * bytes_per_pixel;
+        // Strips span the whole width of the image, so they are chunks of a single column.
+        let (chunk_width, chunk_height) = self.inner.chunk_dimensions();
+        let chunks_across = (self.dimensions.0 + chunk_width - 1) / chunk_width;
+        // Each chunk is decoded into a buffer of its own before it is copied into `buf`.
+        let chunk_bytes = u64::from(chunk_width) * u64::from(chunk_height) * bytes_per_pixel as u64;
+        self.limits.clone().reserve(chunk_bytes)?;
+
+        let mut bytes_read = 0;
+        progress_callback(Progress::new(0, total_bytes as u64));
+        for chunk_y in y / chunk_height..=(y + height - 1) / chunk_height {
+            for chunk_x in x / chunk_width..=(x + width - 1) / chunk_width {
+                // Decompressing a chunk takes a while after its data was read, so cancellation is
+                // checked before each of them.
+                self.limits.check_cancelled()?;
+                let index = chunk_y * chunks_across + chunk_x;
+                let chunk = decoding_result_bytes(
+                    self.inner
+                        .read_chunk(index)
+                        .map_err(ImageError::from_tiff_decode)?,
+                );
+                let (data_width, data_height) = self.inner.chunk_data_dimensions(index);
+                let (left, top) = (chunk_x * chunk_width, chunk_y * chunk_height);
+
+                // The part of the chunk that lies within the section.
+                let start_x = x.max(left);
+                let end_x = (x + width).min(left + data_width);
+                let start_y = y.max(top);
+                let end_y = (y + height).min(top + data_height);
+                let len = (end_x - start_x) as usize * bytes_per_pixel;
+                for row in start_y..end_y {
+                    let src =
+                        ((row - top) * data_width + start_x - left) as usize * bytes_per_pixel;
+                    let dst =
+                        (row - y) as usize * row_bytes + (start_x - x) as usize * bytes_per_pixel;
+                    copy(dst, &chunk[src..][..len]);
+                }
+
+                bytes_read += (end_y - start_y) as usize * len;
+                progress_callback(Progress::new(bytes_read as u64, total_bytes as u64));
             }
-            tiff::decoder::DecodingResult::F64(v) => {
-                buf.copy_from_slice(bytemuck::cast_slice(&v));
+        }
+        Ok(())
+    }
+}
+
+impl<'a, R: 'a + Read + Seek> MultiImageDecoder<'a> for TiffDecoder<R> {
+    fn image_count(&mut self) -> ImageResult<usize> {
+        if let Some(count) = self.image_count {
+            return Ok(count);
+        }
+
+        // The directories form a linked list, so they have to be visited to count them.
+        let mut count = self.image_index + 1;
+        loop {
+            match self.inner.seek_to_image(count) {
+                Ok(()) => count += 1,
+                Err(tiff::TiffError::FormatError(
+                    tiff::TiffFormatError::ImageFileDirectoryNotFound,
+                )) => break,
+                Err(err) => return Err(ImageError::from_tiff_decode(err)),
             }
         }
+        self.inner
+            .seek_to_image(self.image_index)
+            .map_err(ImageError::from_tiff_decode)?;
+
+        self.image_count = Some(count);
+        Ok(count)
+    }
+
+    fn select_image(&mut self, index: usize) -> ImageResult<()> {
+        if index >= self.image_count()? {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!("the file has no image at index {}", index)),
+            )));
+        }
+        self.inner
+            .seek_to_image(index)
+            .map_err(ImageError::from_tiff_decode)?;
+        let (dimensions, color_type) = read_image_info(&mut self.inner)?;
+        self.dimensions = dimensions;
+        self.color_type = color_type;
+        self.image_index = index;
         Ok(())
     }
+
+    fn read_page(&mut self, index: usize, buf: &mut [u8]) -> ImageResult<()> {
+        self.select_image(index)?;
+        assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
+        let (width, height) = self.dimensions;
+        self.read_rect(0, 0, width, height, buf)
+    }
+}
+
+/// Convert the samples decoded by the tiff crate into native endian bytes.
+fn decoding_result_bytes(result: tiff::decoder::DecodingResult) -> Vec<u8> {
+    match result {
+        tiff::decoder::DecodingResult::U8(v) => v,
+        tiff::decoder::DecodingResult::U16(v) => utils::vec_copy_to_u8(&v),
+        tiff::decoder::DecodingResult::U32(v) => utils::vec_copy_to_u8(&v),
+        tiff::decoder::DecodingResult::U64(v) => utils::vec_copy_to_u8(&v),
+        tiff::decoder::DecodingResult::I8(v) => utils::vec_copy_to_u8(&v),
+        tiff::decoder::DecodingResult::I16(v) => utils::vec_copy_to_u8(&v),
+        tiff::decoder::DecodingResult::I32(v) => utils::vec_copy_to_u8(&v),
+        tiff::decoder::DecodingResult::I64(v) => utils::vec_copy_to_u8(&v),
+        tiff::decoder::DecodingResult::F32(v) => utils::vec_copy_to_u8(&v),
+        tiff::decoder::DecodingResult::F64(v) => utils::vec_copy_to_u8(&v),
+    }
 }
 
 /// Encoder for tiff images
+///
+/// Multiple pages can be written with the [`MultiImageEncoder`] implementation.
+///
+/// [`MultiImageEncoder`]: ../../trait.MultiImageEncoder.html
 pub struct TiffEncoder<W> {
-    w: W,
+    // The writer until the first image is written, after that it is owned by `encoder`.
+    w: Option<W>,
+    encoder: Option<tiff::encoder::TiffEncoder<W>>,
 }
 
 // Utility to simplify and deduplicate error handling during 16-bit encoding.
@@ -258,15 +499,139 @@ fn u8_slice_as_u16(buf: &[u8]) -> ImageResult<&[u16]> {
 impl<W: Write + Seek> TiffEncoder<W> {
     /// Create a new encoder that writes its output to `w`
     pub fn new(w: W) -> TiffEncoder<W> {
-        TiffEncoder { w }
+        TiffEncoder {
+            w: Some(w),
+            encoder: None,
+        }
     }
 
     /// Encodes the image `image` that has dimensions `width` and `height` and `ColorType` `c`.
     ///
     /// 16-bit types assume the buffer is native endian.
-    pub fn encode(self, data: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<()> {
-        let mut encoder =
-            tiff::encoder::TiffEncoder::new(self.w).map_err(ImageError::from_tiff_encode)?;
+    pub fn encode(
+        mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        color: ColorType,
+    ) -> ImageResult<()> {
+        self.write_page(data, width, height, color)
+    }
+
+    /// Encodes the image `image` as tiles, optionally followed by reduced-resolution levels, see
+    /// [`TiledTiffOptions`].
+    ///
+    /// Each level is half the size of the previous one and marked as a reduced-resolution page,
+    /// which viewers use as the pyramid of the image. A BigTIFF file is written when the image
+    /// would not fit into the 4 GiB of a standard TIFF. 16-bit types assume the buffer is native
+    /// endian.
+    ///
+    /// [`TiledTiffOptions`]: struct.TiledTiffOptions.html
+    pub fn encode_tiled(
+        mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        color: ColorType,
+        options: TiledTiffOptions,
+    ) -> ImageResult<()> {
+        let tile = (options.tile_width, options.tile_height);
+        if tile.0 == 0 || tile.0 % 16 != 0 || tile.1 == 0 || tile.1 % 16 != 0 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!(
+                    "tile size {}x{} is not a multiple of 16",
+                    tile.0, tile.1
+                )),
+            )));
+        }
+        if tiff_photometric(color).is_none() {
+            return Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    ImageFormat::Tiff.into(),
+                    UnsupportedErrorKind::Color(color.into()),
+                ),
+            ));
+        }
+        let pixel_bytes = u64::from(color.bytes_per_pixel());
+        if width == 0
+            || height == 0
+            || u64::from(width) * u64::from(height) * pixel_bytes != data.len() as u64
+        {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+
+        let levels = pyramid_dimensions(width, height, tile, options.pyramid_levels);
+        let tile_bytes = u64::from(tile.0) * u64::from(tile.1) * pixel_bytes;
+        let file_bytes: u64 = levels
+            .iter()
+            .map(|&(width, height)| {
+                let tiles = u64::from((width + tile.0 - 1) / tile.0)
+                    * u64::from((height + tile.1 - 1) / tile.1);
+                // The tile, its offset and its byte count.
+                tiles * (tile_bytes + 16)
+            })
+            .sum();
+        // Leave some room for the directories.
+        if options.big_tiff || file_bytes > u64::from(u32::MAX) - (1 << 20) {
+            let w = self.w.take().ok_or_else(|| {
+                ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                    "a BigTIFF image can not be appended to a standard TIFF".to_string(),
+                )))
+            })?;
+            let mut encoder =
+                tiff::encoder::TiffEncoder::new_big(w).map_err(ImageError::from_tiff_encode)?;
+            write_pyramid(&mut encoder, data, color, &levels, tile)
+        } else {
+            write_pyramid(self.inner()?, data, color, &levels, tile)
+        }
+        .map_err(ImageError::from_tiff_encode)
+    }
+
+    /// Start writing a page of `width`, `height` and `color_type` row by row, see
+    /// [`StreamingEncoder`].
+    ///
+    /// The rows are collected into strips of about 1 MB, each of which is written as soon as it
+    /// is complete. Once the page is finished, further pages can be appended.
+    ///
+    /// [`StreamingEncoder`]: ../../trait.StreamingEncoder.html
+    pub fn streaming(
+        &mut self,
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<StreamingTiffEncoder<'_, W>> {
+        let progress = RowProgress::new(width, height, color_type)?;
+        let page = StreamingPage::new(self.inner()?, width, height, color_type)?;
+        Ok(StreamingTiffEncoder {
+            page,
+            progress,
+            strip: Vec::new(),
+        })
+    }
+
+    /// The encoder of the `tiff` crate, which writes the header of the file when it is created.
+    fn inner(&mut self) -> ImageResult<&mut tiff::encoder::TiffEncoder<W>> {
+        if let Some(w) = self.w.take() {
+            let encoder =
+                tiff::encoder::TiffEncoder::new(w).map_err(ImageError::from_tiff_encode)?;
+            self.encoder = Some(encoder);
+        }
+        // Only missing if writing the header failed before.
+        self.encoder.as_mut().ok_or_else(|| {
+            ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::FailedAlready))
+        })
+    }
+
+    fn write_page(
+        &mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        color: ColorType,
+    ) -> ImageResult<()> {
+        let encoder = self.inner()?;
         match color {
             ColorType::L8 => {
                 encoder.write_image::<tiff::encoder::colortype::Gray8>(width, height, data)
@@ -307,6 +672,206 @@ impl<W: Write + Seek> TiffEncoder<W> {
     }
 }
 
+/// The layout of a tiled TIFF written by [`TiffEncoder::encode_tiled`].
+///
+/// Construct it with `Default` and set the fields of interest.
+///
+/// [`TiffEncoder::encode_tiled`]: struct.TiffEncoder.html#method.encode_tiled
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TiledTiffOptions {
+    /// The width of a tile, a multiple of 16. Defaults to 256.
+    pub tile_width: u32,
+    /// The height of a tile, a multiple of 16. Defaults to 256.
+    pub tile_height: u32,
+    /// The maximum number of reduced-resolution levels after the full image. Defaults to 0.
+    ///
+    /// No further levels are written once a level fits into a single tile.
+    pub pyramid_levels: u32,
+    /// Write a BigTIFF file even if the image fits into a standard TIFF. Defaults to `false`.
+    pub big_tiff: bool,
+}
+
+impl Default for TiledTiffOptions {
+    fn default() -> Self {
+        TiledTiffOptions {
+            tile_width: 256,
+            tile_height: 256,
+            pyramid_levels: 0,
+            big_tiff: false,
+        }
+    }
+}
+
+/// The photometric interpretation of the color types that can be written as tiles.
+fn tiff_photometric(color: ColorType) -> Option<tiff::tags::PhotometricInterpretation> {
+    use tiff::tags::PhotometricInterpretation;
+
+    match color {
+        ColorType::L8 | ColorType::L16 => Some(PhotometricInterpretation::BlackIsZero),
+        ColorType::Rgb8 | ColorType::Rgba8 | ColorType::Rgb16 | ColorType::Rgba16 => {
+            Some(PhotometricInterpretation::RGB)
+        }
+        _ => None,
+    }
+}
+
+/// The dimensions of the full image and of each reduced-resolution level.
+fn pyramid_dimensions(
+    width: u32,
+    height: u32,
+    (tile_width, tile_height): (u32, u32),
+    max_levels: u32,
+) -> Vec<(u32, u32)> {
+    let mut levels = vec![(width, height)];
+    while levels.len() <= max_levels as usize {
+        let (width, height) = levels[levels.len() - 1];
+        if width <= tile_width && height <= tile_height {
+            break;
+        }
+        levels.push(((width + 1) / 2, (height + 1) / 2));
+    }
+    levels
+}
+
+/// Halve the dimensions of an image by averaging blocks of 2x2 pixels.
+///
+/// At an odd width or height, the last column or row is repeated.
+fn halve(data: &[u8], width: u32, height: u32, color: ColorType) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let channels = usize::from(color.channel_count());
+    let sample_bytes = usize::from(color.bytes_per_pixel()) / channels;
+    let sample = |x: usize, y: usize, c: usize| {
+        let i = ((y * width + x) * channels + c) * sample_bytes;
+        match sample_bytes {
+            2 => u32::from(u16::from_ne_bytes([data[i], data[i + 1]])),
+            _ => u32::from(data[i]),
+        }
+    };
+
+    let (out_width, out_height) = ((width + 1) / 2, (height + 1) / 2);
+    let mut out = Vec::with_capacity(out_width * out_height * channels * sample_bytes);
+    for y in 0..out_height {
+        let rows = [2 * y, (2 * y + 1).min(height - 1)];
+        for x in 0..out_width {
+            let columns = [2 * x, (2 * x + 1).min(width - 1)];
+            for c in 0..channels {
+                let sum: u32 = rows
+                    .iter()
+                    .flat_map(|&y| columns.iter().map(move |&x| (x, y)))
+                    .map(|(x, y)| sample(x, y, c))
+                    .sum();
+                let value = (sum + 2) / 4;
+                match sample_bytes {
+                    2 => out.extend_from_slice(&(value as u16).to_ne_bytes()),
+                    _ => out.push(value as u8),
+                }
+            }
+        }
+    }
+    out
+}
+
+/// Write the full image and its reduced-resolution `levels` as tiled pages.
+fn write_pyramid<W: Write + Seek, K: tiff::encoder::TiffKind>(
+    encoder: &mut tiff::encoder::TiffEncoder<W, K>,
+    data: &[u8],
+    color: ColorType,
+    levels: &[(u32, u32)],
+    tile: (u32, u32),
+) -> tiff::TiffResult<()> {
+    let (width, height) = levels[0];
+    write_tiled_page(encoder, data, width, height, color, tile, false)?;
+
+    let mut level = None;
+    for window in levels.windows(2) {
+        let ((width, height), (reduced_width, reduced_height)) = (window[0], window[1]);
+        let reduced = halve(level.as_deref().unwrap_or(data), width, height, color);
+        write_tiled_page(
+            encoder,
+            &reduced,
+            reduced_width,
+            reduced_height,
+            color,
+            tile,
+            true,
+        )?;
+        level = Some(reduced);
+    }
+    Ok(())
+}
+
+fn write_tiled_page<W: Write + Seek, K: tiff::encoder::TiffKind>(
+    encoder: &mut tiff::encoder::TiffEncoder<W, K>,
+    data: &[u8],
+    width: u32,
+    height: u32,
+    color: ColorType,
+    (tile_width, tile_height): (u32, u32),
+    reduced: bool,
+) -> tiff::TiffResult<()> {
+    use tiff::tags::{CompressionMethod, PlanarConfiguration, SampleFormat, Tag};
+
+    let channels = color.channel_count();
+    let pixel_bytes = usize::from(color.bytes_per_pixel());
+    let row_bytes = width as usize * pixel_bytes;
+    let tile_row_bytes = tile_width as usize * pixel_bytes;
+
+    let mut directory = encoder.new_directory()?;
+    let mut offsets = Vec::new();
+    let mut byte_counts = Vec::new();
+    let mut tile = vec![0; tile_row_bytes * tile_height as usize];
+    for y in (0..height as usize).step_by(tile_height as usize) {
+        for x in (0..width as usize).step_by(tile_width as usize) {
+            // Tiles at the right and bottom edge are padded with zeros.
+            let columns = (width as usize - x).min(tile_width as usize) * pixel_bytes;
+            for (row, out) in tile.chunks_exact_mut(tile_row_bytes).enumerate() {
+                let y = y + row;
+                if y < height as usize {
+                    let start = y * row_bytes + x * pixel_bytes;
+                    out[..columns].copy_from_slice(&data[start..][..columns]);
+                    out[columns..].fill(0);
+                } else {
+                    out.fill(0);
+                }
+            }
+            offsets.push(K::convert_offset(directory.write_data(&tile[..])?)?);
+            byte_counts.push(tile.len() as u32);
+        }
+    }
+
+    let bits_per_sample = color.bits_per_pixel() / u16::from(channels);
+    directory.write_tag(Tag::NewSubfileType, u32::from(reduced))?;
+    directory.write_tag(Tag::ImageWidth, width)?;
+    directory.write_tag(Tag::ImageLength, height)?;
+    directory.write_tag(
+        Tag::BitsPerSample,
+        &vec![bits_per_sample; usize::from(channels)][..],
+    )?;
+    directory.write_tag(Tag::Compression, CompressionMethod::None.to_u16())?;
+    // Only called with color types that have an interpretation.
+    let photometric = tiff_photometric(color).unwrap();
+    directory.write_tag(Tag::PhotometricInterpretation, photometric.to_u16())?;
+    directory.write_tag(Tag::SamplesPerPixel, u16::from(channels))?;
+    directory.write_tag(
+        Tag::PlanarConfiguration,
+        PlanarConfiguration::Chunky.to_u16(),
+    )?;
+    if color.has_alpha() {
+        // Unassociated alpha.
+        directory.write_tag(Tag::ExtraSamples, 2u16)?;
+    }
+    directory.write_tag(Tag::TileWidth, tile_width)?;
+    directory.write_tag(Tag::TileLength, tile_height)?;
+    directory.write_tag(Tag::TileOffsets, K::convert_slice(&offsets))?;
+    directory.write_tag(Tag::TileByteCounts, &byte_counts[..])?;
+    directory.write_tag(
+        Tag::SampleFormat,
+        &vec![SampleFormat::Uint.to_u16(); usize::from(channels)][..],
+    )?;
+    directory.finish()
+}
+
 impl<W: Write + Seek> ImageEncoder for TiffEncoder<W> {
     fn write_image(
         self,
@@ -318,3 +883,431 @@ impl<W: Write + Seek> ImageEncoder for TiffEncoder<W> {
         self.encode(buf, width, height, color_type)
     }
 }
+
+impl<W: Write + Seek> MultiImageEncoder for TiffEncoder<W> {
+    fn append_image(
+        &mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        self.write_page(buf, width, height, color_type)
+    }
+
+    fn finish(self) -> ImageResult<()> {
+        // Every page is complete once it was appended, there is nothing left to write.
+        if self.encoder.is_none() {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic("no image was appended".to_string()),
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// TIFF encoder that writes a page row by row, created by [`TiffEncoder::streaming`].
+///
+/// [`TiffEncoder::streaming`]: struct.TiffEncoder.html#method.streaming
+pub struct StreamingTiffEncoder<'a, W: Write + Seek> {
+    page: StreamingPage<'a, W>,
+    progress: RowProgress,
+    /// The rows of the current strip.
+    strip: Vec<u8>,
+}
+
+/// The page encoders of the `tiff` crate, one for each supported color type.
+enum StreamingPage<'a, W: Write + Seek> {
+    L8(PageEncoder<'a, W, tiff::encoder::colortype::Gray8>),
+    Rgb8(PageEncoder<'a, W, tiff::encoder::colortype::RGB8>),
+    Rgba8(PageEncoder<'a, W, tiff::encoder::colortype::RGBA8>),
+    L16(PageEncoder<'a, W, tiff::encoder::colortype::Gray16>),
+    Rgb16(PageEncoder<'a, W, tiff::encoder::colortype::RGB16>),
+    Rgba16(PageEncoder<'a, W, tiff::encoder::colortype::RGBA16>),
+}
+
+type PageEncoder<'a, W, C> = tiff::encoder::ImageEncoder<'a, W, C, tiff::encoder::TiffKindStandard>;
+
+/// Call `$f` with the page encoder, whatever its color type.
+macro_rules! with_page {
+    ($page:expr, $encoder:ident => $f:expr) => {
+        match $page {
+            StreamingPage::L8($encoder) => $f,
+            StreamingPage::Rgb8($encoder) => $f,
+            StreamingPage::Rgba8($encoder) => $f,
+            StreamingPage::L16($encoder) => $f,
+            StreamingPage::Rgb16($encoder) => $f,
+            StreamingPage::Rgba16($encoder) => $f,
+        }
+    };
+}
+
+impl<'a, W: Write + Seek> StreamingPage<'a, W> {
+    fn new(
+        encoder: &'a mut tiff::encoder::TiffEncoder<W>,
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<Self> {
+        use tiff::encoder::colortype;
+
+        match color_type {
+            ColorType::L8 => encoder
+                .new_image::<colortype::Gray8>(width, height)
+                .map(StreamingPage::L8),
+            ColorType::Rgb8 => encoder
+                .new_image::<colortype::RGB8>(width, height)
+                .map(StreamingPage::Rgb8),
+            ColorType::Rgba8 => encoder
+                .new_image::<colortype::RGBA8>(width, height)
+                .map(StreamingPage::Rgba8),
+            ColorType::L16 => encoder
+                .new_image::<colortype::Gray16>(width, height)
+                .map(StreamingPage::L16),
+            ColorType::Rgb16 => encoder
+                .new_image::<colortype::RGB16>(width, height)
+                .map(StreamingPage::Rgb16),
+            ColorType::Rgba16 => encoder
+                .new_image::<colortype::RGBA16>(width, height)
+                .map(StreamingPage::Rgba16),
+            _ => {
+                return Err(ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(
+                        ImageFormat::Tiff.into(),
+                        UnsupportedErrorKind::Color(color_type.into()),
+                    ),
+                ))
+            }
+        }
+        .map_err(ImageError::from_tiff_encode)
+    }
+
+    /// The length of the next strip in bytes.
+    fn strip_bytes(&self) -> usize {
+        let bytes_per_sample = match self {
+            StreamingPage::L8(_) | StreamingPage::Rgb8(_) | StreamingPage::Rgba8(_) => 1,
+            _ => 2,
+        };
+        with_page!(self, encoder => encoder.next_strip_sample_count() as usize * bytes_per_sample)
+    }
+
+    fn write_strip(&mut self, strip: &[u8]) -> tiff::TiffResult<()> {
+        match self {
+            StreamingPage::L8(encoder) => encoder.write_strip(strip),
+            StreamingPage::Rgb8(encoder) => encoder.write_strip(strip),
+            StreamingPage::Rgba8(encoder) => encoder.write_strip(strip),
+            _ => {
+                // The strip is not aligned for 16-bit samples.
+                let samples: Vec<u16> = strip
+                    .chunks_exact(2)
+                    .map(|sample| u16::from_ne_bytes([sample[0], sample[1]]))
+                    .collect();
+                match self {
+                    StreamingPage::L16(encoder) => encoder.write_strip(&samples),
+                    StreamingPage::Rgb16(encoder) => encoder.write_strip(&samples),
+                    StreamingPage::Rgba16(encoder) => encoder.write_strip(&samples),
+                    _ => unreachable!(),
+                }
+            }
+        }
+    }
+
+    fn finish(self) -> tiff::TiffResult<()> {
+        with_page!(self, encoder => encoder.finish())
+    }
+}
+
+impl<W: Write + Seek> StreamingEncoder for StreamingTiffEncoder<'_, W> {
+    fn write_rows(&mut self, mut buf: &[u8], rows: u32) -> ImageResult<()> {
+        self.progress.advance(buf, rows)?;
+        while !buf.is_empty() {
+            let strip_bytes = self.page.strip_bytes();
+            let (rows, rest) = buf.split_at((strip_bytes - self.strip.len()).min(buf.len()));
+            self.strip.extend_from_slice(rows);
+            buf = rest;
+            if self.strip.len() == strip_bytes {
+                self.page
+                    .write_strip(&self.strip)
+                    .map_err(ImageError::from_tiff_encode)?;
+                self.strip.clear();
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> ImageResult<()> {
+        self.progress.finish()?;
+        self.page.finish().map_err(ImageError::from_tiff_encode)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_image_into_uninit() {
+        let data = std::fs::read("tests/images/tiff/testsuite/rgb-3c-16b.tiff").unwrap();
+        let decoder = TiffDecoder::new(Cursor::new(&data)).unwrap();
+        let mut expected = vec![0; decoder.total_bytes() as usize];
+        decoder.read_image(&mut expected).unwrap();
+
+        let decoder = TiffDecoder::new(Cursor::new(&data)).unwrap();
+        let mut buf = vec![mem::MaybeUninit::uninit(); expected.len()];
+        decoder.read_image_into_uninit(&mut buf).unwrap();
+        // Safety: the decoder initialized the whole buffer.
+        let buf: Vec<u8> = buf
+            .iter()
+            .map(|byte| unsafe { byte.assume_init() })
+            .collect();
+        assert_eq!(buf, expected);
+    }
+
+    #[test]
+    fn multiple_pages() {
+        let mut encoded = Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut encoded);
+        encoder
+            .append_image(&[1; 4 * 3], 4, 3, ColorType::L8)
+            .unwrap();
+        encoder
+            .append_image(&[2; 2 * 5 * 3], 2, 5, ColorType::Rgb8)
+            .unwrap();
+        encoder.finish().unwrap();
+
+        encoded.set_position(0);
+        let mut decoder = TiffDecoder::new(encoded).unwrap();
+        assert_eq!(decoder.image_count().unwrap(), 2);
+        assert_eq!(decoder.dimensions(), (4, 3));
+        decoder.select_image(1).unwrap();
+        assert_eq!(decoder.dimensions(), (2, 5));
+        assert_eq!(decoder.color_type(), ColorType::Rgb8);
+        assert!(decoder.select_image(2).is_err());
+
+        let mut first = vec![0; 4 * 3];
+        decoder.read_page(0, &mut first).unwrap();
+        assert!(first.iter().all(|&b| b == 1));
+
+        let mut buf = vec![0; 2 * 5 * 3];
+        decoder.read_page(1, &mut buf).unwrap();
+        assert!(buf.iter().all(|&b| b == 2));
+        buf.iter_mut().for_each(|b| *b = 0);
+        decoder.read_image(&mut buf).unwrap();
+        assert!(buf.iter().all(|&b| b == 2));
+    }
+
+    #[test]
+    fn read_rect_of_strips() {
+        use crate::ImageDecoderRect;
+
+        // Large enough to be stored in several strips.
+        let (width, height) = (97, 203);
+        let image: Vec<u8> = (0..width * height * 3).map(|i| (i % 251) as u8).collect();
+        let mut encoded = Cursor::new(Vec::new());
+        TiffEncoder::new(&mut encoded)
+            .write_image(&image, width as u32, height as u32, ColorType::Rgb8)
+            .unwrap();
+
+        encoded.set_position(0);
+        let mut decoder = TiffDecoder::new(encoded).unwrap();
+        for &(x, y, rect_width, rect_height) in
+            &[(0, 0, 97, 203), (13, 50, 40, 120), (96, 202, 1, 1)]
+        {
+            let mut buf = vec![0; rect_width * rect_height * 3];
+            decoder
+                .read_rect(
+                    x as u32,
+                    y as u32,
+                    rect_width as u32,
+                    rect_height as u32,
+                    &mut buf,
+                )
+                .unwrap();
+            for (row, out) in buf.chunks(rect_width * 3).enumerate() {
+                let start = ((y + row) * width + x) * 3;
+                assert_eq!(out, &image[start..][..out.len()]);
+            }
+        }
+        assert!(decoder.read_rect(90, 0, 8, 1, &mut [0; 24]).is_err());
+    }
+
+    #[test]
+    fn cancelled_between_chunks() {
+        use crate::error::LimitErrorKind;
+        use crate::io::CancelToken;
+        use crate::ImageDecoderRect;
+        use std::cell::Cell;
+
+        // The input is in memory, so nothing but the decoder checks for cancellation. The
+        // encoder writes strips of about a megabyte.
+        let (width, height) = (600, 1200);
+        let image: Vec<u8> = (0..width * height * 3).map(|i| (i % 251) as u8).collect();
+        let mut encoded = Cursor::new(Vec::new());
+        TiffEncoder::new(&mut encoded)
+            .write_image(&image, width, height, ColorType::Rgb8)
+            .unwrap();
+
+        let token = CancelToken::new();
+        let mut limits = Limits::no_limits();
+        limits.cancel_token = Some(token.clone());
+        encoded.set_position(0);
+        let mut decoder = TiffDecoder::with_limits(encoded, limits).unwrap();
+        let chunks = Cell::new(0);
+        let mut buf = vec![0; image.len()];
+        let result = decoder.read_rect_with_progress(0, 0, width, height, &mut buf, |progress| {
+            if progress.current() > 0 {
+                chunks.set(chunks.get() + 1);
+                token.cancel();
+            }
+        });
+        match result {
+            Err(ImageError::Limits(err)) => assert_eq!(err.kind(), LimitErrorKind::Cancelled),
+            _ => panic!("decoding was cancelled after the first chunk"),
+        }
+        assert_eq!(chunks.get(), 1);
+    }
+
+    #[test]
+    fn reduced_pages() {
+        // A full image with two reduced resolutions, followed by another full image.
+        let mut encoded = Cursor::new(Vec::new());
+        let mut tiff = tiff::encoder::TiffEncoder::new(&mut encoded).unwrap();
+        for &(size, subfile_type) in &[(8, 0u32), (4, 1), (2, 1), (5, 0)] {
+            let mut image = tiff
+                .new_image::<tiff::encoder::colortype::Gray8>(size, size)
+                .unwrap();
+            image
+                .encoder()
+                .write_tag(tiff::tags::Tag::NewSubfileType, subfile_type)
+                .unwrap();
+            image
+                .write_data(&vec![size as u8; (size * size) as usize])
+                .unwrap();
+        }
+
+        let decoder = |encoded: &Cursor<Vec<u8>>| {
+            TiffDecoder::new(Cursor::new(encoded.get_ref().clone())).unwrap()
+        };
+        let mut reduced = decoder(&encoded);
+        assert!(reduced.request_dimensions(3, 3));
+        assert_eq!(reduced.dimensions(), (4, 4));
+        let mut buf = vec![0; 16];
+        reduced.read_image(&mut buf).unwrap();
+        assert_eq!(buf, vec![4; 16]);
+
+        let mut smallest = decoder(&encoded);
+        assert!(smallest.request_dimensions(1, 1));
+        assert_eq!(smallest.dimensions(), (2, 2));
+
+        let mut full = decoder(&encoded);
+        assert!(!full.request_dimensions(5, 5));
+        assert_eq!(full.dimensions(), (8, 8));
+    }
+
+    #[test]
+    fn streaming_pages() {
+        // Tall enough to be stored in several strips.
+        let (width, height) = (300, 2000);
+        let image: Vec<u16> = (0..width * height * 3).map(|i| (i * 7) as u16).collect();
+        let bytes: &[u8] = bytemuck::cast_slice(&image);
+        let mut encoded = Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut encoded);
+        let mut page = encoder.streaming(width, height, ColorType::Rgb16).unwrap();
+        for rows in bytes.chunks(width as usize * 6 * 333) {
+            let count = rows.len() as u32 / (width * 6);
+            page.write_rows(rows, count).unwrap();
+        }
+        assert!(page.write_rows(&[0; 6 * 300], 1).is_err());
+        page.finish().unwrap();
+
+        let mut page = encoder.streaming(2, 2, ColorType::L8).unwrap();
+        page.write_rows(&[1, 2], 1).unwrap();
+        assert!(page.finish().is_err());
+        assert!(encoder.streaming(2, 2, ColorType::La8).is_err());
+        encoder.finish().unwrap();
+
+        encoded.set_position(0);
+        let decoder = TiffDecoder::new(encoded).unwrap();
+        assert_eq!(decoder.dimensions(), (width, height));
+        let mut buf = vec![0; bytes.len()];
+        decoder.read_image(&mut buf).unwrap();
+        assert!(buf == bytes);
+    }
+
+    #[test]
+    fn tiled_pyramid() {
+        let (width, height) = (100, 70);
+        let image: Vec<u8> = (0..height)
+            .flat_map(|y| (0..width).flat_map(move |x| vec![x as u8, y as u8, (x + y) as u8]))
+            .collect();
+        let options = TiledTiffOptions {
+            tile_width: 32,
+            tile_height: 16,
+            pyramid_levels: 10,
+            ..TiledTiffOptions::default()
+        };
+        let mut encoded = Cursor::new(Vec::new());
+        TiffEncoder::new(&mut encoded)
+            .encode_tiled(&image, width, height, ColorType::Rgb8, options)
+            .unwrap();
+
+        let decoder = |encoded: &Cursor<Vec<u8>>| {
+            TiffDecoder::new(Cursor::new(encoded.get_ref().clone())).unwrap()
+        };
+        let mut full = decoder(&encoded);
+        // Halved until a level fits into a single tile.
+        assert_eq!(full.image_count().unwrap(), 4);
+        let mut buf = vec![0; image.len()];
+        full.read_image(&mut buf).unwrap();
+        assert!(buf == image);
+
+        let mut reduced = decoder(&encoded);
+        assert!(reduced.request_dimensions(20, 10));
+        assert_eq!(reduced.dimensions(), (25, 18));
+        let mut buf = vec![0; 25 * 18 * 3];
+        reduced.read_image(&mut buf).unwrap();
+        let half = halve(&image, width, height, ColorType::Rgb8);
+        assert_eq!(buf, halve(&half, 50, 35, ColorType::Rgb8));
+
+        let mut smallest = decoder(&encoded);
+        assert!(smallest.request_dimensions(1, 1));
+        assert_eq!(smallest.dimensions(), (13, 9));
+    }
+
+    #[test]
+    fn tiled_big_tiff() {
+        let image: Vec<u16> = (0..40 * 20 * 4).map(|i| (i * 37) as u16).collect();
+        let bytes: &[u8] = bytemuck::cast_slice(&image);
+        let options = TiledTiffOptions {
+            big_tiff: true,
+            ..TiledTiffOptions::default()
+        };
+        let mut encoded = Cursor::new(Vec::new());
+        TiffEncoder::new(&mut encoded)
+            .encode_tiled(bytes, 40, 20, ColorType::Rgba16, options)
+            .unwrap();
+        assert_eq!(&encoded.get_ref()[2..4], &43u16.to_ne_bytes());
+
+        encoded.set_position(0);
+        let decoder = TiffDecoder::new(encoded).unwrap();
+        assert_eq!(decoder.color_type(), ColorType::Rgba16);
+        let mut buf = vec![0; bytes.len()];
+        decoder.read_image(&mut buf).unwrap();
+        assert!(buf == bytes);
+
+        let encoder = TiffEncoder::new(Cursor::new(Vec::new()));
+        let options = TiledTiffOptions {
+            tile_width: 20,
+            ..options
+        };
+        assert!(encoder
+            .encode_tiled(bytes, 40, 20, ColorType::Rgba16, options)
+            .is_err());
+    }
+
+    #[test]
+    fn finish_without_pages() {
+        let encoder = TiffEncoder::new(Cursor::new(Vec::new()));
+        assert!(encoder.finish().is_err());
+    }
+}
@@ -4,10 +4,16 @@ use std::io::{self, Cursor, Read};
 use std::marker::PhantomData;
 use std::{error, fmt, mem};
 
+use crate::animation::{self, Delay, DirtyRegion, Frames};
 use crate::error::{
     DecodingError, ImageError, ImageResult, UnsupportedError, UnsupportedErrorKind,
 };
-use crate::image::{ImageDecoder, ImageFormat};
+use crate::image::{AnimationDecoder, ImageDecoder, ImageFormat};
+use crate::io::Limits;
+use crate::math::Rect;
+use crate::metadata::{Exif, MetadataMap};
+use crate::traits::Pixel;
+use crate::{DynamicImage, Rgba, RgbaImage};
 
 use crate::color;
 
@@ -18,11 +24,13 @@ use super::vp8::Vp8Decoder;
 
 /// All errors that can occur when attempting to parse a WEBP container
 #[derive(Debug, Clone, Copy)]
-enum DecoderError {
+pub(crate) enum DecoderError {
     /// RIFF's "RIFF" signature not found or invalid
     RiffSignatureInvalid([u8; 4]),
     /// WebP's "WEBP" signature not found or invalid
     WebpSignatureInvalid([u8; 4]),
+    /// An "ANMF" chunk that is too short or holds no image data
+    AnimationFrameInvalid,
 }
 
 impl fmt::Display for DecoderError {
@@ -47,6 +55,7 @@ impl fmt::Display for DecoderError {
                 "Invalid WebP signature: {}",
                 SignatureWriter(*webp)
             )),
+            DecoderError::AnimationFrameInvalid => f.write_str("Invalid animation frame"),
         }
     }
 }
@@ -64,20 +73,95 @@ enum Frame {
     Lossless(LosslessFrame),
 }
 
+impl Frame {
+    fn to_rgba(&self) -> RgbaImage {
+        match self {
+            Frame::Lossy(vp8_frame) => {
+                let mut data = vec![0; vp8_frame.get_buf_size()];
+                vp8_frame.fill_rgb(&mut data);
+                let (width, height) = (u32::from(vp8_frame.width), u32::from(vp8_frame.height));
+                let image = crate::RgbImage::from_raw(width, height, data).unwrap();
+                DynamicImage::ImageRgb8(image).into_rgba8()
+            }
+            Frame::Lossless(lossless_frame) => {
+                let mut data = vec![0; lossless_frame.get_buf_size()];
+                lossless_frame.fill_rgba(&mut data);
+                let width = u32::from(lossless_frame.width);
+                let height = u32::from(lossless_frame.height);
+                RgbaImage::from_raw(width, height, data).unwrap()
+            }
+        }
+    }
+}
+
+/// The placement of a frame of an animation on the canvas, from its `ANMF` chunk.
+#[derive(Clone, Copy, Debug)]
+struct AnimationFrame {
+    left: u32,
+    top: u32,
+    duration: u32,
+    /// The frame is alpha blended onto the canvas, instead of replacing the pixels it covers.
+    blend: bool,
+    /// The area of the frame is cleared before the next frame is drawn.
+    dispose: bool,
+}
+
+/// The canvas of an animated image, from its `VP8X` and `ANIM` chunks.
+#[derive(Clone, Copy, Debug)]
+struct Animation {
+    width: u32,
+    height: u32,
+    background: Rgba<u8>,
+    /// The placement of the first frame, which the decoder holds.
+    first: AnimationFrame,
+}
+
 /// WebP Image format decoder. Currently only supports lossy RGB images or lossless RGBA images.
+///
+/// Animated images are decoded as their first frame, or frame by frame as an
+/// [`AnimationDecoder`](../../trait.AnimationDecoder.html). Their frames must not use an alpha
+/// channel with lossy compression.
 pub struct WebPDecoder<R> {
     r: R,
     frame: Frame,
+    animation: Option<Animation>,
+    icc_profile: Option<Vec<u8>>,
+    exif: Option<Vec<u8>>,
+    xmp: Option<Vec<u8>>,
+    /// Whether the chunks after the image data of a still image have been read.
+    read_trailer: bool,
+    /// The limits on the chunks that are read and the frames that are decoded from them.
+    limits: Limits,
 }
 
 impl<R: Read> WebPDecoder<R> {
     /// Create a new WebPDecoder from the Reader ```r```.
     /// This function takes ownership of the Reader.
     pub fn new(r: R) -> ImageResult<WebPDecoder<R>> {
+        Self::with_limits(r, Limits::no_limits())
+    }
+
+    /// Create a new WebPDecoder from the Reader ```r``` that decodes within the given limits.
+    ///
+    /// The first frame is decoded when the decoder is created, so the `max_alloc` limit bounds
+    /// the chunks that are read and the frame that is decoded from them.
+    pub fn with_limits(r: R, limits: Limits) -> ImageResult<WebPDecoder<R>> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
         let frame = Frame::Lossy(Default::default());
 
-        let mut decoder = WebPDecoder { r, frame };
+        let mut decoder = WebPDecoder {
+            r,
+            frame,
+            animation: None,
+            icc_profile: None,
+            exif: None,
+            xmp: None,
+            read_trailer: false,
+            limits,
+        };
         decoder.read_data()?;
+        let (width, height) = decoder.dimensions();
+        decoder.limits.check_dimensions(width, height)?;
         Ok(decoder)
     }
 
@@ -102,13 +186,53 @@ impl<R: Read> WebPDecoder<R> {
 
     //reads the chunk header, decodes the frame and returns the inner decoder
     fn read_frame(&mut self) -> ImageResult<Frame> {
+        let mut canvas = None;
+        let mut background = Rgba([0, 0, 0, 0]);
         loop {
             let mut chunk = [0; 4];
             self.r.read_exact(&mut chunk)?;
 
             match &chunk {
+                b"VP8X" => {
+                    let header = read_len_cursor(&mut self.r, &mut self.limits)?.into_inner();
+                    if header.len() >= 10 {
+                        canvas = Some((u24(&header[4..]) + 1, u24(&header[7..]) + 1));
+                    }
+                }
+                b"ICCP" => {
+                    self.icc_profile = Some(read_padded_chunk(&mut self.r, &mut self.limits)?);
+                }
+                b"EXIF" => {
+                    self.exif = Some(read_padded_chunk(&mut self.r, &mut self.limits)?);
+                }
+                b"XMP " => {
+                    self.xmp = Some(read_padded_chunk(&mut self.r, &mut self.limits)?);
+                }
+                b"ANIM" => {
+                    let anim = read_len_cursor(&mut self.r, &mut self.limits)?.into_inner();
+                    if let [b, g, r, a, ..] = anim[..] {
+                        background = Rgba([r, g, b, a]);
+                    }
+                }
+                b"ANMF" => {
+                    let data = read_padded_chunk(&mut self.r, &mut self.limits)?;
+                    let (frame, first) = decode_animation_frame(&data, &mut self.limits)?;
+                    let (width, height) = canvas.unwrap_or_else(|| {
+                        let image = frame.to_rgba();
+                        (first.left + image.width(), first.top + image.height())
+                    });
+                    self.animation = Some(Animation {
+                        width,
+                        height,
+                        background,
+                        first,
+                    });
+                    return Ok(frame);
+                }
                 b"VP8 " => {
-                    let m = read_len_cursor(&mut self.r)?;
+                    let m = read_len_cursor(&mut self.r, &mut self.limits)?;
+                    skip_padding(&mut self.r, m.get_ref().len())?;
+                    reserve_frame(&mut self.limits, &chunk, m.get_ref())?;
 
                     let mut vp8_decoder = Vp8Decoder::new(m);
                     let frame = vp8_decoder.decode_frame()?;
@@ -116,15 +240,17 @@ impl<R: Read> WebPDecoder<R> {
                     return Ok(Frame::Lossy(frame.clone()));
                 }
                 b"VP8L" => {
-                    let m = read_len_cursor(&mut self.r)?;
+                    let m = read_len_cursor(&mut self.r, &mut self.limits)?;
+                    skip_padding(&mut self.r, m.get_ref().len())?;
+                    reserve_frame(&mut self.limits, &chunk, m.get_ref())?;
 
                     let mut lossless_decoder = LosslessDecoder::new(m);
                     let frame = lossless_decoder.decode_frame()?;
 
                     return Ok(Frame::Lossless(frame.clone()));
                 }
-                b"ALPH" | b"ANIM" | b"ANMF" => {
-                    // Alpha and Animation isn't supported
+                b"ALPH" => {
+                    // Alpha isn't supported
                     return Err(ImageError::Unsupported(
                         UnsupportedError::from_format_and_kind(
                             ImageFormat::WebP.into(),
@@ -160,19 +286,282 @@ impl<R: Read> WebPDecoder<R> {
 
         Ok(())
     }
+
+    /// Reads the `EXIF` and `XMP ` chunks that follow the image data of a still image.
+    ///
+    /// Animations must place their metadata before their frames.
+    fn read_trailer(&mut self) {
+        if self.read_trailer || self.animation.is_some() {
+            return;
+        }
+        self.read_trailer = true;
+        let mut chunk = [0; 4];
+        while self.r.read_exact(&mut chunk).is_ok() {
+            let data = match read_padded_chunk(&mut self.r, &mut self.limits) {
+                Ok(data) => data,
+                Err(_) => return,
+            };
+            match &chunk {
+                b"EXIF" => self.exif = Some(data),
+                b"XMP " => self.xmp = Some(data),
+                _ => {}
+            }
+        }
+    }
 }
 
-fn read_len_cursor<R>(r: &mut R) -> ImageResult<Cursor<Vec<u8>>>
+fn read_len_cursor<R>(r: &mut R, limits: &mut Limits) -> ImageResult<Cursor<Vec<u8>>>
 where
     R: Read,
 {
     let len = r.read_u32::<LittleEndian>()?;
+    limits.reserve(u64::from(len))?;
 
     let mut framedata = Vec::new();
     r.by_ref().take(len as u64).read_to_end(&mut framedata)?;
     Ok(io::Cursor::new(framedata))
 }
 
+/// Reads the length of a chunk and its data, including the padding of chunks of odd length.
+fn read_padded_chunk<R: Read>(r: &mut R, limits: &mut Limits) -> ImageResult<Vec<u8>> {
+    let len = r.read_u32::<LittleEndian>()?;
+    limits.reserve(u64::from(len))?;
+    let mut data = Vec::new();
+    r.by_ref()
+        .take(u64::from(len) + u64::from(len % 2))
+        .read_to_end(&mut data)?;
+    data.truncate(len as usize);
+    Ok(data)
+}
+
+/// Skips the byte that pads a chunk of odd length.
+fn skip_padding<R: Read>(r: &mut R, len: usize) -> io::Result<()> {
+    if len % 2 == 1 {
+        io::copy(&mut r.by_ref().take(1), &mut io::sink())?;
+    }
+    Ok(())
+}
+
+fn u24(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0]) | u32::from(bytes[1]) << 8 | u32::from(bytes[2]) << 16
+}
+
+/// Checks the dimensions of the frame in the data of a `VP8 ` or `VP8L` chunk against the limits,
+/// and reserves the memory of decoding it, at most four bytes a pixel, before it is decoded.
+///
+/// The whole chunk is read before the frame is decoded, so this also checks that decoding was not
+/// cancelled in the meantime.
+fn reserve_frame(limits: &mut Limits, chunk: &[u8], data: &[u8]) -> ImageResult<()> {
+    limits.check_cancelled()?;
+    let (width, height) = match chunk {
+        b"VP8 " if data.len() >= 10 && data[3..6] == [0x9d, 0x01, 0x2a] => {
+            let width = u16::from_le_bytes([data[6], data[7]]) & 0x3fff;
+            let height = u16::from_le_bytes([data[8], data[9]]) & 0x3fff;
+            (u32::from(width), u32::from(height))
+        }
+        b"VP8L" if data.len() >= 5 && data[0] == 0x2f => {
+            let bits = u32::from_le_bytes([data[1], data[2], data[3], data[4]]);
+            ((bits & 0x3fff) + 1, (bits >> 14 & 0x3fff) + 1)
+        }
+        // The decoders reject the frame before they allocate it.
+        _ => return Ok(()),
+    };
+    limits.check_dimensions(width, height)?;
+    limits.reserve(u64::from(width) * u64::from(height) * 4)
+}
+
+/// Decodes the data of an `ANMF` chunk.
+fn decode_animation_frame(
+    data: &[u8],
+    limits: &mut Limits,
+) -> ImageResult<(Frame, AnimationFrame)> {
+    if data.len() < 16 {
+        return Err(DecoderError::AnimationFrameInvalid.into());
+    }
+    let info = AnimationFrame {
+        left: 2 * u24(&data[0..]),
+        top: 2 * u24(&data[3..]),
+        duration: u24(&data[12..]),
+        blend: data[15] & 2 == 0,
+        dispose: data[15] & 1 != 0,
+    };
+
+    let mut chunks = &data[16..];
+    while chunks.len() >= 8 {
+        let len = u32::from_le_bytes([chunks[4], chunks[5], chunks[6], chunks[7]]) as usize;
+        let end = 8usize.saturating_add(len).min(chunks.len());
+        let padded = end.saturating_add(len % 2).min(chunks.len());
+        if let b"VP8 " | b"VP8L" = &chunks[..4] {
+            reserve_frame(limits, &chunks[..4], &chunks[8..end])?;
+        }
+        let chunk = Cursor::new(chunks[8..end].to_vec());
+        match &chunks[..4] {
+            b"VP8 " => {
+                let frame = Vp8Decoder::new(chunk).decode_frame()?.clone();
+                return Ok((Frame::Lossy(frame), info));
+            }
+            b"VP8L" => {
+                let frame = LosslessDecoder::new(chunk).decode_frame()?.clone();
+                return Ok((Frame::Lossless(frame), info));
+            }
+            b"ALPH" => {
+                return Err(ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(
+                        ImageFormat::WebP.into(),
+                        UnsupportedErrorKind::GenericFeature("ALPH".into()),
+                    ),
+                ))
+            }
+            _ => chunks = &chunks[padded..],
+        }
+    }
+    Err(DecoderError::AnimationFrameInvalid.into())
+}
+
+/// Draws `image` onto `canvas` at the placement of `info`, clipping it to the canvas.
+fn draw_frame(canvas: &mut RgbaImage, image: &RgbaImage, info: &AnimationFrame) {
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let (x, y) = (x + info.left, y + info.top);
+        if x < canvas.width() && y < canvas.height() {
+            let target = canvas.get_pixel_mut(x, y);
+            if info.blend {
+                target.blend(pixel);
+            } else {
+                *target = *pixel;
+            }
+        }
+    }
+}
+
+/// Iterates over the frames of an animated image.
+struct WebPFrameIterator<R> {
+    r: R,
+    /// The first frame, which the decoder already read.
+    first: Option<(RgbaImage, AnimationFrame)>,
+    canvas: RgbaImage,
+    /// The frame drawn last, if its area is cleared before the next one is drawn.
+    disposed: Option<(u32, u32, u32, u32)>,
+    dirty: DirtyRegion,
+    composite: bool,
+    /// The limits on each chunk and the frame decoded from it.
+    limits: Limits,
+}
+
+impl<R: Read> WebPFrameIterator<R> {
+    fn next_frame(&mut self) -> ImageResult<Option<(RgbaImage, AnimationFrame)>> {
+        if let Some(first) = self.first.take() {
+            return Ok(Some(first));
+        }
+        loop {
+            let mut chunk = [0; 4];
+            match self.r.read_exact(&mut chunk) {
+                Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+                result => result?,
+            }
+            let mut limits = self.limits.clone();
+            let data = read_padded_chunk(&mut self.r, &mut limits)?;
+            if &chunk == b"ANMF" {
+                let (frame, info) = decode_animation_frame(&data, &mut limits)?;
+                return Ok(Some((frame.to_rgba(), info)));
+            }
+        }
+    }
+}
+
+impl<R: Read> Iterator for WebPFrameIterator<R> {
+    type Item = ImageResult<animation::Frame>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (image, info) = match self.next_frame() {
+            Ok(Some(frame)) => frame,
+            Ok(None) => return None,
+            Err(err) => return Some(Err(err)),
+        };
+        let delay = Delay::from_numer_denom_ms(info.duration, 1);
+        if !self.composite {
+            return Some(Ok(animation::Frame::from_parts(
+                image, info.left, info.top, delay,
+            )));
+        }
+
+        if let Some((left, top, width, height)) = self.disposed.take() {
+            for y in top..(top + height).min(self.canvas.height()) {
+                for x in left..(left + width).min(self.canvas.width()) {
+                    self.canvas.put_pixel(x, y, Rgba([0, 0, 0, 0]));
+                }
+            }
+        }
+        draw_frame(&mut self.canvas, &image, &info);
+        if info.dispose {
+            self.disposed = Some((info.left, info.top, image.width(), image.height()));
+        }
+        let area = Rect {
+            x: info.left,
+            y: info.top,
+            width: image.width(),
+            height: image.height(),
+        };
+        let dirty = self.dirty.next_frame(area, info.dispose);
+        let frame = animation::Frame::from_parts(self.canvas.clone(), 0, 0, delay);
+        Some(Ok(frame.with_dirty_rect(dirty)))
+    }
+}
+
+impl<R: Read> WebPDecoder<R> {
+    fn frames<'a>(self, composite: bool) -> Frames<'a>
+    where
+        R: 'a,
+    {
+        let image = self.frame.to_rgba();
+        let animation = match self.animation {
+            Some(animation) => animation,
+            None => {
+                let (width, height) = image.dimensions();
+                let frame = animation::Frame::new(image);
+                return Frames::new(Box::new(std::iter::once(Ok(frame))))
+                    .with_canvas(width, height);
+            }
+        };
+
+        // The canvas is kept next to the frames.
+        let mut limits = self.limits;
+        let canvas_size = u64::from(animation.width) * u64::from(animation.height) * 4;
+        if let Err(err) = limits.reserve(canvas_size) {
+            return Frames::new(Box::new(std::iter::once(Err(err))));
+        }
+        let iterator = WebPFrameIterator {
+            r: self.r,
+            first: Some((image, animation.first)),
+            canvas: RgbaImage::new(animation.width, animation.height),
+            disposed: None,
+            dirty: DirtyRegion::new(animation.width, animation.height),
+            composite,
+            limits,
+        };
+        // The background color is only a hint, like for GIF it is not drawn.
+        Frames::new(Box::new(iterator))
+            .with_canvas(animation.width, animation.height)
+            .with_background(animation.background)
+    }
+
+    /// The first frame of an animation, drawn onto the canvas.
+    fn first_canvas(&self, animation: &Animation) -> RgbaImage {
+        let mut canvas = RgbaImage::new(animation.width, animation.height);
+        draw_frame(&mut canvas, &self.frame.to_rgba(), &animation.first);
+        canvas
+    }
+}
+
+impl<'a, R: 'a + Read> AnimationDecoder<'a> for WebPDecoder<R> {
+    fn into_frames(self) -> Frames<'a> {
+        self.frames(true)
+    }
+
+    fn into_raw_frames(self) -> Frames<'a> {
+        self.frames(false)
+    }
+}
+
 /// Wrapper struct around a `Cursor<Vec<u8>>`
 pub struct WebpReader<R>(Cursor<Vec<u8>>, PhantomData<R>);
 impl<R> Read for WebpReader<R> {
@@ -193,6 +582,9 @@ impl<'a, R: 'a + Read> ImageDecoder<'a> for WebPDecoder<R> {
     type Reader = WebpReader<R>;
 
     fn dimensions(&self) -> (u32, u32) {
+        if let Some(animation) = &self.animation {
+            return (animation.width, animation.height);
+        }
         match &self.frame {
             Frame::Lossy(vp8_frame) => (u32::from(vp8_frame.width), u32::from(vp8_frame.height)),
             Frame::Lossless(lossless_frame) => (
@@ -203,13 +595,56 @@ impl<'a, R: 'a + Read> ImageDecoder<'a> for WebPDecoder<R> {
     }
 
     fn color_type(&self) -> color::ColorType {
+        if self.animation.is_some() {
+            return color::ColorType::Rgba8;
+        }
         match &self.frame {
             Frame::Lossy(_) => color::ColorType::Rgb8,
             Frame::Lossless(_) => color::ColorType::Rgba8,
         }
     }
 
+    fn icc_profile(&mut self) -> Option<Vec<u8>> {
+        self.icc_profile.clone()
+    }
+
+    fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        let (width, height) = self.dimensions();
+        limits.check_dimensions(width, height)?;
+        self.limits = limits;
+        Ok(())
+    }
+
+    /// Returns the metadata of the `EXIF` chunk. For still images, the chunks after the image
+    /// data are read to find it, animations must place it before their frames.
+    fn exif_metadata(&mut self) -> Option<Exif> {
+        if self.exif.is_none() {
+            self.read_trailer();
+        }
+        Exif::from_bytes(self.exif.as_ref()?).ok()
+    }
+
+    /// Returns the packet of the `XMP ` chunk, which is found like the `EXIF` chunk. WebP images
+    /// have no other textual metadata.
+    fn text_metadata(&mut self) -> MetadataMap {
+        if self.xmp.is_none() {
+            self.read_trailer();
+        }
+        let mut metadata = MetadataMap::new();
+        let xmp = self
+            .xmp
+            .as_ref()
+            .and_then(|xmp| String::from_utf8(xmp.clone()).ok());
+        metadata.set_xmp(xmp);
+        metadata
+    }
+
     fn into_reader(self) -> ImageResult<Self::Reader> {
+        if let Some(animation) = &self.animation {
+            let data = self.first_canvas(animation).into_raw();
+            return Ok(WebpReader(Cursor::new(data), PhantomData));
+        }
         match &self.frame {
             Frame::Lossy(vp8_frame) => {
                 let mut data = vec![0; vp8_frame.get_buf_size()];
@@ -227,6 +662,10 @@ impl<'a, R: 'a + Read> ImageDecoder<'a> for WebPDecoder<R> {
     fn read_image(self, buf: &mut [u8]) -> ImageResult<()> {
         assert_eq!(u64::try_from(buf.len()), Ok(self.total_bytes()));
 
+        if let Some(animation) = &self.animation {
+            buf.copy_from_slice(&self.first_canvas(animation));
+            return Ok(());
+        }
         match &self.frame {
             Frame::Lossy(vp8_frame) => {
                 vp8_frame.fill_rgb(buf);
@@ -258,4 +697,80 @@ mod tests {
 
         let _ = WebPDecoder::new(data);
     }
+
+    #[test]
+    fn decodes_animations() {
+        use crate::animation::{Delay, Frame};
+        use crate::codecs::webp::WebPAnimationEncoder;
+        use crate::{AnimationDecoder, ColorType, Rgba, RgbaImage};
+
+        let first = RgbaImage::from_fn(6, 4, |x, y| Rgba([x as u8, y as u8, 9, 255]));
+        let second = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 128]));
+        let delay = Delay::from_numer_denom_ms(100, 1);
+        let mut encoder = WebPAnimationEncoder::new(Vec::new());
+        encoder
+            .encode_frame(Frame::from_parts(first.clone(), 0, 0, delay))
+            .unwrap();
+        encoder
+            .encode_frame(Frame::from_parts(second.clone(), 4, 2, delay))
+            .unwrap();
+        let data = encoder.finish().unwrap();
+
+        let decoder = WebPDecoder::new(&data[..]).unwrap();
+        assert_eq!(decoder.dimensions(), (6, 4));
+        assert_eq!(decoder.color_type(), ColorType::Rgba8);
+        let mut still = vec![0; decoder.total_bytes() as usize];
+        decoder.read_image(&mut still).unwrap();
+        assert_eq!(still, first.clone().into_raw());
+
+        let frames = WebPDecoder::new(&data[..]).unwrap().into_raw_frames();
+        assert_eq!(frames.canvas_dimensions(), Some((6, 4)));
+        assert_eq!(frames.background_color(), Some(Rgba([0, 0, 0, 0])));
+        let frames = frames.collect_frames().unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!((frames[1].left(), frames[1].top()), (4, 2));
+        assert_eq!(frames[1].buffer(), &second);
+
+        // The second frame is not blended, it replaces its region of the canvas.
+        let frames = WebPDecoder::new(&data[..])
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        let mut expected = first;
+        for (x, y, pixel) in second.enumerate_pixels() {
+            expected.put_pixel(x + 4, y + 2, *pixel);
+        }
+        assert_eq!(frames[1].buffer(), &expected);
+        assert_eq!(frames[1].delay(), delay);
+    }
+
+    #[test]
+    fn limits_bound_the_first_frame() {
+        use crate::codecs::webp::WebPEncoder;
+        use crate::error::LimitErrorKind;
+        use crate::ColorType;
+
+        let mut data = Vec::new();
+        WebPEncoder::new(&mut data)
+            .encode(&[0x80; 64 * 32 * 4], 64, 32, ColorType::Rgba8)
+            .unwrap();
+
+        let mut limits = Limits::no_limits();
+        limits.max_alloc = Some(64 * 32 * 4);
+        match WebPDecoder::with_limits(&data[..], limits) {
+            Err(ImageError::Limits(err)) => {
+                assert_eq!(err.kind(), LimitErrorKind::InsufficientMemory)
+            }
+            _ => panic!("the frame and its chunk exceed the limits"),
+        }
+
+        let mut limits = Limits::no_limits();
+        limits.max_image_width = Some(32);
+        assert!(matches!(
+            WebPDecoder::with_limits(&data[..], limits),
+            Err(ImageError::Limits(_))
+        ));
+        assert!(WebPDecoder::with_limits(&data[..], Limits::default()).is_ok());
+    }
 }
@@ -0,0 +1,942 @@
+//! Encoding of WebP images
+//!
+//! Images are compressed losslessly into a VP8L bitstream. The encoder applies the subtract
+//! green transform, replaces runs of pixels repeating their left or upper neighbour by backward
+//! references and builds prefix codes from the resulting symbol statistics. The predictor and
+//! color transforms as well as the color cache are not used.
+//!
+//! # Related Links
+//! * <https://developers.google.com/speed/webp/docs/riff_container> - The WebP container
+//! * <https://developers.google.com/speed/webp/docs/webp_lossless_bitstream_specification> - The
+//!   lossless bitstream
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::convert::TryFrom;
+use std::io::Write;
+
+use crate::animation::{map_frames_in_order, Frame};
+use crate::color::ColorType;
+use crate::error::{
+    ImageError, ImageResult, ParameterError, ParameterErrorKind, UnsupportedError,
+    UnsupportedErrorKind,
+};
+use crate::image::{ImageEncoder, ImageFormat};
+use crate::metadata::{Exif, MetadataMap};
+
+/// The largest width and height of a VP8L bitstream.
+const MAX_DIMENSION: u32 = 1 << 14;
+/// The largest canvas width and height of an animation.
+const MAX_CANVAS_DIMENSION: u32 = 1 << 24;
+/// The longest backward reference.
+const MAX_LENGTH: usize = 4096;
+/// The longest code of a prefix code.
+const MAX_CODE_LENGTH: u8 = 15;
+/// The longest code of the code that the lengths of a prefix code are written with.
+const MAX_CODE_LENGTH_CODE_LENGTH: u8 = 7;
+/// The order in which the code lengths of the code length code are written.
+const CODE_LENGTH_CODE_ORDER: [usize; 19] = [
+    17, 18, 0, 1, 2, 3, 4, 5, 16, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15,
+];
+/// Number of symbols of the green, red, blue, alpha and distance codes.
+const ALPHABET_SIZE: [usize; 5] = [256 + 24, 256, 256, 256, 40];
+/// The plane codes of the upper and the left neighbour of a pixel.
+const PLANE_CODE_UP: usize = 1;
+const PLANE_CODE_LEFT: usize = 2;
+
+/// WebP encoder, writing lossless still images.
+pub struct WebPEncoder<W: Write> {
+    w: W,
+    icc_profile: Option<Vec<u8>>,
+    exif: Option<Vec<u8>>,
+    xmp: Option<String>,
+}
+
+impl<W: Write> WebPEncoder<W> {
+    /// Create a new encoder that writes its output to ```w```.
+    pub fn new(w: W) -> WebPEncoder<W> {
+        WebPEncoder {
+            w,
+            icc_profile: None,
+            exif: None,
+            xmp: None,
+        }
+    }
+
+    /// Encode an image with the given dimensions and color type.
+    ///
+    /// Supported color types are `L8`, `La8`, `Rgb8` and `Rgba8`. The image is stored
+    /// losslessly, so decoding it again yields the same pixels as RGBA.
+    pub fn encode(
+        mut self,
+        data: &[u8],
+        width: u32,
+        height: u32,
+        color: ColorType,
+    ) -> ImageResult<()> {
+        let pixels = argb_pixels(data, width, height, color)?;
+        let bitstream = encode_vp8l(&pixels, width, height)?;
+
+        let mut chunks = Vec::new();
+        if self.icc_profile.is_some() || self.exif.is_some() || self.xmp.is_some() {
+            // Metadata requires the extended format, whose header flags it.
+            let mut flags = if color.has_alpha() { 0x10 } else { 0 };
+            if self.icc_profile.is_some() {
+                flags |= 0x20;
+            }
+            if self.exif.is_some() {
+                flags |= 0x08;
+            }
+            if self.xmp.is_some() {
+                flags |= 0x04;
+            }
+            let mut header = Vec::with_capacity(10);
+            header.extend_from_slice(&[flags, 0, 0, 0]);
+            write_u24(&mut header, width - 1);
+            write_u24(&mut header, height - 1);
+            write_chunk(&mut chunks, b"VP8X", &header);
+        }
+        if let Some(icc_profile) = &self.icc_profile {
+            write_chunk(&mut chunks, b"ICCP", icc_profile);
+        }
+        write_chunk(&mut chunks, b"VP8L", &bitstream);
+        if let Some(exif) = &self.exif {
+            write_chunk(&mut chunks, b"EXIF", exif);
+        }
+        if let Some(xmp) = &self.xmp {
+            write_chunk(&mut chunks, b"XMP ", xmp.as_bytes());
+        }
+        write_riff(&mut self.w, &chunks)
+    }
+}
+
+impl<W: Write> ImageEncoder for WebPEncoder<W> {
+    fn write_image(
+        self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        self.encode(buf, width, height, color_type)
+    }
+
+    /// Embed the profile in an `ICCP` chunk.
+    fn set_icc_profile(&mut self, icc_profile: Vec<u8>) -> ImageResult<()> {
+        self.icc_profile = Some(icc_profile);
+        Ok(())
+    }
+
+    /// Embed the metadata in an `EXIF` chunk.
+    fn set_exif_metadata(&mut self, exif: &Exif) -> ImageResult<()> {
+        self.exif = Some(exif.to_bytes());
+        Ok(())
+    }
+
+    /// Embed the XMP packet in an `XMP ` chunk.
+    ///
+    /// WebP images have no place for other text entries, which return an
+    /// `ImageError::Unsupported`.
+    fn set_text_metadata(&mut self, metadata: &MetadataMap) -> ImageResult<()> {
+        if metadata.iter().next().is_some() {
+            return Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    ImageFormat::WebP.into(),
+                    UnsupportedErrorKind::GenericFeature("text metadata entries".to_string()),
+                ),
+            ));
+        }
+        self.xmp = metadata.xmp().map(str::to_string);
+        Ok(())
+    }
+}
+
+/// WebP encoder for animations.
+///
+/// Frames are compressed losslessly and placed on the canvas at their offset, which must be even
+/// as required by the format. The canvas is the smallest one containing all frames. Each frame
+/// replaces the pixels of the canvas it covers instead of being blended with them.
+///
+/// Since the size of the canvas is only known once all frames are added, they are buffered and
+/// the file is written by [`finish`].
+///
+/// [`finish`]: #method.finish
+pub struct WebPAnimationEncoder<W: Write> {
+    w: W,
+    loop_count: u16,
+    canvas_width: u32,
+    canvas_height: u32,
+    has_alpha: bool,
+    frames: Vec<u8>,
+}
+
+impl<W: Write> WebPAnimationEncoder<W> {
+    /// Create a new encoder that writes its output to ```w```.
+    ///
+    /// The animation loops forever unless set otherwise with [`set_loop_count`].
+    ///
+    /// [`set_loop_count`]: #method.set_loop_count
+    pub fn new(w: W) -> WebPAnimationEncoder<W> {
+        WebPAnimationEncoder {
+            w,
+            loop_count: 0,
+            canvas_width: 0,
+            canvas_height: 0,
+            has_alpha: false,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Set how often the animation is played, where `0` means that it loops forever.
+    pub fn set_loop_count(&mut self, loop_count: u16) {
+        self.loop_count = loop_count;
+    }
+
+    /// Encode one frame of animation.
+    ///
+    /// The delay of the frame is rounded to whole milliseconds.
+    pub fn encode_frame(&mut self, frame: Frame) -> ImageResult<()> {
+        let frame = compress_frame(frame)?;
+        self.add_frame(frame);
+        Ok(())
+    }
+
+    /// Encodes Frames.
+    /// Consider using `try_encode_frames` instead to encode an `animation::Frames` like iterator.
+    ///
+    /// With the `rayon` feature, several frames are compressed in parallel.
+    pub fn encode_frames<F>(&mut self, frames: F) -> ImageResult<()>
+    where
+        F: IntoIterator<Item = Frame>,
+    {
+        self.try_encode_frames(frames.into_iter().map(Ok))
+    }
+
+    /// Try to encode a collection of `ImageResult<animation::Frame>` objects.
+    /// Use this function to encode an `animation::Frames` like iterator.
+    /// Whenever an `Err` item is encountered, that value is returned without further actions.
+    ///
+    /// With the `rayon` feature, several frames are compressed in parallel.
+    pub fn try_encode_frames<F>(&mut self, frames: F) -> ImageResult<()>
+    where
+        F: IntoIterator<Item = ImageResult<Frame>>,
+    {
+        map_frames_in_order(
+            frames,
+            |frame| compress_frame(frame?),
+            |frame| {
+                self.add_frame(frame);
+                Ok(())
+            },
+        )
+    }
+
+    /// Append a compressed frame to the animation.
+    fn add_frame(&mut self, frame: CompressedFrame) {
+        let (width, height) = (frame.width, frame.height);
+        let mut data = Vec::with_capacity(16 + 8 + frame.bitstream.len() + 1);
+        write_u24(&mut data, frame.left / 2);
+        write_u24(&mut data, frame.top / 2);
+        write_u24(&mut data, width - 1);
+        write_u24(&mut data, height - 1);
+        write_u24(&mut data, frame.duration);
+        // Do not blend the frame with the canvas and do not dispose it afterwards.
+        data.push(0b10);
+        write_chunk(&mut data, b"VP8L", &frame.bitstream);
+        write_chunk(&mut self.frames, b"ANMF", &data);
+
+        self.canvas_width = self.canvas_width.max(frame.left + width);
+        self.canvas_height = self.canvas_height.max(frame.top + height);
+        self.has_alpha |= frame.has_alpha;
+    }
+
+    /// Write the animation and return the underlying writer.
+    ///
+    /// Returns an error if no frame was encoded.
+    pub fn finish(mut self) -> ImageResult<W> {
+        if self.frames.is_empty() {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::NoMoreData,
+            )));
+        }
+
+        let mut header = Vec::with_capacity(10);
+        let alpha_flag = if self.has_alpha { 0x10 } else { 0 };
+        header.extend_from_slice(&[alpha_flag | 0x02, 0, 0, 0]);
+        write_u24(&mut header, self.canvas_width - 1);
+        write_u24(&mut header, self.canvas_height - 1);
+
+        let mut animation = Vec::with_capacity(6);
+        // Transparent black background.
+        animation.extend_from_slice(&[0, 0, 0, 0]);
+        animation.extend_from_slice(&self.loop_count.to_le_bytes());
+
+        let mut chunks = Vec::with_capacity(18 + 14 + self.frames.len());
+        write_chunk(&mut chunks, b"VP8X", &header);
+        write_chunk(&mut chunks, b"ANIM", &animation);
+        chunks.extend_from_slice(&self.frames);
+        write_riff(&mut self.w, &chunks)?;
+        Ok(self.w)
+    }
+}
+
+/// A frame of animation, compressed into a VP8L bitstream.
+struct CompressedFrame {
+    left: u32,
+    top: u32,
+    width: u32,
+    height: u32,
+    /// The duration in milliseconds, saturated to 24 bits.
+    duration: u32,
+    bitstream: Vec<u8>,
+    has_alpha: bool,
+}
+
+/// Check the placement of a frame of animation and compress it.
+fn compress_frame(frame: Frame) -> ImageResult<CompressedFrame> {
+    let (left, top) = (frame.left(), frame.top());
+    if left % 2 != 0 || top % 2 != 0 {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::Generic(format!(
+                "WebP frames must be placed at even offsets, not at ({}, {})",
+                left, top
+            )),
+        )));
+    }
+
+    let (numer, denom) = frame.delay().numer_denom_ms();
+    let duration = (u64::from(numer) + u64::from(denom) / 2) / u64::from(denom);
+    let buffer = frame.into_buffer();
+    let (width, height) = buffer.dimensions();
+    let right = u64::from(left) + u64::from(width);
+    let bottom = u64::from(top) + u64::from(height);
+    if right > u64::from(MAX_CANVAS_DIMENSION) || bottom > u64::from(MAX_CANVAS_DIMENSION) {
+        return Err(dimension_error());
+    }
+
+    let pixels = argb_pixels(&buffer, width, height, ColorType::Rgba8)?;
+    let bitstream = encode_vp8l(&pixels, width, height)?;
+    Ok(CompressedFrame {
+        left,
+        top,
+        width,
+        height,
+        duration: duration.min(0xff_ffff) as u32,
+        bitstream,
+        has_alpha: pixels.iter().any(|&argb| argb >> 24 != 0xff),
+    })
+}
+
+fn dimension_error() -> ImageError {
+    ImageError::Parameter(ParameterError::from_kind(
+        ParameterErrorKind::DimensionMismatch,
+    ))
+}
+
+/// Convert the samples of an image to pixels in the ARGB order of a VP8L bitstream.
+fn argb_pixels(data: &[u8], width: u32, height: u32, color: ColorType) -> ImageResult<Vec<u32>> {
+    let argb = |r: u8, g: u8, b: u8, a: u8| {
+        u32::from(a) << 24 | u32::from(r) << 16 | u32::from(g) << 8 | u32::from(b)
+    };
+    let samples = match color {
+        ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8 => {
+            usize::from(color.channel_count())
+        }
+        _ => {
+            return Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    ImageFormat::WebP.into(),
+                    UnsupportedErrorKind::Color(color.into()),
+                ),
+            ))
+        }
+    };
+    let len = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|pixels| pixels.checked_mul(samples));
+    if width == 0 || height == 0 || len != Some(data.len()) {
+        return Err(dimension_error());
+    }
+
+    Ok(data
+        .chunks_exact(samples)
+        .map(|pixel| match *pixel {
+            [l] => argb(l, l, l, 0xff),
+            [l, a] => argb(l, l, l, a),
+            [r, g, b] => argb(r, g, b, 0xff),
+            [r, g, b, a] => argb(r, g, b, a),
+            _ => unreachable!(),
+        })
+        .collect())
+}
+
+/// Write `chunks` into a RIFF container of a WebP file.
+fn write_riff<W: Write>(w: &mut W, chunks: &[u8]) -> ImageResult<()> {
+    let size = u32::try_from(4 + chunks.len()).map_err(|_| {
+        ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+            "the encoded image is too large for a WebP file".to_string(),
+        )))
+    })?;
+    w.write_all(b"RIFF")?;
+    w.write_all(&size.to_le_bytes())?;
+    w.write_all(b"WEBP")?;
+    w.write_all(chunks)?;
+    Ok(())
+}
+
+/// Append a chunk, padded to an even length.
+fn write_chunk(out: &mut Vec<u8>, name: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(name);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 != 0 {
+        out.push(0);
+    }
+}
+
+fn write_u24(out: &mut Vec<u8>, value: u32) {
+    out.extend_from_slice(&value.to_le_bytes()[..3]);
+}
+
+/// Writes bits starting at the least significant bit of each byte.
+struct BitWriter {
+    buf: Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn new() -> BitWriter {
+        BitWriter {
+            buf: Vec::new(),
+            acc: 0,
+            bits: 0,
+        }
+    }
+
+    fn write(&mut self, value: u32, bits: u32) {
+        debug_assert!(bits <= 32 && u64::from(value) < 1 << bits);
+        self.acc |= u64::from(value) << self.bits;
+        self.bits += bits;
+        while self.bits >= 8 {
+            self.buf.push(self.acc as u8);
+            self.acc >>= 8;
+            self.bits -= 8;
+        }
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            self.buf.push(self.acc as u8);
+        }
+        self.buf
+    }
+}
+
+/// A symbol of the image data.
+#[derive(Clone, Copy)]
+enum Symbol {
+    Literal(u32),
+    BackwardReference { length: usize, plane_code: usize },
+}
+
+/// Encode pixels into a VP8L bitstream, including its signature.
+fn encode_vp8l(pixels: &[u32], width: u32, height: u32) -> ImageResult<Vec<u8>> {
+    if width > MAX_DIMENSION || height > MAX_DIMENSION {
+        return Err(dimension_error());
+    }
+
+    let mut w = BitWriter::new();
+    w.write(0x2f, 8);
+    w.write(width - 1, 14);
+    w.write(height - 1, 14);
+    let alpha_is_used = pixels.iter().any(|&argb| argb >> 24 != 0xff);
+    w.write(u32::from(alpha_is_used), 1);
+    // Version
+    w.write(0, 3);
+
+    // The subtract green transform, followed by the end of the transforms.
+    w.write(1, 1);
+    w.write(2, 2);
+    w.write(0, 1);
+    let pixels: Vec<u32> = pixels.iter().map(|&argb| subtract_green(argb)).collect();
+
+    // No color cache and a single group of prefix codes.
+    w.write(0, 1);
+    w.write(0, 1);
+
+    let symbols = find_symbols(&pixels, width as usize);
+    let mut counts: Vec<Vec<u32>> = ALPHABET_SIZE.iter().map(|&size| vec![0; size]).collect();
+    for symbol in &symbols {
+        match *symbol {
+            Symbol::Literal(argb) => {
+                counts[0][(argb >> 8 & 0xff) as usize] += 1;
+                counts[1][(argb >> 16 & 0xff) as usize] += 1;
+                counts[2][(argb & 0xff) as usize] += 1;
+                counts[3][(argb >> 24) as usize] += 1;
+            }
+            Symbol::BackwardReference { length, plane_code } => {
+                counts[0][256 + prefix_encode(length).0] += 1;
+                counts[4][prefix_encode(plane_code).0] += 1;
+            }
+        }
+    }
+    let codes: Vec<PrefixCode> = counts
+        .iter()
+        .map(|counts| write_prefix_code(&mut w, counts))
+        .collect();
+
+    for symbol in symbols {
+        match symbol {
+            Symbol::Literal(argb) => {
+                codes[0].write(&mut w, (argb >> 8 & 0xff) as usize);
+                codes[1].write(&mut w, (argb >> 16 & 0xff) as usize);
+                codes[2].write(&mut w, (argb & 0xff) as usize);
+                codes[3].write(&mut w, (argb >> 24) as usize);
+            }
+            Symbol::BackwardReference { length, plane_code } => {
+                let (prefix, extra_bits, extra) = prefix_encode(length);
+                codes[0].write(&mut w, 256 + prefix);
+                w.write(extra, extra_bits);
+                let (prefix, extra_bits, extra) = prefix_encode(plane_code);
+                codes[4].write(&mut w, prefix);
+                w.write(extra, extra_bits);
+            }
+        }
+    }
+
+    Ok(w.finish())
+}
+
+fn subtract_green(argb: u32) -> u32 {
+    let green = argb >> 8 & 0xff;
+    let red = (argb >> 16).wrapping_sub(green) & 0xff;
+    let blue = argb.wrapping_sub(green) & 0xff;
+    argb & 0xff00_ff00 | red << 16 | blue
+}
+
+/// Split pixels into literals and backward references to runs repeating the left or upper
+/// neighbours.
+fn find_symbols(pixels: &[u32], width: usize) -> Vec<Symbol> {
+    let run = |index: usize, distance: usize| {
+        if index < distance {
+            return 0;
+        }
+        pixels[index..]
+            .iter()
+            .zip(&pixels[index - distance..])
+            .take(MAX_LENGTH)
+            .take_while(|(pixel, earlier)| pixel == earlier)
+            .count()
+    };
+
+    let mut symbols = Vec::new();
+    let mut index = 0;
+    while index < pixels.len() {
+        let left = run(index, 1);
+        let up = run(index, width);
+        let (length, plane_code) = if up > left {
+            (up, PLANE_CODE_UP)
+        } else {
+            (left, PLANE_CODE_LEFT)
+        };
+
+        if length >= 3 {
+            symbols.push(Symbol::BackwardReference { length, plane_code });
+            index += length;
+        } else {
+            symbols.push(Symbol::Literal(pixels[index]));
+            index += 1;
+        }
+    }
+    symbols
+}
+
+/// Split a length or distance into its prefix symbol and extra bits, returning the symbol, the
+/// number of extra bits and their value.
+fn prefix_encode(value: usize) -> (usize, u32, u32) {
+    let value = value - 1;
+    if value < 4 {
+        return (value, 0, 0);
+    }
+    let highest_bit = 63 - (value as u64).leading_zeros();
+    let second_bit = value >> (highest_bit - 1) & 1;
+    let extra_bits = highest_bit - 1;
+    let extra = value & ((1 << extra_bits) - 1);
+    (
+        2 * highest_bit as usize + second_bit,
+        extra_bits,
+        extra as u32,
+    )
+}
+
+/// A canonical prefix code.
+struct PrefixCode {
+    /// The length of each code as written. This is zero for all symbols if only one is used.
+    lengths: Vec<u8>,
+    /// The codes, with their bits reversed to be written starting at the least significant bit.
+    codes: Vec<u16>,
+}
+
+impl PrefixCode {
+    fn new(lengths: &[u8]) -> PrefixCode {
+        let mut count = [0u16; MAX_CODE_LENGTH as usize + 1];
+        for &length in lengths {
+            count[usize::from(length)] += 1;
+        }
+        count[0] = 0;
+
+        let mut next_code = [0u16; MAX_CODE_LENGTH as usize + 1];
+        let mut code = 0;
+        for length in 1..next_code.len() {
+            code = (code + count[length - 1]) << 1;
+            next_code[length] = code;
+        }
+
+        let codes = lengths
+            .iter()
+            .map(|&length| {
+                if length == 0 {
+                    return 0;
+                }
+                let code = next_code[usize::from(length)];
+                next_code[usize::from(length)] += 1;
+                code.reverse_bits() >> (16 - length)
+            })
+            .collect();
+
+        // A code of a single symbol is decoded without reading any bits.
+        let used = lengths.iter().filter(|&&length| length > 0).count();
+        let lengths = if used > 1 {
+            lengths.to_vec()
+        } else {
+            vec![0; lengths.len()]
+        };
+        PrefixCode { lengths, codes }
+    }
+
+    fn write(&self, w: &mut BitWriter, symbol: usize) {
+        w.write(
+            u32::from(self.codes[symbol]),
+            u32::from(self.lengths[symbol]),
+        );
+    }
+}
+
+/// Write a prefix code for symbols with the given counts and return it.
+fn write_prefix_code(w: &mut BitWriter, counts: &[u32]) -> PrefixCode {
+    let mut used = counts.iter().enumerate().filter(|&(_, &count)| count > 0);
+    let first = used.next().map_or(0, |(symbol, _)| symbol);
+    if used.next().is_none() && first < 256 {
+        // A simple code of one symbol.
+        w.write(1, 1);
+        w.write(0, 1);
+        if first < 2 {
+            w.write(0, 1);
+            w.write(first as u32, 1);
+        } else {
+            w.write(1, 1);
+            w.write(first as u32, 8);
+        }
+        let mut lengths = vec![0; counts.len()];
+        lengths[first] = 1;
+        return PrefixCode::new(&lengths);
+    }
+
+    let lengths = code_lengths(counts, MAX_CODE_LENGTH);
+
+    // Run length encode the code lengths, using 17 and 18 for runs of zeros.
+    let mut tokens = Vec::new();
+    let mut index = 0;
+    while index < lengths.len() {
+        if lengths[index] != 0 {
+            tokens.push((usize::from(lengths[index]), 0, 0));
+            index += 1;
+            continue;
+        }
+
+        let zeros = lengths[index..]
+            .iter()
+            .take_while(|&&length| length == 0)
+            .count();
+        index += zeros;
+        let mut remaining = zeros;
+        while remaining >= 3 {
+            let run = remaining.min(138);
+            if run <= 10 {
+                tokens.push((17, 3, run as u32 - 3));
+            } else {
+                tokens.push((18, 7, run as u32 - 11));
+            }
+            remaining -= run;
+        }
+        tokens.extend((0..remaining).map(|_| (0, 0, 0)));
+    }
+
+    let mut token_counts = [0u32; CODE_LENGTH_CODE_ORDER.len()];
+    for &(token, _, _) in &tokens {
+        token_counts[token] += 1;
+    }
+    let token_lengths = code_lengths(&token_counts, MAX_CODE_LENGTH_CODE_LENGTH);
+    let written = CODE_LENGTH_CODE_ORDER
+        .iter()
+        .rposition(|&token| token_lengths[token] != 0)
+        .map_or(0, |position| position + 1)
+        .max(4);
+
+    w.write(0, 1);
+    w.write(written as u32 - 4, 4);
+    for &token in &CODE_LENGTH_CODE_ORDER[..written] {
+        w.write(u32::from(token_lengths[token]), 3);
+    }
+    // The lengths of all symbols follow.
+    w.write(0, 1);
+
+    let token_code = PrefixCode::new(&token_lengths);
+    for (token, extra_bits, extra) in tokens {
+        token_code.write(w, token);
+        w.write(extra, extra_bits);
+    }
+
+    PrefixCode::new(&lengths)
+}
+
+/// Compute the code lengths of a Huffman code for symbols with the given counts, limited to
+/// `max_length` bits.
+///
+/// The counts are flattened until the limit is met. A single used symbol gets a length of one.
+fn code_lengths(counts: &[u32], max_length: u8) -> Vec<u8> {
+    let mut counts = counts.to_vec();
+    loop {
+        let lengths = huffman_code_lengths(&counts);
+        if lengths.iter().all(|&length| length <= max_length) {
+            return lengths;
+        }
+        for count in counts.iter_mut().filter(|count| **count > 0) {
+            *count = (*count >> 1) | 1;
+        }
+    }
+}
+
+fn huffman_code_lengths(counts: &[u32]) -> Vec<u8> {
+    let used: Vec<usize> = (0..counts.len()).filter(|&s| counts[s] > 0).collect();
+
+    // The parent of every node, with the leaves first.
+    let mut parents = vec![0; used.len()];
+    let mut heap: BinaryHeap<_> = used
+        .iter()
+        .enumerate()
+        .map(|(node, &symbol)| Reverse((u64::from(counts[symbol]), node)))
+        .collect();
+    while heap.len() > 1 {
+        let Reverse((first, first_node)) = heap.pop().unwrap();
+        let Reverse((second, second_node)) = heap.pop().unwrap();
+        let node = parents.len();
+        parents.push(0);
+        parents[first_node] = node;
+        parents[second_node] = node;
+        heap.push(Reverse((first + second, node)));
+    }
+
+    let root = parents.len().saturating_sub(1);
+    let mut lengths = vec![0; counts.len()];
+    for (leaf, &symbol) in used.iter().enumerate() {
+        let mut depth = 0;
+        let mut node = leaf;
+        while node != root {
+            node = parents[node];
+            depth += 1;
+        }
+        lengths[symbol] = depth.max(1);
+    }
+    lengths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::Delay;
+    use crate::codecs::webp::WebPDecoder;
+    use crate::{ImageDecoder, RgbaImage};
+
+    fn decode(data: &[u8]) -> (u32, u32, Vec<u8>) {
+        let decoder = WebPDecoder::new(data).unwrap();
+        assert_eq!(decoder.color_type(), ColorType::Rgba8);
+        let (width, height) = decoder.dimensions();
+        let mut buf = vec![0; decoder.total_bytes() as usize];
+        decoder.read_image(&mut buf).unwrap();
+        (width, height, buf)
+    }
+
+    #[test]
+    fn roundtrip_still_images() {
+        let gradient: Vec<u8> = (0..37 * 19 * 4).map(|i| (i * 7 % 251) as u8).collect();
+        let mut flat = vec![0u8; 64 * 3 * 4];
+        flat[100] = 3;
+        flat[200] = 77;
+        for (pixels, width, height) in vec![
+            (gradient, 37, 19),
+            (flat, 64, 3),
+            (vec![10, 20, 30, 40], 1, 1),
+        ] {
+            let mut encoded = Vec::new();
+            WebPEncoder::new(&mut encoded)
+                .write_image(&pixels, width, height, ColorType::Rgba8)
+                .unwrap();
+            assert_eq!(decode(&encoded), (width, height, pixels));
+        }
+    }
+
+    #[test]
+    fn roundtrip_icc_profile() {
+        let icc_profile = b"an odd-length profile".to_vec();
+        let mut encoded = Vec::new();
+        let mut encoder = WebPEncoder::new(&mut encoded);
+        encoder.set_icc_profile(icc_profile.clone()).unwrap();
+        encoder
+            .write_image(&[1, 2, 3, 4], 1, 1, ColorType::Rgba8)
+            .unwrap();
+
+        let mut decoder = WebPDecoder::new(&encoded[..]).unwrap();
+        assert_eq!(decoder.icc_profile(), Some(icc_profile));
+        assert_eq!(decode(&encoded), (1, 1, vec![1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn roundtrip_exif() {
+        let exif = Exif {
+            make: Some("Camera Co".to_string()),
+            ..Exif::default()
+        };
+        let mut encoded = Vec::new();
+        let mut encoder = WebPEncoder::new(&mut encoded);
+        encoder.set_exif_metadata(&exif).unwrap();
+        encoder
+            .write_image(&[1, 2, 3, 4], 1, 1, ColorType::Rgba8)
+            .unwrap();
+
+        let mut decoder = WebPDecoder::new(&encoded[..]).unwrap();
+        assert_eq!(decoder.exif_metadata(), Some(exif));
+        assert_eq!(decode(&encoded), (1, 1, vec![1, 2, 3, 4]));
+    }
+
+    #[test]
+    fn roundtrip_xmp() {
+        let exif = Exif {
+            make: Some("Camera Co".to_string()),
+            ..Exif::default()
+        };
+        let mut metadata = MetadataMap::new();
+        metadata.set_xmp(Some("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/>".to_string()));
+        let mut encoded = Vec::new();
+        let mut encoder = WebPEncoder::new(&mut encoded);
+        encoder.set_exif_metadata(&exif).unwrap();
+        encoder.set_text_metadata(&metadata).unwrap();
+        encoder
+            .write_image(&[1, 2, 3, 4], 1, 1, ColorType::Rgba8)
+            .unwrap();
+
+        // The packet follows the EXIF chunk, both are read after the image data.
+        let mut decoder = WebPDecoder::new(&encoded[..]).unwrap();
+        assert_eq!(decoder.text_metadata(), metadata);
+        assert_eq!(decoder.exif_metadata(), Some(exif));
+
+        metadata.insert("Title", "Untitled");
+        let mut encoder = WebPEncoder::new(Vec::new());
+        assert!(encoder.set_text_metadata(&metadata).is_err());
+    }
+
+    #[test]
+    fn roundtrip_color_types() {
+        let mut encoded = Vec::new();
+        WebPEncoder::new(&mut encoded)
+            .encode(&[0, 128, 255, 7], 2, 2, ColorType::L8)
+            .unwrap();
+        let (_, _, decoded) = decode(&encoded);
+        assert_eq!(&decoded[4..8], &[128, 128, 128, 255]);
+
+        let mut encoded = Vec::new();
+        WebPEncoder::new(&mut encoded)
+            .encode(&[1, 2, 3, 4, 5, 6], 2, 1, ColorType::Rgb8)
+            .unwrap();
+        assert_eq!(decode(&encoded).2, vec![1, 2, 3, 255, 4, 5, 6, 255]);
+
+        assert!(WebPEncoder::new(Vec::new())
+            .encode(&[0; 4], 1, 1, ColorType::L16)
+            .is_err());
+        assert!(WebPEncoder::new(Vec::new())
+            .encode(&[0; 3], 1, 1, ColorType::Rgba8)
+            .is_err());
+    }
+
+    #[test]
+    fn limits_code_lengths() {
+        let counts: Vec<u32> = (0..40).map(|i| 1 << (i % 30)).collect();
+        let lengths = code_lengths(&counts, MAX_CODE_LENGTH);
+        assert!(lengths.iter().all(|&length| (1..=15).contains(&length)));
+        let kraft: f64 = lengths.iter().map(|&l| 0.5f64.powi(i32::from(l))).sum();
+        assert!(kraft <= 1.0);
+    }
+
+    #[test]
+    fn encodes_animations() {
+        let first = RgbaImage::from_fn(6, 4, |x, y| crate::Rgba([x as u8, y as u8, 9, 255]));
+        let second = RgbaImage::from_pixel(2, 2, crate::Rgba([255, 0, 0, 128]));
+        let frames = vec![
+            Frame::from_parts(first.clone(), 0, 0, Delay::from_numer_denom_ms(100, 1)),
+            Frame::from_parts(second.clone(), 4, 2, Delay::from_numer_denom_ms(50, 3)),
+        ];
+
+        let mut encoder = WebPAnimationEncoder::new(Vec::new());
+        encoder.set_loop_count(3);
+        encoder.encode_frames(frames).unwrap();
+        let data = encoder.finish().unwrap();
+
+        assert_eq!(&data[..4], b"RIFF");
+        assert_eq!(
+            u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize,
+            data.len() - 8
+        );
+        assert_eq!(&data[8..16], b"WEBPVP8X");
+        // Animation and alpha flags, a 6x4 canvas.
+        assert_eq!(&data[20..30], &[0x12, 0, 0, 0, 5, 0, 0, 3, 0, 0]);
+        assert_eq!(&data[30..34], b"ANIM");
+        assert_eq!(&data[42..44], &[3, 0]);
+
+        let mut chunks = &data[44..];
+        let mut decoded = Vec::new();
+        while !chunks.is_empty() {
+            assert_eq!(&chunks[..4], b"ANMF");
+            let len = u32::from_le_bytes([chunks[4], chunks[5], chunks[6], chunks[7]]) as usize;
+            let frame = &chunks[8..8 + len];
+            let u24 = |i: usize| u32::from_le_bytes([frame[i], frame[i + 1], frame[i + 2], 0]);
+            assert_eq!(&frame[16..20], b"VP8L");
+            let bitstream = &frame[24..24 + u24(20) as usize];
+            let riff = {
+                let mut chunk = Vec::new();
+                write_chunk(&mut chunk, b"VP8L", bitstream);
+                let mut riff = Vec::new();
+                write_riff(&mut riff, &chunk).unwrap();
+                riff
+            };
+            decoded.push((u24(0) * 2, u24(3) * 2, u24(12), decode(&riff)));
+            chunks = &chunks[8 + len + len % 2..];
+        }
+
+        assert_eq!(
+            decoded,
+            vec![
+                (0, 0, 100, (6, 4, first.into_raw())),
+                (4, 2, 17, (2, 2, second.into_raw())),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_odd_offsets_and_empty_animations() {
+        let frame = Frame::from_parts(RgbaImage::new(1, 1), 1, 0, Delay::from_numer_denom_ms(0, 1));
+        let mut encoder = WebPAnimationEncoder::new(Vec::new());
+        assert!(encoder.encode_frame(frame).is_err());
+        assert!(encoder.finish().is_err());
+    }
+}
@@ -1,8 +1,12 @@
-//! Decoding of WebP Images
+//! Decoding and Encoding of WebP Images
 
 pub use self::decoder::WebPDecoder;
+pub use self::encoder::{WebPAnimationEncoder, WebPEncoder};
+pub use self::streaming::StreamingDecoder;
 
 mod decoder;
+mod encoder;
+mod streaming;
 mod loop_filter;
 mod transform;
 
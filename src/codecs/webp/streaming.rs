@@ -0,0 +1,475 @@
+//! Incremental decoding of WebP images whose data is still arriving.
+//!
+//! Lossy images are decoded a row of macroblocks at a time, as soon as the data of the row has
+//! arrived, like the incremental decoder of libwebp. Lossless images are decoded once the whole
+//! image has arrived.
+use std::cmp;
+use std::convert::TryFrom;
+use std::io::{self, Cursor};
+use std::ops::Range;
+
+use byteorder::{ByteOrder, LittleEndian};
+
+use crate::color::ColorType;
+use crate::dynimage::DynamicImage;
+use crate::error::{ImageError, ImageResult, UnsupportedError, UnsupportedErrorKind};
+use crate::image::ImageFormat;
+use crate::io::Limits;
+use crate::{RgbImage, RgbaImage};
+
+use super::decoder::DecoderError;
+use super::lossless::LosslessDecoder;
+use super::vp8::Vp8Decoder;
+
+enum State {
+    /// Waiting for the header of the chunk at this offset
+    Chunk(usize),
+    /// Decoding a lossy frame
+    Lossy(Box<LossyFrame>),
+    /// Waiting for the whole lossless frame, which spans this range of the data
+    Lossless(Range<usize>),
+    /// The whole image has been decoded
+    Done,
+}
+
+struct LossyFrame {
+    decoder: Vp8Decoder<Cursor<Vec<u8>>>,
+    /// Where the frame header ends and the table of partition sizes starts
+    header_end: usize,
+    /// Where the whole frame ends
+    end: usize,
+    /// The ranges of the data of the token partitions, once their sizes have arrived
+    partitions: Vec<Range<usize>>,
+    /// How much of each token partition has been passed to the decoder
+    fed: Vec<usize>,
+    /// The next row of macroblocks to decode
+    row: usize,
+}
+
+/// A WebP decoder that the data of an image is pushed to as it arrives, for example over a
+/// network connection, and that decodes as many rows of the image as it can with the data so far.
+///
+/// This allows images to be shown progressively while they are loaded. The dimensions are known
+/// as soon as the header has arrived. Rows are decoded top to bottom, and the decoded rows do not
+/// change anymore once decoded.
+///
+/// ```no_run
+/// # fn main() -> image::ImageResult<()> {
+/// use std::io::Read;
+/// use image::codecs::webp::StreamingDecoder;
+///
+/// # let mut connection = std::io::empty();
+/// let mut decoder = StreamingDecoder::new();
+/// let mut chunk = [0; 4096];
+/// while !decoder.is_finished() {
+///     let len = connection.read(&mut chunk)?;
+///     if len == 0 {
+///         break;
+///     }
+///     decoder.push(&chunk[..len])?;
+///     if let Some((width, _)) = decoder.dimensions() {
+///         println!("{} rows of width {} decoded", decoder.decoded_rows(), width);
+///     }
+/// }
+/// let image = decoder.into_image()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Like `WebPDecoder`, this decodes lossy RGB images and lossless RGBA images. Once `push`
+/// returned an error, the decoder should not be used any further.
+pub struct StreamingDecoder {
+    data: Vec<u8>,
+    state: State,
+    dimensions: Option<(u32, u32)>,
+    color_type: Option<ColorType>,
+    /// The decoded image, of which only the first rows are filled in
+    buf: Vec<u8>,
+    rows: u32,
+    /// The limits on the dimensions and the memory that is left for the decoded image.
+    limits: Limits,
+}
+
+impl StreamingDecoder {
+    /// Create a decoder that has not received any data yet.
+    pub fn new() -> StreamingDecoder {
+        StreamingDecoder {
+            data: Vec::new(),
+            state: State::Chunk(12),
+            dimensions: None,
+            color_type: None,
+            buf: Vec::new(),
+            rows: 0,
+            limits: Limits::no_limits(),
+        }
+    }
+
+    /// Set the limits of the decoder, before any data is pushed to it.
+    ///
+    /// The `max_alloc` limit bounds the data pushed so far, which the decoder keeps until the image
+    /// is done, together with the buffers of the decoded image. The dimensions are checked
+    /// once the header has arrived, and `push` returns an `ImageError::Limits` if they exceed the
+    /// limits.
+    pub fn set_limits(&mut self, limits: Limits) -> ImageResult<()> {
+        limits.check_support(&crate::io::LimitSupport::default())?;
+        if let Some((width, height)) = self.dimensions {
+            limits.check_dimensions(width, height)?;
+        }
+        self.limits = limits;
+        Ok(())
+    }
+
+    /// Pass the next part of the data of the image to the decoder, and decode as much of the
+    /// image as possible.
+    ///
+    /// Data that follows the image is ignored.
+    pub fn push(&mut self, data: &[u8]) -> ImageResult<()> {
+        if let State::Done = self.state {
+            return Ok(());
+        }
+
+        self.limits.reserve(data.len() as u64)?;
+        let had_header = self.data.len() >= 12;
+        self.data.extend_from_slice(data);
+        if !had_header && self.data.len() >= 12 {
+            let riff = <[u8; 4]>::try_from(&self.data[0..4]).unwrap();
+            if &riff != b"RIFF" {
+                return Err(DecoderError::RiffSignatureInvalid(riff).into());
+            }
+            let webp = <[u8; 4]>::try_from(&self.data[8..12]).unwrap();
+            if &webp != b"WEBP" {
+                return Err(DecoderError::WebpSignatureInvalid(webp).into());
+            }
+        }
+
+        loop {
+            let next = match &mut self.state {
+                State::Chunk(position) => {
+                    let position = *position;
+                    match self.read_chunk(position)? {
+                        Some(next) => next,
+                        None => return Ok(()),
+                    }
+                }
+                State::Lossy(frame) => {
+                    let done = decode_lossy(frame, &self.data, &mut self.buf, &mut self.rows)?;
+                    if !done {
+                        return Ok(());
+                    }
+                    State::Done
+                }
+                State::Lossless(range) => {
+                    if self.data.len() < range.end {
+                        return Ok(());
+                    }
+                    let data = self.data[range.clone()].to_vec();
+                    // The lossless decoder keeps its own copy of the pixels next to the image.
+                    if let Some((width, height)) = self.dimensions {
+                        self.limits
+                            .reserve(u64::from(width) * u64::from(height) * 4 * 2)?;
+                    }
+                    let mut decoder = LosslessDecoder::new(Cursor::new(data));
+                    let frame = decoder.decode_frame()?;
+                    self.dimensions = Some((u32::from(frame.width), u32::from(frame.height)));
+                    self.buf = vec![0; frame.get_buf_size()];
+                    frame.fill_rgba(&mut self.buf);
+                    self.rows = u32::from(frame.height);
+                    State::Done
+                }
+                State::Done => return Ok(()),
+            };
+            self.state = next;
+        }
+    }
+
+    /// Reads the chunk at a position, returning the next state once its header has arrived.
+    fn read_chunk(&mut self, position: usize) -> ImageResult<Option<State>> {
+        let header = match self.data.get(position..position + 8) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let len = LittleEndian::read_u32(&header[4..]) as usize;
+        let start = position + 8;
+        let end = start + len;
+
+        match &header[..4] {
+            b"VP8 " => {
+                // The frame tag holds the size of the first partition, which must have arrived
+                // completely before any macroblock can be decoded.
+                let tag = match self.data.get(start..start + 3) {
+                    Some(tag) => LittleEndian::read_u24(tag) as usize,
+                    None => return Ok(None),
+                };
+                let header_end = cmp::min(start + 10 + (tag >> 5), end);
+                if self.data.len() < header_end {
+                    return Ok(None);
+                }
+                let mut decoder =
+                    Vp8Decoder::new(Cursor::new(self.data[start..header_end].to_vec()));
+                decoder.read_frame_header()?;
+
+                let frame = decoder.frame();
+                let (width, height) = (u32::from(frame.width), u32::from(frame.height));
+                self.limits.check_dimensions(width, height)?;
+                self.limits.reserve(frame.get_buf_size() as u64)?;
+                self.dimensions = Some((width, height));
+                self.color_type = Some(ColorType::Rgb8);
+                self.buf = vec![0; frame.get_buf_size()];
+
+                let partitions = decoder.num_partitions();
+                Ok(Some(State::Lossy(Box::new(LossyFrame {
+                    decoder,
+                    header_end,
+                    end,
+                    partitions: Vec::new(),
+                    fed: vec![0; partitions],
+                    row: 0,
+                }))))
+            }
+            b"VP8L" => {
+                let header = match self.data.get(start..start + 5) {
+                    Some(header) => header,
+                    None => return Ok(None),
+                };
+                // The signature is followed by the width and height minus one, of 14 bits each.
+                if header[0] == 0x2f {
+                    let size = LittleEndian::read_u32(&header[1..]);
+                    let (width, height) = ((size & 0x3fff) + 1, (size >> 14 & 0x3fff) + 1);
+                    self.limits.check_dimensions(width, height)?;
+                    self.dimensions = Some((width, height));
+                }
+                self.color_type = Some(ColorType::Rgba8);
+                Ok(Some(State::Lossless(start..end)))
+            }
+            b"ALPH" | b"ANIM" | b"ANMF" => {
+                // Alpha and Animation isn't supported
+                Err(ImageError::Unsupported(
+                    UnsupportedError::from_format_and_kind(
+                        ImageFormat::WebP.into(),
+                        UnsupportedErrorKind::GenericFeature(
+                            header[..4].iter().map(|&b| b as char).collect(),
+                        ),
+                    ),
+                ))
+            }
+            // RIFF chunks containing an uneven number of bytes are padded with an extra 0x00
+            _ => Ok(Some(State::Chunk(end + len % 2))),
+        }
+    }
+
+    /// The dimensions of the image, once its header has arrived.
+    pub fn dimensions(&self) -> Option<(u32, u32)> {
+        self.dimensions
+    }
+
+    /// The color type of the decoded pixels, once the kind of image is known.
+    pub fn color_type(&self) -> Option<ColorType> {
+        self.color_type
+    }
+
+    /// The number of rows at the top of the image that have been decoded.
+    pub fn decoded_rows(&self) -> u32 {
+        self.rows
+    }
+
+    /// The pixels of the decoded rows, in the color type of the image.
+    pub fn rows(&self) -> &[u8] {
+        let (width, _) = self.dimensions.unwrap_or((0, 0));
+        let bytes_per_pixel = self.color_type.map_or(0, |color| color.bytes_per_pixel());
+        &self.buf[..self.rows as usize * width as usize * usize::from(bytes_per_pixel)]
+    }
+
+    /// Returns if the whole image has been decoded.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    /// Returns the decoded image.
+    ///
+    /// Returns an `ImageError::IoError` if the image has not been decoded completely.
+    pub fn into_image(self) -> ImageResult<DynamicImage> {
+        if !self.is_finished() {
+            return Err(ImageError::IoError(io::ErrorKind::UnexpectedEof.into()));
+        }
+        let (width, height) = self.dimensions.unwrap();
+        Ok(match self.color_type {
+            Some(ColorType::Rgb8) => {
+                DynamicImage::ImageRgb8(RgbImage::from_raw(width, height, self.buf).unwrap())
+            }
+            _ => DynamicImage::ImageRgba8(RgbaImage::from_raw(width, height, self.buf).unwrap()),
+        })
+    }
+}
+
+impl Default for StreamingDecoder {
+    fn default() -> StreamingDecoder {
+        StreamingDecoder::new()
+    }
+}
+
+/// Decodes the rows of a lossy frame whose data has arrived, returning if the whole frame is done.
+fn decode_lossy(
+    frame: &mut LossyFrame,
+    data: &[u8],
+    buf: &mut [u8],
+    rows: &mut u32,
+) -> ImageResult<bool> {
+    let truncated = || ImageError::IoError(io::ErrorKind::UnexpectedEof.into());
+
+    if frame.partitions.is_empty() {
+        let count = frame.fed.len();
+        let table = frame.header_end..frame.header_end + 3 * (count - 1);
+        if table.end > frame.end {
+            return Err(truncated());
+        }
+        let sizes = match data.get(table.clone()) {
+            Some(sizes) => sizes,
+            None => return Ok(false),
+        };
+        let mut start = table.end;
+        for size in sizes.chunks(3) {
+            let end = start + LittleEndian::read_u24(size) as usize;
+            if end > frame.end {
+                return Err(truncated());
+            }
+            frame.partitions.push(start..end);
+            start = end;
+        }
+        frame.partitions.push(start..frame.end);
+        for (fed, partition) in frame.fed.iter_mut().zip(&frame.partitions) {
+            *fed = partition.start;
+        }
+    }
+
+    let mut complete = Vec::with_capacity(frame.partitions.len());
+    for (i, partition) in frame.partitions.iter().enumerate() {
+        let available = cmp::min(partition.end, data.len());
+        if frame.fed[i] < available {
+            frame
+                .decoder
+                .extend_partition(i, &data[frame.fed[i]..available]);
+            frame.fed[i] = available;
+        }
+        complete.push(available == partition.end);
+    }
+
+    let macroblock_rows = frame.decoder.macroblock_rows();
+    let decoded = frame.row;
+    while frame.row < macroblock_rows {
+        if !frame.decoder.decode_available_row(frame.row, &complete)? {
+            break;
+        }
+        // Filtering a row changes the bottom of the row above it, so the previous row is done
+        // once this one has been filtered.
+        if frame.row > 0 {
+            frame.decoder.filter_row(frame.row - 1);
+        }
+        frame.row += 1;
+    }
+    if frame.row == decoded && frame.row < macroblock_rows {
+        return Ok(false);
+    }
+
+    let height = usize::from(frame.decoder.frame().height);
+    let done = frame.row == macroblock_rows;
+    if done && macroblock_rows > 0 {
+        frame.decoder.filter_row(macroblock_rows - 1);
+    }
+    let finished_rows = if done {
+        height
+    } else {
+        cmp::min(16 * frame.row.saturating_sub(2), height)
+    };
+
+    frame
+        .decoder
+        .frame()
+        .fill_rgb_rows(buf, *rows as usize..finished_rows);
+    *rows = finished_rows as u32;
+    Ok(done)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::codecs::webp::WebPDecoder;
+    use std::fs;
+
+    fn decode_whole(data: &[u8]) -> DynamicImage {
+        DynamicImage::from_decoder(WebPDecoder::new(Cursor::new(data)).unwrap()).unwrap()
+    }
+
+    fn check_incremental(path: &str, step: usize) {
+        let data = fs::read(path).unwrap();
+        let expected = decode_whole(&data);
+
+        let mut decoder = StreamingDecoder::new();
+        let mut rows = 0;
+        for chunk in data.chunks(step) {
+            decoder.push(chunk).unwrap();
+            assert!(decoder.decoded_rows() >= rows);
+            rows = decoder.decoded_rows();
+            // Decoded rows are final.
+            let decoded = decoder.rows();
+            assert_eq!(decoded, &expected.as_bytes()[..decoded.len()]);
+        }
+        assert!(decoder.is_finished());
+        assert_eq!(
+            decoder.dimensions(),
+            Some((expected.width(), expected.height()))
+        );
+        assert_eq!(decoder.color_type(), Some(expected.color()));
+        assert_eq!(decoder.into_image().unwrap(), expected);
+    }
+
+    #[test]
+    fn lossy_incremental() {
+        check_incremental("tests/images/webp/lossy_images/simple-rgb.webp", 1);
+        check_incremental("tests/images/webp/lossy_images/simple-gray.webp", 37);
+        check_incremental("tests/images/webp/lossy_images/vp8x-rgb.webp", 100);
+    }
+
+    #[test]
+    fn lossy_rows_arrive_early() {
+        let data = fs::read("tests/images/webp/lossy_images/simple-rgb.webp").unwrap();
+        let mut decoder = StreamingDecoder::new();
+        decoder.push(&data[..data.len() * 3 / 4]).unwrap();
+        assert_eq!(decoder.dimensions(), Some((100, 100)));
+        assert!(decoder.decoded_rows() > 0 && decoder.decoded_rows() < 100);
+        assert!(decoder.into_image().is_err());
+    }
+
+    #[test]
+    fn lossless_incremental() {
+        check_incremental("tests/images/webp/lossless_images/simple.webp", 4096);
+
+        let data = fs::read("tests/images/webp/lossless_images/2-color.webp").unwrap();
+        let mut decoder = StreamingDecoder::new();
+        decoder.push(&data[..data.len() - 1]).unwrap();
+        assert!(decoder.dimensions().is_some());
+        assert_eq!(decoder.decoded_rows(), 0);
+        decoder.push(&data[data.len() - 1..]).unwrap();
+        assert!(decoder.is_finished());
+    }
+
+    #[test]
+    fn pushed_data_counts_against_limits() {
+        let data = fs::read("tests/images/webp/lossy_images/simple-rgb.webp").unwrap();
+        let mut limits = Limits::no_limits();
+        limits.max_alloc = Some(data.len() as u64 / 2);
+        let mut decoder = StreamingDecoder::new();
+        decoder.set_limits(limits).unwrap();
+        decoder.push(&data[..12]).unwrap();
+        assert!(matches!(
+            decoder.push(&data[12..]),
+            Err(ImageError::Limits(_))
+        ));
+    }
+
+    #[test]
+    fn invalid_signature() {
+        let mut decoder = StreamingDecoder::new();
+        decoder.push(b"RIFF").unwrap();
+        assert!(decoder.push(b"\0\0\0\0WEBQ").is_err());
+    }
+}
@@ -16,6 +16,7 @@ use byteorder::{LittleEndian, ReadBytesExt};
 use std::convert::TryInto;
 use std::default::Default;
 use std::io::Read;
+use std::ops::Range;
 use std::{cmp, error, fmt};
 
 use super::loop_filter;
@@ -729,6 +730,19 @@ struct BoolReader {
     range: u32,
     value: u32,
     bit_count: u8,
+
+    // Set once a bit past the end of the buffer was needed
+    eof: bool,
+}
+
+/// The state of a `BoolReader`, to resume reading from
+#[derive(Clone, Copy)]
+struct BoolReaderPosition {
+    index: usize,
+    range: u32,
+    value: u32,
+    bit_count: u8,
+    eof: bool,
 }
 
 impl BoolReader {
@@ -739,6 +753,7 @@ impl BoolReader {
             value: 0,
             bit_count: 0,
             index: 0,
+            eof: false,
         }
     }
 
@@ -748,13 +763,48 @@ impl BoolReader {
         }
 
         self.buf = buf;
-        // Direct access safe, since length has just been validated.
+        self.start();
+
+        Ok(())
+    }
+
+    /// Appends data to a buffer that is still arriving, starting to read once there is enough.
+    pub(crate) fn extend(&mut self, data: &[u8]) {
+        self.buf.extend_from_slice(data);
+        if !self.is_started() && self.buf.len() >= 2 {
+            self.start();
+        }
+    }
+
+    fn start(&mut self) {
+        // Direct access safe, since length has been validated by the callers.
         self.value = (u32::from(self.buf[0]) << 8) | u32::from(self.buf[1]);
         self.index = 2;
         self.range = 255;
         self.bit_count = 0;
+        self.eof = false;
+    }
 
-        Ok(())
+    fn is_started(&self) -> bool {
+        self.index != 0
+    }
+
+    fn position(&self) -> BoolReaderPosition {
+        BoolReaderPosition {
+            index: self.index,
+            range: self.range,
+            value: self.value,
+            bit_count: self.bit_count,
+            eof: self.eof,
+        }
+    }
+
+    fn seek(&mut self, position: BoolReaderPosition) {
+        self.index = position.index;
+        self.range = position.range;
+        self.value = position.value;
+        self.bit_count = position.bit_count;
+        self.eof = position.eof;
     }
 
     pub(crate) fn read_bool(&mut self, probability: u8) -> bool {
@@ -783,6 +833,8 @@ impl BoolReader {
                 if self.index < self.buf.len() {
                     self.value |= u32::from(self.buf[self.index]);
                     self.index += 1;
+                } else {
+                    self.eof = true;
                 }
             }
         }
@@ -893,7 +945,13 @@ impl Frame {
 
     /// Conversion values from https://docs.microsoft.com/en-us/windows/win32/medfound/recommended-8-bit-yuv-formats-for-video-rendering#converting-8-bit-yuv-to-rgb888
     pub fn fill_rgb(&self, buf: &mut [u8]) {
-        for index in 0..self.ybuf.len() {
+        self.fill_rgb_rows(buf, 0..self.height as usize);
+    }
+
+    /// Converts only the given rows, leaving the rest of the buffer untouched
+    pub(crate) fn fill_rgb_rows(&self, buf: &mut [u8], rows: Range<usize>) {
+        let width = self.width as usize;
+        for index in rows.start * width..rows.end * width {
             let y = index / self.width as usize;
             let x = index % self.width as usize;
             let chroma_index = self.chroma_width() as usize * (y / 2) + x / 2;
@@ -1206,7 +1264,8 @@ impl<R: Read> Vp8Decoder<R> {
         }
     }
 
-    fn read_frame_header(&mut self) -> ImageResult<()> {
+    /// Reads the frame header and the first partition, but not the token partitions.
+    pub(crate) fn read_frame_header(&mut self) -> ImageResult<()> {
         let tag = self.r.read_u24::<LittleEndian>()?;
 
         self.frame.keyframe = tag & 1 == 0;
@@ -1276,8 +1335,6 @@ impl<R: Read> Vp8Decoder<R> {
         }
 
         self.num_partitions = (1usize << self.b.read_literal(2) as usize) as u8;
-        let num_partitions = self.num_partitions as usize;
-        self.init_partitions(num_partitions)?;
 
         self.read_quantization_indices();
 
@@ -2024,50 +2081,124 @@ impl<R: Read> Vp8Decoder<R> {
         (filter_level, interior_limit, hev_threshold)
     }
 
+    fn decode_row(&mut self, mby: usize) -> ImageResult<()> {
+        let p = mby % self.num_partitions as usize;
+        self.left = MacroBlock::default();
+
+        for mbx in 0..self.mbwidth as usize {
+            let mb = self.read_macroblock_header(mbx)?;
+            let blocks = if !mb.coeffs_skipped {
+                self.read_residual_data(&mb, mbx, p)
+            } else {
+                if mb.luma_mode != LumaMode::B {
+                    self.left.complexity[0] = 0;
+                    self.top[mbx].complexity[0] = 0;
+                }
+
+                for i in 1usize..9 {
+                    self.left.complexity[i] = 0;
+                    self.top[mbx].complexity[i] = 0;
+                }
+
+                [0i32; 384]
+            };
+
+            self.intra_predict_luma(mbx, mby, &mb, &blocks);
+            self.intra_predict_chroma(mbx, mby, &mb, &blocks);
+
+            self.macroblocks.push(mb);
+        }
+
+        self.left_border = vec![129u8; 1 + 16];
+
+        Ok(())
+    }
+
+    /// Does loop filtering on a row of macroblocks.
+    ///
+    /// The chroma planes of a row are predicted from the unfiltered row above, so a row may
+    /// only be filtered once the row below it has been decoded.
+    pub(crate) fn filter_row(&mut self, mby: usize) {
+        for mbx in 0..self.mbwidth as usize {
+            let mb = self.macroblocks[mby * self.mbwidth as usize + mbx];
+            self.loop_filter(mbx, mby, &mb);
+        }
+    }
+
     /// Decodes the current frame
     pub fn decode_frame(&mut self) -> ImageResult<&Frame> {
         self.read_frame_header()?;
+        self.init_partitions(self.num_partitions as usize)?;
 
         for mby in 0..self.mbheight as usize {
-            let p = mby % self.num_partitions as usize;
-            self.left = MacroBlock::default();
+            self.decode_row(mby)?;
+        }
 
-            for mbx in 0..self.mbwidth as usize {
-                let mb = self.read_macroblock_header(mbx)?;
-                let blocks = if !mb.coeffs_skipped {
-                    self.read_residual_data(&mb, mbx, p)
-                } else {
-                    if mb.luma_mode != LumaMode::B {
-                        self.left.complexity[0] = 0;
-                        self.top[mbx].complexity[0] = 0;
-                    }
+        //do loop filtering
+        for mby in 0..self.mbheight as usize {
+            self.filter_row(mby);
+        }
 
-                    for i in 1usize..9 {
-                        self.left.complexity[i] = 0;
-                        self.top[mbx].complexity[i] = 0;
-                    }
+        Ok(&self.frame)
+    }
 
-                    [0i32; 384]
-                };
+    /// The number of token partitions, known once the frame header has been read.
+    pub(crate) fn num_partitions(&self) -> usize {
+        self.num_partitions as usize
+    }
 
-                self.intra_predict_luma(mbx, mby, &mb, &blocks);
-                self.intra_predict_chroma(mbx, mby, &mb, &blocks);
+    /// The number of rows of macroblocks, known once the frame header has been read.
+    pub(crate) fn macroblock_rows(&self) -> usize {
+        self.mbheight as usize
+    }
 
-                self.macroblocks.push(mb);
-            }
+    pub(crate) fn frame(&self) -> &Frame {
+        &self.frame
+    }
 
-            self.left_border = vec![129u8; 1 + 16];
-        }
+    /// Appends data that arrived to a token partition.
+    pub(crate) fn extend_partition(&mut self, index: usize, data: &[u8]) {
+        self.partitions[index].extend(data);
+    }
 
-        //do loop filtering
-        for mby in 0..self.mbheight as usize {
-            for mbx in 0..self.mbwidth as usize {
-                let mb = self.macroblocks[mby * self.mbwidth as usize + mbx];
-                self.loop_filter(mbx, mby, &mb);
+    /// Decodes a row of macroblocks while the token partitions are still arriving.
+    ///
+    /// `complete` tells for each partition whether all of its data has arrived. If the row
+    /// needs data of its partition that has not arrived yet, the decoder is returned to its state
+    /// before the row and `false` is returned, so the row can be decoded again later.
+    pub(crate) fn decode_available_row(
+        &mut self,
+        mby: usize,
+        complete: &[bool],
+    ) -> ImageResult<bool> {
+        let p = mby % self.num_partitions as usize;
+        if !self.partitions[p].is_started() {
+            if complete[p] {
+                return Err(DecoderError::NotEnoughInitData.into());
             }
+            return Ok(false);
         }
 
-        Ok(&self.frame)
+        let header = self.b.position();
+        let partition = self.partitions[p].position();
+        let top = self.top.clone();
+        let top_border = self.top_border.clone();
+        let left_border = self.left_border.clone();
+        let macroblocks = self.macroblocks.len();
+
+        self.decode_row(mby)?;
+        if !self.partitions[p].eof || complete[p] {
+            return Ok(true);
+        }
+
+        // The pixels written to the frame are overwritten when the row is decoded again.
+        self.b.seek(header);
+        self.partitions[p].seek(partition);
+        self.top = top;
+        self.top_border = top_border;
+        self.left_border = left_border;
+        self.macroblocks.truncate(macroblocks);
+        Ok(false)
     }
 }
 
@@ -2,6 +2,7 @@ use std::ops::{Index, IndexMut};
 
 use num_traits::{NumCast, ToPrimitive, Zero};
 
+use crate::image::{Direction, ImageFormat};
 use crate::traits::{Enlargeable, Pixel, Primitive};
 
 /// An enumeration over supported color types and bit depths
@@ -76,6 +77,26 @@ impl ColorType {
         let e: ExtendedColorType = self.into();
         e.channel_count()
     }
+
+    /// Returns if the codec for `format` can handle this color type in the given direction.
+    ///
+    /// For `Direction::Write` the answer is determined by the encoder itself, which is asked to
+    /// encode a single pixel of this color type. For `Direction::Read` the decoder is asked for
+    /// the color types of the pixels that the encoder writes in each color type, together with
+    /// those of the layouts that no encoder of this crate writes. Formats whose codec is not
+    /// enabled support no color type at all.
+    ///
+    /// See [`ImageFormat::supported_color_types`] for the complete table of a format.
+    ///
+    /// [`ImageFormat::supported_color_types`]: enum.ImageFormat.html#method.supported_color_types
+    pub fn is_supported_by(self, format: ImageFormat, direction: Direction) -> bool {
+        match direction {
+            Direction::Read => {
+                crate::io::free_functions::decoded_color_types(format).contains(&self)
+            }
+            Direction::Write => crate::io::free_functions::probe_color_type(format, self),
+        }
+    }
 }
 
 /// An enumeration of color types encountered in image formats.
@@ -369,6 +390,56 @@ define_colors! {
     pub struct LumaA<T: Primitive>([T; 2, 1]) = "YA";
 }
 
+impl<T> Rgb<T> {
+    /// Creates a color from its red, green and blue channels.
+    pub const fn new(r: T, g: T, b: T) -> Self {
+        Rgb([r, g, b])
+    }
+}
+
+impl<T> Luma<T> {
+    /// Creates a color from its luminance.
+    pub const fn new(l: T) -> Self {
+        Luma([l])
+    }
+}
+
+impl<T> Rgba<T> {
+    /// Creates a color from its red, green, blue and alpha channels.
+    pub const fn new(r: T, g: T, b: T, a: T) -> Self {
+        Rgba([r, g, b, a])
+    }
+}
+
+impl<T> LumaA<T> {
+    /// Creates a color from its luminance and alpha channels.
+    pub const fn new(l: T, a: T) -> Self {
+        LumaA([l, a])
+    }
+}
+
+// Moving the channels out of a generic color is not possible in a `const fn`, so these are
+// implemented for each type of channel.
+macro_rules! impl_with_alpha {
+    ($($t:ty),*) => {$(
+        impl Rgb<$t> {
+            /// Adds an alpha channel to this color.
+            pub const fn with_alpha(self, a: $t) -> Rgba<$t> {
+                Rgba([self.0[0], self.0[1], self.0[2], a])
+            }
+        }
+
+        impl Luma<$t> {
+            /// Adds an alpha channel to this color.
+            pub const fn with_alpha(self, a: $t) -> LumaA<$t> {
+                LumaA([self.0[0], a])
+            }
+        }
+    )*};
+}
+
+impl_with_alpha!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize, f32, f64);
+
 /// Convert from one pixel component type to another. For example, convert from `u8` to `f32` pixel values.
 pub trait FromPrimitive<Component> {
     /// Converts from any pixel component type to this type.
@@ -465,9 +536,69 @@ where
     }
 }
 
+/// Whether samples of `from` can be converted to `to` by `convert_samples`.
+///
+/// Decoders use this to produce a requested color type while copying out their rows, which is
+/// supported between the 8-bit gray and RGB types with and without alpha.
+pub(crate) fn can_convert_samples(from: ColorType, to: ColorType) -> bool {
+    let eight_bit = |color| {
+        matches!(
+            color,
+            ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8
+        )
+    };
+    eight_bit(from) && eight_bit(to)
+}
+
+/// Convert the pixels in `src` of color type `from` into the pixels in `dst` of color type `to`.
+///
+/// Panics if the conversion is not supported, see `can_convert_samples`.
+pub(crate) fn convert_samples(from: ColorType, to: ColorType, src: &[u8], dst: &mut [u8]) {
+    fn convert<S, T>(src: &[u8], dst: &mut [u8])
+    where
+        S: Pixel<Subpixel = u8>,
+        T: Pixel<Subpixel = u8> + FromColor<S>,
+    {
+        let src = src.chunks_exact(S::CHANNEL_COUNT as usize);
+        let dst = dst.chunks_exact_mut(T::CHANNEL_COUNT as usize);
+        for (s, t) in src.zip(dst) {
+            T::from_slice_mut(t).from_color(S::from_slice(s));
+        }
+    }
+
+    fn convert_from<S>(to: ColorType, src: &[u8], dst: &mut [u8])
+    where
+        S: Pixel<Subpixel = u8>,
+        Luma<u8>: FromColor<S>,
+        LumaA<u8>: FromColor<S>,
+        Rgb<u8>: FromColor<S>,
+        Rgba<u8>: FromColor<S>,
+    {
+        match to {
+            ColorType::L8 => convert::<S, Luma<u8>>(src, dst),
+            ColorType::La8 => convert::<S, LumaA<u8>>(src, dst),
+            ColorType::Rgb8 => convert::<S, Rgb<u8>>(src, dst),
+            ColorType::Rgba8 => convert::<S, Rgba<u8>>(src, dst),
+            _ => panic!("unsupported sample conversion to {:?}", to),
+        }
+    }
+
+    if from == to {
+        dst.copy_from_slice(src);
+        return;
+    }
+    match from {
+        ColorType::L8 => convert_from::<Luma<u8>>(to, src, dst),
+        ColorType::La8 => convert_from::<LumaA<u8>>(to, src, dst),
+        ColorType::Rgb8 => convert_from::<Rgb<u8>>(to, src, dst),
+        ColorType::Rgba8 => convert_from::<Rgba<u8>>(to, src, dst),
+        _ => panic!("unsupported sample conversion from {:?}", from),
+    }
+}
+
 /// Coefficients to transform from sRGB to a CIE Y (luminance) value.
-const SRGB_LUMA: [u32; 3] = [2126, 7152, 722];
-const SRGB_LUMA_DIV: u32 = 10000;
+pub(crate) const SRGB_LUMA: [u32; 3] = [2126, 7152, 722];
+pub(crate) const SRGB_LUMA_DIV: u32 = 10000;
 
 #[inline]
 fn rgb_to_luma<T: Primitive + Enlargeable>(rgb: &[T]) -> T {
@@ -841,7 +972,23 @@ impl<T: Primitive> Invert for Rgb<T> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Luma, LumaA, Pixel, Rgb, Rgba};
+    use super::{ColorType, Luma, LumaA, Pixel, Rgb, Rgba};
+
+    #[test]
+    fn convert_samples() {
+        let rgb = [255, 0, 0, 10, 20, 30];
+        let mut rgba = [0; 8];
+        super::convert_samples(ColorType::Rgb8, ColorType::Rgba8, &rgb, &mut rgba);
+        assert_eq!(rgba, [255, 0, 0, 255, 10, 20, 30, 255]);
+        let mut luma = [0; 2];
+        super::convert_samples(ColorType::Rgba8, ColorType::L8, &rgba, &mut luma);
+        assert_eq!(luma, [54, 18]);
+        assert!(super::can_convert_samples(ColorType::La8, ColorType::Rgb8));
+        assert!(!super::can_convert_samples(
+            ColorType::Rgb8,
+            ColorType::Rgb16
+        ));
+    }
 
     #[test]
     fn test_apply_with_alpha_rgba() {
@@ -974,4 +1121,54 @@ mod tests {
         let Luma([luma]) = pixel.to_luma();
         assert_eq!(luma, 13);
     }
+
+    #[test]
+    #[cfg(all(feature = "png", feature = "bmp", feature = "openexr"))]
+    fn color_type_support_from_codecs() {
+        use super::ColorType;
+        use crate::image::{Direction, ImageFormat};
+
+        assert!(ColorType::Rgba16.is_supported_by(ImageFormat::Png, Direction::Write));
+        assert!(ColorType::Rgba16.is_supported_by(ImageFormat::Png, Direction::Read));
+        assert!(!ColorType::L16.is_supported_by(ImageFormat::Bmp, Direction::Write));
+        assert_eq!(
+            ImageFormat::OpenExr.supported_color_types(Direction::Write),
+            vec![ColorType::Rgb32F, ColorType::Rgba32F]
+        );
+    }
+
+    #[test]
+    fn decoded_color_types_from_decoders() {
+        use super::ColorType;
+        use crate::image::{Direction, ImageFormat};
+
+        // The PNG entries of icons keep their color type.
+        #[cfg(feature = "ico")]
+        assert!(ColorType::L16.is_supported_by(ImageFormat::Ico, Direction::Read));
+        // Colors with alpha that the encoder writes as PAM, and PGM with 16-bit samples.
+        #[cfg(feature = "pnm")]
+        assert!(!ColorType::La8.is_supported_by(ImageFormat::Pnm, Direction::Read));
+        #[cfg(feature = "pnm")]
+        assert!(ColorType::L16.is_supported_by(ImageFormat::Pnm, Direction::Read));
+        #[cfg(feature = "farbfeld")]
+        assert_eq!(
+            ImageFormat::Farbfeld.supported_color_types(Direction::Read),
+            vec![ColorType::Rgba16]
+        );
+        #[cfg(feature = "dds")]
+        assert!(ColorType::Rgba8.is_supported_by(ImageFormat::Dds, Direction::Read));
+        #[cfg(not(feature = "gif"))]
+        assert!(ImageFormat::Gif
+            .supported_color_types(Direction::Read)
+            .is_empty());
+    }
+
+    #[test]
+    fn const_constructors() {
+        const BACKGROUND: Rgba<u8> = Rgb::new(10u8, 20, 30).with_alpha(255);
+        static SHADOW: LumaA<f32> = Luma::new(0.0f32).with_alpha(0.5);
+        assert_eq!(BACKGROUND, Rgba::new(10, 20, 30, 255));
+        assert_eq!(SHADOW, LumaA([0.0, 0.5]));
+        assert_eq!(Luma::new(7u16), Luma([7]));
+    }
 }
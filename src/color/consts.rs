@@ -0,0 +1,355 @@
+//! The named colors of CSS, as constants.
+//!
+//! The constants are plain values, so they can be used in `const` and `static` items, for
+//! example as the background of padding or the color of drawn shapes.
+//!
+//! ```
+//! use image::color::consts;
+//! use image::{Rgb, Rgba};
+//!
+//! static BACKGROUND: Rgba<u8> = consts::CORNFLOWER_BLUE.with_alpha(255);
+//!
+//! assert_eq!(consts::CORNFLOWER_BLUE, Rgb([100, 149, 237]));
+//! assert_eq!(consts::from_name("CornflowerBlue"), Some(consts::CORNFLOWER_BLUE));
+//! ```
+//!
+//! # Related Links
+//! * <https://www.w3.org/TR/css-color-4/#named-colors> - The named colors of CSS
+
+use super::Rgb;
+
+macro_rules! named_colors {
+    ($($(#[$doc:meta])* $ident:ident = $name:literal ($r:expr, $g:expr, $b:expr);)*) => {
+        $(
+            $(#[$doc])*
+            pub const $ident: Rgb<u8> = Rgb::new($r, $g, $b);
+        )*
+
+        /// All named colors with their names in CSS, in alphabetical order.
+        ///
+        /// Some colors have more than one name, such as `aqua` and `cyan`, or the spellings
+        /// `gray` and `grey`.
+        pub const ALL: &[(&str, Rgb<u8>)] = &[$(($name, $ident)),*];
+    };
+}
+
+named_colors! {
+    /// `#f0f8ff`
+    ALICE_BLUE = "aliceblue" (240, 248, 255);
+    /// `#faebd7`
+    ANTIQUE_WHITE = "antiquewhite" (250, 235, 215);
+    /// `#00ffff`
+    AQUA = "aqua" (0, 255, 255);
+    /// `#7fffd4`
+    AQUAMARINE = "aquamarine" (127, 255, 212);
+    /// `#f0ffff`
+    AZURE = "azure" (240, 255, 255);
+    /// `#f5f5dc`
+    BEIGE = "beige" (245, 245, 220);
+    /// `#ffe4c4`
+    BISQUE = "bisque" (255, 228, 196);
+    /// `#000000`
+    BLACK = "black" (0, 0, 0);
+    /// `#ffebcd`
+    BLANCHED_ALMOND = "blanchedalmond" (255, 235, 205);
+    /// `#0000ff`
+    BLUE = "blue" (0, 0, 255);
+    /// `#8a2be2`
+    BLUE_VIOLET = "blueviolet" (138, 43, 226);
+    /// `#a52a2a`
+    BROWN = "brown" (165, 42, 42);
+    /// `#deb887`
+    BURLY_WOOD = "burlywood" (222, 184, 135);
+    /// `#5f9ea0`
+    CADET_BLUE = "cadetblue" (95, 158, 160);
+    /// `#7fff00`
+    CHARTREUSE = "chartreuse" (127, 255, 0);
+    /// `#d2691e`
+    CHOCOLATE = "chocolate" (210, 105, 30);
+    /// `#ff7f50`
+    CORAL = "coral" (255, 127, 80);
+    /// `#6495ed`
+    CORNFLOWER_BLUE = "cornflowerblue" (100, 149, 237);
+    /// `#fff8dc`
+    CORNSILK = "cornsilk" (255, 248, 220);
+    /// `#dc143c`
+    CRIMSON = "crimson" (220, 20, 60);
+    /// `#00ffff`
+    CYAN = "cyan" (0, 255, 255);
+    /// `#00008b`
+    DARK_BLUE = "darkblue" (0, 0, 139);
+    /// `#008b8b`
+    DARK_CYAN = "darkcyan" (0, 139, 139);
+    /// `#b8860b`
+    DARK_GOLDENROD = "darkgoldenrod" (184, 134, 11);
+    /// `#a9a9a9`
+    DARK_GRAY = "darkgray" (169, 169, 169);
+    /// `#006400`
+    DARK_GREEN = "darkgreen" (0, 100, 0);
+    /// `#a9a9a9`
+    DARK_GREY = "darkgrey" (169, 169, 169);
+    /// `#bdb76b`
+    DARK_KHAKI = "darkkhaki" (189, 183, 107);
+    /// `#8b008b`
+    DARK_MAGENTA = "darkmagenta" (139, 0, 139);
+    /// `#556b2f`
+    DARK_OLIVE_GREEN = "darkolivegreen" (85, 107, 47);
+    /// `#ff8c00`
+    DARK_ORANGE = "darkorange" (255, 140, 0);
+    /// `#9932cc`
+    DARK_ORCHID = "darkorchid" (153, 50, 204);
+    /// `#8b0000`
+    DARK_RED = "darkred" (139, 0, 0);
+    /// `#e9967a`
+    DARK_SALMON = "darksalmon" (233, 150, 122);
+    /// `#8fbc8f`
+    DARK_SEA_GREEN = "darkseagreen" (143, 188, 143);
+    /// `#483d8b`
+    DARK_SLATE_BLUE = "darkslateblue" (72, 61, 139);
+    /// `#2f4f4f`
+    DARK_SLATE_GRAY = "darkslategray" (47, 79, 79);
+    /// `#2f4f4f`
+    DARK_SLATE_GREY = "darkslategrey" (47, 79, 79);
+    /// `#00ced1`
+    DARK_TURQUOISE = "darkturquoise" (0, 206, 209);
+    /// `#9400d3`
+    DARK_VIOLET = "darkviolet" (148, 0, 211);
+    /// `#ff1493`
+    DEEP_PINK = "deeppink" (255, 20, 147);
+    /// `#00bfff`
+    DEEP_SKY_BLUE = "deepskyblue" (0, 191, 255);
+    /// `#696969`
+    DIM_GRAY = "dimgray" (105, 105, 105);
+    /// `#696969`
+    DIM_GREY = "dimgrey" (105, 105, 105);
+    /// `#1e90ff`
+    DODGER_BLUE = "dodgerblue" (30, 144, 255);
+    /// `#b22222`
+    FIREBRICK = "firebrick" (178, 34, 34);
+    /// `#fffaf0`
+    FLORAL_WHITE = "floralwhite" (255, 250, 240);
+    /// `#228b22`
+    FOREST_GREEN = "forestgreen" (34, 139, 34);
+    /// `#ff00ff`
+    FUCHSIA = "fuchsia" (255, 0, 255);
+    /// `#dcdcdc`
+    GAINSBORO = "gainsboro" (220, 220, 220);
+    /// `#f8f8ff`
+    GHOST_WHITE = "ghostwhite" (248, 248, 255);
+    /// `#ffd700`
+    GOLD = "gold" (255, 215, 0);
+    /// `#daa520`
+    GOLDENROD = "goldenrod" (218, 165, 32);
+    /// `#808080`
+    GRAY = "gray" (128, 128, 128);
+    /// `#008000`
+    GREEN = "green" (0, 128, 0);
+    /// `#adff2f`
+    GREEN_YELLOW = "greenyellow" (173, 255, 47);
+    /// `#808080`
+    GREY = "grey" (128, 128, 128);
+    /// `#f0fff0`
+    HONEYDEW = "honeydew" (240, 255, 240);
+    /// `#ff69b4`
+    HOT_PINK = "hotpink" (255, 105, 180);
+    /// `#cd5c5c`
+    INDIAN_RED = "indianred" (205, 92, 92);
+    /// `#4b0082`
+    INDIGO = "indigo" (75, 0, 130);
+    /// `#fffff0`
+    IVORY = "ivory" (255, 255, 240);
+    /// `#f0e68c`
+    KHAKI = "khaki" (240, 230, 140);
+    /// `#e6e6fa`
+    LAVENDER = "lavender" (230, 230, 250);
+    /// `#fff0f5`
+    LAVENDER_BLUSH = "lavenderblush" (255, 240, 245);
+    /// `#7cfc00`
+    LAWN_GREEN = "lawngreen" (124, 252, 0);
+    /// `#fffacd`
+    LEMON_CHIFFON = "lemonchiffon" (255, 250, 205);
+    /// `#add8e6`
+    LIGHT_BLUE = "lightblue" (173, 216, 230);
+    /// `#f08080`
+    LIGHT_CORAL = "lightcoral" (240, 128, 128);
+    /// `#e0ffff`
+    LIGHT_CYAN = "lightcyan" (224, 255, 255);
+    /// `#fafad2`
+    LIGHT_GOLDENROD_YELLOW = "lightgoldenrodyellow" (250, 250, 210);
+    /// `#d3d3d3`
+    LIGHT_GRAY = "lightgray" (211, 211, 211);
+    /// `#90ee90`
+    LIGHT_GREEN = "lightgreen" (144, 238, 144);
+    /// `#d3d3d3`
+    LIGHT_GREY = "lightgrey" (211, 211, 211);
+    /// `#ffb6c1`
+    LIGHT_PINK = "lightpink" (255, 182, 193);
+    /// `#ffa07a`
+    LIGHT_SALMON = "lightsalmon" (255, 160, 122);
+    /// `#20b2aa`
+    LIGHT_SEA_GREEN = "lightseagreen" (32, 178, 170);
+    /// `#87cefa`
+    LIGHT_SKY_BLUE = "lightskyblue" (135, 206, 250);
+    /// `#778899`
+    LIGHT_SLATE_GRAY = "lightslategray" (119, 136, 153);
+    /// `#778899`
+    LIGHT_SLATE_GREY = "lightslategrey" (119, 136, 153);
+    /// `#b0c4de`
+    LIGHT_STEEL_BLUE = "lightsteelblue" (176, 196, 222);
+    /// `#ffffe0`
+    LIGHT_YELLOW = "lightyellow" (255, 255, 224);
+    /// `#00ff00`
+    LIME = "lime" (0, 255, 0);
+    /// `#32cd32`
+    LIME_GREEN = "limegreen" (50, 205, 50);
+    /// `#faf0e6`
+    LINEN = "linen" (250, 240, 230);
+    /// `#ff00ff`
+    MAGENTA = "magenta" (255, 0, 255);
+    /// `#800000`
+    MAROON = "maroon" (128, 0, 0);
+    /// `#66cdaa`
+    MEDIUM_AQUAMARINE = "mediumaquamarine" (102, 205, 170);
+    /// `#0000cd`
+    MEDIUM_BLUE = "mediumblue" (0, 0, 205);
+    /// `#ba55d3`
+    MEDIUM_ORCHID = "mediumorchid" (186, 85, 211);
+    /// `#9370db`
+    MEDIUM_PURPLE = "mediumpurple" (147, 112, 219);
+    /// `#3cb371`
+    MEDIUM_SEA_GREEN = "mediumseagreen" (60, 179, 113);
+    /// `#7b68ee`
+    MEDIUM_SLATE_BLUE = "mediumslateblue" (123, 104, 238);
+    /// `#00fa9a`
+    MEDIUM_SPRING_GREEN = "mediumspringgreen" (0, 250, 154);
+    /// `#48d1cc`
+    MEDIUM_TURQUOISE = "mediumturquoise" (72, 209, 204);
+    /// `#c71585`
+    MEDIUM_VIOLET_RED = "mediumvioletred" (199, 21, 133);
+    /// `#191970`
+    MIDNIGHT_BLUE = "midnightblue" (25, 25, 112);
+    /// `#f5fffa`
+    MINT_CREAM = "mintcream" (245, 255, 250);
+    /// `#ffe4e1`
+    MISTY_ROSE = "mistyrose" (255, 228, 225);
+    /// `#ffe4b5`
+    MOCCASIN = "moccasin" (255, 228, 181);
+    /// `#ffdead`
+    NAVAJO_WHITE = "navajowhite" (255, 222, 173);
+    /// `#000080`
+    NAVY = "navy" (0, 0, 128);
+    /// `#fdf5e6`
+    OLD_LACE = "oldlace" (253, 245, 230);
+    /// `#808000`
+    OLIVE = "olive" (128, 128, 0);
+    /// `#6b8e23`
+    OLIVE_DRAB = "olivedrab" (107, 142, 35);
+    /// `#ffa500`
+    ORANGE = "orange" (255, 165, 0);
+    /// `#ff4500`
+    ORANGE_RED = "orangered" (255, 69, 0);
+    /// `#da70d6`
+    ORCHID = "orchid" (218, 112, 214);
+    /// `#eee8aa`
+    PALE_GOLDENROD = "palegoldenrod" (238, 232, 170);
+    /// `#98fb98`
+    PALE_GREEN = "palegreen" (152, 251, 152);
+    /// `#afeeee`
+    PALE_TURQUOISE = "paleturquoise" (175, 238, 238);
+    /// `#db7093`
+    PALE_VIOLET_RED = "palevioletred" (219, 112, 147);
+    /// `#ffefd5`
+    PAPAYA_WHIP = "papayawhip" (255, 239, 213);
+    /// `#ffdab9`
+    PEACH_PUFF = "peachpuff" (255, 218, 185);
+    /// `#cd853f`
+    PERU = "peru" (205, 133, 63);
+    /// `#ffc0cb`
+    PINK = "pink" (255, 192, 203);
+    /// `#dda0dd`
+    PLUM = "plum" (221, 160, 221);
+    /// `#b0e0e6`
+    POWDER_BLUE = "powderblue" (176, 224, 230);
+    /// `#800080`
+    PURPLE = "purple" (128, 0, 128);
+    /// `#663399`
+    REBECCA_PURPLE = "rebeccapurple" (102, 51, 153);
+    /// `#ff0000`
+    RED = "red" (255, 0, 0);
+    /// `#bc8f8f`
+    ROSY_BROWN = "rosybrown" (188, 143, 143);
+    /// `#4169e1`
+    ROYAL_BLUE = "royalblue" (65, 105, 225);
+    /// `#8b4513`
+    SADDLE_BROWN = "saddlebrown" (139, 69, 19);
+    /// `#fa8072`
+    SALMON = "salmon" (250, 128, 114);
+    /// `#f4a460`
+    SANDY_BROWN = "sandybrown" (244, 164, 96);
+    /// `#2e8b57`
+    SEA_GREEN = "seagreen" (46, 139, 87);
+    /// `#fff5ee`
+    SEASHELL = "seashell" (255, 245, 238);
+    /// `#a0522d`
+    SIENNA = "sienna" (160, 82, 45);
+    /// `#c0c0c0`
+    SILVER = "silver" (192, 192, 192);
+    /// `#87ceeb`
+    SKY_BLUE = "skyblue" (135, 206, 235);
+    /// `#6a5acd`
+    SLATE_BLUE = "slateblue" (106, 90, 205);
+    /// `#708090`
+    SLATE_GRAY = "slategray" (112, 128, 144);
+    /// `#708090`
+    SLATE_GREY = "slategrey" (112, 128, 144);
+    /// `#fffafa`
+    SNOW = "snow" (255, 250, 250);
+    /// `#00ff7f`
+    SPRING_GREEN = "springgreen" (0, 255, 127);
+    /// `#4682b4`
+    STEEL_BLUE = "steelblue" (70, 130, 180);
+    /// `#d2b48c`
+    TAN = "tan" (210, 180, 140);
+    /// `#008080`
+    TEAL = "teal" (0, 128, 128);
+    /// `#d8bfd8`
+    THISTLE = "thistle" (216, 191, 216);
+    /// `#ff6347`
+    TOMATO = "tomato" (255, 99, 71);
+    /// `#40e0d0`
+    TURQUOISE = "turquoise" (64, 224, 208);
+    /// `#ee82ee`
+    VIOLET = "violet" (238, 130, 238);
+    /// `#f5deb3`
+    WHEAT = "wheat" (245, 222, 179);
+    /// `#ffffff`
+    WHITE = "white" (255, 255, 255);
+    /// `#f5f5f5`
+    WHITE_SMOKE = "whitesmoke" (245, 245, 245);
+    /// `#ffff00`
+    YELLOW = "yellow" (255, 255, 0);
+    /// `#9acd32`
+    YELLOW_GREEN = "yellowgreen" (154, 205, 50);
+}
+
+/// Looks up a color by its name in CSS, ignoring ASCII case.
+pub fn from_name(name: &str) -> Option<Rgb<u8>> {
+    ALL.iter()
+        .find(|(candidate, _)| candidate.eq_ignore_ascii_case(name))
+        .map(|&(_, color)| color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        assert_eq!(ALL.len(), 148);
+        assert!(ALL.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        assert_eq!(from_name("rebeccapurple"), Some(Rgb([0x66, 0x33, 0x99])));
+        assert_eq!(from_name("Grey"), from_name("gray"));
+        assert_eq!(from_name("AQUA"), Some(CYAN));
+        assert_eq!(from_name("transparent"), None);
+    }
+}
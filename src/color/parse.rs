@@ -0,0 +1,319 @@
+//! Parsing colors from the strings users write them as.
+use std::error::Error;
+use std::fmt;
+
+use super::{Rgb, Rgba};
+
+/// An error that occurred while parsing a color from a string.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseColorError {
+    kind: ParseColorErrorKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ParseColorErrorKind {
+    /// The string is not a hexadecimal color of a supported length.
+    Hex,
+    /// The string is neither a hexadecimal color, a color function nor a name.
+    #[cfg_attr(not(feature = "css"), allow(dead_code))]
+    Notation,
+    /// The string is not the name of a color.
+    #[cfg_attr(not(feature = "css"), allow(dead_code))]
+    UnknownName,
+}
+
+impl ParseColorError {
+    fn new(kind: ParseColorErrorKind) -> Self {
+        ParseColorError { kind }
+    }
+}
+
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ParseColorErrorKind::Hex => {
+                f.write_str("Expected a hexadecimal color with 3, 4, 6 or 8 digits")
+            }
+            ParseColorErrorKind::Notation => f.write_str("Invalid color notation"),
+            ParseColorErrorKind::UnknownName => f.write_str("Unknown color name"),
+        }
+    }
+}
+
+impl Error for ParseColorError {}
+
+/// Parses the digits of `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa`, with an optional `#`.
+fn parse_hex(hex: &str) -> Result<Rgba<u8>, ParseColorError> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex).as_bytes();
+    if !digits.iter().all(u8::is_ascii_hexdigit) {
+        return Err(ParseColorError::new(ParseColorErrorKind::Hex));
+    }
+    let digit = |i: usize| (digits[i] as char).to_digit(16).unwrap() as u8;
+
+    let mut channels = [255; 4];
+    match digits.len() {
+        3 | 4 => {
+            for (i, channel) in channels.iter_mut().take(digits.len()).enumerate() {
+                *channel = digit(i) * 0x11;
+            }
+        }
+        6 | 8 => {
+            for (i, channel) in channels.iter_mut().take(digits.len() / 2).enumerate() {
+                *channel = digit(2 * i) << 4 | digit(2 * i + 1);
+            }
+        }
+        _ => return Err(ParseColorError::new(ParseColorErrorKind::Hex)),
+    }
+    Ok(Rgba(channels))
+}
+
+impl Rgb<u8> {
+    /// Parses a color in hexadecimal notation, `#rrggbb` or the short form `#rgb`.
+    ///
+    /// The leading `#` is optional and the digits are case-insensitive.
+    ///
+    /// ```
+    /// use image::Rgb;
+    ///
+    /// assert_eq!(Rgb::from_hex("#ff8000"), Ok(Rgb([255, 128, 0])));
+    /// assert_eq!(Rgb::from_hex("f80"), Ok(Rgb([255, 136, 0])));
+    /// assert!(Rgb::from_hex("#ff800080").is_err());
+    /// ```
+    pub fn from_hex(hex: &str) -> Result<Self, ParseColorError> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        match digits.len() {
+            3 | 6 => {
+                let Rgba([r, g, b, _]) = parse_hex(digits)?;
+                Ok(Rgb([r, g, b]))
+            }
+            _ => Err(ParseColorError::new(ParseColorErrorKind::Hex)),
+        }
+    }
+}
+
+impl Rgba<u8> {
+    /// Parses a color in hexadecimal notation, `#rrggbbaa` or the short form `#rgba`.
+    ///
+    /// The leading `#` is optional and the digits are case-insensitive. Colors without an alpha
+    /// channel, `#rrggbb` and `#rgb`, are opaque.
+    ///
+    /// ```
+    /// use image::Rgba;
+    ///
+    /// assert_eq!(Rgba::from_hex("#ff800080"), Ok(Rgba([255, 128, 0, 128])));
+    /// assert_eq!(Rgba::from_hex("#F80"), Ok(Rgba([255, 136, 0, 255])));
+    /// ```
+    pub fn from_hex(hex: &str) -> Result<Self, ParseColorError> {
+        parse_hex(hex)
+    }
+}
+
+#[cfg(feature = "css")]
+mod css {
+    use std::str::FromStr;
+
+    use super::{parse_hex, ParseColorError, ParseColorErrorKind};
+    use crate::color::{consts, Rgba};
+
+    impl Rgba<u8> {
+        /// Parses a color in any of the notations of CSS.
+        ///
+        /// Supported are hexadecimal colors, the named colors and `transparent`, as well as the
+        /// functions `rgb()`, `rgba()`, `hsl()` and `hsla()` with either comma or space separated
+        /// arguments. Channels outside of their range are clamped, like browsers do.
+        ///
+        /// ```
+        /// use image::Rgba;
+        ///
+        /// assert_eq!(Rgba::from_css("rebeccapurple"), Ok(Rgba([102, 51, 153, 255])));
+        /// assert_eq!(Rgba::from_css("rgb(255 128 0 / 50%)"), Ok(Rgba([255, 128, 0, 128])));
+        /// assert_eq!(Rgba::from_css("hsla(120, 100%, 25%, 1)"), Ok(Rgba([0, 128, 0, 255])));
+        /// ```
+        pub fn from_css(css: &str) -> Result<Self, ParseColorError> {
+            let css = css.trim();
+            if css.starts_with('#') {
+                return parse_hex(css);
+            }
+            if let Some(open) = css.find('(') {
+                let name = css[..open].trim_end().to_ascii_lowercase();
+                let args = css[open + 1..]
+                    .strip_suffix(')')
+                    .ok_or_else(|| ParseColorError::new(ParseColorErrorKind::Notation))?;
+                return parse_function(&name, args)
+                    .ok_or_else(|| ParseColorError::new(ParseColorErrorKind::Notation));
+            }
+            if css.eq_ignore_ascii_case("transparent") {
+                return Ok(Rgba([0, 0, 0, 0]));
+            }
+            consts::from_name(css)
+                .map(|color| color.with_alpha(255))
+                .ok_or_else(|| ParseColorError::new(ParseColorErrorKind::UnknownName))
+        }
+    }
+
+    /// Parses a color in any of the notations of CSS, see [`Rgba::from_css`].
+    ///
+    /// [`Rgba::from_css`]: struct.Rgba.html#method.from_css
+    impl FromStr for Rgba<u8> {
+        type Err = ParseColorError;
+
+        fn from_str(s: &str) -> Result<Self, ParseColorError> {
+            Rgba::from_css(s)
+        }
+    }
+
+    /// Parses the arguments of a color function.
+    fn parse_function(name: &str, args: &str) -> Option<Rgba<u8>> {
+        // Either the legacy `r, g, b, a` or the modern `r g b / a` syntax.
+        let mut values: Vec<&str> = if args.contains(',') {
+            args.split(',').map(str::trim).collect()
+        } else {
+            let mut parts = args.splitn(2, '/');
+            let mut values: Vec<&str> = parts.next()?.split_whitespace().collect();
+            if let Some(alpha) = parts.next() {
+                values.push(alpha.trim());
+            }
+            values
+        };
+        if values.len() == 3 {
+            values.push("1");
+        }
+        if values.len() != 4 {
+            return None;
+        }
+
+        let alpha = match parse_number(values[3])? {
+            Number::Percentage(alpha) => alpha / 100.0,
+            Number::Plain(alpha) => alpha,
+        };
+        let rgb = match name {
+            "rgb" | "rgba" => {
+                let mut rgb = [0.0; 3];
+                for (channel, value) in rgb.iter_mut().zip(&values) {
+                    *channel = match parse_number(value)? {
+                        Number::Percentage(value) => value / 100.0,
+                        Number::Plain(value) => value / 255.0,
+                    };
+                }
+                rgb
+            }
+            "hsl" | "hsla" => {
+                let hue = parse_hue(values[0])?;
+                let mut sl = [0.0; 2];
+                for (channel, value) in sl.iter_mut().zip(&values[1..3]) {
+                    *channel = match parse_number(value)? {
+                        Number::Percentage(value) | Number::Plain(value) => value / 100.0,
+                    };
+                }
+                hsl_to_rgb(hue, sl[0], sl[1])
+            }
+            _ => return None,
+        };
+
+        let quantize = |value: f32| (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+        Some(Rgba([
+            quantize(rgb[0]),
+            quantize(rgb[1]),
+            quantize(rgb[2]),
+            quantize(alpha),
+        ]))
+    }
+
+    enum Number {
+        Plain(f32),
+        Percentage(f32),
+    }
+
+    fn parse_number(value: &str) -> Option<Number> {
+        let (value, percentage) = match value.strip_suffix('%') {
+            Some(value) => (value, true),
+            None => (value, false),
+        };
+        let value: f32 = value.parse().ok().filter(|value: &f32| value.is_finite())?;
+        Some(if percentage {
+            Number::Percentage(value)
+        } else {
+            Number::Plain(value)
+        })
+    }
+
+    /// Parses a hue, in degrees unless another unit of angle is given.
+    fn parse_hue(value: &str) -> Option<f32> {
+        let value = value.to_ascii_lowercase();
+        let units = [
+            ("deg", 1.0),
+            ("grad", 0.9),
+            ("rad", 180.0 / std::f32::consts::PI),
+            ("turn", 360.0),
+        ];
+        let (number, scale) = units
+            .iter()
+            .find_map(|&(unit, scale)| value.strip_suffix(unit).map(|number| (number, scale)))
+            .unwrap_or((value.as_str(), 1.0));
+        let number: f32 = number
+            .parse()
+            .ok()
+            .filter(|number: &f32| number.is_finite())?;
+        Some(number * scale)
+    }
+
+    /// Converts a hue in degrees and saturation and lightness in `[0, 1]` to RGB in `[0, 1]`.
+    ///
+    /// This is the algorithm of the CSS specification.
+    fn hsl_to_rgb(hue: f32, saturation: f32, lightness: f32) -> [f32; 3] {
+        let hue = hue.rem_euclid(360.0);
+        let saturation = saturation.clamp(0.0, 1.0);
+        let lightness = lightness.clamp(0.0, 1.0);
+        let a = saturation * lightness.min(1.0 - lightness);
+        let f = |n: f32| {
+            let k = (n + hue / 30.0) % 12.0;
+            lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0)
+        };
+        [f(0.0), f(8.0), f(4.0)]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex() {
+        assert_eq!(
+            Rgba::from_hex("#12345678"),
+            Ok(Rgba([0x12, 0x34, 0x56, 0x78]))
+        );
+        assert_eq!(Rgba::from_hex("aBcD"), Ok(Rgba([0xaa, 0xbb, 0xcc, 0xdd])));
+        assert_eq!(Rgb::from_hex("#000"), Ok(Rgb([0, 0, 0])));
+        for invalid in &["", "#", "#12345", "#1234567g", "+12", "#ééé", "##123"] {
+            assert!(Rgba::from_hex(invalid).is_err(), "{:?}", invalid);
+        }
+        assert!(Rgb::from_hex("#1234").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "css")]
+    fn css() {
+        let parse = |css: &str| css.parse::<Rgba<u8>>();
+        assert_eq!(parse(" #0f0 "), Ok(Rgba([0, 255, 0, 255])));
+        assert_eq!(parse("Transparent"), Ok(Rgba([0, 0, 0, 0])));
+        assert_eq!(parse("navy"), Ok(Rgba([0, 0, 128, 255])));
+        assert_eq!(parse("rgb(300, -5, 50%)"), Ok(Rgba([255, 0, 128, 255])));
+        assert_eq!(parse("RGBA(1, 2, 3, 0.5)"), Ok(Rgba([1, 2, 3, 128])));
+        assert_eq!(parse("rgb(1 2 3/20%)"), Ok(Rgba([1, 2, 3, 51])));
+        assert_eq!(parse("hsl(0, 100%, 50%)"), Ok(Rgba([255, 0, 0, 255])));
+        assert_eq!(parse("hsl(0.5turn 100% 50%)"), Ok(Rgba([0, 255, 255, 255])));
+        assert_eq!(parse("hsl(-120deg 100% 50%)"), Ok(Rgba([0, 0, 255, 255])));
+        assert_eq!(parse("hsl(0 0% 100%)"), Ok(Rgba([255, 255, 255, 255])));
+        for invalid in &[
+            "",
+            "notacolor",
+            "rgb(1, 2)",
+            "rgb(1 2 3",
+            "cmyk(1 2 3)",
+            "rgb(a b c)",
+        ] {
+            assert!(parse(invalid).is_err(), "{:?}", invalid);
+        }
+    }
+}
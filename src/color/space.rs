@@ -0,0 +1,549 @@
+//! Conversion of colors between color spaces.
+//!
+//! The samples of an image buffer do not carry their color space, most images are stored and
+//! displayed as sRGB. Wide gamut and HDR images use other spaces, such as Display P3 or linear
+//! RGB, and must be converted before they are processed or shown together with sRGB images.
+//! [`Conversion`] converts single colors, and [`DynamicImage::convert_color_space`] whole images.
+//!
+//! Colors are converted through CIE XYZ, relative to the D50 white point that ICC profiles use.
+//! The transfer functions of the RGB spaces are inverted first, so the conversion works on
+//! light intensities rather than on the encoded values.
+//!
+//! ```no_run
+//! use image::color::space::ColorSpace;
+//! use image::io::Reader;
+//!
+//! // Convert a photo to sRGB, according to the profile it embeds.
+//! let (mut image, icc_profile) = Reader::open("photo.jpg")?.decode_with_icc_profile()?;
+//! let space = icc_profile
+//!     .as_deref()
+//!     .and_then(ColorSpace::from_icc_profile)
+//!     .unwrap_or(ColorSpace::Srgb);
+//! image.convert_color_space(space, ColorSpace::Srgb);
+//! # Ok::<(), image::ImageError>(())
+//! ```
+//!
+//! [`Conversion`]: struct.Conversion.html
+//! [`DynamicImage::convert_color_space`]: ../../enum.DynamicImage.html#method.convert_color_space
+//!
+//! # Related Links
+//! * <https://www.color.org/specification/ICC.1-2022-05.pdf> - The ICC profile format
+//! * <http://www.brucelindbloom.com/index.html?Math.html> - Color space formulas
+
+use std::convert::TryFrom;
+
+use crate::buffer_::ImageBuffer;
+use crate::color::transfer::{linear_to_srgb, srgb_to_linear};
+use crate::traits::{Pixel, Primitive};
+
+/// The color spaces that images can be converted between.
+///
+/// The RGB spaces encode their channels from 0 to 1, values outside of this range are out of
+/// gamut. In integer images the range spans all values of the sample type.
+///
+/// Lab colors have a lightness `L` from 0 to 100 and the opponent axes `a` and `b`, which range
+/// from about -128 to 127. Floating point images store them as they are, integer images store
+/// `L` scaled to the whole range of the sample type and `a + 128` and `b + 128` scaled from 0 to
+/// 255 to it, as the 8-bit encoding of ICC profiles does.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ColorSpace {
+    /// The sRGB space of the web and most displays.
+    Srgb,
+    /// The primaries of DCI-P3 with the white point and transfer function of sRGB, which wide
+    /// gamut displays use.
+    DisplayP3,
+    /// Adobe RGB (1998), which covers more greens than sRGB.
+    AdobeRgb,
+    /// The primaries of sRGB without its transfer function, where values are proportional to
+    /// light intensity.
+    LinearSrgb,
+    /// The CIE 1976 L\*a\*b\* space relative to the D50 white point.
+    Lab,
+}
+
+/// The sRGB primaries, as the matrix from linear RGB to XYZ relative to D65.
+const SRGB_PRIMARIES: [[f64; 3]; 3] = [
+    [0.412_456_4, 0.357_576_1, 0.180_437_5],
+    [0.212_672_9, 0.715_152_2, 0.072_175_0],
+    [0.019_333_9, 0.119_192_0, 0.950_304_1],
+];
+/// The Display P3 primaries, as the matrix from linear RGB to XYZ relative to D65.
+const DISPLAY_P3_PRIMARIES: [[f64; 3]; 3] = [
+    [0.486_570_9, 0.265_667_7, 0.198_217_3],
+    [0.228_974_6, 0.691_738_5, 0.079_286_9],
+    [0.0, 0.045_113_4, 1.043_944_4],
+];
+/// The Adobe RGB primaries, as the matrix from linear RGB to XYZ relative to D65.
+const ADOBE_RGB_PRIMARIES: [[f64; 3]; 3] = [
+    [0.576_730_9, 0.185_554_0, 0.188_185_2],
+    [0.297_376_9, 0.627_349_1, 0.075_274_1],
+    [0.027_034_3, 0.070_687_2, 0.991_108_5],
+];
+/// The Bradford adaptation of XYZ colors from the D65 to the D50 white point.
+const D65_TO_D50: [[f64; 3]; 3] = [
+    [1.047_811_2, 0.022_886_6, -0.050_127_0],
+    [0.029_542_4, 0.990_484_4, -0.017_049_1],
+    [-0.009_234_5, 0.015_043_6, 0.752_131_6],
+];
+/// The D50 white point in XYZ.
+const D50: [f64; 3] = [0.964_22, 1.0, 0.825_21];
+/// The exponent of the transfer function of Adobe RGB.
+const ADOBE_RGB_GAMMA: f64 = 563.0 / 256.0;
+
+impl ColorSpace {
+    /// Recognize the color space of an ICC profile.
+    ///
+    /// The colorants and tone curves of RGB profiles are compared to those of the supported
+    /// spaces, so that profiles of the same space by different vendors are recognized. Returns
+    /// `None` for profiles of other spaces and for malformed profiles.
+    pub fn from_icc_profile(profile: &[u8]) -> Option<ColorSpace> {
+        if profile.get(16..20)? != b"RGB " {
+            return None;
+        }
+        let colorants = [
+            icc_xyz(icc_tag(profile, b"rXYZ")?)?,
+            icc_xyz(icc_tag(profile, b"gXYZ")?)?,
+            icc_xyz(icc_tag(profile, b"bXYZ")?)?,
+        ];
+        let curve = icc_curve(icc_tag(profile, b"rTRC")?)?;
+        for &(space, primaries) in &[
+            (ColorSpace::Srgb, SRGB_PRIMARIES),
+            (ColorSpace::DisplayP3, DISPLAY_P3_PRIMARIES),
+            (ColorSpace::AdobeRgb, ADOBE_RGB_PRIMARIES),
+        ] {
+            let adapted = multiply(&D65_TO_D50, &primaries);
+            let matches =
+                (0..3).all(|c| (0..3).all(|i| (adapted[i][c] - colorants[c][i]).abs() < 2e-3));
+            if !matches {
+                continue;
+            }
+            return match (space, curve) {
+                (ColorSpace::Srgb, Curve::Gamma(gamma)) if (gamma - 1.0).abs() < 1e-2 => {
+                    Some(ColorSpace::LinearSrgb)
+                }
+                (ColorSpace::AdobeRgb, Curve::Gamma(gamma))
+                    if (gamma - ADOBE_RGB_GAMMA).abs() < 1e-2 =>
+                {
+                    Some(space)
+                }
+                (ColorSpace::Srgb, Curve::Srgb) | (ColorSpace::DisplayP3, Curve::Srgb) => {
+                    Some(space)
+                }
+                _ => None,
+            };
+        }
+        None
+    }
+
+    /// The matrix from the linear values of the space to XYZ relative to D50.
+    fn to_xyz(self) -> [[f64; 3]; 3] {
+        let primaries = match self {
+            ColorSpace::Srgb | ColorSpace::LinearSrgb => SRGB_PRIMARIES,
+            ColorSpace::DisplayP3 => DISPLAY_P3_PRIMARIES,
+            ColorSpace::AdobeRgb => ADOBE_RGB_PRIMARIES,
+            ColorSpace::Lab => return IDENTITY,
+        };
+        multiply(&D65_TO_D50, &primaries)
+    }
+
+    /// Convert an encoded color to linear values, or Lab to XYZ.
+    fn decode(self, color: [f64; 3]) -> [f64; 3] {
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => map(color, srgb_to_linear),
+            ColorSpace::AdobeRgb => map(color, |v| v.signum() * v.abs().powf(ADOBE_RGB_GAMMA)),
+            ColorSpace::LinearSrgb => color,
+            ColorSpace::Lab => lab_to_xyz(color),
+        }
+    }
+
+    /// Encode linear values, or XYZ as Lab.
+    fn encode(self, color: [f64; 3]) -> [f64; 3] {
+        match self {
+            ColorSpace::Srgb | ColorSpace::DisplayP3 => map(color, linear_to_srgb),
+            ColorSpace::AdobeRgb => {
+                map(color, |v| v.signum() * v.abs().powf(1.0 / ADOBE_RGB_GAMMA))
+            }
+            ColorSpace::LinearSrgb => color,
+            ColorSpace::Lab => xyz_to_lab(color),
+        }
+    }
+}
+
+/// The conversion of colors from one color space to another.
+///
+/// The conversion is computed once, so that it can be applied to many colors.
+///
+/// ```
+/// use image::color::space::{ColorSpace, Conversion};
+///
+/// let conversion = Conversion::new(ColorSpace::Srgb, ColorSpace::DisplayP3);
+/// let [r, g, b] = conversion.apply([1.0, 0.0, 0.0]);
+/// // The red of sRGB is inside of the gamut of Display P3, so it is less saturated there.
+/// assert!(r < 1.0 && g > 0.0 && b > 0.0);
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Conversion {
+    from: ColorSpace,
+    to: ColorSpace,
+    /// The matrix from the linear values of `from` to those of `to`.
+    matrix: [[f64; 3]; 3],
+}
+
+impl Conversion {
+    /// Create the conversion of colors in the space `from` to the space `to`.
+    pub fn new(from: ColorSpace, to: ColorSpace) -> Conversion {
+        let matrix = multiply(&invert(&to.to_xyz()), &from.to_xyz());
+        Conversion { from, to, matrix }
+    }
+
+    /// The color space that colors are converted from.
+    pub fn from(&self) -> ColorSpace {
+        self.from
+    }
+
+    /// The color space that colors are converted to.
+    pub fn to(&self) -> ColorSpace {
+        self.to
+    }
+
+    /// Convert a color.
+    ///
+    /// Colors that are out of the gamut of the target space are not clipped, so that the
+    /// conversion of floating point colors can be reverted.
+    pub fn apply(&self, color: [f32; 3]) -> [f32; 3] {
+        let [a, b, c] = self.apply_f64([
+            f64::from(color[0]),
+            f64::from(color[1]),
+            f64::from(color[2]),
+        ]);
+        [a as f32, b as f32, c as f32]
+    }
+
+    fn apply_f64(&self, color: [f64; 3]) -> [f64; 3] {
+        if self.from == self.to {
+            return color;
+        }
+        let linear = self.from.decode(color);
+        let m = &self.matrix;
+        let converted = [
+            m[0][0] * linear[0] + m[0][1] * linear[1] + m[0][2] * linear[2],
+            m[1][0] * linear[0] + m[1][1] * linear[1] + m[1][2] * linear[2],
+            m[2][0] * linear[0] + m[2][1] * linear[1] + m[2][2] * linear[2],
+        ];
+        self.to.encode(converted)
+    }
+}
+
+/// Convert the colors of all pixels of an image in place, leaving their alpha as it is.
+///
+/// Gray pixels are converted as neutral colors, which remain neutral as all supported spaces
+/// share their white point. Their value is the lightness in Lab, and otherwise the mean of the
+/// converted channels.
+pub(crate) fn convert_pixels<P>(
+    image: &mut ImageBuffer<P, Vec<P::Subpixel>>,
+    conversion: &Conversion,
+) where
+    P: Pixel + 'static,
+{
+    if conversion.from == conversion.to {
+        return;
+    }
+    let max = num_traits::cast::<_, f64>(P::Subpixel::DEFAULT_MAX_VALUE).unwrap();
+    // Floating point samples store the values of the spaces as they are.
+    let float = max == 1.0;
+    let unpack = |space: ColorSpace, sample: P::Subpixel, channel: usize| {
+        let value = num_traits::cast::<_, f64>(sample).unwrap();
+        match (space, float) {
+            (_, true) => value,
+            (ColorSpace::Lab, false) if channel == 0 => value / max * 100.0,
+            (ColorSpace::Lab, false) => value / max * 255.0 - 128.0,
+            (_, false) => value / max,
+        }
+    };
+    let pack = |space: ColorSpace, value: f64, channel: usize| -> P::Subpixel {
+        let value = match (space, float) {
+            (_, true) => return num_traits::cast(value).unwrap(),
+            (ColorSpace::Lab, false) if channel == 0 => value / 100.0,
+            (ColorSpace::Lab, false) => (value + 128.0) / 255.0,
+            (_, false) => value,
+        };
+        num_traits::cast((value.clamp(0.0, 1.0) * max).round()).unwrap()
+    };
+
+    let (from, to) = (conversion.from, conversion.to);
+    for pixel in image.pixels_mut() {
+        let channels = pixel.channels_mut();
+        if channels.len() >= 3 {
+            let color = [
+                unpack(from, channels[0], 0),
+                unpack(from, channels[1], 1),
+                unpack(from, channels[2], 2),
+            ];
+            let converted = conversion.apply_f64(color);
+            for (channel, value) in converted.iter().enumerate() {
+                channels[channel] = pack(to, *value, channel);
+            }
+        } else {
+            let value = unpack(from, channels[0], 0);
+            let color = match from {
+                ColorSpace::Lab => [value, 0.0, 0.0],
+                _ => [value; 3],
+            };
+            let converted = conversion.apply_f64(color);
+            let value = match to {
+                ColorSpace::Lab => converted[0],
+                _ => converted.iter().sum::<f64>() / 3.0,
+            };
+            channels[0] = pack(to, value, 0);
+        }
+    }
+}
+
+/// The kinds of tone curves of ICC profiles that the supported spaces use.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Curve {
+    Gamma(f64),
+    Srgb,
+}
+
+/// Returns the data of the tag `signature` of an ICC profile.
+fn icc_tag<'a>(profile: &'a [u8], signature: &[u8; 4]) -> Option<&'a [u8]> {
+    let count = usize::try_from(read_u32(profile, 128)?).ok()?;
+    (0..count).find_map(|index| {
+        let entry = profile.get(132 + 12 * index..144 + 12 * index)?;
+        if &entry[..4] != signature {
+            return None;
+        }
+        let offset = usize::try_from(read_u32(entry, 4)?).ok()?;
+        let size = usize::try_from(read_u32(entry, 8)?).ok()?;
+        profile.get(offset..offset.checked_add(size)?)
+    })
+}
+
+/// Reads an `XYZ ` tag.
+fn icc_xyz(tag: &[u8]) -> Option<[f64; 3]> {
+    if tag.get(..4)? != b"XYZ " {
+        return None;
+    }
+    let fixed = |offset| Some(f64::from(read_u32(tag, offset)? as i32) / 65536.0);
+    Some([fixed(8)?, fixed(12)?, fixed(16)?])
+}
+
+/// Reads a `curv` or `para` tag, and recognizes the curve of sRGB or a pure gamma curve.
+fn icc_curve(tag: &[u8]) -> Option<Curve> {
+    match tag.get(..4)? {
+        b"curv" => {
+            let count = usize::try_from(read_u32(tag, 8)?).ok()?;
+            let entry = |index: usize| {
+                let bytes = tag.get(12 + 2 * index..14 + 2 * index)?;
+                Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+            };
+            match count {
+                0 => Some(Curve::Gamma(1.0)),
+                1 => Some(Curve::Gamma(f64::from(entry(0)?) / 256.0)),
+                _ => {
+                    // Compare a sampled curve to both curves at about a quarter of its range.
+                    let index = (count - 1) / 4;
+                    let x = index as f64 / (count - 1) as f64;
+                    let y = f64::from(entry(index)?) / 65535.0;
+                    if (y - srgb_to_linear(x)).abs() < 5e-3 {
+                        Some(Curve::Srgb)
+                    } else if (y - x).abs() < 5e-3 {
+                        Some(Curve::Gamma(1.0))
+                    } else {
+                        Some(Curve::Gamma(y.ln() / x.ln()))
+                    }
+                }
+            }
+        }
+        b"para" => {
+            let function = u16::from_be_bytes([*tag.get(8)?, *tag.get(9)?]);
+            let param =
+                |index: usize| Some(f64::from(read_u32(tag, 12 + 4 * index)? as i32) / 65536.0);
+            let gamma = param(0)?;
+            match function {
+                0 => Some(Curve::Gamma(gamma)),
+                3 | 4 if (gamma - 2.4).abs() < 1e-2 && (param(1)? - 1.0 / 1.055).abs() < 1e-3 => {
+                    Some(Curve::Srgb)
+                }
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// The constants of CIE Lab, as exact fractions.
+const EPSILON: f64 = 216.0 / 24389.0;
+const KAPPA: f64 = 24389.0 / 27.0;
+
+fn xyz_to_lab(xyz: [f64; 3]) -> [f64; 3] {
+    let f = |value: f64| {
+        if value > EPSILON {
+            value.cbrt()
+        } else {
+            (KAPPA * value + 16.0) / 116.0
+        }
+    };
+    let [x, y, z] = [f(xyz[0] / D50[0]), f(xyz[1] / D50[1]), f(xyz[2] / D50[2])];
+    [116.0 * y - 16.0, 500.0 * (x - y), 200.0 * (y - z)]
+}
+
+fn lab_to_xyz(lab: [f64; 3]) -> [f64; 3] {
+    let y = (lab[0] + 16.0) / 116.0;
+    let x = lab[1] / 500.0 + y;
+    let z = y - lab[2] / 200.0;
+    let f = |value: f64| {
+        let cube = value * value * value;
+        if cube > EPSILON {
+            cube
+        } else {
+            (116.0 * value - 16.0) / KAPPA
+        }
+    };
+    [f(x) * D50[0], f(y) * D50[1], f(z) * D50[2]]
+}
+
+const IDENTITY: [[f64; 3]; 3] = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+
+fn map(color: [f64; 3], f: impl Fn(f64) -> f64) -> [f64; 3] {
+    [f(color[0]), f(color[1]), f(color[2])]
+}
+
+fn multiply(a: &[[f64; 3]; 3], b: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let mut product = [[0.0; 3]; 3];
+    for (i, row) in product.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    product
+}
+
+fn invert(m: &[[f64; 3]; 3]) -> [[f64; 3]; 3] {
+    let cofactor = |i: usize, j: usize| {
+        let (r0, r1) = ((i + 1) % 3, (i + 2) % 3);
+        let (c0, c1) = ((j + 1) % 3, (j + 2) % 3);
+        m[r0][c0] * m[r1][c1] - m[r0][c1] * m[r1][c0]
+    };
+    let determinant: f64 = (0..3).map(|j| m[0][j] * cofactor(0, j)).sum();
+    let mut inverse = [[0.0; 3]; 3];
+    for (i, row) in inverse.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            // The inverse is the transposed matrix of cofactors over the determinant.
+            *value = cofactor(j, i) / determinant;
+        }
+    }
+    inverse
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DynamicImage;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3], tolerance: f32) {
+        for (a, e) in actual.iter().zip(&expected) {
+            assert!((a - e).abs() < tolerance, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn conversions() {
+        let convert = |from, to, color| Conversion::new(from, to).apply(color);
+        let red = [1.0, 0.0, 0.0];
+        assert_close(
+            convert(ColorSpace::Srgb, ColorSpace::DisplayP3, red),
+            [0.9175, 0.2003, 0.1386],
+            1e-3,
+        );
+        assert_close(
+            convert(ColorSpace::Srgb, ColorSpace::Lab, red),
+            [54.29, 80.80, 69.89],
+            5e-2,
+        );
+        assert_close(
+            convert(ColorSpace::Srgb, ColorSpace::LinearSrgb, [0.5; 3]),
+            [0.2140; 3],
+            1e-4,
+        );
+        assert_close(
+            convert(ColorSpace::AdobeRgb, ColorSpace::Lab, [1.0; 3]),
+            [100.0, 0.0, 0.0],
+            1e-2,
+        );
+
+        // Out of gamut colors survive a roundtrip.
+        let green = convert(ColorSpace::DisplayP3, ColorSpace::Srgb, [0.0, 1.0, 0.0]);
+        assert!(green[0] < 0.0);
+        assert_close(
+            convert(ColorSpace::Srgb, ColorSpace::DisplayP3, green),
+            [0.0, 1.0, 0.0],
+            1e-4,
+        );
+    }
+
+    #[test]
+    fn convert_images() {
+        // 16-bit samples keep the precision of Lab colors.
+        let image: ImageBuffer<crate::Rgb<u16>, _> = ImageBuffer::from_fn(16, 16, |x, y| {
+            crate::Rgb([x as u16 * 4096, y as u16 * 4096, 32768])
+        });
+        let mut converted = DynamicImage::ImageRgb16(image.clone());
+        converted.convert_color_space(ColorSpace::Srgb, ColorSpace::Lab);
+        assert_ne!(converted.as_rgb16().unwrap(), &image);
+        converted.convert_color_space(ColorSpace::Lab, ColorSpace::Srgb);
+        let converted = converted.into_rgb16();
+        for (a, b) in converted.as_raw().iter().zip(image.as_raw()) {
+            assert!(
+                (i32::from(*a) - i32::from(*b)).abs() <= 64,
+                "{} != {}",
+                a,
+                b
+            );
+        }
+
+        // Gray stays gray, and is stored as the lightness in Lab.
+        let mut gray =
+            DynamicImage::ImageLuma16(ImageBuffer::from_pixel(1, 1, crate::Luma([65535])));
+        gray.convert_color_space(ColorSpace::Srgb, ColorSpace::Lab);
+        assert_eq!(gray.as_luma16().unwrap().as_raw(), &[65535]);
+
+        let mut float = DynamicImage::ImageRgba32F(ImageBuffer::from_pixel(
+            1,
+            1,
+            crate::Rgba([1.0, 0.0, 0.0, 0.5]),
+        ));
+        float.convert_color_space(ColorSpace::Srgb, ColorSpace::Lab);
+        let pixel = float.as_rgba32f().unwrap().get_pixel(0, 0).0;
+        assert_close([pixel[0], pixel[1], pixel[2]], [54.29, 80.80, 69.89], 5e-2);
+        assert_eq!(pixel[3], 0.5);
+    }
+
+    #[test]
+    #[cfg(feature = "jpeg")]
+    fn recognizes_icc_profiles() {
+        use crate::codecs::jpeg::JpegDecoder;
+        use crate::ImageDecoder;
+
+        let data = std::fs::read("tests/images/jpg/progressive/cat.jpg").unwrap();
+        let profile = JpegDecoder::new(&data[..]).unwrap().icc_profile().unwrap();
+        assert_eq!(
+            ColorSpace::from_icc_profile(&profile),
+            Some(ColorSpace::Srgb)
+        );
+
+        // The same profile with the tone curve of Adobe RGB is of no supported space.
+        let mut gamma = profile.clone();
+        let trc = icc_tag(&profile, b"rTRC").unwrap().as_ptr() as usize - profile.as_ptr() as usize;
+        gamma[trc + 8..trc + 14].copy_from_slice(&[0, 0, 0, 1, 2, 51]);
+        assert_eq!(ColorSpace::from_icc_profile(&gamma), None);
+        assert_eq!(ColorSpace::from_icc_profile(&profile[..100]), None);
+    }
+}
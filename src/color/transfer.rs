@@ -0,0 +1,39 @@
+//! The sRGB transfer function, which relates encoded sample values to linear light.
+//!
+//! Display P3 and most 8-bit images use it, so conversions, filters and codecs that work on light
+//! intensities share these functions. Negative values are mirrored, which keeps colors outside of
+//! the sRGB gamut intact when they are converted back and forth.
+
+/// Decodes an sRGB encoded value into linear light.
+pub(crate) fn srgb_to_linear(value: f64) -> f64 {
+    let abs = value.abs();
+    if abs <= 0.040_45 {
+        value / 12.92
+    } else {
+        value.signum() * ((abs + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Encodes a value in linear light with the sRGB transfer function.
+pub(crate) fn linear_to_srgb(value: f64) -> f64 {
+    let abs = value.abs();
+    if abs <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        value.signum() * (1.055 * abs.powf(1.0 / 2.4) - 0.055)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        for &value in &[-0.5, -0.01, 0.0, 0.002, 0.04, 0.2, 0.5, 1.0, 2.0] {
+            assert!((linear_to_srgb(srgb_to_linear(value)) - value).abs() < 1e-12);
+        }
+        assert!((srgb_to_linear(0.5) - 0.214_041).abs() < 1e-6);
+        assert_eq!(srgb_to_linear(1.0), 1.0);
+    }
+}
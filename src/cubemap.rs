@@ -0,0 +1,328 @@
+//! Cubemaps, the six square faces of a cube that together show the environment in all directions.
+//!
+//! A [`Cubemap`] holds its faces as separate image buffers. It is usually built from a single image
+//! that arranges the faces in one of the [`CubemapLayout`]s, from a DDS file with the `dds`
+//! feature or a KTX file with the `ktx` feature, or from an equirectangular panorama. The faces follow the OpenGL convention described
+//! in [`imageops::projection`].
+//!
+//! ```
+//! use image::cubemap::{Cubemap, CubemapLayout};
+//! use image::imageops::projection::CubeFace;
+//! use image::{Luma, GrayImage};
+//!
+//! let cross = GrayImage::new(4 * 16, 3 * 16);
+//! let cubemap = Cubemap::from_layout(&cross, CubemapLayout::HorizontalCross).unwrap();
+//! assert_eq!(cubemap.size(), 16);
+//!
+//! let brighter = cubemap.map_faces(|_, face| image::imageops::brighten(face, 10));
+//! assert_eq!(brighter.face(CubeFace::PositiveZ).get_pixel(0, 0), &Luma([10]));
+//! ```
+//!
+//! [`Cubemap`]: struct.Cubemap.html
+//! [`CubemapLayout`]: enum.CubemapLayout.html
+//! [`imageops::projection`]: ../imageops/projection/index.html
+
+use crate::buffer_::ImageBuffer;
+use crate::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use crate::image::{GenericImage, GenericImageView};
+use crate::imageops::projection::{cubemap_to_equirectangular, equirectangular_to_cubemap};
+use crate::imageops::{rotate180, rotate180_in_place};
+use crate::traits::{Pixel, Primitive};
+
+pub use crate::imageops::projection::CubeFace;
+
+/// The arrangement of the six faces of a cubemap within a single image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CubemapLayout {
+    /// Four faces by three, with the side faces in the middle row from the negative X face to the
+    /// negative Z face, and the positive and negative Y faces above and below the positive Z face.
+    HorizontalCross,
+    /// Three faces by four, like the horizontal cross but with the negative Z face below the
+    /// negative Y face, upside down.
+    VerticalCross,
+    /// Six faces in a row, in the order of [`CubeFace::ALL`].
+    ///
+    /// [`CubeFace::ALL`]: ../imageops/projection/enum.CubeFace.html#associatedconstant.ALL
+    HorizontalStrip,
+    /// Six faces in a column, in the order of [`CubeFace::ALL`].
+    ///
+    /// [`CubeFace::ALL`]: ../imageops/projection/enum.CubeFace.html#associatedconstant.ALL
+    VerticalStrip,
+}
+
+impl CubemapLayout {
+    /// The number of faces in a row and in a column of the layout.
+    pub fn grid(self) -> (u32, u32) {
+        match self {
+            CubemapLayout::HorizontalCross => (4, 3),
+            CubemapLayout::VerticalCross => (3, 4),
+            CubemapLayout::HorizontalStrip => (6, 1),
+            CubemapLayout::VerticalStrip => (1, 6),
+        }
+    }
+
+    /// The column and row of a face, and whether it is stored upside down.
+    fn cell(self, face: CubeFace) -> (u32, u32, bool) {
+        let index = face as u32;
+        match (self, face) {
+            (CubemapLayout::HorizontalStrip, _) => (index, 0, false),
+            (CubemapLayout::VerticalStrip, _) => (0, index, false),
+            (_, CubeFace::PositiveY) => (1, 0, false),
+            (_, CubeFace::NegativeX) => (0, 1, false),
+            (_, CubeFace::PositiveZ) => (1, 1, false),
+            (_, CubeFace::PositiveX) => (2, 1, false),
+            (_, CubeFace::NegativeY) => (1, 2, false),
+            (CubemapLayout::HorizontalCross, CubeFace::NegativeZ) => (3, 1, false),
+            (CubemapLayout::VerticalCross, CubeFace::NegativeZ) => (1, 3, true),
+        }
+    }
+}
+
+/// The six square faces of a cube, in the order of [`CubeFace::ALL`].
+///
+/// [`CubeFace::ALL`]: ../imageops/projection/enum.CubeFace.html#associatedconstant.ALL
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cubemap<P: Pixel> {
+    faces: [ImageBuffer<P, Vec<P::Subpixel>>; 6],
+}
+
+impl<P: Pixel + 'static> Cubemap<P> {
+    /// Creates a cubemap from its faces, in the order of [`CubeFace::ALL`].
+    ///
+    /// Returns an `ImageError::Parameter` if the faces are not squares of the same size.
+    ///
+    /// [`CubeFace::ALL`]: ../imageops/projection/enum.CubeFace.html#associatedconstant.ALL
+    pub fn from_faces(faces: [ImageBuffer<P, Vec<P::Subpixel>>; 6]) -> ImageResult<Self> {
+        let size = faces[0].width();
+        if faces.iter().any(|face| face.dimensions() != (size, size)) {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+        Ok(Cubemap { faces })
+    }
+
+    /// Cuts the faces out of an image that arranges them in `layout`.
+    ///
+    /// Returns an `ImageError::Parameter` if the dimensions of the image do not divide into square
+    /// faces of the layout.
+    pub fn from_layout<I>(image: &I, layout: CubemapLayout) -> ImageResult<Self>
+    where
+        I: GenericImageView<Pixel = P>,
+    {
+        let (columns, rows) = layout.grid();
+        let (width, height) = image.dimensions();
+        let size = width / columns;
+        if width % columns != 0 || height != size * rows {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!(
+                    "an image of size {}x{} does not hold square faces in a {:?} layout",
+                    width, height, layout
+                )),
+            )));
+        }
+
+        let face = |face: CubeFace| {
+            let (column, row, flipped) = layout.cell(face);
+            let (left, top) = (column * size, row * size);
+            ImageBuffer::from_fn(size, size, |x, y| {
+                if flipped {
+                    image.get_pixel(left + size - 1 - x, top + size - 1 - y)
+                } else {
+                    image.get_pixel(left + x, top + y)
+                }
+            })
+        };
+        Ok(Cubemap {
+            faces: [
+                face(CubeFace::PositiveX),
+                face(CubeFace::NegativeX),
+                face(CubeFace::PositiveY),
+                face(CubeFace::NegativeY),
+                face(CubeFace::PositiveZ),
+                face(CubeFace::NegativeZ),
+            ],
+        })
+    }
+
+    /// Arranges the faces in a single image of the given `layout`.
+    ///
+    /// The cells of a cross layout that hold no face are zero, which is fully transparent for
+    /// pixels with an alpha channel.
+    pub fn to_layout(&self, layout: CubemapLayout) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        let (columns, rows) = layout.grid();
+        let size = self.size();
+        let mut image = ImageBuffer::new(columns * size, rows * size);
+        for &face in CubeFace::ALL.iter() {
+            let (column, row, flipped) = layout.cell(face);
+            let source = self.face(face);
+            let copied = if flipped {
+                image.copy_from(&rotate180(source), column * size, row * size)
+            } else {
+                image.copy_from(source, column * size, row * size)
+            };
+            // The image was sized to hold every cell.
+            copied.unwrap();
+        }
+        image
+    }
+
+    /// The width and height of each face.
+    pub fn size(&self) -> u32 {
+        self.faces[0].width()
+    }
+
+    /// Returns a face.
+    pub fn face(&self, face: CubeFace) -> &ImageBuffer<P, Vec<P::Subpixel>> {
+        &self.faces[face as usize]
+    }
+
+    /// Returns a face mutably.
+    pub fn face_mut(&mut self, face: CubeFace) -> &mut ImageBuffer<P, Vec<P::Subpixel>> {
+        &mut self.faces[face as usize]
+    }
+
+    /// Returns an iterator over the faces together with their position on the cube.
+    pub fn faces(&self) -> impl Iterator<Item = (CubeFace, &ImageBuffer<P, Vec<P::Subpixel>>)> {
+        CubeFace::ALL.iter().copied().zip(self.faces.iter())
+    }
+
+    /// Returns an iterator over the mutable faces together with their position on the cube.
+    pub fn faces_mut(
+        &mut self,
+    ) -> impl Iterator<Item = (CubeFace, &mut ImageBuffer<P, Vec<P::Subpixel>>)> {
+        CubeFace::ALL.iter().copied().zip(self.faces.iter_mut())
+    }
+
+    /// Consumes the cubemap and returns its faces, in the order of [`CubeFace::ALL`].
+    ///
+    /// [`CubeFace::ALL`]: ../imageops/projection/enum.CubeFace.html#associatedconstant.ALL
+    pub fn into_faces(self) -> [ImageBuffer<P, Vec<P::Subpixel>>; 6] {
+        self.faces
+    }
+
+    /// Applies an operation to every face, such as a color conversion or a filter.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the operation returns faces that are not squares of the same size.
+    pub fn map_faces<Q, F>(&self, mut f: F) -> Cubemap<Q>
+    where
+        Q: Pixel + 'static,
+        F: FnMut(CubeFace, &ImageBuffer<P, Vec<P::Subpixel>>) -> ImageBuffer<Q, Vec<Q::Subpixel>>,
+    {
+        let faces = [
+            f(CubeFace::PositiveX, self.face(CubeFace::PositiveX)),
+            f(CubeFace::NegativeX, self.face(CubeFace::NegativeX)),
+            f(CubeFace::PositiveY, self.face(CubeFace::PositiveY)),
+            f(CubeFace::NegativeY, self.face(CubeFace::NegativeY)),
+            f(CubeFace::PositiveZ, self.face(CubeFace::PositiveZ)),
+            f(CubeFace::NegativeZ, self.face(CubeFace::NegativeZ)),
+        ];
+        Cubemap::from_faces(faces).expect("faces of different sizes")
+    }
+
+    /// Flips every face upside down in place, as is needed between the conventions of APIs that
+    /// store rows from the top and from the bottom.
+    pub fn rotate180_in_place(&mut self) {
+        for face in &mut self.faces {
+            rotate180_in_place(face);
+        }
+    }
+}
+
+impl<P, S> Cubemap<P>
+where
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    /// Projects an equirectangular panorama onto a cubemap with faces of `face_size` pixels.
+    pub fn from_equirectangular<I>(image: &I, face_size: u32) -> Self
+    where
+        I: GenericImageView<Pixel = P>,
+    {
+        Cubemap {
+            faces: equirectangular_to_cubemap(image, face_size),
+        }
+    }
+
+    /// Projects the cubemap onto an equirectangular panorama of the given size.
+    pub fn to_equirectangular(&self, width: u32, height: u32) -> ImageBuffer<P, Vec<S>> {
+        cubemap_to_equirectangular(&self.faces, width, height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayImage, Luma};
+
+    /// A cubemap whose faces are filled with their index plus one, with a marker in the top left
+    /// corner.
+    fn numbered() -> Cubemap<Luma<u8>> {
+        let face = |i: u8| {
+            GrayImage::from_fn(4, 4, |x, y| {
+                Luma([if (x, y) == (0, 0) { 100 } else { i + 1 }])
+            })
+        };
+        Cubemap::from_faces([face(0), face(1), face(2), face(3), face(4), face(5)]).unwrap()
+    }
+
+    #[test]
+    fn layouts_round_trip() {
+        let cubemap = numbered();
+        for &layout in &[
+            CubemapLayout::HorizontalCross,
+            CubemapLayout::VerticalCross,
+            CubemapLayout::HorizontalStrip,
+            CubemapLayout::VerticalStrip,
+        ] {
+            let image = cubemap.to_layout(layout);
+            let (columns, rows) = layout.grid();
+            assert_eq!(image.dimensions(), (columns * 4, rows * 4));
+            assert_eq!(Cubemap::from_layout(&image, layout).unwrap(), cubemap);
+        }
+    }
+
+    #[test]
+    fn cross_positions() {
+        let cross = numbered().to_layout(CubemapLayout::HorizontalCross);
+        assert_eq!(cross.get_pixel(5, 1)[0], 3);
+        assert_eq!(cross.get_pixel(1, 5)[0], 2);
+        assert_eq!(cross.get_pixel(13, 5)[0], 6);
+        assert_eq!(cross.get_pixel(1, 1)[0], 0);
+
+        let cross = numbered().to_layout(CubemapLayout::VerticalCross);
+        assert_eq!(cross.get_pixel(5, 13)[0], 6);
+        // The negative Z face is upside down, with its marker in the bottom right corner.
+        assert_eq!(cross.get_pixel(7, 15)[0], 100);
+    }
+
+    #[test]
+    fn mismatched_dimensions() {
+        assert!(
+            Cubemap::from_layout(&GrayImage::new(16, 13), CubemapLayout::HorizontalCross).is_err()
+        );
+        assert!(
+            Cubemap::from_layout(&GrayImage::new(25, 4), CubemapLayout::HorizontalStrip).is_err()
+        );
+        let mut faces = numbered().into_faces();
+        faces[3] = GrayImage::new(4, 5);
+        assert!(Cubemap::from_faces(faces).is_err());
+    }
+
+    #[test]
+    fn map_faces_by_position() {
+        let cubemap = numbered().map_faces(|face, image| {
+            let value = if face == CubeFace::NegativeY {
+                50
+            } else {
+                image[(1, 1)][0]
+            };
+            GrayImage::from_pixel(2, 2, Luma([value]))
+        });
+        assert_eq!(cubemap.size(), 2);
+        assert_eq!(cubemap.face(CubeFace::NegativeY)[(0, 0)][0], 50);
+        assert_eq!(cubemap.face(CubeFace::NegativeZ)[(0, 0)][0], 6);
+    }
+}
@@ -0,0 +1,668 @@
+//! Reading the images of datasets packed into archives.
+//!
+//! Datasets of images, such as those used to train machine learning models, are commonly
+//! distributed as zip or tar archives holding thousands of small files next to labels and other
+//! metadata. [`iter_archive`] decodes the images out of such an archive one entry at a time,
+//! without extracting it first.
+//!
+//! An [`Archive`] is a [`FileSystem`] of its own, so the other entries, such as the labels, can
+//! be opened by path while iterating, and [`Reader::open_in`] reads single images out of it. The
+//! archive itself is opened through a file system with [`iter_archive_in`].
+//!
+//! Zip archives may store their entries uncompressed or deflated. Tar archives are read in the
+//! ustar format, with GNU and pax long file names, and must not be compressed as a whole.
+//!
+//! ```no_run
+//! use image::io::StdFileSystem;
+//!
+//! for entry in image::dataset::iter_archive_in(StdFileSystem, "train.zip")? {
+//!     let (path, image) = entry?;
+//!     println!("{}: {:?}", path.display(), image.color());
+//! }
+//! # Ok::<(), image::ImageError>(())
+//! ```
+//!
+//! [`iter_archive`]: fn.iter_archive.html
+//! [`iter_archive_in`]: fn.iter_archive_in.html
+//! [`Archive`]: struct.Archive.html
+//! [`FileSystem`]: ../io/trait.FileSystem.html
+//! [`Reader::open_in`]: ../io/struct.Reader.html#method.open_in
+
+use std::cell::RefCell;
+use std::convert::TryFrom;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use crate::dynimage::DynamicImage;
+use crate::error::{
+    DecodingError, ImageError, ImageFormatHint, ImageResult, LimitError, LimitErrorKind,
+    UnsupportedError, UnsupportedErrorKind,
+};
+use crate::image::ImageFormat;
+use crate::io::{FileMetadata, FileSystem, Limits, Reader};
+
+/// The size of the blocks of a tar archive.
+const TAR_BLOCK: usize = 512;
+
+/// Iterate over the images in a zip or tar archive.
+///
+/// The kind of archive is detected from its content. Each item holds the path of an entry in the
+/// archive and the image decoded from it. The format of an entry is guessed from its path, or
+/// from its content if the path has no extension. Entries that are not images, such as
+/// directories, labels or a manifest, are skipped, while an image that fails to decode yields an
+/// error and the iteration continues with the next entry.
+///
+/// Returns an `ImageError::Unsupported` if the archive is neither a zip nor a tar archive.
+pub fn iter_archive<R: Read + Seek>(reader: R) -> ImageResult<ArchiveImages<R>> {
+    Ok(ArchiveImages {
+        archive: Archive::new(reader)?,
+        next: 0,
+    })
+}
+
+/// Iterate over the images in the zip or tar archive at `path` of a [`FileSystem`].
+///
+/// This opens the archive with [`FileSystem::open`] and reads it like [`iter_archive`].
+///
+/// [`FileSystem`]: ../io/trait.FileSystem.html
+/// [`FileSystem::open`]: ../io/trait.FileSystem.html#tymethod.open
+/// [`iter_archive`]: fn.iter_archive.html
+pub fn iter_archive_in<S, P>(fs: S, path: P) -> ImageResult<ArchiveImages<BufReader<S::File>>>
+where
+    S: FileSystem,
+    P: AsRef<Path>,
+{
+    iter_archive(BufReader::new(fs.open(path.as_ref())?))
+}
+
+/// A zip or tar archive, read as a [`FileSystem`] of its entries.
+///
+/// Opening an entry reads it whole into memory, within the limits of the archive. Directories are
+/// not listed and entries report no modification time.
+///
+/// [`FileSystem`]: ../io/trait.FileSystem.html
+pub struct Archive<R> {
+    reader: RefCell<R>,
+    entries: Vec<Entry>,
+    limits: Limits,
+}
+
+/// A file in an archive.
+struct Entry {
+    path: PathBuf,
+    size: u64,
+    location: Location,
+}
+
+/// Where the data of an entry is stored in its archive.
+enum Location {
+    Zip {
+        flags: u16,
+        method: u16,
+        compressed_size: u64,
+        header_offset: u64,
+    },
+    Tar {
+        offset: u64,
+    },
+}
+
+impl<R: Read + Seek> Archive<R> {
+    /// Reads the list of entries of a zip or tar archive.
+    ///
+    /// The kind of archive is detected from its content. Returns an `ImageError::Unsupported` if
+    /// the archive is neither a zip nor a tar archive.
+    pub fn new(mut reader: R) -> ImageResult<Self> {
+        let mut start = [0; TAR_BLOCK];
+        let len = read_full(&mut reader, &mut start)?;
+        reader.seek(SeekFrom::Start(0))?;
+
+        let entries = if start[..len].starts_with(b"PK\x03\x04")
+            || start[..len].starts_with(b"PK\x05\x06")
+        {
+            zip_directory(&mut reader)?
+        } else if len == TAR_BLOCK && &start[257..262] == b"ustar" {
+            tar_directory(&mut reader)?
+        } else {
+            return Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    ImageFormatHint::Unknown,
+                    UnsupportedErrorKind::GenericFeature("archives other than zip and tar".into()),
+                ),
+            ));
+        };
+        Ok(Archive {
+            reader: RefCell::new(reader),
+            entries,
+            limits: Limits::default(),
+        })
+    }
+
+    /// Returns the paths of the files in the archive, in the order they are stored.
+    pub fn paths(&self) -> impl Iterator<Item = &Path> {
+        self.entries.iter().map(|entry| entry.path.as_path())
+    }
+
+    /// Set the limits used for reading each entry.
+    pub fn limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Disable all limits.
+    pub fn no_limits(&mut self) {
+        self.limits = Limits::no_limits();
+    }
+
+    fn entry(&self, path: &Path) -> io::Result<&Entry> {
+        self.entries
+            .iter()
+            .find(|entry| entry.path == path)
+            .ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    fn read_entry(&self, entry: &Entry) -> ImageResult<Vec<u8>> {
+        let reader = &mut *self.reader.borrow_mut();
+        match entry.location {
+            Location::Zip {
+                flags,
+                method,
+                compressed_size,
+                header_offset,
+            } => {
+                if flags & 1 != 0 || (method != 0 && method != 8) {
+                    return Err(ImageError::Unsupported(
+                        UnsupportedError::from_format_and_kind(
+                            ImageFormatHint::Name("zip".into()),
+                            UnsupportedErrorKind::GenericFeature(
+                                "encrypted entries and compression other than deflate".into(),
+                            ),
+                        ),
+                    ));
+                }
+                reader.seek(SeekFrom::Start(header_offset))?;
+                let mut header = [0; 30];
+                reader.read_exact(&mut header)?;
+                if !header.starts_with(b"PK\x03\x04") {
+                    return Err(archive_error("zip", "invalid local file header"));
+                }
+                let skip = i64::from(u16_at(&header, 26)) + i64::from(u16_at(&header, 28));
+                reader.seek(SeekFrom::Current(skip))?;
+
+                let data = read_data(reader, compressed_size, &self.limits)?;
+                if method == 0 {
+                    return Ok(data);
+                }
+                self.limits.clone().reserve(entry.size)?;
+                let size = usize::try_from(entry.size).map_err(|_| {
+                    ImageError::Limits(LimitError::from_kind(LimitErrorKind::InsufficientMemory))
+                })?;
+                match miniz_oxide::inflate::decompress_to_vec_with_limit(&data, size) {
+                    Ok(data) if data.len() == size => Ok(data),
+                    _ => Err(archive_error("zip", "invalid deflate stream")),
+                }
+            }
+            Location::Tar { offset } => {
+                reader.seek(SeekFrom::Start(offset))?;
+                read_data(reader, entry.size, &self.limits)
+            }
+        }
+    }
+}
+
+impl<R: Read + Seek> FileSystem for Archive<R> {
+    type File = Cursor<Vec<u8>>;
+
+    fn open(&self, path: &Path) -> io::Result<Self::File> {
+        match self.read_entry(self.entry(path)?) {
+            Ok(data) => Ok(Cursor::new(data)),
+            Err(ImageError::IoError(err)) => Err(err),
+            Err(err) => Err(io::Error::new(io::ErrorKind::InvalidData, err)),
+        }
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        Ok(FileMetadata {
+            len: self.entry(path)?.size,
+            modified: None,
+        })
+    }
+}
+
+/// An iterator over the images in an archive.
+///
+/// This struct is created by [`iter_archive`].
+///
+/// [`iter_archive`]: fn.iter_archive.html
+pub struct ArchiveImages<R> {
+    archive: Archive<R>,
+    next: usize,
+}
+
+impl<R> ArchiveImages<R> {
+    /// Returns the archive, to open its other entries such as the labels of the images.
+    pub fn archive(&self) -> &Archive<R> {
+        &self.archive
+    }
+
+    /// Set the limits used for reading and decoding each entry.
+    pub fn limits(&mut self, limits: Limits) {
+        self.archive.limits = limits;
+    }
+
+    /// Disable all decoding limits.
+    pub fn no_limits(&mut self) {
+        self.archive.limits = Limits::no_limits();
+    }
+}
+
+impl<R: Read + Seek> Iterator for ArchiveImages<R> {
+    type Item = ImageResult<(PathBuf, DynamicImage)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let entry = self.archive.entries.get(self.next)?;
+            self.next += 1;
+            if !is_candidate(&entry.path) {
+                continue;
+            }
+            let data = match self.archive.read_entry(entry) {
+                Ok(data) => data,
+                Err(err) => return Some(Err(err)),
+            };
+            if let Some(image) = decode(&entry.path, data, &self.archive.limits) {
+                return Some(image.map(|image| (entry.path.clone(), image)));
+            }
+        }
+    }
+}
+
+/// Returns whether an entry might be an image, which is when its extension is that of a
+/// supported format or it has none.
+fn is_candidate(path: &Path) -> bool {
+    match ImageFormat::from_path(path) {
+        Ok(format) => format.can_read(),
+        Err(_) => path.extension().is_none(),
+    }
+}
+
+/// Decodes an entry, returning `None` if it is not an image of a supported format.
+fn decode(path: &Path, data: Vec<u8>, limits: &Limits) -> Option<ImageResult<DynamicImage>> {
+    let mut reader = Reader::new(Cursor::new(data));
+    match ImageFormat::from_path(path) {
+        Ok(format) => reader.set_format(format),
+        Err(_) => {
+            reader = reader.with_guessed_format().expect("Cursor io never fails");
+            if !reader.format()?.can_read() {
+                return None;
+            }
+        }
+    }
+    reader.limits(limits.clone());
+    Some(reader.decode())
+}
+
+fn read_full(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut len = 0;
+    while len < buf.len() {
+        match reader.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(len)
+}
+
+/// Reads the data of an entry of `size` bytes, within the limits.
+fn read_data(reader: &mut impl Read, size: u64, limits: &Limits) -> ImageResult<Vec<u8>> {
+    limits.clone().reserve(size)?;
+    let mut data = Vec::new();
+    reader.take(size).read_to_end(&mut data)?;
+    if (data.len() as u64) < size {
+        return Err(ImageError::IoError(io::ErrorKind::UnexpectedEof.into()));
+    }
+    Ok(data)
+}
+
+fn archive_error(format: &str, message: &'static str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name(format.into()),
+        message,
+    ))
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(<[u8; 4]>::try_from(&data[offset..offset + 4]).unwrap())
+}
+
+/// Reads the central directory of a zip archive, which lists its entries at the end.
+fn zip_directory(reader: &mut (impl Read + Seek)) -> ImageResult<Vec<Entry>> {
+    // The end of central directory record is followed by a comment of up to 65535 bytes.
+    let len = reader.seek(SeekFrom::End(0))?;
+    let tail_len = len.min(22 + 0xffff);
+    reader.seek(SeekFrom::Start(len - tail_len))?;
+    let mut tail = vec![0; tail_len as usize];
+    reader.read_exact(&mut tail)?;
+    let end = (0..tail.len().saturating_sub(21))
+        .rev()
+        .find(|&i| tail[i..].starts_with(b"PK\x05\x06"))
+        .ok_or_else(|| archive_error("zip", "missing end of central directory"))?;
+    let end = &tail[end..];
+
+    let count = u16_at(end, 10);
+    let (directory_size, directory_offset) = (u32_at(end, 12), u32_at(end, 16));
+    if count == 0xffff || directory_size == 0xffff_ffff || directory_offset == 0xffff_ffff {
+        return Err(ImageError::Unsupported(
+            UnsupportedError::from_format_and_kind(
+                ImageFormatHint::Name("zip".into()),
+                UnsupportedErrorKind::GenericFeature("zip64 archives".into()),
+            ),
+        ));
+    }
+    if u64::from(directory_offset) + u64::from(directory_size) > len {
+        return Err(archive_error("zip", "central directory out of bounds"));
+    }
+    reader.seek(SeekFrom::Start(directory_offset.into()))?;
+    let mut directory = vec![0; directory_size as usize];
+    reader.read_exact(&mut directory)?;
+
+    let mut entries = Vec::with_capacity(count.into());
+    let mut position = 0;
+    for _ in 0..count {
+        let header = directory
+            .get(position..position + 46)
+            .filter(|header| header.starts_with(b"PK\x01\x02"))
+            .ok_or_else(|| archive_error("zip", "invalid central directory entry"))?;
+        let name_len = usize::from(u16_at(header, 28));
+        let extra_len = usize::from(u16_at(header, 30));
+        let comment_len = usize::from(u16_at(header, 32));
+        let name = directory
+            .get(position + 46..position + 46 + name_len)
+            .ok_or_else(|| archive_error("zip", "invalid central directory entry"))?;
+        let path = String::from_utf8_lossy(name);
+        if !path.ends_with('/') {
+            entries.push(Entry {
+                path: PathBuf::from(path.as_ref()),
+                size: u32_at(header, 24).into(),
+                location: Location::Zip {
+                    flags: u16_at(header, 8),
+                    method: u16_at(header, 10),
+                    compressed_size: u32_at(header, 20).into(),
+                    header_offset: u32_at(header, 42).into(),
+                },
+            });
+        }
+        position += 46 + name_len + extra_len + comment_len;
+    }
+    Ok(entries)
+}
+
+/// Parses an octal number of a tar header, or a base-256 number if its high bit is set.
+fn tar_number(field: &[u8]) -> Option<u64> {
+    if field.first()? & 0x80 != 0 {
+        return field[1..]
+            .iter()
+            .try_fold(0u64, |n, &b| n.checked_mul(256)?.checked_add(b.into()));
+    }
+    let digits = field
+        .iter()
+        .skip_while(|&&b| b == b' ')
+        .take_while(|&&b| (b'0'..=b'7').contains(&b));
+    let mut n = 0u64;
+    for &digit in digits {
+        n = n.checked_mul(8)?.checked_add(u64::from(digit - b'0'))?;
+    }
+    Some(n)
+}
+
+/// Returns the bytes of a tar header field up to the first nul byte.
+fn tar_string(field: &[u8]) -> &[u8] {
+    let len = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    &field[..len]
+}
+
+/// Lists the regular files of a tar archive by walking its headers, skipping over their data.
+fn tar_directory(reader: &mut (impl Read + Seek)) -> ImageResult<Vec<Entry>> {
+    let mut entries = Vec::new();
+    let mut long_name = None;
+    loop {
+        let mut header = [0; TAR_BLOCK];
+        let len = read_full(reader, &mut header)?;
+        if len == 0 || header.iter().all(|&b| b == 0) {
+            return Ok(entries);
+        }
+        if len < TAR_BLOCK {
+            return Err(ImageError::IoError(io::ErrorKind::UnexpectedEof.into()));
+        }
+        let size = tar_number(&header[124..136])
+            .ok_or_else(|| archive_error("tar", "invalid entry size"))?;
+        let padding = (TAR_BLOCK as u64 - size % TAR_BLOCK as u64) % TAR_BLOCK as u64;
+
+        let path = match long_name.take() {
+            Some(path) => path,
+            None => {
+                let name = tar_string(&header[0..100]);
+                let prefix = tar_string(&header[345..500]);
+                let mut path = String::from_utf8_lossy(prefix).into_owned();
+                if !path.is_empty() {
+                    path.push('/');
+                }
+                path.push_str(&String::from_utf8_lossy(name));
+                path
+            }
+        };
+
+        match header[156] {
+            b'0' | 0 if !path.ends_with('/') => {
+                entries.push(Entry {
+                    path: PathBuf::from(path),
+                    size,
+                    location: Location::Tar {
+                        offset: reader.stream_position()?,
+                    },
+                });
+                reader.seek(SeekFrom::Current((size + padding) as i64))?;
+            }
+            // The name of the next entry, in GNU or pax extensions.
+            b'L' | b'x' => {
+                let data = read_data(reader, size, &Limits::default())?;
+                reader.seek(SeekFrom::Current(padding as i64))?;
+                long_name = if header[156] == b'L' {
+                    Some(String::from_utf8_lossy(tar_string(&data)).into_owned())
+                } else {
+                    pax_path(&data)
+                };
+            }
+            _ => {
+                reader.seek(SeekFrom::Current((size + padding) as i64))?;
+            }
+        }
+    }
+}
+
+/// Finds the path among the records of a pax extended header, each `<length> <key>=<value>\n`.
+fn pax_path(mut data: &[u8]) -> Option<String> {
+    while !data.is_empty() {
+        let space = data.iter().position(|&b| b == b' ')?;
+        let len: usize = std::str::from_utf8(&data[..space]).ok()?.parse().ok()?;
+        let record = data.get(space + 1..len)?;
+        if let Some(path) = record.strip_prefix(b"path=") {
+            let path = path.strip_suffix(b"\n").unwrap_or(path);
+            return Some(String::from_utf8_lossy(path).into_owned());
+        }
+        data = &data[len..];
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PGM: &[u8] = b"P5 2 1 255\n\x07\x09";
+
+    fn zip(entries: &[(&str, &[u8], bool)]) -> Vec<u8> {
+        let mut file = Vec::new();
+        let mut directory = Vec::new();
+        for &(name, data, deflate) in entries {
+            let (method, stored) = if deflate {
+                (8u16, miniz_oxide::deflate::compress_to_vec(data, 6))
+            } else {
+                (0u16, data.to_vec())
+            };
+            // The fields shared by the local and the central header, from the version needed to
+            // extract up to the length of the extra field. The time and checksum are left out.
+            let mut header = vec![20, 0, 0, 0];
+            header.extend_from_slice(&method.to_le_bytes());
+            header.extend_from_slice(&[0; 8]);
+            header.extend_from_slice(&(stored.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(data.len() as u32).to_le_bytes());
+            header.extend_from_slice(&(name.len() as u16).to_le_bytes());
+            header.extend_from_slice(&0u16.to_le_bytes());
+
+            directory.extend_from_slice(b"PK\x01\x02\x14\x00");
+            directory.extend_from_slice(&header);
+            directory.extend_from_slice(&[0; 10]);
+            directory.extend_from_slice(&(file.len() as u32).to_le_bytes());
+            directory.extend_from_slice(name.as_bytes());
+
+            file.extend_from_slice(b"PK\x03\x04");
+            file.extend_from_slice(&header);
+            file.extend_from_slice(name.as_bytes());
+            file.extend_from_slice(&stored);
+        }
+        let offset = file.len() as u32;
+        file.extend_from_slice(&directory);
+        file.extend_from_slice(b"PK\x05\x06\0\0\0\0");
+        file.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        file.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        file.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+        file.extend_from_slice(&offset.to_le_bytes());
+        file.extend_from_slice(&0u16.to_le_bytes());
+        file
+    }
+
+    fn tar(entries: &[(&str, u8, &[u8])]) -> Vec<u8> {
+        let mut file = Vec::new();
+        for &(name, kind, data) in entries {
+            let mut header = [0; TAR_BLOCK];
+            header[..name.len()].copy_from_slice(name.as_bytes());
+            header[124..135].copy_from_slice(format!("{:011o}", data.len()).as_bytes());
+            header[156] = kind;
+            header[257..263].copy_from_slice(b"ustar\0");
+            file.extend_from_slice(&header);
+            file.extend_from_slice(data);
+            file.resize((file.len() + TAR_BLOCK - 1) / TAR_BLOCK * TAR_BLOCK, 0);
+        }
+        file.resize(file.len() + 2 * TAR_BLOCK, 0);
+        file
+    }
+
+    fn collect(archive: Vec<u8>) -> Vec<ImageResult<(PathBuf, DynamicImage)>> {
+        iter_archive(Cursor::new(archive)).unwrap().collect()
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn zip_archive() {
+        let archive = zip(&[
+            ("train/", b"", false),
+            ("train/a.pgm", PGM, false),
+            ("train/labels.csv", b"a,cat\nb,dog\n", false),
+            ("train/b", PGM, true),
+            ("train/c.pgm", b"P5 2 1 255\n", true),
+        ]);
+        let images = collect(archive);
+        assert_eq!(images.len(), 3);
+        let (path, image) = images[0].as_ref().unwrap();
+        assert_eq!(path, &PathBuf::from("train/a.pgm"));
+        assert_eq!(image.as_bytes(), &[7, 9]);
+        let (path, image) = images[1].as_ref().unwrap();
+        assert_eq!(path, &PathBuf::from("train/b"));
+        assert_eq!(image.as_bytes(), &[7, 9]);
+        assert!(images[2].is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn tar_archive() {
+        let long = format!("{}/image.pgm", "nested".repeat(20));
+        let archive = tar(&[
+            ("train/", b'5', b""),
+            ("train/readme.txt", b'0', b"hello"),
+            ("././@LongLink", b'L', long.as_bytes()),
+            ("nested", b'0', PGM),
+            ("train/b.pgm", b'0', PGM),
+        ]);
+        let images = collect(archive);
+        assert_eq!(images.len(), 2);
+        let (path, image) = images[0].as_ref().unwrap();
+        assert_eq!(path, &PathBuf::from(long));
+        assert_eq!(image.as_bytes(), &[7, 9]);
+        assert_eq!(images[1].as_ref().unwrap().0, PathBuf::from("train/b.pgm"));
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn archive_file_system() {
+        let inner = tar(&[("b.pgm", b'0', PGM)]);
+        let archive = zip(&[
+            ("a.pgm", PGM, true),
+            ("labels.csv", b"a,cat\n", false),
+            ("inner.tar", &inner, false),
+        ]);
+        let images = iter_archive(Cursor::new(archive)).unwrap();
+        let archive = images.archive();
+        assert_eq!(
+            archive.paths().collect::<Vec<_>>(),
+            [
+                Path::new("a.pgm"),
+                Path::new("labels.csv"),
+                Path::new("inner.tar")
+            ]
+        );
+        assert_eq!(archive.metadata(Path::new("a.pgm")).unwrap().len, 13);
+        let mut labels = String::new();
+        archive
+            .open(Path::new("labels.csv"))
+            .unwrap()
+            .read_to_string(&mut labels)
+            .unwrap();
+        assert_eq!(labels, "a,cat\n");
+        let image = Reader::open_in(archive, "a.pgm").unwrap().decode().unwrap();
+        assert_eq!(image.as_bytes(), &[7, 9]);
+        let missing = archive.open(Path::new("b.pgm")).err().unwrap();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+
+        // Archives in an archive are opened through the file system of the outer one.
+        let nested: Vec<_> = iter_archive_in(archive, "inner.tar")
+            .unwrap()
+            .map(|entry| entry.unwrap().0)
+            .collect();
+        assert_eq!(nested, [PathBuf::from("b.pgm")]);
+    }
+
+    #[test]
+    fn limits_and_unknown_archives() {
+        let archive = zip(&[("a.pgm", PGM, false)]);
+        let mut images = iter_archive(Cursor::new(archive)).unwrap();
+        images.limits({
+            let mut limits = Limits::default();
+            limits.max_alloc = Some(4);
+            limits
+        });
+        assert!(matches!(images.next(), Some(Err(ImageError::Limits(_)))));
+        assert!(images.next().is_none());
+
+        assert!(iter_archive(Cursor::new(PGM)).is_err());
+        assert_eq!(
+            pax_path(b"18 path=a/b/c.png\n11 uid=100\n"),
+            Some("a/b/c.png".into())
+        );
+    }
+}
@@ -1,5 +1,6 @@
+use std::fs::File;
 use std::io;
-use std::io::{Seek, Write};
+use std::io::{BufWriter, Write};
 use std::path::Path;
 use std::u32;
 
@@ -10,10 +11,12 @@ use crate::codecs::png;
 #[cfg(feature = "pnm")]
 use crate::codecs::pnm;
 
+use crate::animation::{AnimationOptions, Frame};
 use crate::buffer_::{
     ConvertBuffer, Gray16Image, GrayAlpha16Image, GrayAlphaImage, GrayImage, ImageBuffer,
     Rgb16Image, RgbImage, Rgba16Image, RgbaImage,
 };
+use crate::color::space::{self, ColorSpace, Conversion};
 use crate::color::{self, IntoColor};
 use crate::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
 // FIXME: These imports exist because we don't support all of our own color types.
@@ -21,9 +24,13 @@ use crate::error::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
 use crate::flat::FlatSamples;
 use crate::image::{GenericImage, GenericImageView, ImageDecoder, ImageFormat, ImageOutputFormat};
 use crate::imageops;
+use crate::io::allocator::{GlobalAllocator, OutputBufferAllocator};
 use crate::io::free_functions;
 use crate::math::resize_dimensions;
+use crate::metadata::Orientation;
+use crate::palette::Palette;
 use crate::traits::Pixel;
+use crate::utils::simd;
 use crate::{image, Luma, LumaA};
 use crate::{Rgb32FImage, Rgba32FImage};
 
@@ -87,7 +94,9 @@ pub enum DynamicImage {
 macro_rules! dynamic_map(
         ($dynimage: expr, $image: pat => $action: expr) => ({
             use DynamicImage::*;
-            match $dynimage {
+            // The result has the color type of the image, so its profile still applies.
+            let icc_profile = $dynimage.icc_profile().map(<[u8]>::to_vec);
+            let mut image = match $dynimage {
                 ImageLuma8($image) => ImageLuma8($action),
                 ImageLumaA8($image) => ImageLumaA8($action),
                 ImageRgb8($image) => ImageRgb8($action),
@@ -98,7 +107,9 @@ macro_rules! dynamic_map(
                 ImageRgba16($image) => ImageRgba16($action),
                 ImageRgb32F($image) => ImageRgb32F($action),
                 ImageRgba32F($image) => ImageRgba32F($action),
-            }
+            };
+            image.set_icc_profile(icc_profile);
+            image
         });
 
         ($dynimage: expr, |$image: pat| $action: expr) => (
@@ -177,7 +188,15 @@ impl DynamicImage {
 
     /// Returns a copy of this image as an RGB image.
     pub fn to_rgb8(&self) -> RgbImage {
-        dynamic_map!(*self, |ref p| p.convert())
+        // Common conversions of 8-bit images have vectorized fast paths.
+        match *self {
+            DynamicImage::ImageRgba8(ref p) => {
+                let mut out = RgbImage::new(p.width(), p.height());
+                simd::rgba_to_rgb(p.as_raw(), &mut out);
+                out
+            }
+            _ => dynamic_map!(*self, |ref p| p.convert()),
+        }
     }
 
     /// Returns a copy of this image as an RGB image.
@@ -186,13 +205,23 @@ impl DynamicImage {
     }
 
     /// Returns a copy of this image as an RGB image.
+    ///
+    /// Integer samples are scaled to the range from 0 to 1, float samples are copied without
+    /// clamping so that the colors of HDR images are kept.
     pub fn to_rgb32f(&self) -> Rgb32FImage {
         dynamic_map!(*self, |ref p| p.convert())
     }
 
     /// Returns a copy of this image as an RGBA image.
     pub fn to_rgba8(&self) -> RgbaImage {
-        dynamic_map!(*self, |ref p| p.convert())
+        match *self {
+            DynamicImage::ImageRgb8(ref p) => {
+                let mut out = RgbaImage::new(p.width(), p.height());
+                simd::rgb_to_rgba(p.as_raw(), &mut out);
+                out
+            }
+            _ => dynamic_map!(*self, |ref p| p.convert()),
+        }
     }
 
     /// Returns a copy of this image as an RGBA image.
@@ -201,13 +230,59 @@ impl DynamicImage {
     }
 
     /// Returns a copy of this image as an RGBA image.
+    ///
+    /// Integer samples are scaled to the range from 0 to 1, float samples are copied without
+    /// clamping so that the colors of HDR images are kept.
     pub fn to_rgba32f(&self) -> Rgba32FImage {
         dynamic_map!(*self, |ref p| p.convert())
     }
 
+    /// Returns a copy of this image as an RGB image, with the colors of float images compressed
+    /// by `tone_mapping`.
+    ///
+    /// Float images hold linear colors that may exceed 1. They are tone mapped and encoded as sRGB
+    /// before being reduced to 8 bits. Integer images are converted like with `to_rgb8`.
+    pub fn to_rgb8_tone_mapped(&self, tone_mapping: imageops::ToneMapping) -> RgbImage {
+        match *self {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                let mut image = self.to_rgb32f();
+                imageops::tone_map_in_place(&mut image, tone_mapping);
+                image.convert()
+            }
+            _ => self.to_rgb8(),
+        }
+    }
+
+    /// Returns a copy of this image as an RGBA image, with the colors of float images compressed
+    /// by `tone_mapping`.
+    ///
+    /// Like `to_rgb8_tone_mapped`, alpha is only scaled to 8 bits.
+    pub fn to_rgba8_tone_mapped(&self, tone_mapping: imageops::ToneMapping) -> RgbaImage {
+        match *self {
+            DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+                let mut image = self.to_rgba32f();
+                imageops::tone_map_in_place(&mut image, tone_mapping);
+                image.convert()
+            }
+            _ => self.to_rgba8(),
+        }
+    }
+
     /// Returns a copy of this image as a Luma image.
     pub fn to_luma8(&self) -> GrayImage {
-        dynamic_map!(*self, |ref p| p.convert())
+        match *self {
+            DynamicImage::ImageRgb8(ref p) => {
+                let mut out = GrayImage::new(p.width(), p.height());
+                simd::rgb_to_luma(p.as_raw(), 3, &mut out);
+                out
+            }
+            DynamicImage::ImageRgba8(ref p) => {
+                let mut out = GrayImage::new(p.width(), p.height());
+                simd::rgb_to_luma(p.as_raw(), 4, &mut out);
+                out
+            }
+            _ => dynamic_map!(*self, |ref p| p.convert()),
+        }
     }
 
     /// Returns a copy of this image as a Luma image.
@@ -603,6 +678,23 @@ impl DynamicImage {
         dynamic_map!(*self, |ref p| { p.height() })
     }
 
+    /// Returns the ICC color profile that describes the colors of this image, if it has one.
+    ///
+    /// Images decoded from a file keep the profile embedded in it, which is embedded again when
+    /// the image is saved or written in a format that can store it. Operations that keep the
+    /// color type, such as resizing, cropping or rotating, keep the profile. Conversions to other
+    /// color types return images without it.
+    pub fn icc_profile(&self) -> Option<&[u8]> {
+        dynamic_map!(*self, |ref p| p.icc_profile())
+    }
+
+    /// Set or remove the ICC color profile that describes the colors of this image.
+    ///
+    /// The profile must describe the color space of the samples. It is not validated.
+    pub fn set_icc_profile(&mut self, icc_profile: Option<Vec<u8>>) {
+        dynamic_map!(*self, |ref mut p| p.set_icc_profile(icc_profile))
+    }
+
     /// Return a grayscale version of this image.
     /// Returns `Luma` images in most cases. However, for `f32` images,
     /// this will return a greyscale `Rgb/Rgba` image instead.
@@ -633,6 +725,23 @@ impl DynamicImage {
         }
     }
 
+    /// Reduce this image to at most `n_colors` colors, returning the palette and the indices of
+    /// the palette colors of all pixels. Alpha is ignored.
+    ///
+    /// The palette is ordered from the most to the least common color. See
+    /// [`imageops::quantize`] for the details, and for other algorithms than median cut.
+    ///
+    /// Returns an `ImageError::Parameter` if `n_colors` is not between 1 and 256.
+    ///
+    /// [`imageops::quantize`]: imageops/fn.quantize.html
+    pub fn quantize(&self, n_colors: usize) -> ImageResult<(Palette, GrayImage)> {
+        imageops::quantize(
+            &self.to_rgb8(),
+            n_colors,
+            imageops::QuantizeAlgorithm::default(),
+        )
+    }
+
     /// Invert the colors of this image.
     /// This method operates inplace.
     pub fn invert(&mut self) {
@@ -737,6 +846,12 @@ impl DynamicImage {
         dynamic_map!(*self, ref p => imageops::filter3x3(p, kernel))
     }
 
+    /// Convolves this image with `kernel`, sampling neighbors beyond the edges according to
+    /// `edges`.
+    pub fn convolve(&self, kernel: &imageops::Kernel, edges: imageops::EdgeMode) -> DynamicImage {
+        dynamic_map!(*self, ref p => imageops::convolve(p, kernel, edges))
+    }
+
     /// Adjust the contrast of this image.
     /// `contrast` is the amount to adjust the contrast by.
     /// Negative values decrease the contrast and positive values increase the contrast.
@@ -784,14 +899,109 @@ impl DynamicImage {
         dynamic_map!(*self, ref p => imageops::rotate270(p))
     }
 
+    /// Rotate and flip this image from the orientation it is stored in to the one it is displayed
+    /// in, such as the orientation of its EXIF metadata.
+    ///
+    /// Afterwards the image is displayed as it is, so the orientation should not be stored with
+    /// it again. Rotations by 90 and 270 degrees allocate a new image, the other transforms
+    /// operate in place.
+    pub fn apply_orientation(&mut self, orientation: Orientation) {
+        match orientation {
+            Orientation::NoTransforms => {}
+            Orientation::FlipHorizontal => {
+                dynamic_map!(*self, |ref mut p| imageops::flip_horizontal_in_place(p))
+            }
+            Orientation::Rotate180 => {
+                dynamic_map!(*self, |ref mut p| imageops::rotate180_in_place(p))
+            }
+            Orientation::FlipVertical => {
+                dynamic_map!(*self, |ref mut p| imageops::flip_vertical_in_place(p))
+            }
+            Orientation::Rotate90FlipH => {
+                *self = self.rotate90();
+                self.apply_orientation(Orientation::FlipHorizontal);
+            }
+            Orientation::Rotate90 => *self = self.rotate90(),
+            Orientation::Rotate270FlipH => {
+                *self = self.rotate270();
+                self.apply_orientation(Orientation::FlipHorizontal);
+            }
+            Orientation::Rotate270 => *self = self.rotate270(),
+        }
+    }
+
+    /// Convert the colors of this image from the color space `from` to the space `to`.
+    ///
+    /// The samples are converted in place and keep their type, so colors that are out of the
+    /// gamut of `to` are clipped in integer images. Floating point images keep them, which
+    /// preserves wide gamut and HDR colors. Alpha is not changed. See the [`space`] module for
+    /// how Lab colors are stored.
+    ///
+    /// ```
+    /// use image::color::space::ColorSpace;
+    /// use image::{DynamicImage, Rgb, RgbImage};
+    ///
+    /// let mut image = DynamicImage::ImageRgb8(RgbImage::from_pixel(1, 1, Rgb([255, 0, 0])));
+    /// image.convert_color_space(ColorSpace::Srgb, ColorSpace::DisplayP3);
+    /// assert_eq!(image.as_rgb8().unwrap().get_pixel(0, 0), &Rgb([234, 51, 35]));
+    /// ```
+    ///
+    /// [`space`]: color/space/index.html
+    pub fn convert_color_space(&mut self, from: ColorSpace, to: ColorSpace) {
+        let conversion = Conversion::new(from, to);
+        dynamic_map!(*self, |ref mut p| space::convert_pixels(p, &conversion))
+    }
+
     /// Encode this image and write it to ```w```.
     ///
     /// Assumes the writer is buffered. In most cases,
     /// you should wrap your writer in a `BufWriter` for best performance.
-    pub fn write_to<W: Write + Seek, F: Into<ImageOutputFormat>>(
+    ///
+    /// The writer does not need to be seekable. Formats whose encoders seek, such as TIFF and
+    /// OpenEXR, are encoded into memory first, which fails if it exceeds the default `Limits`.
+    /// Use [`encoder_for`] to set other limits.
+    ///
+    /// Images with an alpha channel are composited over white for formats that can not store
+    /// it, such as JPEG. Use [`write_to_with_matte`] to choose another color. The [ICC profile]
+    /// of the image is embedded if the format can store it.
+    ///
+    /// [`write_to_with_matte`]: #method.write_to_with_matte
+    /// [`encoder_for`]: fn.encoder_for.html
+    /// [ICC profile]: #method.icc_profile
+    pub fn write_to<W: Write, F: Into<ImageOutputFormat>>(
         &self,
         w: &mut W,
         format: F,
+    ) -> ImageResult<()> {
+        self.write_to_with_matte(w, format, free_functions::DEFAULT_MATTE)
+    }
+
+    /// Encode this image and write it to ```w```, compositing it over `matte` if the format can
+    /// not store its alpha channel.
+    ///
+    /// Otherwise the alpha channel would be dropped, or the encoder would reject the image, which
+    /// turns transparent areas into whatever color their pixels happen to hold, usually black.
+    ///
+    /// ```
+    /// # #[cfg(feature = "jpeg")]
+    /// # fn main() -> image::ImageResult<()> {
+    /// use image::{DynamicImage, ImageOutputFormat, Rgb, RgbaImage};
+    ///
+    /// let transparent = DynamicImage::ImageRgba8(RgbaImage::new(8, 8));
+    /// let mut bytes = Vec::new();
+    /// transparent.write_to_with_matte(&mut bytes, ImageOutputFormat::Jpeg(90), Rgb([255, 0, 0]))?;
+    ///
+    /// let decoded = image::load_from_memory(&bytes)?.into_rgb8();
+    /// assert!(decoded.get_pixel(4, 4)[0] > 250);
+    /// # Ok(())
+    /// # }
+    /// # #[cfg(not(feature = "jpeg"))] fn main() {}
+    /// ```
+    pub fn write_to_with_matte<W: Write, F: Into<ImageOutputFormat>>(
+        &self,
+        w: &mut W,
+        format: F,
+        matte: color::Rgb<u8>,
     ) -> ImageResult<()> {
         #[allow(unused_variables)]
         // When no features are supported
@@ -804,6 +1014,28 @@ impl DynamicImage {
         let mut color = self.color();
         let format = format.into();
 
+        if let Some(icc_profile) =
+            free_functions::embeddable_icc_profile(self.icc_profile(), &format)
+        {
+            return free_functions::write_buffer_with_icc_profile_impl(
+                &mut free_functions::Stream::new(w),
+                bytes,
+                width,
+                height,
+                color,
+                format,
+                matte,
+                Some(icc_profile),
+            );
+        }
+
+        let flattened =
+            free_functions::flatten_for_format(bytes, width, height, color, &format, matte);
+        if let Some((ref flat, flat_color)) = flattened {
+            bytes = flat;
+            color = flat_color;
+        }
+
         // TODO do not repeat this match statement across the crate
 
         #[allow(deprecated)]
@@ -829,13 +1061,54 @@ impl DynamicImage {
                 Ok(())
             }
 
-            format => write_buffer_with_format(w, bytes, width, height, color, format),
+            format => free_functions::write_buffer_impl(
+                &mut free_functions::Stream::new(w),
+                bytes,
+                width,
+                height,
+                color,
+                format,
+                matte,
+            ),
         }
     }
 
+    /// Encode this image and write it to ```w```, embedding the ICC color profile `icc_profile`.
+    ///
+    /// The profile must describe the color space of this image, and replaces the [profile of the
+    /// image] if it has one. Images with an alpha channel are
+    /// composited over white for formats that can not store it, as in [`write_to`].
+    ///
+    /// Returns an `ImageError::Unsupported` if the encoder of the format can not store a profile.
+    /// PNG, JPEG and WebP can.
+    ///
+    /// [profile of the image]: #method.icc_profile
+    /// [`write_to`]: #method.write_to
+    pub fn write_to_with_icc_profile<W: Write, F: Into<ImageOutputFormat>>(
+        &self,
+        w: &mut W,
+        format: F,
+        icc_profile: &[u8],
+    ) -> ImageResult<()> {
+        let (width, height) = self.dimensions();
+        free_functions::write_buffer_with_icc_profile_impl(
+            &mut free_functions::Stream::new(w),
+            self.as_bytes(),
+            width,
+            height,
+            self.color(),
+            format.into(),
+            free_functions::DEFAULT_MATTE,
+            Some(icc_profile),
+        )
+    }
+
     /// Saves the buffer to a file at the path specified.
     ///
-    /// The image format is derived from the file extension.
+    /// The image format is derived from the file extension. The [ICC profile] of the image is
+    /// embedded if the format can store it.
+    ///
+    /// [ICC profile]: #method.icc_profile
     pub fn save<Q>(&self, path: Q) -> ImageResult<()>
     where
         Q: AsRef<Path>,
@@ -843,6 +1116,49 @@ impl DynamicImage {
         dynamic_map!(*self, |ref p| p.save(path))
     }
 
+    /// Saves the buffer to a file at the path specified, compositing it over `matte` if the
+    /// format can not store its alpha channel.
+    ///
+    /// The image format is derived from the file extension. See [`write_to_with_matte`] for
+    /// details.
+    ///
+    /// [`write_to_with_matte`]: #method.write_to_with_matte
+    pub fn save_with_matte<Q>(&self, path: Q, matte: color::Rgb<u8>) -> ImageResult<()>
+    where
+        Q: AsRef<Path>,
+    {
+        let (width, height) = self.dimensions();
+        free_functions::save_buffer_impl(
+            path.as_ref(),
+            self.as_bytes(),
+            width,
+            height,
+            self.color(),
+            matte,
+            self.icc_profile(),
+        )
+    }
+
+    /// Saves the buffer to a file at the path specified, embedding the ICC color profile
+    /// `icc_profile`.
+    ///
+    /// The image format is derived from the file extension. See [`write_to_with_icc_profile`]
+    /// for details.
+    ///
+    /// [`write_to_with_icc_profile`]: #method.write_to_with_icc_profile
+    pub fn save_with_icc_profile<Q>(&self, path: Q, icc_profile: &[u8]) -> ImageResult<()>
+    where
+        Q: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path)?;
+        let format = free_functions::output_format_for_path(path, format)?;
+        let mut file = BufWriter::new(File::create(path)?);
+        self.write_to_with_icc_profile(&mut file, format, icc_profile)?;
+        file.flush()?;
+        Ok(())
+    }
+
     /// Saves the buffer to a file at the specified path in
     /// the specified format.
     ///
@@ -854,6 +1170,27 @@ impl DynamicImage {
     {
         dynamic_map!(*self, |ref p| p.save_with_format(path, format))
     }
+
+    /// Saves the buffer to a file at the path specified, without blocking the executor.
+    ///
+    /// The image format is derived from the file extension. The image is encoded on the blocking
+    /// thread pool of the Tokio runtime and then written asynchronously, see [`io::AsyncWriter`]
+    /// for more control.
+    ///
+    /// [`io::AsyncWriter`]: io/struct.AsyncWriter.html
+    #[cfg(feature = "async")]
+    pub async fn save_async<Q>(&self, path: Q) -> ImageResult<()>
+    where
+        Q: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let format = free_functions::output_format_for_path(path, ImageFormat::from_path(path)?)?;
+        let file = tokio::fs::File::create(path).await?;
+        let (width, height) = self.dimensions();
+        crate::io::AsyncWriter::with_format(file, format)
+            .write_image(self.as_bytes(), width, height, self.color())
+            .await
+    }
 }
 
 impl From<GrayImage> for DynamicImage {
@@ -999,57 +1336,66 @@ impl Default for DynamicImage {
 
 /// Decodes an image and stores it into a dynamic image
 fn decoder_to_image<'a, I: ImageDecoder<'a>>(decoder: I) -> ImageResult<DynamicImage> {
+    decoder_to_image_in(decoder, &GlobalAllocator)
+}
+
+/// Decodes an image into buffers supplied by `allocator`.
+pub(crate) fn decoder_to_image_in<'a, I: ImageDecoder<'a>>(
+    mut decoder: I,
+    allocator: &dyn OutputBufferAllocator,
+) -> ImageResult<DynamicImage> {
     let (w, h) = decoder.dimensions();
     let color_type = decoder.color_type();
+    let icc_profile = decoder.icc_profile();
 
     let image = match color_type {
         color::ColorType::Rgb8 => {
-            let buf = image::decoder_to_vec(decoder)?;
+            let buf = image::decoder_to_vec_in(decoder, allocator)?;
             ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageRgb8)
         }
 
         color::ColorType::Rgba8 => {
-            let buf = image::decoder_to_vec(decoder)?;
+            let buf = image::decoder_to_vec_in(decoder, allocator)?;
             ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageRgba8)
         }
 
         color::ColorType::L8 => {
-            let buf = image::decoder_to_vec(decoder)?;
+            let buf = image::decoder_to_vec_in(decoder, allocator)?;
             ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageLuma8)
         }
 
         color::ColorType::La8 => {
-            let buf = image::decoder_to_vec(decoder)?;
+            let buf = image::decoder_to_vec_in(decoder, allocator)?;
             ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageLumaA8)
         }
 
         color::ColorType::Rgb16 => {
-            let buf = image::decoder_to_vec(decoder)?;
+            let buf = image::decoder_to_vec_in(decoder, allocator)?;
             ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageRgb16)
         }
 
         color::ColorType::Rgba16 => {
-            let buf = image::decoder_to_vec(decoder)?;
+            let buf = image::decoder_to_vec_in(decoder, allocator)?;
             ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageRgba16)
         }
 
         color::ColorType::Rgb32F => {
-            let buf = image::decoder_to_vec(decoder)?;
+            let buf = image::decoder_to_vec_in(decoder, allocator)?;
             ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageRgb32F)
         }
 
         color::ColorType::Rgba32F => {
-            let buf = image::decoder_to_vec(decoder)?;
+            let buf = image::decoder_to_vec_in(decoder, allocator)?;
             ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageRgba32F)
         }
 
         color::ColorType::L16 => {
-            let buf = image::decoder_to_vec(decoder)?;
+            let buf = image::decoder_to_vec_in(decoder, allocator)?;
             ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageLuma16)
         }
 
         color::ColorType::La16 => {
-            let buf = image::decoder_to_vec(decoder)?;
+            let buf = image::decoder_to_vec_in(decoder, allocator)?;
             ImageBuffer::from_raw(w, h, buf).map(DynamicImage::ImageLumaA16)
         }
 
@@ -1066,7 +1412,10 @@ fn decoder_to_image<'a, I: ImageDecoder<'a>>(decoder: I) -> ImageResult<DynamicI
     };
 
     match image {
-        Some(image) => Ok(image),
+        Some(mut image) => {
+            image.set_icc_profile(icc_profile);
+            Ok(image)
+        }
         None => Err(ImageError::Parameter(ParameterError::from_kind(
             ParameterErrorKind::DimensionMismatch,
         ))),
@@ -1088,6 +1437,27 @@ where
     free_functions::open_impl(path.as_ref())
 }
 
+/// Open the image located at the path specified, without blocking the executor.
+/// The image's format is determined from the path's file extension.
+///
+/// The file is read asynchronously and decoded on the blocking thread pool of the Tokio runtime.
+/// Try [`io::AsyncReader`] for more advanced uses.
+///
+/// [`io::AsyncReader`]: io/struct.AsyncReader.html
+#[cfg(feature = "async")]
+pub async fn open_async<P>(path: P) -> ImageResult<DynamicImage>
+where
+    P: AsRef<Path>,
+{
+    let path = path.as_ref();
+    crate::io::AsyncReader::with_format(
+        tokio::io::BufReader::new(tokio::fs::File::open(path).await?),
+        ImageFormat::from_path(path)?,
+    )
+    .decode()
+    .await
+}
+
 /// Read the dimensions of the image located at the specified path.
 /// This is faster than fully loading the image and then getting its dimensions.
 ///
@@ -1121,7 +1491,15 @@ where
     P: AsRef<Path>,
 {
     // thin wrapper function to strip generics before calling save_buffer_impl
-    free_functions::save_buffer_impl(path.as_ref(), buf, width, height, color)
+    free_functions::save_buffer_impl(
+        path.as_ref(),
+        buf,
+        width,
+        height,
+        color,
+        free_functions::DEFAULT_MATTE,
+        None,
+    )
 }
 
 /// Saves the supplied buffer to a file at the path specified
@@ -1144,7 +1522,45 @@ where
     P: AsRef<Path>,
 {
     // thin wrapper function to strip generics
-    free_functions::save_buffer_with_format_impl(path.as_ref(), buf, width, height, color, format)
+    free_functions::save_buffer_with_format_impl(
+        path.as_ref(),
+        buf,
+        width,
+        height,
+        color,
+        format,
+        free_functions::DEFAULT_MATTE,
+        None,
+    )
+}
+
+/// Saves an animation to a file at the path specified.
+///
+/// The format is derived from the file extension, GIF, APNG (with a `.png` extension) and WebP
+/// are supported. Frames are quantized to a palette for GIF, and `options` controls the speed of
+/// the quantization and how often the animation is played.
+///
+/// # Examples
+///
+/// ```no_run
+/// # fn main() -> image::ImageResult<()> {
+/// use image::{AnimationOptions, Delay, Frame, RgbaImage};
+///
+/// let frames = (0..10u8).map(|i| {
+///     let image = RgbaImage::from_pixel(64, 64, image::Rgba([i * 25, 0, 0, 255]));
+///     Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(100, 1))
+/// });
+/// image::save_animation("fade.gif", frames, AnimationOptions::default())?;
+/// # Ok(())
+/// # }
+/// ```
+pub fn save_animation<P, F>(path: P, frames: F, options: AnimationOptions) -> ImageResult<()>
+where
+    P: AsRef<Path>,
+    F: IntoIterator<Item = Frame>,
+{
+    // thin wrapper function to strip generics
+    free_functions::save_animation_impl(path.as_ref(), &mut frames.into_iter(), &options)
 }
 
 /// Writes the supplied buffer to a writer in the specified format.
@@ -1155,10 +1571,14 @@ where
 /// malformed data.
 ///
 /// See [`ImageOutputFormat`](../enum.ImageOutputFormat.html) for
-/// supported types.
+/// supported types. Buffers with an alpha channel are composited over white for formats that
+/// can not store it.
 ///
 /// Assumes the writer is buffered. In most cases,
 /// you should wrap your writer in a `BufWriter` for best performance.
+///
+/// The writer does not need to be seekable. Formats whose encoders seek, such as TIFF and
+/// OpenEXR, are encoded into memory first, which fails if it exceeds the default `Limits`.
 pub fn write_buffer_with_format<W, F>(
     buffered_writer: &mut W,
     buf: &[u8],
@@ -1168,11 +1588,19 @@ pub fn write_buffer_with_format<W, F>(
     format: F,
 ) -> ImageResult<()>
 where
-    W: Write + Seek,
+    W: Write,
     F: Into<ImageOutputFormat>,
 {
     // thin wrapper function to strip generics
-    free_functions::write_buffer_impl(buffered_writer, buf, width, height, color, format.into())
+    free_functions::write_buffer_impl(
+        &mut free_functions::Stream::new(buffered_writer),
+        buf,
+        width,
+        height,
+        color,
+        format.into(),
+        free_functions::DEFAULT_MATTE,
+    )
 }
 
 /// Create a new image from a byte slice
@@ -1240,6 +1668,101 @@ mod test {
         assert_eq!(image.color(), super::color::ColorType::Rgba16);
     }
 
+    #[cfg(all(feature = "gif", feature = "webp"))]
+    #[test]
+    fn save_animations() {
+        use crate::{AnimationDecoder, AnimationOptions, Delay, Frame, RgbaImage};
+
+        let frames: Vec<_> = (0..3u8)
+            .map(|i| {
+                let image = RgbaImage::from_pixel(4, 2, crate::Rgba([i * 100, 0, 0, 255]));
+                Frame::from_parts(image, 0, 0, Delay::from_numer_denom_ms(50, 1))
+            })
+            .collect();
+
+        let dir = std::env::temp_dir();
+        let gif_path = dir.join("image-save-animation.gif");
+        let webp_path = dir.join("image-save-animation.webp");
+        let options = AnimationOptions {
+            quantization_speed: Some(10),
+            ..AnimationOptions::default()
+        };
+        super::save_animation(&gif_path, frames.clone(), options.clone()).unwrap();
+        super::save_animation(&webp_path, frames.clone(), options).unwrap();
+
+        let gif = std::fs::read(&gif_path).unwrap();
+        let webp = std::fs::read(&webp_path).unwrap();
+        std::fs::remove_file(&gif_path).unwrap();
+        std::fs::remove_file(&webp_path).unwrap();
+
+        let decoded = crate::codecs::gif::GifDecoder::new(&gif[..])
+            .unwrap()
+            .into_frames()
+            .collect_frames()
+            .unwrap();
+        assert_eq!(decoded.len(), 3);
+        assert_eq!(
+            decoded[1].buffer().get_pixel(0, 0),
+            &crate::Rgba([100, 0, 0, 255])
+        );
+        assert_eq!(&webp[8..16], b"WEBPVP8X");
+        // Loops forever.
+        assert_eq!(&webp[42..44], &[0, 0]);
+        assert_eq!(webp.windows(4).filter(|w| w == b"ANMF").count(), 3);
+
+        let unsupported = dir.join("image-save-animation.bmp");
+        assert!(super::save_animation(&unsupported, frames, AnimationOptions::default()).is_err());
+        let empty = super::save_animation(&gif_path, Vec::new(), AnimationOptions::default());
+        assert!(empty.is_err());
+    }
+
+    #[cfg(all(feature = "png", feature = "bmp"))]
+    #[test]
+    fn save_keeps_icc_profile() {
+        let icc_profile = vec![7; 200];
+        let mut image = super::DynamicImage::new_rgb8(3, 2);
+        image.set_icc_profile(Some(icc_profile.clone()));
+
+        let dir = std::env::temp_dir();
+        let png_path = dir.join("image-save-keeps-icc-profile.png");
+        let bmp_path = dir.join("image-save-keeps-icc-profile.bmp");
+        image.save(&png_path).unwrap();
+        let decoded = crate::io::Reader::open(&png_path)
+            .unwrap()
+            .decode()
+            .unwrap();
+        assert_eq!(decoded.icc_profile(), Some(&icc_profile[..]));
+        decoded.rotate90().save(&png_path).unwrap();
+        let decoded = super::open(&png_path).unwrap();
+        assert_eq!(decoded.icc_profile(), Some(&icc_profile[..]));
+        // BMP has no place for the profile.
+        decoded.save(&bmp_path).unwrap();
+        assert!(super::open(&bmp_path).unwrap().icc_profile().is_none());
+        std::fs::remove_file(&png_path).unwrap();
+        std::fs::remove_file(&bmp_path).unwrap();
+    }
+
+    #[cfg(all(feature = "async", feature = "pnm"))]
+    #[test]
+    fn save_and_open_async() {
+        let path = std::env::temp_dir().join("image-save-and-open-async.pgm");
+        let image = super::DynamicImage::ImageLuma8(crate::GrayImage::from_fn(5, 3, |x, y| {
+            crate::Luma([(x * y) as u8])
+        }));
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let opened = runtime.block_on(async {
+            image.save_async(&path).await?;
+            super::open_async(&path).await
+        });
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(opened.unwrap().as_bytes(), image.as_bytes());
+
+        let unknown = runtime.block_on(super::open_async("./tests/images/unknown.extension"));
+        assert!(unknown.is_err());
+    }
+
     fn test_grayscale(mut img: super::DynamicImage, alpha_discarded: bool) {
         use crate::image::{GenericImage, GenericImageView};
         img.put_pixel(0, 0, crate::color::Rgba([255, 0, 0, 100]));
@@ -1317,4 +1840,115 @@ mod test {
             image: super::DynamicImage,
         }
     }
+
+    #[cfg(feature = "tiff")]
+    #[test]
+    fn write_to_non_seekable_writer() {
+        let image = super::DynamicImage::new_rgb8(7, 3);
+        // A `Vec<u8>` is `Write` but not `Seek`.
+        let mut encoded = Vec::new();
+        image
+            .write_to(&mut encoded, crate::ImageOutputFormat::Tiff)
+            .unwrap();
+        let decoded =
+            super::load_from_memory_with_format(&encoded, crate::ImageFormat::Tiff).unwrap();
+        assert_eq!(decoded, image);
+    }
+
+    #[cfg(feature = "jpeg")]
+    #[test]
+    fn jpeg_composites_over_matte() {
+        let mut image = crate::RgbaImage::new(16, 16);
+        for x in 8..16 {
+            for y in 0..16 {
+                image.put_pixel(x, y, crate::Rgba([0, 0, 255, 255]));
+            }
+        }
+        let image = super::DynamicImage::ImageRgba8(image);
+
+        let mut encoded = Vec::new();
+        image
+            .write_to(&mut encoded, crate::ImageOutputFormat::Jpeg(100))
+            .unwrap();
+        let decoded = super::load_from_memory(&encoded).unwrap().into_rgb8();
+        assert!(decoded.get_pixel(2, 8).0.iter().all(|&c| c > 250));
+        assert!(decoded.get_pixel(13, 8)[2] > 250);
+
+        let mut encoded = Vec::new();
+        image
+            .write_to_with_matte(
+                &mut encoded,
+                crate::ImageOutputFormat::Jpeg(100),
+                crate::Rgb([0, 0, 0]),
+            )
+            .unwrap();
+        let decoded = super::load_from_memory(&encoded).unwrap().into_rgb8();
+        assert!(decoded.get_pixel(2, 8).0.iter().all(|&c| c < 5));
+    }
+
+    #[cfg(feature = "pnm")]
+    #[test]
+    fn pixmap_composites_over_matte() {
+        use crate::codecs::pnm::{PnmSubtype, SampleEncoding};
+
+        let image = super::DynamicImage::ImageLumaA8(
+            crate::ImageBuffer::from_raw(2, 1, vec![0, 0, 100, 128]).unwrap(),
+        );
+        let mut encoded = Vec::new();
+        image
+            .write_to_with_matte(
+                &mut encoded,
+                crate::ImageOutputFormat::Pnm(PnmSubtype::Graymap(SampleEncoding::Binary)),
+                crate::Rgb([200, 200, 200]),
+            )
+            .unwrap();
+        let decoded = super::load_from_memory(&encoded).unwrap();
+        assert_eq!(decoded.as_bytes(), &[200, 150]);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn png_keeps_alpha() {
+        let image = super::DynamicImage::ImageRgba8(
+            crate::RgbaImage::from_raw(2, 1, vec![10, 20, 30, 0, 40, 50, 60, 128]).unwrap(),
+        );
+        let mut encoded = Vec::new();
+        image
+            .write_to(&mut encoded, crate::ImageOutputFormat::Png)
+            .unwrap();
+        assert_eq!(super::load_from_memory(&encoded).unwrap(), image);
+    }
+
+    #[test]
+    fn tone_mapped_conversions() {
+        use crate::imageops::ToneMapping;
+
+        let image = super::DynamicImage::ImageRgb32F(
+            crate::Rgb32FImage::from_raw(2, 1, vec![0.0, 1.0, 4.0, 0.5, 0.5, 0.5]).unwrap(),
+        );
+        assert_eq!(image.to_rgb32f().as_raw()[2], 4.0);
+
+        let clamped = image.to_rgb8_tone_mapped(ToneMapping::Clamp);
+        assert_eq!(clamped.as_raw(), &[0, 255, 255, 188, 188, 188]);
+        let reinhard = image.to_rgba8_tone_mapped(ToneMapping::Reinhard);
+        assert_eq!(reinhard.get_pixel(0, 0), &crate::Rgba([0, 188, 231, 255]));
+
+        let integer = super::DynamicImage::ImageRgb8(crate::RgbImage::new(1, 1));
+        assert_eq!(
+            integer.to_rgb8_tone_mapped(ToneMapping::Aces),
+            integer.to_rgb8()
+        );
+    }
+
+    #[test]
+    fn quantize() {
+        let image = super::DynamicImage::ImageRgba8(crate::RgbaImage::from_fn(4, 4, |x, _| {
+            crate::Rgba([x as u8 * 60, 0, 0, 255])
+        }));
+        let (palette, indices) = image.quantize(2).unwrap();
+        assert_eq!(palette.len(), 2);
+        assert_eq!(indices.get_pixel(0, 0), indices.get_pixel(1, 0));
+        assert_ne!(indices.get_pixel(0, 0), indices.get_pixel(3, 0));
+        assert!(image.quantize(0).is_err());
+    }
 }
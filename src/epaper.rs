@@ -0,0 +1,202 @@
+//! Preparation of images for e-paper displays.
+//!
+//! E-paper panels show only a few colors, so images are dithered to the [`Palette`] of a
+//! [`Panel`] and sent to the display driver as bit packed buffers. [`encode`] does both in the
+//! layout that common drivers, such as those of Waveshare panels, expect.
+//!
+//! ```
+//! use image::epaper::{encode, Panel};
+//! use image::{Rgb, RgbImage};
+//!
+//! let image = RgbImage::from_fn(16, 2, |x, _| Rgb([if x < 8 { 0 } else { 255 }; 3]));
+//! let buffers = encode(&image, Panel::BlackWhite);
+//! // Black pixels are cleared bits, eight pixels to a byte.
+//! assert_eq!(buffers, vec![vec![0x00, 0xFF, 0x00, 0xFF]]);
+//! ```
+//!
+//! [`Palette`]: ../palette/struct.Palette.html
+//! [`Panel`]: enum.Panel.html
+//! [`encode`]: fn.encode.html
+
+use crate::imageops::dither::{dither_with, DitherAlgorithm};
+use crate::imageops::index_colors;
+use crate::palette::Palette;
+use crate::{GrayImage, Rgb, RgbImage};
+
+/// The colors of a kind of e-paper panel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Panel {
+    /// Black and white.
+    BlackWhite,
+    /// Black, white and red, as in tri-color panels.
+    BlackWhiteRed,
+    /// Black, white, green, blue, red, yellow and orange, as in seven color ACeP panels.
+    Acep7,
+}
+
+impl Panel {
+    /// The colors of the panel, in the order of the indices that its driver uses.
+    pub fn palette(self) -> Palette {
+        let colors = match self {
+            Panel::BlackWhite => vec![Rgb([0, 0, 0]), Rgb([255, 255, 255])],
+            Panel::BlackWhiteRed => vec![Rgb([0, 0, 0]), Rgb([255, 255, 255]), Rgb([255, 0, 0])],
+            Panel::Acep7 => vec![
+                Rgb([0, 0, 0]),
+                Rgb([255, 255, 255]),
+                Rgb([0, 255, 0]),
+                Rgb([0, 0, 255]),
+                Rgb([255, 0, 0]),
+                Rgb([255, 255, 0]),
+                Rgb([255, 128, 0]),
+            ],
+        };
+        Palette::new(colors)
+    }
+}
+
+/// The order of pixels within a byte of a bit packed buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BitOrder {
+    /// The first pixel is in the most significant bits.
+    MsbFirst,
+    /// The first pixel is in the least significant bits.
+    LsbFirst,
+}
+
+/// Dither an image to the colors of a panel with Floyd-Steinberg error diffusion.
+///
+/// Returns the index of the color of each pixel in the [`palette`] of the panel.
+///
+/// [`palette`]: enum.Panel.html#method.palette
+pub fn dither_to_panel(image: &RgbImage, panel: Panel) -> GrayImage {
+    let palette = panel.palette();
+    let mut image = image.clone();
+    dither_with(&mut image, &palette, DitherAlgorithm::FloydSteinberg);
+    index_colors(&image, &palette)
+}
+
+/// Pack small values into bytes, `bits_per_pixel` bits for each.
+///
+/// Each row starts at a new byte, as display controllers expect, with the unused bits of the last
+/// byte of a row set to zero. Values are truncated to their lowest `bits_per_pixel` bits.
+///
+/// # Panics
+///
+/// Panics if `bits_per_pixel` is not 1, 2, 4 or 8.
+pub fn pack_bits(indices: &GrayImage, bits_per_pixel: u8, order: BitOrder) -> Vec<u8> {
+    assert!(
+        [1, 2, 4, 8].contains(&bits_per_pixel),
+        "unsupported bits per pixel: {}",
+        bits_per_pixel
+    );
+    let bits = usize::from(bits_per_pixel);
+    let per_byte = 8 / bits;
+    let mask = ((1u16 << bits) - 1) as u8;
+
+    let mut packed = Vec::new();
+    for row in indices.rows() {
+        let row: Vec<u8> = row.map(|p| p[0] & mask).collect();
+        for chunk in row.chunks(per_byte) {
+            let mut byte = 0;
+            for (i, &value) in chunk.iter().enumerate() {
+                let shift = match order {
+                    BitOrder::MsbFirst => 8 - bits * (i + 1),
+                    BitOrder::LsbFirst => bits * i,
+                };
+                byte |= value << shift;
+            }
+            packed.push(byte);
+        }
+    }
+    packed
+}
+
+/// Dither an image for a panel and pack it into the buffers its driver expects.
+///
+/// * Black and white panels take one buffer with a bit for each pixel, cleared for black.
+/// * Tri-color panels take two such buffers. In the first a cleared bit is black, in the second
+///   a cleared bit is red.
+/// * Seven color panels take one buffer with four bits for each pixel, the index of its color in
+///   the [`palette`] of the panel.
+///
+/// The first pixel of each byte is in the most significant bits and every row starts at a new
+/// byte. Use [`dither_to_panel`] and [`pack_bits`] for drivers with other layouts.
+///
+/// [`palette`]: enum.Panel.html#method.palette
+/// [`dither_to_panel`]: fn.dither_to_panel.html
+/// [`pack_bits`]: fn.pack_bits.html
+pub fn encode(image: &RgbImage, panel: Panel) -> Vec<Vec<u8>> {
+    let indices = dither_to_panel(image, panel);
+    let plane = |ink: u8| {
+        let mut bits = indices.clone();
+        for pixel in bits.pixels_mut() {
+            pixel[0] = u8::from(pixel[0] != ink);
+        }
+        pack_bits(&bits, 1, BitOrder::MsbFirst)
+    };
+    match panel {
+        Panel::BlackWhite => vec![plane(0)],
+        Panel::BlackWhiteRed => vec![plane(0), plane(2)],
+        Panel::Acep7 => vec![pack_bits(&indices, 4, BitOrder::MsbFirst)],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Luma;
+
+    #[test]
+    fn packs_rows_separately() {
+        let indices = GrayImage::from_fn(3, 2, |x, y| Luma([(x + y * 3) as u8]));
+        assert_eq!(
+            pack_bits(&indices, 8, BitOrder::MsbFirst),
+            [0, 1, 2, 3, 4, 5]
+        );
+        assert_eq!(
+            pack_bits(&indices, 4, BitOrder::MsbFirst),
+            [0x01, 0x20, 0x34, 0x50]
+        );
+        assert_eq!(
+            pack_bits(&indices, 4, BitOrder::LsbFirst),
+            [0x10, 0x02, 0x43, 0x05]
+        );
+        assert_eq!(pack_bits(&indices, 1, BitOrder::MsbFirst), [0x40, 0xA0]);
+        assert_eq!(pack_bits(&indices, 2, BitOrder::LsbFirst), [0x24, 0x13]);
+    }
+
+    #[test]
+    fn tri_color_planes() {
+        let image = RgbImage::from_fn(8, 1, |x, _| match x % 3 {
+            0 => Rgb([10, 0, 0]),
+            1 => Rgb([250, 250, 250]),
+            _ => Rgb([240, 20, 10]),
+        });
+        let buffers = encode(&image, Panel::BlackWhiteRed);
+        assert_eq!(buffers, vec![vec![0b0110_1101], vec![0b1101_1011]]);
+    }
+
+    #[test]
+    fn acep_indices() {
+        let colors = Panel::Acep7.palette().into_colors();
+        let image = RgbImage::from_fn(7, 1, |x, _| colors[x as usize]);
+        assert_eq!(
+            encode(&image, Panel::Acep7),
+            vec![vec![0x01, 0x23, 0x45, 0x60]]
+        );
+    }
+
+    #[test]
+    fn dithers_gray() {
+        let image = RgbImage::from_pixel(16, 16, Rgb([128, 128, 128]));
+        let indices = dither_to_panel(&image, Panel::BlackWhite);
+        let white = indices.pixels().filter(|p| p[0] == 1).count();
+        assert!((100..=156).contains(&white), "{}", white);
+
+        // A single column is dithered as well.
+        let column = RgbImage::from_pixel(1, 16, Rgb([128, 128, 128]));
+        let indices = dither_to_panel(&column, Panel::BlackWhite);
+        let white = indices.pixels().filter(|p| p[0] == 1).count();
+        assert!((4..=12).contains(&white), "{}", white);
+    }
+}
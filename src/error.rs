@@ -170,6 +170,10 @@ pub enum LimitErrorKind {
         /// The supported strict limits
         supported: crate::io::LimitSupport,
     },
+    /// Decoding was cancelled with a `CancelToken`.
+    Cancelled,
+    /// Decoding was aborted because its deadline passed.
+    DeadlineExceeded,
 }
 
 /// A best effort representation for image formats.
@@ -471,6 +475,8 @@ impl fmt::Display for LimitError {
                 write!(fmt, "The following strict limits are specified but not supported by the opertation: ")?;
                 Ok(())
             }
+            LimitErrorKind::Cancelled => write!(fmt, "Decoding was cancelled"),
+            LimitErrorKind::DeadlineExceeded => write!(fmt, "The deadline for decoding passed"),
         }
     }
 }
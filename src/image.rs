@@ -3,6 +3,7 @@ use std::convert::TryFrom;
 use std::ffi::OsStr;
 use std::io;
 use std::io::Read;
+use std::mem::MaybeUninit;
 use std::ops::{Deref, DerefMut};
 use std::path::Path;
 use std::usize;
@@ -10,9 +11,13 @@ use std::usize;
 use crate::color::{ColorType, ExtendedColorType};
 use crate::error::{
     ImageError, ImageFormatHint, ImageResult, LimitError, LimitErrorKind, ParameterError,
-    ParameterErrorKind,
+    ParameterErrorKind, UnsupportedError, UnsupportedErrorKind,
 };
+use crate::imageops::neighborhood::Windows;
+use crate::imageops::sample::{self, EdgeMode};
+use crate::io::allocator::{OutputBufferAllocator, OutputSample};
 use crate::math::Rect;
+use crate::metadata::{Exif, MetadataMap};
 use crate::traits::Pixel;
 use crate::ImageBuffer;
 
@@ -56,6 +61,9 @@ pub enum ImageFormat {
     /// An Image in ICO Format
     Ico,
 
+    /// An Image in ICNS Format
+    Icns,
+
     /// An Image in Radiance HDR Format
     Hdr,
 
@@ -67,6 +75,27 @@ pub enum ImageFormat {
 
     /// An Image in AVIF format.
     Avif,
+
+    /// An Image in FITS Format
+    Fits,
+
+    /// An Image in DICOM Format
+    Dicom,
+
+    /// An Image in JPEG XS Format
+    JpegXs,
+
+    /// An Image in JPEG XL Format
+    Jxl,
+
+    /// An Image in HEIF Format, including HEIC
+    Heif,
+
+    /// An Image in SVG Format, rasterized when decoded
+    Svg,
+
+    /// An Image in QOI Format
+    Qoi,
 }
 
 impl ImageFormat {
@@ -91,7 +120,8 @@ impl ImageFormat {
 
             Some(match ext.as_str() {
                 "avif" => ImageFormat::Avif,
-                "jpg" | "jpeg" => ImageFormat::Jpeg,
+                // Stereo and multi-picture files start with a regular JPEG image.
+                "jpg" | "jpeg" | "jps" | "mpo" => ImageFormat::Jpeg,
                 "png" => ImageFormat::Png,
                 "gif" => ImageFormat::Gif,
                 "webp" => ImageFormat::WebP,
@@ -100,10 +130,18 @@ impl ImageFormat {
                 "dds" => ImageFormat::Dds,
                 "bmp" => ImageFormat::Bmp,
                 "ico" => ImageFormat::Ico,
+                "icns" => ImageFormat::Icns,
                 "hdr" => ImageFormat::Hdr,
                 "exr" => ImageFormat::OpenExr,
                 "pbm" | "pam" | "ppm" | "pgm" => ImageFormat::Pnm,
                 "ff" | "farbfeld" => ImageFormat::Farbfeld,
+                "fits" | "fit" | "fts" => ImageFormat::Fits,
+                "dcm" | "dicom" => ImageFormat::Dicom,
+                "jxs" => ImageFormat::JpegXs,
+                "jxl" => ImageFormat::Jxl,
+                "heic" | "heif" | "hif" => ImageFormat::Heif,
+                "svg" | "svgz" => ImageFormat::Svg,
+                "qoi" => ImageFormat::Qoi,
                 _ => return None,
             })
         }
@@ -170,12 +208,20 @@ impl ImageFormat {
             "image/vnd-ms.dds" => Some(ImageFormat::Dds),
             "image/bmp" => Some(ImageFormat::Bmp),
             "image/x-icon" => Some(ImageFormat::Ico),
+            "image/icns" => Some(ImageFormat::Icns),
             "image/vnd.radiance" => Some(ImageFormat::Hdr),
             "image/x-exr" => Some(ImageFormat::OpenExr),
             "image/x-portable-bitmap"
             | "image/x-portable-graymap"
             | "image/x-portable-pixmap"
             | "image/x-portable-anymap" => Some(ImageFormat::Pnm),
+            "image/fits" => Some(ImageFormat::Fits),
+            "application/dicom" => Some(ImageFormat::Dicom),
+            "image/jxs" => Some(ImageFormat::JpegXs),
+            "image/jxl" => Some(ImageFormat::Jxl),
+            "image/heic" | "image/heif" => Some(ImageFormat::Heif),
+            "image/svg+xml" => Some(ImageFormat::Svg),
+            "image/qoi" => Some(ImageFormat::Qoi),
             _ => None,
         }
     }
@@ -194,11 +240,28 @@ impl ImageFormat {
             ImageFormat::Dds => false,
             ImageFormat::Bmp => true,
             ImageFormat::Ico => true,
+            ImageFormat::Icns => true,
             ImageFormat::Hdr => true,
             ImageFormat::OpenExr => true,
             ImageFormat::Pnm => true,
             ImageFormat::Farbfeld => true,
             ImageFormat::Avif => true,
+            ImageFormat::Fits => true,
+            ImageFormat::Dicom => true,
+            #[cfg(feature = "jpegxs")]
+            ImageFormat::JpegXs => crate::codecs::jpegxs::has_decoder(),
+            #[cfg(not(feature = "jpegxs"))]
+            ImageFormat::JpegXs => false,
+            #[cfg(feature = "jxl")]
+            ImageFormat::Jxl => crate::codecs::jxl::has_decoder(),
+            #[cfg(not(feature = "jxl"))]
+            ImageFormat::Jxl => false,
+            #[cfg(feature = "heif")]
+            ImageFormat::Heif => crate::codecs::heif::has_decoder(),
+            #[cfg(not(feature = "heif"))]
+            ImageFormat::Heif => false,
+            ImageFormat::Svg => true,
+            ImageFormat::Qoi => true,
         }
     }
 
@@ -209,6 +272,7 @@ impl ImageFormat {
         match self {
             ImageFormat::Gif => true,
             ImageFormat::Ico => true,
+            ImageFormat::Icns => true,
             ImageFormat::Jpeg => true,
             ImageFormat::Png => true,
             ImageFormat::Bmp => true,
@@ -217,10 +281,20 @@ impl ImageFormat {
             ImageFormat::Pnm => true,
             ImageFormat::Farbfeld => true,
             ImageFormat::Avif => true,
-            ImageFormat::WebP => false,
+            ImageFormat::WebP => true,
             ImageFormat::Hdr => false,
             ImageFormat::OpenExr => true,
             ImageFormat::Dds => false,
+            ImageFormat::Fits => true,
+            ImageFormat::Dicom => false,
+            ImageFormat::JpegXs => false,
+            #[cfg(feature = "jxl")]
+            ImageFormat::Jxl => crate::codecs::jxl::has_encoder(),
+            #[cfg(not(feature = "jxl"))]
+            ImageFormat::Jxl => false,
+            ImageFormat::Heif => false,
+            ImageFormat::Svg => false,
+            ImageFormat::Qoi => true,
         }
     }
 
@@ -245,13 +319,44 @@ impl ImageFormat {
             ImageFormat::Dds => &["dds"],
             ImageFormat::Bmp => &["bmp"],
             ImageFormat::Ico => &["ico"],
+            ImageFormat::Icns => &["icns"],
             ImageFormat::Hdr => &["hdr"],
             ImageFormat::OpenExr => &["exr"],
             ImageFormat::Farbfeld => &["ff"],
             // According to: https://aomediacodec.github.io/av1-avif/#mime-registration
             ImageFormat::Avif => &["avif"],
+            ImageFormat::Fits => &["fits", "fit", "fts"],
+            ImageFormat::Dicom => &["dcm", "dicom"],
+            ImageFormat::JpegXs => &["jxs"],
+            ImageFormat::Jxl => &["jxl"],
+            ImageFormat::Heif => &["heic", "heif", "hif"],
+            ImageFormat::Svg => &["svg", "svgz"],
+            ImageFormat::Qoi => &["qoi"],
         }
     }
+
+    /// Return the color types that the codec of this format handles in the given direction.
+    ///
+    /// See [`ColorType::is_supported_by`] for how the codec is asked. The result is empty if the
+    /// codec is not enabled.
+    ///
+    /// [`ColorType::is_supported_by`]: enum.ColorType.html#method.is_supported_by
+    pub fn supported_color_types(self, direction: Direction) -> Vec<ColorType> {
+        crate::io::free_functions::ALL_COLOR_TYPES
+            .iter()
+            .copied()
+            .filter(|color| color.is_supported_by(self, direction))
+            .collect()
+    }
+}
+
+/// The direction of data through an image codec.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Decoding an image from its encoded representation.
+    Read,
+    /// Encoding an image into its encoded representation.
+    Write,
 }
 
 /// An enumeration of supported image formats for encoding.
@@ -278,6 +383,10 @@ pub enum ImageOutputFormat {
     /// An Image in ICO Format
     Ico,
 
+    #[cfg(feature = "icns")]
+    /// An Image in ICNS Format
+    Icns,
+
     #[cfg(feature = "bmp")]
     /// An Image in BMP Format
     Bmp,
@@ -302,6 +411,26 @@ pub enum ImageOutputFormat {
     /// An image in AVIF Format
     Avif,
 
+    #[cfg(feature = "avif-encoder")]
+    /// An image in AVIF Format with the specified speed, quality and alpha handling
+    AvifWithOptions(crate::codecs::avif::AvifOptions),
+
+    #[cfg(feature = "fits")]
+    /// An Image in FITS Format
+    Fits,
+
+    #[cfg(feature = "webp")]
+    /// An Image in lossless WebP Format
+    WebP,
+
+    #[cfg(feature = "jxl")]
+    /// An Image in JPEG XL Format, encoded by the registered encoder
+    Jxl,
+
+    #[cfg(feature = "qoi")]
+    /// An Image in QOI Format
+    Qoi,
+
     /// A value for signalling an error: An unsupported format was requested
     // Note: When TryFrom is stabilized, this value should not be needed, and
     // a TryInto<ImageOutputFormat> should be used instead of an Into<ImageOutputFormat>.
@@ -321,6 +450,8 @@ impl From<ImageFormat> for ImageOutputFormat {
             ImageFormat::Gif => ImageOutputFormat::Gif,
             #[cfg(feature = "ico")]
             ImageFormat::Ico => ImageOutputFormat::Ico,
+            #[cfg(feature = "icns")]
+            ImageFormat::Icns => ImageOutputFormat::Icns,
             #[cfg(feature = "bmp")]
             ImageFormat::Bmp => ImageOutputFormat::Bmp,
             #[cfg(feature = "farbfeld")]
@@ -334,12 +465,76 @@ impl From<ImageFormat> for ImageOutputFormat {
 
             #[cfg(feature = "avif-encoder")]
             ImageFormat::Avif => ImageOutputFormat::Avif,
+            #[cfg(feature = "fits")]
+            ImageFormat::Fits => ImageOutputFormat::Fits,
+            #[cfg(feature = "webp")]
+            ImageFormat::WebP => ImageOutputFormat::WebP,
+            #[cfg(feature = "jxl")]
+            ImageFormat::Jxl => ImageOutputFormat::Jxl,
+            #[cfg(feature = "qoi")]
+            ImageFormat::Qoi => ImageOutputFormat::Qoi,
 
             f => ImageOutputFormat::Unsupported(format!("{:?}", f)),
         }
     }
 }
 
+/// Options for the encoder returned by [`encoder_for`].
+///
+/// Construct it with `Default` and set the fields of interest, options that do not apply to the
+/// chosen format are ignored.
+///
+/// [`encoder_for`]: fn.encoder_for.html
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct EncoderOptions {
+    /// The quality of lossy formats, from 1 to 100. JPEG defaults to 75, AVIF to 100.
+    pub quality: Option<u8>,
+
+    /// The encoding speed of formats that trade it for size, from 0 (slowest) to 10. AVIF
+    /// defaults to 1.
+    pub speed: Option<u8>,
+
+    #[cfg(feature = "pnm")]
+    /// The PNM subtype to write, defaults to `PnmSubtype::ArbitraryMap`.
+    pub pnm_subtype: Option<PnmSubtype>,
+    /// The limits on the memory used to encode formats that need to seek in their output, such
+    /// as TIFF, which are encoded into memory first. Defaults to `Limits::default()`.
+    pub limits: crate::io::Limits,
+
+    /// Whether to decode the encoded image again and check it against the source before writing
+    /// it. Defaults to the process-wide setting of [`set_encoder_self_check`].
+    ///
+    /// [`set_encoder_self_check`]: io/fn.set_encoder_self_check.html
+    pub self_check: Option<bool>,
+}
+
+impl EncoderOptions {
+    /// The output format for `format` with these options applied.
+    pub(crate) fn output_format(&self, format: ImageFormat) -> ImageOutputFormat {
+        match ImageOutputFormat::from(format) {
+            #[cfg(feature = "jpeg")]
+            ImageOutputFormat::Jpeg(quality) => {
+                ImageOutputFormat::Jpeg(self.quality.unwrap_or(quality))
+            }
+            #[cfg(feature = "pnm")]
+            ImageOutputFormat::Pnm(subtype) => {
+                ImageOutputFormat::Pnm(self.pnm_subtype.unwrap_or(subtype))
+            }
+            #[cfg(feature = "avif-encoder")]
+            ImageOutputFormat::Avif => {
+                let defaults = crate::codecs::avif::AvifOptions::default();
+                ImageOutputFormat::AvifWithOptions(crate::codecs::avif::AvifOptions {
+                    quality: self.quality.unwrap_or(defaults.quality),
+                    speed: self.speed.unwrap_or(defaults.speed),
+                    ..defaults
+                })
+            }
+            other => other,
+        }
+    }
+}
+
 // This struct manages buffering associated with implementing `Read` and `Seek` on decoders that can
 // must decode ranges of bytes at a time.
 #[allow(dead_code)]
@@ -566,6 +761,73 @@ where
     Ok(seek_scanline(decoder, 0)?)
 }
 
+/// Checks that a rectangle requested from `decoder` lies within the image and fits into `buf`.
+///
+/// Returns the number of bytes of the rectangle. Like [`load_rect`], this panics if the buffer is
+/// too short for the rectangle.
+///
+/// [`load_rect`]: fn.load_rect.html
+#[allow(dead_code)]
+// When no image formats that use it are enabled
+pub(crate) fn check_rect<'a, D: ImageDecoder<'a>>(
+    decoder: &D,
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    buf: &[u8],
+) -> ImageResult<usize> {
+    let (image_width, image_height) = decoder.dimensions();
+    if u64::from(x) + u64::from(width) > u64::from(image_width)
+        || u64::from(y) + u64::from(height) > u64::from(image_height)
+        || width == 0
+        || height == 0
+    {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::DimensionMismatch,
+        )));
+    }
+
+    let total_bytes =
+        u64::from(decoder.color_type().bytes_per_pixel()) * u64::from(width) * u64::from(height);
+    if buf.len() < usize::try_from(total_bytes).unwrap_or(usize::MAX) {
+        panic!(
+            "output buffer too short\n expected `{}`, provided `{}`",
+            total_bytes,
+            buf.len()
+        );
+    }
+    Ok(total_bytes as usize)
+}
+
+/// Fill `buf` with zeros, for decoders that can only write into initialized memory.
+pub(crate) fn zero_uninit(buf: &mut [MaybeUninit<u8>]) -> &mut [u8] {
+    for byte in buf.iter_mut() {
+        *byte = MaybeUninit::new(0);
+    }
+    // Safety: every byte was just initialized.
+    unsafe { assume_init(buf) }
+}
+
+/// Copy `src` to the start of `dst`, which need not be initialized.
+#[allow(dead_code)]
+// When no image formats that use it are enabled
+pub(crate) fn copy_to_uninit(dst: &mut [MaybeUninit<u8>], src: &[u8]) {
+    for (dst, &src) in dst[..src.len()].iter_mut().zip(src) {
+        *dst = MaybeUninit::new(src);
+    }
+}
+
+/// View a buffer whose bytes were all written as initialized.
+///
+/// # Safety
+///
+/// Every byte of `buf` must be initialized.
+pub(crate) unsafe fn assume_init(buf: &mut [MaybeUninit<u8>]) -> &mut [u8] {
+    // `MaybeUninit<u8>` has the layout of `u8`.
+    &mut *(buf as *mut [MaybeUninit<u8>] as *mut [u8])
+}
+
 /// Reads all of the bytes of a decoder into a Vec<T>. No particular alignment
 /// of the output buffer is guaranteed.
 ///
@@ -574,18 +836,39 @@ pub(crate) fn decoder_to_vec<'a, T>(decoder: impl ImageDecoder<'a>) -> ImageResu
 where
     T: crate::traits::Primitive + bytemuck::Pod,
 {
-    let total_bytes = usize::try_from(decoder.total_bytes());
-    if total_bytes.is_err() || total_bytes.unwrap() > isize::max_value() as usize {
-        return Err(ImageError::Limits(LimitError::from_kind(
-            LimitErrorKind::InsufficientMemory,
-        )));
-    }
+    let mut buf = vec![num_traits::Zero::zero(); decoder_samples::<T>(decoder.total_bytes())?];
+    decoder.read_image(bytemuck::cast_slice_mut(buf.as_mut_slice()))?;
+    Ok(buf)
+}
 
-    let mut buf = vec![num_traits::Zero::zero(); total_bytes.unwrap() / std::mem::size_of::<T>()];
+/// Reads all of the bytes of a decoder into a buffer supplied by `allocator`.
+pub(crate) fn decoder_to_vec_in<'a, T>(
+    decoder: impl ImageDecoder<'a>,
+    allocator: &dyn OutputBufferAllocator,
+) -> ImageResult<Vec<T>>
+where
+    T: OutputSample,
+{
+    let len = decoder_samples::<T>(decoder.total_bytes())?;
+    let mut buf = T::allocate(allocator, len);
+    // The decoder overwrites all samples, a recycled buffer keeps its old ones until then.
+    buf.resize(len, num_traits::Zero::zero());
     decoder.read_image(bytemuck::cast_slice_mut(buf.as_mut_slice()))?;
     Ok(buf)
 }
 
+/// The number of samples of type `T` in `total_bytes`, if a buffer of them can be allocated.
+fn decoder_samples<T>(total_bytes: u64) -> ImageResult<usize> {
+    match usize::try_from(total_bytes) {
+        Ok(total_bytes) if total_bytes <= isize::MAX as usize => {
+            Ok(total_bytes / std::mem::size_of::<T>())
+        }
+        _ => Err(ImageError::Limits(LimitError::from_kind(
+            LimitErrorKind::InsufficientMemory,
+        ))),
+    }
+}
+
 /// Represents the progress of an image operation.
 ///
 /// Note that this is not necessarily accurate and no change to the values passed to the progress
@@ -637,6 +920,70 @@ pub trait ImageDecoder<'a>: Sized {
         self.color_type().into()
     }
 
+    /// Returns the ICC color profile embedded in the image, or `None` if the image has none.
+    ///
+    /// The profile describes the color space of the decoded samples. Without it, they are
+    /// usually assumed to be sRGB, which shifts the colors of images in a wider gamut. Keep the
+    /// profile and pass it to [`ImageEncoder::set_icc_profile`] to preserve them when the image
+    /// is encoded again.
+    ///
+    /// [`ImageEncoder::set_icc_profile`]: trait.ImageEncoder.html#method.set_icc_profile
+    fn icc_profile(&mut self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Returns the EXIF metadata of the image, or `None` if it has none or it could not be read.
+    ///
+    /// Pass it to [`ImageEncoder::set_exif_metadata`] to preserve it when the image is encoded
+    /// again. Its orientation is not applied to the decoded samples, see
+    /// [`DynamicImage::apply_orientation`].
+    ///
+    /// [`ImageEncoder::set_exif_metadata`]: trait.ImageEncoder.html#method.set_exif_metadata
+    /// [`DynamicImage::apply_orientation`]: enum.DynamicImage.html#method.apply_orientation
+    fn exif_metadata(&mut self) -> Option<Exif> {
+        None
+    }
+
+    /// Returns the textual metadata of the image and its XMP packet, which is empty if the image
+    /// has none.
+    ///
+    /// Pass it to [`ImageEncoder::set_text_metadata`] to preserve entries such as the copyright
+    /// of the image when it is encoded again.
+    ///
+    /// [`ImageEncoder::set_text_metadata`]: trait.ImageEncoder.html#method.set_text_metadata
+    fn text_metadata(&mut self) -> MetadataMap {
+        MetadataMap::new()
+    }
+
+    /// Asks the decoder to produce samples of `color_type` instead of its own color type.
+    ///
+    /// Decoders that can convert their samples while they are decoded, such as expanding RGB to
+    /// RGBA as the rows are copied out, return `true`. After that `color_type` and `total_bytes`
+    /// describe the requested color type, while `original_color_type` still describes the file.
+    /// Otherwise `false` is returned and the samples should be converted after decoding, see
+    /// [`io::Reader::decode_as`].
+    ///
+    /// [`io::Reader::decode_as`]: io/struct.Reader.html#method.decode_as
+    fn request_color_type(&mut self, color_type: ColorType) -> bool {
+        color_type == self.color_type()
+    }
+
+    /// Asks the decoder to decode a reduced resolution of the image, of at least `width` and
+    /// `height`, instead of its full resolution.
+    ///
+    /// Decoders that can skip the detail of large images return `true` if they reduced the
+    /// resolution, such as the JPEG decoder, which scales its IDCT by 1/2, 1/4 or 1/8, and the
+    /// TIFF decoder, which selects a reduced-resolution page that follows the image. After that
+    /// `dimensions` and `total_bytes` describe the reduced image, which lies between the
+    /// requested and the full resolution. Otherwise `false` is returned and the image should be
+    /// downscaled after decoding, see [`Limits::max_megapixels_with_downscale`].
+    ///
+    /// [`Limits::max_megapixels_with_downscale`]: io/struct.Limits.html#structfield.max_megapixels_with_downscale
+    fn request_dimensions(&mut self, width: u32, height: u32) -> bool {
+        let _ = (width, height);
+        false
+    }
+
     /// Returns a reader that can be used to obtain the bytes of the image. For the best
     /// performance, always try to read at least `scanline_bytes` from the reader at a time. Reading
     /// fewer bytes will cause the reader to perform internal buffering.
@@ -686,6 +1033,21 @@ pub trait ImageDecoder<'a>: Sized {
         self.read_image_with_progress(buf, |_| {})
     }
 
+    /// Same as `read_image` but into memory that need not be initialized.
+    ///
+    /// This decodes straight into memory that was allocated for another purpose, such as a GPU
+    /// staging buffer or a mapped region of shared memory, without first filling it. The whole
+    /// buffer is initialized when this returns `Ok`. The default implementation fills the buffer
+    /// with zeros and calls `read_image`. The PNG, JPEG and TIFF decoders copy the decoded image
+    /// into the buffer without filling it first, unless they have to convert its samples.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if buf.len() != self.total_bytes().
+    fn read_image_into_uninit(self, buf: &mut [MaybeUninit<u8>]) -> ImageResult<()> {
+        self.read_image(zero_uninit(buf))
+    }
+
     /// Same as `read_image` but periodically calls the provided callback to give updates on loading
     /// progress.
     fn read_image_with_progress<F: Fn(Progress)>(
@@ -781,11 +1143,227 @@ pub trait ImageDecoderRect<'a>: ImageDecoder<'a> + Sized {
 /// AnimationDecoder trait
 pub trait AnimationDecoder<'a> {
     /// Consume the decoder producing a series of frames.
+    ///
+    /// Every frame is composited onto the frames before it and covers the whole canvas.
     fn into_frames(self) -> Frames<'a>;
+
+    /// Consume the decoder producing the frames as they are stored in the file.
+    ///
+    /// Frames are not composited, each covers only its own region of the canvas at the offset
+    /// given by `Frame::left` and `Frame::top`. This is what editors and custom compositors need.
+    /// The default implementation returns the composited frames of `into_frames`.
+    fn into_raw_frames(self) -> Frames<'a>
+    where
+        Self: Sized,
+    {
+        self.into_frames()
+    }
+}
+
+/// Decoder for files that hold several independent images, such as the pages of a TIFF file or
+/// the entries of an ICO file.
+///
+/// The [`ImageDecoder`] methods of the decoder describe and decode the selected image. Which image
+/// is selected initially depends on the format, for example the first page of a TIFF file but the
+/// largest entry of an ICO file.
+///
+/// ```no_run
+/// # #[cfg(feature = "tiff")] {
+/// use image::{ImageDecoder, MultiImageDecoder};
+/// use image::codecs::tiff::TiffDecoder;
+///
+/// # fn main() -> image::ImageResult<()> {
+/// let mut decoder = TiffDecoder::new(std::io::BufReader::new(std::fs::File::open("pages.tiff")?))?;
+/// for page in 0..decoder.image_count()? {
+///     decoder.select_image(page)?;
+///     let mut buf = vec![0; decoder.total_bytes() as usize];
+///     decoder.read_page(page, &mut buf)?;
+///     println!("page {} is {:?}", page, decoder.dimensions());
+/// }
+/// # Ok(())
+/// # }
+/// # }
+/// ```
+///
+/// [`ImageDecoder`]: trait.ImageDecoder.html
+pub trait MultiImageDecoder<'a>: ImageDecoder<'a> {
+    /// Returns the number of images in the file.
+    fn image_count(&mut self) -> ImageResult<usize>;
+
+    /// Selects the image at `index` for all further calls to the `ImageDecoder` methods.
+    ///
+    /// Returns an `ImageError::Parameter` if there is no image at `index`.
+    fn select_image(&mut self, index: usize) -> ImageResult<()>;
+
+    /// Selects the image at `index` and decodes it into `buf`, keeping the decoder usable for
+    /// the other images.
+    ///
+    /// Returns an `ImageError::Parameter` if there is no image at `index`.
+    ///
+    /// # Panics
+    ///
+    /// This function panics if `buf.len() != self.total_bytes()` after selecting the image.
+    fn read_page(&mut self, index: usize, buf: &mut [u8]) -> ImageResult<()>;
+}
+
+/// Encoder for files that hold several independent images, such as the pages of a TIFF file or
+/// the entries of an ICO file.
+pub trait MultiImageEncoder {
+    /// Adds an image to the file.
+    ///
+    /// The pixel data in `buf` is in native endian, as for `ImageEncoder::write_image`.
+    fn append_image(
+        &mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()>;
+
+    /// Completes the file after all images have been appended.
+    ///
+    /// Returns an `ImageError::Parameter` if no image was appended.
+    fn finish(self) -> ImageResult<()>;
+}
+
+/// Encoder that writes an image row by row, as the rows are produced, instead of from a buffer
+/// of the whole image.
+///
+/// Rendering pipelines that generate very large images can pass each band of rows on as soon as
+/// it is done, so that only the band has to be kept in memory. The streaming encoders of the PNG,
+/// TIFF and BMP formats are created from their encoders with the dimensions and color type of
+/// the image, for example with [`PngEncoder::streaming`].
+///
+/// ```no_run
+/// # #[cfg(feature = "png")] {
+/// use image::codecs::png::PngEncoder;
+/// use image::{ColorType, StreamingEncoder};
+///
+/// # fn main() -> image::ImageResult<()> {
+/// let file = std::fs::File::create("gradient.png")?;
+/// let (width, height) = (4096, 65536);
+/// let mut encoder = PngEncoder::new(file).streaming(width, height, ColorType::L8)?;
+/// for band in 0..height / 256 {
+///     let rows = vec![(band % 256) as u8; width as usize * 256];
+///     encoder.write_rows(&rows, 256)?;
+/// }
+/// encoder.finish()?;
+/// # Ok(())
+/// # }
+/// # }
+/// ```
+///
+/// [`PngEncoder::streaming`]: codecs/png/struct.PngEncoder.html#method.streaming
+pub trait StreamingEncoder {
+    /// Writes the next `rows` rows of the image.
+    ///
+    /// The samples in `buf` are in native endian, as for `ImageEncoder::write_image`, and must
+    /// make up exactly `rows` complete rows. Returns an `ImageError::Parameter` if they do not,
+    /// or if they exceed the height of the image.
+    fn write_rows(&mut self, buf: &[u8], rows: u32) -> ImageResult<()>;
+
+    /// Completes the file after all rows of the image have been written.
+    ///
+    /// Returns an `ImageError::Parameter` if rows are missing.
+    fn finish(self) -> ImageResult<()>;
+}
+
+/// The rows that a [`StreamingEncoder`] still expects, which its implementations check the rows
+/// they are given against.
+///
+/// [`StreamingEncoder`]: trait.StreamingEncoder.html
+#[allow(dead_code)]
+// When no image formats that use it are enabled
+pub(crate) struct RowProgress {
+    row_bytes: usize,
+    rows_left: u32,
+}
+
+#[allow(dead_code)]
+impl RowProgress {
+    pub(crate) fn new(width: u32, height: u32, color_type: ColorType) -> ImageResult<RowProgress> {
+        let row_bytes = u64::from(width) * u64::from(color_type.bytes_per_pixel());
+        let row_bytes = usize::try_from(row_bytes).map_err(|_| {
+            ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            ))
+        })?;
+        Ok(RowProgress {
+            row_bytes,
+            rows_left: height,
+        })
+    }
+
+    /// The length of a row in bytes.
+    pub(crate) fn row_bytes(&self) -> usize {
+        self.row_bytes
+    }
+
+    /// Checks that `buf` holds `rows` rows that fit into the image, and counts them as written.
+    pub(crate) fn advance(&mut self, buf: &[u8], rows: u32) -> ImageResult<()> {
+        let len = (rows as usize).checked_mul(self.row_bytes);
+        if rows > self.rows_left || len != Some(buf.len()) {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+        self.rows_left -= rows;
+        Ok(())
+    }
+
+    /// Checks that all rows were written.
+    pub(crate) fn finish(&self) -> ImageResult<()> {
+        if self.rows_left > 0 {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!("{} rows were not written", self.rows_left)),
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Decoder that refines an image as more of its file arrives, such as the passes of an interlaced
+/// PNG or the scans of a progressive JPEG.
+///
+/// The file is fed in pieces of any size, for example as they are received over the network.
+/// Whenever a piece completes a new refinement, it is returned as a preview of the whole image,
+/// until finally the complete image is returned.
+///
+/// ```no_run
+/// # #[cfg(feature = "jpeg")] {
+/// use image::ProgressiveDecoder;
+/// use image::codecs::jpeg::ProgressiveJpegDecoder;
+///
+/// # fn main() -> image::ImageResult<()> {
+/// let data = std::fs::read("photo.jpg")?;
+/// let mut decoder = ProgressiveJpegDecoder::new();
+/// for chunk in data.chunks(4096) {
+///     if let Some(_preview) = decoder.feed(chunk)? {
+///         println!("refined, complete: {}", decoder.is_complete());
+///     }
+/// }
+/// # Ok(())
+/// # }
+/// # }
+/// ```
+pub trait ProgressiveDecoder {
+    /// Appends the next piece of the file.
+    ///
+    /// Returns the image as far as it is known, if the data received so far allows a more refined
+    /// image than the one returned last. Data after the end of the image is ignored.
+    fn feed(&mut self, data: &[u8]) -> ImageResult<Option<crate::DynamicImage>>;
+
+    /// Returns whether the complete image has been received and returned.
+    fn is_complete(&self) -> bool;
 }
 
 /// The trait all encoders implement
-pub trait ImageEncoder {
+///
+/// The trait is object safe, a `Box<dyn ImageEncoder>` as returned by [`encoder_for`] is itself
+/// an `ImageEncoder`. This allows choosing an encoder at runtime without naming its type.
+///
+/// [`encoder_for`]: fn.encoder_for.html
+pub trait ImageEncoder: ImageEncoderBoxed {
     /// Writes all the bytes in an image to the encoder.
     ///
     /// This function takes a slice of bytes of the pixel data of the image
@@ -803,6 +1381,113 @@ pub trait ImageEncoder {
         height: u32,
         color_type: ColorType,
     ) -> ImageResult<()>;
+
+    /// Embed an ICC color profile in the image that is written.
+    ///
+    /// The profile must describe the color space of the samples passed to `write_image`, such as
+    /// one returned by [`ImageDecoder::icc_profile`]. It is written as is and not validated.
+    ///
+    /// Returns an `ImageError::Unsupported` if the format, or this encoder, can not store a
+    /// profile, which is the default.
+    ///
+    /// [`ImageDecoder::icc_profile`]: trait.ImageDecoder.html#method.icc_profile
+    fn set_icc_profile(&mut self, icc_profile: Vec<u8>) -> ImageResult<()> {
+        let _ = icc_profile;
+        Err(ImageError::Unsupported(
+            UnsupportedError::from_format_and_kind(
+                ImageFormatHint::Unknown,
+                UnsupportedErrorKind::GenericFeature("ICC color profiles".to_string()),
+            ),
+        ))
+    }
+
+    /// Embed EXIF metadata in the image that is written.
+    ///
+    /// The fields of `exif` are written as they are. If the samples passed to `write_image` were
+    /// already rotated into the orientation of the metadata, its orientation should be cleared.
+    ///
+    /// Returns an `ImageError::Unsupported` if the format, or this encoder, can not store EXIF
+    /// metadata, which is the default.
+    fn set_exif_metadata(&mut self, exif: &Exif) -> ImageResult<()> {
+        let _ = exif;
+        Err(ImageError::Unsupported(
+            UnsupportedError::from_format_and_kind(
+                ImageFormatHint::Unknown,
+                UnsupportedErrorKind::GenericFeature("EXIF metadata".to_string()),
+            ),
+        ))
+    }
+
+    /// Embed textual metadata and an XMP packet in the image that is written.
+    ///
+    /// Returns an `ImageError::Unsupported` if `metadata` is not empty and the format, or this
+    /// encoder, can not store it, which is the default.
+    fn set_text_metadata(&mut self, metadata: &MetadataMap) -> ImageResult<()> {
+        if metadata.is_empty() {
+            return Ok(());
+        }
+        Err(ImageError::Unsupported(
+            UnsupportedError::from_format_and_kind(
+                ImageFormatHint::Unknown,
+                UnsupportedErrorKind::GenericFeature("text metadata".to_string()),
+            ),
+        ))
+    }
+}
+
+/// Consumes an encoder behind a `Box`.
+///
+/// This is implemented for every [`ImageEncoder`] and is what allows calling `write_image` on a
+/// `Box<dyn ImageEncoder>`, since `write_image` takes the encoder by value.
+///
+/// [`ImageEncoder`]: trait.ImageEncoder.html
+pub trait ImageEncoderBoxed {
+    /// Writes all the bytes in an image to the boxed encoder.
+    ///
+    /// See `ImageEncoder::write_image`.
+    fn write_image_boxed(
+        self: Box<Self>,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()>;
+}
+
+impl<T: ImageEncoder> ImageEncoderBoxed for T {
+    fn write_image_boxed(
+        self: Box<Self>,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        (*self).write_image(buf, width, height, color_type)
+    }
+}
+
+impl<'a> ImageEncoder for Box<dyn ImageEncoder + 'a> {
+    fn write_image(
+        self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        self.write_image_boxed(buf, width, height, color_type)
+    }
+
+    fn set_icc_profile(&mut self, icc_profile: Vec<u8>) -> ImageResult<()> {
+        (**self).set_icc_profile(icc_profile)
+    }
+
+    fn set_exif_metadata(&mut self, exif: &Exif) -> ImageResult<()> {
+        (**self).set_exif_metadata(exif)
+    }
+
+    fn set_text_metadata(&mut self, metadata: &MetadataMap) -> ImageResult<()> {
+        (**self).set_text_metadata(metadata)
+    }
 }
 
 /// Immutable pixel iterator
@@ -927,6 +1612,95 @@ pub trait GenericImageView {
         assert!(y as u64 + height as u64 <= self.height() as u64);
         SubImage::new(self, x, y, width, height)
     }
+
+    /// Returns the pixel at a position between pixel centers, interpolated bilinearly from the
+    /// four nearest pixels.
+    ///
+    /// The centers of pixels lie at whole coordinates, so `(0.0, 0.0)` is exactly the top left
+    /// pixel. Positions beyond the edges take the nearest edge pixel, see
+    /// [`sample_bilinear_with_edges`] for other choices.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image is empty.
+    ///
+    /// [`sample_bilinear_with_edges`]: #method.sample_bilinear_with_edges
+    fn sample_bilinear(&self, x: f32, y: f32) -> Self::Pixel {
+        self.sample_bilinear_with_edges(x, y, EdgeMode::Clamp)
+    }
+
+    /// Returns the pixel at a position between pixel centers, interpolated bilinearly, with
+    /// positions beyond the edges sampled according to `edges`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image is empty.
+    fn sample_bilinear_with_edges(&self, x: f32, y: f32, edges: EdgeMode) -> Self::Pixel {
+        sample::interpolate(self, x, y, edges, 1, sample::triangle_kernel)
+    }
+
+    /// Returns the pixel at a position between pixel centers, interpolated bicubically from the
+    /// sixteen nearest pixels.
+    ///
+    /// This uses the Catmull-Rom spline, like [`FilterType::CatmullRom`], which keeps edges
+    /// sharper than bilinear interpolation. Positions beyond the edges take the nearest edge
+    /// pixel.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image is empty.
+    ///
+    /// [`FilterType::CatmullRom`]: imageops/enum.FilterType.html#variant.CatmullRom
+    fn sample_bicubic(&self, x: f32, y: f32) -> Self::Pixel {
+        self.sample_bicubic_with_edges(x, y, EdgeMode::Clamp)
+    }
+
+    /// Returns the pixel at a position between pixel centers, interpolated bicubically, with
+    /// positions beyond the edges sampled according to `edges`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the image is empty.
+    fn sample_bicubic_with_edges(&self, x: f32, y: f32, edges: EdgeMode) -> Self::Pixel {
+        sample::interpolate(self, x, y, edges, 2, sample::catmullrom_kernel)
+    }
+
+    /// Returns an iterator over the pixels of this image together with their neighborhoods of
+    /// `radius` pixels in each direction.
+    ///
+    /// The iterator yields the coordinates of each pixel along with a view of its neighborhood,
+    /// which is useful for writing local filters. Neighbors beyond the edges take the nearest
+    /// edge pixel, see [`windows_with_edges`] for other choices.
+    ///
+    /// ```
+    /// use image::{GenericImageView, GrayImage, Luma};
+    ///
+    /// let image = GrayImage::from_fn(8, 8, |x, y| Luma([(x * y) as u8]));
+    /// // The largest value around each pixel.
+    /// let mut dilated = GrayImage::new(8, 8);
+    /// for (x, y, window) in image.windows(1) {
+    ///     let max = window.pixels().map(|(_, _, pixel)| pixel[0]).max().unwrap();
+    ///     dilated.put_pixel(x, y, Luma([max]));
+    /// }
+    /// assert_eq!(dilated[(2, 3)], Luma([12]));
+    /// ```
+    ///
+    /// [`windows_with_edges`]: #method.windows_with_edges
+    fn windows(&self, radius: u32) -> Windows<'_, Self>
+    where
+        Self: Sized,
+    {
+        self.windows_with_edges(radius, EdgeMode::Clamp)
+    }
+
+    /// Returns an iterator over the pixels of this image together with their neighborhoods, with
+    /// neighbors beyond the edges sampled according to `edges`.
+    fn windows_with_edges(&self, radius: u32, edges: EdgeMode) -> Windows<'_, Self>
+    where
+        Self: Sized,
+    {
+        Windows::new(self, radius, edges)
+    }
 }
 
 /// A trait for manipulating images.
@@ -1299,8 +2073,8 @@ mod tests {
     use std::path::Path;
 
     use super::{
-        load_rect, ColorType, GenericImage, GenericImageView, ImageDecoder, ImageFormat,
-        ImageResult,
+        load_rect, ColorType, GenericImage, GenericImageView, ImageDecoder, ImageEncoder,
+        ImageError, ImageFormat, ImageResult,
     };
     use crate::color::Rgba;
     use crate::math::Rect;
@@ -1631,6 +2405,13 @@ mod tests {
         assert_eq!(from_path("./a.Ppm").unwrap(), ImageFormat::Pnm);
         assert_eq!(from_path("./a.pgm").unwrap(), ImageFormat::Pnm);
         assert_eq!(from_path("./a.AViF").unwrap(), ImageFormat::Avif);
+        assert_eq!(from_path("./a.fits").unwrap(), ImageFormat::Fits);
+        assert_eq!(from_path("./a.FIT").unwrap(), ImageFormat::Fits);
+        assert_eq!(from_path("./a.dcm").unwrap(), ImageFormat::Dicom);
+        assert_eq!(from_path("./a.jxs").unwrap(), ImageFormat::JpegXs);
+        assert_eq!(from_path("./a.jxl").unwrap(), ImageFormat::Jxl);
+        assert_eq!(from_path("./a.HEIC").unwrap(), ImageFormat::Heif);
+        assert_eq!(from_path("./a.svgz").unwrap(), ImageFormat::Svg);
         assert!(from_path("./a.txt").is_err());
         assert!(from_path("./a").is_err());
     }
@@ -1802,7 +2583,8 @@ mod tests {
     fn image_formats_are_recognized() {
         use ImageFormat::*;
         const ALL_FORMATS: &'static [ImageFormat] = &[
-            Avif, Png, Jpeg, Gif, WebP, Pnm, Tiff, Tga, Dds, Bmp, Ico, Hdr, Farbfeld, OpenExr,
+            Avif, Png, Jpeg, Gif, WebP, Pnm, Tiff, Tga, Dds, Bmp, Ico, Icns, Hdr, Farbfeld,
+            OpenExr, Fits, Dicom, JpegXs, Jxl, Heif, Svg, Qoi,
         ];
         for &format in ALL_FORMATS {
             let mut file = Path::new("file.nothing").to_owned();
@@ -1836,4 +2618,135 @@ mod tests {
         let v: ImageResult<Vec<u8>> = super::decoder_to_vec(D);
         assert!(v.is_err());
     }
+
+    #[test]
+    fn read_image_into_uninit() {
+        struct D;
+        impl<'a> ImageDecoder<'a> for D {
+            type Reader = io::Cursor<Vec<u8>>;
+            fn color_type(&self) -> ColorType {
+                ColorType::L8
+            }
+            fn dimensions(&self) -> (u32, u32) {
+                (3, 2)
+            }
+            fn into_reader(self) -> ImageResult<Self::Reader> {
+                Ok(io::Cursor::new(vec![1, 2, 3, 4, 5, 6]))
+            }
+        }
+
+        let mut buf = [std::mem::MaybeUninit::uninit(); 6];
+        D.read_image_into_uninit(&mut buf).unwrap();
+        // Safety: the decoder initialized the whole buffer.
+        let buf: Vec<u8> = buf
+            .iter()
+            .map(|byte| unsafe { byte.assume_init() })
+            .collect();
+        assert_eq!(buf, [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    #[cfg(all(feature = "pnm", feature = "tiff"))]
+    fn boxed_encoders() {
+        let pixels = [1u8, 2, 3, 4, 5, 6];
+        let mut outputs: Vec<(ImageFormat, Vec<u8>)> = Vec::new();
+        for &format in &[ImageFormat::Pnm, ImageFormat::Tiff] {
+            let mut encoded = Vec::new();
+            let options = crate::EncoderOptions {
+                pnm_subtype: Some(crate::codecs::pnm::PnmSubtype::Graymap(
+                    crate::codecs::pnm::SampleEncoding::Ascii,
+                )),
+                ..Default::default()
+            };
+            let encoder: Box<dyn ImageEncoder + '_> =
+                crate::encoder_for(format, &mut encoded, options).unwrap();
+            encoder.write_image(&pixels, 3, 2, ColorType::L8).unwrap();
+            outputs.push((format, encoded));
+        }
+
+        assert!(outputs[0].1.starts_with(b"P2"));
+        for (format, encoded) in outputs {
+            let image = crate::load_from_memory_with_format(&encoded, format).unwrap();
+            assert_eq!(image.as_bytes(), &pixels[..]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "tiff")]
+    fn buffers_seeking_encoders_within_limits() {
+        use crate::io::free_functions::{self, Seekable, Stream};
+        use crate::ImageOutputFormat;
+
+        let pixels = vec![0u8; 64 * 64];
+        let mut limits = crate::io::Limits::no_limits();
+        limits.max_alloc = Some(1024);
+        let options = crate::EncoderOptions {
+            limits: limits.clone(),
+            ..Default::default()
+        };
+        let encoder = crate::encoder_for(ImageFormat::Tiff, Vec::new(), options).unwrap();
+        let result = encoder.write_image(&pixels, 64, 64, ColorType::L8);
+        assert!(matches!(result, Err(ImageError::Limits(_))));
+
+        // Writers that can seek are written directly, so the limits do not apply.
+        let mut encoded = Vec::new();
+        let writer = Seekable(std::io::Cursor::new(&mut encoded));
+        let encoder = free_functions::boxed_encoder(writer, ImageOutputFormat::Tiff).unwrap();
+        encoder.write_image(&pixels, 64, 64, ColorType::L8).unwrap();
+        let mut streamed = Vec::new();
+        let writer = Stream {
+            writer: &mut streamed,
+            limits: crate::io::Limits::no_limits(),
+        };
+        let encoder = free_functions::boxed_encoder(writer, ImageOutputFormat::Tiff).unwrap();
+        encoder.write_image(&pixels, 64, 64, ColorType::L8).unwrap();
+        assert_eq!(encoded, streamed);
+    }
+
+    #[test]
+    #[cfg(all(feature = "png", feature = "jpeg", feature = "bmp"))]
+    fn estimate_encoded_sizes() {
+        // Smooth gradients with some texture, as in photos.
+        let image = crate::DynamicImage::ImageRgb8(crate::RgbImage::from_fn(1200, 900, |x, y| {
+            let texture = ((x * 7 + y * 13) % 17) as u8;
+            crate::Rgb([(x / 5) as u8, (y / 4) as u8, ((x + y) / 9) as u8 + texture])
+        }));
+        for &format in &[ImageFormat::Jpeg, ImageFormat::Png, ImageFormat::Bmp] {
+            let estimate =
+                crate::estimate_encoded_size(&image, format, crate::EncoderOptions::default())
+                    .unwrap();
+            let mut encoded = Vec::new();
+            image.write_to(&mut encoded, format).unwrap();
+            let actual = encoded.len() as u64;
+            if format == ImageFormat::Bmp {
+                assert_eq!(estimate, actual);
+            } else {
+                let error = (estimate as f64 - actual as f64).abs() / actual as f64;
+                assert!(error < 0.25, "{:?}: {} vs {}", format, estimate, actual);
+            }
+        }
+
+        let options = crate::EncoderOptions {
+            quality: Some(30),
+            ..Default::default()
+        };
+        let low_quality = crate::estimate_encoded_size(&image, ImageFormat::Jpeg, options).unwrap();
+        let high_quality = crate::estimate_encoded_size(
+            &image,
+            ImageFormat::Jpeg,
+            crate::EncoderOptions::default(),
+        )
+        .unwrap();
+        assert!(low_quality < high_quality);
+    }
+
+    #[test]
+    fn boxed_encoder_rejects_unwritable_formats() {
+        let encoder = crate::encoder_for(
+            ImageFormat::Dds,
+            Vec::new(),
+            crate::EncoderOptions::default(),
+        );
+        assert!(matches!(encoder, Err(ImageError::Unsupported(_))));
+    }
 }
@@ -0,0 +1,208 @@
+//! Calibration of the frames of scientific cameras.
+use num_traits::NumCast;
+
+use crate::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use crate::traits::{Pixel, Primitive};
+use crate::ImageBuffer;
+
+use super::sample::FloatNearest;
+
+/// Remove the sensor artifacts from a frame of an astronomy or microscopy camera.
+///
+/// The steps are the standard preprocessing of such frames, computed with floating point values
+/// and rounded to the channel type only at the end:
+///
+/// * The `dark_frame`, an exposure of equal length with the shutter closed, is subtracted to remove
+///   the thermal signal and the bias of the sensor.
+/// * The frame is divided by the `flat_field`, an exposure of an evenly lit surface, and scaled by
+///   the mean of the flat field. This evens out vignetting, dust shadows and differences in the
+///   sensitivity of pixels. The flat field should itself be corrected for its own dark frame, as
+///   master flats usually are. Pixels where it is zero are not scaled.
+/// * If a `hot_pixel_threshold` is given, pixels that exceed the median of their eight neighbours
+///   by more than that many standard deviations of all such differences are replaced by that
+///   median. Values of 3 to 5 are common.
+///
+/// Each channel is calibrated by itself, the alpha channel is left as it is.
+///
+/// Returns an `ImageError::Parameter` if the dark frame or flat field differ in size from the
+/// image.
+pub fn calibrate<P, S>(
+    image: &ImageBuffer<P, Vec<S>>,
+    dark_frame: Option<&ImageBuffer<P, Vec<S>>>,
+    flat_field: Option<&ImageBuffer<P, Vec<S>>>,
+    hot_pixel_threshold: Option<f32>,
+) -> ImageResult<ImageBuffer<P, Vec<S>>>
+where
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let (width, height) = image.dimensions();
+    for frame in dark_frame.iter().chain(flat_field.iter()) {
+        if frame.dimensions() != (width, height) {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+    }
+
+    let channels = P::CHANNEL_COUNT as usize;
+    let color_channels = if P::COLOR_MODEL.ends_with('A') {
+        channels - 1
+    } else {
+        channels
+    };
+    let to_f32 = |value: &S| value.to_f32().unwrap();
+    let mut values: Vec<f32> = image.iter().map(to_f32).collect();
+
+    if let Some(dark_frame) = dark_frame {
+        for (i, (value, dark)) in values.iter_mut().zip(dark_frame.iter()).enumerate() {
+            if i % channels < color_channels {
+                *value -= to_f32(dark);
+            }
+        }
+    }
+
+    if let Some(flat_field) = flat_field {
+        for c in 0..color_channels {
+            let flat: Vec<f32> = flat_field
+                .iter()
+                .skip(c)
+                .step_by(channels)
+                .map(to_f32)
+                .collect();
+            let mean = flat.iter().map(|&v| v as f64).sum::<f64>() / flat.len().max(1) as f64;
+            for (value, &flat) in values.iter_mut().skip(c).step_by(channels).zip(&flat) {
+                if flat != 0.0 {
+                    *value *= mean as f32 / flat;
+                }
+            }
+        }
+    }
+
+    if let Some(threshold) = hot_pixel_threshold {
+        for c in 0..color_channels {
+            remove_hot_pixels(&mut values[c..], channels, width, height, threshold);
+        }
+    }
+
+    let max: f32 = NumCast::from(S::DEFAULT_MAX_VALUE).unwrap();
+    let min: f32 = NumCast::from(S::DEFAULT_MIN_VALUE).unwrap();
+    let data = values
+        .into_iter()
+        .map(|value| NumCast::from(FloatNearest(value.clamp(min, max))).unwrap())
+        .collect();
+    Ok(ImageBuffer::from_raw(width, height, data).unwrap())
+}
+
+/// Replace the values of one channel that are outliers above the median of their neighbours.
+///
+/// The values of the channel are every `stride`th value of `values`, row by row.
+fn remove_hot_pixels(values: &mut [f32], stride: usize, width: u32, height: u32, threshold: f32) {
+    let (width, height) = (width as usize, height as usize);
+    let at = |values: &[f32], x: usize, y: usize| values[(y * width + x) * stride];
+
+    let mut medians = Vec::with_capacity(width * height);
+    let mut neighbours = Vec::with_capacity(8);
+    for y in 0..height {
+        for x in 0..width {
+            neighbours.clear();
+            for ny in y.saturating_sub(1)..(y + 2).min(height) {
+                for nx in x.saturating_sub(1)..(x + 2).min(width) {
+                    if (nx, ny) != (x, y) {
+                        neighbours.push(at(values, nx, ny));
+                    }
+                }
+            }
+            medians.push(median(&mut neighbours));
+        }
+    }
+
+    let residual = |i: usize, values: &[f32]| match medians[i] {
+        Some(median) => (values[i * stride] - median) as f64,
+        None => 0.0,
+    };
+    let count = medians.len().max(1) as f64;
+    let mean = (0..medians.len()).map(|i| residual(i, values)).sum::<f64>() / count;
+    let variance = (0..medians.len())
+        .map(|i| (residual(i, values) - mean).powi(2))
+        .sum::<f64>()
+        / count;
+    let limit = threshold as f64 * variance.sqrt();
+
+    for (i, median) in medians.iter().enumerate() {
+        if let Some(median) = *median {
+            if residual(i, values) > limit {
+                values[i * stride] = median;
+            }
+        }
+    }
+}
+
+/// The median of some values, or `None` if there are none.
+fn median(values: &mut [f32]) -> Option<f32> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let middle = values.len() / 2;
+    if values.len() % 2 == 0 {
+        Some((values[middle - 1] + values[middle]) / 2.0)
+    } else {
+        Some(values[middle])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Luma, LumaA};
+
+    type Gray16 = ImageBuffer<Luma<u16>, Vec<u16>>;
+
+    #[test]
+    fn dark_and_flat() {
+        let dark = Gray16::from_fn(4, 1, |x, _| Luma([100 + x as u16 * 10]));
+        let flat = Gray16::from_fn(4, 1, |x, _| Luma([[1000, 2000, 1000, 4000][x as usize]]));
+        // A uniform scene of 500 through the vignetting of the flat field, on top of the dark.
+        let image = Gray16::from_fn(4, 1, |x, _| {
+            Luma([dark.get_pixel(x, 0)[0] + flat.get_pixel(x, 0)[0] / 4])
+        });
+        let calibrated = calibrate(&image, Some(&dark), Some(&flat), None).unwrap();
+        assert!(calibrated.pixels().all(|p| p[0] == 500), "{:?}", calibrated);
+
+        let calibrated = calibrate(&image, Some(&dark), None, None).unwrap();
+        assert_eq!(calibrated.get_pixel(3, 0)[0], 1000);
+        // Values below the dark frame clip to zero.
+        let calibrated = calibrate(&dark, Some(&image), None, None).unwrap();
+        assert!(calibrated.pixels().all(|p| p[0] == 0));
+    }
+
+    #[test]
+    fn hot_pixels() {
+        let mut image = Gray16::from_fn(8, 8, |x, y| Luma([1000 + (x + y) as u16 * 3]));
+        let clean = image.clone();
+        image.put_pixel(5, 2, Luma([60000]));
+        image.put_pixel(0, 7, Luma([50000]));
+        let calibrated = calibrate(&image, None, None, Some(4.0)).unwrap();
+        assert_eq!(calibrated.get_pixel(5, 2), clean.get_pixel(5, 2));
+        assert!(calibrated.get_pixel(0, 7)[0] < 1100);
+        assert_eq!(calibrated.get_pixel(3, 3), clean.get_pixel(3, 3));
+        assert_eq!(calibrated.get_pixel(7, 7), clean.get_pixel(7, 7));
+    }
+
+    #[test]
+    fn keeps_alpha() {
+        let image = ImageBuffer::from_pixel(2, 2, LumaA([300u16, 700]));
+        let dark = ImageBuffer::from_pixel(2, 2, LumaA([100u16, 600]));
+        let calibrated = calibrate(&image, Some(&dark), None, None).unwrap();
+        assert_eq!(calibrated.get_pixel(1, 1), &LumaA([200, 700]));
+    }
+
+    #[test]
+    fn mismatched_frames() {
+        let image = Gray16::new(3, 2);
+        assert!(calibrate(&image, Some(&Gray16::new(2, 3)), None, None).is_err());
+        assert!(calibrate(&image, None, Some(&Gray16::new(3, 3)), None).is_err());
+        assert!(calibrate(&Gray16::new(0, 0), None, None, Some(3.0)).is_ok());
+    }
+}
@@ -5,6 +5,7 @@ use std::f64::consts::PI;
 
 use crate::color::{FromColor, IntoColor, Luma, LumaA, Rgba};
 use crate::image::{GenericImage, GenericImageView};
+use crate::imageops::sample::sample_range;
 use crate::traits::{Pixel, Primitive};
 use crate::utils::clamp;
 use crate::ImageBuffer;
@@ -85,12 +86,70 @@ pub fn invert<I: GenericImage>(image: &mut I) {
     }
 }
 
+/// Options for the tone adjustments `brighten` and `contrast`, which control how they treat
+/// samples of types other than `u8`.
+///
+/// Construct it with `Default` and set the fields of interest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub struct ToneOptions {
+    /// Clip adjusted samples to the nominal range of their type, which is 0 to 1 for floats.
+    /// Defaults to `false`, which only saturates samples at the bounds of their type, so that
+    /// floating point images keep HDR values.
+    pub clamp: bool,
+
+    /// Brighten samples of every type in steps of 1/255 of their nominal range, so that `u16`
+    /// images change as much as `u8` images. Defaults to `false`, which adds the amount to integer
+    /// samples as it is. Floating point samples are always brightened in steps of 1/255.
+    pub scale: bool,
+}
+
+impl ToneOptions {
+    /// The range that adjusted samples of type `S` are limited to.
+    fn range<S: Primitive>(self) -> (f32, f32) {
+        if self.clamp {
+            (0.0, NumCast::from(S::DEFAULT_MAX_VALUE).unwrap())
+        } else {
+            sample_range::<S>()
+        }
+    }
+
+    /// The amount that `brighten` adds to samples of type `S`.
+    fn brightness_step<S: Primitive>(self, value: i32) -> f32 {
+        let max: f32 = NumCast::from(S::DEFAULT_MAX_VALUE).unwrap();
+        let (_, upper) = sample_range::<S>();
+        if self.scale || upper > max {
+            value as f32 * max / 255.0
+        } else {
+            value as f32
+        }
+    }
+}
+
 /// Adjust the contrast of the supplied image.
 /// ```contrast``` is the amount to adjust the contrast by.
 /// Negative values decrease the contrast and positive values increase the contrast.
 ///
+/// Floating point images are not clamped to the range from 0 to 1, so they keep HDR values.
+///
 /// *[See also `contrast_in_place`.][contrast_in_place]*
 pub fn contrast<I, P, S>(image: &I, contrast: f32) -> ImageBuffer<P, Vec<S>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    contrast_with(image, contrast, ToneOptions::default())
+}
+
+/// Adjust the contrast of the supplied image, with the behavior of `options`.
+///
+/// *[See also `contrast`.][contrast]*
+pub fn contrast_with<I, P, S>(
+    image: &I,
+    contrast: f32,
+    options: ToneOptions,
+) -> ImageBuffer<P, Vec<S>>
 where
     I: GenericImageView<Pixel = P>,
     P: Pixel<Subpixel = S> + 'static,
@@ -101,6 +160,7 @@ where
 
     let max = S::DEFAULT_MAX_VALUE;
     let max: f32 = NumCast::from(max).unwrap();
+    let (lower, upper) = options.range::<S>();
 
     let percent = ((100.0 + contrast) / 100.0).powi(2);
 
@@ -111,7 +171,7 @@ where
                 let c: f32 = NumCast::from(b).unwrap();
 
                 let d = ((c / max - 0.5) * percent + 0.5) * max;
-                let e = clamp(d, 0.0, max);
+                let e = clamp(d, lower, upper);
 
                 NumCast::from(e).unwrap()
             });
@@ -129,6 +189,16 @@ where
 ///
 /// *[See also `contrast`.][contrast]*
 pub fn contrast_in_place<I>(image: &mut I, contrast: f32)
+where
+    I: GenericImage,
+{
+    contrast_in_place_with(image, contrast, ToneOptions::default())
+}
+
+/// Adjust the contrast of the supplied image in place, with the behavior of `options`.
+///
+/// *[See also `contrast_in_place`.][contrast_in_place]*
+pub fn contrast_in_place_with<I>(image: &mut I, contrast: f32, options: ToneOptions)
 where
     I: GenericImage,
 {
@@ -136,6 +206,7 @@ where
 
     let max = <I::Pixel as Pixel>::Subpixel::DEFAULT_MAX_VALUE;
     let max: f32 = NumCast::from(max).unwrap();
+    let (lower, upper) = options.range::<<I::Pixel as Pixel>::Subpixel>();
 
     let percent = ((100.0 + contrast) / 100.0).powi(2);
 
@@ -146,7 +217,7 @@ where
                 let c: f32 = NumCast::from(b).unwrap();
 
                 let d = ((c / max - 0.5) * percent + 0.5) * max;
-                let e = clamp(d, 0.0, max);
+                let e = clamp(d, lower, upper);
 
                 NumCast::from(e).unwrap()
             });
@@ -160,8 +231,23 @@ where
 /// ```value``` is the amount to brighten each pixel by.
 /// Negative values decrease the brightness and positive values increase it.
 ///
+/// Floating point samples, which range from 0 to 1, are brightened in steps of 1/255 like 8-bit
+/// samples. They are not clamped to that range, so they keep HDR values.
+///
 /// *[See also `brighten_in_place`.][brighten_in_place]*
 pub fn brighten<I, P, S>(image: &I, value: i32) -> ImageBuffer<P, Vec<S>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    brighten_with(image, value, ToneOptions::default())
+}
+
+/// Brighten the supplied image, with the behavior of `options`.
+///
+/// *[See also `brighten`.][brighten]*
+pub fn brighten_with<I, P, S>(image: &I, value: i32, options: ToneOptions) -> ImageBuffer<P, Vec<S>>
 where
     I: GenericImageView<Pixel = P>,
     P: Pixel<Subpixel = S> + 'static,
@@ -170,16 +256,16 @@ where
     let (width, height) = image.dimensions();
     let mut out = ImageBuffer::new(width, height);
 
-    let max = S::DEFAULT_MAX_VALUE;
-    let max: i32 = NumCast::from(max).unwrap();
+    let (lower, upper) = options.range::<S>();
+    let value = options.brightness_step::<S>(value);
 
     // TODO use pixels_mut?
     for y in 0..height {
         for x in 0..width {
             let e = image.get_pixel(x, y).map_with_alpha(
                 |b| {
-                    let c: i32 = NumCast::from(b).unwrap();
-                    let d = clamp(c + value, 0, max);
+                    let c: f32 = NumCast::from(b).unwrap();
+                    let d = clamp(c + value, lower, upper);
 
                     NumCast::from(d).unwrap()
                 },
@@ -199,21 +285,31 @@ where
 ///
 /// *[See also `brighten`.][brighten]*
 pub fn brighten_in_place<I>(image: &mut I, value: i32)
+where
+    I: GenericImage,
+{
+    brighten_in_place_with(image, value, ToneOptions::default())
+}
+
+/// Brighten the supplied image in place, with the behavior of `options`.
+///
+/// *[See also `brighten_in_place`.][brighten_in_place]*
+pub fn brighten_in_place_with<I>(image: &mut I, value: i32, options: ToneOptions)
 where
     I: GenericImage,
 {
     let (width, height) = image.dimensions();
 
-    let max = <I::Pixel as Pixel>::Subpixel::DEFAULT_MAX_VALUE;
-    let max: i32 = NumCast::from(max).unwrap(); // TODO what does this do for f32? clamp at 1??
+    let (lower, upper) = options.range::<<I::Pixel as Pixel>::Subpixel>();
+    let value = options.brightness_step::<<I::Pixel as Pixel>::Subpixel>(value);
 
     // TODO use pixels_mut?
     for y in 0..height {
         for x in 0..width {
             let e = image.get_pixel(x, y).map_with_alpha(
                 |b| {
-                    let c: i32 = NumCast::from(b).unwrap();
-                    let d = clamp(c + value, 0, max);
+                    let c: f32 = NumCast::from(b).unwrap();
+                    let d = clamp(c + value, lower, upper);
 
                     NumCast::from(d).unwrap()
                 },
@@ -566,4 +662,54 @@ mod test {
         assert_eq!(&*image, &[0, 0xFF, 0xFF, 0]);
         assert_eq!(index_colors(&image, &cmap).into_raw(), vec![0, 1, 1, 0])
     }
+
+    #[test]
+    fn test_brighten_and_contrast() {
+        let image: ImageBuffer<Luma<u8>, _> =
+            ImageBuffer::from_raw(3, 1, vec![0, 128, 250]).unwrap();
+        assert_eq!(brighten(&image, 10).into_raw(), vec![10, 138, 255]);
+        assert_eq!(brighten(&image, -10).into_raw(), vec![0, 118, 240]);
+        assert_eq!(contrast(&image, 100.0).into_raw(), vec![0, 129, 255]);
+
+        // Floating point samples keep values beyond 1.
+        let mut image: ImageBuffer<Luma<f32>, _> =
+            ImageBuffer::from_raw(3, 1, vec![0.0, 0.5, 4.0]).unwrap();
+        let assert_close = |image: &ImageBuffer<Luma<f32>, Vec<f32>>, expected: [f32; 3]| {
+            for (value, expected) in image.as_raw().iter().zip(&expected) {
+                assert!((value - expected).abs() < 1e-5, "{} != {}", value, expected);
+            }
+        };
+        brighten_in_place(&mut image, 51);
+        assert_close(&image, [0.2, 0.7, 4.2]);
+        contrast_in_place(&mut image, 100.0);
+        assert_close(&image, [-0.7, 1.3, 15.3]);
+    }
+
+    #[test]
+    fn test_tone_options() {
+        let clamp = ToneOptions {
+            clamp: true,
+            ..ToneOptions::default()
+        };
+        let image: ImageBuffer<Luma<f32>, _> =
+            ImageBuffer::from_raw(3, 1, vec![0.0, 0.5, 4.0]).unwrap();
+        assert_eq!(
+            brighten_with(&image, 51, clamp).into_raw(),
+            vec![0.2, 0.7, 1.0]
+        );
+        assert_eq!(
+            contrast_with(&image, 100.0, clamp).into_raw(),
+            vec![0.0, 0.5, 1.0]
+        );
+
+        let scale = ToneOptions {
+            scale: true,
+            ..ToneOptions::default()
+        };
+        let mut image: ImageBuffer<Luma<u16>, _> =
+            ImageBuffer::from_raw(2, 1, vec![0, 65000]).unwrap();
+        assert_eq!(brighten(&image, 10).into_raw(), vec![10, 65010]);
+        brighten_in_place_with(&mut image, 10, scale);
+        assert_eq!(image.into_raw(), vec![2570, 65535]);
+    }
 }
@@ -0,0 +1,275 @@
+//! Reconstruction of color images from the raw frames of color filter array sensors.
+use num_traits::NumCast;
+
+use crate::traits::{Enlargeable, Primitive};
+use crate::{ImageBuffer, Luma, Rgb};
+
+use super::sample::FloatNearest;
+
+/// The arrangement of color filters of a Bayer sensor, named after its top left 2x2 block read
+/// row by row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CfaPattern {
+    /// Red and green in the first row, green and blue in the second.
+    Rggb,
+    /// Blue and green in the first row, green and red in the second.
+    Bggr,
+    /// Green and red in the first row, blue and green in the second.
+    Grbg,
+    /// Green and blue in the first row, red and green in the second.
+    Gbrg,
+}
+
+impl CfaPattern {
+    /// The index of the color, 0 for red, 1 for green and 2 for blue, sampled at a position.
+    fn color_at(self, x: i64, y: i64) -> usize {
+        let block = match self {
+            CfaPattern::Rggb => [[0, 1], [1, 2]],
+            CfaPattern::Bggr => [[2, 1], [1, 0]],
+            CfaPattern::Grbg => [[1, 0], [2, 1]],
+            CfaPattern::Gbrg => [[1, 2], [0, 1]],
+        };
+        block[(y & 1) as usize][(x & 1) as usize]
+    }
+}
+
+/// The algorithm to interpolate missing colors with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DemosaicAlgorithm {
+    /// Averages the nearest samples of each color. Fast, but blurs edges and adds color fringes.
+    Bilinear,
+    /// The gradient corrected linear interpolation of Malvar, He and Cutler, which also uses the
+    /// samples of the other colors and gives much sharper results at little extra cost.
+    Malvar,
+}
+
+/// Weights of the samples around a pixel, as offsets in x and y.
+type Kernel = &'static [(i64, i64, f32)];
+
+const BILINEAR_CROSS: Kernel = &[(0, -1, 0.25), (-1, 0, 0.25), (1, 0, 0.25), (0, 1, 0.25)];
+const BILINEAR_HORIZONTAL: Kernel = &[(-1, 0, 0.5), (1, 0, 0.5)];
+const BILINEAR_VERTICAL: Kernel = &[(0, -1, 0.5), (0, 1, 0.5)];
+const BILINEAR_DIAGONAL: Kernel = &[(-1, -1, 0.25), (1, -1, 0.25), (-1, 1, 0.25), (1, 1, 0.25)];
+
+const MALVAR_CROSS: Kernel = &[
+    (0, 0, 0.5),
+    (0, -1, 0.25),
+    (-1, 0, 0.25),
+    (1, 0, 0.25),
+    (0, 1, 0.25),
+    (0, -2, -0.125),
+    (-2, 0, -0.125),
+    (2, 0, -0.125),
+    (0, 2, -0.125),
+];
+const MALVAR_HORIZONTAL: Kernel = &[
+    (0, 0, 0.625),
+    (-1, 0, 0.5),
+    (1, 0, 0.5),
+    (-2, 0, -0.125),
+    (2, 0, -0.125),
+    (-1, -1, -0.125),
+    (1, -1, -0.125),
+    (-1, 1, -0.125),
+    (1, 1, -0.125),
+    (0, -2, 0.0625),
+    (0, 2, 0.0625),
+];
+const MALVAR_VERTICAL: Kernel = &[
+    (0, 0, 0.625),
+    (0, -1, 0.5),
+    (0, 1, 0.5),
+    (0, -2, -0.125),
+    (0, 2, -0.125),
+    (-1, -1, -0.125),
+    (1, -1, -0.125),
+    (-1, 1, -0.125),
+    (1, 1, -0.125),
+    (-2, 0, 0.0625),
+    (2, 0, 0.0625),
+];
+const MALVAR_DIAGONAL: Kernel = &[
+    (0, 0, 0.75),
+    (-1, -1, 0.25),
+    (1, -1, 0.25),
+    (-1, 1, 0.25),
+    (1, 1, 0.25),
+    (0, -2, -0.1875),
+    (-2, 0, -0.1875),
+    (2, 0, -0.1875),
+    (0, 2, -0.1875),
+];
+
+/// Reconstruct a color image from the raw frame of a sensor with a Bayer color filter array.
+///
+/// Each pixel of `raw` holds the sample of the one color that the filter over it passes, as
+/// machine vision cameras deliver them. The missing two colors of every pixel are interpolated
+/// from its neighbours, mirroring the frame at its edges.
+///
+/// ```
+/// use image::imageops::{demosaic, CfaPattern, DemosaicAlgorithm};
+/// use image::{GrayImage, Luma, Rgb};
+///
+/// // A frame of a uniformly orange scene.
+/// let raw = GrayImage::from_fn(8, 8, |x, y| match (x % 2, y % 2) {
+///     (0, 0) => Luma([240]),
+///     (1, 1) => Luma([20]),
+///     _ => Luma([120]),
+/// });
+/// let rgb = demosaic(&raw, CfaPattern::Rggb, DemosaicAlgorithm::Malvar);
+/// assert_eq!(rgb.get_pixel(3, 4), &Rgb([240, 120, 20]));
+/// ```
+pub fn demosaic<S>(
+    raw: &ImageBuffer<Luma<S>, Vec<S>>,
+    pattern: CfaPattern,
+    algorithm: DemosaicAlgorithm,
+) -> ImageBuffer<Rgb<S>, Vec<S>>
+where
+    S: Primitive + Enlargeable + 'static,
+{
+    let (width, height) = raw.dimensions();
+    let max: f32 = NumCast::from(S::DEFAULT_MAX_VALUE).unwrap();
+    let min: f32 = NumCast::from(S::DEFAULT_MIN_VALUE).unwrap();
+    let (cross, horizontal, vertical, diagonal) = match algorithm {
+        DemosaicAlgorithm::Bilinear => (
+            BILINEAR_CROSS,
+            BILINEAR_HORIZONTAL,
+            BILINEAR_VERTICAL,
+            BILINEAR_DIAGONAL,
+        ),
+        DemosaicAlgorithm::Malvar => (
+            MALVAR_CROSS,
+            MALVAR_HORIZONTAL,
+            MALVAR_VERTICAL,
+            MALVAR_DIAGONAL,
+        ),
+    };
+
+    let sample = |x: i64, y: i64| -> f32 {
+        let x = reflect(x, width);
+        let y = reflect(y, height);
+        raw.get_pixel(x, y)[0].to_f32().unwrap()
+    };
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let own = pattern.color_at(x, y);
+        let mut pixel = Rgb([S::DEFAULT_MIN_VALUE; 3]);
+        for (color, channel) in pixel.0.iter_mut().enumerate() {
+            if color == own {
+                *channel = raw.get_pixel(x as u32, y as u32)[0];
+                continue;
+            }
+            let kernel = if own != 1 && color == 1 {
+                cross
+            } else if own != 1 {
+                diagonal
+            } else if pattern.color_at(x + 1, y) == color {
+                horizontal
+            } else {
+                vertical
+            };
+            let value: f32 = kernel
+                .iter()
+                .map(|&(dx, dy, weight)| sample(x + dx, y + dy) * weight)
+                .sum();
+            *channel = NumCast::from(FloatNearest(value.clamp(min, max))).unwrap();
+        }
+        pixel
+    })
+}
+
+/// Mirror a coordinate at the edges of a line of `len` pixels, without repeating the edge pixel
+/// so that the colors of the pattern stay in place.
+fn reflect(position: i64, len: u32) -> u32 {
+    let last = len as i64 - 1;
+    let position = if position < 0 {
+        -position
+    } else if position > last {
+        2 * last - position
+    } else {
+        position
+    };
+    // Lines shorter than the kernels are reflected more than once, just clamp them.
+    position.max(0).min(last) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PATTERNS: [CfaPattern; 4] = [
+        CfaPattern::Rggb,
+        CfaPattern::Bggr,
+        CfaPattern::Grbg,
+        CfaPattern::Gbrg,
+    ];
+    const ALGORITHMS: [DemosaicAlgorithm; 2] =
+        [DemosaicAlgorithm::Bilinear, DemosaicAlgorithm::Malvar];
+
+    fn mosaic<F>(
+        width: u32,
+        height: u32,
+        pattern: CfaPattern,
+        color: F,
+    ) -> ImageBuffer<Luma<u16>, Vec<u16>>
+    where
+        F: Fn(u32, u32) -> [u16; 3],
+    {
+        ImageBuffer::from_fn(width, height, |x, y| {
+            Luma([color(x, y)[pattern.color_at(x as i64, y as i64)]])
+        })
+    }
+
+    #[test]
+    fn uniform_colors() {
+        let color = [40000, 3000, 15000];
+        for &pattern in &PATTERNS {
+            let raw = mosaic(7, 5, pattern, |_, _| color);
+            for &algorithm in &ALGORITHMS {
+                let rgb = demosaic(&raw, pattern, algorithm);
+                assert!(rgb.pixels().all(|p| p.0 == color), "{:?}", pattern);
+            }
+        }
+    }
+
+    #[test]
+    fn gray_ramp_is_exact() {
+        let ramp = |x: u32, y: u32| [(1000 + x * 300 + y * 200) as u16; 3];
+        for &pattern in &PATTERNS {
+            let raw = mosaic(9, 9, pattern, ramp);
+            for &algorithm in &ALGORITHMS {
+                let rgb = demosaic(&raw, pattern, algorithm);
+                for y in 2..7 {
+                    for x in 2..7 {
+                        assert_eq!(rgb.get_pixel(x, y).0, ramp(x, y));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn keeps_samples() {
+        let raw = ImageBuffer::from_fn(6, 4, |x, y| Luma([(x * 40 + y * 7) as u8]));
+        for &algorithm in &ALGORITHMS {
+            let rgb = demosaic(&raw, CfaPattern::Gbrg, algorithm);
+            for (x, y, pixel) in rgb.enumerate_pixels() {
+                let color = CfaPattern::Gbrg.color_at(x as i64, y as i64);
+                assert_eq!(pixel[color], raw.get_pixel(x, y)[0]);
+            }
+        }
+    }
+
+    #[test]
+    fn tiny_frames() {
+        let raw = ImageBuffer::from_pixel(1, 1, Luma([9u8]));
+        let rgb = demosaic(&raw, CfaPattern::Rggb, DemosaicAlgorithm::Malvar);
+        assert_eq!(rgb.get_pixel(0, 0)[0], 9);
+        let raw = ImageBuffer::<Luma<u8>, _>::new(0, 3);
+        assert_eq!(
+            demosaic(&raw, CfaPattern::Rggb, DemosaicAlgorithm::Bilinear).dimensions(),
+            (0, 3)
+        );
+    }
+}
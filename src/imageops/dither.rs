@@ -0,0 +1,427 @@
+//! Dithering of images to the colors of a color map.
+//!
+//! Reducing an image to few colors, such as the gray levels of an e-ink display or the palette of
+//! an indexed image, bands smooth areas. Dithering trades the bands for a fine pattern that looks
+//! like the original colors from a distance. [`dither_with`] dithers an image to any
+//! [`ColorMap`], such as a [`Palette`], [`BiLevel`] or the gray levels of a bit depth
+//! [`GrayLevels`], after which [`index_colors`] returns the indices of the colors and
+//! [`epaper::pack_bits`] packs them for a display. The [`imageops::dither`] function is the
+//! Floyd-Steinberg dithering that predates this module, which images need at least two columns
+//! and rows for.
+//!
+//! ```
+//! use image::epaper::{pack_bits, BitOrder};
+//! use image::imageops::dither::{dither_with, DitherAlgorithm, GrayLevels};
+//! use image::imageops::index_colors;
+//! use image::{GrayImage, Luma};
+//!
+//! let mut image = GrayImage::from_fn(64, 64, |x, _| Luma([x as u8 * 4]));
+//! let levels = GrayLevels::new(1);
+//! dither_with(&mut image, &levels, DitherAlgorithm::Atkinson);
+//! assert!(image.iter().all(|&value| value == 0 || value == 255));
+//!
+//! let bits = pack_bits(&index_colors(&image, &levels), 1, BitOrder::MsbFirst);
+//! assert_eq!(bits.len(), 64 * 64 / 8);
+//! ```
+//!
+//! [`dither_with`]: fn.dither_with.html
+//! [`GrayLevels`]: struct.GrayLevels.html
+//! [`ColorMap`]: ../colorops/trait.ColorMap.html
+//! [`BiLevel`]: ../colorops/struct.BiLevel.html
+//! [`Palette`]: ../../palette/struct.Palette.html
+//! [`index_colors`]: ../fn.index_colors.html
+//! [`epaper::pack_bits`]: ../../epaper/fn.pack_bits.html
+//! [`imageops::dither`]: ../fn.dither.html
+use crate::imageops::colorops::ColorMap;
+use crate::traits::Pixel;
+use crate::{ImageBuffer, Luma};
+
+/// Algorithms that spread the difference between the colors of an image and the colors of a
+/// color map.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum DitherAlgorithm {
+    /// Diffuse the error of every pixel to its four unvisited neighbours, the classic of Floyd
+    /// and Steinberg.
+    FloydSteinberg,
+    /// Diffuse three quarters of the error over six pixels, as Bill Atkinson did for the
+    /// Macintosh. Keeps more contrast and detail than Floyd-Steinberg, at the cost of clipping
+    /// highlights and shadows, which suits black and white displays.
+    Atkinson,
+    /// Diffuse the error over ten pixels in the next three rows, the three row filter of Frankie
+    /// Sierra. Smoother than Floyd-Steinberg.
+    Sierra,
+    /// Shift every pixel by a threshold from a Bayer matrix before mapping it, which produces a
+    /// regular cross-hatch pattern. Pixels do not depend on each other, so small changes to an
+    /// image do not ripple through the pattern, which suits animations and partial refreshes.
+    Bayer {
+        /// The width and height of the matrix, a power of two from 2 to 16. Larger matrices
+        /// render more shades between two colors.
+        size: u32,
+        /// How far the thresholds shift colors, about the distance between neighbouring colors of
+        /// the map, such as `GrayLevels::spacing`.
+        spread: f32,
+    },
+}
+
+/// Reduce the colors of an image to the colors of a color map, dithering them with an
+/// algorithm.
+///
+/// # Panics
+///
+/// Panics if the size of a Bayer matrix is not a power of two from 2 to 16.
+pub fn dither_with<Pix, Map>(
+    image: &mut ImageBuffer<Pix, Vec<u8>>,
+    color_map: &Map,
+    algorithm: DitherAlgorithm,
+) where
+    Map: ColorMap<Color = Pix> + ?Sized,
+    Pix: Pixel<Subpixel = u8> + 'static,
+{
+    match algorithm {
+        DitherAlgorithm::FloydSteinberg => diffuse(
+            image,
+            color_map,
+            &[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)],
+            16,
+        ),
+        DitherAlgorithm::Atkinson => diffuse(
+            image,
+            color_map,
+            &[
+                (1, 0, 1),
+                (2, 0, 1),
+                (-1, 1, 1),
+                (0, 1, 1),
+                (1, 1, 1),
+                (0, 2, 1),
+            ],
+            8,
+        ),
+        DitherAlgorithm::Sierra => diffuse(
+            image,
+            color_map,
+            &[
+                (1, 0, 5),
+                (2, 0, 3),
+                (-2, 1, 2),
+                (-1, 1, 4),
+                (0, 1, 5),
+                (1, 1, 4),
+                (2, 1, 2),
+                (-1, 2, 2),
+                (0, 2, 3),
+                (1, 2, 2),
+            ],
+            32,
+        ),
+        DitherAlgorithm::Bayer { size, spread } => bayer(image, color_map, size, spread),
+    }
+}
+
+/// Map every pixel and diffuse its error to its neighbours by the weights of a kernel of
+/// `(dx, dy, weight)` entries, divided by `divisor`.
+fn diffuse<Pix, Map>(
+    image: &mut ImageBuffer<Pix, Vec<u8>>,
+    color_map: &Map,
+    kernel: &[(i64, usize, i32)],
+    divisor: i32,
+) where
+    Map: ColorMap<Color = Pix> + ?Sized,
+    Pix: Pixel<Subpixel = u8> + 'static,
+{
+    let (width, height) = image.dimensions();
+    // Only the color channels are diffused, alpha is mapped as is, like ordered dithering does.
+    let channels = if Pix::COLOR_MODEL.ends_with('A') {
+        usize::from(Pix::CHANNEL_COUNT) - 1
+    } else {
+        usize::from(Pix::CHANNEL_COUNT)
+    };
+    // The errors diffused to the current row and the two below it, multiplied by the divisor.
+    let mut errors = vec![vec![0i32; width as usize * channels]; 3];
+    let mut error = vec![0i32; channels];
+    for y in 0..height {
+        for x in 0..width {
+            let offset = x as usize * channels;
+            let pixel = image.get_pixel_mut(x, y);
+            let diffused = &errors[0][offset..offset + channels];
+            for (value, &error) in pixel.channels_mut().iter_mut().zip(diffused) {
+                let adjusted = i32::from(*value) + (error + divisor / 2).div_euclid(divisor);
+                *value = adjusted.clamp(0, 255) as u8;
+            }
+            let adjusted = *pixel;
+            color_map.map_color(pixel);
+
+            for ((error, &old), &new) in error
+                .iter_mut()
+                .zip(adjusted.channels())
+                .zip(pixel.channels())
+            {
+                *error = i32::from(old) - i32::from(new);
+            }
+            for &(dx, dy, weight) in kernel {
+                let target = i64::from(x) + dx;
+                if target < 0 || target >= i64::from(width) {
+                    continue;
+                }
+                let start = target as usize * channels;
+                let row = &mut errors[dy][start..start + channels];
+                for (diffused, &error) in row.iter_mut().zip(&error) {
+                    *diffused += error * weight;
+                }
+            }
+        }
+        errors.rotate_left(1);
+        errors[2].iter_mut().for_each(|error| *error = 0);
+    }
+}
+
+/// Shift every pixel by the threshold of a Bayer matrix and map it.
+fn bayer<Pix, Map>(image: &mut ImageBuffer<Pix, Vec<u8>>, color_map: &Map, size: u32, spread: f32)
+where
+    Map: ColorMap<Color = Pix> + ?Sized,
+    Pix: Pixel<Subpixel = u8> + 'static,
+{
+    assert!(
+        size.is_power_of_two() && (2..=16).contains(&size),
+        "the size of a Bayer matrix must be a power of two from 2 to 16"
+    );
+    let levels = (size * size) as f32;
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let threshold = bayer_threshold(size, x, y) as f32;
+        let offset = ((threshold + 0.5) / levels - 0.5) * spread;
+        pixel.apply_without_alpha(|value| {
+            (f32::from(value) + offset).round().clamp(0.0, 255.0) as u8
+        });
+        color_map.map_color(pixel);
+    }
+}
+
+/// The entry of a Bayer matrix of a size that is a power of two, from 0 to `size * size - 1`.
+fn bayer_threshold(size: u32, x: u32, y: u32) -> u32 {
+    const BASE: [[u32; 2]; 2] = [[0, 2], [3, 1]];
+    let mut threshold = 0;
+    let mut bit = 0;
+    while 1 << bit < size {
+        let (x, y) = ((x >> bit) & 1, (y >> bit) & 1);
+        threshold = 4 * threshold + BASE[y as usize][x as usize];
+        bit += 1;
+    }
+    threshold
+}
+
+/// A color map of the evenly spaced gray levels of a bit depth, such as the black and white of
+/// one bit or the sixteen grays of four bits that e-ink displays show.
+///
+/// Indices are the levels, from 0 for black to `2^bits - 1` for white.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GrayLevels {
+    bits: u8,
+}
+
+impl GrayLevels {
+    /// The gray levels of a bit depth.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bits` is not between 1 and 8.
+    pub fn new(bits: u8) -> Self {
+        assert!(
+            (1..=8).contains(&bits),
+            "the bit depth of gray levels must be between 1 and 8"
+        );
+        GrayLevels { bits }
+    }
+
+    /// The bit depth of the levels.
+    pub fn bits(self) -> u8 {
+        self.bits
+    }
+
+    /// The distance between neighbouring levels, the spread that ordered dithering to the levels
+    /// needs.
+    pub fn spacing(self) -> f32 {
+        255.0 / f32::from(self.max_level())
+    }
+
+    fn max_level(self) -> u16 {
+        (1 << self.bits) - 1
+    }
+}
+
+impl ColorMap for GrayLevels {
+    type Color = Luma<u8>;
+
+    fn index_of(&self, color: &Luma<u8>) -> usize {
+        let max = u32::from(self.max_level());
+        ((u32::from(color[0]) * max + 127) / 255) as usize
+    }
+
+    fn lookup(&self, index: usize) -> Option<Luma<u8>> {
+        let max = usize::from(self.max_level());
+        if index > max {
+            return None;
+        }
+        Some(Luma([((index * 255 + max / 2) / max) as u8]))
+    }
+
+    fn has_lookup(&self) -> bool {
+        true
+    }
+
+    fn map_color(&self, color: &mut Luma<u8>) {
+        if let Some(level) = self.lookup(self.index_of(color)) {
+            *color = level;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imageops::BiLevel;
+    use crate::palette::Palette;
+    use crate::{GrayImage, Rgb, RgbImage, Rgba, RgbaImage};
+
+    fn algorithms() -> [DitherAlgorithm; 4] {
+        [
+            DitherAlgorithm::FloydSteinberg,
+            DitherAlgorithm::Atkinson,
+            DitherAlgorithm::Sierra,
+            DitherAlgorithm::Bayer {
+                size: 4,
+                spread: 255.0,
+            },
+        ]
+    }
+
+    #[test]
+    fn keeps_the_average_gray() {
+        for &algorithm in &algorithms() {
+            // Atkinson drops a quarter of the error, which darkens other grays than the middle.
+            let mut image = GrayImage::from_pixel(32, 32, Luma([128]));
+            dither_with(&mut image, &BiLevel, algorithm);
+            let white = image.iter().filter(|&&value| value == 255).count();
+            assert!(image.iter().all(|&value| value == 0 || value == 255));
+            assert!((480..=544).contains(&white), "{:?} {}", algorithm, white);
+        }
+    }
+
+    #[test]
+    fn narrow_images() {
+        for &algorithm in &algorithms() {
+            let mut image = GrayImage::from_pixel(1, 5, Luma([128]));
+            dither_with(&mut image, &GrayLevels::new(2), algorithm);
+            let mut image = GrayImage::new(0, 0);
+            dither_with(&mut image, &BiLevel, algorithm);
+        }
+    }
+
+    #[test]
+    fn palettes() {
+        let palette = Palette::new(vec![Rgb([0, 0, 0]), Rgb([255, 0, 0]), Rgb([0, 0, 255])]);
+        for &algorithm in &algorithms() {
+            let mut image = RgbImage::from_pixel(16, 16, Rgb([60, 0, 120]));
+            dither_with(&mut image, &palette, algorithm);
+            assert!(image.pixels().all(|pixel| palette.colors().contains(pixel)));
+            // Ordered dithering shifts all channels alike, so it can not mix every color of a
+            // sparse palette.
+            if let DitherAlgorithm::Bayer { .. } = algorithm {
+                continue;
+            }
+            // Loosely, as Atkinson darkens the image.
+            for &(channel, expected) in &[(0, 60), (2, 120)] {
+                let sum: u32 = image.pixels().map(|pixel| u32::from(pixel[channel])).sum();
+                let mean = sum / 256;
+                assert!(
+                    (expected - 30..=expected + 30).contains(&mean),
+                    "{:?} {}",
+                    algorithm,
+                    mean
+                );
+            }
+        }
+    }
+
+    /// Thresholds every channel, including alpha.
+    struct Threshold;
+
+    impl ColorMap for Threshold {
+        type Color = Rgba<u8>;
+
+        fn index_of(&self, _: &Rgba<u8>) -> usize {
+            0
+        }
+
+        fn map_color(&self, color: &mut Rgba<u8>) {
+            color
+                .0
+                .iter_mut()
+                .for_each(|c| *c = if *c < 128 { 0 } else { 255 });
+        }
+    }
+
+    #[test]
+    fn alpha_is_not_diffused() {
+        for &algorithm in &algorithms() {
+            let mut image = RgbaImage::from_pixel(16, 16, Rgba([100, 100, 100, 100]));
+            dither_with(&mut image, &Threshold, algorithm);
+            assert!(image.pixels().all(|pixel| pixel[3] == 0), "{:?}", algorithm);
+            assert!(
+                image.pixels().any(|pixel| pixel[0] == 255),
+                "{:?}",
+                algorithm
+            );
+        }
+    }
+
+    #[test]
+    fn bayer_matrices() {
+        let matrix: Vec<u32> = (0..16).map(|i| bayer_threshold(4, i % 4, i / 4)).collect();
+        assert_eq!(
+            matrix,
+            [0, 8, 2, 10, 12, 4, 14, 6, 3, 11, 1, 9, 15, 7, 13, 5]
+        );
+        let mut entries: Vec<u32> = (0..256)
+            .map(|i| bayer_threshold(16, i % 16, i / 16))
+            .collect();
+        entries.sort_unstable();
+        assert_eq!(entries, (0..256).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn gray_levels() {
+        let levels = GrayLevels::new(2);
+        assert_eq!(levels.spacing(), 85.0);
+        let mapped: Vec<usize> = [0, 42, 43, 128, 212, 213, 255]
+            .iter()
+            .map(|&value| levels.index_of(&Luma([value])))
+            .collect();
+        assert_eq!(mapped, [0, 0, 1, 2, 2, 3, 3]);
+        assert_eq!(levels.lookup(1), Some(Luma([85])));
+        assert_eq!(levels.lookup(4), None);
+        assert_eq!(GrayLevels::new(3).lookup(3), Some(Luma([109])));
+        assert_eq!(GrayLevels::new(8).lookup(200), Some(Luma([200])));
+
+        let mut gradient = GrayImage::from_fn(256, 1, |x, _| Luma([x as u8]));
+        let spread = levels.spacing();
+        dither_with(
+            &mut gradient,
+            &levels,
+            DitherAlgorithm::Bayer { size: 2, spread },
+        );
+        assert!(gradient
+            .iter()
+            .all(|value| [0, 85, 170, 255].contains(value)));
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_bayer_size() {
+        let algorithm = DitherAlgorithm::Bayer {
+            size: 3,
+            spread: 255.0,
+        };
+        dither_with(&mut GrayImage::new(2, 2), &BiLevel, algorithm);
+    }
+}
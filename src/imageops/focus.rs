@@ -0,0 +1,300 @@
+//! Combination of a stack of images focused at different depths.
+use num_traits::NumCast;
+
+use crate::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use crate::traits::{Pixel, Primitive};
+use crate::ImageBuffer;
+
+use super::sample::FloatNearest;
+
+/// The binomial kernel of the pyramids.
+const KERNEL: [f32; 5] = [0.0625, 0.25, 0.375, 0.25, 0.0625];
+
+/// One channel of an image, as floating point values.
+#[derive(Clone)]
+struct Plane {
+    width: usize,
+    height: usize,
+    data: Vec<f32>,
+}
+
+impl Plane {
+    fn from_fn<F: Fn(usize, usize) -> f32>(width: usize, height: usize, f: F) -> Plane {
+        let mut data = Vec::with_capacity(width * height);
+        for y in 0..height {
+            for x in 0..width {
+                data.push(f(x, y));
+            }
+        }
+        Plane {
+            width,
+            height,
+            data,
+        }
+    }
+
+    fn get(&self, x: usize, y: usize) -> f32 {
+        self.data[y * self.width + x]
+    }
+}
+
+/// Combine images of the same scene, each focused at a different depth, into one image that is
+/// sharp throughout.
+///
+/// This is focus stacking as used for microscopy and macro photography, where the depth of field
+/// is too shallow to capture a subject in one exposure. The sharpness of each image is measured
+/// by the local contrast of its luminance, and every pixel is taken from the image that is
+/// sharpest there. To hide the seams between those regions the images are blended in a Laplacian
+/// pyramid, so that fine details switch between images abruptly while coarse shades change
+/// smoothly. The images should be aligned with each other.
+///
+/// The alpha channel is blended like the colors but does not count towards the sharpness.
+///
+/// Returns an `ImageError::Parameter` if the stack is empty or the images differ in size.
+pub fn focus_stack<P, S>(images: &[ImageBuffer<P, Vec<S>>]) -> ImageResult<ImageBuffer<P, Vec<S>>>
+where
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let first = match images.first() {
+        Some(first) => first,
+        None => {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic("the stack contains no images".into()),
+            )))
+        }
+    };
+    let (width, height) = first.dimensions();
+    if images
+        .iter()
+        .any(|image| image.dimensions() != (width, height))
+    {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::DimensionMismatch,
+        )));
+    }
+    let (width, height) = (width as usize, height as usize);
+
+    let channels = P::CHANNEL_COUNT as usize;
+    let color_channels = if P::COLOR_MODEL.ends_with('A') {
+        channels - 1
+    } else {
+        channels
+    };
+    let plane = |image: &ImageBuffer<P, Vec<S>>, c: usize| {
+        let samples = image.as_raw();
+        Plane::from_fn(width, height, |x, y| {
+            samples[(y * width + x) * channels + c].to_f32().unwrap()
+        })
+    };
+
+    // Choose the sharpest image at every pixel.
+    let contrasts: Vec<Plane> = images
+        .iter()
+        .map(|image| {
+            let mut luminance = plane(image, 0);
+            for c in 1..color_channels {
+                let other = plane(image, c);
+                for (value, other) in luminance.data.iter_mut().zip(&other.data) {
+                    *value += other;
+                }
+            }
+            blur(&laplacian(&luminance))
+        })
+        .collect();
+    let mut masks = vec![Plane::from_fn(width, height, |_, _| 0.0); images.len()];
+    for i in 0..width * height {
+        let mut sharpest = 0;
+        for (k, contrast) in contrasts.iter().enumerate() {
+            if contrast.data[i] > contrasts[sharpest].data[i] {
+                sharpest = k;
+            }
+        }
+        masks[sharpest].data[i] = 1.0;
+    }
+
+    let mut levels = 1;
+    let (mut level_width, mut level_height) = (width, height);
+    while level_width.min(level_height) > 8 {
+        level_width = (level_width + 1) / 2;
+        level_height = (level_height + 1) / 2;
+        levels += 1;
+    }
+    let masks: Vec<Vec<Plane>> = masks
+        .iter()
+        .map(|mask| gaussian_pyramid(mask, levels))
+        .collect();
+
+    let max: f32 = NumCast::from(S::DEFAULT_MAX_VALUE).unwrap();
+    let min: f32 = NumCast::from(S::DEFAULT_MIN_VALUE).unwrap();
+    let mut data = vec![S::DEFAULT_MIN_VALUE; width * height * channels];
+    for c in 0..channels {
+        let mut blended: Option<Vec<Plane>> = None;
+        for (image, mask) in images.iter().zip(&masks) {
+            let mut pyramid = laplacian_pyramid(&plane(image, c), levels);
+            for (level, weights) in pyramid.iter_mut().zip(mask) {
+                for (value, weight) in level.data.iter_mut().zip(&weights.data) {
+                    *value *= weight;
+                }
+            }
+            blended = Some(match blended {
+                None => pyramid,
+                Some(mut sum) => {
+                    for (sum, level) in sum.iter_mut().zip(&pyramid) {
+                        for (sum, value) in sum.data.iter_mut().zip(&level.data) {
+                            *sum += value;
+                        }
+                    }
+                    sum
+                }
+            });
+        }
+        let result = collapse(blended.unwrap());
+        for (sample, &value) in data.iter_mut().skip(c).step_by(channels).zip(&result.data) {
+            *sample = NumCast::from(FloatNearest(value.clamp(min, max))).unwrap();
+        }
+    }
+    Ok(ImageBuffer::from_raw(width as u32, height as u32, data).unwrap())
+}
+
+/// The magnitude of the discrete Laplacian, with the edges of the plane repeated.
+fn laplacian(plane: &Plane) -> Plane {
+    let (width, height) = (plane.width, plane.height);
+    Plane::from_fn(width, height, |x, y| {
+        let left = plane.get(x.saturating_sub(1), y);
+        let right = plane.get((x + 1).min(width - 1), y);
+        let up = plane.get(x, y.saturating_sub(1));
+        let down = plane.get(x, (y + 1).min(height - 1));
+        (4.0 * plane.get(x, y) - left - right - up - down).abs()
+    })
+}
+
+/// The value of a line smoothed by the kernel at a position, with the ends of the line repeated.
+fn blur_at<F: Fn(usize) -> f32>(position: usize, len: usize, sample: F) -> f32 {
+    KERNEL
+        .iter()
+        .enumerate()
+        .map(|(k, weight)| {
+            let i = (position as isize + k as isize - 2)
+                .max(0)
+                .min(len as isize - 1);
+            weight * sample(i as usize)
+        })
+        .sum()
+}
+
+/// The value of a line interpolated to twice its length at a position.
+fn expand_at<F: Fn(usize) -> f32>(position: usize, len: usize, sample: F) -> f32 {
+    KERNEL
+        .iter()
+        .enumerate()
+        .filter_map(|(k, weight)| {
+            let offset = position as isize + k as isize - 2;
+            if offset % 2 != 0 {
+                return None;
+            }
+            let i = (offset / 2).max(0).min(len as isize - 1);
+            Some(2.0 * weight * sample(i as usize))
+        })
+        .sum()
+}
+
+fn blur(plane: &Plane) -> Plane {
+    let rows = Plane::from_fn(plane.width, plane.height, |x, y| {
+        blur_at(x, plane.width, |i| plane.get(i, y))
+    });
+    Plane::from_fn(plane.width, plane.height, |x, y| {
+        blur_at(y, rows.height, |i| rows.get(x, i))
+    })
+}
+
+/// Smooth a plane and halve its size.
+fn reduce(plane: &Plane) -> Plane {
+    let rows = Plane::from_fn((plane.width + 1) / 2, plane.height, |x, y| {
+        blur_at(2 * x, plane.width, |i| plane.get(i, y))
+    });
+    Plane::from_fn(rows.width, (plane.height + 1) / 2, |x, y| {
+        blur_at(2 * y, rows.height, |i| rows.get(x, i))
+    })
+}
+
+/// Interpolate a plane to a size of up to twice its own.
+fn expand(plane: &Plane, width: usize, height: usize) -> Plane {
+    let rows = Plane::from_fn(width, plane.height, |x, y| {
+        expand_at(x, plane.width, |i| plane.get(i, y))
+    });
+    Plane::from_fn(width, height, |x, y| {
+        expand_at(y, rows.height, |i| rows.get(x, i))
+    })
+}
+
+fn gaussian_pyramid(plane: &Plane, levels: usize) -> Vec<Plane> {
+    let mut pyramid = vec![plane.clone()];
+    for _ in 1..levels {
+        let next = reduce(pyramid.last().unwrap());
+        pyramid.push(next);
+    }
+    pyramid
+}
+
+/// The differences between the levels of the Gaussian pyramid, and its smallest level.
+fn laplacian_pyramid(plane: &Plane, levels: usize) -> Vec<Plane> {
+    let mut pyramid = gaussian_pyramid(plane, levels);
+    for i in 0..levels - 1 {
+        let coarse = expand(&pyramid[i + 1], pyramid[i].width, pyramid[i].height);
+        for (value, coarse) in pyramid[i].data.iter_mut().zip(&coarse.data) {
+            *value -= coarse;
+        }
+    }
+    pyramid
+}
+
+/// Reconstruct a plane from its Laplacian pyramid.
+fn collapse(mut pyramid: Vec<Plane>) -> Plane {
+    let mut result = pyramid.pop().unwrap();
+    while let Some(mut level) = pyramid.pop() {
+        let coarse = expand(&result, level.width, level.height);
+        for (value, coarse) in level.data.iter_mut().zip(&coarse.data) {
+            *value += coarse;
+        }
+        result = level;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayImage, Luma, Rgba};
+
+    #[test]
+    fn single_image() {
+        let image = ImageBuffer::from_fn(21, 13, |x, y| {
+            Rgba([(x * 12) as u8, (y * 19) as u8, ((x * y) % 256) as u8, 200])
+        });
+        assert_eq!(focus_stack(&[image.clone()]).unwrap(), image);
+        assert_eq!(focus_stack(&[image.clone(), image.clone()]).unwrap(), image);
+    }
+
+    #[test]
+    fn picks_sharp_regions() {
+        let sharp = |x: u32, y: u32| Luma([if (x + y) % 2 == 0 { 40 } else { 160 }]);
+        let blurred = Luma([100]);
+        let near = GrayImage::from_fn(32, 32, |x, y| if x < 16 { sharp(x, y) } else { blurred });
+        let far = GrayImage::from_fn(32, 32, |x, y| if x < 16 { blurred } else { sharp(x, y) });
+        let stacked = focus_stack(&[near, far]).unwrap();
+        for y in 2..30 {
+            for x in (2..12).chain(20..30) {
+                let difference = stacked.get_pixel(x, y)[0] as i32 - sharp(x, y)[0] as i32;
+                assert!(difference.abs() <= 8, "{} {} {}", x, y, difference);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_stacks() {
+        assert!(focus_stack::<Luma<u8>, u8>(&[]).is_err());
+        assert!(focus_stack(&[GrayImage::new(4, 4), GrayImage::new(4, 5)]).is_err());
+        assert!(focus_stack(&[GrayImage::new(0, 0)]).is_ok());
+    }
+}
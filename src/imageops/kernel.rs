@@ -0,0 +1,371 @@
+//! Convolution kernels and the convolution of images with them.
+use num_traits::NumCast;
+
+use crate::image::GenericImageView;
+use crate::traits::{Pixel, Primitive};
+use crate::ImageBuffer;
+
+use super::sample::{sample_range, EdgeMode, FloatNearest};
+
+/// A rectangular matrix of weights to convolve images with.
+///
+/// Kernels have an odd width and height, so that their center lies on a pixel. The weight at
+/// column `x` and row `y` is applied to the pixel at offset `(x - width / 2, y - height / 2)` from
+/// the pixel being filtered, the kernel is not mirrored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kernel {
+    width: u32,
+    height: u32,
+    weights: Vec<f32>,
+}
+
+impl Kernel {
+    /// Create a kernel from its weights in row-major order.
+    ///
+    /// Returns `None` if the width or height is even or if the number of weights does not match
+    /// the dimensions.
+    pub fn new(width: u32, height: u32, weights: Vec<f32>) -> Option<Kernel> {
+        let len = (width as usize).checked_mul(height as usize)?;
+        if width % 2 == 0 || height % 2 == 0 || weights.len() != len {
+            return None;
+        }
+        Some(Kernel {
+            width,
+            height,
+            weights,
+        })
+    }
+
+    /// Create a kernel as the product of a column and a row of weights, which is separable.
+    ///
+    /// Returns `None` if either has an even length.
+    pub fn separable(column: &[f32], row: &[f32]) -> Option<Kernel> {
+        let weights = column
+            .iter()
+            .flat_map(|&c| row.iter().map(move |&r| c * r))
+            .collect();
+        Kernel::new(row.len() as u32, column.len() as u32, weights)
+    }
+
+    /// A normalized Gaussian blur with standard deviation `sigma`, which reaches three standard
+    /// deviations from its center. A `sigma` that is not positive is replaced by 1, like in
+    /// [`blur`](fn.blur.html).
+    pub fn gaussian(sigma: f32) -> Kernel {
+        let sigma = if sigma <= 0.0 { 1.0 } else { sigma };
+        let radius = (3.0 * sigma).ceil() as i32;
+        let line: Vec<f32> = (-radius..=radius)
+            .map(|x| (-((x * x) as f32) / (2.0 * sigma * sigma)).exp())
+            .collect();
+        let mut kernel = Kernel::separable(&line, &line).unwrap();
+        kernel.normalize();
+        kernel
+    }
+
+    /// The horizontal Sobel operator, which responds to edges between dark left and bright right
+    /// pixels.
+    pub fn sobel_x() -> Kernel {
+        Kernel::separable(&[1.0, 2.0, 1.0], &[-1.0, 0.0, 1.0]).unwrap()
+    }
+
+    /// The vertical Sobel operator, which responds to edges between dark upper and bright lower
+    /// pixels.
+    pub fn sobel_y() -> Kernel {
+        Kernel::separable(&[-1.0, 0.0, 1.0], &[1.0, 2.0, 1.0]).unwrap()
+    }
+
+    /// The Laplacian operator of the four direct neighbors, which responds to changes of the
+    /// gradient such as lines and corners.
+    pub fn laplacian() -> Kernel {
+        Kernel::new(3, 3, vec![0.0, 1.0, 0.0, 1.0, -4.0, 1.0, 0.0, 1.0, 0.0]).unwrap()
+    }
+
+    /// A normalized box blur of `size` by `size` pixels, which averages them with equal weights.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is even.
+    pub fn box_filter(size: u32) -> Kernel {
+        assert!(size % 2 == 1, "the size of a box filter must be odd");
+        let line = vec![1.0 / size as f32; size as usize];
+        Kernel::separable(&line, &line).unwrap()
+    }
+
+    /// The number of columns of the kernel.
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    /// The number of rows of the kernel.
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// The weights in row-major order.
+    pub fn weights(&self) -> &[f32] {
+        &self.weights
+    }
+
+    /// The weight at column `x` and row `y`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the position is outside of the kernel.
+    pub fn get(&self, x: u32, y: u32) -> f32 {
+        assert!(x < self.width && y < self.height);
+        self.weights[y as usize * self.width as usize + x as usize]
+    }
+
+    /// The sum of all weights, which is the factor that the kernel scales uniform areas by.
+    pub fn sum(&self) -> f32 {
+        self.weights.iter().sum()
+    }
+
+    /// Scale the weights so that they sum to 1 and the kernel keeps the brightness of images.
+    ///
+    /// Kernels whose weights sum to 0, like edge detectors, are not changed.
+    pub fn normalize(&mut self) {
+        let sum = self.sum();
+        if sum != 0.0 {
+            for weight in &mut self.weights {
+                *weight /= sum;
+            }
+        }
+    }
+
+    /// Returns whether the kernel is the product of a column and a row, so that convolving with
+    /// it takes `width + height` instead of `width * height` multiplications per pixel.
+    pub fn is_separable(&self) -> bool {
+        self.separate().is_some()
+    }
+
+    /// Split the kernel into a column and a row whose product it is, if it is separable.
+    pub fn separate(&self) -> Option<(Vec<f32>, Vec<f32>)> {
+        let width = self.width as usize;
+        // The largest weight determines the row and column that the others are proportional to.
+        let (pivot, &largest) = self
+            .weights
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.abs().partial_cmp(&b.abs()).unwrap())?;
+        if largest == 0.0 {
+            return Some((vec![0.0; self.height as usize], vec![0.0; width]));
+        }
+
+        let (pivot_x, pivot_y) = (pivot % width, pivot / width);
+        let column: Vec<f32> = self
+            .weights
+            .chunks_exact(width)
+            .map(|row| row[pivot_x])
+            .collect();
+        let row: Vec<f32> = self.weights[pivot_y * width..][..width]
+            .iter()
+            .map(|&weight| weight / largest)
+            .collect();
+
+        let tolerance = largest.abs() * 1e-5;
+        let rows = self.weights.chunks_exact(width).zip(&column);
+        for (weights, &c) in rows {
+            for (&weight, &r) in weights.iter().zip(&row) {
+                if (weight - c * r).abs() > tolerance {
+                    return None;
+                }
+            }
+        }
+        Some((column, row))
+    }
+}
+
+/// Convolve an image with a kernel.
+///
+/// All channels, including alpha, are filtered. Neighbors beyond the edges of the image are
+/// sampled according to `edges`. Separable kernels are applied as a horizontal and a vertical
+/// pass. The results saturate at the bounds of the sample type, so integer images lose negative
+/// responses while floating point images keep them.
+///
+/// ```
+/// use image::imageops::{convolve, EdgeMode, Kernel};
+/// use image::{GrayImage, Luma};
+///
+/// let image = GrayImage::from_fn(5, 5, |x, _| Luma([if x < 2 { 0 } else { 200 }]));
+/// let edges = convolve(&image, &Kernel::sobel_x(), EdgeMode::Clamp);
+/// assert_eq!(edges.get_pixel(1, 2), &Luma([255]));
+/// assert_eq!(edges.get_pixel(3, 2), &Luma([0]));
+/// ```
+pub fn convolve<I, P, S>(image: &I, kernel: &Kernel, edges: EdgeMode) -> ImageBuffer<P, Vec<S>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let sums = match kernel.separate() {
+        Some((column, row)) => convolve_separable(image, &column, &row, edges),
+        None => convolve_direct(image, kernel, edges),
+    };
+
+    let (width, height) = image.dimensions();
+    let (lower, upper) = sample_range::<S>();
+    let mut out: ImageBuffer<P, Vec<S>> = ImageBuffer::new(width, height);
+    let channels = P::CHANNEL_COUNT as usize;
+    for (pixel, sums) in out.pixels_mut().zip(sums.chunks_exact(channels)) {
+        for (channel, &sum) in pixel.channels_mut().iter_mut().zip(sums) {
+            *channel = NumCast::from(FloatNearest(sum.clamp(lower, upper))).unwrap();
+        }
+    }
+    out
+}
+
+/// The filtered channels of all pixels in row-major order, with every tap looked up.
+fn convolve_direct<I: GenericImageView>(image: &I, kernel: &Kernel, edges: EdgeMode) -> Vec<f32> {
+    let (width, height) = image.dimensions();
+    let channels = <I::Pixel as Pixel>::CHANNEL_COUNT as usize;
+    let (radius_x, radius_y) = ((kernel.width / 2) as i64, (kernel.height / 2) as i64);
+    let mut sums = vec![0.0; width as usize * height as usize * channels];
+
+    let mut sums_iter = sums.chunks_exact_mut(channels);
+    for y in 0..height {
+        for x in 0..width {
+            let sums = sums_iter.next().unwrap();
+            for (i, &weight) in kernel.weights.iter().enumerate() {
+                let dx = (i % kernel.width as usize) as i64 - radius_x;
+                let dy = (i / kernel.width as usize) as i64 - radius_y;
+                let tap = (
+                    edges.map(x as i64 + dx, width),
+                    edges.map(y as i64 + dy, height),
+                );
+                if let (Some(x), Some(y)) = tap {
+                    let pixel = image.get_pixel(x, y);
+                    for (sum, &c) in sums.iter_mut().zip(pixel.channels()) {
+                        let c: f32 = NumCast::from(c).unwrap();
+                        *sum += weight * c;
+                    }
+                }
+            }
+        }
+    }
+    sums
+}
+
+/// The filtered channels of all pixels in row-major order, filtered by `row` and then `column`.
+fn convolve_separable<I: GenericImageView>(
+    image: &I,
+    column: &[f32],
+    row: &[f32],
+    edges: EdgeMode,
+) -> Vec<f32> {
+    let (width, height) = image.dimensions();
+    let channels = <I::Pixel as Pixel>::CHANNEL_COUNT as usize;
+    let stride = width as usize * channels;
+    let (radius_x, radius_y) = ((row.len() / 2) as i64, (column.len() / 2) as i64);
+
+    let mut horizontal = vec![0.0; stride * height as usize];
+    for y in 0..height {
+        for x in 0..width {
+            let sums = &mut horizontal[y as usize * stride + x as usize * channels..][..channels];
+            for (i, &weight) in row.iter().enumerate() {
+                if let Some(x) = edges.map(x as i64 + i as i64 - radius_x, width) {
+                    let pixel = image.get_pixel(x, y);
+                    for (sum, &c) in sums.iter_mut().zip(pixel.channels()) {
+                        let c: f32 = NumCast::from(c).unwrap();
+                        *sum += weight * c;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut sums = vec![0.0; stride * height as usize];
+    for y in 0..height {
+        let out = &mut sums[y as usize * stride..][..stride];
+        for (i, &weight) in column.iter().enumerate() {
+            if let Some(y) = edges.map(y as i64 + i as i64 - radius_y, height) {
+                let line = &horizontal[y as usize * stride..][..stride];
+                for (sum, &value) in out.iter_mut().zip(line) {
+                    *sum += weight * value;
+                }
+            }
+        }
+    }
+    sums
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayImage, Luma, Rgb, Rgb32FImage};
+
+    #[test]
+    fn constructors() {
+        assert!(Kernel::new(2, 3, vec![0.0; 6]).is_none());
+        assert!(Kernel::new(3, 3, vec![0.0; 8]).is_none());
+        assert!(Kernel::separable(&[1.0, 1.0], &[1.0]).is_none());
+
+        let gaussian = Kernel::gaussian(1.0);
+        assert_eq!((gaussian.width(), gaussian.height()), (7, 7));
+        assert!((gaussian.sum() - 1.0).abs() < 1e-5);
+        assert!(gaussian.get(3, 3) > gaussian.get(2, 3));
+        assert_eq!(gaussian.get(2, 3), gaussian.get(3, 4));
+
+        assert_eq!(
+            Kernel::sobel_x().weights(),
+            &[-1.0, 0.0, 1.0, -2.0, 0.0, 2.0, -1.0, 0.0, 1.0]
+        );
+        assert_eq!(Kernel::sobel_y().get(1, 2), 2.0);
+        assert_eq!(Kernel::box_filter(3).sum(), 1.0);
+    }
+
+    #[test]
+    fn normalization() {
+        let mut kernel = Kernel::new(1, 3, vec![1.0, 2.0, 1.0]).unwrap();
+        kernel.normalize();
+        assert_eq!(kernel.weights(), &[0.25, 0.5, 0.25]);
+
+        let mut laplacian = Kernel::laplacian();
+        laplacian.normalize();
+        assert_eq!(laplacian, Kernel::laplacian());
+    }
+
+    #[test]
+    fn separability() {
+        let (column, row) = Kernel::sobel_x().separate().unwrap();
+        assert_eq!(Kernel::separable(&column, &row).unwrap(), Kernel::sobel_x());
+        assert!(Kernel::gaussian(2.0).is_separable());
+        assert!(Kernel::new(1, 1, vec![0.0]).unwrap().is_separable());
+        assert!(!Kernel::laplacian().is_separable());
+    }
+
+    #[test]
+    fn separable_and_direct_agree() {
+        let image = Rgb32FImage::from_fn(9, 7, |x, y| {
+            Rgb([x as f32, (x * y) as f32 / 10.0, (y % 3) as f32])
+        });
+        let kernel = Kernel::separable(&[0.5, 1.0, 2.0, 1.0, -0.5], &[1.0, 3.0, -1.0]).unwrap();
+        let modes = [
+            EdgeMode::Clamp,
+            EdgeMode::Wrap,
+            EdgeMode::Mirror,
+            EdgeMode::Zero,
+        ];
+        for &edges in &modes {
+            let (column, row) = kernel.separate().unwrap();
+            let separable = convolve_separable(&image, &column, &row, edges);
+            let direct = convolve_direct(&image, &kernel, edges);
+            for (a, b) in separable.iter().zip(&direct) {
+                assert!((a - b).abs() < 1e-3, "{} != {} with {:?}", a, b, edges);
+            }
+        }
+    }
+
+    #[test]
+    fn convolves_images() {
+        let image = GrayImage::from_fn(3, 3, |x, y| Luma([if (x, y) == (1, 1) { 90 } else { 0 }]));
+        let blurred = convolve(&image, &Kernel::box_filter(3), EdgeMode::Zero);
+        assert!(blurred.pixels().all(|pixel| pixel[0] == 10));
+
+        let lines = convolve(&image, &Kernel::laplacian(), EdgeMode::Zero);
+        assert_eq!(lines.into_raw(), vec![0, 90, 0, 90, 0, 90, 0, 90, 0]);
+
+        let image = Rgb32FImage::from_fn(3, 1, |x, _| Rgb([x as f32; 3]));
+        let gradient = convolve(&image, &Kernel::sobel_x(), EdgeMode::Clamp);
+        assert_eq!(gradient.get_pixel(1, 0), &Rgb([8.0; 3]));
+    }
+}
@@ -4,7 +4,7 @@ use std::cmp;
 use crate::image::{GenericImage, GenericImageView, SubImage};
 use crate::traits::{Lerp, Pixel, Primitive};
 
-pub use self::sample::FilterType;
+pub use self::sample::{EdgeMode, Filter, FilterType};
 
 pub use self::sample::FilterType::{CatmullRom, Gaussian, Lanczos3, Nearest, Triangle};
 
@@ -16,19 +16,69 @@ pub use self::affine::{
 };
 
 /// Image sampling
-pub use self::sample::{blur, filter3x3, resize, thumbnail, unsharpen};
+pub use self::sample::{
+    blur, filter3x3, resize, resize_region, resize_with_filter, thumbnail, thumbnail_dpr, unsharpen,
+};
+
+/// Convolution with kernels
+pub use self::kernel::{convolve, Kernel};
+
+/// Calibration of scientific camera frames
+pub use self::calibrate::calibrate;
+
+/// Demosaicing of raw sensor frames
+pub use self::demosaic::{demosaic, CfaPattern, DemosaicAlgorithm};
+
+/// Focus stacking
+pub use self::focus::focus_stack;
+
+/// Alignment of images
+#[cfg(feature = "registration")]
+pub use self::registration::register_translation;
+
+/// Neighborhoods of pixels
+pub use self::neighborhood::{Neighborhood, Windows};
+
+/// Display of high bit depth images
+pub use self::window::window_level;
+
+/// Display of HDR images
+pub use self::tonemap::{tone_map, tone_map_in_place, ToneMapping};
+
+/// Color quantization
+pub use self::quantize::{quantize, QuantizeAlgorithm};
+
+/// Dithering
+pub use self::dither::{dither_with, DitherAlgorithm, GrayLevels};
+
+/// Watermarks
+pub use self::watermark::{watermark, WatermarkOptions, WatermarkPosition};
 
 /// Color operations
 pub use self::colorops::{
-    brighten, contrast, dither, grayscale, grayscale_alpha, grayscale_with_type,
-    grayscale_with_type_alpha, huerotate, index_colors, invert, BiLevel, ColorMap,
+    brighten, brighten_with, contrast, contrast_with, dither, grayscale, grayscale_alpha,
+    grayscale_with_type, grayscale_with_type_alpha, huerotate, index_colors, invert, BiLevel,
+    ColorMap, ToneOptions,
 };
 
 mod affine;
+mod calibrate;
+mod demosaic;
+pub mod dither;
+mod focus;
+mod kernel;
+pub(crate) mod neighborhood;
+mod quantize;
+#[cfg(feature = "registration")]
+mod registration;
 // Public only because of Rust bug:
 // https://github.com/rust-lang/rust/issues/18241
 pub mod colorops;
-mod sample;
+pub mod projection;
+pub(crate) mod sample;
+pub mod tonemap;
+mod watermark;
+pub(crate) mod window;
 
 /// Return a mutable view into an image
 /// The coordinates set the position of the top left corner of the crop.
@@ -212,6 +262,9 @@ fn overlay_bounds_ext(
 }
 
 /// Overlay an image at a given coordinate (x, y)
+///
+/// Pixels with alpha are composited over the image. Floating point pixels are composited as they
+/// are, so that colors beyond 1 of HDR images are kept.
 pub fn overlay<I, J>(bottom: &mut I, top: &J, x: i64, y: i64)
 where
     I: GenericImage,
@@ -403,6 +456,18 @@ mod tests {
         assert!(*target.get_pixel(0, 16) == Rgb([0u8, 0, 0]));
     }
 
+    #[test]
+    fn test_overlay_float() {
+        use crate::color::Rgba;
+        use crate::Rgba32FImage;
+
+        let mut bottom = Rgba32FImage::from_pixel(2, 1, Rgba([4.0, 0.0, 0.0, 1.0]));
+        let top = Rgba32FImage::from_pixel(1, 1, Rgba([0.0, 2.0, 0.0, 0.5]));
+        overlay(&mut bottom, &top, 1, 0);
+        assert_eq!(bottom.get_pixel(0, 0), &Rgba([4.0, 0.0, 0.0, 1.0]));
+        assert_eq!(bottom.get_pixel(1, 0), &Rgba([2.0, 1.0, 0.0, 1.0]));
+    }
+
     #[test]
     /// Test that images written outside of a frame doesn't blow up
     fn test_image_in_image_outside_of_bounds() {
@@ -0,0 +1,193 @@
+//! Iteration over the neighborhoods of pixels, for local filters.
+use num_traits::Zero;
+
+use crate::image::GenericImageView;
+use crate::traits::Pixel;
+
+use super::sample::EdgeMode;
+
+/// Iterate over the pixels of an image together with their neighborhoods.
+///
+/// This struct is created by [`GenericImageView::windows`] and
+/// [`GenericImageView::windows_with_edges`].
+///
+/// [`GenericImageView::windows`]: ../trait.GenericImageView.html#method.windows
+/// [`GenericImageView::windows_with_edges`]: ../trait.GenericImageView.html#method.windows_with_edges
+#[derive(Debug)]
+pub struct Windows<'a, I: ?Sized> {
+    image: &'a I,
+    radius: u32,
+    edges: EdgeMode,
+    x: u32,
+    y: u32,
+}
+
+impl<'a, I: GenericImageView + ?Sized> Windows<'a, I> {
+    pub(crate) fn new(image: &'a I, radius: u32, edges: EdgeMode) -> Self {
+        Windows {
+            image,
+            radius,
+            edges,
+            x: 0,
+            y: 0,
+        }
+    }
+}
+
+impl<'a, I: GenericImageView + ?Sized> Iterator for Windows<'a, I> {
+    type Item = (u32, u32, Neighborhood<'a, I>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (width, height) = self.image.dimensions();
+        if self.x >= width {
+            self.x = 0;
+            self.y += 1;
+        }
+        if self.y >= height || width == 0 {
+            return None;
+        }
+        let (x, y) = (self.x, self.y);
+        self.x += 1;
+
+        let radius = u64::from(self.radius);
+        let interior = u64::from(x) >= radius
+            && u64::from(y) >= radius
+            && u64::from(x) + radius < u64::from(width)
+            && u64::from(y) + radius < u64::from(height);
+        let neighborhood = Neighborhood {
+            image: self.image,
+            x,
+            y,
+            radius: self.radius,
+            edges: self.edges,
+            interior,
+        };
+        Some((x, y, neighborhood))
+    }
+}
+
+/// The pixels around a pixel of an image, within a radius.
+///
+/// Pixels are addressed by their offset from the center. Offsets that reach beyond the edges of
+/// the image are sampled according to the [`EdgeMode`] of the iteration, so a filter needs no
+/// special cases at the borders.
+///
+/// [`EdgeMode`]: enum.EdgeMode.html
+#[derive(Debug)]
+pub struct Neighborhood<'a, I: ?Sized> {
+    image: &'a I,
+    x: u32,
+    y: u32,
+    radius: u32,
+    edges: EdgeMode,
+    /// Whether the whole neighborhood lies within the image.
+    interior: bool,
+}
+
+impl<'a, I: GenericImageView + ?Sized> Neighborhood<'a, I> {
+    /// The position of the center pixel in the image.
+    pub fn position(&self) -> (u32, u32) {
+        (self.x, self.y)
+    }
+
+    /// The largest offset from the center in either direction.
+    pub fn radius(&self) -> u32 {
+        self.radius
+    }
+
+    /// The center pixel.
+    pub fn center(&self) -> I::Pixel {
+        self.image.get_pixel(self.x, self.y)
+    }
+
+    /// Returns whether the whole neighborhood lies within the image.
+    pub(crate) fn is_interior(&self) -> bool {
+        self.interior
+    }
+
+    /// Returns the pixel at an offset from the center.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the offset is larger than the radius in either direction.
+    pub fn get(&self, dx: i32, dy: i32) -> I::Pixel {
+        let radius = i64::from(self.radius);
+        let (dx, dy) = (i64::from(dx), i64::from(dy));
+        assert!(
+            dx.abs() <= radius && dy.abs() <= radius,
+            "offset ({}, {}) is outside of the neighborhood of radius {}",
+            dx,
+            dy,
+            radius
+        );
+        let (x, y) = (i64::from(self.x) + dx, i64::from(self.y) + dy);
+        if self.interior {
+            // The offset is within the radius, so the position is within the image.
+            return unsafe { self.image.unsafe_get_pixel(x as u32, y as u32) };
+        }
+
+        let (width, height) = self.image.dimensions();
+        match (self.edges.map(x, width), self.edges.map(y, height)) {
+            (Some(x), Some(y)) => self.image.get_pixel(x, y),
+            _ => {
+                let mut pixel = self.center();
+                for channel in pixel.channels_mut() {
+                    *channel = Zero::zero();
+                }
+                pixel
+            }
+        }
+    }
+
+    /// Returns an iterator over the pixels of the neighborhood, row by row, with their offsets
+    /// from the center.
+    pub fn pixels(&self) -> impl Iterator<Item = (i32, i32, I::Pixel)> + '_ {
+        let radius = self.radius as i32;
+        (-radius..=radius)
+            .flat_map(move |dy| (-radius..=radius).map(move |dx| (dx, dy)))
+            .map(move |(dx, dy)| (dx, dy, self.get(dx, dy)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayImage, Luma};
+
+    #[test]
+    fn edges_and_interior() {
+        let image = GrayImage::from_fn(4, 3, |x, y| Luma([(10 * y + x) as u8]));
+        let windows: Vec<_> = image.windows(1).collect();
+        assert_eq!(windows.len(), 12);
+
+        let (x, y, window) = &windows[5];
+        assert_eq!((*x, *y), (1, 1));
+        assert!(window.is_interior());
+        assert_eq!(window.get(1, -1), Luma([2]));
+        assert_eq!(window.center(), Luma([11]));
+
+        let (_, _, corner) = &windows[0];
+        assert!(!corner.is_interior());
+        assert_eq!(corner.get(-1, -1), Luma([0]));
+        assert_eq!(corner.get(1, 1), Luma([11]));
+        let sum: u32 = corner.pixels().map(|(_, _, p)| u32::from(p[0])).sum();
+        // The first row, of sum 1, is repeated for the missing row above.
+        assert_eq!(sum, 33);
+
+        let (_, _, corner) = image.windows_with_edges(1, EdgeMode::Wrap).next().unwrap();
+        assert_eq!(corner.get(-1, -1), Luma([23]));
+        let (_, _, corner) = image.windows_with_edges(1, EdgeMode::Zero).last().unwrap();
+        assert_eq!(corner.get(1, 0), Luma([0]));
+        assert_eq!(corner.get(-1, 0), Luma([22]));
+
+        assert_eq!(GrayImage::new(0, 5).windows(2).count(), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn offset_beyond_radius() {
+        let image = GrayImage::new(8, 8);
+        let (_, _, window) = image.windows(1).nth(20).unwrap();
+        window.get(2, 0);
+    }
+}
@@ -0,0 +1,265 @@
+//! Conversions between the projections of 360° panoramas.
+//!
+//! An equirectangular panorama maps the longitude of a direction linearly to the x coordinate and
+//! its latitude to the y coordinate. The center of the image looks forward, its left and right
+//! edges look backward, and its top and bottom edges look straight up and down.
+//!
+//! The faces of a cubemap follow the OpenGL convention. Looking forward is the positive Z face,
+//! right is the positive X face and up is the positive Y face.
+use std::f32::consts::PI;
+
+use crate::image::GenericImageView;
+use crate::traits::{Pixel, Primitive};
+use crate::ImageBuffer;
+
+use super::sample::EdgeMode;
+
+/// A face of a cubemap.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CubeFace {
+    /// The face to the right.
+    PositiveX,
+    /// The face to the left.
+    NegativeX,
+    /// The face above.
+    PositiveY,
+    /// The face below.
+    NegativeY,
+    /// The face in front.
+    PositiveZ,
+    /// The face behind.
+    NegativeZ,
+}
+
+impl CubeFace {
+    /// All faces, in the order in which cubemaps are stored.
+    pub const ALL: [CubeFace; 6] = [
+        CubeFace::PositiveX,
+        CubeFace::NegativeX,
+        CubeFace::PositiveY,
+        CubeFace::NegativeY,
+        CubeFace::PositiveZ,
+        CubeFace::NegativeZ,
+    ];
+
+    /// The direction through a point of this face, where `s` goes right and `t` goes down, both
+    /// from -1 to 1.
+    fn direction(self, s: f32, t: f32) -> [f32; 3] {
+        match self {
+            CubeFace::PositiveX => [1.0, -t, -s],
+            CubeFace::NegativeX => [-1.0, -t, s],
+            CubeFace::PositiveY => [s, 1.0, t],
+            CubeFace::NegativeY => [s, -1.0, -t],
+            CubeFace::PositiveZ => [s, -t, 1.0],
+            CubeFace::NegativeZ => [-s, -t, -1.0],
+        }
+    }
+
+    /// The face a direction points through, and the point on it as in `direction`.
+    fn from_direction([x, y, z]: [f32; 3]) -> (CubeFace, f32, f32) {
+        let (ax, ay, az) = (x.abs(), y.abs(), z.abs());
+        if ax >= ay && ax >= az {
+            if x > 0.0 {
+                (CubeFace::PositiveX, -z / ax, -y / ax)
+            } else {
+                (CubeFace::NegativeX, z / ax, -y / ax)
+            }
+        } else if ay >= az {
+            if y > 0.0 {
+                (CubeFace::PositiveY, x / ay, z / ay)
+            } else {
+                (CubeFace::NegativeY, x / ay, -z / ay)
+            }
+        } else if z > 0.0 {
+            (CubeFace::PositiveZ, x / az, -y / az)
+        } else {
+            (CubeFace::NegativeZ, -x / az, -y / az)
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+/// Converts an equirectangular panorama into the six square faces of a cubemap.
+///
+/// The faces are returned in the order of [`CubeFace::ALL`].
+///
+/// [`CubeFace::ALL`]: enum.CubeFace.html#associatedconstant.ALL
+pub fn equirectangular_to_cubemap<I, P, S>(image: &I, face_size: u32) -> [ImageBuffer<P, Vec<S>>; 6]
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let face = |face: CubeFace| {
+        render(image, face_size, face_size, |x, y| {
+            let s = 2.0 * (x as f32 + 0.5) / face_size as f32 - 1.0;
+            let t = 2.0 * (y as f32 + 0.5) / face_size as f32 - 1.0;
+            face.direction(s, t)
+        })
+    };
+    [
+        face(CubeFace::PositiveX),
+        face(CubeFace::NegativeX),
+        face(CubeFace::PositiveY),
+        face(CubeFace::NegativeY),
+        face(CubeFace::PositiveZ),
+        face(CubeFace::NegativeZ),
+    ]
+}
+
+/// Converts the six faces of a cubemap, in the order of [`CubeFace::ALL`], into an
+/// equirectangular panorama.
+///
+/// [`CubeFace::ALL`]: enum.CubeFace.html#associatedconstant.ALL
+pub fn cubemap_to_equirectangular<I, P, S>(
+    faces: &[I; 6],
+    width: u32,
+    height: u32,
+) -> ImageBuffer<P, Vec<S>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    if faces
+        .iter()
+        .any(|face| face.width() == 0 || face.height() == 0)
+    {
+        return ImageBuffer::new(width, height);
+    }
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let longitude = ((x as f32 + 0.5) / width as f32 - 0.5) * 2.0 * PI;
+        let latitude = (0.5 - (y as f32 + 0.5) / height as f32) * PI;
+        let (face, s, t) = CubeFace::from_direction(direction(longitude, latitude));
+        let image = &faces[face.index()];
+        let (face_width, face_height) = image.dimensions();
+        let x = (s + 1.0) / 2.0 * face_width as f32 - 0.5;
+        let y = (t + 1.0) / 2.0 * face_height as f32 - 0.5;
+        image.sample_bilinear(x, y)
+    })
+}
+
+/// Renders a perspective view of an equirectangular panorama.
+///
+/// The view looks into `view_direction`, given as yaw and pitch in radians. Positive yaw turns to
+/// the right and positive pitch looks up. The horizontal field of view `fov` is given in radians
+/// as well, the vertical one follows from the aspect ratio of `output_size`.
+pub fn reproject<I, P, S>(
+    image: &I,
+    view_direction: (f32, f32),
+    fov: f32,
+    output_size: (u32, u32),
+) -> ImageBuffer<P, Vec<S>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let (width, height) = output_size;
+    let (yaw, pitch) = view_direction;
+    let (sin_yaw, cos_yaw) = yaw.sin_cos();
+    let (sin_pitch, cos_pitch) = pitch.sin_cos();
+    let scale = (fov / 2.0).tan();
+
+    render(image, width, height, |x, y| {
+        let s = (2.0 * (x as f32 + 0.5) / width as f32 - 1.0) * scale;
+        let t =
+            (2.0 * (y as f32 + 0.5) / height as f32 - 1.0) * scale * height as f32 / width as f32;
+        // Tilt the ray of the camera looking forward, then turn it.
+        let (x, y, z) = (s, -t, 1.0);
+        let (y, z) = (y * cos_pitch + z * sin_pitch, z * cos_pitch - y * sin_pitch);
+        [x * cos_yaw + z * sin_yaw, y, z * cos_yaw - x * sin_yaw]
+    })
+}
+
+/// The unit direction of a longitude and latitude, in radians.
+fn direction(longitude: f32, latitude: f32) -> [f32; 3] {
+    let (sin_lon, cos_lon) = longitude.sin_cos();
+    let (sin_lat, cos_lat) = latitude.sin_cos();
+    [cos_lat * sin_lon, sin_lat, cos_lat * cos_lon]
+}
+
+/// Renders an image by sampling the equirectangular `image` in the direction of each pixel.
+fn render<I, P, S, F>(image: &I, width: u32, height: u32, direction: F) -> ImageBuffer<P, Vec<S>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+    F: Fn(u32, u32) -> [f32; 3],
+{
+    let (source_width, source_height) = image.dimensions();
+    if source_width == 0 || source_height == 0 {
+        return ImageBuffer::new(width, height);
+    }
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let [x, y, z] = direction(x, y);
+        let length = (x * x + y * y + z * z).sqrt();
+        let longitude = x.atan2(z);
+        let latitude = (y / length).clamp(-1.0, 1.0).asin();
+        let u = (longitude / (2.0 * PI) + 0.5) * source_width as f32 - 0.5;
+        // The panorama repeats around the horizon, but not beyond the poles.
+        let v = ((0.5 - latitude / PI) * source_height as f32 - 0.5)
+            .clamp(0.0, source_height as f32 - 1.0);
+        image.sample_bilinear_with_edges(u, v, EdgeMode::Wrap)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayImage, Luma};
+    use std::f32::consts::FRAC_PI_2;
+
+    /// A panorama whose value changes with the quadrant of longitude, 0 to 3 from behind on the
+    /// left, plus 100 in the upper half.
+    fn quadrants() -> GrayImage {
+        GrayImage::from_fn(64, 32, |x, y| {
+            let quadrant = ((x + 8) / 16 % 4) as u8;
+            Luma([quadrant * 10 + if y < 16 { 100 } else { 0 }])
+        })
+    }
+
+    #[test]
+    fn cubemap_faces() {
+        let faces = equirectangular_to_cubemap(&quadrants(), 16);
+        assert!(faces[CubeFace::PositiveY.index()]
+            .pixels()
+            .all(|p| p[0] >= 100));
+        assert!(faces[CubeFace::NegativeY.index()]
+            .pixels()
+            .all(|p| p[0] < 100));
+        // Away from the seams, the side faces take the quadrant they look into.
+        assert_eq!(faces[CubeFace::PositiveZ.index()].get_pixel(8, 4)[0], 120);
+        assert_eq!(faces[CubeFace::PositiveX.index()].get_pixel(8, 12)[0], 30);
+        assert_eq!(faces[CubeFace::NegativeZ.index()].get_pixel(8, 12)[0], 0);
+        assert_eq!(faces[CubeFace::NegativeX.index()].get_pixel(8, 12)[0], 10);
+    }
+
+    #[test]
+    fn cubemap_round_trip() {
+        let panorama = GrayImage::from_fn(128, 64, |_, y| Luma([(y * 4) as u8]));
+        let faces = equirectangular_to_cubemap(&panorama, 48);
+        let round_trip: GrayImage = cubemap_to_equirectangular(&faces, 128, 64);
+        let error: u32 = panorama
+            .pixels()
+            .zip(round_trip.pixels())
+            .map(|(a, b)| (a[0] as i32 - b[0] as i32).unsigned_abs())
+            .sum();
+        assert!(error / (128 * 64) <= 2);
+    }
+
+    #[test]
+    fn reproject_view_direction() {
+        let panorama = quadrants();
+        let view = |yaw, pitch| reproject(&panorama, (yaw, pitch), FRAC_PI_2 / 2.0, (9, 5));
+        assert_eq!(view(0.0, 0.2).get_pixel(4, 2)[0], 120);
+        assert_eq!(view(0.0, -0.2).get_pixel(4, 2)[0], 20);
+        assert_eq!(view(FRAC_PI_2, -0.2).get_pixel(4, 2)[0], 30);
+        assert_eq!(view(-FRAC_PI_2, -0.2).get_pixel(4, 2)[0], 10);
+        // Looking straight up sees the upper half in every direction.
+        assert!(view(0.0, FRAC_PI_2).pixels().all(|p| p[0] >= 100));
+    }
+}
@@ -0,0 +1,333 @@
+//! Reduction of images to a small palette of colors.
+use std::collections::HashMap;
+
+use crate::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use crate::imageops::colorops::ColorMap;
+use crate::palette::Palette;
+use crate::{GrayImage, ImageBuffer, Rgb, RgbImage};
+
+/// Algorithms that choose the colors of a palette for an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum QuantizeAlgorithm {
+    /// Split the colors into boxes of about as many pixels each, cutting the box with the widest
+    /// range of a channel, and take the average color of every box. Fast, and keeps gradients
+    /// even.
+    MedianCut,
+    /// Sort the colors into an octree of their bits and merge the least common branches until
+    /// few enough leaves remain. Fast, and keeps small areas of distinct color.
+    Octree,
+    /// Start from the median cut palette and move every color to the average of the pixels
+    /// nearest to it, until the colors settle. Slower, but finds the colors that represent the
+    /// image best, such as its dominant colors.
+    KMeans {
+        /// The most rounds of moving the colors, commonly 10.
+        iterations: u32,
+    },
+}
+
+impl Default for QuantizeAlgorithm {
+    fn default() -> Self {
+        QuantizeAlgorithm::MedianCut
+    }
+}
+
+/// Reduce an image to at most `max_colors` colors, returning the palette and the indices of the
+/// palette colors of all pixels.
+///
+/// The palette is ordered from the most to the least common color, so its first colors are the
+/// dominant colors of the image. An image of few enough colors keeps them exactly. Every pixel is
+/// replaced by the nearest color of the palette, use [`dither`] on the image first to spread the
+/// error instead. The indexed image suits encoders of palette images, such as PNG8 and GIF, and
+/// [`Palette::apply`] turns it back into colors.
+///
+/// ```
+/// use image::imageops::{quantize, QuantizeAlgorithm};
+/// use image::{Rgb, RgbImage};
+///
+/// let red = Rgb([200, 0, 0]);
+/// let blue = Rgb([0, 0, 90]);
+/// let image = RgbImage::from_fn(8, 8, |x, _| if x < 6 { red } else { blue });
+/// let algorithm = QuantizeAlgorithm::KMeans { iterations: 10 };
+/// let (palette, indices) = quantize(&image, 16, algorithm)?;
+/// assert_eq!(palette.colors(), &[red, blue]);
+/// assert_eq!(palette.apply(&indices), image);
+/// # Ok::<(), image::ImageError>(())
+/// ```
+///
+/// Returns an `ImageError::Parameter` if `max_colors` is not between 1 and 256, the number of
+/// indices that an eight bit image holds.
+///
+/// [`dither`]: fn.dither.html
+/// [`Palette::apply`]: ../palette/struct.Palette.html#method.apply
+pub fn quantize(
+    image: &RgbImage,
+    max_colors: usize,
+    algorithm: QuantizeAlgorithm,
+) -> ImageResult<(Palette, GrayImage)> {
+    if max_colors == 0 || max_colors > 256 {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::Generic(format!(
+                "a palette of {} colors can not index an eight bit image",
+                max_colors
+            )),
+        )));
+    }
+
+    let counts = color_counts(image);
+    let colors = if counts.len() <= max_colors {
+        counts.iter().map(|&(color, _)| color).collect()
+    } else {
+        match algorithm {
+            QuantizeAlgorithm::MedianCut => median_cut(&counts, max_colors),
+            QuantizeAlgorithm::Octree => octree(&counts, max_colors),
+            QuantizeAlgorithm::KMeans { iterations } => {
+                k_means(&counts, median_cut(&counts, max_colors), iterations)
+            }
+        }
+    };
+    let palette = by_popularity(&counts, colors);
+
+    // Images hold far fewer distinct colors than pixels, so every color is looked up once.
+    let indices: HashMap<Rgb<u8>, u8> = counts
+        .iter()
+        .map(|&(color, _)| (color, palette.index_of(&color) as u8))
+        .collect();
+    let data = image.pixels().map(|pixel| indices[pixel]).collect();
+    let indexed = ImageBuffer::from_raw(image.width(), image.height(), data).unwrap();
+    Ok((palette, indexed))
+}
+
+/// The distinct colors of an image with their number of pixels, ordered by color.
+fn color_counts(image: &RgbImage) -> Vec<(Rgb<u8>, u64)> {
+    let mut counts: HashMap<Rgb<u8>, u64> = HashMap::new();
+    for &pixel in image.pixels() {
+        *counts.entry(pixel).or_insert(0) += 1;
+    }
+    let mut counts: Vec<_> = counts.into_iter().collect();
+    counts.sort_unstable_by_key(|(color, _)| color.0);
+    counts
+}
+
+fn median_cut(counts: &[(Rgb<u8>, u64)], max_colors: usize) -> Vec<Rgb<u8>> {
+    Palette::median_cut_of_counts(counts.to_vec(), max_colors).into_colors()
+}
+
+/// A node of the octree, holding the colors whose top `level` bits of every channel are `key`.
+struct Node {
+    level: u8,
+    key: [u8; 3],
+    sums: [u64; 3],
+    count: u64,
+}
+
+impl Node {
+    fn color(&self) -> Rgb<u8> {
+        let average = |sum: u64| ((sum + self.count / 2) / self.count) as u8;
+        Rgb([
+            average(self.sums[0]),
+            average(self.sums[1]),
+            average(self.sums[2]),
+        ])
+    }
+}
+
+fn octree(counts: &[(Rgb<u8>, u64)], max_colors: usize) -> Vec<Rgb<u8>> {
+    let mut leaves: Vec<Node> = counts
+        .iter()
+        .map(|&(color, count)| Node {
+            level: 8,
+            key: color.0,
+            sums: [
+                u64::from(color[0]) * count,
+                u64::from(color[1]) * count,
+                u64::from(color[2]) * count,
+            ],
+            count,
+        })
+        .collect();
+
+    while leaves.len() > max_colors {
+        // Merge the children of the least common parents of the deepest leaves.
+        let deepest = leaves.iter().map(|node| node.level).max().unwrap();
+        let (deep, mut kept): (Vec<Node>, Vec<Node>) =
+            leaves.into_iter().partition(|node| node.level == deepest);
+        let mut parents: HashMap<[u8; 3], Vec<Node>> = HashMap::new();
+        for node in deep {
+            let key = [node.key[0] >> 1, node.key[1] >> 1, node.key[2] >> 1];
+            parents.entry(key).or_default().push(node);
+        }
+        let mut parents: Vec<([u8; 3], Vec<Node>)> = parents.into_iter().collect();
+        parents.sort_unstable_by_key(|(key, children)| {
+            (children.iter().map(|node| node.count).sum::<u64>(), *key)
+        });
+
+        let mut remaining = kept.len() + parents.iter().map(|(_, c)| c.len()).sum::<usize>();
+        let mut parents = parents.into_iter();
+        while remaining > max_colors {
+            let (key, children) = match parents.next() {
+                Some(parent) => parent,
+                None => break,
+            };
+            remaining -= children.len() - 1;
+            let mut merged = Node {
+                level: deepest - 1,
+                key,
+                sums: [0; 3],
+                count: 0,
+            };
+            for child in children {
+                for (sum, child) in merged.sums.iter_mut().zip(&child.sums) {
+                    *sum += child;
+                }
+                merged.count += child.count;
+            }
+            kept.push(merged);
+        }
+        kept.extend(parents.flat_map(|(_, children)| children));
+        leaves = kept;
+    }
+
+    leaves.iter().map(Node::color).collect()
+}
+
+fn k_means(counts: &[(Rgb<u8>, u64)], mut centers: Vec<Rgb<u8>>, iterations: u32) -> Vec<Rgb<u8>> {
+    let mut assignment = vec![usize::MAX; counts.len()];
+    for _ in 0..iterations {
+        let palette = Palette::new(centers.clone());
+        let mut changed = false;
+        for (nearest, &(color, _)) in assignment.iter_mut().zip(counts) {
+            let index = palette.index_of(&color);
+            changed |= *nearest != index;
+            *nearest = index;
+        }
+        if !changed {
+            break;
+        }
+
+        let mut sums = vec![([0u64; 3], 0u64); centers.len()];
+        for (&nearest, &(color, count)) in assignment.iter().zip(counts) {
+            let (sum, total) = &mut sums[nearest];
+            for (sum, &value) in sum.iter_mut().zip(&color.0) {
+                *sum += u64::from(value) * count;
+            }
+            *total += count;
+        }
+        // A color that no pixel is nearest to stays where it is.
+        for (center, &(sum, total)) in centers.iter_mut().zip(&sums) {
+            if total > 0 {
+                let average = |sum: u64| ((sum + total / 2) / total) as u8;
+                *center = Rgb([average(sum[0]), average(sum[1]), average(sum[2])]);
+            }
+        }
+    }
+    centers
+}
+
+/// Order the colors of a palette by the number of pixels nearest to them, most first, and drop
+/// the colors that no pixel is nearest to.
+fn by_popularity(counts: &[(Rgb<u8>, u64)], colors: Vec<Rgb<u8>>) -> Palette {
+    let palette = Palette::new(colors);
+    let mut popularity = vec![0u64; palette.len()];
+    for &(color, count) in counts {
+        popularity[palette.index_of(&color)] += count;
+    }
+    let mut order: Vec<(u64, Rgb<u8>)> =
+        popularity.into_iter().zip(palette.into_colors()).collect();
+    order.retain(|&(count, _)| count > 0);
+    order.sort_by_key(|&(count, color)| (std::cmp::Reverse(count), color.0));
+    Palette::new(order.into_iter().map(|(_, color)| color).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Luma;
+
+    fn gradient() -> RgbImage {
+        RgbImage::from_fn(32, 32, |x, y| {
+            Rgb([(x * 8) as u8, (y * 8) as u8, ((x + y) * 4) as u8])
+        })
+    }
+
+    fn algorithms() -> [QuantizeAlgorithm; 3] {
+        [
+            QuantizeAlgorithm::MedianCut,
+            QuantizeAlgorithm::Octree,
+            QuantizeAlgorithm::KMeans { iterations: 10 },
+        ]
+    }
+
+    #[test]
+    fn reduces_colors() {
+        let image = gradient();
+        for &algorithm in &algorithms() {
+            let (palette, indices) = quantize(&image, 16, algorithm).unwrap();
+            assert!(
+                !palette.is_empty() && palette.len() <= 16,
+                "{:?}",
+                algorithm
+            );
+            assert!(indices
+                .iter()
+                .all(|&index| usize::from(index) < palette.len()));
+
+            let mapped = palette.apply(&indices);
+            let error: i64 = mapped
+                .iter()
+                .zip(image.iter())
+                .map(|(&a, &b)| (i64::from(a) - i64::from(b)).abs())
+                .sum();
+            let mean = error as f64 / (32.0 * 32.0 * 3.0);
+            assert!(mean < 24.0, "{:?} {}", algorithm, mean);
+        }
+    }
+
+    #[test]
+    fn k_means_improves_median_cut() {
+        let image = gradient();
+        let squared_error = |algorithm| {
+            let (palette, indices) = quantize(&image, 8, algorithm).unwrap();
+            let mapped = palette.apply(&indices);
+            mapped
+                .iter()
+                .zip(image.iter())
+                .map(|(&a, &b)| (i64::from(a) - i64::from(b)).pow(2))
+                .sum::<i64>()
+        };
+        assert!(
+            squared_error(QuantizeAlgorithm::KMeans { iterations: 10 })
+                <= squared_error(QuantizeAlgorithm::MedianCut)
+        );
+    }
+
+    #[test]
+    fn orders_by_popularity() {
+        let image = RgbImage::from_fn(10, 1, |x, _| match x {
+            0 => Rgb([1, 2, 3]),
+            1..=3 => Rgb([250, 0, 0]),
+            _ => Rgb([0, 250, 0]),
+        });
+        for &algorithm in &algorithms() {
+            // The green pixels are the most common whichever colors they are reduced to.
+            let (_, indices) = quantize(&image, 2, algorithm).unwrap();
+            assert_eq!(indices.get_pixel(9, 0), &Luma([0]), "{:?}", algorithm);
+
+            let (palette, indices) = quantize(&image, 4, algorithm).unwrap();
+            assert_eq!(
+                palette.colors(),
+                &[Rgb([0, 250, 0]), Rgb([250, 0, 0]), Rgb([1, 2, 3])]
+            );
+            assert_eq!(palette.apply(&indices), image);
+        }
+    }
+
+    #[test]
+    fn rejects_palette_sizes() {
+        let image = gradient();
+        assert!(quantize(&image, 0, QuantizeAlgorithm::MedianCut).is_err());
+        assert!(quantize(&image, 257, QuantizeAlgorithm::Octree).is_err());
+        let (palette, _) = quantize(&image, 256, QuantizeAlgorithm::Octree).unwrap();
+        assert!(palette.len() <= 256);
+    }
+}
@@ -0,0 +1,291 @@
+//! Alignment of images of the same scene.
+use rustfft::num_complex::Complex;
+use rustfft::{FftDirection, FftPlanner};
+use std::f64::consts::PI;
+
+use crate::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use crate::image::GenericImageView;
+use crate::traits::{Pixel, Primitive};
+
+/// Find the translation that moves the content of image `a` onto that of image `b`.
+///
+/// Returns the shift `(dx, dy)` in pixels, to sub-pixel precision, such that the pixel of `a` at
+/// `(x, y)` shows the same point of the scene as the pixel of `b` at `(x + dx, y + dy)`. Shifting
+/// `b` back by that amount aligns it with `a`, for example before the frames of a stack are
+/// combined with [`focus_stack`] or averaged.
+///
+/// The shift is found by phase correlation of the luminance of both images, which is robust to
+/// differences of brightness and contrast between them. The images are tapered towards their
+/// edges first, so shifts of up to about a fifth of the image size are found reliably. Rotation
+/// and scaling are not detected.
+///
+/// Returns an `ImageError::Parameter` if the images differ in size.
+///
+/// [`focus_stack`]: fn.focus_stack.html
+pub fn register_translation<I, P, S>(a: &I, b: &I) -> ImageResult<(f32, f32)>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    if a.dimensions() != b.dimensions() {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::DimensionMismatch,
+        )));
+    }
+    let (width, height) = a.dimensions();
+    if width == 0 || height == 0 {
+        return Ok((0.0, 0.0));
+    }
+    let luminance = |image: &I| -> Vec<f64> {
+        image
+            .pixels()
+            .map(|(_, _, pixel)| pixel.to_luma()[0].to_f64().unwrap())
+            .collect()
+    };
+    let (dx, dy) = phase_correlation(
+        &luminance(a),
+        &luminance(b),
+        width as usize,
+        height as usize,
+    );
+    Ok((dx as f32, dy as f32))
+}
+
+/// The shift between two planes of values.
+///
+/// The tapering window biases the peak of the correlation towards zero, so it is computed a
+/// second time with the window of `b` moved by the first estimate.
+fn phase_correlation(a: &[f64], b: &[f64], width: usize, height: usize) -> (f64, f64) {
+    let estimate = correlate(a, b, width, height, (0.0, 0.0));
+    correlate(a, b, width, height, estimate)
+}
+
+/// The position of the peak of the phase correlation of two planes of values, with the window
+/// of `b` moved by `shift`.
+fn correlate(a: &[f64], b: &[f64], width: usize, height: usize, shift: (f64, f64)) -> (f64, f64) {
+    let mut planner = FftPlanner::new();
+    let mut spectrum = |values: &[f64], (dx, dy): (f64, f64)| {
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let mut data: Vec<Complex<f64>> = values
+            .iter()
+            .enumerate()
+            .map(|(i, &value)| {
+                let x = (i % width) as f64 - dx;
+                let y = (i / width) as f64 - dy;
+                Complex::new((value - mean) * hann(x, width) * hann(y, height), 0.0)
+            })
+            .collect();
+        fft2(
+            &mut planner,
+            &mut data,
+            width,
+            height,
+            FftDirection::Forward,
+        );
+        data
+    };
+    let a = spectrum(a, (0.0, 0.0));
+    let mut cross = spectrum(b, shift);
+    for (cross, a) in cross.iter_mut().zip(&a) {
+        *cross = a.conj() * *cross;
+    }
+    // Whiten the cross power spectrum, except for frequencies that hold almost no signal and
+    // would only add noise.
+    let floor = 1e-3 * cross.iter().map(|c| c.norm()).fold(0.0, f64::max);
+    for cross in cross.iter_mut() {
+        let norm = cross.norm() + floor;
+        if norm > 0.0 {
+            *cross /= norm;
+        }
+    }
+    let normalized = cross.clone();
+    fft2(
+        &mut planner,
+        &mut cross,
+        width,
+        height,
+        FftDirection::Inverse,
+    );
+
+    let peak = (0..cross.len())
+        .max_by(|&i, &j| {
+            cross[i]
+                .re
+                .partial_cmp(&cross[j].re)
+                .unwrap_or(std::cmp::Ordering::Equal)
+        })
+        .unwrap();
+    let mut x = wrap(peak % width, width);
+    let mut y = wrap(peak / width, height);
+    for &step in &[0.1, 0.01] {
+        let refined = refine(&normalized, width, height, (x, y), step);
+        x = refined.0;
+        y = refined.1;
+    }
+    (x, y)
+}
+
+/// Search the correlation around a position on a grid with the given step, evaluating it
+/// directly from the spectrum.
+fn refine(
+    spectrum: &[Complex<f64>],
+    width: usize,
+    height: usize,
+    (x, y): (f64, f64),
+    step: f64,
+) -> (f64, f64) {
+    let offsets: Vec<f64> = (-10..=10).map(|i| f64::from(i) * step).collect();
+    let phases = |position: f64, len: usize| -> Vec<Complex<f64>> {
+        (0..len)
+            .map(|k| {
+                let frequency = if k < (len + 1) / 2 {
+                    k as f64
+                } else {
+                    k as f64 - len as f64
+                };
+                Complex::from_polar(1.0, 2.0 * PI * frequency * position / len as f64)
+            })
+            .collect()
+    };
+    let column_phases: Vec<_> = offsets.iter().map(|dy| phases(y + dy, height)).collect();
+
+    let mut best = (f64::NEG_INFINITY, x, y);
+    for dx in &offsets {
+        let row_phases = phases(x + dx, width);
+        let rows: Vec<Complex<f64>> = spectrum
+            .chunks(width)
+            .map(|row| row.iter().zip(&row_phases).map(|(s, p)| s * p).sum())
+            .collect();
+        for (dy, column_phases) in offsets.iter().zip(&column_phases) {
+            let value: f64 = rows
+                .iter()
+                .zip(column_phases)
+                .map(|(s, p)| (s * p).re)
+                .sum();
+            if value > best.0 {
+                best = (value, x + dx, y + dy);
+            }
+        }
+    }
+    (best.1, best.2)
+}
+
+/// The two dimensional Fourier transform of a plane of values, unnormalized.
+fn fft2(
+    planner: &mut FftPlanner<f64>,
+    data: &mut [Complex<f64>],
+    width: usize,
+    height: usize,
+    direction: FftDirection,
+) {
+    planner.plan_fft(width, direction).process(data);
+    let mut columns: Vec<Complex<f64>> = (0..width * height)
+        .map(|i| data[(i % height) * width + i / height])
+        .collect();
+    planner.plan_fft(height, direction).process(&mut columns);
+    for (i, value) in columns.into_iter().enumerate() {
+        data[(i % height) * width + i / height] = value;
+    }
+}
+
+/// The weight of a Hann window over `len` pixels at a position, zero outside of it.
+fn hann(position: f64, len: usize) -> f64 {
+    if position < -0.5 || position > len as f64 - 0.5 {
+        return 0.0;
+    }
+    0.5 - 0.5 * (2.0 * PI * (position + 0.5) / len as f64).cos()
+}
+
+/// Map a position on a circle of `len` pixels to the shift closest to zero.
+fn wrap(position: usize, len: usize) -> f64 {
+    if position > len / 2 {
+        position as f64 - len as f64
+    } else {
+        position as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayImage, ImageBuffer, Luma, Rgb};
+
+    /// A textured scene of random waves, which can be sampled at fractional positions.
+    fn scene(x: f64, y: f64) -> f64 {
+        let mut seed = 12345u32;
+        let mut random = || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 8) as f64 / (1 << 24) as f64
+        };
+        let mut value = 128.0;
+        for _ in 0..40 {
+            let (fx, fy) = (random() * 2.4 - 1.2, random() * 2.4 - 1.2);
+            value += 8.0 * (fx * x + fy * y + random() * 6.3).sin();
+        }
+        value
+    }
+
+    fn render(width: u32, height: u32, dx: f64, dy: f64) -> GrayImage {
+        ImageBuffer::from_fn(width, height, |x, y| {
+            Luma([scene(x as f64 - dx, y as f64 - dy)
+                .round()
+                .max(0.0)
+                .min(255.0) as u8])
+        })
+    }
+
+    #[test]
+    fn whole_pixels() {
+        let a = render(80, 64, 0.0, 0.0);
+        for &(dx, dy) in &[
+            (0.0, 0.0),
+            (5.0, -3.0),
+            (-9.0, 7.0),
+            (12.0, 0.0),
+            (-17.0, 10.0),
+        ] {
+            let b = render(80, 64, dx, dy);
+            let (x, y) = register_translation(&a, &b).unwrap();
+            assert_eq!((x.round(), y.round()), (dx as f32, dy as f32));
+        }
+    }
+
+    #[test]
+    fn sub_pixels() {
+        let a = render(80, 64, 0.0, 0.0);
+        let b = render(80, 64, 2.5, -1.25);
+        let (x, y) = register_translation(&a, &b).unwrap();
+        assert!(
+            (x - 2.5).abs() < 0.05 && (y + 1.25).abs() < 0.05,
+            "{} {}",
+            x,
+            y
+        );
+    }
+
+    #[test]
+    fn color_and_brightness() {
+        let a = render(64, 64, 0.0, 0.0);
+        let b = render(64, 64, -4.0, 6.0);
+        let b = ImageBuffer::from_fn(64, 64, |x, y| {
+            let value = b.get_pixel(x, y)[0] / 2 + 40;
+            Rgb([value, value, value])
+        });
+        let a = ImageBuffer::from_fn(64, 64, |x, y| {
+            let value = a.get_pixel(x, y)[0];
+            Rgb([value, value, value])
+        });
+        let (x, y) = register_translation(&a, &b).unwrap();
+        assert_eq!((x.round(), y.round()), (-4.0, 6.0));
+    }
+
+    #[test]
+    fn mismatched_sizes() {
+        assert!(register_translation(&GrayImage::new(4, 4), &GrayImage::new(4, 5)).is_err());
+        assert_eq!(
+            register_translation(&GrayImage::new(0, 3), &GrayImage::new(0, 3)).unwrap(),
+            (0.0, 0.0)
+        );
+    }
+}
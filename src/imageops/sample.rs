@@ -4,13 +4,15 @@
 // for some of the theory behind image scaling and convolution
 
 use std::f32;
+use std::fmt;
 
 use num_traits::{NumCast, ToPrimitive, Zero};
 
 use crate::image::GenericImageView;
+use crate::math::{FloatRect, Rect};
 use crate::traits::{Enlargeable, Pixel, Primitive};
-use crate::utils::clamp;
-use crate::{ImageBuffer, Rgba32FImage};
+use crate::utils::{clamp, simd};
+use crate::ImageBuffer;
 
 /// Available Sampling Filters.
 ///
@@ -97,7 +99,13 @@ pub enum FilterType {
 }
 
 /// A Representation of a separable filter.
-pub(crate) struct Filter<'a> {
+///
+/// Besides the filters of [`FilterType`], custom kernels can be passed to
+/// [`resize_with_filter`] to control resampling exactly.
+///
+/// [`FilterType`]: enum.FilterType.html
+/// [`resize_with_filter`]: fn.resize_with_filter.html
+pub struct Filter<'a> {
     /// The filter's filter function.
     pub(crate) kernel: Box<dyn Fn(f32) -> f32 + 'a>,
 
@@ -105,7 +113,77 @@ pub(crate) struct Filter<'a> {
     pub(crate) support: f32,
 }
 
-struct FloatNearest(f32);
+impl<'a> Filter<'a> {
+    /// Create a filter from a kernel function and its support.
+    ///
+    /// The kernel is evaluated at the distance of input pixel centres from the position that an
+    /// output pixel samples, measured in input pixels when enlarging and in output pixels when
+    /// shrinking. It only needs to be defined for distances up to `support`, beyond which it is
+    /// treated as zero. The weights of each output pixel are normalized to sum to one.
+    ///
+    /// ```
+    /// use image::imageops::{resize_with_filter, Filter};
+    /// use image::{GrayImage, Luma};
+    ///
+    /// // A tent that reaches two pixels, blurring more than `FilterType::Triangle`.
+    /// let tent = Filter::new(|x| (1.0 - x.abs() / 2.0).max(0.0), 2.0);
+    /// let image = GrayImage::from_fn(4, 1, |x, _| Luma([if x < 2 { 0 } else { 200 }]));
+    /// let resized = resize_with_filter(&image, 8, 1, &tent);
+    /// assert!(resized.get_pixel(3, 0)[0] > 0);
+    /// ```
+    pub fn new<F>(kernel: F, support: f32) -> Filter<'a>
+    where
+        F: Fn(f32) -> f32 + 'a,
+    {
+        Filter {
+            kernel: Box::new(kernel),
+            support,
+        }
+    }
+
+    /// A Lanczos filter, the sinc function windowed by a sinc stretched to `window` lobes.
+    /// `FilterType::Lanczos3` is the filter with a window of 3.
+    pub fn lanczos(window: f32) -> Filter<'a> {
+        Filter::new(move |x| lanczos(x, window), window)
+    }
+
+    /// The distance beyond which the kernel is zero.
+    pub fn support(&self) -> f32 {
+        self.support
+    }
+}
+
+impl From<FilterType> for Filter<'_> {
+    fn from(filter: FilterType) -> Self {
+        match filter {
+            FilterType::Nearest => Filter::new(box_kernel, 0.0),
+            FilterType::Triangle => Filter::new(triangle_kernel, 1.0),
+            FilterType::CatmullRom => Filter::new(catmullrom_kernel, 2.0),
+            FilterType::Gaussian => Filter::new(gaussian_kernel, 3.0),
+            FilterType::Lanczos3 => Filter::new(lanczos3_kernel, 3.0),
+        }
+    }
+}
+
+impl fmt::Debug for Filter<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Filter")
+            .field("support", &self.support)
+            .finish()
+    }
+}
+
+pub(crate) struct FloatNearest(pub(crate) f32);
+
+/// The range that filtered samples are clamped to, the bounds of the subpixel type.
+///
+/// Integer samples saturate, floating point samples keep values outside of 0 to 1, such as the
+/// highlights of HDR images.
+pub(crate) fn sample_range<S: Primitive>() -> (f32, f32) {
+    let min = NumCast::from(S::min_value()).unwrap();
+    let max = NumCast::from(S::max_value()).unwrap();
+    (min, max)
+}
 
 // to_i64, to_u64, and to_f64 implicitly affect all other lower conversions.
 // Note that to_f64 by default calls to_i64 and thus needs to be overridden.
@@ -216,169 +294,334 @@ pub(crate) fn box_kernel(_x: f32) -> f32 {
     1.0
 }
 
-// Sample the rows of the supplied image using the provided filter.
-// The height of the image remains unchanged.
-// ```new_width``` is the desired width of the new image
-// ```filter``` is the filter to use for sampling.
-// ```image``` is not necessarily Rgba and the order of channels is passed through.
-fn horizontal_sample<P, S>(
-    image: &Rgba32FImage,
-    new_width: u32,
-    filter: &mut Filter,
-) -> ImageBuffer<P, Vec<S>>
+/// How positions beyond the edges of an image are sampled.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EdgeMode {
+    /// Take the nearest edge pixel.
+    Clamp,
+    /// Repeat the image, as for tiling textures.
+    Wrap,
+    /// Repeat the image mirrored at each edge, so that it continues smoothly.
+    Mirror,
+    /// Treat all channels as zero, which is black or fully transparent.
+    Zero,
+}
+
+impl EdgeMode {
+    /// Maps a coordinate onto the pixels of a row or column of `len` pixels, or `None` if it is
+    /// outside and sampled as zero.
+    pub(crate) fn map(self, position: i64, len: u32) -> Option<u32> {
+        let len = len as i64;
+        let position = match self {
+            EdgeMode::Clamp => position.max(0).min(len - 1),
+            EdgeMode::Wrap => position.rem_euclid(len),
+            EdgeMode::Mirror => {
+                let position = position.rem_euclid(2 * len);
+                if position < len {
+                    position
+                } else {
+                    2 * len - 1 - position
+                }
+            }
+            EdgeMode::Zero if position < 0 || position >= len => return None,
+            EdgeMode::Zero => position,
+        };
+        Some(position as u32)
+    }
+}
+
+/// Interpolates the pixel at a position between pixel centers with a separable kernel, which is
+/// zero beyond `support` pixels.
+///
+/// Pixel centers lie at whole coordinates. The result is clamped to the range of the subpixel
+/// type, as kernels with negative lobes overshoot at sharp edges.
+pub(crate) fn interpolate<I, K>(
+    image: &I,
+    x: f32,
+    y: f32,
+    edges: EdgeMode,
+    support: i64,
+    kernel: K,
+) -> I::Pixel
 where
-    P: Pixel<Subpixel = S> + 'static,
-    S: Primitive + 'static,
+    I: GenericImageView + ?Sized,
+    K: Fn(f32) -> f32,
 {
     let (width, height) = image.dimensions();
-    let mut out = ImageBuffer::new(new_width, height);
-    let mut ws = Vec::new();
+    assert!(width > 0 && height > 0, "cannot sample an empty image");
+    let (x0, y0) = (x.floor(), y.floor());
+    let taps = |position: f32, start: f32, len: u32| {
+        (1 - support..=support)
+            .filter_map(|i| {
+                let weight = kernel(position - (start + i as f32));
+                edges.map(start as i64 + i, len).map(|p| (p, weight))
+            })
+            .collect::<Vec<_>>()
+    };
+    let columns = taps(x, x0, width);
+    let rows = taps(y, y0, height);
+
+    let mut sums = [0f32; 4];
+    let mut pixel = image.get_pixel(0, 0);
+    for &(row, row_weight) in &rows {
+        for &(column, column_weight) in &columns {
+            let weight = row_weight * column_weight;
+            let sample = image.get_pixel(column, row);
+            for (sum, channel) in sums.iter_mut().zip(sample.channels()) {
+                *sum += channel.to_f32().unwrap() * weight;
+            }
+        }
+    }
 
-    let max: f32 = NumCast::from(S::DEFAULT_MAX_VALUE).unwrap();
-    let min: f32 = NumCast::from(S::DEFAULT_MIN_VALUE).unwrap();
-    let ratio = width as f32 / new_width as f32;
+    let (min, max) = sample_range::<<I::Pixel as Pixel>::Subpixel>();
+    for (channel, &sum) in pixel.channels_mut().iter_mut().zip(&sums) {
+        *channel = NumCast::from(FloatNearest(clamp(sum, min, max))).unwrap();
+    }
+    pixel
+}
+
+/// The contributions of the input pixels to one output pixel of a row or column, starting at the
+/// input pixel `left`.
+struct Weights {
+    left: usize,
+    weights: Vec<f32>,
+}
+
+// Compute the normalized filter weights of each output pixel of a line.
+// ```len``` is the length of the input line, ```new_len``` the length of the output line.
+// The output line covers the input from ```start``` over ```extent``` pixels, which may be
+// fractional and reach beyond the input, whose edge pixels are then repeated.
+// The weights are computed up front, as the filter function is not shared between threads.
+fn line_weights(
+    filter: &Filter,
+    (start, extent): (f32, f32),
+    len: u32,
+    new_len: u32,
+) -> Vec<Weights> {
+    if len == 0 {
+        let empty = || Weights {
+            left: 0,
+            weights: Vec::new(),
+        };
+        return (0..new_len).map(|_| empty()).collect();
+    }
+
+    let ratio = extent / new_len as f32;
     let sratio = if ratio < 1.0 { 1.0 } else { ratio };
     let src_support = filter.support * sratio;
 
-    for outx in 0..new_width {
-        // Find the point in the input image corresponding to the centre
-        // of the current pixel in the output image.
-        let inputx = (outx as f32 + 0.5) * ratio;
-
-        // Left and right are slice bounds for the input pixels relevant
-        // to the output pixel we are calculating.  Pixel x is relevant
-        // if and only if (x >= left) && (x < right).
-
-        // Invariant: 0 <= left < right <= width
-
-        let left = (inputx - src_support).floor() as i64;
-        let left = clamp(left, 0, <i64 as From<_>>::from(width) - 1) as u32;
-
-        let right = (inputx + src_support).ceil() as i64;
-        let right = clamp(
-            right,
-            <i64 as From<_>>::from(left) + 1,
-            <i64 as From<_>>::from(width),
-        ) as u32;
-
-        // Go back to left boundary of pixel, to properly compare with i
-        // below, as the kernel treats the centre of a pixel as 0.
-        let inputx = inputx - 0.5;
-
-        ws.clear();
-        let mut sum = 0.0;
-        for i in left..right {
-            let w = (filter.kernel)((i as f32 - inputx) / sratio);
-            ws.push(w);
-            sum += w;
-        }
-        ws.iter_mut().for_each(|w| *w /= sum);
+    (0..new_len)
+        .map(|out| {
+            // Find the point in the input line corresponding to the centre
+            // of the current pixel in the output line.
+            let input = start + (out as f32 + 0.5) * ratio;
+
+            // Left and right are slice bounds for the input pixels relevant
+            // to the output pixel we are calculating.  Pixel x is relevant
+            // if and only if (x >= left) && (x < right).
+
+            // Invariant: 0 <= left < right <= len
+
+            let left = (input - src_support).floor() as i64;
+            let left = clamp(left, 0, <i64 as From<_>>::from(len) - 1) as u32;
+            let nearest = clamp(input.floor() as i64, 0, <i64 as From<_>>::from(len) - 1);
+
+            let right = (input + src_support).ceil() as i64;
+            let right = clamp(
+                right,
+                <i64 as From<_>>::from(left) + 1,
+                <i64 as From<_>>::from(len),
+            ) as u32;
+
+            // Go back to left boundary of pixel, to properly compare with i
+            // below, as the kernel treats the centre of a pixel as 0.
+            let input = input - 0.5;
+
+            let mut weights: Vec<f32> = (left..right)
+                .map(|i| (filter.kernel)((i as f32 - input) / sratio))
+                .collect();
+            let sum: f32 = weights.iter().sum();
+            if sum == 0.0 {
+                // The kernel does not reach any input pixel, which happens beyond the edges.
+                return Weights {
+                    left: nearest as usize,
+                    weights: vec![1.0],
+                };
+            }
+            weights.iter_mut().for_each(|w| *w /= sum);
 
-        for y in 0..height {
-            let mut t = (0.0, 0.0, 0.0, 0.0);
+            Weights {
+                left: left as usize,
+                weights,
+            }
+        })
+        .collect()
+}
 
-            for (i, w) in ws.iter().enumerate() {
-                let p = image.get_pixel(left + i as u32, y);
+// Fill the rows of ```out```, each ```row_len``` long, with ```f``` of their index.
+// With the `rayon` feature, the rows are filled on the threads of the global thread pool.
+fn for_each_row<T, F>(out: &mut [T], row_len: usize, f: F)
+where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Sync,
+{
+    if row_len == 0 {
+        return;
+    }
 
-                #[allow(deprecated)]
-                let vec = p.channels4();
+    #[cfg(feature = "rayon")]
+    {
+        use rayon::prelude::*;
 
-                t.0 += vec.0 * w;
-                t.1 += vec.1 * w;
-                t.2 += vec.2 * w;
-                t.3 += vec.3 * w;
-            }
+        out.par_chunks_mut(row_len)
+            .enumerate()
+            .for_each(|(y, row)| f(y, row));
+    }
 
-            #[allow(deprecated)]
-            let t = Pixel::from_channels(
-                NumCast::from(FloatNearest(clamp(t.0, min, max))).unwrap(),
-                NumCast::from(FloatNearest(clamp(t.1, min, max))).unwrap(),
-                NumCast::from(FloatNearest(clamp(t.2, min, max))).unwrap(),
-                NumCast::from(FloatNearest(clamp(t.3, min, max))).unwrap(),
-            );
-
-            out.put_pixel(outx, y, t);
+    #[cfg(not(feature = "rayon"))]
+    {
+        for (y, row) in out.chunks_mut(row_len).enumerate() {
+            f(y, row);
         }
     }
+}
+
+// Sample the rows of the supplied image using the provided filter.
+// The height of the image remains unchanged.
+// ```image``` holds the `C` channels of each pixel of an image of the given dimensions.
+// ```columns``` are the weights of each column of the new image.
+fn horizontal_sample<const C: usize>(
+    image: &[[f32; C]],
+    (width, height): (u32, u32),
+    columns: &[Weights],
+) -> Vec<[f32; C]> {
+    let new_width = columns.len() as u32;
+    let mut out = vec![[0.0; C]; new_width as usize * height as usize];
+
+    for_each_row(&mut out, new_width as usize, |y, out| {
+        let row = &image[y * width as usize..][..width as usize];
+        for (t, column) in out.iter_mut().zip(columns) {
+            for (p, w) in row[column.left..].iter().zip(&column.weights) {
+                for (t, &c) in t.iter_mut().zip(p) {
+                    *t += c * w;
+                }
+            }
+        }
+    });
 
     out
 }
 
 // Sample the columns of the supplied image using the provided filter.
 // The width of the image remains unchanged.
-// ```new_height``` is the desired height of the new image
-// ```filter``` is the filter to use for sampling.
-// The return value is not necessarily Rgba, the underlying order of channels in ```image``` is
-// preserved.
-fn vertical_sample<I, P, S>(image: &I, new_height: u32, filter: &mut Filter) -> Rgba32FImage
+// ```image``` holds the `C` channels of each pixel of an image of the given width.
+// ```rows``` are the weights of each row of the new image.
+fn vertical_sample<const C: usize>(
+    image: &[[f32; C]],
+    width: u32,
+    rows: &[Weights],
+) -> Vec<[f32; C]> {
+    let new_height = rows.len() as u32;
+    let mut out = vec![[0.0; C]; width as usize * new_height as usize];
+
+    for_each_row(&mut out, width as usize, |outy, out| {
+        let weights = &rows[outy];
+        for (i, &w) in weights.weights.iter().enumerate() {
+            let row = &image[(weights.left + i) * width as usize..][..width as usize];
+            simd::multiply_add(simd::flatten_mut(out), simd::flatten(row), w);
+        }
+    });
+
+    out
+}
+
+/// Resample the supplied image in both directions, vertically first.
+///
+/// With the `rayon` feature, the rows of both passes are sampled in parallel.
+fn sample<I, P, S>(
+    image: &I,
+    new_width: u32,
+    new_height: u32,
+    filter: &Filter,
+) -> ImageBuffer<P, Vec<S>>
 where
     I: GenericImageView<Pixel = P>,
     P: Pixel<Subpixel = S> + 'static,
     S: Primitive + 'static,
 {
     let (width, height) = image.dimensions();
-    let mut out = ImageBuffer::new(width, new_height);
-    let mut ws = Vec::new();
-
-    let ratio = height as f32 / new_height as f32;
-    let sratio = if ratio < 1.0 { 1.0 } else { ratio };
-    let src_support = filter.support * sratio;
-
-    for outy in 0..new_height {
-        // For an explanation of this algorithm, see the comments
-        // in horizontal_sample.
-        let inputy = (outy as f32 + 0.5) * ratio;
-
-        let left = (inputy - src_support).floor() as i64;
-        let left = clamp(left, 0, <i64 as From<_>>::from(height) - 1) as u32;
-
-        let right = (inputy + src_support).ceil() as i64;
-        let right = clamp(
-            right,
-            <i64 as From<_>>::from(left) + 1,
-            <i64 as From<_>>::from(height),
-        ) as u32;
-
-        let inputy = inputy - 0.5;
-
-        ws.clear();
-        let mut sum = 0.0;
-        for i in left..right {
-            let w = (filter.kernel)((i as f32 - inputy) / sratio);
-            ws.push(w);
-            sum += w;
-        }
-        ws.iter_mut().for_each(|w| *w /= sum);
+    let region = FloatRect::from(Rect {
+        x: 0,
+        y: 0,
+        width,
+        height,
+    });
+    sample_region(image, region, new_width, new_height, filter)
+}
 
-        for x in 0..width {
-            let mut t = (0.0, 0.0, 0.0, 0.0);
-
-            for (i, w) in ws.iter().enumerate() {
-                let p = image.get_pixel(x, left + i as u32);
-
-                #[allow(deprecated)]
-                let (k1, k2, k3, k4) = p.channels4();
-                let vec: (f32, f32, f32, f32) = (
-                    NumCast::from(k1).unwrap(),
-                    NumCast::from(k2).unwrap(),
-                    NumCast::from(k3).unwrap(),
-                    NumCast::from(k4).unwrap(),
-                );
+/// Resample a region of the supplied image in both directions, vertically first.
+fn sample_region<I, P, S>(
+    image: &I,
+    region: FloatRect,
+    new_width: u32,
+    new_height: u32,
+    filter: &Filter,
+) -> ImageBuffer<P, Vec<S>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let (width, height) = image.dimensions();
+    let columns = line_weights(filter, (region.x, region.width), width, new_width);
+    let rows = line_weights(filter, (region.y, region.height), height, new_height);
+
+    // Monomorphize the inner loops for the channel count like `filter3x3`, so that gray images
+    // are sampled in a single channel rather than four.
+    match P::CHANNEL_COUNT {
+        1 => sample_channels::<I, P, S, 1>(image, &columns, &rows),
+        2 => sample_channels::<I, P, S, 2>(image, &columns, &rows),
+        3 => sample_channels::<I, P, S, 3>(image, &columns, &rows),
+        4 => sample_channels::<I, P, S, 4>(image, &columns, &rows),
+        n => panic!("sampling does not support pixels with {} channels", n),
+    }
+}
 
-                t.0 += vec.0 * w;
-                t.1 += vec.1 * w;
-                t.2 += vec.2 * w;
-                t.3 += vec.3 * w;
+fn sample_channels<I, P, S, const C: usize>(
+    image: &I,
+    columns: &[Weights],
+    rows: &[Weights],
+) -> ImageBuffer<P, Vec<S>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let (width, _) = image.dimensions();
+    let (new_width, new_height) = (columns.len() as u32, rows.len() as u32);
+
+    // Copy the samples into contiguous rows, which the passes can read from any thread.
+    let source: Vec<[f32; C]> = image
+        .pixels()
+        .map(|(_, _, pixel)| {
+            let mut channels = [0.0; C];
+            for (t, &c) in channels.iter_mut().zip(pixel.channels()) {
+                *t = NumCast::from(c).unwrap();
             }
-
-            #[allow(deprecated)]
-            // This is not necessarily Rgba.
-            let t = Pixel::from_channels(t.0, t.1, t.2, t.3);
-
-            out.put_pixel(x, outy, t);
+            channels
+        })
+        .collect();
+
+    let tmp = vertical_sample(&source, width, rows);
+    let sums = horizontal_sample(&tmp, (width, new_height), columns);
+
+    let (min, max) = sample_range::<S>();
+    let mut out: ImageBuffer<P, Vec<S>> = ImageBuffer::new(new_width, new_height);
+    for (pixel, t) in out.pixels_mut().zip(&sums) {
+        for (c, &t) in pixel.channels_mut().iter_mut().zip(t) {
+            *c = NumCast::from(FloatNearest(clamp(t, min, max))).unwrap();
         }
     }
-
     out
 }
 
@@ -500,6 +743,78 @@ where
     out
 }
 
+/// Create the thumbnails of an image for screens of several device pixel ratios, in one pass.
+///
+/// Web pages show an image at a size in CSS pixels, `css_size`, and screens of a device pixel
+/// ratio of 2 or 3 need an image of twice or three times that size to show it sharply. For each
+/// of `device_pixel_ratios` a thumbnail of the CSS size scaled by the ratio is returned, in the
+/// same order, with at least one pixel in each dimension.
+///
+/// Rather than reading the whole image for every ratio, the largest thumbnail is made first and
+/// the image is halved into a pyramid along the way, whose levels the smaller thumbnails are
+/// made from. Each level averages blocks of 2 by 2 pixels, so the image is only halved while its
+/// dimensions are even. Each thumbnail is made like [`thumbnail`] from the smallest level that
+/// is at least twice its size, so they differ from thumbnails of the whole image only by
+/// rounding.
+///
+/// ```
+/// use image::imageops::thumbnail_dpr;
+/// use image::RgbImage;
+///
+/// let image = RgbImage::new(1200, 900);
+/// let variants = thumbnail_dpr(&image, (200, 150), &[1.0, 2.0, 3.0]);
+/// let sizes: Vec<_> = variants.iter().map(|variant| variant.dimensions()).collect();
+/// assert_eq!(sizes, [(200, 150), (400, 300), (600, 450)]);
+/// ```
+///
+/// [`thumbnail`]: fn.thumbnail.html
+pub fn thumbnail_dpr<I, P, S>(
+    image: &I,
+    css_size: (u32, u32),
+    device_pixel_ratios: &[f32],
+) -> Vec<ImageBuffer<P, Vec<S>>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + Enlargeable + 'static,
+{
+    let scaled = |len: u32, ratio: f32| ((len as f32 * ratio).round() as u32).max(1);
+    let sizes: Vec<(u32, u32)> = device_pixel_ratios
+        .iter()
+        .map(|&ratio| (scaled(css_size.0, ratio), scaled(css_size.1, ratio)))
+        .collect();
+
+    // The largest first, so that the pyramid only has to shrink.
+    let mut order: Vec<usize> = (0..sizes.len()).collect();
+    order.sort_by_key(|&i| std::cmp::Reverse(sizes[i].0 as u64 * sizes[i].1 as u64));
+
+    let mut level: Option<ImageBuffer<P, Vec<S>>> = None;
+    let mut thumbnails: Vec<Option<ImageBuffer<P, Vec<S>>>> = sizes.iter().map(|_| None).collect();
+    for i in order {
+        let (width, height) = sizes[i];
+        loop {
+            let (level_width, level_height) = match &level {
+                Some(level) => level.dimensions(),
+                None => image.dimensions(),
+            };
+            let even = level_width % 2 == 0 && level_height % 2 == 0;
+            if !even || level_width / 2 < width * 2 || level_height / 2 < height * 2 {
+                break;
+            }
+            let halved = match &level {
+                Some(level) => thumbnail(level, level_width / 2, level_height / 2),
+                None => thumbnail(image, level_width / 2, level_height / 2),
+            };
+            level = Some(halved);
+        }
+        thumbnails[i] = Some(match &level {
+            Some(level) => thumbnail(level, width, height),
+            None => thumbnail(image, width, height),
+        });
+    }
+    thumbnails.into_iter().flatten().collect()
+}
+
 /// Get a pixel for a thumbnail where the input window encloses at least a full pixel.
 fn thumbnail_sample_block<I, P, S>(
     image: &I,
@@ -675,7 +990,7 @@ where
     S: Primitive + 'static,
 {
     // The kernel's input positions relative to the current pixel.
-    let taps: &[(isize, isize)] = &[
+    let taps: &[(i32, i32)] = &[
         (-1, -1),
         (0, -1),
         (1, -1),
@@ -687,9 +1002,87 @@ where
         (1, 1),
     ];
 
+    // Single channel images are convolved on a flat copy of their samples. The inner loops are
+    // monomorphized for the other common channel counts, so the per-channel accumulation works
+    // on arrays of known length. Other pixels accumulate into a vector.
+    match P::CHANNEL_COUNT {
+        1 => filter3x3_single(image, kernel, taps),
+        2 => filter3x3_with(image, kernel, taps, [0.0; 2]),
+        3 => filter3x3_with(image, kernel, taps, [0.0; 3]),
+        4 => filter3x3_with(image, kernel, taps, [0.0; 4]),
+        n => filter3x3_with(image, kernel, taps, vec![0.0; n as usize]),
+    }
+}
+
+/// Apply the kernel to an image of a single channel, such as a mask or a scanned document.
+///
+/// The samples are read into a flat buffer once, so each tap is an index into it instead of a
+/// pixel read through the window of the image.
+fn filter3x3_single<I, P, S>(
+    image: &I,
+    kernel: &[f32],
+    taps: &[(i32, i32)],
+) -> ImageBuffer<P, Vec<S>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
     let (width, height) = image.dimensions();
+    let mut out: ImageBuffer<P, Vec<S>> = ImageBuffer::new(width, height);
+    if width < 3 || height < 3 {
+        return out;
+    }
 
-    let mut out = ImageBuffer::new(width, height);
+    let max: f32 = NumCast::from(S::DEFAULT_MAX_VALUE).unwrap();
+    let sum: f32 = kernel.iter().sum();
+    let sum = if sum == 0.0 { 1.0 } else { sum };
+
+    let stride = width as usize;
+    let samples: Vec<f32> = image
+        .pixels()
+        .map(|(_, _, pixel)| pixel.channels()[0].to_f32().unwrap())
+        .collect();
+    let offsets: Vec<(isize, f32)> = taps
+        .iter()
+        .zip(kernel)
+        .map(|(&(a, b), &k)| (b as isize * stride as isize + a as isize, k))
+        .collect();
+
+    // The pixels along the edges, whose neighborhoods reach beyond the image, are left black.
+    for (y, row) in out.chunks_exact_mut(stride).enumerate() {
+        if y == 0 || y == height as usize - 1 {
+            continue;
+        }
+        for (x, value) in row.iter_mut().enumerate().take(stride - 1).skip(1) {
+            let center = (y * stride + x) as isize;
+            let t: f32 = offsets
+                .iter()
+                .map(|&(offset, k)| samples[(center + offset) as usize] * k)
+                .sum();
+            *value = NumCast::from(clamp(t / sum, 0.0, max)).unwrap();
+        }
+    }
+    out
+}
+
+/// Apply the kernel, accumulating the channels of each pixel in `t`, which holds one element
+/// per channel.
+fn filter3x3_with<I, P, S, A>(
+    image: &I,
+    kernel: &[f32],
+    taps: &[(i32, i32)],
+    mut t: A,
+) -> ImageBuffer<P, Vec<S>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+    A: AsMut<[f32]>,
+{
+    let (width, height) = image.dimensions();
+
+    let mut out: ImageBuffer<P, Vec<S>> = ImageBuffer::new(width, height);
 
     let max = S::DEFAULT_MAX_VALUE;
     let max: f32 = NumCast::from(max).unwrap();
@@ -698,49 +1091,29 @@ where
         x if x == 0.0 => 1.0,
         sum => sum,
     };
-    let sum = (sum, sum, sum, sum);
-
-    for y in 1..height - 1 {
-        for x in 1..width - 1 {
-            let mut t = (0.0, 0.0, 0.0, 0.0);
-
-            // TODO: There is no need to recalculate the kernel for each pixel.
-            // Only a subtract and addition is needed for pixels after the first
-            // in each row.
-            for (&k, &(a, b)) in kernel.iter().zip(taps.iter()) {
-                let k = (k, k, k, k);
-                let x0 = x as isize + a;
-                let y0 = y as isize + b;
-
-                let p = image.get_pixel(x0 as u32, y0 as u32);
-
-                #[allow(deprecated)]
-                let (k1, k2, k3, k4) = p.channels4();
-
-                let vec: (f32, f32, f32, f32) = (
-                    NumCast::from(k1).unwrap(),
-                    NumCast::from(k2).unwrap(),
-                    NumCast::from(k3).unwrap(),
-                    NumCast::from(k4).unwrap(),
-                );
 
-                t.0 += vec.0 * k.0;
-                t.1 += vec.1 * k.1;
-                t.2 += vec.2 * k.2;
-                t.3 += vec.3 * k.3;
+    // The pixels along the edges, whose neighborhoods reach beyond the image, are left black.
+    for (x, y, window) in image.windows(1) {
+        if !window.is_interior() {
+            continue;
+        }
+        let t = t.as_mut();
+        t.iter_mut().for_each(|t| *t = 0.0);
+
+        // TODO: There is no need to recalculate the kernel for each pixel.
+        // Only a subtract and addition is needed for pixels after the first
+        // in each row.
+        for (&k, &(a, b)) in kernel.iter().zip(taps.iter()) {
+            let p = window.get(a, b);
+            for (t, &c) in t.iter_mut().zip(p.channels()) {
+                let c: f32 = NumCast::from(c).unwrap();
+                *t += c * k;
             }
+        }
 
-            let (t1, t2, t3, t4) = (t.0 / sum.0, t.1 / sum.1, t.2 / sum.2, t.3 / sum.3);
-
-            #[allow(deprecated)]
-            let t = Pixel::from_channels(
-                NumCast::from(clamp(t1, 0.0, max)).unwrap(),
-                NumCast::from(clamp(t2, 0.0, max)).unwrap(),
-                NumCast::from(clamp(t3, 0.0, max)).unwrap(),
-                NumCast::from(clamp(t4, 0.0, max)).unwrap(),
-            );
-
-            out.put_pixel(x, y, t);
+        let pixel = out.get_pixel_mut(x, y);
+        for (c, &t) in pixel.channels_mut().iter_mut().zip(t.iter()) {
+            *c = NumCast::from(clamp(t / sum, 0.0, max)).unwrap();
         }
     }
 
@@ -750,6 +1123,11 @@ where
 /// Resize the supplied image to the specified dimensions.
 /// ```nwidth``` and ```nheight``` are the new dimensions.
 /// ```filter``` is the sampling filter to use.
+///
+/// Floating point images are not clamped to the range from 0 to 1, so they keep HDR values.
+///
+/// With the `rayon` feature, the rows of the image are sampled on the threads of the global
+/// thread pool.
 pub fn resize<I: GenericImageView>(
     image: &I,
     nwidth: u32,
@@ -760,36 +1138,66 @@ where
     I::Pixel: 'static,
     <I::Pixel as Pixel>::Subpixel: 'static,
 {
-    let mut method = match filter {
-        FilterType::Nearest => Filter {
-            kernel: Box::new(box_kernel),
-            support: 0.0,
-        },
-        FilterType::Triangle => Filter {
-            kernel: Box::new(triangle_kernel),
-            support: 1.0,
-        },
-        FilterType::CatmullRom => Filter {
-            kernel: Box::new(catmullrom_kernel),
-            support: 2.0,
-        },
-        FilterType::Gaussian => Filter {
-            kernel: Box::new(gaussian_kernel),
-            support: 3.0,
-        },
-        FilterType::Lanczos3 => Filter {
-            kernel: Box::new(lanczos3_kernel),
-            support: 3.0,
-        },
-    };
+    resize_with_filter(image, nwidth, nheight, &Filter::from(filter))
+}
 
-    // Note: tmp is not necessarily actually Rgba
-    let tmp: Rgba32FImage = vertical_sample(image, nheight, &mut method);
-    horizontal_sample(&tmp, nwidth, &mut method)
+/// Resize the supplied image to the specified dimensions with a custom filter.
+/// ```nwidth``` and ```nheight``` are the new dimensions.
+///
+/// Like [`resize`](fn.resize.html), floating point images keep values outside of 0 to 1.
+pub fn resize_with_filter<I: GenericImageView>(
+    image: &I,
+    nwidth: u32,
+    nheight: u32,
+    filter: &Filter<'_>,
+) -> ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>>
+where
+    I::Pixel: 'static,
+    <I::Pixel as Pixel>::Subpixel: 'static,
+{
+    sample(image, nwidth, nheight, filter)
+}
+
+/// Resample a region of the supplied image to the specified dimensions.
+/// ```region``` is the part of the image that the new image shows, ```nwidth``` and ```nheight```
+/// are the new dimensions and ```filter``` is the sampling filter to use.
+///
+/// The region may have fractional coordinates, so that scaling and translating by a fraction of
+/// a pixel happen in a single resampling pass. This avoids the blur of cropping and then
+/// resizing, such as when rendering tiles of a zoomed image. Parts of the region beyond the
+/// edges of the image repeat the edge pixels.
+///
+/// ```
+/// use image::imageops::{resize_region, FilterType};
+/// use image::math::FloatRect;
+/// use image::{GrayImage, Luma};
+///
+/// let image = GrayImage::from_fn(8, 8, |x, _| Luma([x as u8 * 10]));
+/// let region = FloatRect { x: 2.5, y: 0.0, width: 4.0, height: 8.0 };
+/// let tile = resize_region(&image, region, 2, 2, FilterType::Triangle);
+/// // The new pixels are centered on the old pixels 3 and 5.
+/// assert_eq!(tile.get_pixel(0, 0), &Luma([30]));
+/// assert_eq!(tile.get_pixel(1, 0), &Luma([50]));
+/// ```
+pub fn resize_region<I: GenericImageView>(
+    image: &I,
+    region: FloatRect,
+    nwidth: u32,
+    nheight: u32,
+    filter: FilterType,
+) -> ImageBuffer<I::Pixel, Vec<<I::Pixel as Pixel>::Subpixel>>
+where
+    I::Pixel: 'static,
+    <I::Pixel as Pixel>::Subpixel: 'static,
+{
+    sample_region(image, region, nwidth, nheight, &Filter::from(filter))
 }
 
 /// Performs a Gaussian blur on the supplied image.
 /// ```sigma``` is a measure of how much to blur by.
+///
+/// Like [`resize`](fn.resize.html), floating point images keep values outside of 0 to 1 and the
+/// rows are blurred in parallel with the `rayon` feature.
 pub fn blur<I: GenericImageView>(
     image: &I,
     sigma: f32,
@@ -799,18 +1207,13 @@ where
 {
     let sigma = if sigma <= 0.0 { 1.0 } else { sigma };
 
-    let mut method = Filter {
-        kernel: Box::new(|x| gaussian(x, sigma)),
-        support: 2.0 * sigma,
-    };
+    let method = Filter::new(|x| gaussian(x, sigma), 2.0 * sigma);
 
     let (width, height) = image.dimensions();
 
     // Keep width and height the same for horizontal and
     // vertical sampling.
-    // Note: tmp is not necessarily actually Rgba
-    let tmp: Rgba32FImage = vertical_sample(image, height, &mut method);
-    horizontal_sample(&tmp, width, &mut method)
+    sample(image, width, height, &method)
 }
 
 /// Performs an unsharpen mask on the supplied image.
@@ -875,6 +1278,128 @@ mod tests {
         b.bytes = 800 * 800 * 3 + 200 * 200 * 3;
     }
 
+    #[test]
+    fn custom_filters() {
+        use super::{resize_with_filter, Filter};
+
+        let image = crate::GrayImage::from_fn(8, 1, |x, _| crate::Luma([x as u8 * 30]));
+        let lanczos = Filter::lanczos(3.0);
+        assert_eq!(
+            resize_with_filter(&image, 5, 1, &lanczos),
+            resize(&image, 5, 1, FilterType::Lanczos3)
+        );
+
+        // A kernel that averages each pixel with its right neighbor shifts the image by half a
+        // pixel.
+        let shift = Filter::new(|x| if (0.0..=1.0).contains(&x) { 1.0 } else { 0.0 }, 1.0);
+        let shifted = resize_with_filter(&image, 8, 1, &shift);
+        assert_eq!(shifted.get_pixel(2, 0)[0], 75);
+        assert_eq!(shifted.get_pixel(7, 0)[0], 210);
+        assert_eq!(format!("{:?}", shift), "Filter { support: 1.0 }");
+    }
+
+    #[test]
+    fn filter3x3_single_channel_matches_generic_path() {
+        use super::{filter3x3, filter3x3_with};
+
+        let image = crate::GrayImage::from_fn(7, 5, |x, y| crate::Luma([(x * 37 + y * 53) as u8]));
+        let kernel = [-1.0, -1.0, -1.0, -1.0, 9.0, -1.0, -1.0, -1.0, -1.0];
+        let taps: &[(i32, i32)] = &[
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (-1, 0),
+            (0, 0),
+            (1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+        ];
+        assert_eq!(
+            filter3x3(&image, &kernel),
+            filter3x3_with(&image, &kernel, taps, [0.0; 1])
+        );
+
+        let tiny = crate::GrayImage::new(2, 2);
+        assert_eq!(filter3x3(&tiny, &kernel), tiny);
+    }
+
+    #[test]
+    fn thumbnails_for_pixel_ratios() {
+        use super::{thumbnail, thumbnail_dpr};
+
+        let image =
+            crate::GrayImage::from_fn(480, 360, |x, y| crate::Luma([(x / 4 + y / 4) as u8]));
+        let thumbnails = thumbnail_dpr(&image, (40, 30), &[1.0, 3.0, 1.5, 0.01]);
+        let sizes: Vec<_> = thumbnails.iter().map(|t| t.dimensions()).collect();
+        assert_eq!(sizes, [(40, 30), (120, 90), (60, 45), (1, 1)]);
+
+        // The levels of the pyramid barely change the smooth gradient.
+        for thumb in &thumbnails {
+            let (width, height) = thumb.dimensions();
+            let direct = thumbnail(&image, width, height);
+            for (a, b) in thumb.pixels().zip(direct.pixels()) {
+                assert!(
+                    (i32::from(a[0]) - i32::from(b[0])).abs() <= 1,
+                    "{}x{}",
+                    width,
+                    height
+                );
+            }
+        }
+        assert!(thumbnail_dpr(&image, (40, 30), &[]).is_empty());
+    }
+
+    #[test]
+    fn resize_regions() {
+        use super::resize_region;
+        use crate::math::FloatRect;
+
+        let image = crate::GrayImage::from_fn(6, 4, |x, y| crate::Luma([(x + 10 * y) as u8]));
+        let whole = FloatRect {
+            x: 0.0,
+            y: 0.0,
+            width: 6.0,
+            height: 4.0,
+        };
+        assert_eq!(
+            resize_region(&image, whole, 3, 2, FilterType::CatmullRom),
+            resize(&image, 3, 2, FilterType::CatmullRom)
+        );
+
+        // Whole pixels are copied, fractional offsets interpolate between them.
+        let shifted = FloatRect {
+            x: 1.0,
+            y: 2.0,
+            width: 3.0,
+            height: 2.0,
+        };
+        let crop = resize_region(&image, shifted, 3, 2, FilterType::Triangle);
+        assert_eq!(crop.into_raw(), vec![21, 22, 23, 31, 32, 33]);
+        let shifted = FloatRect { x: 1.5, ..shifted };
+        let crop = resize_region(&image, shifted, 3, 2, FilterType::Triangle);
+        assert_eq!(crop.get_pixel(0, 0)[0], 22);
+
+        // Beyond the edges, the edge pixels are repeated.
+        let outside = FloatRect {
+            x: -4.0,
+            y: 10.0,
+            width: 2.0,
+            height: 2.0,
+        };
+        let corner = resize_region(&image, outside, 2, 2, FilterType::Lanczos3);
+        assert!(corner.pixels().all(|pixel| pixel[0] == 30));
+    }
+
+    #[test]
+    fn resize_keeps_rows_apart() {
+        let image = crate::GrayImage::from_fn(3, 40, |_, y| crate::Luma([y as u8 * 5]));
+        let resized = resize(&image, 7, 40, FilterType::CatmullRom);
+        for (_, y, pixel) in resized.enumerate_pixels() {
+            assert_eq!(pixel[0], y as u8 * 5);
+        }
+    }
+
     #[test]
     fn test_issue_186() {
         let img: RgbImage = ImageBuffer::new(100, 100);
@@ -962,4 +1487,66 @@ mod tests {
         let result = resize(&image, 22, 22, FilterType::Lanczos3);
         assert!(result.into_raw().into_iter().any(|c| c != 0));
     }
+
+    #[test]
+    fn gray_matches_rgb() {
+        use crate::{GrayImage, Luma, Rgb};
+
+        let gray = GrayImage::from_fn(17, 11, |x, y| Luma([(x * 13 + y * 29) as u8]));
+        let rgb = RgbImage::from_fn(17, 11, |x, y| Rgb([gray.get_pixel(x, y)[0]; 3]));
+        let red = |image: RgbImage| image.pixels().map(|p| p[0]).collect::<Vec<_>>();
+        let resized = resize(&gray, 7, 23, FilterType::CatmullRom);
+        assert_eq!(
+            resized.into_raw(),
+            red(resize(&rgb, 7, 23, FilterType::CatmullRom))
+        );
+        assert_eq!(
+            super::blur(&gray, 1.5).into_raw(),
+            red(super::blur(&rgb, 1.5))
+        );
+    }
+
+    #[test]
+    fn float_images_keep_hdr_values() {
+        use crate::{Rgb, Rgb32FImage};
+
+        let image = Rgb32FImage::from_fn(8, 8, |x, _| Rgb([x as f32 * 2.0, 0.5, -0.25]));
+        let resized = resize(&image, 4, 4, FilterType::Triangle);
+        assert!(resized.pixels().any(|p| p[0] > 10.0));
+        assert!(resized
+            .pixels()
+            .all(|p| (p[1] - 0.5).abs() < 1e-6 && p[2] < 0.0));
+
+        let blurred = super::blur(&image, 1.0);
+        assert!(blurred.get_pixel(4, 4)[0] > 1.0);
+        assert!((blurred.get_pixel(4, 4)[2] + 0.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn sample_at_float_positions() {
+        use super::EdgeMode;
+        use crate::{GenericImageView, GrayImage, Luma};
+
+        let image = GrayImage::from_raw(3, 2, vec![0, 100, 200, 50, 150, 250]).unwrap();
+        assert_eq!(image.sample_bilinear(1.0, 0.0), Luma([100]));
+        assert_eq!(image.sample_bilinear(0.5, 0.5), Luma([75]));
+        assert_eq!(image.sample_bilinear(-3.0, 5.0), Luma([50]));
+        assert_eq!(image.sample_bicubic(2.0, 1.0), Luma([250]));
+        assert_eq!(image.sample_bicubic(0.5, 0.0), Luma([44]));
+
+        let edges = |edges| image.sample_bilinear_with_edges(2.5, 0.0, edges);
+        assert_eq!(edges(EdgeMode::Clamp), Luma([200]));
+        assert_eq!(edges(EdgeMode::Wrap), Luma([100]));
+        assert_eq!(edges(EdgeMode::Mirror), Luma([200]));
+        assert_eq!(edges(EdgeMode::Zero), Luma([100]));
+        assert_eq!(
+            image.sample_bilinear_with_edges(-1.0, 1.0, EdgeMode::Mirror),
+            Luma([50])
+        );
+
+        // The overshoot of the cubic spline at a sharp edge is clamped.
+        let step = GrayImage::from_raw(4, 1, vec![0, 0, 255, 255]).unwrap();
+        assert_eq!(step.sample_bicubic(2.5, 0.0), Luma([255]));
+        assert_eq!(step.sample_bicubic(0.5, 0.0), Luma([0]));
+    }
 }
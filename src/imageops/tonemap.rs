@@ -0,0 +1,144 @@
+//! Tone mapping, which compresses the colors of HDR images into the range of displays.
+//!
+//! HDR images, like those of the Radiance HDR and OpenEXR decoders, hold linear colors that
+//! exceed 1. The operators of [`ToneMapping`] map them to display colors from 0 to 1, so that the
+//! images can be stored with 8 bits for thumbnails and previews.
+//!
+//! [`ToneMapping`]: enum.ToneMapping.html
+use crate::buffer_::{Rgb32FImage, RgbImage};
+use crate::color::transfer::linear_to_srgb;
+use crate::traits::Pixel;
+use crate::{ImageBuffer, Rgb};
+
+/// Operators that compress the linear colors of HDR images, which exceed 1, into the range from
+/// 0 to 1 that displays and 8-bit images hold.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum ToneMapping {
+    /// Clip colors at 1. Colors below are kept as they are, highlights lose their detail.
+    Clamp,
+    /// The operator `c / (1 + c)` of Reinhard et al., which keeps the detail of all highlights
+    /// but darkens the mid tones.
+    Reinhard,
+    /// The filmic curve of the Academy Color Encoding System, in the approximation of Krzysztof
+    /// Narkowicz, which keeps the contrast of mid tones and rolls highlights off softly.
+    Aces,
+    /// Scale colors by `2^exposure`, clip them at 1 and encode them with the power `1 / gamma`
+    /// instead of the transfer function of sRGB, like cameras and the LDR output of the Radiance
+    /// HDR decoder.
+    Exposure {
+        /// The change of exposure in stops, positive values brighten the image.
+        exposure: f32,
+        /// The gamma of the display, commonly 2.2.
+        gamma: f32,
+    },
+}
+
+impl ToneMapping {
+    /// Map a linear color channel to an encoded display value from 0 to 1.
+    fn map(self, value: f32) -> f32 {
+        let value = value.max(0.0);
+        let linear = match self {
+            ToneMapping::Clamp => value.min(1.0),
+            ToneMapping::Reinhard => value / (1.0 + value),
+            ToneMapping::Aces => {
+                let mapped = value * (2.51 * value + 0.03) / (value * (2.43 * value + 0.59) + 0.14);
+                mapped.min(1.0)
+            }
+            ToneMapping::Exposure { exposure, gamma } => {
+                let scaled = (value * exposure.exp2()).min(1.0);
+                return scaled.powf(gamma.recip());
+            }
+        };
+        linear_to_srgb(f64::from(linear)) as f32
+    }
+}
+
+/// Tone map an image of linear HDR colors into display colors from 0 to 1, in place.
+///
+/// Every color channel is compressed by `tone_mapping` and encoded for display, with the transfer
+/// function of sRGB unless the operator has its own gamma. Alpha is not changed.
+///
+/// ```
+/// use image::imageops::{tone_map_in_place, ToneMapping};
+/// use image::{Rgb, Rgb32FImage};
+///
+/// let mut image = Rgb32FImage::from_pixel(1, 1, Rgb([0.0, 1.0, 15.0]));
+/// tone_map_in_place(&mut image, ToneMapping::Reinhard);
+/// let Rgb([r, g, b]) = *image.get_pixel(0, 0);
+/// assert!(r == 0.0 && g < b && b < 1.0);
+/// ```
+pub fn tone_map_in_place<P>(image: &mut ImageBuffer<P, Vec<f32>>, tone_mapping: ToneMapping)
+where
+    P: Pixel<Subpixel = f32> + 'static,
+{
+    for pixel in image.pixels_mut() {
+        pixel.apply_with_alpha(|value| tone_mapping.map(value), |alpha| alpha);
+    }
+}
+
+/// Tone map an image of linear HDR colors into an 8-bit image for display.
+///
+/// ```
+/// use image::imageops::{tone_map, ToneMapping};
+/// use image::{Rgb, Rgb32FImage};
+///
+/// let image = Rgb32FImage::from_pixel(1, 1, Rgb([0.0, 0.5, 4.0]));
+/// let exposure = ToneMapping::Exposure { exposure: -2.0, gamma: 1.0 };
+/// assert_eq!(tone_map(&image, exposure).get_pixel(0, 0), &Rgb([0, 32, 255]));
+/// ```
+pub fn tone_map(image: &Rgb32FImage, tone_mapping: ToneMapping) -> RgbImage {
+    let (width, height) = image.dimensions();
+    RgbImage::from_fn(width, height, |x, y| {
+        let Rgb(channels) = *image.get_pixel(x, y);
+        let mut mapped = [0; 3];
+        for (mapped, &value) in mapped.iter_mut().zip(&channels) {
+            *mapped = (tone_mapping.map(value) * 255.0).round() as u8;
+        }
+        Rgb(mapped)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Rgba, Rgba32FImage};
+
+    #[test]
+    fn operators() {
+        for &tone_mapping in &[ToneMapping::Clamp, ToneMapping::Reinhard, ToneMapping::Aces] {
+            let values = [-1.0, 0.0, 0.18, 1.0, 4.0, 1000.0];
+            let mapped: Vec<f32> = values.iter().map(|&v| tone_mapping.map(v)).collect();
+            assert_eq!(mapped[0], 0.0);
+            assert!(
+                mapped.windows(2).all(|w| w[0] <= w[1]),
+                "{:?}",
+                tone_mapping
+            );
+            assert!(mapped[5] <= 1.0);
+        }
+        assert!((ToneMapping::Clamp.map(0.5) - 0.735_357).abs() < 1e-5);
+        assert_eq!(ToneMapping::Reinhard.map(1.0), ToneMapping::Clamp.map(0.5));
+        assert!(ToneMapping::Aces.map(1000.0) > 0.99);
+    }
+
+    #[test]
+    fn exposure_and_gamma() {
+        let mapping = ToneMapping::Exposure {
+            exposure: 1.0,
+            gamma: 2.0,
+        };
+        assert_eq!(mapping.map(0.125), 0.5);
+        assert_eq!(mapping.map(2.0), 1.0);
+
+        let image = Rgb32FImage::from_pixel(1, 1, Rgb([0.125, 0.0, 8.0]));
+        assert_eq!(tone_map(&image, mapping).into_raw(), vec![128, 0, 255]);
+    }
+
+    #[test]
+    fn keeps_alpha() {
+        let mut image = Rgba32FImage::from_pixel(1, 1, Rgba([1.0, 0.0, 3.0, 0.25]));
+        tone_map_in_place(&mut image, ToneMapping::Clamp);
+        assert_eq!(image.get_pixel(0, 0), &Rgba([1.0, 0.0, 1.0, 0.25]));
+    }
+}
@@ -0,0 +1,306 @@
+//! Placement of watermarks on images.
+use crate::image::{GenericImage, GenericImageView};
+use crate::imageops::{overlay_bounds_ext, resize, FilterType};
+use crate::traits::{Lerp, Pixel, Primitive};
+
+/// Where a watermark is placed on an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum WatermarkPosition {
+    /// The top left corner.
+    TopLeft,
+    /// The middle of the top edge.
+    Top,
+    /// The top right corner.
+    TopRight,
+    /// The middle of the left edge.
+    Left,
+    /// The center of the image, regardless of the margin.
+    Center,
+    /// The middle of the right edge.
+    Right,
+    /// The bottom left corner.
+    BottomLeft,
+    /// The middle of the bottom edge.
+    Bottom,
+    /// The bottom right corner.
+    BottomRight,
+    /// Repeat the watermark over the whole image, starting at the margin from the top left
+    /// corner.
+    Tiled {
+        /// The gap between neighbouring watermarks, horizontally and vertically.
+        spacing: u32,
+        /// Shift every other row by half a watermark and gap, in a brick pattern that makes the
+        /// watermark harder to crop out.
+        staggered: bool,
+    },
+}
+
+/// Options of [`watermark`].
+///
+/// [`watermark`]: fn.watermark.html
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WatermarkOptions {
+    /// Where the watermark is placed. Defaults to the bottom right corner.
+    pub position: WatermarkPosition,
+    /// How strongly the watermark covers the image, from 0 for invisible to 1 for as opaque as
+    /// its alpha channel. Defaults to 1.
+    pub opacity: f32,
+    /// The distance of the watermark from the edges of the image in pixels. Defaults to 0.
+    pub margin: u32,
+    /// Scale the watermark to this fraction of the width of the image, keeping its aspect ratio,
+    /// so that it covers the same part of images of any size. Defaults to `None`, which keeps the
+    /// watermark at its size.
+    pub scale_relative: Option<f32>,
+}
+
+impl Default for WatermarkOptions {
+    fn default() -> Self {
+        WatermarkOptions {
+            position: WatermarkPosition::BottomRight,
+            opacity: 1.0,
+            margin: 0,
+            scale_relative: None,
+        }
+    }
+}
+
+/// Place a watermark on an image.
+///
+/// The watermark is composited over the image with its alpha channel, then mixed with the image
+/// by the opacity of the options. Parts of the watermark outside of the image are cut off.
+///
+/// ```
+/// use image::imageops::{watermark, WatermarkOptions, WatermarkPosition};
+/// use image::{Rgba, RgbaImage};
+///
+/// let mut photo = RgbaImage::from_pixel(400, 300, Rgba([0, 0, 0, 255]));
+/// let logo = RgbaImage::from_pixel(40, 20, Rgba([255, 255, 255, 255]));
+/// let options = WatermarkOptions {
+///     position: WatermarkPosition::BottomRight,
+///     opacity: 0.5,
+///     margin: 10,
+///     scale_relative: Some(0.25),
+/// };
+/// watermark(&mut photo, &logo, &options);
+/// // The logo is scaled to 100 by 50 pixels, 10 pixels from the bottom right corner.
+/// assert_eq!(photo.get_pixel(389, 289), &Rgba([127, 127, 127, 255]));
+/// assert_eq!(photo.get_pixel(289, 239), &Rgba([0, 0, 0, 255]));
+/// ```
+///
+/// # Panics
+///
+/// Panics if the opacity is not between 0 and 1, or if the relative scale is not positive.
+pub fn watermark<I, J, S>(base: &mut I, mark: &J, options: &WatermarkOptions)
+where
+    I: GenericImage,
+    J: GenericImageView<Pixel = I::Pixel>,
+    I::Pixel: Pixel<Subpixel = S> + 'static,
+    S: Primitive + Lerp + 'static,
+{
+    assert!(
+        (0.0..=1.0).contains(&options.opacity),
+        "the opacity of a watermark must be between 0 and 1"
+    );
+    match options.scale_relative {
+        Some(scale) => {
+            assert!(
+                scale > 0.0 && scale.is_finite(),
+                "the relative scale of a watermark must be positive"
+            );
+            let width = (base.width() as f32 * scale).round().max(1.0);
+            let height = (width * mark.height() as f32 / mark.width().max(1) as f32)
+                .round()
+                .max(1.0);
+            let scaled = resize(mark, width as u32, height as u32, FilterType::Triangle);
+            place(base, &scaled, options)
+        }
+        None => place(base, mark, options),
+    }
+}
+
+fn place<I, J, S>(base: &mut I, mark: &J, options: &WatermarkOptions)
+where
+    I: GenericImage,
+    J: GenericImageView<Pixel = I::Pixel>,
+    I::Pixel: Pixel<Subpixel = S>,
+    S: Primitive + Lerp,
+{
+    let (width, height) = (i64::from(base.width()), i64::from(base.height()));
+    let (mark_width, mark_height) = (i64::from(mark.width()), i64::from(mark.height()));
+    let margin = i64::from(options.margin);
+    let start = margin;
+    let middle = |size: i64, mark_size: i64| (size - mark_size) / 2;
+    let end = |size: i64, mark_size: i64| size - mark_size - margin;
+
+    let (x, y) = match options.position {
+        WatermarkPosition::TopLeft => (start, start),
+        WatermarkPosition::Top => (middle(width, mark_width), start),
+        WatermarkPosition::TopRight => (end(width, mark_width), start),
+        WatermarkPosition::Left => (start, middle(height, mark_height)),
+        WatermarkPosition::Center => (middle(width, mark_width), middle(height, mark_height)),
+        WatermarkPosition::Right => (end(width, mark_width), middle(height, mark_height)),
+        WatermarkPosition::BottomLeft => (start, end(height, mark_height)),
+        WatermarkPosition::Bottom => (middle(width, mark_width), end(height, mark_height)),
+        WatermarkPosition::BottomRight => (end(width, mark_width), end(height, mark_height)),
+        WatermarkPosition::Tiled { spacing, staggered } => {
+            let step_x = mark_width + i64::from(spacing);
+            let step_y = mark_height + i64::from(spacing);
+            if step_x == 0 || step_y == 0 {
+                return;
+            }
+            let mut y = start;
+            let mut row = 0;
+            while y < height {
+                let mut x = start;
+                if staggered && row % 2 == 1 {
+                    // Start left of the image so that the shifted row covers it from the edge.
+                    x -= step_x - step_x / 2;
+                }
+                while x < width {
+                    blend_at(base, mark, x, y, options.opacity);
+                    x += step_x;
+                }
+                y += step_y;
+                row += 1;
+            }
+            return;
+        }
+    };
+    blend_at(base, mark, x, y, options.opacity);
+}
+
+/// Composite a watermark over the image at a position, mixed with the image by the opacity.
+fn blend_at<I, J, S>(base: &mut I, mark: &J, x: i64, y: i64, opacity: f32)
+where
+    I: GenericImage,
+    J: GenericImageView<Pixel = I::Pixel>,
+    I::Pixel: Pixel<Subpixel = S>,
+    S: Primitive + Lerp,
+{
+    let ratio: S::Ratio = num_traits::NumCast::from(opacity).unwrap();
+    let (base_x, base_y, mark_x, mark_y, range_width, range_height) =
+        overlay_bounds_ext(base.dimensions(), mark.dimensions(), x, y);
+
+    for dy in 0..range_height {
+        for dx in 0..range_width {
+            let under = base.get_pixel(base_x + dx, base_y + dy);
+            let mut over = under;
+            over.blend(&mark.get_pixel(mark_x + dx, mark_y + dy));
+            let mixed = under.map2(&over, |a, b| S::lerp(a, b, ratio));
+            base.put_pixel(base_x + dx, base_y + dy, mixed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayImage, Luma, LumaA, Rgb, RgbImage};
+
+    fn marked(position: WatermarkPosition, margin: u32) -> GrayImage {
+        let mut base = GrayImage::new(10, 8);
+        let mark = GrayImage::from_pixel(3, 2, Luma([255]));
+        let options = WatermarkOptions {
+            position,
+            margin,
+            ..WatermarkOptions::default()
+        };
+        watermark(&mut base, &mark, &options);
+        base
+    }
+
+    /// The top left corner of the marked area of an image.
+    fn origin(image: &GrayImage) -> (u32, u32) {
+        let (x, y, _) = image
+            .enumerate_pixels()
+            .find(|(_, _, pixel)| pixel[0] != 0)
+            .unwrap();
+        (x, y)
+    }
+
+    #[test]
+    fn anchored_positions() {
+        assert_eq!(origin(&marked(WatermarkPosition::TopLeft, 1)), (1, 1));
+        assert_eq!(origin(&marked(WatermarkPosition::Top, 1)), (3, 1));
+        assert_eq!(origin(&marked(WatermarkPosition::Right, 2)), (5, 3));
+        assert_eq!(origin(&marked(WatermarkPosition::Center, 2)), (3, 3));
+        assert_eq!(origin(&marked(WatermarkPosition::BottomLeft, 0)), (0, 6));
+        assert_eq!(origin(&marked(WatermarkPosition::BottomRight, 1)), (6, 5));
+        let marked = marked(WatermarkPosition::BottomRight, 1);
+        assert_eq!(marked.iter().filter(|&&value| value != 0).count(), 6);
+    }
+
+    #[test]
+    fn tiling() {
+        let tiled = WatermarkPosition::Tiled {
+            spacing: 1,
+            staggered: false,
+        };
+        let image = marked(tiled, 0);
+        // Columns 0..3, 4..7 and 8..10 and rows 0..2, 3..5 and 6..8 are marked.
+        for (x, y, pixel) in image.enumerate_pixels() {
+            assert_eq!(pixel[0] != 0, x % 4 != 3 && y % 3 != 2, "{} {}", x, y);
+        }
+
+        let staggered = WatermarkPosition::Tiled {
+            spacing: 1,
+            staggered: true,
+        };
+        let image = marked(staggered, 0);
+        assert_eq!(image.get_pixel(0, 0), &Luma([255]));
+        assert_eq!(image.get_pixel(0, 3), &Luma([255]));
+        assert_eq!(image.get_pixel(1, 3), &Luma([0]));
+        assert_eq!(image.get_pixel(2, 3), &Luma([255]));
+    }
+
+    #[test]
+    fn opacity_and_alpha() {
+        let mut base = RgbImage::from_pixel(2, 1, Rgb([0, 100, 200]));
+        let mark = RgbImage::from_pixel(1, 1, Rgb([200, 200, 200]));
+        let options = WatermarkOptions {
+            position: WatermarkPosition::TopLeft,
+            opacity: 0.25,
+            ..WatermarkOptions::default()
+        };
+        watermark(&mut base, &mark, &options);
+        assert_eq!(base.get_pixel(0, 0), &Rgb([50, 125, 200]));
+        assert_eq!(base.get_pixel(1, 0), &Rgb([0, 100, 200]));
+
+        // Transparent parts of the watermark leave the image as it is.
+        let mut base = crate::ImageBuffer::from_pixel(2, 1, LumaA([0u8, 255]));
+        let mark = crate::ImageBuffer::from_fn(2, 1, |x, _| LumaA([200u8, x as u8 * 255]));
+        let options = WatermarkOptions {
+            opacity: 0.5,
+            ..WatermarkOptions::default()
+        };
+        watermark(&mut base, &mark, &options);
+        assert_eq!(base.get_pixel(0, 0), &LumaA([0, 255]));
+        assert_eq!(base.get_pixel(1, 0), &LumaA([100, 255]));
+    }
+
+    #[test]
+    fn relative_scale() {
+        let mut base = GrayImage::new(40, 40);
+        let mark = GrayImage::from_pixel(4, 2, Luma([255]));
+        let options = WatermarkOptions {
+            position: WatermarkPosition::TopLeft,
+            scale_relative: Some(0.5),
+            ..WatermarkOptions::default()
+        };
+        watermark(&mut base, &mark, &options);
+        assert_eq!(base.get_pixel(19, 9), &Luma([255]));
+        assert_eq!(base.get_pixel(20, 0), &Luma([0]));
+        assert_eq!(base.get_pixel(0, 10), &Luma([0]));
+    }
+
+    #[test]
+    #[should_panic]
+    fn rejects_opacity() {
+        let options = WatermarkOptions {
+            opacity: 1.5,
+            ..WatermarkOptions::default()
+        };
+        watermark(&mut GrayImage::new(1, 1), &GrayImage::new(1, 1), &options);
+    }
+}
@@ -0,0 +1,92 @@
+//! Display of high bit depth images through a window of values.
+use crate::{GrayImage, ImageBuffer, Luma};
+
+/// Map a sixteen bit grayscale image to eight bits through a window of values.
+///
+/// Values from `center - width / 2` to `center + width / 2` are spread linearly over all gray
+/// levels, lower values are black and higher values white. This makes the details of a range of
+/// interest visible, such as the soft tissue of a medical scan or a faint nebula of an
+/// astronomical exposure, which a plain conversion to eight bits would flatten.
+///
+/// The mapping is the linear function of the DICOM standard, rounded to the nearest gray level,
+/// so that a window with a `width` of 256 maps 256 consecutive values to distinct levels. A width
+/// below 1 is a threshold at `center`. The levels of all possible values are computed once, so
+/// the cost per pixel is a table lookup.
+///
+/// ```
+/// use image::imageops::window_level;
+/// use image::{ImageBuffer, Luma};
+///
+/// let image = ImageBuffer::from_raw(4, 1, vec![0u16, 1000, 1100, 5000]).unwrap();
+/// let gray = window_level(&image, 1100.5, 201.0);
+/// assert_eq!(gray.into_raw(), vec![0, 0, 128, 255]);
+/// ```
+pub fn window_level(
+    image: &ImageBuffer<Luma<u16>, Vec<u16>>,
+    center: f32,
+    width: f32,
+) -> GrayImage {
+    let lut = window_lut(f64::from(center), f64::from(width), f64::from);
+    apply_lut(image, &lut)
+}
+
+/// The gray levels of all sixteen bit samples, after mapping them to the values of the window.
+pub(crate) fn window_lut<F: Fn(u16) -> f64>(center: f64, width: f64, value: F) -> Vec<u8> {
+    let width = width.max(1.0);
+    let low = center - 0.5 - (width - 1.0) / 2.0;
+    let high = center - 0.5 + (width - 1.0) / 2.0;
+    (0..=u16::MAX)
+        .map(|sample| {
+            let value = value(sample);
+            if value <= low {
+                0
+            } else if value > high {
+                255
+            } else {
+                let level = ((value - (center - 0.5)) / (width - 1.0) + 0.5) * 255.0;
+                level.round().clamp(0.0, 255.0) as u8
+            }
+        })
+        .collect()
+}
+
+/// Look up the gray level of every pixel.
+pub(crate) fn apply_lut(image: &ImageBuffer<Luma<u16>, Vec<u16>>, lut: &[u8]) -> GrayImage {
+    let data = image
+        .iter()
+        .map(|&sample| lut[usize::from(sample)])
+        .collect();
+    ImageBuffer::from_raw(image.width(), image.height(), data).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(center: f32, width: f32, samples: &[u16]) -> Vec<u8> {
+        let image = ImageBuffer::from_raw(samples.len() as u32, 1, samples.to_vec()).unwrap();
+        window_level(&image, center, width).into_raw()
+    }
+
+    #[test]
+    fn full_range() {
+        // The window of all sixteen bit values keeps the order and spreads evenly.
+        let samples: Vec<u16> = (0..=u16::MAX).step_by(257).collect();
+        let expected: Vec<u8> = (0..=255).collect();
+        assert_eq!(levels(32768.0, 65536.0, &samples), expected);
+    }
+
+    #[test]
+    fn distinct_levels_for_width_256() {
+        let samples: Vec<u16> = (1000..1256).collect();
+        let expected: Vec<u8> = (0..=255).collect();
+        assert_eq!(levels(1128.0, 256.0, &samples), expected);
+        assert_eq!(levels(1128.0, 256.0, &[999, 1256]), [0, 255]);
+    }
+
+    #[test]
+    fn threshold() {
+        assert_eq!(levels(100.0, 0.0, &[0, 99, 100, 101]), [0, 0, 255, 255]);
+        assert_eq!(levels(100.0, 1.0, &[99, 100]), [0, 255]);
+    }
+}
@@ -0,0 +1,105 @@
+//! Supplying the buffers images are decoded into.
+use std::fmt;
+use std::sync::Arc;
+
+use crate::traits::Primitive;
+
+/// Supplies the buffers that an [`ImageReader`] decodes images into.
+///
+/// Services that decode many images can recycle the buffers of images they are done with, taking
+/// them back with `DynamicImage::into_bytes` or `ImageBuffer::into_raw`, instead of allocating a
+/// new buffer for each image. The buffer is resized to the requested length if needed, and its
+/// contents are overwritten by the decoder, so a recycled buffer need not be cleared.
+///
+/// Each method has a default implementation that allocates a new zeroed buffer, implement those
+/// for the sample types whose buffers are to be recycled. Decoded images hold 8-bit samples
+/// unless their format has samples of 16 bits or floating point.
+///
+/// # Examples
+///
+/// A pool of buffers for 8-bit images.
+///
+/// ```no_run
+/// use std::sync::{Arc, Mutex};
+/// use image::io::{ImageReader, OutputBufferAllocator};
+///
+/// #[derive(Default)]
+/// struct Pool(Mutex<Vec<Vec<u8>>>);
+///
+/// impl OutputBufferAllocator for Pool {
+///     fn allocate_u8(&self, len: usize) -> Vec<u8> {
+///         let mut buffers = self.0.lock().unwrap();
+///         match buffers.iter().position(|buffer| buffer.capacity() >= len) {
+///             Some(index) => buffers.swap_remove(index),
+///             None => vec![0; len],
+///         }
+///     }
+/// }
+///
+/// let pool = Arc::new(Pool::default());
+/// for path in &["a.png", "b.png"] {
+///     let image = ImageReader::builder()
+///         .allocator(pool.clone())
+///         .open(path)?
+///         .decode()?;
+///     image.thumbnail(64, 64).save(format!("thumbnail-{}", path))?;
+///     pool.0.lock().unwrap().push(image.into_bytes());
+/// }
+/// # Ok::<(), image::ImageError>(())
+/// ```
+///
+/// [`ImageReader`]: type.ImageReader.html
+pub trait OutputBufferAllocator: Send + Sync {
+    /// Returns a buffer for `len` samples of 8 bits.
+    fn allocate_u8(&self, len: usize) -> Vec<u8> {
+        vec![0; len]
+    }
+
+    /// Returns a buffer for `len` samples of 16 bits.
+    fn allocate_u16(&self, len: usize) -> Vec<u16> {
+        vec![0; len]
+    }
+
+    /// Returns a buffer for `len` samples of 32-bit floating point.
+    fn allocate_f32(&self, len: usize) -> Vec<f32> {
+        vec![0.0; len]
+    }
+}
+
+/// Allocates every buffer anew, which is what readers without an allocator do.
+pub(crate) struct GlobalAllocator;
+
+impl OutputBufferAllocator for GlobalAllocator {}
+
+/// An allocator shared between readers.
+#[derive(Clone)]
+pub(crate) struct SharedAllocator(pub(crate) Arc<dyn OutputBufferAllocator>);
+
+impl fmt::Debug for SharedAllocator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SharedAllocator")
+    }
+}
+
+/// The sample types of decoded images, whose buffers an allocator supplies.
+pub(crate) trait OutputSample: Primitive + bytemuck::Pod {
+    fn allocate(allocator: &dyn OutputBufferAllocator, len: usize) -> Vec<Self>;
+}
+
+impl OutputSample for u8 {
+    fn allocate(allocator: &dyn OutputBufferAllocator, len: usize) -> Vec<Self> {
+        allocator.allocate_u8(len)
+    }
+}
+
+impl OutputSample for u16 {
+    fn allocate(allocator: &dyn OutputBufferAllocator, len: usize) -> Vec<Self> {
+        allocator.allocate_u16(len)
+    }
+}
+
+impl OutputSample for f32 {
+    fn allocate(allocator: &dyn OutputBufferAllocator, len: usize) -> Vec<Self> {
+        allocator.allocate_f32(len)
+    }
+}
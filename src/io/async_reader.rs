@@ -1,12 +1,19 @@
+use std::io::Cursor;
 use std::path::Path;
 use tokio::io::AsyncReadExt;
 use tokio::io;
 
 use crate::image::ImageFormat;
 use super::free_functions;
+use super::fs::FileSystem;
+use super::ReaderBuilder;
 use crate::{ImageError, ImageResult};
 use crate::dynimage::DynamicImage;
-use crate::error::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use crate::image::ProgressiveDecoder;
+use crate::error::{
+    ImageFormatHint, LimitError, LimitErrorKind, ParameterError, ParameterErrorKind,
+    UnsupportedError, UnsupportedErrorKind,
+};
 
 pub struct AsyncReader<R: AsyncReadExt> {
     /// The reader. Should be buffered.
@@ -15,6 +22,10 @@ pub struct AsyncReader<R: AsyncReadExt> {
     format: Option<ImageFormat>,
     /// Decoding limits
     limits: super::Limits,
+    /// Whether decoding runs on the blocking thread pool of the runtime.
+    offload: bool,
+    /// Whether the EXIF orientation is applied to the decoded image.
+    apply_orientation: bool,
 }
 
 impl<R: AsyncReadExt> AsyncReader<R> {
@@ -29,11 +40,7 @@ impl<R: AsyncReadExt> AsyncReader<R> {
     /// [`with_guessed_format`]: #method.with_guessed_format
     /// [`set_format`]: method.set_format
     pub fn new(buffered_reader: R) -> Self {
-        AsyncReader {
-            inner: buffered_reader,
-            format: None,
-            limits: super::Limits::default(),
-        }
+        ReaderBuilder::new().build_async(buffered_reader)
     }
 
     /// Construct a reader with specified format.
@@ -41,11 +48,7 @@ impl<R: AsyncReadExt> AsyncReader<R> {
     /// Assumes the reader is already buffered. For optimal performance,
     /// consider wrapping the reader with a `BufReader::new()`.
     pub fn with_format(buffered_reader: R, format: ImageFormat) -> Self {
-        AsyncReader {
-            inner: buffered_reader,
-            format: Some(format),
-            limits: super::Limits::default(),
-        }
+        ReaderBuilder::new().format(format).build_async(buffered_reader)
     }
 
     /// Get the currently determined format.
@@ -76,6 +79,15 @@ impl<R: AsyncReadExt> AsyncReader<R> {
         self.limits = limits;
     }
 
+    /// Choose whether the image is decoded on the blocking thread pool of the runtime.
+    ///
+    /// This is enabled by default, so that decoding a large image does not stall the other tasks
+    /// of the worker thread. Decoding small images inline can be faster, as it avoids moving the
+    /// data to another thread.
+    pub fn offload_decoding(&mut self, offload: bool) {
+        self.offload = offload;
+    }
+
     /// Unwrap the reader.
     pub fn into_inner(self) -> R {
         self.inner
@@ -96,14 +108,85 @@ impl AsyncReader<io::BufReader<tokio::fs::File>>{
     where 
         P: AsRef<Path>,
     {
-        Self::open_impl(path.as_ref()).await
+        ReaderBuilder::new().open_async(path).await
     }
-    async fn open_impl(path: &Path)->io::Result<Self>{
-        Ok(AsyncReader{
-            inner: io::BufReader::new(tokio::fs::File::open(path).await?),
-            format: ImageFormat::from_path(path).ok(),
-            limits: super::Limits::default(),
-        })
+}
+
+impl AsyncReader<Cursor<Vec<u8>>> {
+    /// Read a file of a [`FileSystem`], format will be guessed from path.
+    ///
+    /// The trait is synchronous, so the whole file is read into memory while opening it. This
+    /// blocks the current task, for file systems backed by the network wrap this call in
+    /// `tokio::task::spawn_blocking`.
+    ///
+    /// [`FileSystem`]: trait.FileSystem.html
+    pub fn open_in<S, P>(fs: S, path: P) -> io::Result<Self>
+    where
+        S: FileSystem,
+        P: AsRef<Path>,
+    {
+        ReaderBuilder::new().open_in_async(fs, path)
+    }
+}
+
+impl ReaderBuilder {
+    /// Create an async reader of `buffered_reader`.
+    ///
+    /// Assumes the reader is already buffered. For optimal performance,
+    /// consider wrapping the reader with a `BufReader::new()`.
+    pub fn build_async<R: AsyncReadExt>(self, buffered_reader: R) -> AsyncReader<R> {
+        AsyncReader {
+            inner: buffered_reader,
+            format: self.format,
+            limits: self.limits,
+            offload: self.offload,
+            apply_orientation: self.apply_orientation,
+        }
+    }
+
+    /// Open a file to async read. Unless set, the format will be guessed from path.
+    ///
+    /// This will not attempt any io operation on the opened file.
+    pub async fn open_async<P>(
+        self,
+        path: P,
+    ) -> io::Result<AsyncReader<io::BufReader<tokio::fs::File>>>
+    where
+        P: AsRef<Path>,
+    {
+        self.open_async_impl(path.as_ref()).await
+    }
+
+    async fn open_async_impl(
+        mut self,
+        path: &Path,
+    ) -> io::Result<AsyncReader<io::BufReader<tokio::fs::File>>> {
+        let file = tokio::fs::File::open(path).await?;
+        self.format = self.format.or_else(|| ImageFormat::from_path(path).ok());
+        Ok(self.build_async(io::BufReader::new(file)))
+    }
+
+    /// Read a file of a [`FileSystem`] for an async reader. Unless set, the format will be guessed
+    /// from path.
+    ///
+    /// This reads the whole file into memory, see [`AsyncReader::open_in`].
+    ///
+    /// [`FileSystem`]: trait.FileSystem.html
+    /// [`AsyncReader::open_in`]: struct.AsyncReader.html#method.open_in
+    pub fn open_in_async<S, P>(
+        mut self,
+        fs: S,
+        path: P,
+    ) -> io::Result<AsyncReader<Cursor<Vec<u8>>>>
+    where
+        S: FileSystem,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let mut data = Vec::new();
+        std::io::Read::read_to_end(&mut fs.open(path)?, &mut data)?;
+        self.format = self.format.or_else(|| ImageFormat::from_path(path).ok());
+        Ok(self.build_async(Cursor::new(data)))
     }
 }
 
@@ -149,32 +232,178 @@ impl<R> AsyncReader<R>
     async fn guess_format(&mut self) -> io::Result<Option<ImageFormat>> {
         // Save current offset, read start, restore offset.
         let cur = self.inner.seek(std::io::SeekFrom::Current(0)).await?;
-        let mut start = [0u8; 16];
-        let len = self.inner.read_exact(&mut start).await? as u64;
+        let mut start = Vec::with_capacity(free_functions::GUESS_LEN);
+        // Accept shorter files but read at most `GUESS_LEN` bytes.
+        (&mut self.inner)
+            .take(free_functions::GUESS_LEN as u64)
+            .read_to_end(&mut start)
+            .await?;
 
         self.inner.seek(io::SeekFrom::Start(cur)).await?;
 
-        Ok(free_functions::guess_format_impl(&start[..len as usize]))
+        Ok(free_functions::guess_format_impl(&start))
     }
 
     /// Read the image dimensions.
     ///
     /// Uses the current format to construct the correct reader for the format.
     ///
+    /// If the reader was built to [apply the orientation], these are the dimensions after applying
+    /// it.
+    ///
     /// If no format was determined, returns an `ImageError::Unsupported`.
+    ///
+    /// [apply the orientation]: struct.ReaderBuilder.html#method.apply_orientation
     pub async fn into_dimensions(mut self) -> ImageResult<(u32, u32)> {
         let format = self.require_format()?;
-        free_functions::image_dimensions_with_format_impl_async(self.inner, format).await
+        // Read only as much as the header needs, retrying with more data on errors.
+        let mut data = Vec::new();
+        let mut at_end = false;
+        let (width, height) = loop {
+            at_end = at_end || !self.read_more(&mut data).await?;
+            let limits = self.limits.clone();
+            match free_functions::image_dimensions_with_format_impl(
+                Cursor::new(&data),
+                format,
+                limits,
+            ) {
+                Ok(dimensions) => break dimensions,
+                // More data does not lift the limits.
+                Err(err @ ImageError::Limits(_)) => return Err(err),
+                Err(err) if at_end => return Err(err),
+                Err(_) => {}
+            }
+        };
+        if !self.apply_orientation {
+            return Ok((width, height));
+        }
+        let orientation = loop {
+            match super::exif::orientation(&mut Cursor::new(&data), format) {
+                Ok(orientation) => break orientation,
+                Err(_) if at_end => break None,
+                Err(_) => at_end = !self.read_more(&mut data).await?,
+            }
+        };
+        match orientation {
+            Some(orientation) if super::exif::swaps_dimensions(orientation) => Ok((height, width)),
+            _ => Ok((width, height)),
+        }
     }
 
     /// Read the image (replaces `load`).
     ///
-    /// Uses the current format to construct the correct reader for the format.
+    /// Uses the current format to construct the correct reader for the format. The encoded image
+    /// is read asynchronously and then decoded, on the blocking thread pool of the runtime unless
+    /// disabled with [`offload_decoding`].
+    ///
+    /// If the reader was built to [apply the orientation], the image is rotated and flipped
+    /// accordingly.
     ///
     /// If no format was determined, returns an `ImageError::Unsupported`.
-    pub fn decode(mut self) -> ImageResult<DynamicImage> {
+    ///
+    /// Returns an `ImageError::Parameter` if decoding is offloaded and this is not called from
+    /// within a Tokio runtime.
+    ///
+    /// [`offload_decoding`]: #method.offload_decoding
+    /// [apply the orientation]: struct.ReaderBuilder.html#method.apply_orientation
+    pub async fn decode(mut self) -> ImageResult<DynamicImage> {
+        let format = self.require_format()?;
+        let data = self.read_data().await?;
+        // Reading a slow stream may already have used up the time for decoding.
+        self.limits.check_cancelled()?;
+        let orientation = self.applied_orientation(&data, format);
+        let limits = self.limits;
+        let decode = move || {
+            let image = free_functions::load_inner(Cursor::new(data), limits, format)?;
+            Ok(match orientation {
+                Some(orientation) => super::exif::apply(image, orientation),
+                None => image,
+            })
+        };
+        if !self.offload {
+            return decode();
+        }
+        let runtime = tokio::runtime::Handle::try_current().map_err(|_| {
+            ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                "decoding is offloaded, but there is no Tokio runtime".to_owned(),
+            )))
+        })?;
+        match runtime.spawn_blocking(decode).await {
+            Ok(result) => result,
+            Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+            Err(err) => Err(ImageError::IoError(io::Error::new(io::ErrorKind::Other, err))),
+        }
+    }
+
+    /// Read the image progressively, calling `on_refinement` with a preview of the image
+    /// whenever more of it is known, and return the complete image.
+    ///
+    /// This needs a [`ProgressiveDecoder`] for the current format, which exists for PNG and JPEG.
+    /// Previews are made of interlaced PNG and progressive JPEG images, while other images are
+    /// only returned once complete. Unlike [`decode`], the image is decoded on the current task,
+    /// as the data arrives.
+    ///
+    /// If no format was determined, or the format can not be decoded progressively, returns an
+    /// `ImageError::Unsupported`.
+    ///
+    /// [`ProgressiveDecoder`]: ../trait.ProgressiveDecoder.html
+    /// [`decode`]: #method.decode
+    pub async fn decode_progressively<F>(mut self, mut on_refinement: F) -> ImageResult<DynamicImage>
+    where
+        F: FnMut(&DynamicImage),
+    {
         let format = self.require_format()?;
-        free_functions::load_inner(self.inner, self.limits, format)
+        let mut decoder = progressive_decoder(format)?;
+        let mut buf = vec![0; 16 * 1024];
+        loop {
+            self.limits.check_cancelled()?;
+            let read = self.inner.read(&mut buf).await?;
+            if read == 0 {
+                return Err(ImageError::IoError(io::ErrorKind::UnexpectedEof.into()));
+            }
+            if let Some(image) = decoder.feed(&buf[..read])? {
+                self.limits.check_dimensions(image.width(), image.height())?;
+                if decoder.is_complete() {
+                    return Ok(image);
+                }
+                on_refinement(&image);
+            }
+        }
+    }
+
+    /// Reads the rest of the encoded image into memory.
+    async fn read_data(&mut self) -> ImageResult<Vec<u8>> {
+        let mut data = Vec::new();
+        while self.read_more(&mut data).await? {}
+        Ok(data)
+    }
+
+    /// Reads about as much data as there is already, at least a few kilobytes, within the
+    /// allocation limit. Returns `false` at the end of the encoded image.
+    async fn read_more(&mut self, data: &mut Vec<u8>) -> ImageResult<bool> {
+        let mut chunk = data.len().max(4096) as u64;
+        if let Some(max_alloc) = self.limits.max_alloc {
+            // One more byte than allowed tells an image at the limit from a larger one.
+            chunk = chunk.min((max_alloc + 1).saturating_sub(data.len() as u64));
+        }
+        let read = (&mut self.inner).take(chunk).read_to_end(data).await?;
+        if let Some(max_alloc) = self.limits.max_alloc {
+            if data.len() as u64 > max_alloc {
+                return Err(ImageError::Limits(LimitError::from_kind(
+                    LimitErrorKind::InsufficientMemory,
+                )));
+            }
+        }
+        Ok(read > 0)
+    }
+
+    /// The orientation to apply to the image in `data`, if the reader was built to do so.
+    fn applied_orientation(&self, data: &[u8], format: ImageFormat) -> Option<u16> {
+        if !self.apply_orientation {
+            return None;
+        }
+        // An image without readable metadata might still decode, leave errors to the decoder.
+        super::exif::orientation(&mut Cursor::new(data), format).unwrap_or(None)
     }
 
     fn require_format(&mut self) -> ImageResult<ImageFormat> {
@@ -185,4 +414,147 @@ impl<R> AsyncReader<R>
             ))
         })
     }
-}
\ No newline at end of file
+}
+/// Creates the progressive decoder for `format`.
+fn progressive_decoder(format: ImageFormat) -> ImageResult<Box<dyn ProgressiveDecoder>> {
+    match format {
+        #[cfg(feature = "png")]
+        ImageFormat::Png => Ok(Box::new(crate::codecs::png::ProgressivePngDecoder::new())),
+        #[cfg(feature = "jpeg")]
+        ImageFormat::Jpeg => Ok(Box::new(crate::codecs::jpeg::ProgressiveJpegDecoder::new())),
+        format => Err(ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+            format.into(),
+            UnsupportedErrorKind::GenericFeature("progressive decoding".to_owned()),
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn decode_offloaded_and_inline() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let data = b"P5 3 2 255\n\x00\x01\x02\x03\x04\x05";
+        runtime.block_on(async {
+            let reader = AsyncReader::with_format(Cursor::new(&data[..]), ImageFormat::Pnm);
+            assert_eq!(reader.into_dimensions().await.unwrap(), (3, 2));
+
+            let reader = AsyncReader::new(Cursor::new(&data[..]))
+                .with_guessed_format()
+                .await
+                .unwrap();
+            let image = reader.decode().await.unwrap();
+            assert_eq!(image.as_bytes(), &[0, 1, 2, 3, 4, 5]);
+
+            let mut reader = AsyncReader::with_format(Cursor::new(&data[..]), ImageFormat::Pnm);
+            reader.offload_decoding(false);
+            let image = reader.decode().await.unwrap();
+            assert_eq!(image.as_bytes(), &[0, 1, 2, 3, 4, 5]);
+
+            let reader = ReaderBuilder::new()
+                .format(ImageFormat::Pnm)
+                .offload_decoding(false)
+                .apply_orientation(true)
+                .build_async(Cursor::new(&data[..]));
+            let image = reader.decode().await.unwrap();
+            assert_eq!(image.as_bytes(), &[0, 1, 2, 3, 4, 5]);
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn dimensions_from_header() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut data = b"P5 4000 4000 255\n".to_vec();
+        data.resize(data.len() + 4000 * 4000, 0);
+        runtime.block_on(async {
+            let mut cursor = Cursor::new(&data[..]);
+            let reader = AsyncReader::with_format(&mut cursor, ImageFormat::Pnm);
+            assert_eq!(reader.into_dimensions().await.unwrap(), (4000, 4000));
+            assert!(cursor.position() < 100_000, "read {} bytes", cursor.position());
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn read_within_max_alloc() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let mut data = b"P5 100 100 255\n".to_vec();
+        data.resize(data.len() + 100 * 100, 0);
+        runtime.block_on(async {
+            let limits = crate::io::Limits {
+                max_alloc: Some(1000),
+                ..crate::io::Limits::default()
+            };
+            let mut cursor = Cursor::new(&data[..]);
+            let mut reader = AsyncReader::with_format(&mut cursor, ImageFormat::Pnm);
+            reader.limits(limits);
+            let result = reader.decode().await;
+            assert!(matches!(result, Err(ImageError::Limits(_))));
+            assert!(cursor.position() <= 1001);
+        });
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn offload_without_runtime() {
+        use std::future::Future;
+        use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+        fn noop_raw_waker() -> RawWaker {
+            fn clone(_: *const ()) -> RawWaker {
+                noop_raw_waker()
+            }
+            fn noop(_: *const ()) {}
+            static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+            RawWaker::new(std::ptr::null(), &VTABLE)
+        }
+
+        // Safety: the waker does nothing, so it can not violate the contract of its vtable.
+        let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+        let mut context = Context::from_waker(&waker);
+        let data = b"P5 1 1 255\n\x00";
+        let reader = AsyncReader::with_format(Cursor::new(&data[..]), ImageFormat::Pnm);
+        let mut decode = Box::pin(reader.decode());
+        match decode.as_mut().poll(&mut context) {
+            Poll::Ready(result) => assert!(matches!(result, Err(ImageError::Parameter(_)))),
+            Poll::Pending => panic!("reading from memory should not wait"),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "jpeg")]
+    fn decode_progressively() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let data = std::fs::read("tests/images/jpg/progressive/cat.jpg").unwrap();
+        runtime.block_on(async {
+            let reader = AsyncReader::with_format(Cursor::new(&data[..]), ImageFormat::Jpeg);
+            let mut previews = 0;
+            let image = reader
+                .decode_progressively(|preview| {
+                    assert_eq!(preview.width(), 320);
+                    previews += 1;
+                })
+                .await
+                .unwrap();
+            assert!(previews > 0);
+            assert_eq!(image.as_bytes(), crate::load_from_memory(&data).unwrap().as_bytes());
+
+            let truncated = AsyncReader::with_format(Cursor::new(&data[..100]), ImageFormat::Jpeg);
+            assert!(truncated.decode_progressively(|_| ()).await.is_err());
+            let pnm = AsyncReader::with_format(Cursor::new(&data[..]), ImageFormat::Pnm);
+            assert!(pnm.decode_progressively(|_| ()).await.is_err());
+        });
+    }
+}
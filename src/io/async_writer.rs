@@ -0,0 +1,268 @@
+use std::io::Cursor;
+use std::path::Path;
+
+use tokio::io::{self, AsyncWrite, AsyncWriteExt};
+
+use super::free_functions;
+use crate::color::ColorType;
+use crate::error::{
+    ImageError, ImageFormatHint, ImageResult, UnsupportedError, UnsupportedErrorKind,
+};
+use crate::image::{ImageFormat, ImageOutputFormat};
+
+/// An image writer for asynchronous outputs.
+///
+/// Images are encoded into memory, on the blocking thread pool of the runtime unless disabled
+/// with [`offload_encoding`], and the result is then written to the output asynchronously. This
+/// way encoding a large image does not stall the other tasks of the worker thread.
+///
+/// ## Usage
+///
+/// ```no_run
+/// # async fn save() -> image::ImageResult<()> {
+/// use image::io::AsyncWriter;
+/// use image::RgbImage;
+///
+/// let image = RgbImage::new(64, 64);
+/// // The format is guessed from the path, as with `save`.
+/// let mut writer = AsyncWriter::create("thumbnail.png").await?;
+/// writer
+///     .write_image(&image, 64, 64, image::ColorType::Rgb8)
+///     .await?;
+/// # Ok(()) }
+/// ```
+///
+/// [`offload_encoding`]: #method.offload_encoding
+pub struct AsyncWriter<W> {
+    /// The output.
+    inner: W,
+    /// The format to encode images in, if one has been set or deduced.
+    format: Option<ImageOutputFormat>,
+    /// Whether encoding runs on the blocking thread pool of the runtime.
+    offload: bool,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWriter<W> {
+    /// Create a new image writer without a preset format.
+    ///
+    /// Images can then only be written with [`write_with_encoder`], until a format is set with
+    /// [`set_format`].
+    ///
+    /// [`write_with_encoder`]: #method.write_with_encoder
+    /// [`set_format`]: #method.set_format
+    pub fn new(writer: W) -> Self {
+        AsyncWriter {
+            inner: writer,
+            format: None,
+            offload: true,
+        }
+    }
+
+    /// Construct a writer with the specified format.
+    pub fn with_format<F: Into<ImageOutputFormat>>(writer: W, format: F) -> Self {
+        AsyncWriter {
+            inner: writer,
+            format: Some(format.into()),
+            offload: true,
+        }
+    }
+
+    /// Get the format images are encoded in.
+    pub fn format(&self) -> Option<&ImageOutputFormat> {
+        self.format.as_ref()
+    }
+
+    /// Supply the format in which to encode images.
+    pub fn set_format<F: Into<ImageOutputFormat>>(&mut self, format: F) {
+        self.format = Some(format.into());
+    }
+
+    /// Choose whether images are encoded on the blocking thread pool of the runtime.
+    ///
+    /// This is enabled by default. Encoding small images inline can be faster, as it avoids
+    /// moving the data to another thread.
+    pub fn offload_encoding(&mut self, offload: bool) {
+        self.offload = offload;
+    }
+
+    /// Unwrap the writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Encode an image buffer in the current format and write it to the output.
+    ///
+    /// Like `save_buffer`, images with an alpha channel are composited over white for formats
+    /// that can not store it. If the encoding is offloaded, the buffer is copied first.
+    ///
+    /// If no format was set, returns an `ImageError::Unsupported`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if encoding is offloaded and this is not called from within a Tokio runtime.
+    pub async fn write_image(
+        &mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color: ColorType,
+    ) -> ImageResult<()> {
+        let format = self.format.clone().ok_or_else(|| {
+            ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+                ImageFormatHint::Unknown,
+                UnsupportedErrorKind::Format(ImageFormatHint::Unknown),
+            ))
+        })?;
+        if !self.offload {
+            let mut data = Vec::new();
+            free_functions::write_buffer_impl(
+                &mut free_functions::Seekable(Cursor::new(&mut data)),
+                buf,
+                width,
+                height,
+                color,
+                format,
+                free_functions::DEFAULT_MATTE,
+            )?;
+            return self.write_data(&data).await;
+        }
+
+        let buf = buf.to_vec();
+        self.write_with_encoder(move |data| {
+            free_functions::write_buffer_impl(
+                &mut free_functions::Seekable(Cursor::new(data)),
+                &buf,
+                width,
+                height,
+                color,
+                format,
+                free_functions::DEFAULT_MATTE,
+            )
+        })
+        .await
+    }
+
+    /// Encode an image with a custom encoder and write it to the output.
+    ///
+    /// The function writes the encoded image into the given buffer, for example by passing it to
+    /// the constructor of an encoder with specific options.
+    ///
+    /// ```no_run
+    /// # #[cfg(feature = "png")]
+    /// # async fn save(writer: &mut image::io::AsyncWriter<tokio::fs::File>) -> image::ImageResult<()> {
+    /// use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+    /// use image::{ImageEncoder, RgbImage};
+    ///
+    /// let image = RgbImage::new(64, 64);
+    /// writer
+    ///     .write_with_encoder(move |data| {
+    ///         PngEncoder::new_with_quality(data, CompressionType::Best, FilterType::Adaptive)
+    ///             .write_image(&image, 64, 64, image::ColorType::Rgb8)
+    ///     })
+    ///     .await
+    /// # }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if encoding is offloaded and this is not called from within a Tokio runtime.
+    pub async fn write_with_encoder<F>(&mut self, encode: F) -> ImageResult<()>
+    where
+        F: FnOnce(&mut Vec<u8>) -> ImageResult<()> + Send + 'static,
+    {
+        let encode = move || -> ImageResult<Vec<u8>> {
+            let mut data = Vec::new();
+            encode(&mut data)?;
+            Ok(data)
+        };
+        let data = if self.offload {
+            match tokio::task::spawn_blocking(encode).await {
+                Ok(result) => result?,
+                Err(err) if err.is_panic() => std::panic::resume_unwind(err.into_panic()),
+                Err(err) => {
+                    return Err(ImageError::IoError(io::Error::new(
+                        io::ErrorKind::Other,
+                        err,
+                    )))
+                }
+            }
+        } else {
+            encode()?
+        };
+        self.write_data(&data).await
+    }
+
+    async fn write_data(&mut self, data: &[u8]) -> ImageResult<()> {
+        self.inner.write_all(data).await?;
+        self.inner.flush().await?;
+        Ok(())
+    }
+}
+
+impl AsyncWriter<tokio::fs::File> {
+    /// Create a file to write an image to, format will be guessed from path.
+    ///
+    /// If the format can not be guessed, the writer is created without a preset format.
+    pub async fn create<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let format = ImageFormat::from_path(path)
+            .and_then(|format| free_functions::output_format_for_path(path, format))
+            .ok();
+        Ok(AsyncWriter {
+            inner: tokio::fs::File::create(path).await?,
+            format,
+            offload: true,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn write_offloaded_and_inline() {
+        use crate::codecs::pnm::{PnmSubtype, SampleEncoding};
+
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let pixels = [0u8, 1, 2, 3, 4, 5];
+        let format = ImageOutputFormat::Pnm(PnmSubtype::Graymap(SampleEncoding::Binary));
+        runtime.block_on(async {
+            let mut writer = AsyncWriter::new(Vec::new());
+            assert!(writer
+                .write_image(&pixels, 3, 2, ColorType::L8)
+                .await
+                .is_err());
+
+            writer.set_format(format.clone());
+            writer
+                .write_image(&pixels, 3, 2, ColorType::L8)
+                .await
+                .unwrap();
+            let image = crate::load_from_memory(&writer.into_inner()).unwrap();
+            assert_eq!(image.as_bytes(), &pixels);
+
+            let mut writer = AsyncWriter::with_format(Vec::new(), format);
+            writer.offload_encoding(false);
+            writer
+                .write_image(&pixels, 3, 2, ColorType::L8)
+                .await
+                .unwrap();
+            writer
+                .write_with_encoder(|data| {
+                    data.extend_from_slice(b"trailer");
+                    Ok(())
+                })
+                .await
+                .unwrap();
+            let data = writer.into_inner();
+            assert!(data.starts_with(b"P5") && data.ends_with(b"trailer"));
+        });
+    }
+}
@@ -0,0 +1,284 @@
+//! Locating the EXIF and XMP metadata of an encoded image without decoding it.
+//!
+//! Only the metadata of the first image is read. Malformed or missing metadata is not an error,
+//! the orientation is then simply unknown.
+#[cfg(feature = "jpeg")]
+use std::io::Cursor;
+use std::io::{self, Read, Seek, SeekFrom};
+
+use crate::dynimage::DynamicImage;
+use crate::image::ImageFormat;
+use crate::metadata::{self, Exif, Orientation};
+
+/// Returns the EXIF orientation of the image in `r`, a value from 1 to 8.
+///
+/// The reader is left at an unspecified position.
+pub(crate) fn orientation<R: Read + Seek>(
+    r: &mut R,
+    format: ImageFormat,
+) -> io::Result<Option<u16>> {
+    let exif = exif(r, format)?;
+    Ok(exif
+        .and_then(|exif| exif.orientation)
+        .map(Orientation::to_exif))
+}
+
+/// Returns the EXIF metadata of the image in `r`.
+///
+/// The reader is left at an unspecified position.
+pub(crate) fn exif<R: Read + Seek>(r: &mut R, format: ImageFormat) -> io::Result<Option<Exif>> {
+    let start = r.stream_position()?;
+    let base = match format {
+        #[cfg(feature = "jpeg")]
+        ImageFormat::Jpeg => {
+            return match jpeg_app1(r, metadata::EXIF_IDENTIFIER)? {
+                Some(tiff) => metadata::read_exif(&mut Cursor::new(tiff), 0).map(Some),
+                None => Ok(None),
+            };
+        }
+        ImageFormat::Png => png_exif(r)?,
+        ImageFormat::Tiff => Some(start),
+        ImageFormat::WebP => webp_exif(r)?,
+        _ => None,
+    };
+    match base {
+        Some(base) => metadata::read_exif(r, base).map(Some),
+        None => Ok(None),
+    }
+}
+
+/// Returns the XMP packet of a JPEG image in `r`.
+///
+/// Other formats store the packet where their decoders find it, and return `None`. The reader is
+/// left at an unspecified position.
+#[allow(unused_variables)] // When the JPEG format is not supported
+pub(crate) fn xmp<R: Read + Seek>(r: &mut R, format: ImageFormat) -> io::Result<Option<String>> {
+    match format {
+        #[cfg(feature = "jpeg")]
+        ImageFormat::Jpeg => {
+            Ok(jpeg_app1(r, metadata::XMP_IDENTIFIER)?.and_then(|xmp| String::from_utf8(xmp).ok()))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Returns if the orientation rotates the image by 90 or 270 degrees, swapping its dimensions.
+pub(crate) fn swaps_dimensions(orientation: u16) -> bool {
+    Orientation::from_exif(orientation).map_or(false, Orientation::swaps_dimensions)
+}
+
+/// Transform an image as stored into the orientation it is displayed in.
+pub(crate) fn apply(mut image: DynamicImage, orientation: u16) -> DynamicImage {
+    if let Some(orientation) = Orientation::from_exif(orientation) {
+        image.apply_orientation(orientation);
+    }
+    image
+}
+
+/// Returns the data after `identifier` in the first APP1 segment of a JPEG image that starts
+/// with it.
+#[cfg(feature = "jpeg")]
+fn jpeg_app1<R: Read>(r: &mut R, identifier: &[u8]) -> io::Result<Option<Vec<u8>>> {
+    use crate::error::ImageError;
+
+    let segments = match crate::codecs::jpeg::JpegSegmentReader::new(r) {
+        Ok(segments) => segments,
+        Err(ImageError::IoError(err)) => return Err(err),
+        Err(_) => return Ok(None),
+    };
+    for segment in segments {
+        match segment {
+            Ok(segment) if segment.marker == 0xe1 && segment.has_identifier(identifier) => {
+                return Ok(Some(segment.data[identifier.len()..].to_vec()));
+            }
+            Ok(_) => {}
+            Err(ImageError::IoError(err)) => return Err(err),
+            // The position in the file is lost after a malformed segment.
+            Err(_) => return Ok(None),
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the offset of the `eXIf` chunk of a PNG image.
+fn png_exif<R: Read + Seek>(r: &mut R) -> io::Result<Option<u64>> {
+    let mut signature = [0; 8];
+    r.read_exact(&mut signature)?;
+
+    loop {
+        let mut header = [0; 8];
+        r.read_exact(&mut header)?;
+        let length = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
+        match &header[4..] {
+            b"eXIf" => return Ok(Some(r.stream_position()?)),
+            // Metadata after the image data is not considered for the layout.
+            b"IDAT" | b"IEND" => return Ok(None),
+            // Skip the data and the checksum.
+            _ => r.seek(SeekFrom::Current(i64::from(length) + 4))?,
+        };
+    }
+}
+
+/// Returns the offset of the TIFF structure in the `EXIF` chunk of a WebP image.
+fn webp_exif<R: Read + Seek>(r: &mut R) -> io::Result<Option<u64>> {
+    let mut header = [0; 12];
+    r.read_exact(&mut header)?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WEBP" {
+        return Ok(None);
+    }
+
+    loop {
+        let mut chunk = [0; 8];
+        r.read_exact(&mut chunk)?;
+        let length = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        let start = r.stream_position()?;
+        if &chunk[..4] == b"EXIF" {
+            // Some encoders keep the identifier of the JPEG segment.
+            let mut identifier = [0; 6];
+            r.read_exact(&mut identifier)?;
+            if identifier == metadata::EXIF_IDENTIFIER {
+                return Ok(Some(start + 6));
+            }
+            return Ok(Some(start));
+        }
+        // Chunks are padded to an even length.
+        r.seek(SeekFrom::Start(
+            start + u64::from(length) + u64::from(length & 1),
+        ))?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A little endian TIFF structure with a single orientation entry.
+    fn exif(orientation: u16) -> Vec<u8> {
+        let mut exif = b"II*\0\x08\0\0\0\x01\0\x12\x01\x03\0\x01\0\0\0".to_vec();
+        exif.extend_from_slice(&orientation.to_le_bytes());
+        exif.extend_from_slice(&[0; 6]);
+        exif
+    }
+
+    #[cfg(feature = "jpeg")]
+    #[test]
+    fn jpeg_app1() {
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe0, 0, 4, 0, 0, 0xff, 0xe1];
+        let exif = exif(6);
+        jpeg.extend_from_slice(&(exif.len() as u16 + 8).to_be_bytes());
+        jpeg.extend_from_slice(b"Exif\0\0");
+        jpeg.extend_from_slice(&exif);
+        jpeg.extend_from_slice(&[0xff, 0xda]);
+
+        let orientation = orientation(&mut Cursor::new(jpeg), ImageFormat::Jpeg).unwrap();
+        assert_eq!(orientation, Some(6));
+    }
+
+    #[cfg(feature = "jpeg")]
+    #[test]
+    fn jpeg_xmp() {
+        let packet = "<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/>";
+        let mut jpeg = vec![0xff, 0xd8, 0xff, 0xe1];
+        jpeg.extend_from_slice(&(packet.len() as u16 + 31).to_be_bytes());
+        jpeg.extend_from_slice(metadata::XMP_IDENTIFIER);
+        jpeg.extend_from_slice(packet.as_bytes());
+        jpeg.extend_from_slice(&[0xff, 0xda, 0, 2]);
+
+        let xmp = xmp(&mut Cursor::new(&jpeg), ImageFormat::Jpeg).unwrap();
+        assert_eq!(xmp.as_deref(), Some(packet));
+        let orientation = orientation(&mut Cursor::new(&jpeg), ImageFormat::Jpeg).unwrap();
+        assert_eq!(orientation, None);
+    }
+
+    #[test]
+    fn big_endian_tiff() {
+        let tiff = b"MM\0*\0\0\0\x08\0\x02\x01\0\0\x03\0\0\0\x01\0\x10\0\0\x01\x12\0\x03\0\0\0\x01\0\x08\0\0";
+        let orientation = orientation(&mut Cursor::new(&tiff[..]), ImageFormat::Tiff).unwrap();
+        assert_eq!(orientation, Some(8));
+    }
+
+    #[test]
+    fn invalid_orientation() {
+        let tiff = exif(9);
+        let orientation = orientation(&mut Cursor::new(tiff), ImageFormat::Tiff).unwrap();
+        assert_eq!(orientation, None);
+    }
+
+    /// A JPEG image of 3x2 pixels with the given orientation.
+    #[cfg(feature = "jpeg")]
+    fn oriented_jpeg(orientation: u16) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        crate::codecs::jpeg::JpegEncoder::new(&mut encoded)
+            .encode(&[0, 0, 0, 255, 255, 255], 3, 2, crate::ColorType::L8)
+            .unwrap();
+        let exif = exif(orientation);
+        let mut segment = vec![0xff, 0xe1];
+        segment.extend_from_slice(&(exif.len() as u16 + 8).to_be_bytes());
+        segment.extend_from_slice(b"Exif\0\0");
+        segment.extend_from_slice(&exif);
+        encoded[..2]
+            .iter()
+            .chain(&segment)
+            .chain(&encoded[2..])
+            .copied()
+            .collect()
+    }
+
+    #[cfg(feature = "jpeg")]
+    #[test]
+    fn reader_swaps_dimensions() {
+        let jpeg = oriented_jpeg(6);
+        let reader = || crate::io::Reader::with_format(Cursor::new(&jpeg), ImageFormat::Jpeg);
+        assert_eq!(reader().into_dimensions().unwrap(), (3, 2));
+        assert_eq!(reader().into_dimensions_oriented().unwrap(), (2, 3));
+    }
+
+    #[cfg(feature = "jpeg")]
+    #[test]
+    fn reader_applies_orientation() {
+        let jpeg = oriented_jpeg(6);
+        let reader = || {
+            crate::io::ImageReader::builder()
+                .format(ImageFormat::Jpeg)
+                .apply_orientation(true)
+                .build(Cursor::new(&jpeg))
+        };
+        assert_eq!(reader().into_dimensions().unwrap(), (2, 3));
+        let image = reader().decode().unwrap().to_luma8();
+        assert_eq!(image.dimensions(), (2, 3));
+        // The dark upper row of the stored image is on the right after rotating it.
+        assert!(image.get_pixel(1, 0)[0] < 64 && image.get_pixel(0, 0)[0] > 192);
+    }
+
+    #[test]
+    fn apply_transposes() {
+        let image = DynamicImage::ImageLuma8(
+            crate::GrayImage::from_raw(3, 2, vec![0, 1, 2, 3, 4, 5]).unwrap(),
+        );
+        assert_eq!(apply(image.clone(), 1).as_bytes(), image.as_bytes());
+        assert_eq!(apply(image.clone(), 5).as_bytes(), &[0, 3, 1, 4, 2, 5]);
+        assert_eq!(apply(image.clone(), 7).as_bytes(), &[5, 2, 4, 1, 3, 0]);
+        assert_eq!(apply(image.clone(), 6).as_bytes(), &[3, 0, 4, 1, 5, 2]);
+        assert_eq!(apply(image.clone(), 8).as_bytes(), &[2, 5, 1, 4, 0, 3]);
+        assert_eq!(apply(image.clone(), 2).as_bytes(), &[2, 1, 0, 5, 4, 3]);
+        assert_eq!(apply(image.clone(), 3).as_bytes(), &[5, 4, 3, 2, 1, 0]);
+        assert_eq!(apply(image, 4).as_bytes(), &[3, 4, 5, 0, 1, 2]);
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn png_without_exif() {
+        let mut png = Vec::new();
+        crate::ImageEncoder::write_image(
+            crate::codecs::png::PngEncoder::new(&mut png),
+            &[0; 6],
+            2,
+            1,
+            crate::ColorType::Rgb8,
+        )
+        .unwrap();
+        let orientation = orientation(&mut Cursor::new(png), ImageFormat::Png).unwrap();
+        assert_eq!(orientation, None);
+    }
+}
@@ -1,22 +1,32 @@
+#[allow(unused_imports)] // When no animated formats are supported
+use std::convert::TryFrom;
 use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Seek};
+use std::io::{self, BufRead, BufReader, BufWriter, Cursor, Read, Seek, Write};
+use std::mem::size_of;
 use std::path::Path;
 use std::u32;
 
 use crate::codecs::*;
 
-use crate::dynimage::DynamicImage;
-use crate::error::{ImageError, ImageFormatHint, ImageResult};
-use crate::image;
-use crate::image::ImageFormat;
+use crate::animation::{AnimationOptions, Frame};
+use crate::dynimage::{self, DynamicImage};
+use crate::error::{ImageError, ImageFormatHint, ImageResult, ParameterError, ParameterErrorKind};
+use crate::image::{EncoderOptions, GenericImageView, ImageFormat};
 #[allow(unused_imports)] // When no features are supported
 use crate::image::{ImageDecoder, ImageEncoder};
+use crate::imageops::FilterType;
+use crate::io::allocator::SharedAllocator;
+use crate::io::self_check::SelfChecked;
+use crate::io::Limits;
 use crate::{
     color,
     error::{UnsupportedError, UnsupportedErrorKind},
-    ImageOutputFormat,
+    ImageOutputFormat, Rgb,
 };
 
+/// The color that images are composited over when saved to a format without alpha channel.
+pub(crate) const DEFAULT_MATTE: Rgb<u8> = Rgb([255, 255, 255]);
+
 pub(crate) fn open_impl(path: &Path) -> ImageResult<DynamicImage> {
     let buffered_read = BufReader::new(File::open(path).map_err(ImageError::IoError)?);
 
@@ -34,7 +44,7 @@ pub(crate) fn open_impl(path: &Path) -> ImageResult<DynamicImage> {
 #[allow(unused_variables)]
 // r is unused if no features are supported.
 pub fn load<R: BufRead + Seek>(r: R, format: ImageFormat) -> ImageResult<DynamicImage> {
-    load_inner(r, super::Limits::default(), format)
+    load_inner(r, Limits::default(), format)
 }
 
 pub(crate) trait DecoderVisitor {
@@ -42,148 +52,217 @@ pub(crate) trait DecoderVisitor {
     fn visit_decoder<'a, D: ImageDecoder<'a>>(self, decoder: D) -> ImageResult<Self::Result>;
 }
 
+/// Constructs the decoder of `format` and passes it to `visitor`.
+///
+/// Decoders that need the limits before they read the header, such as those that bound the
+/// buffers of the underlying crates, are constructed with `limits`. The visitor still passes the
+/// limits to [`ImageDecoder::set_limits`]. The decoders read through a [`CancellableReader`], so
+/// decoding is aborted once it is cancelled.
 pub(crate) fn load_decoder<R: BufRead + Seek, V: DecoderVisitor>(
     r: R,
     format: ImageFormat,
+    limits: Limits,
     visitor: V,
 ) -> ImageResult<V::Result> {
-    #[allow(unreachable_patterns)]
-    // Default is unreachable if all features are supported.
-    match format {
-        #[cfg(feature = "avif-decoder")]
-        image::ImageFormat::Avif => visitor.visit_decoder(avif::AvifDecoder::new(r)?),
-        #[cfg(feature = "png")]
-        image::ImageFormat::Png => visitor.visit_decoder(png::PngDecoder::new(r)?),
-        #[cfg(feature = "gif")]
-        image::ImageFormat::Gif => visitor.visit_decoder(gif::GifDecoder::new(r)?),
-        #[cfg(feature = "jpeg")]
-        image::ImageFormat::Jpeg => visitor.visit_decoder(jpeg::JpegDecoder::new(r)?),
-        #[cfg(feature = "webp")]
-        image::ImageFormat::WebP => visitor.visit_decoder(webp::WebPDecoder::new(r)?),
-        #[cfg(feature = "tiff")]
-        image::ImageFormat::Tiff => visitor.visit_decoder(tiff::TiffDecoder::new(r)?),
-        #[cfg(feature = "tga")]
-        image::ImageFormat::Tga => visitor.visit_decoder(tga::TgaDecoder::new(r)?),
-        #[cfg(feature = "dds")]
-        image::ImageFormat::Dds => visitor.visit_decoder(dds::DdsDecoder::new(r)?),
-        #[cfg(feature = "bmp")]
-        image::ImageFormat::Bmp => visitor.visit_decoder(bmp::BmpDecoder::new(r)?),
-        #[cfg(feature = "ico")]
-        image::ImageFormat::Ico => visitor.visit_decoder(ico::IcoDecoder::new(r)?),
-        #[cfg(feature = "hdr")]
-        image::ImageFormat::Hdr => visitor.visit_decoder(hdr::HdrAdapter::new(BufReader::new(r))?),
-        #[cfg(feature = "openexr")]
-        image::ImageFormat::OpenExr => visitor.visit_decoder(openexr::OpenExrDecoder::new(r)?),
-        #[cfg(feature = "pnm")]
-        image::ImageFormat::Pnm => visitor.visit_decoder(pnm::PnmDecoder::new(r)?),
-        #[cfg(feature = "farbfeld")]
-        image::ImageFormat::Farbfeld => visitor.visit_decoder(farbfeld::FarbfeldDecoder::new(r)?),
-        _ => Err(ImageError::Unsupported(
-            ImageFormatHint::Exact(format).into(),
-        )),
-    }
+    let r = CancellableReader::new(r, &limits);
+    let cancellation = limits.clone();
+    let result = visit_format_decoder(r, format, limits, visitor);
+    // Decoders report the failed read in errors of their own, report why it failed instead.
+    result.map_err(|err| cancellation.check_cancelled().err().unwrap_or(err))
 }
 
-#[cfg(feature = "async")]
-pub(crate) async fn load_decoder_async<R: tokio::io::AsyncBufRead + tokio::io::AsyncSeek, V: DecoderVisitor>(
+fn visit_format_decoder<R: BufRead + Seek, V: DecoderVisitor>(
     r: R,
     format: ImageFormat,
+    limits: Limits,
     visitor: V,
 ) -> ImageResult<V::Result> {
     #[allow(unreachable_patterns)]
     // Default is unreachable if all features are supported.
     match format {
         #[cfg(feature = "avif-decoder")]
-        image::ImageFormat::Avif => visitor.visit_decoder(avif::AvifDecoder::new(r)?),
+        ImageFormat::Avif => visitor.visit_decoder(avif::AvifDecoder::with_limits(r, limits)?),
         #[cfg(feature = "png")]
-        image::ImageFormat::Png => visitor.visit_decoder(png::PngDecoder::new(r)?),
+        ImageFormat::Png => visitor.visit_decoder(png::PngDecoder::with_limits(r, limits)?),
         #[cfg(feature = "gif")]
-        image::ImageFormat::Gif => visitor.visit_decoder(gif::GifDecoder::new(r)?),
+        ImageFormat::Gif => visitor.visit_decoder(gif::GifDecoder::with_limits(r, limits)?),
         #[cfg(feature = "jpeg")]
-        image::ImageFormat::Jpeg => visitor.visit_decoder(jpeg::JpegDecoder::new(r)?),
+        ImageFormat::Jpeg => visitor.visit_decoder(jpeg::JpegDecoder::new(r)?),
         #[cfg(feature = "webp")]
-        image::ImageFormat::WebP => visitor.visit_decoder(webp::WebPDecoder::new(r)?),
+        ImageFormat::WebP => visitor.visit_decoder(webp::WebPDecoder::with_limits(r, limits)?),
         #[cfg(feature = "tiff")]
-        image::ImageFormat::Tiff => visitor.visit_decoder(tiff::TiffDecoder::new(r)?),
+        ImageFormat::Tiff => visitor.visit_decoder(tiff::TiffDecoder::with_limits(r, limits)?),
         #[cfg(feature = "tga")]
-        image::ImageFormat::Tga => visitor.visit_decoder(tga::TgaDecoder::new(r)?),
+        ImageFormat::Tga => visitor.visit_decoder(tga::TgaDecoder::new(r)?),
         #[cfg(feature = "dds")]
-        image::ImageFormat::Dds => visitor.visit_decoder(dds::DdsDecoder::new(r)?),
+        ImageFormat::Dds => visitor.visit_decoder(dds::DdsDecoder::new(r)?),
         #[cfg(feature = "bmp")]
-        image::ImageFormat::Bmp => visitor.visit_decoder(bmp::BmpDecoder::new(r)?),
+        ImageFormat::Bmp => visitor.visit_decoder(bmp::BmpDecoder::new(r)?),
         #[cfg(feature = "ico")]
-        image::ImageFormat::Ico => visitor.visit_decoder(ico::IcoDecoder::new(r)?),
+        ImageFormat::Ico => visitor.visit_decoder(ico::IcoDecoder::with_limits(r, limits)?),
+        #[cfg(feature = "icns")]
+        ImageFormat::Icns => visitor.visit_decoder(icns::IcnsDecoder::with_limits(r, limits)?),
         #[cfg(feature = "hdr")]
-        image::ImageFormat::Hdr => visitor.visit_decoder(hdr::HdrAdapter::new(BufReader::new(r))?),
+        ImageFormat::Hdr => visitor.visit_decoder(hdr::HdrAdapter::new(BufReader::new(r))?),
         #[cfg(feature = "openexr")]
-        image::ImageFormat::OpenExr => visitor.visit_decoder(openexr::OpenExrDecoder::new(r)?),
+        ImageFormat::OpenExr => visitor.visit_decoder(openexr::OpenExrDecoder::new(r)?),
         #[cfg(feature = "pnm")]
-        image::ImageFormat::Pnm => visitor.visit_decoder(pnm::PnmDecoder::new(r)?),
+        ImageFormat::Pnm => visitor.visit_decoder(pnm::PnmDecoder::new(r)?),
         #[cfg(feature = "farbfeld")]
-        image::ImageFormat::Farbfeld => visitor.visit_decoder(farbfeld::FarbfeldDecoder::new(r)?),
+        ImageFormat::Farbfeld => visitor.visit_decoder(farbfeld::FarbfeldDecoder::new(r)?),
+        #[cfg(feature = "fits")]
+        ImageFormat::Fits => visitor.visit_decoder(fits::FitsDecoder::new(r)?),
+        #[cfg(feature = "dicom")]
+        ImageFormat::Dicom => visitor.visit_decoder(dicom::DicomDecoder::with_limits(r, limits)?),
+        #[cfg(feature = "jpegxs")]
+        ImageFormat::JpegXs => visitor.visit_decoder(jpegxs::JpegXsDecoder::new(r)?),
+        #[cfg(feature = "jxl")]
+        ImageFormat::Jxl => visitor.visit_decoder(jxl::JxlDecoder::new(r)?),
+        #[cfg(feature = "heif")]
+        ImageFormat::Heif => visitor.visit_decoder(heif::HeifDecoder::new(r)?),
+        #[cfg(feature = "svg")]
+        ImageFormat::Svg => visitor.visit_decoder(svg::SvgDecoder::new(r)?),
+        #[cfg(feature = "qoi")]
+        ImageFormat::Qoi => visitor.visit_decoder(qoi::QoiDecoder::new(r)?),
         _ => Err(ImageError::Unsupported(
             ImageFormatHint::Exact(format).into(),
         )),
     }
 }
 
+/// The number of reads between checks for cancellation, as reading the clock takes a moment.
+/// Reads of whole buffers and seeks are always checked.
+const CANCEL_CHECK_INTERVAL: u32 = 64;
 
-pub(crate) fn load_inner<R: BufRead + Seek>(
-    r: R,
-    limits: super::Limits,
-    format: ImageFormat,
-) -> ImageResult<DynamicImage> {
-    struct LoadVisitor(super::Limits);
-
-    impl DecoderVisitor for LoadVisitor {
-        type Result = DynamicImage;
-
-        fn visit_decoder<'a, D: ImageDecoder<'a>>(
-            self,
-            mut decoder: D,
-        ) -> ImageResult<Self::Result> {
-            let mut limits = self.0;
-            // Check that we do not allocate a bigger buffer than we are allowed to
-            // FIXME: should this rather go in `DynamicImage::from_decoder` somehow?
-            limits.reserve(decoder.total_bytes())?;
-            decoder.set_limits(limits)?;
-            DynamicImage::from_decoder(decoder)
+/// A reader that fails once decoding was cancelled with the limits, or their deadline passed.
+///
+/// Decoders read their input as they decode, so this aborts them without checks of their own.
+struct CancellableReader<R> {
+    inner: R,
+    /// The limits with the deadline and the cancel token, if there are any.
+    limits: Option<Limits>,
+    unchecked_reads: u32,
+}
+
+impl<R> CancellableReader<R> {
+    fn new(inner: R, limits: &Limits) -> Self {
+        CancellableReader {
+            inner,
+            limits: Some(limits.clone()).filter(Limits::is_cancellable),
+            unchecked_reads: 0,
         }
     }
 
-    load_decoder(r, format, LoadVisitor(limits))
+    fn check(&mut self, always: bool) -> io::Result<()> {
+        let limits = match &self.limits {
+            Some(limits) => limits,
+            None => return Ok(()),
+        };
+        self.unchecked_reads += 1;
+        if !always && self.unchecked_reads < CANCEL_CHECK_INTERVAL {
+            return Ok(());
+        }
+        self.unchecked_reads = 0;
+        limits
+            .check_cancelled()
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+    }
 }
 
-pub(crate) fn image_dimensions_impl(path: &Path) -> ImageResult<(u32, u32)> {
-    let format = image::ImageFormat::from_path(path)?;
-    let reader = BufReader::new(File::open(path)?);
-    image_dimensions_with_format_impl(reader, format)
+impl<R: Read> Read for CancellableReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.check(buf.len() >= 4096)?;
+        self.inner.read(buf)
+    }
 }
 
-#[allow(unused_variables)]
-// fin is unused if no features are supported.
-pub(crate) fn image_dimensions_with_format_impl<R: BufRead + Seek>(
-    buffered_read: R,
-    format: ImageFormat,
-) -> ImageResult<(u32, u32)> {
-    struct DimVisitor;
+impl<R: BufRead> BufRead for CancellableReader<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.check(false)?;
+        self.inner.fill_buf()
+    }
 
-    impl DecoderVisitor for DimVisitor {
-        type Result = (u32, u32);
-        fn visit_decoder<'a, D: ImageDecoder<'a>>(self, decoder: D) -> ImageResult<Self::Result> {
-            Ok(decoder.dimensions())
+    fn consume(&mut self, amt: usize) {
+        self.inner.consume(amt);
+    }
+}
+
+impl<R: Seek> Seek for CancellableReader<R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.check(true)?;
+        self.inner.seek(pos)
+    }
+}
+
+/// Decodes an image within the given limits, into buffers of the allocator if there is one.
+pub(crate) struct LoadVisitor {
+    pub(crate) limits: Limits,
+    pub(crate) allocator: Option<SharedAllocator>,
+}
+
+impl LoadVisitor {
+    pub(crate) fn new(limits: Limits) -> Self {
+        LoadVisitor {
+            limits,
+            allocator: None,
+        }
+    }
+}
+
+impl DecoderVisitor for LoadVisitor {
+    type Result = DynamicImage;
+
+    fn visit_decoder<'a, D: ImageDecoder<'a>>(self, mut decoder: D) -> ImageResult<Self::Result> {
+        let mut limits = self.limits;
+        let (width, height) = decoder.dimensions();
+        let bytes_per_pixel = u64::from(decoder.color_type().bytes_per_pixel());
+        let downscaled = limits.downscaled_dimensions(width, height, bytes_per_pixel);
+        if let Some((width, height)) = downscaled {
+            decoder.request_dimensions(width, height);
+            // The dimension limits apply to the downscaled image.
+            limits.max_image_width = None;
+            limits.max_image_height = None;
+        }
+
+        // Check that we do not allocate a bigger buffer than we are allowed to
+        // FIXME: should this rather go in `DynamicImage::from_decoder` somehow?
+        limits.reserve(decoder.total_bytes())?;
+        limits.check_cancelled()?;
+        decoder.set_limits(limits.clone())?;
+        let image = match &self.allocator {
+            Some(allocator) => dynimage::decoder_to_image_in(decoder, &*allocator.0)?,
+            None => DynamicImage::from_decoder(decoder)?,
+        };
+        limits.check_cancelled()?;
+        match downscaled {
+            Some((width, height)) if image.dimensions() != (width, height) => {
+                Ok(image.resize_exact(width, height, FilterType::Triangle))
+            }
+            _ => Ok(image),
         }
     }
+}
+
+pub(crate) fn load_inner<R: BufRead + Seek>(
+    r: R,
+    limits: Limits,
+    format: ImageFormat,
+) -> ImageResult<DynamicImage> {
+    load_decoder(r, format, limits.clone(), LoadVisitor::new(limits))
+}
 
-    load_decoder(buffered_read, format, DimVisitor)
+pub(crate) fn image_dimensions_impl(path: &Path) -> ImageResult<(u32, u32)> {
+    let format = ImageFormat::from_path(path)?;
+    let reader = BufReader::new(File::open(path)?);
+    image_dimensions_with_format_impl(reader, format, Limits::default())
 }
 
-#[cfg(feature = "async")]
 #[allow(unused_variables)]
 // fin is unused if no features are supported.
-pub(crate) async fn image_dimensions_with_format_impl_async<R: tokio::io::AsyncBufRead + tokio::io::AsyncSeek>(
+pub(crate) fn image_dimensions_with_format_impl<R: BufRead + Seek>(
     buffered_read: R,
     format: ImageFormat,
+    limits: Limits,
 ) -> ImageResult<(u32, u32)> {
     struct DimVisitor;
 
@@ -194,10 +273,9 @@ pub(crate) async fn image_dimensions_with_format_impl_async<R: tokio::io::AsyncB
         }
     }
 
-    load_decoder(buffered_read, format, DimVisitor)
+    load_decoder(buffered_read, format, limits, DimVisitor)
 }
 
-
 #[allow(unused_variables)]
 // Most variables when no features are supported
 pub(crate) fn save_buffer_impl(
@@ -206,26 +284,24 @@ pub(crate) fn save_buffer_impl(
     width: u32,
     height: u32,
     color: color::ColorType,
+    matte: Rgb<u8>,
+    icc_profile: Option<&[u8]>,
 ) -> ImageResult<()> {
     let format = ImageFormat::from_path(path)?;
-    save_buffer_with_format_impl(path, buf, width, height, color, format)
+    save_buffer_with_format_impl(path, buf, width, height, color, format, matte, icc_profile)
 }
 
+/// The output format for a file of `format` at `path`, with the PNM subtype chosen by the
+/// extension.
 #[allow(unused_variables)]
-// Most variables when no features are supported
-pub(crate) fn save_buffer_with_format_impl(
+// Path is unused if no features are supported
+pub(crate) fn output_format_for_path(
     path: &Path,
-    buf: &[u8],
-    width: u32,
-    height: u32,
-    color: color::ColorType,
     format: ImageFormat,
-) -> ImageResult<()> {
-    let buffered_file_write = &mut BufWriter::new(File::create(path)?); // always seekable
-
-    let format = match format {
+) -> ImageResult<ImageOutputFormat> {
+    Ok(match format {
         #[cfg(feature = "pnm")]
-        image::ImageFormat::Pnm => {
+        ImageFormat::Pnm => {
             let ext = path
                 .extension()
                 .and_then(|s| s.to_str())
@@ -243,80 +319,820 @@ pub(crate) fn save_buffer_with_format_impl(
             })
         }
         // #[cfg(feature = "hdr")]
-        // image::ImageFormat::Hdr => hdr::HdrEncoder::new(fout).encode(&[Rgb<f32>], width, height), // usize
+        // ImageFormat::Hdr => hdr::HdrEncoder::new(fout).encode(&[Rgb<f32>], width, height), // usize
         format => format.into(),
-    };
+    })
+}
 
-    write_buffer_impl(buffered_file_write, buf, width, height, color, format)
+/// Save a buffer, embedding `icc_profile` if the encoder of the format can store one.
+#[allow(unused_variables, clippy::too_many_arguments)]
+// Most variables when no features are supported
+pub(crate) fn save_buffer_with_format_impl(
+    path: &Path,
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    color: color::ColorType,
+    format: ImageFormat,
+    matte: Rgb<u8>,
+    icc_profile: Option<&[u8]>,
+) -> ImageResult<()> {
+    let buffered_file_write = &mut Seekable(BufWriter::new(File::create(path)?));
+    let format = output_format_for_path(path, format)?;
+    let icc_profile = embeddable_icc_profile(icc_profile, &format);
+
+    write_buffer_with_icc_profile_impl(
+        buffered_file_write,
+        buf,
+        width,
+        height,
+        color,
+        format,
+        matte,
+        icc_profile,
+    )
+}
+
+/// Returns the ICC profile of an image if the encoder of `format` can embed it.
+///
+/// The profile of a decoded image is written again where it can be stored, while images are
+/// still saved in formats that have no place for it.
+pub(crate) fn embeddable_icc_profile<'a>(
+    icc_profile: Option<&'a [u8]>,
+    format: &ImageOutputFormat,
+) -> Option<&'a [u8]> {
+    let icc_profile = icc_profile?;
+    let mut output = Seekable(Cursor::new(Vec::new()));
+    let mut encoder = boxed_encoder(&mut output, format.clone()).ok()?;
+    match encoder.set_icc_profile(Vec::new()) {
+        Ok(()) => Some(icc_profile),
+        Err(_) => None,
+    }
 }
 
 #[allow(unused_variables)]
-// Most variables when no features are supported
-pub(crate) fn write_buffer_impl<W: std::io::Write + Seek>(
+// Most variables when no animated formats are supported
+pub(crate) fn save_animation_impl(
+    path: &Path,
+    frames: &mut dyn Iterator<Item = Frame>,
+    options: &AnimationOptions,
+) -> ImageResult<()> {
+    let format = ImageFormat::from_path(path)?;
+    let mut frames = frames.peekable();
+    if frames.peek().is_none() {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::NoMoreData,
+        )));
+    }
+
+    match format {
+        #[cfg(feature = "gif")]
+        ImageFormat::Gif => {
+            let speed = options.quantization_speed.unwrap_or(1);
+            if !(1..=30).contains(&speed) {
+                return Err(ImageError::Parameter(ParameterError::from_kind(
+                    ParameterErrorKind::Generic(format!(
+                        "quantization speed {} is not in the range [1, 30]",
+                        speed
+                    )),
+                )));
+            }
+            let repeat = match options.loop_count {
+                0 => gif::Repeat::Infinite,
+                n => gif::Repeat::Finite(u16::try_from(n).map_err(|_| {
+                    ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                        format!("a GIF can not be played {} times", n),
+                    )))
+                })?),
+            };
+
+            let file = BufWriter::new(File::create(path)?);
+            let mut encoder = gif::GifEncoder::new_with_speed(file, speed);
+            encoder.set_repeat(repeat)?;
+            encoder.encode_frames(frames)
+        }
+        #[cfg(feature = "png")]
+        ImageFormat::Png => {
+            let file = BufWriter::new(File::create(path)?);
+            let mut encoder = png::ApngEncoder::new(file);
+            encoder.set_loop_count(options.loop_count);
+            encoder.encode_frames(frames)?;
+            encoder.finish()
+        }
+        #[cfg(feature = "webp")]
+        ImageFormat::WebP => {
+            let loop_count = u16::try_from(options.loop_count).map_err(|_| {
+                ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                    format!(
+                        "a WebP animation can not be played {} times",
+                        options.loop_count
+                    ),
+                )))
+            })?;
+
+            let file = BufWriter::new(File::create(path)?);
+            let mut encoder = webp::WebPAnimationEncoder::new(file);
+            encoder.set_loop_count(loop_count);
+            encoder.encode_frames(frames)?;
+            encoder.finish()?.flush()?;
+            Ok(())
+        }
+        format => Err(ImageError::Unsupported(
+            ImageFormatHint::Exact(format).into(),
+        )),
+    }
+}
+
+/// Encode a buffer, compositing it over `matte` first if the format can not store its alpha.
+pub(crate) fn write_buffer_impl<W: EncoderOutput>(
     buffered_write: &mut W,
     buf: &[u8],
     width: u32,
     height: u32,
     color: color::ColorType,
     format: ImageOutputFormat,
+    matte: Rgb<u8>,
 ) -> ImageResult<()> {
+    write_buffer_with_icc_profile_impl(
+        buffered_write,
+        buf,
+        width,
+        height,
+        color,
+        format,
+        matte,
+        None,
+    )
+}
+
+/// Encode a buffer like `write_buffer_impl`, embedding `icc_profile` if there is one.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn write_buffer_with_icc_profile_impl<W: EncoderOutput>(
+    buffered_write: &mut W,
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    color: color::ColorType,
+    format: ImageOutputFormat,
+    matte: Rgb<u8>,
+    icc_profile: Option<&[u8]>,
+) -> ImageResult<()> {
+    match flatten_for_format(buf, width, height, color, &format, matte) {
+        Some((flat, flat_color)) => encode_buffer_impl(
+            buffered_write,
+            &flat,
+            width,
+            height,
+            flat_color,
+            format,
+            icc_profile,
+            super::encoder_self_check(),
+        ),
+        None => encode_buffer_impl(
+            buffered_write,
+            buf,
+            width,
+            height,
+            color,
+            format,
+            icc_profile,
+            super::encoder_self_check(),
+        ),
+    }
+}
+
+/// Encode a buffer, checking the result first if `self_check` is set.
+#[allow(clippy::too_many_arguments)]
+fn encode_buffer_impl<W: EncoderOutput>(
+    buffered_write: &mut W,
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    color: color::ColorType,
+    format: ImageOutputFormat,
+    icc_profile: Option<&[u8]>,
+    self_check: bool,
+) -> ImageResult<()> {
+    let mut encoder = checked_encoder(buffered_write, format, self_check)?;
+    if let Some(icc_profile) = icc_profile {
+        encoder.set_icc_profile(icc_profile.to_vec())?;
+    }
+    encoder.write_image(buf, width, height, color)
+}
+
+/// Create an encoder for `format` that writes to `writer`.
+///
+/// The encoder is returned as a trait object, which allows pipelines and plugins that select the
+/// format at runtime to hold and pass around encoders without naming every codec type. Options
+/// that do not apply to `format` are ignored. Formats that need a seekable writer, such as TIFF,
+/// are encoded into memory first, which fails if it exceeds `options.limits`. The output is
+/// checked as described in [`set_encoder_self_check`], unless `options.self_check` says otherwise.
+///
+/// # Examples
+///
+/// ```no_run
+/// # #[cfg(feature = "jpeg")]
+/// # fn main() -> image::ImageResult<()> {
+/// use image::{ColorType, EncoderOptions, ImageEncoder, ImageFormat};
+///
+/// let mut options = EncoderOptions::default();
+/// options.quality = Some(90);
+/// let file = std::fs::File::create("out.jpg")?;
+/// let encoder = image::encoder_for(ImageFormat::Jpeg, file, options)?;
+/// encoder.write_image(&[255, 0, 0], 1, 1, ColorType::Rgb8)?;
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "jpeg"))]
+/// # fn main() {}
+/// ```
+///
+/// [`set_encoder_self_check`]: io/fn.set_encoder_self_check.html
+pub fn encoder_for<'a, W: Write + 'a>(
+    format: ImageFormat,
+    writer: W,
+    options: EncoderOptions,
+) -> ImageResult<Box<dyn ImageEncoder + 'a>> {
+    let format = options.output_format(format);
+    let self_check = options.self_check.unwrap_or_else(super::encoder_self_check);
+    let writer = Stream {
+        writer,
+        limits: options.limits,
+    };
+    checked_encoder(writer, format, self_check)
+}
+
+/// The side of the blocks that are sampled to estimate the size of a compressed image.
+const ESTIMATE_BLOCK: u32 = 64;
+/// The number of sampled blocks across and down the image.
+const ESTIMATE_GRID: u32 = 6;
+
+/// Estimate the size in bytes of `image` encoded as `format` with `options`.
+///
+/// This allows picking a format or a quality without encoding the image several times. Formats
+/// that compress, such as JPEG, PNG, WebP and AVIF, are estimated by encoding a sample of blocks
+/// spread over the image, which is much faster for large images but only approximate: images
+/// whose detail is concentrated in a few areas may be off by a fifth or more. Other formats are
+/// encoded in full without keeping the output, so their size is exact.
+///
+/// Images with an alpha channel are composited over white for formats that can not store it, as
+/// in [`DynamicImage::write_to`].
+///
+/// # Examples
+///
+/// ```
+/// # #[cfg(feature = "jpeg")]
+/// # fn main() -> image::ImageResult<()> {
+/// use image::{DynamicImage, EncoderOptions, ImageFormat};
+///
+/// let image = DynamicImage::new_rgb8(1024, 768);
+/// let mut options = EncoderOptions::default();
+/// options.quality = Some(80);
+/// let size = image::estimate_encoded_size(&image, ImageFormat::Jpeg, options)?;
+/// assert!(size < 1024 * 768 * 3);
+/// # Ok(())
+/// # }
+/// # #[cfg(not(feature = "jpeg"))]
+/// # fn main() {}
+/// ```
+///
+/// [`DynamicImage::write_to`]: enum.DynamicImage.html#method.write_to
+pub fn estimate_encoded_size(
+    image: &DynamicImage,
+    format: ImageFormat,
+    options: EncoderOptions,
+) -> ImageResult<u64> {
+    let format = options.output_format(format);
+    let (width, height) = image.dimensions();
+    let color = image.color();
+    let encoded_size = |buf: &[u8], width: u32, height: u32| -> ImageResult<u64> {
+        let mut counter = Stream {
+            writer: ByteCounter(0),
+            limits: options.limits.clone(),
+        };
+        write_buffer_impl(
+            &mut counter,
+            buf,
+            width,
+            height,
+            color,
+            format.clone(),
+            DEFAULT_MATTE,
+        )?;
+        Ok(counter.writer.0)
+    };
+
+    let columns = ESTIMATE_GRID.min(width / ESTIMATE_BLOCK);
+    let rows = ESTIMATE_GRID.min(height / ESTIMATE_BLOCK);
+    let sample_pixels = u64::from(columns * rows * ESTIMATE_BLOCK * ESTIMATE_BLOCK);
+    let pixels = u64::from(width) * u64::from(height);
+    // Sampling only pays off if the sample is much smaller than the image.
+    if !is_compressed(&format) || sample_pixels == 0 || pixels <= 4 * sample_pixels {
+        return encoded_size(image.as_bytes(), width, height);
+    }
+
+    // A mosaic of blocks centered in the cells of a grid over the image. The offsets are
+    // multiples of 16, so the blocks keep the alignment of the macroblocks of the codecs.
+    let pixel_bytes = usize::from(color.bytes_per_pixel());
+    let bytes = image.as_bytes();
+    let block_row_bytes = ESTIMATE_BLOCK as usize * pixel_bytes;
+    let (mosaic_width, mosaic_height) = (columns * ESTIMATE_BLOCK, rows * ESTIMATE_BLOCK);
+    let mut mosaic = Vec::with_capacity(sample_pixels as usize * pixel_bytes);
+    let block_offset = |cell: u32, cells: u32, len: u32| {
+        let center = u64::from(2 * cell + 1) * u64::from(len) / u64::from(2 * cells);
+        let offset = (center as u32).saturating_sub(ESTIMATE_BLOCK / 2);
+        offset.min(len - ESTIMATE_BLOCK) & !15
+    };
+    for row in 0..rows {
+        let y = block_offset(row, rows, height) as usize;
+        for line in y..y + ESTIMATE_BLOCK as usize {
+            for column in 0..columns {
+                let x = block_offset(column, columns, width) as usize;
+                let start = (line * width as usize + x) * pixel_bytes;
+                mosaic.extend_from_slice(&bytes[start..][..block_row_bytes]);
+            }
+        }
+    }
+
+    // Fit the size as a fixed overhead plus a cost per pixel, measured with the mosaic and with
+    // the top left quarter of its first block.
+    let small = ESTIMATE_BLOCK / 2;
+    let small_block: Vec<u8> = mosaic
+        .chunks_exact(mosaic_width as usize * pixel_bytes)
+        .take(small as usize)
+        .flat_map(|line| &line[..small as usize * pixel_bytes])
+        .copied()
+        .collect();
+    let small_size = encoded_size(&small_block, small, small)?;
+    let mosaic_size = encoded_size(&mosaic, mosaic_width, mosaic_height)?;
+
+    let small_pixels = u64::from(small * small);
+    let per_pixel =
+        mosaic_size.saturating_sub(small_size) as f64 / (sample_pixels - small_pixels) as f64;
+    let overhead = (small_size as f64 - per_pixel * small_pixels as f64).max(0.0);
+    Ok((overhead + per_pixel * pixels as f64).round() as u64)
+}
+
+/// Whether the size of images encoded as `format` depends on their content.
+fn is_compressed(format: &ImageOutputFormat) -> bool {
     match format {
         #[cfg(feature = "png")]
-        ImageOutputFormat::Png => {
-            png::PngEncoder::new(buffered_write).write_image(buf, width, height, color)
-        }
+        ImageOutputFormat::Png => true,
+        #[cfg(feature = "jpeg")]
+        ImageOutputFormat::Jpeg(_) => true,
+        #[cfg(feature = "gif")]
+        ImageOutputFormat::Gif => true,
+        #[cfg(feature = "avif-encoder")]
+        ImageOutputFormat::Avif | ImageOutputFormat::AvifWithOptions(_) => true,
+        #[cfg(feature = "webp")]
+        ImageOutputFormat::WebP => true,
+        #[cfg(feature = "jxl")]
+        ImageOutputFormat::Jxl => true,
+        _ => false,
+    }
+}
+
+/// A writer that only counts the bytes written to it.
+struct ByteCounter(u64);
+
+impl Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0 += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Create an encoder for `format`, which checks its output before writing it if `self_check` is
+/// set.
+pub(crate) fn checked_encoder<'a, W: EncoderOutput + 'a>(
+    w: W,
+    format: ImageOutputFormat,
+    self_check: bool,
+) -> ImageResult<Box<dyn ImageEncoder + 'a>> {
+    if self_check {
+        Ok(Box::new(SelfChecked::new(w, format)?))
+    } else {
+        boxed_encoder(w, format)
+    }
+}
+
+#[allow(unused_variables)]
+// Most variables when no features are supported
+pub(crate) fn boxed_encoder<'a, W: EncoderOutput + 'a>(
+    w: W,
+    format: ImageOutputFormat,
+) -> ImageResult<Box<dyn ImageEncoder + 'a>> {
+    Ok(match format {
+        #[cfg(feature = "png")]
+        ImageOutputFormat::Png => Box::new(png::PngEncoder::new(w)),
         #[cfg(feature = "jpeg")]
         ImageOutputFormat::Jpeg(quality) => {
-            jpeg::JpegEncoder::new_with_quality(buffered_write, quality)
-                .write_image(buf, width, height, color)
+            Box::new(jpeg::JpegEncoder::new_with_quality(w, quality))
         }
         #[cfg(feature = "pnm")]
-        ImageOutputFormat::Pnm(subtype) => pnm::PnmEncoder::new(buffered_write)
-            .with_subtype(subtype)
-            .write_image(buf, width, height, color),
+        ImageOutputFormat::Pnm(subtype) => Box::new(pnm::PnmEncoder::new(w).with_subtype(subtype)),
         #[cfg(feature = "gif")]
-        ImageOutputFormat::Gif => {
-            gif::GifEncoder::new(buffered_write).encode(buf, width, height, color)
-        }
+        ImageOutputFormat::Gif => Box::new(gif::GifEncoder::new(w)),
         #[cfg(feature = "ico")]
-        ImageOutputFormat::Ico => {
-            ico::IcoEncoder::new(buffered_write).write_image(buf, width, height, color)
-        }
+        ImageOutputFormat::Ico => Box::new(ico::IcoEncoder::new(w)),
+        #[cfg(feature = "icns")]
+        ImageOutputFormat::Icns => Box::new(icns::IcnsEncoder::new(w)),
         #[cfg(feature = "bmp")]
-        ImageOutputFormat::Bmp => {
-            bmp::BmpEncoder::new(buffered_write).write_image(buf, width, height, color)
-        }
+        ImageOutputFormat::Bmp => Box::new(EncodeWith {
+            writer: w,
+            encode: |w, buf, width, height, color| {
+                bmp::BmpEncoder::new(w).write_image(buf, width, height, color)
+            },
+        }),
         #[cfg(feature = "farbfeld")]
-        ImageOutputFormat::Farbfeld => {
-            farbfeld::FarbfeldEncoder::new(buffered_write).write_image(buf, width, height, color)
-        }
+        ImageOutputFormat::Farbfeld => Box::new(farbfeld::FarbfeldEncoder::new(w)),
         #[cfg(feature = "tga")]
-        ImageOutputFormat::Tga => {
-            tga::TgaEncoder::new(buffered_write).write_image(buf, width, height, color)
-        }
+        ImageOutputFormat::Tga => Box::new(tga::TgaEncoder::new(w)),
         #[cfg(feature = "openexr")]
-        ImageOutputFormat::OpenExr => {
-            openexr::OpenExrEncoder::new(buffered_write).write_image(buf, width, height, color)
-        }
+        ImageOutputFormat::OpenExr => Box::new(EncodeWith {
+            writer: w,
+            encode: |w, buf, width, height, color| {
+                write_seekable(w, buf.len(), |w| {
+                    openexr::OpenExrEncoder::new(w).write_image(buf, width, height, color)
+                })
+            },
+        }),
         #[cfg(feature = "tiff")]
-        ImageOutputFormat::Tiff => {
-            tiff::TiffEncoder::new(buffered_write).write_image(buf, width, height, color)
-        }
+        ImageOutputFormat::Tiff => Box::new(EncodeWith {
+            writer: w,
+            encode: |w, buf, width, height, color| {
+                write_seekable(w, buf.len(), |w| {
+                    tiff::TiffEncoder::new(w).write_image(buf, width, height, color)
+                })
+            },
+        }),
+        #[cfg(feature = "avif-encoder")]
+        ImageOutputFormat::Avif => Box::new(avif::AvifEncoder::new(w)),
         #[cfg(feature = "avif-encoder")]
-        ImageOutputFormat::Avif => {
-            avif::AvifEncoder::new(buffered_write).write_image(buf, width, height, color)
+        ImageOutputFormat::AvifWithOptions(options) => {
+            Box::new(avif::AvifEncoder::new_with_options(w, options))
         }
+        #[cfg(feature = "fits")]
+        ImageOutputFormat::Fits => Box::new(fits::FitsEncoder::new(w)),
+        #[cfg(feature = "webp")]
+        ImageOutputFormat::WebP => Box::new(webp::WebPEncoder::new(w)),
+        #[cfg(feature = "jxl")]
+        ImageOutputFormat::Jxl => Box::new(jxl::JxlEncoder::new(w)),
+        #[cfg(feature = "qoi")]
+        ImageOutputFormat::Qoi => Box::new(qoi::QoiEncoder::new(w)),
 
-        image::ImageOutputFormat::Unsupported(msg) => Err(ImageError::Unsupported(
-            UnsupportedError::from_format_and_kind(
-                ImageFormatHint::Unknown,
-                UnsupportedErrorKind::Format(ImageFormatHint::Name(msg)),
-            ),
-        )),
+        ImageOutputFormat::Unsupported(msg) => {
+            return Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    ImageFormatHint::Unknown,
+                    UnsupportedErrorKind::Format(ImageFormatHint::Name(msg)),
+                ),
+            ))
+        }
+    })
+}
+
+/// An owning encoder for codecs that borrow their writer or need to seek, such as BMP and TIFF.
+#[allow(dead_code)]
+// When no such formats are enabled
+struct EncodeWith<W> {
+    writer: W,
+    encode: fn(&mut W, &[u8], u32, u32, color::ColorType) -> ImageResult<()>,
+}
+
+impl<W: EncoderOutput> ImageEncoder for EncodeWith<W> {
+    fn write_image(
+        mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: color::ColorType,
+    ) -> ImageResult<()> {
+        (self.encode)(&mut self.writer, buf, width, height, color_type)
+    }
+}
+
+/// Composite an image over an opaque `matte` if `format` can not store its alpha channel.
+///
+/// This is the case if the encoder silently drops the alpha channel, as JPEG does, or if it
+/// rejects the color type but accepts it without alpha, as the PPM subtype of PNM does. Returns
+/// the flattened samples and their color type, or `None` if the image should be encoded as is.
+pub(crate) fn flatten_for_format(
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    color: color::ColorType,
+    format: &ImageOutputFormat,
+    matte: Rgb<u8>,
+) -> Option<(Vec<u8>, color::ColorType)> {
+    use color::ColorType::*;
+    let opaque = match color {
+        La8 => L8,
+        La16 => L16,
+        Rgba8 => Rgb8,
+        Rgba16 => Rgb16,
+        Rgba32F => Rgb32F,
+        _ => return None,
+    };
+
+    // Probe the encoder with a single pixel, as `probe_color_type` does.
+    let encodes = |color: color::ColorType| {
+        let pixel = vec![0; usize::from(color.bytes_per_pixel())];
+        let mut encoded = Seekable(Cursor::new(Vec::new()));
+        encode_buffer_impl(
+            &mut encoded,
+            &pixel,
+            1,
+            1,
+            color,
+            format.clone(),
+            None,
+            false,
+        )
+        .ok()
+        .map(|_| encoded.0.into_inner())
+    };
+    let drops_alpha = match encodes(color) {
+        Some(encoded) => guess_format_impl(&encoded)
+            .and_then(|format| load(Cursor::new(encoded), format).ok())
+            .map_or(false, |image| !image.color().has_alpha()),
+        None => encodes(opaque).is_some(),
+    };
+    if !drops_alpha {
+        return None;
+    }
+
+    let image = image_from_bytes(buf, width, height, color)?;
+    let [r, g, b] = matte.0;
+    let matte = [
+        f32::from(r) / 255.0,
+        f32::from(g) / 255.0,
+        f32::from(b) / 255.0,
+    ];
+    let mut rgba = image.into_rgba32f();
+    for pixel in rgba.pixels_mut() {
+        let alpha = pixel[3];
+        for (c, m) in pixel.0.iter_mut().zip(&matte) {
+            *c = *c * alpha + m * (1.0 - alpha);
+        }
+    }
+    let rgb = DynamicImage::ImageRgb32F(DynamicImage::ImageRgba32F(rgba).into_rgb32f());
+    let flat = match opaque {
+        L8 => DynamicImage::ImageLuma8(rgb.into_luma8()),
+        L16 => DynamicImage::ImageLuma16(rgb.into_luma16()),
+        Rgb8 => DynamicImage::ImageRgb8(rgb.into_rgb8()),
+        Rgb16 => DynamicImage::ImageRgb16(rgb.into_rgb16()),
+        _ => rgb,
+    };
+    Some((flat.as_bytes().to_vec(), opaque))
+}
+
+/// Copy a buffer of samples into an image, or `None` if it does not match the dimensions.
+pub(crate) fn image_from_bytes(
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    color: color::ColorType,
+) -> Option<DynamicImage> {
+    fn samples<T: bytemuck::Pod + num_traits::Zero>(buf: &[u8]) -> Vec<T> {
+        let mut samples = vec![T::zero(); buf.len() / size_of::<T>()];
+        let len = samples.len() * size_of::<T>();
+        bytemuck::cast_slice_mut(&mut samples).copy_from_slice(&buf[..len]);
+        samples
+    }
+
+    use crate::ImageBuffer;
+    use color::ColorType::*;
+    Some(match color {
+        L8 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, buf.to_vec())?),
+        La8 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, buf.to_vec())?),
+        Rgb8 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, buf.to_vec())?),
+        Rgba8 => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, buf.to_vec())?),
+        L16 => DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, height, samples(buf))?),
+        La16 => DynamicImage::ImageLumaA16(ImageBuffer::from_raw(width, height, samples(buf))?),
+        Rgb16 => DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, height, samples(buf))?),
+        Rgba16 => DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, samples(buf))?),
+        Rgb32F => DynamicImage::ImageRgb32F(ImageBuffer::from_raw(width, height, samples(buf))?),
+        Rgba32F => DynamicImage::ImageRgba32F(ImageBuffer::from_raw(width, height, samples(buf))?),
+    })
+}
+
+/// Run an encoder that needs to seek on `w`, or on an in-memory buffer if `w` can not seek.
+///
+/// This lets such formats be written to any stream, such as a socket or a compressor. The
+/// buffer is accounted against the limits of the stream using `estimate` as its expected size,
+/// and its content copied to `w` once the encoder is done.
+#[allow(dead_code)]
+// When no formats that need seeking are enabled
+fn write_seekable<W, F>(w: &mut W, estimate: usize, encode: F) -> ImageResult<()>
+where
+    W: EncoderOutput,
+    F: FnOnce(&mut dyn WriteSeek) -> ImageResult<()>,
+{
+    let mut limits = match w.seekable() {
+        Ok(w) => return encode(w),
+        Err(limits) => limits.clone(),
+    };
+    limits.reserve(estimate as u64)?;
+
+    let mut buffer = Cursor::new(Vec::with_capacity(estimate));
+    encode(&mut buffer)?;
+    w.write_all(buffer.get_ref())?;
+    Ok(())
+}
+
+/// A writer that encoders can seek in.
+pub(crate) trait WriteSeek: Write + Seek {}
+
+impl<W: Write + Seek + ?Sized> WriteSeek for W {}
+
+/// Where an encoded image is written, and whether encoders can seek there.
+///
+/// Generic code can not tell if a writer implements `Seek`, so the callers of the encoding
+/// functions state it by wrapping their writer in a [`Stream`] or a [`Seekable`].
+pub(crate) trait EncoderOutput: Write {
+    /// This writer if encoders can seek in it, otherwise the limits on buffering their output.
+    fn seekable(&mut self) -> Result<&mut dyn WriteSeek, &Limits>;
+}
+
+impl<W: EncoderOutput + ?Sized> EncoderOutput for &mut W {
+    fn seekable(&mut self) -> Result<&mut dyn WriteSeek, &Limits> {
+        (**self).seekable()
+    }
+}
+
+/// A writer that can only be written in order, such as a socket or a compressor.
+///
+/// Encoders that need to seek write into memory first, which fails if it exceeds `limits`.
+pub(crate) struct Stream<W> {
+    pub(crate) writer: W,
+    pub(crate) limits: Limits,
+}
+
+impl<W: Write> Stream<W> {
+    /// Wrap `writer`, buffering within the default limits.
+    pub(crate) fn new(writer: W) -> Self {
+        Stream {
+            writer,
+            limits: Limits::default(),
+        }
     }
 }
 
-static MAGIC_BYTES: [(&[u8], ImageFormat); 22] = [
+impl<W: Write> Write for Stream<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.writer.write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.writer.write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> EncoderOutput for Stream<W> {
+    fn seekable(&mut self) -> Result<&mut dyn WriteSeek, &Limits> {
+        Err(&self.limits)
+    }
+}
+
+/// A writer that encoders can seek in, such as a file or a `Cursor`.
+pub(crate) struct Seekable<W>(pub(crate) W);
+
+impl<W: Write> Write for Seekable<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.write(buf)
+    }
+
+    fn write_all(&mut self, buf: &[u8]) -> io::Result<()> {
+        self.0.write_all(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+impl<W: Write + Seek> EncoderOutput for Seekable<W> {
+    fn seekable(&mut self) -> Result<&mut dyn WriteSeek, &Limits> {
+        Ok(&mut self.0)
+    }
+}
+
+/// Every color type, used to probe the capabilities of the encoders.
+pub(crate) static ALL_COLOR_TYPES: [color::ColorType; 10] = [
+    color::ColorType::L8,
+    color::ColorType::La8,
+    color::ColorType::Rgb8,
+    color::ColorType::Rgba8,
+    color::ColorType::L16,
+    color::ColorType::La16,
+    color::ColorType::Rgb16,
+    color::ColorType::Rgba16,
+    color::ColorType::Rgb32F,
+    color::ColorType::Rgba32F,
+];
+
+/// Determine if an encoder supports a color type by letting it encode a single pixel.
+///
+/// This keeps the reported capabilities in sync with the actual encoders, including any that are
+/// added or extended later, without maintaining a separate table.
+pub(crate) fn probe_color_type(format: ImageFormat, color: color::ColorType) -> bool {
+    let pixel = vec![0; usize::from(color.bytes_per_pixel())];
+    let mut encoded = Cursor::new(Vec::new());
+    let mut output = Seekable(&mut encoded);
+    encode_buffer_impl(&mut output, &pixel, 1, 1, color, format.into(), None, false).is_ok()
+}
+
+/// Determine the color types that the decoder of a format produces.
+///
+/// Like [`probe_color_type`], this asks the codec itself: the encoder of the format writes a
+/// single pixel of every color type and the decoder reports the color type of each of them.
+/// Decoders convert the layouts they do not represent, such as palettes, CMYK or low bit depths,
+/// so this finds the color types that the encoder writes and those it is converted to. The
+/// layouts that no encoder of this crate writes are added from [`unprobed_color_types`].
+pub(crate) fn decoded_color_types(format: ImageFormat) -> Vec<color::ColorType> {
+    struct ColorVisitor;
+
+    impl DecoderVisitor for ColorVisitor {
+        type Result = color::ColorType;
+        fn visit_decoder<'a, D: ImageDecoder<'a>>(self, decoder: D) -> ImageResult<Self::Result> {
+            Ok(decoder.color_type())
+        }
+    }
+
+    let mut decoded = unprobed_color_types(format).to_vec();
+    for &color in &ALL_COLOR_TYPES {
+        let pixel = vec![0; usize::from(color.bytes_per_pixel())];
+        let mut encoded = Cursor::new(Vec::new());
+        let mut output = Seekable(&mut encoded);
+        if encode_buffer_impl(&mut output, &pixel, 1, 1, color, format.into(), None, false).is_err()
+        {
+            continue;
+        }
+        encoded.set_position(0);
+        if let Ok(color) = load_decoder(encoded, format, Limits::default(), ColorVisitor) {
+            if !decoded.contains(&color) {
+                decoded.push(color);
+            }
+        }
+    }
+    decoded
+}
+
+/// The color types that decoders produce for layouts that no encoder of this crate writes, so
+/// that [`decoded_color_types`] can not find them by probing.
+fn unprobed_color_types(format: ImageFormat) -> &'static [color::ColorType] {
+    use color::ColorType::*;
+
+    #[allow(unreachable_patterns)]
+    // Default is unreachable if all features are supported.
+    match format {
+        // The AVIF decoder is enabled without the encoder, and converts to 8-bit RGBA.
+        #[cfg(feature = "avif-decoder")]
+        ImageFormat::Avif => &[Rgba8],
+        // Images of 12-bit precision.
+        #[cfg(feature = "jpeg")]
+        ImageFormat::Jpeg => &[L16],
+        // Lossy images without alpha.
+        #[cfg(feature = "webp")]
+        ImageFormat::WebP => &[Rgb8],
+        // Grayscale images with alpha.
+        #[cfg(feature = "tiff")]
+        ImageFormat::Tiff => &[La8, La16],
+        // Grayscale palettes.
+        #[cfg(feature = "bmp")]
+        ImageFormat::Bmp => &[L8],
+        // Compressed textures, with and without alpha.
+        #[cfg(feature = "dds")]
+        ImageFormat::Dds => &[Rgb8, Rgba8],
+        #[cfg(feature = "hdr")]
+        ImageFormat::Hdr => &[Rgb32F],
+        // Maps with a maximum value above 255.
+        #[cfg(feature = "pnm")]
+        ImageFormat::Pnm => &[L16, Rgb16],
+        #[cfg(feature = "dicom")]
+        ImageFormat::Dicom => &[L8, Rgb8, L16, Rgb16],
+        // The registered decoders may return an image of any color type.
+        #[cfg(feature = "jpegxs")]
+        ImageFormat::JpegXs if jpegxs::has_decoder() => &ALL_COLOR_TYPES,
+        #[cfg(feature = "jxl")]
+        ImageFormat::Jxl if jxl::has_decoder() => &ALL_COLOR_TYPES,
+        #[cfg(feature = "heif")]
+        ImageFormat::Heif if heif::has_decoder() => &ALL_COLOR_TYPES,
+        #[cfg(feature = "svg")]
+        ImageFormat::Svg => &[Rgba8],
+        _ => &[],
+    }
+}
+
+static MAGIC_BYTES: [(&[u8], ImageFormat); 31] = [
     (b"\x89PNG\r\n\x1a\n", ImageFormat::Png),
     (&[0xff, 0xd8, 0xff], ImageFormat::Jpeg),
     (b"GIF89a", ImageFormat::Gif),
@@ -327,6 +1143,7 @@ static MAGIC_BYTES: [(&[u8], ImageFormat); 22] = [
     (b"DDS ", ImageFormat::Dds),
     (b"BM", ImageFormat::Bmp),
     (&[0, 0, 1, 0], ImageFormat::Ico),
+    (b"icns", ImageFormat::Icns),
     (b"#?RADIANCE", ImageFormat::Hdr),
     (b"P1", ImageFormat::Pnm),
     (b"P2", ImageFormat::Pnm),
@@ -336,11 +1153,23 @@ static MAGIC_BYTES: [(&[u8], ImageFormat); 22] = [
     (b"P6", ImageFormat::Pnm),
     (b"P7", ImageFormat::Pnm),
     (b"farbfeld", ImageFormat::Farbfeld),
+    (b"SIMPLE  =", ImageFormat::Fits),
+    (b"qoif", ImageFormat::Qoi),
+    (&[0xff, 0x10, 0xff, 0x50], ImageFormat::JpegXs),
+    (b"\0\0\0\x0cJXS \r\n\x87\n", ImageFormat::JpegXs),
+    (&[0xff, 0x0a], ImageFormat::Jxl),
+    (b"\0\0\0\x0cJXL \r\n\x87\n", ImageFormat::Jxl),
     (b"\0\0\0 ftypavif", ImageFormat::Avif),
     (b"\0\0\0\x1cftypavif", ImageFormat::Avif),
+    (b"\0\0\0 ftypavis", ImageFormat::Avif),
+    (b"\0\0\0\x1cftypavis", ImageFormat::Avif),
     (&[0x76, 0x2f, 0x31, 0x01], ImageFormat::OpenExr), // = &exr::meta::magic_number::BYTES
 ];
 
+/// The number of bytes that readers look at to guess the format of an image, enough for the
+/// preamble of DICOM files and the XML prolog of most SVG files.
+pub(crate) const GUESS_LEN: usize = 512;
+
 /// Guess image format from memory block
 ///
 /// Makes an educated guess about the image format based on the Magic Bytes at the beginning.
@@ -360,5 +1189,71 @@ pub(crate) fn guess_format_impl(buffer: &[u8]) -> Option<ImageFormat> {
         }
     }
 
+    // DICOM files start with a preamble of 128 arbitrary bytes.
+    if buffer.get(128..132) == Some(&b"DICM"[..]) {
+        return Some(ImageFormat::Dicom);
+    }
+
+    if is_heif(buffer) {
+        return Some(ImageFormat::Heif);
+    }
+
+    if is_svg(buffer) {
+        return Some(ImageFormat::Svg);
+    }
+
     None
 }
+
+/// The length of the prefix of `buffer` that `guess_format` recognizes its format by, or zero if
+/// it has none.
+#[cfg(feature = "test-util")]
+pub(crate) fn signature_len(buffer: &[u8]) -> usize {
+    for &(signature, _) in &MAGIC_BYTES {
+        if buffer.starts_with(signature) {
+            return signature.len();
+        }
+    }
+
+    if buffer.get(128..132) == Some(&b"DICM"[..]) {
+        return 132;
+    }
+
+    if is_heif(buffer) {
+        return 12;
+    }
+
+    0
+}
+
+/// HEIF files start with a file type box, of any size, whose major brand names a HEIF profile.
+/// AVIF files use the same container but are recognized by their own brands before.
+fn is_heif(buffer: &[u8]) -> bool {
+    const BRANDS: [&[u8]; 8] = [
+        b"heic", b"heix", b"heim", b"heis", b"hevc", b"hevx", b"mif1", b"msf1",
+    ];
+    buffer.get(4..8) == Some(&b"ftyp"[..])
+        && buffer
+            .get(8..12)
+            .map_or(false, |brand| BRANDS.contains(&brand))
+}
+
+/// SVG files are XML, which may start with a declaration, comments and a doctype before the
+/// `svg` element. This prolog is searched within the first `GUESS_LEN` bytes.
+fn is_svg(buffer: &[u8]) -> bool {
+    let text = &buffer[..buffer.len().min(GUESS_LEN)];
+    // Skip a byte order mark and leading whitespace.
+    let text = text.strip_prefix(b"\xef\xbb\xbf").unwrap_or(text);
+    let start = text
+        .iter()
+        .position(|byte| !byte.is_ascii_whitespace())
+        .unwrap_or(text.len());
+    let text = &text[start..];
+
+    if text.starts_with(b"<svg") {
+        return true;
+    }
+    let prolog = [&b"<?xml"[..], b"<!--", b"<!DOCTYPE"];
+    prolog.iter().any(|start| text.starts_with(start))
+        && text.windows(4).any(|window| window == b"<svg")
+}
@@ -0,0 +1,128 @@
+//! Access to files through a pluggable file system.
+//!
+//! [`Reader::open_in`] reads images from any [`FileSystem`], such as the entries of an archive, a
+//! game packfile or an object storage bucket, while the format is still guessed from the path.
+//!
+//! [`Reader::open_in`]: struct.Reader.html#method.open_in
+//! [`FileSystem`]: trait.FileSystem.html
+
+use std::fs::File;
+use std::io::{self, Read, Seek};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// The metadata of a file that is relevant for reading it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct FileMetadata {
+    /// The size of the file in bytes.
+    pub len: u64,
+    /// The time the file was last modified, if the file system records it.
+    pub modified: Option<SystemTime>,
+}
+
+/// A source of files addressed by paths.
+///
+/// Implement this for a virtual file system to read images from it with [`Reader::open_in`].
+/// References to a file system are file systems as well.
+///
+/// [`Reader::open_in`]: struct.Reader.html#method.open_in
+pub trait FileSystem {
+    /// The type of opened files.
+    type File: Read + Seek;
+
+    /// Opens the file at `path` for reading.
+    fn open(&self, path: &Path) -> io::Result<Self::File>;
+
+    /// Queries the metadata of the file at `path`, without opening it.
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+}
+
+/// The file system of the operating system, as accessed through `std::fs`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdFileSystem;
+
+impl FileSystem for StdFileSystem {
+    type File = File;
+
+    fn open(&self, path: &Path) -> io::Result<File> {
+        File::open(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        let metadata = std::fs::metadata(path)?;
+        Ok(FileMetadata {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
+
+impl<F: FileSystem + ?Sized> FileSystem for &F {
+    type File = F::File;
+
+    fn open(&self, path: &Path) -> io::Result<F::File> {
+        (**self).open(path)
+    }
+
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        (**self).metadata(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::ImageFormat;
+    use crate::io::Reader;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+
+    /// A file system holding its files in memory, like the entries of an archive.
+    struct MemoryFileSystem(HashMap<PathBuf, Vec<u8>>);
+
+    impl FileSystem for MemoryFileSystem {
+        type File = Cursor<Vec<u8>>;
+
+        fn open(&self, path: &Path) -> io::Result<Self::File> {
+            self.0
+                .get(path)
+                .map(|data| Cursor::new(data.clone()))
+                .ok_or_else(|| io::ErrorKind::NotFound.into())
+        }
+
+        fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+            let data = self.0.get(path).ok_or(io::ErrorKind::NotFound)?;
+            Ok(FileMetadata {
+                len: data.len() as u64,
+                modified: None,
+            })
+        }
+    }
+
+    #[test]
+    fn open_in_memory() {
+        let data = b"P5 3 2 255\n\x00\x01\x02\x03\x04\x05".to_vec();
+        let mut files = HashMap::new();
+        files.insert(PathBuf::from("textures/stone.pgm"), data);
+        files.insert(PathBuf::from("textures/stone.bin"), b"P1 1 1\n1\n".to_vec());
+        let fs = MemoryFileSystem(files);
+
+        assert_eq!(
+            fs.metadata(Path::new("textures/stone.pgm")).unwrap().len,
+            17
+        );
+        let reader = Reader::open_in(&fs, "textures/stone.pgm").unwrap();
+        assert_eq!(reader.format(), Some(ImageFormat::Pnm));
+        #[cfg(feature = "pnm")]
+        assert_eq!(reader.decode().unwrap().as_bytes(), &[0, 1, 2, 3, 4, 5]);
+
+        let reader = Reader::open_in(&fs, "textures/stone.bin").unwrap();
+        assert_eq!(reader.format(), None);
+        let reader = reader.with_guessed_format().unwrap();
+        assert_eq!(reader.format(), Some(ImageFormat::Pnm));
+
+        let missing = Reader::open_in(&fs, "textures/missing.pgm").err().unwrap();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
+    }
+}
@@ -1,18 +1,38 @@
 //! Input and output of images.
 
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+
 use crate::{error, ImageError, ImageResult};
 
+pub(crate) mod allocator;
+mod exif;
 pub(crate) mod free_functions;
+mod fs;
 mod reader;
+mod self_check;
+mod strip;
+mod transcode;
 
-pub use self::reader::Reader;
+pub use self::allocator::OutputBufferAllocator;
+pub use self::fs::{FileMetadata, FileSystem, StdFileSystem};
+pub use self::reader::{DecodeStats, ImageReader, Reader, ReaderBuilder};
+pub use self::self_check::{encoder_self_check, set_encoder_self_check};
+pub use self::strip::strip_metadata;
+pub use self::transcode::transcode_lossless;
 
 #[cfg(feature = "async")]
 mod async_reader;
+#[cfg(feature = "async")]
+mod async_writer;
 
 #[cfg(feature = "async")]
 pub use self::async_reader::AsyncReader;
-
+#[cfg(feature = "async")]
+pub use self::async_writer::AsyncWriter;
 
 /// Set of supported strict limits for a decoder.
 #[derive(Clone, Debug, Eq, PartialEq, Hash)]
@@ -29,6 +49,59 @@ impl Default for LimitSupport {
     }
 }
 
+/// A token to abort decoding from another thread.
+///
+/// Clones of a token share its state. Keep one to cancel the decoding of an image whose
+/// [`Limits`] hold another, for example when the client that requested the image disconnected.
+/// Decoding then fails with `image::error::LimitErrorKind::Cancelled` soon after.
+///
+/// ```
+/// use image::io::{CancelToken, Limits};
+///
+/// let token = CancelToken::new();
+/// let mut limits = Limits::default();
+/// limits.cancel_token = Some(token.clone());
+///
+/// token.cancel();
+/// assert!(limits.check_cancelled().is_err());
+/// ```
+///
+/// [`Limits`]: ./struct.Limits.html
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Create a token that is not cancelled.
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    /// Cancel decoding with this token and all of its clones.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Whether this token or one of its clones was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Tokens are equal if they are clones of each other.
+impl PartialEq for CancelToken {
+    fn eq(&self, other: &CancelToken) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for CancelToken {}
+
+impl Hash for CancelToken {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (Arc::as_ptr(&self.0) as usize).hash(state);
+    }
+}
+
 /// Resource limits for decoding.
 ///
 /// Limits can be either *strict* or *non-strict*. Non-strict limits are best-effort
@@ -61,6 +134,26 @@ pub struct Limits {
     /// allocator overhead. This limit is non-strict by default and some decoders may ignore it.
     /// The default is 512MiB.
     pub max_alloc: Option<u64>,
+    /// The number of megapixels that images which exceed the limits are downscaled to when they
+    /// are decoded by an [`io::Reader`], instead of failing. The default is `None`, which fails.
+    ///
+    /// When set, images with more megapixels, or that exceed the other limits, are decoded at the
+    /// largest resolution that fits all of them, keeping their aspect ratio. Decoders that can
+    /// decode a reduced resolution do so, such as the IDCT scaling of JPEG and the reduced pages
+    /// of TIFF, see [`ImageDecoder::request_dimensions`]. Other images are decoded fully and
+    /// downscaled after, so the allocation limit still applies to their full resolution, while
+    /// the dimension limits apply to the downscaled image.
+    ///
+    /// [`io::Reader`]: ./struct.Reader.html
+    /// [`ImageDecoder::request_dimensions`]: ../trait.ImageDecoder.html#method.request_dimensions
+    pub max_megapixels_with_downscale: Option<u32>,
+    /// The instant after which decoding is aborted. This limit is non-strict, decoders notice it
+    /// when they next read from the input, so that a decompression bomb can still take a moment
+    /// longer. The default is no deadline.
+    pub deadline: Option<Instant>,
+    /// A token to abort decoding from another thread, which is noticed like the `deadline`. The
+    /// default is none.
+    pub cancel_token: Option<CancelToken>,
     _non_exhaustive: (),
 }
 
@@ -70,6 +163,9 @@ impl Default for Limits {
             max_image_width: None,
             max_image_height: None,
             max_alloc: Some(512 * 1024 * 1024),
+            max_megapixels_with_downscale: None,
+            deadline: None,
+            cancel_token: None,
             _non_exhaustive: (),
         }
     }
@@ -82,6 +178,9 @@ impl Limits {
             max_image_width: None,
             max_image_height: None,
             max_alloc: None,
+            max_megapixels_with_downscale: None,
+            deadline: None,
+            cancel_token: None,
             _non_exhaustive: (),
         }
     }
@@ -113,6 +212,81 @@ impl Limits {
         Ok(())
     }
 
+    /// The dimensions that an image of `width` and `height`, with `bytes_per_pixel`, is
+    /// downscaled to when it is decoded, or `None` if it fits the limits or downscaling is
+    /// disabled. See [`max_megapixels_with_downscale`].
+    ///
+    /// [`max_megapixels_with_downscale`]: #structfield.max_megapixels_with_downscale
+    pub fn downscaled_dimensions(
+        &self,
+        width: u32,
+        height: u32,
+        bytes_per_pixel: u64,
+    ) -> Option<(u32, u32)> {
+        let megapixels = self.max_megapixels_with_downscale?;
+        if width == 0 || height == 0 {
+            return None;
+        }
+        let (w, h) = (f64::from(width), f64::from(height));
+        let pixels = w * h;
+
+        let mut scale = (f64::from(megapixels) * 1_000_000.0 / pixels).sqrt();
+        if let Some(max_width) = self.max_image_width {
+            scale = scale.min(f64::from(max_width) / w);
+        }
+        if let Some(max_height) = self.max_image_height {
+            scale = scale.min(f64::from(max_height) / h);
+        }
+        if let Some(max_alloc) = self.max_alloc {
+            let max_pixels = max_alloc as f64 / bytes_per_pixel.max(1) as f64;
+            scale = scale.min((max_pixels / pixels).sqrt());
+        }
+
+        if scale >= 1.0 {
+            return None;
+        }
+        let scaled = |len: f64| ((len * scale) as u32).max(1);
+        Some((scaled(w), scaled(h)))
+    }
+
+    /// This function checks that decoding was not cancelled with the `cancel_token` and that the
+    /// `deadline` has not passed.
+    ///
+    /// Decoders that are constructed by a [`Reader`] are checked whenever they read from the
+    /// input. Long running loops that do not read can call this to abort sooner.
+    ///
+    /// [`Reader`]: ./struct.Reader.html
+    pub fn check_cancelled(&self) -> ImageResult<()> {
+        if let Some(cancel_token) = &self.cancel_token {
+            if cancel_token.is_cancelled() {
+                return Err(ImageError::Limits(error::LimitError::from_kind(
+                    error::LimitErrorKind::Cancelled,
+                )));
+            }
+        }
+
+        if let Some(deadline) = self.deadline {
+            if Instant::now() >= deadline {
+                return Err(ImageError::Limits(error::LimitError::from_kind(
+                    error::LimitErrorKind::DeadlineExceeded,
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether decoding can be cancelled with a `cancel_token` or a `deadline`.
+    pub(crate) fn is_cancellable(&self) -> bool {
+        self.deadline.is_some() || self.cancel_token.is_some()
+    }
+
+    /// The `max_alloc` limit as the size of a buffer, for the limits of the underlying decoders.
+    pub(crate) fn max_alloc_usize(&self) -> usize {
+        self.max_alloc
+            .map_or(usize::MAX, |max| usize::try_from(max).unwrap_or(usize::MAX))
+    }
+
     /// This function checks that the current limit allows for reserving the set amount
     /// of bytes, it then reduces the limit accordingly.
     pub fn reserve(&mut self, amount: u64) -> ImageResult<()> {
@@ -1,13 +1,23 @@
+use std::cell::Cell;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, Cursor, Read, Seek, SeekFrom};
 use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
+use crate::color::ColorType;
 use crate::dynimage::DynamicImage;
-use crate::error::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
-use crate::image::ImageFormat;
+use crate::error::{
+    ImageFormatHint, LimitError, LimitErrorKind, ParameterError, ParameterErrorKind,
+    UnsupportedError, UnsupportedErrorKind,
+};
+use crate::image::{ImageDecoder, ImageFormat};
+use crate::metadata::{Exif, MetadataMap, Orientation};
 use crate::{ImageError, ImageResult};
 
-use super::free_functions;
+use super::allocator::{OutputBufferAllocator, SharedAllocator};
+use super::free_functions::{self, DecoderVisitor, LoadVisitor};
+use super::fs::{FileSystem, StdFileSystem};
 
 /// A multi-format image reader.
 ///
@@ -56,8 +66,13 @@ use super::free_functions;
 /// As a final fallback or if only a specific format must be used, the reader always allows manual
 /// specification of the supposed image format with [`set_format`].
 ///
+/// All settings can also be chosen up front with a [`ReaderBuilder`], which configures
+/// [`AsyncReader`] in the same way.
+///
 /// [`set_format`]: #method.set_format
 /// [`ImageDecoder`]: ../trait.ImageDecoder.html
+/// [`ReaderBuilder`]: struct.ReaderBuilder.html
+/// [`AsyncReader`]: struct.AsyncReader.html
 pub struct Reader<R: Read> {
     /// The reader. Should be buffered.
     inner: R,
@@ -65,8 +80,20 @@ pub struct Reader<R: Read> {
     format: Option<ImageFormat>,
     /// Decoding limits
     limits: super::Limits,
+    /// Whether the EXIF orientation is applied to the decoded image.
+    apply_orientation: bool,
+    /// Supplies the buffers images are decoded into, if not the global allocator.
+    allocator: Option<SharedAllocator>,
 }
 
+/// The name of [`Reader`] matching the other types of this crate, such as [`ImageFormat`] and
+/// [`ImageDecoder`].
+///
+/// [`Reader`]: struct.Reader.html
+/// [`ImageFormat`]: ../enum.ImageFormat.html
+/// [`ImageDecoder`]: ../trait.ImageDecoder.html
+pub type ImageReader<R = BufReader<File>> = Reader<R>;
+
 impl<R: Read> Reader<R> {
     /// Create a new image reader without a preset format.
     ///
@@ -79,11 +106,7 @@ impl<R: Read> Reader<R> {
     /// [`with_guessed_format`]: #method.with_guessed_format
     /// [`set_format`]: method.set_format
     pub fn new(buffered_reader: R) -> Self {
-        Reader {
-            inner: buffered_reader,
-            format: None,
-            limits: super::Limits::default(),
-        }
+        ReaderBuilder::new().build(buffered_reader)
     }
 
     /// Construct a reader with specified format.
@@ -91,11 +114,7 @@ impl<R: Read> Reader<R> {
     /// Assumes the reader is already buffered. For optimal performance,
     /// consider wrapping the reader with a `BufReader::new()`.
     pub fn with_format(buffered_reader: R, format: ImageFormat) -> Self {
-        Reader {
-            inner: buffered_reader,
-            format: Some(format),
-            limits: super::Limits::default(),
-        }
+        ReaderBuilder::new().format(format).build(buffered_reader)
     }
 
     /// Get the currently determined format.
@@ -126,6 +145,15 @@ impl<R: Read> Reader<R> {
         self.limits = limits;
     }
 
+    /// Decode images into buffers supplied by `allocator`.
+    ///
+    /// See [`OutputBufferAllocator`] for recycling the buffers of decoded images.
+    ///
+    /// [`OutputBufferAllocator`]: trait.OutputBufferAllocator.html
+    pub fn allocator(&mut self, allocator: Arc<dyn OutputBufferAllocator>) {
+        self.allocator = Some(SharedAllocator(allocator));
+    }
+
     /// Unwrap the reader.
     pub fn into_inner(self) -> R {
         self.inner
@@ -133,6 +161,27 @@ impl<R: Read> Reader<R> {
 }
 
 impl Reader<BufReader<File>> {
+    /// Start configuring a reader, which is then created by one of the methods of the builder.
+    ///
+    /// ```no_run
+    /// # use image::ImageError;
+    /// # use image::io::ImageReader;
+    /// # fn main() -> Result<(), ImageError> {
+    /// use image::ImageFormat;
+    /// use image::io::Limits;
+    ///
+    /// let image = ImageReader::builder()
+    ///     .limits(Limits::default())
+    ///     .format(ImageFormat::Jpeg)
+    ///     .apply_orientation(true)
+    ///     .open("path/to/photo")?
+    ///     .decode()?;
+    /// # Ok(()) }
+    /// ```
+    pub fn builder() -> ReaderBuilder {
+        ReaderBuilder::new()
+    }
+
     /// Open a file to read, format will be guessed from path.
     ///
     /// This will not attempt any io operation on the opened file.
@@ -145,15 +194,54 @@ impl Reader<BufReader<File>> {
     where
         P: AsRef<Path>,
     {
-        Self::open_impl(path.as_ref())
+        Self::open_in(StdFileSystem, path)
     }
+}
 
-    fn open_impl(path: &Path) -> io::Result<Self> {
-        Ok(Reader {
-            inner: BufReader::new(File::open(path)?),
-            format: ImageFormat::from_path(path).ok(),
-            limits: super::Limits::default(),
-        })
+#[cfg(feature = "mmap")]
+impl Reader<Cursor<memmap2::Mmap>> {
+    /// Map a file into memory to read, format will be guessed from path.
+    ///
+    /// The decoders read directly from the mapped pages, which the operating system loads as
+    /// they are accessed and can evict again. This avoids holding a copy of the whole file in
+    /// memory, which matters for images of several gigabytes. Decoders of formats that support
+    /// random access, such as TIFF, BMP, TGA and DDS, only touch the parts of the file they need,
+    /// for example when decoding a section with [`ImageDecoderRect`].
+    ///
+    /// The file must not be modified or truncated while it is mapped, otherwise decoding may read
+    /// inconsistent data or the process may be terminated by the operating system.
+    ///
+    /// [`ImageDecoderRect`]: ../trait.ImageDecoderRect.html
+    pub fn from_mmap<P>(path: P) -> io::Result<Self>
+    where
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        // Safety: the mapping is only read, and the caller is asked not to modify the file while
+        // it is mapped, as documented above.
+        let map = unsafe { memmap2::Mmap::map(&file)? };
+        let mut reader = Reader::new(Cursor::new(map));
+        reader.format = ImageFormat::from_path(path).ok();
+        Ok(reader)
+    }
+}
+
+impl<F: Read + Seek> Reader<BufReader<F>> {
+    /// Open a file of a [`FileSystem`] to read, format will be guessed from path.
+    ///
+    /// This works like [`open`], but the file is opened by `fs` instead of `std::fs`. This allows
+    /// reading images from virtual file systems such as archives, while still guessing their
+    /// format from the path.
+    ///
+    /// [`FileSystem`]: trait.FileSystem.html
+    /// [`open`]: #method.open
+    pub fn open_in<S, P>(fs: S, path: P) -> io::Result<Self>
+    where
+        S: FileSystem<File = F>,
+        P: AsRef<Path>,
+    {
+        ReaderBuilder::new().open_in(fs, path)
     }
 }
 
@@ -194,13 +282,13 @@ impl<R: BufRead + Seek> Reader<R> {
     }
 
     fn guess_format(&mut self) -> io::Result<Option<ImageFormat>> {
-        let mut start = [0; 16];
+        let mut start = [0; free_functions::GUESS_LEN];
 
         // Save current offset, read start, restore offset.
         let cur = self.inner.seek(SeekFrom::Current(0))?;
         let len = io::copy(
-            // Accept shorter files but read at most 16 bytes.
-            &mut self.inner.by_ref().take(16),
+            // Accept shorter files but read at most `GUESS_LEN` bytes.
+            &mut self.inner.by_ref().take(free_functions::GUESS_LEN as u64),
             &mut Cursor::new(&mut start[..]),
         )?;
         self.inner.seek(SeekFrom::Start(cur))?;
@@ -213,19 +301,269 @@ impl<R: BufRead + Seek> Reader<R> {
     /// Uses the current format to construct the correct reader for the format.
     ///
     /// If no format was determined, returns an `ImageError::Unsupported`.
+    /// If the reader was built to [apply the orientation], these are the dimensions after applying
+    /// it, as returned by [`into_dimensions_oriented`].
+    ///
+    /// [apply the orientation]: struct.ReaderBuilder.html#method.apply_orientation
+    /// [`into_dimensions_oriented`]: #method.into_dimensions_oriented
     pub fn into_dimensions(mut self) -> ImageResult<(u32, u32)> {
+        if self.apply_orientation {
+            return self.into_dimensions_oriented();
+        }
         let format = self.require_format()?;
-        free_functions::image_dimensions_with_format_impl(self.inner, format)
+        free_functions::image_dimensions_with_format_impl(self.inner, format, self.limits)
+    }
+
+    /// Read the image dimensions as they are displayed, after applying the EXIF orientation.
+    ///
+    /// Photos from cameras and phones are commonly stored in the orientation of the sensor and
+    /// tagged with the rotation needed for display. If that rotation is by 90 or 270 degrees the
+    /// width and height are swapped compared to [`into_dimensions`]. This is the size to reserve
+    /// when laying out such an image. The orientation is read from JPEG, PNG, TIFF and WebP
+    /// files, images in other formats or without the tag are assumed to be upright.
+    ///
+    /// If no format was determined, returns an `ImageError::Unsupported`.
+    ///
+    /// [`into_dimensions`]: #method.into_dimensions
+    pub fn into_dimensions_oriented(mut self) -> ImageResult<(u32, u32)> {
+        let format = self.require_format()?;
+        let orientation = self.read_orientation(format)?;
+
+        let (width, height) =
+            free_functions::image_dimensions_with_format_impl(self.inner, format, self.limits)?;
+        match orientation {
+            Some(orientation) if super::exif::swaps_dimensions(orientation) => Ok((height, width)),
+            _ => Ok((width, height)),
+        }
     }
 
     /// Read the image (replaces `load`).
     ///
     /// Uses the current format to construct the correct reader for the format.
     ///
+    /// If the reader was built to [apply the orientation], the image is rotated and flipped
+    /// accordingly.
+    ///
     /// If no format was determined, returns an `ImageError::Unsupported`.
+    ///
+    /// [apply the orientation]: struct.ReaderBuilder.html#method.apply_orientation
     pub fn decode(mut self) -> ImageResult<DynamicImage> {
         let format = self.require_format()?;
-        free_functions::load_inner(self.inner, self.limits, format)
+        let orientation = self.applied_orientation(format)?;
+        let visitor = self.load_visitor();
+        let image = free_functions::load_decoder(self.inner, format, self.limits, visitor)?;
+        Ok(orient(image, orientation))
+    }
+
+    /// Read the image like [`decode`], and measure what decoding it cost.
+    ///
+    /// Services that decode images of many formats can use the statistics to attribute the cost
+    /// to each format, and to choose limits from the sizes of real images. Measuring adds no cost
+    /// beyond counting the bytes that are read.
+    ///
+    /// If no format was determined, returns an `ImageError::Unsupported`.
+    ///
+    /// [`decode`]: #method.decode
+    pub fn decode_with_stats(mut self) -> ImageResult<(DynamicImage, DecodeStats)> {
+        let format = self.require_format()?;
+        let orientation = self.applied_orientation(format)?;
+        let bytes_read = Cell::new(0);
+        let visitor = StatsVisitor {
+            load: self.load_visitor(),
+            format,
+            start: Instant::now(),
+            bytes_read: &bytes_read,
+        };
+        let reader = CountingReader {
+            inner: self.inner,
+            count: &bytes_read,
+        };
+        let (image, stats) = free_functions::load_decoder(reader, format, self.limits, visitor)?;
+        Ok((orient(image, orientation), stats))
+    }
+
+    /// Read the image like [`decode`], together with its embedded ICC color profile.
+    ///
+    /// The decoded image holds the profile as well, see [`DynamicImage::icc_profile`], and
+    /// embeds it again when it is saved in a format that can store it.
+    ///
+    /// If no format was determined, returns an `ImageError::Unsupported`.
+    ///
+    /// [`decode`]: #method.decode
+    /// [`DynamicImage::icc_profile`]: ../enum.DynamicImage.html#method.icc_profile
+    pub fn decode_with_icc_profile(self) -> ImageResult<(DynamicImage, Option<Vec<u8>>)> {
+        let image = self.decode()?;
+        let icc_profile = image.icc_profile().map(<[u8]>::to_vec);
+        Ok((image, icc_profile))
+    }
+
+    /// Read the image like [`decode`], together with its EXIF metadata.
+    ///
+    /// The metadata is read from JPEG, PNG, TIFF and WebP images. If the reader was built to
+    /// [apply the orientation], the image is returned upright and the orientation of the metadata
+    /// is reset, so that it can be written back with the image.
+    ///
+    /// ```no_run
+    /// use image::codecs::jpeg::JpegEncoder;
+    /// use image::io::Reader;
+    /// use image::ImageEncoder;
+    ///
+    /// let (image, exif) = Reader::open("photo.jpg")?.decode_with_exif()?;
+    /// let thumbnail = image.thumbnail(256, 256).into_rgb8();
+    /// let mut encoder = JpegEncoder::new(std::fs::File::create("thumbnail.jpg")?);
+    /// if let Some(exif) = exif {
+    ///     encoder.set_exif_metadata(&exif)?;
+    /// }
+    /// let (width, height) = thumbnail.dimensions();
+    /// encoder.write_image(&thumbnail, width, height, image::ColorType::Rgb8)?;
+    /// # Ok::<(), image::ImageError>(())
+    /// ```
+    ///
+    /// If no format was determined, returns an `ImageError::Unsupported`.
+    ///
+    /// [`decode`]: #method.decode
+    /// [apply the orientation]: struct.ReaderBuilder.html#method.apply_orientation
+    pub fn decode_with_exif(mut self) -> ImageResult<(DynamicImage, Option<Exif>)> {
+        let format = self.require_format()?;
+        let start = self.inner.stream_position()?;
+        let mut exif = super::exif::exif(&mut self.inner, format).unwrap_or(None);
+        self.inner.seek(SeekFrom::Start(start))?;
+
+        let orientation = match &mut exif {
+            Some(exif) if self.apply_orientation && exif.orientation.is_some() => {
+                exif.orientation.replace(Orientation::NoTransforms)
+            }
+            _ => None,
+        };
+        let visitor = self.load_visitor();
+        let mut image = free_functions::load_decoder(self.inner, format, self.limits, visitor)?;
+        if let Some(orientation) = orientation {
+            image.apply_orientation(orientation);
+        }
+        Ok((image, exif))
+    }
+
+    /// Read the image like [`decode`], with samples of the color type `color_type`.
+    ///
+    /// Decoders that can produce the color type while decoding are asked to do so, which saves a
+    /// conversion of the whole image afterwards. The JPEG and PNG decoders convert between the
+    /// 8-bit gray and RGB color types, with and without alpha, as they copy out their rows. Other
+    /// images are converted after decoding.
+    ///
+    /// ```no_run
+    /// use image::io::Reader;
+    /// use image::ColorType;
+    ///
+    /// // Upload textures as RGBA, whatever the files contain.
+    /// let texture = Reader::open("texture.png")?.decode_as(ColorType::Rgba8)?.into_rgba8();
+    /// # Ok::<(), image::ImageError>(())
+    /// ```
+    ///
+    /// If no format was determined, returns an `ImageError::Unsupported`.
+    ///
+    /// [`decode`]: #method.decode
+    pub fn decode_as(mut self, color_type: ColorType) -> ImageResult<DynamicImage> {
+        let format = self.require_format()?;
+        let orientation = self.applied_orientation(format)?;
+        let visitor = ColorTypeVisitor {
+            load: self.load_visitor(),
+            color_type,
+        };
+        let image = free_functions::load_decoder(self.inner, format, self.limits, visitor)?;
+        Ok(orient(image, orientation))
+    }
+
+    /// Read the image like [`decode`], together with its textual metadata and XMP packet.
+    ///
+    /// PNG images store text in their `tEXt`, `zTXt` and `iTXt` chunks. JPEG, TIFF and WebP
+    /// images only have an XMP packet. The metadata can be written back with
+    /// [`ImageEncoder::set_text_metadata`].
+    ///
+    /// ```no_run
+    /// use image::io::Reader;
+    ///
+    /// let (image, metadata) = Reader::open("scan.png")?.decode_with_metadata()?;
+    /// if let Some(author) = metadata.get("Author") {
+    ///     println!("{}x{} pixels by {}", image.width(), image.height(), author);
+    /// }
+    /// # Ok::<(), image::ImageError>(())
+    /// ```
+    ///
+    /// If no format was determined, returns an `ImageError::Unsupported`.
+    ///
+    /// [`decode`]: #method.decode
+    /// [`ImageEncoder::set_text_metadata`]: ../trait.ImageEncoder.html#method.set_text_metadata
+    pub fn decode_with_metadata(mut self) -> ImageResult<(DynamicImage, MetadataMap)> {
+        let format = self.require_format()?;
+        let orientation = self.applied_orientation(format)?;
+        let start = self.inner.stream_position()?;
+        // Malformed metadata is left to the decoder, like the orientation.
+        let xmp = super::exif::xmp(&mut self.inner, format).unwrap_or(None);
+        self.inner.seek(SeekFrom::Start(start))?;
+
+        let visitor = MetadataVisitor(self.load_visitor());
+        let (image, mut metadata) =
+            free_functions::load_decoder(self.inner, format, self.limits, visitor)?;
+        if metadata.xmp().is_none() {
+            metadata.set_xmp(xmp);
+        }
+        Ok((orient(image, orientation), metadata))
+    }
+
+    /// Read the image into `buf`, returning its dimensions and color type.
+    ///
+    /// This decodes straight into an existing frame buffer, GPU staging buffer or region of
+    /// shared memory, without allocating an image. The samples are laid out as in the buffer of
+    /// a `DynamicImage`, row by row in native endian. The length the buffer needs is the product
+    /// of the dimensions and the bytes per pixel of the color type, which are known from a
+    /// decoder or a previous image of the same source.
+    ///
+    /// ```no_run
+    /// use image::io::Reader;
+    ///
+    /// let mut frame = vec![0; 1920 * 1080 * 3];
+    /// let (width, height, color_type) = Reader::open("frame.png")?.decode_into(&mut frame)?;
+    /// # Ok::<(), image::ImageError>(())
+    /// ```
+    ///
+    /// The image is neither oriented nor downscaled, as both would need a second buffer. Images
+    /// that the limits would downscale return an `ImageError::Limits` instead. The buffer does
+    /// not count towards the `max_alloc` limit, as it is not allocated while decoding.
+    ///
+    /// Returns an `ImageError::Parameter` if the length of `buf` does not match the image. If no
+    /// format was determined, returns an `ImageError::Unsupported`.
+    pub fn decode_into(mut self, buf: &mut [u8]) -> ImageResult<(u32, u32, ColorType)> {
+        let format = self.require_format()?;
+        let visitor = IntoBufferVisitor {
+            limits: self.limits.clone(),
+            buf,
+        };
+        free_functions::load_decoder(self.inner, format, self.limits, visitor)
+    }
+
+    fn load_visitor(&self) -> LoadVisitor {
+        LoadVisitor {
+            limits: self.limits.clone(),
+            allocator: self.allocator.clone(),
+        }
+    }
+
+    /// Read the EXIF orientation, leaving the reader at its current position.
+    fn read_orientation(&mut self, format: ImageFormat) -> io::Result<Option<u16>> {
+        let start = self.inner.stream_position()?;
+        // An image without readable metadata might still decode, leave errors to the decoder.
+        let orientation = super::exif::orientation(&mut self.inner, format).unwrap_or(None);
+        self.inner.seek(SeekFrom::Start(start))?;
+        Ok(orientation)
+    }
+
+    /// The orientation to apply to the decoded image, if the reader was built to do so.
+    fn applied_orientation(&mut self, format: ImageFormat) -> io::Result<Option<u16>> {
+        if self.apply_orientation {
+            self.read_orientation(format)
+        } else {
+            Ok(None)
+        }
     }
 
     fn require_format(&mut self) -> ImageResult<ImageFormat> {
@@ -237,3 +575,692 @@ impl<R: BufRead + Seek> Reader<R> {
         })
     }
 }
+
+/// Configures an [`ImageReader`] or an [`AsyncReader`] before creating it.
+///
+/// The same settings are available for both readers, and the builder can be cloned to create
+/// several readers configured alike.
+///
+/// [`ImageReader`]: type.ImageReader.html
+/// [`AsyncReader`]: struct.AsyncReader.html
+#[derive(Clone, Debug)]
+pub struct ReaderBuilder {
+    pub(super) format: Option<ImageFormat>,
+    pub(super) limits: super::Limits,
+    pub(super) apply_orientation: bool,
+    pub(super) offload: bool,
+    pub(super) allocator: Option<SharedAllocator>,
+}
+
+impl ReaderBuilder {
+    /// Create a builder with the default settings.
+    ///
+    /// No format is preset, the default limits apply, the orientation is not applied and async
+    /// readers decode on the blocking thread pool.
+    pub fn new() -> Self {
+        ReaderBuilder {
+            format: None,
+            limits: super::Limits::default(),
+            apply_orientation: false,
+            offload: true,
+            allocator: None,
+        }
+    }
+
+    /// Supply the format as which to interpret the read image.
+    ///
+    /// This takes precedence over the format guessed from the path when opening a file.
+    pub fn format(mut self, format: ImageFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
+    /// Set a custom set of decoding limits.
+    pub fn limits(mut self, limits: super::Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Disable all decoding limits.
+    pub fn no_limits(self) -> Self {
+        self.limits(super::Limits::no_limits())
+    }
+
+    /// Choose whether the EXIF orientation is applied when decoding.
+    ///
+    /// When enabled, decoded images are rotated and flipped into the orientation they are meant
+    /// to be displayed in, and the dimensions are reported accordingly. The orientation is read
+    /// from JPEG, PNG, TIFF and WebP files. This is disabled by default.
+    pub fn apply_orientation(mut self, apply: bool) -> Self {
+        self.apply_orientation = apply;
+        self
+    }
+
+    /// Choose whether async readers decode the image on the blocking thread pool of the runtime.
+    ///
+    /// This is enabled by default and has no effect on synchronous readers. See
+    /// [`AsyncReader::offload_decoding`].
+    ///
+    /// [`AsyncReader::offload_decoding`]: struct.AsyncReader.html#method.offload_decoding
+    pub fn offload_decoding(mut self, offload: bool) -> Self {
+        self.offload = offload;
+        self
+    }
+
+    /// Decode images into buffers supplied by `allocator`.
+    ///
+    /// See [`OutputBufferAllocator`] for recycling the buffers of decoded images. Async readers
+    /// allocate their buffers themselves.
+    ///
+    /// [`OutputBufferAllocator`]: trait.OutputBufferAllocator.html
+    pub fn allocator(mut self, allocator: Arc<dyn OutputBufferAllocator>) -> Self {
+        self.allocator = Some(SharedAllocator(allocator));
+        self
+    }
+
+    /// Create a reader of `buffered_reader`.
+    ///
+    /// Assumes the reader is already buffered. For optimal performance,
+    /// consider wrapping the reader with a `BufReader::new()`.
+    pub fn build<R: Read>(self, buffered_reader: R) -> Reader<R> {
+        Reader {
+            inner: buffered_reader,
+            format: self.format,
+            limits: self.limits,
+            apply_orientation: self.apply_orientation,
+            allocator: self.allocator,
+        }
+    }
+
+    /// Open a file to read. Unless set, the format will be guessed from path.
+    ///
+    /// This will not attempt any io operation on the opened file.
+    pub fn open<P>(self, path: P) -> io::Result<Reader<BufReader<File>>>
+    where
+        P: AsRef<Path>,
+    {
+        self.open_in(StdFileSystem, path)
+    }
+
+    /// Open a file of a [`FileSystem`] to read. Unless set, the format will be guessed from path.
+    ///
+    /// [`FileSystem`]: trait.FileSystem.html
+    pub fn open_in<S, P>(mut self, fs: S, path: P) -> io::Result<Reader<BufReader<S::File>>>
+    where
+        S: FileSystem,
+        P: AsRef<Path>,
+    {
+        let path = path.as_ref();
+        let file = fs.open(path)?;
+        self.format = self.format.or_else(|| ImageFormat::from_path(path).ok());
+        Ok(self.build(BufReader::new(file)))
+    }
+}
+
+impl Default for ReaderBuilder {
+    fn default() -> Self {
+        ReaderBuilder::new()
+    }
+}
+
+/// Apply an orientation read by the reader to the decoded image.
+fn orient(image: DynamicImage, orientation: Option<u16>) -> DynamicImage {
+    match orientation {
+        Some(orientation) => super::exif::apply(image, orientation),
+        None => image,
+    }
+}
+
+/// Statistics about decoding an image, returned by [`Reader::decode_with_stats`].
+///
+/// [`Reader::decode_with_stats`]: struct.Reader.html#method.decode_with_stats
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DecodeStats {
+    /// The format the image was decoded as.
+    pub format: ImageFormat,
+    /// The number of bytes read from the input. Bytes that a decoder reads again after seeking
+    /// back are counted again.
+    pub bytes_read: u64,
+    /// The size of the largest buffer allocated for the image, which is the buffer the pixels
+    /// are decoded into. Buffers that decoders use internally are not counted.
+    pub peak_buffer_bytes: u64,
+    /// The time spent reading the header of the image and creating its decoder.
+    pub header_time: Duration,
+    /// The time spent decoding the pixels of the image.
+    pub decode_time: Duration,
+}
+
+/// Decodes an image like `LoadVisitor` and measures the stages of decoding it.
+struct StatsVisitor<'c> {
+    load: LoadVisitor,
+    format: ImageFormat,
+    start: Instant,
+    bytes_read: &'c Cell<u64>,
+}
+
+impl DecoderVisitor for StatsVisitor<'_> {
+    type Result = (DynamicImage, DecodeStats);
+
+    fn visit_decoder<'a, D: ImageDecoder<'a>>(self, decoder: D) -> ImageResult<Self::Result> {
+        let header_time = self.start.elapsed();
+        let peak_buffer_bytes = decoder.total_bytes();
+        let start = Instant::now();
+        let image = self.load.visit_decoder(decoder)?;
+        let stats = DecodeStats {
+            format: self.format,
+            bytes_read: self.bytes_read.get(),
+            peak_buffer_bytes,
+            header_time,
+            decode_time: start.elapsed(),
+        };
+        Ok((image, stats))
+    }
+}
+
+/// Decodes an image together with its textual metadata.
+struct MetadataVisitor(LoadVisitor);
+
+impl DecoderVisitor for MetadataVisitor {
+    type Result = (DynamicImage, MetadataMap);
+
+    fn visit_decoder<'a, D: ImageDecoder<'a>>(self, mut decoder: D) -> ImageResult<Self::Result> {
+        let metadata = decoder.text_metadata();
+        let image = self.0.visit_decoder(decoder)?;
+        Ok((image, metadata))
+    }
+}
+
+/// Decodes an image into samples of the requested color type.
+struct ColorTypeVisitor {
+    load: LoadVisitor,
+    color_type: ColorType,
+}
+
+impl DecoderVisitor for ColorTypeVisitor {
+    type Result = DynamicImage;
+
+    fn visit_decoder<'a, D: ImageDecoder<'a>>(self, mut decoder: D) -> ImageResult<Self::Result> {
+        decoder.request_color_type(self.color_type);
+        let image = self.load.visit_decoder(decoder)?;
+        if image.color() == self.color_type {
+            Ok(image)
+        } else {
+            Ok(super::self_check::convert(&image, self.color_type))
+        }
+    }
+}
+
+/// Decodes an image into a buffer of the caller.
+struct IntoBufferVisitor<'b> {
+    limits: super::Limits,
+    buf: &'b mut [u8],
+}
+
+impl DecoderVisitor for IntoBufferVisitor<'_> {
+    type Result = (u32, u32, ColorType);
+
+    fn visit_decoder<'a, D: ImageDecoder<'a>>(self, mut decoder: D) -> ImageResult<Self::Result> {
+        let (width, height) = decoder.dimensions();
+        let color_type = decoder.color_type();
+        let bytes_per_pixel = u64::from(color_type.bytes_per_pixel());
+        if self
+            .limits
+            .downscaled_dimensions(width, height, bytes_per_pixel)
+            .is_some()
+        {
+            return Err(ImageError::Limits(LimitError::from_kind(
+                LimitErrorKind::DimensionError,
+            )));
+        }
+        if self.buf.len() as u64 != decoder.total_bytes() {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(format!(
+                    "the buffer holds {} bytes, but the {}x{} image of {:?} pixels needs {}",
+                    self.buf.len(),
+                    width,
+                    height,
+                    color_type,
+                    decoder.total_bytes()
+                )),
+            )));
+        }
+
+        decoder.set_limits(self.limits.clone())?;
+        decoder.read_image(self.buf)?;
+        self.limits.check_cancelled()?;
+        Ok((width, height, color_type))
+    }
+}
+
+/// A reader that counts the bytes read through it.
+struct CountingReader<'c, R> {
+    inner: R,
+    count: &'c Cell<u64>,
+}
+
+impl<R: Read> Read for CountingReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = self.inner.read(buf)?;
+        self.count.set(self.count.get() + len as u64);
+        Ok(len)
+    }
+}
+
+impl<R: BufRead> BufRead for CountingReader<'_, R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.count.set(self.count.get() + amt as u64);
+        self.inner.consume(amt);
+    }
+}
+
+impl<R: Seek> Seek for CountingReader<'_, R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::imageops::FilterType;
+
+    #[test]
+    #[cfg(all(feature = "mmap", feature = "bmp"))]
+    fn decode_from_mmap() {
+        let image = crate::RgbImage::from_fn(5, 3, |x, y| crate::Rgb([x as u8, y as u8, 7]));
+        // A name of its own per process, so that concurrent test runs do not share the file.
+        let path =
+            std::env::temp_dir().join(format!("image-decode-from-mmap-{}.bmp", std::process::id()));
+        image.save(&path).unwrap();
+
+        let reader = Reader::from_mmap(&path).unwrap();
+        assert_eq!(reader.format(), Some(ImageFormat::Bmp));
+        let decoded = reader.decode().unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(decoded.into_rgb8(), image);
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn decode_with_stats() {
+        let data = b"P5 3 2 255\n\x00\x01\x02\x03\x04\x05";
+        let reader = Reader::with_format(Cursor::new(&data[..]), ImageFormat::Pnm);
+        let (image, stats) = reader.decode_with_stats().unwrap();
+        assert_eq!(image.as_bytes(), &[0, 1, 2, 3, 4, 5]);
+        assert_eq!(stats.format, ImageFormat::Pnm);
+        assert_eq!(stats.bytes_read, data.len() as u64);
+        assert_eq!(stats.peak_buffer_bytes, 6);
+
+        let mut reader = Reader::with_format(Cursor::new(&data[..]), ImageFormat::Pnm);
+        reader.limits(crate::io::Limits {
+            max_alloc: Some(4),
+            ..crate::io::Limits::default()
+        });
+        assert!(reader.decode_with_stats().is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn decode_into() {
+        let data = b"P5 3 2 255\n\x00\x01\x02\x03\x04\x05";
+        let mut buf = [0xff; 6];
+        let reader = Reader::with_format(Cursor::new(&data[..]), ImageFormat::Pnm);
+        let layout = reader.decode_into(&mut buf).unwrap();
+        assert_eq!(layout, (3, 2, ColorType::L8));
+        assert_eq!(buf, [0, 1, 2, 3, 4, 5]);
+
+        let reader = Reader::with_format(Cursor::new(&data[..]), ImageFormat::Pnm);
+        assert!(matches!(
+            reader.decode_into(&mut [0; 5]),
+            Err(ImageError::Parameter(_))
+        ));
+
+        let mut reader = Reader::with_format(Cursor::new(&data[..]), ImageFormat::Pnm);
+        reader.limits(crate::io::Limits {
+            max_image_width: Some(2),
+            ..crate::io::Limits::default()
+        });
+        assert!(matches!(
+            reader.decode_into(&mut buf),
+            Err(ImageError::Limits(_))
+        ));
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn decode_with_exif() {
+        use crate::ImageEncoder;
+
+        let exif = Exif {
+            orientation: Some(Orientation::Rotate90),
+            model: Some("Model 1".to_string()),
+            ..Exif::default()
+        };
+        let mut png = Vec::new();
+        let mut encoder = crate::codecs::png::PngEncoder::new(&mut png);
+        encoder.set_exif_metadata(&exif).unwrap();
+        encoder
+            .write_image(&[0; 6], 3, 2, crate::ColorType::L8)
+            .unwrap();
+
+        let reader = Reader::with_format(Cursor::new(&png), ImageFormat::Png);
+        let (image, decoded) = reader.decode_with_exif().unwrap();
+        assert_eq!((image.width(), image.height()), (3, 2));
+        assert_eq!(decoded.as_ref(), Some(&exif));
+
+        let reader = ImageReader::builder()
+            .format(ImageFormat::Png)
+            .apply_orientation(true)
+            .build(Cursor::new(&png));
+        let (image, decoded) = reader.decode_with_exif().unwrap();
+        assert_eq!((image.width(), image.height()), (2, 3));
+        let decoded = decoded.unwrap();
+        assert_eq!(decoded.orientation, Some(Orientation::NoTransforms));
+        assert_eq!(decoded.model, exif.model);
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn decode_as() {
+        let image = crate::RgbImage::from_fn(5, 3, |x, y| crate::Rgb([x as u8 * 50, y as u8, 7]));
+        let image = DynamicImage::ImageRgb8(image);
+        let mut png = Vec::new();
+        image.write_to(&mut png, ImageFormat::Png).unwrap();
+
+        let decode_as = |color_type| {
+            let reader = Reader::with_format(Cursor::new(&png), ImageFormat::Png);
+            reader.decode_as(color_type).unwrap()
+        };
+        // Converted by the decoder.
+        assert_eq!(
+            decode_as(ColorType::Rgba8),
+            DynamicImage::ImageRgba8(image.to_rgba8())
+        );
+        assert_eq!(
+            decode_as(ColorType::L8),
+            DynamicImage::ImageLuma8(image.to_luma8())
+        );
+        assert_eq!(decode_as(ColorType::Rgb8), image);
+        // Converted after decoding.
+        assert_eq!(
+            decode_as(ColorType::Rgb16),
+            DynamicImage::ImageRgb16(image.to_rgb16())
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn decode_with_metadata() {
+        use crate::codecs::png::PngEncoder;
+        use crate::ImageEncoder;
+
+        let mut metadata = MetadataMap::new();
+        metadata.insert("Author", "Someone");
+        metadata.set_xmp(Some("<x:xmpmeta xmlns:x=\"adobe:ns:meta/\"/>".to_string()));
+        let mut png = Vec::new();
+        let mut encoder = PngEncoder::new(&mut png);
+        encoder.set_text_metadata(&metadata).unwrap();
+        encoder.write_image(&[1, 2], 2, 1, ColorType::L8).unwrap();
+
+        let reader = Reader::with_format(Cursor::new(&png), ImageFormat::Png);
+        let (image, decoded) = reader.decode_with_metadata().unwrap();
+        assert_eq!(image.as_bytes(), &[1, 2]);
+        assert_eq!(decoded, metadata);
+    }
+
+    #[test]
+    #[cfg(all(feature = "png", feature = "jpeg", feature = "bmp"))]
+    fn preserves_icc_profiles() {
+        let icc_profile: Vec<u8> = (0..300).map(|i| (i % 256) as u8).collect();
+        let image = DynamicImage::ImageRgba8(crate::RgbaImage::new(3, 2));
+        let mut png = Vec::new();
+        image
+            .write_to_with_icc_profile(&mut png, ImageFormat::Png, &icc_profile)
+            .unwrap();
+
+        let reader = Reader::with_format(Cursor::new(&png), ImageFormat::Png);
+        let (image, decoded) = reader.decode_with_icc_profile().unwrap();
+        assert_eq!(decoded.as_deref(), Some(&icc_profile[..]));
+
+        // The alpha channel is composited away for JPEG, the profile is kept.
+        let mut jpeg = Vec::new();
+        image
+            .write_to_with_icc_profile(&mut jpeg, ImageFormat::Jpeg, &icc_profile)
+            .unwrap();
+        let reader = Reader::with_format(Cursor::new(&jpeg), ImageFormat::Jpeg);
+        let (_, decoded) = reader.decode_with_icc_profile().unwrap();
+        assert_eq!(decoded, Some(icc_profile.clone()));
+
+        let mut bmp = Vec::new();
+        let result = image.write_to_with_icc_profile(&mut bmp, ImageFormat::Bmp, &icc_profile);
+        assert!(matches!(result, Err(ImageError::Unsupported(_))));
+
+        // Decoded images hold their profile, which is written again where the format can.
+        let reader = Reader::with_format(Cursor::new(&jpeg), ImageFormat::Jpeg);
+        let image = reader
+            .decode()
+            .unwrap()
+            .resize_exact(2, 1, FilterType::Nearest);
+        assert_eq!(image.icc_profile(), Some(&icc_profile[..]));
+        let mut png = Vec::new();
+        image.write_to(&mut png, ImageFormat::Png).unwrap();
+        let decoded = crate::load_from_memory_with_format(&png, ImageFormat::Png).unwrap();
+        assert_eq!(decoded.icc_profile(), Some(&icc_profile[..]));
+        let mut bmp = Vec::new();
+        image.write_to(&mut bmp, ImageFormat::Bmp).unwrap();
+        assert!(image.to_rgba8().icc_profile().is_none());
+
+        let mut image = image;
+        image.set_icc_profile(None);
+        let mut png = Vec::new();
+        image.write_to(&mut png, ImageFormat::Png).unwrap();
+        let reader = Reader::with_format(Cursor::new(&png), ImageFormat::Png);
+        assert_eq!(reader.decode_with_icc_profile().unwrap().1, None);
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn builder_configures_reader() {
+        let data = b"P5 3 2 255\n\x00\x01\x02\x03\x04\x05";
+        let limits = crate::io::Limits {
+            max_alloc: Some(4),
+            ..crate::io::Limits::default()
+        };
+        let builder = ImageReader::builder()
+            .format(ImageFormat::Pnm)
+            .limits(limits);
+        assert_eq!(
+            builder.clone().build(&data[..]).format(),
+            Some(ImageFormat::Pnm)
+        );
+        assert!(builder
+            .clone()
+            .build(Cursor::new(&data[..]))
+            .decode()
+            .is_err());
+
+        let image = builder
+            .no_limits()
+            .build(Cursor::new(&data[..]))
+            .decode()
+            .unwrap();
+        assert_eq!(image.as_bytes(), &[0, 1, 2, 3, 4, 5]);
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn decode_into_recycled_buffer() {
+        use std::sync::Mutex;
+
+        #[derive(Default)]
+        struct Pool(Mutex<Vec<Vec<u8>>>);
+
+        impl OutputBufferAllocator for Pool {
+            fn allocate_u8(&self, len: usize) -> Vec<u8> {
+                self.0.lock().unwrap().pop().unwrap_or_else(|| vec![0; len])
+            }
+        }
+
+        let data = b"P5 3 2 255\n\x00\x01\x02\x03\x04\x05";
+        let pool = Arc::new(Pool::default());
+        let recycled = Vec::with_capacity(64);
+        let recycled_ptr = recycled.as_ptr();
+        pool.0.lock().unwrap().push(recycled);
+
+        let image = ImageReader::builder()
+            .format(ImageFormat::Pnm)
+            .allocator(pool.clone())
+            .build(Cursor::new(&data[..]))
+            .decode()
+            .unwrap();
+        assert!(pool.0.lock().unwrap().is_empty());
+        assert_eq!(image.as_bytes(), &[0, 1, 2, 3, 4, 5]);
+        assert_eq!(image.as_bytes().as_ptr(), recycled_ptr);
+
+        // Buffers of other sample types are allocated by default.
+        let data = b"P5 1 1 65535\n\x01\x02";
+        let mut reader = ImageReader::with_format(Cursor::new(&data[..]), ImageFormat::Pnm);
+        reader.allocator(pool);
+        assert_eq!(reader.decode().unwrap().as_bytes().len(), 2);
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn downscales_images_over_limits() {
+        let data = b"P5 6 4 255\n\
+            \x00\x00\x40\x40\x80\x80\x00\x00\x40\x40\x80\x80\
+            \xc0\xc0\xff\xff\x20\x20\xc0\xc0\xff\xff\x20\x20";
+        let mut limits = crate::io::Limits {
+            max_image_width: Some(3),
+            ..crate::io::Limits::default()
+        };
+        let decode = |limits: &crate::io::Limits| {
+            let mut reader = Reader::with_format(Cursor::new(&data[..]), ImageFormat::Pnm);
+            reader.limits(limits.clone());
+            reader.decode()
+        };
+        assert!(decode(&limits).is_err());
+
+        limits.max_megapixels_with_downscale = Some(1);
+        let image = decode(&limits).unwrap();
+        assert_eq!((image.width(), image.height()), (3, 2));
+
+        // Images that fit are decoded as they are.
+        limits.max_image_width = None;
+        assert_eq!(decode(&limits).unwrap().as_bytes(), &data[11..]);
+
+        assert_eq!(
+            limits.downscaled_dimensions(2000, 1000, 1),
+            Some((1414, 707))
+        );
+        limits.max_alloc = Some(3 * 1000 * 500);
+        assert_eq!(
+            limits.downscaled_dimensions(2000, 1000, 3),
+            Some((1000, 500))
+        );
+        assert_eq!(limits.downscaled_dimensions(0, 1000, 3), None);
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn decode_cancelled() {
+        use crate::error::LimitErrorKind;
+        use crate::io::CancelToken;
+
+        /// Cancels the token once the decoder has read past the header.
+        struct CancelAfterHeader {
+            inner: Cursor<Vec<u8>>,
+            token: CancelToken,
+        }
+
+        impl Read for CancelAfterHeader {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.fill_buf()?;
+                self.inner.read(buf)
+            }
+        }
+
+        impl BufRead for CancelAfterHeader {
+            fn fill_buf(&mut self) -> io::Result<&[u8]> {
+                if self.inner.position() > 100 {
+                    self.token.cancel();
+                }
+                self.inner.fill_buf()
+            }
+
+            fn consume(&mut self, amt: usize) {
+                self.inner.consume(amt);
+            }
+        }
+
+        impl Seek for CancelAfterHeader {
+            fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+                self.inner.seek(pos)
+            }
+        }
+
+        // Noise, so that the image data is read in several parts.
+        let image = crate::GrayImage::from_fn(512, 512, |x, y| {
+            let hash = (x.wrapping_mul(2_654_435_761) ^ y).wrapping_mul(2_246_822_519);
+            crate::Luma([(hash >> 24) as u8])
+        });
+        let mut png = Vec::new();
+        DynamicImage::ImageLuma8(image)
+            .write_to(&mut png, ImageFormat::Png)
+            .unwrap();
+        let error_kind = |result: ImageResult<DynamicImage>| match result {
+            Err(ImageError::Limits(err)) => Some(err.kind()),
+            _ => None,
+        };
+
+        let token = CancelToken::new();
+        let mut limits = crate::io::Limits {
+            cancel_token: Some(token.clone()),
+            ..crate::io::Limits::default()
+        };
+        let reader = ReaderBuilder::new()
+            .format(ImageFormat::Png)
+            .limits(limits.clone())
+            .build(CancelAfterHeader {
+                inner: Cursor::new(png.clone()),
+                token,
+            });
+        assert_eq!(error_kind(reader.decode()), Some(LimitErrorKind::Cancelled));
+
+        limits.cancel_token = None;
+        limits.deadline = Some(Instant::now());
+        let reader = ReaderBuilder::new()
+            .format(ImageFormat::Png)
+            .limits(limits)
+            .build(Cursor::new(&png));
+        assert_eq!(
+            error_kind(reader.decode()),
+            Some(LimitErrorKind::DeadlineExceeded)
+        );
+    }
+
+    #[test]
+    fn guesses_formats_past_a_prolog() {
+        let guess = |data: &[u8]| {
+            Reader::new(Cursor::new(data))
+                .with_guessed_format()
+                .unwrap()
+                .format()
+        };
+        let svg = b"\xef\xbb\xbf<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+            <!-- Created with an editor -->\n\
+            <svg xmlns=\"http://www.w3.org/2000/svg\" width=\"4\" height=\"4\"/>";
+        assert_eq!(guess(svg), Some(ImageFormat::Svg));
+        assert_eq!(guess(b"  <svg/>"), Some(ImageFormat::Svg));
+        assert_eq!(guess(b"<?xml version=\"1.0\"?><html/>"), None);
+
+        let mut dicom = vec![0; 128];
+        dicom.extend_from_slice(b"DICM");
+        assert_eq!(guess(&dicom), Some(ImageFormat::Dicom));
+    }
+}
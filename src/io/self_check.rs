@@ -0,0 +1,313 @@
+use std::cell::RefCell;
+use std::io::{self, Cursor, Seek, SeekFrom, Write};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use super::free_functions::{self, EncoderOutput, WriteSeek};
+use crate::color::ColorType;
+use crate::dynimage::DynamicImage;
+use crate::error::{EncodingError, ImageError, ImageFormatHint, ImageResult};
+use crate::image::{ImageEncoder, ImageFormat, ImageOutputFormat};
+use crate::io::Limits;
+use crate::metadata::{Exif, MetadataMap};
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Choose whether encoded images are decoded again and checked against their source.
+///
+/// When enabled, every image written through the functions of this crate, such as `save`,
+/// `save_buffer`, `DynamicImage::write_to` and `AsyncWriter`, or through an encoder returned by
+/// `encoder_for`, is first encoded into memory and then decoded again. The output is only written if the decoded image has the dimensions of the
+/// source and keeps its color and alpha channels. For lossless formats the bit depth and the
+/// pixels must match as well. Otherwise an `ImageError::Encoding` describing the difference is
+/// returned. Formats that can not be decoded with the enabled features are not checked.
+///
+/// This is intended for debug builds and test suites, to catch encoders silently corrupting
+/// images at the boundary of a pipeline. It is disabled by default as it roughly doubles the
+/// cost of writing an image. The setting applies to the whole process, `EncoderOptions` can
+/// override it for a single encoder. Encoders that are created directly, such as
+/// `PngEncoder::new`, are not checked.
+pub fn set_encoder_self_check(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether encoded images are decoded again and checked against their source.
+///
+/// See [`set_encoder_self_check`].
+///
+/// [`set_encoder_self_check`]: fn.set_encoder_self_check.html
+pub fn encoder_self_check() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Check that `encoded` decodes to the image described by the other arguments.
+pub(crate) fn verify(
+    encoded: &[u8],
+    buf: &[u8],
+    width: u32,
+    height: u32,
+    color: ColorType,
+    format: &ImageOutputFormat,
+) -> ImageResult<()> {
+    let (format, lossless) = match decoding_format(format) {
+        Some(decoding) => decoding,
+        None => return Ok(()),
+    };
+    let mismatch = |message: String| {
+        Err(ImageError::Encoding(EncodingError::new(
+            ImageFormatHint::Exact(format),
+            format!("self-check of the encoded image failed: {}", message),
+        )))
+    };
+
+    let decoded = match free_functions::load(Cursor::new(encoded), format) {
+        Ok(decoded) => decoded,
+        Err(ImageError::Unsupported(_)) => return Ok(()),
+        Err(err) => return mismatch(format!("the output can not be decoded ({})", err)),
+    };
+    if (decoded.width(), decoded.height()) != (width, height) {
+        return mismatch(format!(
+            "encoded {}x{} pixels but decoded {}x{}",
+            width,
+            height,
+            decoded.width(),
+            decoded.height()
+        ));
+    }
+    let decoded_color = decoded.color();
+    let bits = |color: ColorType| color.bytes_per_pixel() / color.channel_count();
+    if (color.has_color() && !decoded_color.has_color())
+        || (color.has_alpha() && !decoded_color.has_alpha())
+        || (lossless && bits(decoded_color) < bits(color))
+    {
+        return mismatch(format!(
+            "encoded {:?} but decoded {:?}",
+            color, decoded_color
+        ));
+    }
+    if !lossless {
+        return Ok(());
+    }
+
+    let source = match free_functions::image_from_bytes(buf, width, height, color) {
+        Some(source) => convert(&source, decoded_color),
+        None => return Ok(()),
+    };
+    if source.as_bytes() != decoded.as_bytes() {
+        return mismatch("the decoded pixels differ from the source".to_string());
+    }
+    Ok(())
+}
+
+/// The format to decode an encoded image with, and whether it is expected to be lossless.
+#[allow(unreachable_patterns)]
+// Default is unreachable if all features are supported.
+fn decoding_format(format: &ImageOutputFormat) -> Option<(ImageFormat, bool)> {
+    Some(match format {
+        #[cfg(feature = "png")]
+        ImageOutputFormat::Png => (ImageFormat::Png, true),
+        #[cfg(feature = "jpeg")]
+        ImageOutputFormat::Jpeg(_) => (ImageFormat::Jpeg, false),
+        #[cfg(feature = "pnm")]
+        ImageOutputFormat::Pnm(subtype) => {
+            use crate::codecs::pnm::PnmSubtype;
+            // Bitmaps only store black and white.
+            let lossless = !matches!(subtype, PnmSubtype::Bitmap(_));
+            (ImageFormat::Pnm, lossless)
+        }
+        // Images with more than 256 colors are quantized.
+        #[cfg(feature = "gif")]
+        ImageOutputFormat::Gif => (ImageFormat::Gif, false),
+        #[cfg(feature = "ico")]
+        ImageOutputFormat::Ico => (ImageFormat::Ico, true),
+        #[cfg(feature = "icns")]
+        ImageOutputFormat::Icns => (ImageFormat::Icns, true),
+        #[cfg(feature = "bmp")]
+        ImageOutputFormat::Bmp => (ImageFormat::Bmp, true),
+        #[cfg(feature = "farbfeld")]
+        ImageOutputFormat::Farbfeld => (ImageFormat::Farbfeld, true),
+        #[cfg(feature = "tga")]
+        ImageOutputFormat::Tga => (ImageFormat::Tga, true),
+        #[cfg(feature = "openexr")]
+        ImageOutputFormat::OpenExr => (ImageFormat::OpenExr, true),
+        #[cfg(feature = "tiff")]
+        ImageOutputFormat::Tiff => (ImageFormat::Tiff, true),
+        #[cfg(all(feature = "avif-encoder", feature = "avif-decoder"))]
+        ImageOutputFormat::Avif => (ImageFormat::Avif, false),
+        #[cfg(all(feature = "avif-encoder", feature = "avif-decoder"))]
+        ImageOutputFormat::AvifWithOptions(options) => (ImageFormat::Avif, options.lossless),
+        #[cfg(feature = "fits")]
+        ImageOutputFormat::Fits => (ImageFormat::Fits, true),
+        #[cfg(feature = "webp")]
+        ImageOutputFormat::WebP => (ImageFormat::WebP, true),
+        #[cfg(feature = "qoi")]
+        ImageOutputFormat::Qoi => (ImageFormat::Qoi, true),
+        _ => return None,
+    })
+}
+
+/// An encoder that encodes into memory, and only writes the output if it passes [`verify`].
+pub(crate) struct SelfChecked<W> {
+    encoder: Box<dyn ImageEncoder>,
+    encoded: SharedBuffer,
+    format: ImageOutputFormat,
+    writer: W,
+}
+
+impl<W: Write> SelfChecked<W> {
+    pub(crate) fn new(writer: W, format: ImageOutputFormat) -> ImageResult<Self> {
+        let encoded = SharedBuffer::default();
+        let encoder = free_functions::boxed_encoder(encoded.clone(), format.clone())?;
+        Ok(SelfChecked {
+            encoder,
+            encoded,
+            format,
+            writer,
+        })
+    }
+}
+
+impl<W: Write> ImageEncoder for SelfChecked<W> {
+    fn write_image(
+        mut self,
+        buf: &[u8],
+        width: u32,
+        height: u32,
+        color_type: ColorType,
+    ) -> ImageResult<()> {
+        self.encoder.write_image(buf, width, height, color_type)?;
+        let encoded = self.encoded.0.borrow();
+        verify(
+            encoded.get_ref(),
+            buf,
+            width,
+            height,
+            color_type,
+            &self.format,
+        )?;
+        self.writer.write_all(encoded.get_ref())?;
+        Ok(())
+    }
+
+    fn set_icc_profile(&mut self, icc_profile: Vec<u8>) -> ImageResult<()> {
+        self.encoder.set_icc_profile(icc_profile)
+    }
+
+    fn set_exif_metadata(&mut self, exif: &Exif) -> ImageResult<()> {
+        self.encoder.set_exif_metadata(exif)
+    }
+
+    fn set_text_metadata(&mut self, metadata: &MetadataMap) -> ImageResult<()> {
+        self.encoder.set_text_metadata(metadata)
+    }
+}
+
+/// The memory a [`SelfChecked`] encoder lets its inner encoder write to.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Cursor<Vec<u8>>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Seek for SharedBuffer {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.borrow_mut().seek(pos)
+    }
+}
+
+impl EncoderOutput for SharedBuffer {
+    fn seekable(&mut self) -> Result<&mut dyn WriteSeek, &Limits> {
+        Ok(self)
+    }
+}
+
+/// Convert the samples of `image` to color type `color`.
+pub(super) fn convert(image: &DynamicImage, color: ColorType) -> DynamicImage {
+    match color {
+        ColorType::L8 => DynamicImage::ImageLuma8(image.to_luma8()),
+        ColorType::La8 => DynamicImage::ImageLumaA8(image.to_luma_alpha8()),
+        ColorType::Rgb8 => DynamicImage::ImageRgb8(image.to_rgb8()),
+        ColorType::Rgba8 => DynamicImage::ImageRgba8(image.to_rgba8()),
+        ColorType::L16 => DynamicImage::ImageLuma16(image.to_luma16()),
+        ColorType::La16 => DynamicImage::ImageLumaA16(image.to_luma_alpha16()),
+        ColorType::Rgb16 => DynamicImage::ImageRgb16(image.to_rgb16()),
+        ColorType::Rgba16 => DynamicImage::ImageRgba16(image.to_rgba16()),
+        ColorType::Rgb32F => DynamicImage::ImageRgb32F(image.to_rgb32f()),
+        ColorType::Rgba32F => DynamicImage::ImageRgba32F(image.to_rgba32f()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn detects_mismatches() {
+        use crate::codecs::pnm::{PnmSubtype, SampleEncoding};
+
+        let pixels = [0u8, 64, 128, 255, 32, 16];
+        let format = ImageOutputFormat::Pnm(PnmSubtype::Graymap(SampleEncoding::Ascii));
+        let mut encoded = Vec::new();
+        free_functions::write_buffer_impl(
+            &mut free_functions::Seekable(Cursor::new(&mut encoded)),
+            &pixels,
+            3,
+            2,
+            ColorType::L8,
+            format.clone(),
+            free_functions::DEFAULT_MATTE,
+        )
+        .unwrap();
+        verify(&encoded, &pixels, 3, 2, ColorType::L8, &format).unwrap();
+
+        assert!(verify(&encoded, &pixels, 2, 3, ColorType::L8, &format).is_err());
+        assert!(verify(&encoded, &[0; 12], 3, 2, ColorType::La8, &format).is_err());
+        let mut other = pixels;
+        other[4] = 33;
+        assert!(verify(&encoded, &other, 3, 2, ColorType::L8, &format).is_err());
+        assert!(verify(b"P2 garbage", &pixels, 3, 2, ColorType::L8, &format).is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "pnm", feature = "farbfeld"))]
+    fn checks_written_images() {
+        use crate::codecs::pnm::{PnmSubtype, SampleEncoding};
+        use crate::EncoderOptions;
+
+        let pixels = [0u8, 64, 128, 255, 32, 16];
+        let options = EncoderOptions {
+            pnm_subtype: Some(PnmSubtype::Graymap(SampleEncoding::Binary)),
+            self_check: Some(true),
+            ..Default::default()
+        };
+        let mut encoded = Vec::new();
+        let encoder = crate::encoder_for(ImageFormat::Pnm, &mut encoded, options).unwrap();
+        encoder.write_image(&pixels, 3, 2, ColorType::L8).unwrap();
+        assert_eq!(
+            crate::load_from_memory(&encoded).unwrap().as_bytes(),
+            &pixels
+        );
+
+        // Nothing is written if the check fails, here as the output is decoded as another format.
+        let format = ImageOutputFormat::Pnm(PnmSubtype::Graymap(SampleEncoding::Binary));
+        let buffer = SharedBuffer::default();
+        let mut encoded = Vec::new();
+        let encoder = SelfChecked {
+            encoder: free_functions::boxed_encoder(buffer.clone(), format).unwrap(),
+            encoded: buffer,
+            format: ImageOutputFormat::Farbfeld,
+            writer: &mut encoded,
+        };
+        let result = encoder.write_image(&pixels, 3, 2, ColorType::L8);
+        assert!(matches!(result, Err(ImageError::Encoding(_))));
+        assert!(encoded.is_empty());
+    }
+}
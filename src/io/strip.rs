@@ -0,0 +1,143 @@
+use std::io::{Cursor, Read, Write};
+
+use crate::error::{ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use crate::metadata::StripPreset;
+use crate::{ImageError, ImageResult};
+
+#[allow(unused_imports)] // When no format supports stripping
+use crate::image::ImageFormat;
+
+use super::free_functions;
+
+/// Remove the metadata of an encoded image without decoding it.
+///
+/// The format of `r` is guessed from its content. The metadata that `preset` does not keep is
+/// removed while the image is copied to `w`, all other bytes are copied unchanged. The image
+/// data is neither decoded nor encoded again, so the pixels are exactly the same and stripping
+/// runs at the speed of copying the file. Images appended to a JPEG file, such as the views of a
+/// multi-picture file or a gain map, are dropped, as they carry metadata of their own. JPEG and
+/// PNG images are supported, others return an `ImageError::Unsupported`.
+///
+/// # Examples
+///
+/// Remove the location and all other metadata from a photo before publishing it, but keep it
+/// upright and its colors intact.
+///
+/// ```no_run
+/// use image::metadata::StripPreset;
+///
+/// let input = std::io::BufReader::new(std::fs::File::open("photo.jpg")?);
+/// let output = std::fs::File::create("public.jpg")?;
+/// image::strip_metadata(input, output, StripPreset::KeepOrientation)?;
+/// # Ok::<(), image::ImageError>(())
+/// ```
+///
+/// See `codecs::jpeg::edit_segments` and `codecs::png::edit_chunks` for finer control.
+#[allow(unused_variables)] // When no format supports stripping
+pub fn strip_metadata<R: Read, W: Write>(mut r: R, w: W, preset: StripPreset) -> ImageResult<()> {
+    let mut signature = Vec::new();
+    r.by_ref().take(16).read_to_end(&mut signature)?;
+    let format = free_functions::guess_format_impl(&signature);
+    let r = Cursor::new(signature).chain(r);
+    match format {
+        #[cfg(feature = "jpeg")]
+        Some(ImageFormat::Jpeg) => crate::codecs::jpeg::strip_with_preset(r, w, preset),
+        #[cfg(feature = "png")]
+        Some(ImageFormat::Png) => crate::codecs::png::strip_with_preset(r, w, preset),
+        format => {
+            let hint = format.map_or(ImageFormatHint::Unknown, ImageFormatHint::Exact);
+            Err(ImageError::Unsupported(
+                UnsupportedError::from_format_and_kind(
+                    hint,
+                    UnsupportedErrorKind::GenericFeature("stripping metadata".to_string()),
+                ),
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image::ImageEncoder;
+    use crate::metadata::{Exif, Orientation};
+    use crate::ColorType;
+
+    fn photo_exif() -> Exif {
+        Exif {
+            orientation: Some(Orientation::Rotate90),
+            make: Some("Camera".to_string()),
+            ..Exif::default()
+        }
+    }
+
+    fn read_exif(data: &[u8]) -> Option<Exif> {
+        let reader = super::super::Reader::new(Cursor::new(data))
+            .with_guessed_format()
+            .unwrap();
+        reader.decode_with_exif().unwrap().1
+    }
+
+    fn read_icc_profile(data: &[u8]) -> Option<Vec<u8>> {
+        let reader = super::super::Reader::new(Cursor::new(data))
+            .with_guessed_format()
+            .unwrap();
+        reader.decode_with_icc_profile().unwrap().1
+    }
+
+    fn check_presets(encoded: &[u8]) {
+        assert_eq!(read_exif(encoded), Some(photo_exif()));
+
+        let mut stripped = Vec::new();
+        strip_metadata(encoded, &mut stripped, StripPreset::KeepOrientation).unwrap();
+        let exif = read_exif(&stripped).unwrap();
+        assert_eq!(exif.orientation, Some(Orientation::Rotate90));
+        assert_eq!(exif.make, None);
+        assert!(read_icc_profile(&stripped).is_some());
+
+        let mut stripped = Vec::new();
+        strip_metadata(encoded, &mut stripped, StripPreset::KeepColorProfile).unwrap();
+        assert_eq!(read_exif(&stripped), None);
+        assert!(read_icc_profile(&stripped).is_some());
+
+        let mut stripped = Vec::new();
+        strip_metadata(encoded, &mut stripped, StripPreset::All).unwrap();
+        assert_eq!(read_exif(&stripped), None);
+        assert_eq!(read_icc_profile(&stripped), None);
+        let before = crate::load_from_memory(encoded).unwrap();
+        let after = crate::load_from_memory(&stripped).unwrap();
+        assert_eq!(before.as_bytes(), after.as_bytes());
+    }
+
+    #[test]
+    #[cfg(feature = "jpeg")]
+    fn strips_jpeg() {
+        let mut encoded = Vec::new();
+        let mut encoder = crate::codecs::jpeg::JpegEncoder::new(&mut encoded);
+        encoder.set_exif_metadata(&photo_exif()).unwrap();
+        encoder.set_icc_profile(vec![1; 300]).unwrap();
+        encoder
+            .write_image(&[0x40; 8 * 8 * 3], 8, 8, ColorType::Rgb8)
+            .unwrap();
+        check_presets(&encoded);
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn strips_png() {
+        let mut encoded = Vec::new();
+        let mut encoder = crate::codecs::png::PngEncoder::new(&mut encoded);
+        encoder.set_exif_metadata(&photo_exif()).unwrap();
+        encoder.set_icc_profile(vec![1; 300]).unwrap();
+        encoder
+            .write_image(&[0x40; 8 * 8 * 4], 8, 8, ColorType::Rgba8)
+            .unwrap();
+        check_presets(&encoded);
+    }
+
+    #[test]
+    fn unsupported_format() {
+        let result = strip_metadata(&b"P5 1 1 255\n\0"[..], Vec::new(), StripPreset::All);
+        assert!(matches!(result, Err(ImageError::Unsupported(_))));
+    }
+}
@@ -0,0 +1,286 @@
+use std::io::{BufRead, Cursor, Seek, SeekFrom};
+
+#[cfg(feature = "gif")]
+use crate::codecs::gif::{Dithering, GifDecoder, GifEncoder, Quantizer};
+#[cfg(feature = "png")]
+use crate::codecs::png::{PngDecoder, PngEncoder};
+use crate::dynimage::{load_from_memory_with_format, write_buffer_with_format};
+use crate::error::{EncodingError, ImageFormatHint, UnsupportedError, UnsupportedErrorKind};
+use crate::image::ImageFormat;
+#[cfg(feature = "gif")]
+use crate::palette::Palette;
+#[cfg(feature = "gif")]
+use crate::Rgb;
+use crate::{ColorType, DynamicImage, ImageError, ImageResult, Rgba};
+
+use super::Reader;
+
+/// Convert an encoded image into another format without losing any information.
+///
+/// The format of `src` is guessed from its content. If it already is in the requested `format`
+/// then its bytes are returned unchanged, which also keeps format specific details such as a
+/// palette intact. Otherwise the image is decoded and encoded again in its original color type,
+/// no conversion is performed. A 16-bit image thus stays 16-bit and the transcoding fails if the
+/// target format can not represent the color type of the source.
+///
+/// The palette of an indexed PNG, or the global palette of a GIF, is carried over in its order
+/// when the target is PNG or GIF, though GIF pads it to a power of two. Other targets, such as
+/// QOI, store the colors of the pixels instead. Palettes of other source formats are not kept.
+///
+/// The encoded result is decoded once more and compared to the source pixels. An
+/// `ImageError::Encoding` is returned if they are not identical. Only an opaque 8-bit RGBA image
+/// may come back as RGB, or the other way around, as GIF always decodes with alpha and a PNG
+/// palette only has alpha if some of its colors are translucent. Formats which are lossy by
+/// design, such as JPEG, are rejected with an `ImageError::Unsupported` up front.
+///
+/// This is intended for migrating archives between formats where a silent loss of data is not
+/// acceptable.
+pub fn transcode_lossless<R: BufRead + Seek>(
+    mut src: R,
+    format: ImageFormat,
+) -> ImageResult<Vec<u8>> {
+    if is_lossy(format) {
+        return Err(ImageError::Unsupported(
+            UnsupportedError::from_format_and_kind(
+                format.into(),
+                UnsupportedErrorKind::GenericFeature(format!("lossless encoding to {:?}", format)),
+            ),
+        ));
+    }
+
+    let start = src.stream_position()?;
+    let reader = Reader::new(src).with_guessed_format()?;
+    if reader.format() == Some(format) {
+        let mut src = reader.into_inner();
+        let mut bytes = Vec::new();
+        src.seek(SeekFrom::Start(start))?;
+        src.read_to_end(&mut bytes)?;
+        return Ok(bytes);
+    }
+
+    let source = reader.format();
+    let mut src = reader.into_inner();
+    src.seek(SeekFrom::Start(start))?;
+    let palette = source_palette(&mut src, source)?;
+    src.seek(SeekFrom::Start(start))?;
+    let mut reader = Reader::new(src);
+    if let Some(source) = source {
+        reader.set_format(source);
+    }
+
+    let image = reader.decode()?;
+    let (width, height) = (image.width(), image.height());
+    let encoded = match palette {
+        Some(palette) => match encode_with_palette(&image, format, &palette) {
+            Err(ImageError::Parameter(_)) => encode(&image, format)?,
+            encoded => encoded?,
+        },
+        None => encode(&image, format)?,
+    };
+
+    let roundtrip = load_from_memory_with_format(&encoded, format)?;
+    if (roundtrip.width(), roundtrip.height()) != (width, height)
+        || !same_pixels(&roundtrip, &image)
+    {
+        return Err(ImageError::Encoding(EncodingError::new(
+            ImageFormatHint::Exact(format),
+            "transcoded image does not match the source pixels",
+        )));
+    }
+
+    Ok(encoded)
+}
+
+/// Whether both images hold the same pixels, allowing an opaque alpha channel to be added or
+/// dropped for 8-bit RGB.
+fn same_pixels(a: &DynamicImage, b: &DynamicImage) -> bool {
+    match (a.color(), b.color()) {
+        (ColorType::Rgb8, ColorType::Rgba8) | (ColorType::Rgba8, ColorType::Rgb8) => {
+            a.to_rgba8() == b.to_rgba8()
+        }
+        (x, y) => x == y && a.as_bytes() == b.as_bytes(),
+    }
+}
+
+/// The palette of an indexed source image, with opaque entries for GIF.
+#[cfg_attr(not(any(feature = "png", feature = "gif")), allow(unused_variables))]
+fn source_palette<R: BufRead + Seek>(
+    src: &mut R,
+    format: Option<ImageFormat>,
+) -> ImageResult<Option<Vec<Rgba<u8>>>> {
+    let palette = match format {
+        #[cfg(feature = "png")]
+        Some(ImageFormat::Png) => PngDecoder::new(&mut *src)?.palette(),
+        #[cfg(feature = "gif")]
+        Some(ImageFormat::Gif) => GifDecoder::new(&mut *src)?.global_palette().map(|colors| {
+            colors
+                .iter()
+                .map(|&Rgb([r, g, b])| Rgba([r, g, b, 0xFF]))
+                .collect()
+        }),
+        _ => None,
+    };
+    Ok(palette)
+}
+
+/// Encode the pixels of the image with the color type it has.
+fn encode(image: &DynamicImage, format: ImageFormat) -> ImageResult<Vec<u8>> {
+    let mut encoded = Cursor::new(Vec::new());
+    write_buffer_with_format(
+        &mut encoded,
+        image.as_bytes(),
+        image.width(),
+        image.height(),
+        image.color(),
+        format,
+    )?;
+    Ok(encoded.into_inner())
+}
+
+/// Encode the image as indices into `palette` if the target format has palettes.
+///
+/// Returns an `ImageError::Parameter` if the palette does not fit the image or the format.
+#[cfg_attr(not(any(feature = "png", feature = "gif")), allow(unused_variables))]
+fn encode_with_palette(
+    image: &DynamicImage,
+    format: ImageFormat,
+    palette: &[Rgba<u8>],
+) -> ImageResult<Vec<u8>> {
+    match format {
+        #[cfg(feature = "png")]
+        ImageFormat::Png => {
+            let mut encoded = Vec::new();
+            PngEncoder::new(&mut encoded).encode_with_palette(
+                image.as_bytes(),
+                image.width(),
+                image.height(),
+                image.color(),
+                palette,
+            )?;
+            Ok(encoded)
+        }
+        #[cfg(feature = "gif")]
+        ImageFormat::Gif if palette.iter().all(|color| color[3] == 0xFF) => {
+            let colors = palette
+                .iter()
+                .map(|&Rgba([r, g, b, _])| Rgb([r, g, b]))
+                .collect();
+            let quantizer = Quantizer::Palette(Palette::new(colors));
+            let mut encoded = Vec::new();
+            GifEncoder::new(&mut encoded)
+                .with_quantizer(quantizer, Dithering::None)
+                .encode(
+                    image.as_bytes(),
+                    image.width(),
+                    image.height(),
+                    image.color(),
+                )?;
+            Ok(encoded)
+        }
+        _ => encode(image, format),
+    }
+}
+
+fn is_lossy(format: ImageFormat) -> bool {
+    matches!(format, ImageFormat::Jpeg | ImageFormat::Avif)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use std::io::BufReader;
+
+    const SIXTEEN_BIT: &str = "tests/images/png/16bpc/basn6a16.png";
+
+    #[test]
+    #[cfg(all(feature = "png", feature = "farbfeld"))]
+    fn keeps_sixteen_bit() {
+        let file = BufReader::new(File::open(SIXTEEN_BIT).unwrap());
+        let encoded = transcode_lossless(file, ImageFormat::Farbfeld).unwrap();
+        let image = load_from_memory_with_format(&encoded, ImageFormat::Farbfeld).unwrap();
+        assert_eq!(image.color(), crate::ColorType::Rgba16);
+    }
+
+    #[test]
+    #[cfg(feature = "png")]
+    fn same_format_is_verbatim() {
+        let original = std::fs::read(SIXTEEN_BIT).unwrap();
+        let encoded = transcode_lossless(Cursor::new(&original), ImageFormat::Png).unwrap();
+        assert_eq!(encoded, original);
+    }
+
+    #[test]
+    #[cfg(all(feature = "png", feature = "bmp"))]
+    fn unrepresentable_color_type_fails() {
+        let file = BufReader::new(File::open(SIXTEEN_BIT).unwrap());
+        assert!(transcode_lossless(file, ImageFormat::Bmp).is_err());
+    }
+
+    #[test]
+    #[cfg(all(feature = "png", feature = "qoi"))]
+    fn png_to_qoi() {
+        let file = BufReader::new(File::open("tests/images/png/interlaced/basi2c08.png").unwrap());
+        let encoded = transcode_lossless(file, ImageFormat::Qoi).unwrap();
+        let image = load_from_memory_with_format(&encoded, ImageFormat::Qoi).unwrap();
+        assert_eq!(image.color(), crate::ColorType::Rgb8);
+    }
+
+    /// A 2x2 image whose palette is in neither the order of the pixels nor of the colors.
+    const PALETTE: [Rgba<u8>; 3] = [
+        Rgba([0, 255, 0, 255]),
+        Rgba([255, 0, 0, 255]),
+        Rgba([0, 0, 255, 255]),
+    ];
+    const INDICES: [u8; 4] = [1, 2, 1, 0];
+
+    #[test]
+    #[cfg(all(feature = "png", feature = "gif"))]
+    fn gif_palette_is_kept_in_png() {
+        let table: Vec<u8> = PALETTE.iter().flat_map(|c| c.0[..3].to_vec()).collect();
+        let mut gif = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut gif, 2, 2, &table).unwrap();
+            let mut frame = gif::Frame::default();
+            frame.width = 2;
+            frame.height = 2;
+            frame.buffer = std::borrow::Cow::Borrowed(&INDICES);
+            encoder.write_frame(&frame).unwrap();
+        }
+
+        let encoded = transcode_lossless(Cursor::new(gif), ImageFormat::Png).unwrap();
+        let decoder = PngDecoder::new(Cursor::new(encoded)).unwrap();
+        // GIF pads its color tables to a power of two.
+        let palette = decoder.palette().unwrap();
+        assert!(palette.starts_with(&PALETTE));
+    }
+
+    #[test]
+    #[cfg(all(feature = "png", feature = "gif"))]
+    fn png_palette_is_kept_in_gif() {
+        let pixels: Vec<u8> = INDICES
+            .iter()
+            .flat_map(|&i| PALETTE[usize::from(i)].0[..3].to_vec())
+            .collect();
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .encode_with_palette(&pixels, 2, 2, crate::ColorType::Rgb8, &PALETTE)
+            .unwrap();
+
+        let encoded = transcode_lossless(Cursor::new(png), ImageFormat::Gif).unwrap();
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(Cursor::new(encoded))
+            .unwrap();
+        let frame = decoder.read_next_frame().unwrap().unwrap();
+        let table: Vec<u8> = PALETTE.iter().flat_map(|c| c.0[..3].to_vec()).collect();
+        assert!(frame.palette.as_ref().unwrap().starts_with(&table));
+        assert_eq!(&frame.buffer[..], &INDICES[..]);
+    }
+
+    #[test]
+    fn lossy_target_is_rejected() {
+        let file = BufReader::new(File::open(SIXTEEN_BIT).unwrap());
+        let result = transcode_lossless(file, ImageFormat::Jpeg);
+        assert!(matches!(result, Err(ImageError::Unsupported(_))));
+    }
+}
@@ -13,16 +13,17 @@
 //!
 //! # High level API
 //!
-//! Load images using [`io::Reader`]:
+//! Load images using [`io::ImageReader`]:
 //!
 //! ```rust,no_run
 //! use std::io::Cursor;
-//! use image::io::Reader as ImageReader;
+//! use image::io::ImageReader;
 //! # fn main() -> Result<(), image::ImageError> {
 //! # let bytes = vec![0u8];
 //!
 //! let img = ImageReader::open("myimage.png")?.decode()?;
 //! let img2 = ImageReader::new(Cursor::new(bytes)).with_guessed_format()?.decode()?;
+//! let img3 = ImageReader::builder().apply_orientation(true).open("photo.jpg")?.decode()?;
 //! # Ok(())
 //! # }
 //! ```
@@ -50,7 +51,7 @@
 //!
 //! [`save`]: enum.DynamicImage.html#method.save
 //! [`write_to`]: enum.DynamicImage.html#method.write_to
-//! [`io::Reader`]: io/struct.Reader.html
+//! [`io::ImageReader`]: io/type.ImageReader.html
 //!
 //! # Image buffers
 //!
@@ -64,7 +65,12 @@
 //! * [`GenericImageView`] trait for read only references to a GenericImage.
 //! * [`flat`] module containing types for interoperability with generic channel
 //!     matrices and foreign interfaces.
+//! * [`ArrayImage`] for small images with a size fixed at compile time, stored without any
+//!     heap allocation.
+//! * [`SharedImage`] as a reference counted, copy-on-write handle to an image buffer.
 //!
+//! [`ArrayImage`]: struct.ArrayImage.html
+//! [`SharedImage`]: struct.SharedImage.html
 //! [`GenericImageView`]: trait.GenericImageView.html
 //! [`GenericImage`]: trait.GenericImage.html
 //! [`ImageBuffer`]: struct.ImageBuffer.html
@@ -101,21 +107,28 @@ extern crate test;
 #[macro_use]
 extern crate quickcheck;
 
-pub use crate::color::{ColorType, ExtendedColorType};
+pub use crate::color_::{ColorType, ExtendedColorType};
 
-pub use crate::color::{Luma, LumaA, Rgb, Rgba};
+pub use crate::color_::{Luma, LumaA, Rgb, Rgba};
 
 pub use crate::error::{ImageError, ImageResult};
 
 pub use crate::image::{
     AnimationDecoder,
+    Direction,
+    EncoderOptions,
     GenericImage,
     GenericImageView,
     ImageDecoder,
     ImageDecoderRect,
     ImageEncoder,
+    ImageEncoderBoxed,
     ImageFormat,
     ImageOutputFormat,
+    MultiImageDecoder,
+    MultiImageEncoder,
+    ProgressiveDecoder,
+    StreamingEncoder,
     // Iterators
     Pixels,
     Progress,
@@ -133,21 +146,28 @@ pub use crate::buffer_::{
     RgbaImage,
 };
 
+pub use crate::array_image::ArrayImage;
+
 pub use crate::flat::FlatSamples;
 
+pub use crate::shared_image::SharedImage;
+
 // Traits
 pub use crate::traits::{EncodableLayout, Pixel, PixelWithColorType, Primitive};
 
 // Opening and loading images
 pub use crate::dynimage::{
-    image_dimensions, load_from_memory, load_from_memory_with_format, open, save_buffer,
-    save_buffer_with_format, write_buffer_with_format,
+    image_dimensions, load_from_memory, load_from_memory_with_format, open, save_animation,
+    save_buffer, save_buffer_with_format, write_buffer_with_format,
 };
-pub use crate::io::free_functions::{guess_format, load};
+pub use crate::io::free_functions::{encoder_for, estimate_encoded_size, guess_format, load};
+pub use crate::io::strip_metadata;
+#[cfg(feature = "async")]
+pub use crate::dynimage::open_async;
 
 pub use crate::dynimage::DynamicImage;
 
-pub use crate::animation::{Delay, Frame, Frames};
+pub use crate::animation::{AnimationOptions, Delay, Frame, Frames};
 
 // More detailed error type
 pub mod error;
@@ -161,18 +181,68 @@ pub mod buffer {
     };
 }
 
+/// Pixel color types and named colors.
+pub mod color {
+    pub use crate::color_::{ColorType, ExtendedColorType, Luma, LumaA, Rgb, Rgba};
+
+    // Used throughout the crate, but not part of the public interface.
+    #[allow(unused_imports)]
+    pub(crate) use crate::color_::{
+        can_convert_samples, convert_samples, Blend, FromColor, IntoColor,
+    };
+
+    pub mod consts;
+    mod parse;
+    pub mod space;
+    pub(crate) mod transfer;
+
+    pub use self::parse::ParseColorError;
+}
+
 // Math utils
 pub mod math;
 
 // Image processing functions
 pub mod imageops;
 
+// Packing images into texture atlases
+pub mod atlas;
+
+// Cubemaps for environment textures
+pub mod cubemap;
+
+// Texture preparation for 3D rendering
+pub mod texture;
+
+// Color palettes and palette files
+pub mod palette;
+
+// Output for e-paper displays
+pub mod epaper;
+
+// Packed 16 bit pixel formats of embedded displays
+pub mod packed;
+
 // Io bindings
 pub mod io;
 
 // Buffer representations for ffi.
 pub mod flat;
 
+// EXIF, XMP and textual metadata of images
+pub mod metadata;
+
+// Animations as sequences of numbered image files
+pub mod sequence;
+
+// Reading images out of dataset archives
+#[cfg(feature = "dataset")]
+pub mod dataset;
+
+// Generators of random images for property-based testing
+#[cfg(feature = "test-util")]
+pub mod testing;
+
 /// Encoding and decoding for various image file formats.
 ///
 /// # Supported formats
@@ -184,6 +254,7 @@ pub mod flat;
 /// | GIF    | Yes | Yes |
 /// | BMP    | Yes | Rgb8, Rgba8, Gray8, GrayA8 |
 /// | ICO    | Yes | Yes |
+/// | ICNS   | PNG entries | PNG entries |
 /// | TIFF   | Baseline(no fax support) + LZW + PackBits | Rgb8, Rgba8, Gray8 |
 /// | WebP   | Lossy(Luma channel only) | No |
 /// | AVIF   | Only 8-bit | Lossy |
@@ -192,6 +263,13 @@ pub mod flat;
 /// | TGA    | Yes | Rgb8, Rgba8, Bgr8, Bgra8, Gray8, GrayA8 |
 /// | OpenEXR  | Rgb32F, Rgba32F (no dwa compression) | Rgb32F, Rgba32F (no dwa compression) |
 /// | farbfeld | Yes | Yes |
+/// | DICOM  | Uncompressed and JPEG pixel data | No |
+/// | FITS   | 2D primary images, one or three planes | L8, L16, Rgb8, Rgb16, Rgb32F |
+/// | JPEG XS | With a registered decoder | No |
+/// | JPEG XL | With a registered decoder | With a registered encoder |
+/// | HEIF/HEIC | With a registered decoder | No |
+/// | SVG    | Rasterized, without text | No |
+/// | QOI    | Rgb8, Rgba8 | Rgb8, Rgba8 |
 ///
 /// ## A note on format specific features
 ///
@@ -221,25 +299,45 @@ pub mod codecs {
     pub mod bmp;
     #[cfg(feature = "dds")]
     pub mod dds;
+    #[cfg(feature = "dicom")]
+    pub mod dicom;
     #[cfg(feature = "dxt")]
     #[deprecated = "DXT support will be removed or reworked in a future version. Prefer the `squish` crate instead. See https://github.com/image-rs/image/issues/1623"]
     pub mod dxt;
     #[cfg(feature = "farbfeld")]
     pub mod farbfeld;
+    #[cfg(feature = "fits")]
+    pub mod fits;
     #[cfg(feature = "gif")]
     pub mod gif;
     #[cfg(feature = "hdr")]
     pub mod hdr;
+    #[cfg(feature = "heif")]
+    pub mod heif;
+    #[cfg(feature = "icns")]
+    pub mod icns;
     #[cfg(feature = "ico")]
     pub mod ico;
     #[cfg(feature = "jpeg")]
     pub mod jpeg;
+    #[cfg(feature = "jpegxs")]
+    pub mod jpegxs;
+    #[cfg(feature = "jxl")]
+    pub mod jxl;
+    #[cfg(feature = "ktx")]
+    pub mod ktx;
     #[cfg(feature = "openexr")]
     pub mod openexr;
     #[cfg(feature = "png")]
     pub mod png;
     #[cfg(feature = "pnm")]
     pub mod pnm;
+    #[cfg(feature = "qoi")]
+    pub mod qoi;
+    #[cfg(any(feature = "heif", feature = "jpegxs", feature = "jxl"))]
+    mod registry;
+    #[cfg(feature = "svg")]
+    pub mod svg;
     #[cfg(feature = "tga")]
     pub mod tga;
     #[cfg(feature = "tiff")]
@@ -249,11 +347,14 @@ pub mod codecs {
 }
 
 mod animation;
+mod array_image;
 #[path = "buffer.rs"]
 mod buffer_;
-mod color;
+#[path = "color.rs"]
+mod color_;
 mod dynimage;
 mod image;
+mod shared_image;
 mod traits;
 mod utils;
 
@@ -2,5 +2,5 @@
 mod rect;
 mod utils;
 
-pub use self::rect::Rect;
+pub use self::rect::{FloatRect, Rect};
 pub(super) use utils::resize_dimensions;
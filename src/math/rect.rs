@@ -10,3 +10,66 @@ pub struct Rect {
     /// The rectangle's height.
     pub height: u32,
 }
+
+impl Rect {
+    /// The part of this rectangle that lies on a canvas of `width` by `height` pixels.
+    pub(crate) fn clip(self, width: u32, height: u32) -> Rect {
+        let x = self.x.min(width);
+        let y = self.y.min(height);
+        Rect {
+            x,
+            y,
+            width: (u64::from(self.x) + u64::from(self.width)).min(u64::from(width)) as u32 - x,
+            height: (u64::from(self.y) + u64::from(self.height)).min(u64::from(height)) as u32 - y,
+        }
+    }
+
+    /// The smallest rectangle containing both rectangles, where empty ones are ignored.
+    pub(crate) fn union(self, other: Rect) -> Rect {
+        if other.width == 0 || other.height == 0 {
+            return self;
+        }
+        if self.width == 0 || self.height == 0 {
+            return other;
+        }
+        let right = (u64::from(self.x) + u64::from(self.width))
+            .max(u64::from(other.x) + u64::from(other.width));
+        let bottom = (u64::from(self.y) + u64::from(self.height))
+            .max(u64::from(other.y) + u64::from(other.height));
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        Rect {
+            x,
+            y,
+            width: (right - u64::from(x)) as u32,
+            height: (bottom - u64::from(y)) as u32,
+        }
+    }
+}
+
+/// A rectangle with fractional coordinates, defined by its top left corner, width and height.
+///
+/// It describes regions of images that do not align with the pixel grid, such as the source of
+/// [`resize_region`](../imageops/fn.resize_region.html).
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FloatRect {
+    /// The x coordinate of the top left corner.
+    pub x: f32,
+    /// The y coordinate of the top left corner.
+    pub y: f32,
+    /// The rectangle's width.
+    pub width: f32,
+    /// The rectangle's height.
+    pub height: f32,
+}
+
+impl From<Rect> for FloatRect {
+    fn from(rect: Rect) -> Self {
+        FloatRect {
+            x: rect.x as f32,
+            y: rect.y as f32,
+            width: rect.width as f32,
+            height: rect.height as f32,
+        }
+    }
+}
@@ -45,7 +45,14 @@ mod test {
         fn resize_bounds_correctly_width(old_w: u32, new_w: u32) -> bool {
             if old_w == 0 || new_w == 0 { return true; }
             let result = super::resize_dimensions(old_w, 400, new_w, ::std::u32::MAX, false);
-            result.0 == new_w && result.1 == (400 as f64 * new_w as f64 / old_w as f64).round() as u32
+            let new_h = 400.0 * f64::from(new_w) / f64::from(old_w);
+            if new_h > f64::from(u32::MAX) {
+                // The height reaches its bound first and limits the width.
+                let ratio = f64::from(u32::MAX) / 400.0;
+                result.1 == u32::MAX && result.0 == (f64::from(old_w) * ratio).round().max(1.0) as u32
+            } else {
+                result.0 == new_w && result.1 == new_h.round().max(1.0) as u32
+            }
         }
     }
 
@@ -53,7 +60,14 @@ mod test {
         fn resize_bounds_correctly_height(old_h: u32, new_h: u32) -> bool {
             if old_h == 0 || new_h == 0 { return true; }
             let result = super::resize_dimensions(400, old_h, ::std::u32::MAX, new_h, false);
-            result.1 == new_h && result.0 == (400 as f64 * new_h as f64 / old_h as f64).round() as u32
+            let new_w = 400.0 * f64::from(new_h) / f64::from(old_h);
+            if new_w > f64::from(u32::MAX) {
+                // The width reaches its bound first and limits the height.
+                let ratio = f64::from(u32::MAX) / 400.0;
+                result.0 == u32::MAX && result.1 == (f64::from(old_h) * ratio).round().max(1.0) as u32
+            } else {
+                result.1 == new_h && result.0 == new_w.round().max(1.0) as u32
+            }
         }
     }
 
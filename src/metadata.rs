@@ -0,0 +1,664 @@
+//! Metadata stored alongside the pixels of an image.
+//!
+//! Cameras and phones record EXIF metadata with the photos they take: the orientation a photo is
+//! meant to be displayed in, when it was taken, and the camera and settings it was taken with.
+//! [`Exif`] holds these fields. [`io::Reader::decode_with_exif`] reads it from JPEG, PNG, TIFF
+//! and WebP images, the decoders of JPEG, TIFF and WebP images also return it from
+//! `ImageDecoder::exif_metadata`. The encoders of JPEG, PNG and WebP images write it back with
+//! `ImageEncoder::set_exif_metadata`.
+//!
+//! ```no_run
+//! use image::io::Reader;
+//!
+//! let (mut image, exif) = Reader::open("photo.jpg")?.decode_with_exif()?;
+//! if let Some(exif) = exif {
+//!     println!("taken with a {:?} at {:?}", exif.model, exif.date_time_original);
+//!     if let Some(orientation) = exif.orientation {
+//!         image.apply_orientation(orientation);
+//!     }
+//! }
+//! # Ok::<(), image::ImageError>(())
+//! ```
+//!
+//! Textual metadata, such as the copyright and the author of an image, is held by a
+//! [`MetadataMap`] together with the XMP packet of the image.
+//! [`io::Reader::decode_with_metadata`] reads it, and the encoders of JPEG, PNG and WebP images
+//! write it back with `ImageEncoder::set_text_metadata`.
+//!
+//! [`Exif`]: struct.Exif.html
+//! [`MetadataMap`]: struct.MetadataMap.html
+//! [`io::Reader::decode_with_exif`]: ../io/struct.Reader.html#method.decode_with_exif
+//! [`io::Reader::decode_with_metadata`]: ../io/struct.Reader.html#method.decode_with_metadata
+//!
+//! # Related Links
+//! * <https://www.cipa.jp/std/documents/e/DC-008-2012_E.pdf> - The EXIF 2.3 specification
+//! * <https://www.adobe.com/devnet/xmp.html> - The XMP specification
+
+use std::convert::TryFrom;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+
+use crate::error::{DecodingError, ImageError, ImageFormatHint, ImageResult};
+
+/// The identifier that precedes EXIF metadata in JPEG segments, and in files of some encoders.
+pub(crate) const EXIF_IDENTIFIER: &[u8] = b"Exif\0\0";
+/// The identifier at the start of a JPEG segment holding an XMP packet.
+pub(crate) const XMP_IDENTIFIER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+/// The keyword of the PNG text chunk holding an XMP packet.
+pub(crate) const XMP_KEYWORD: &str = "XML:com.adobe.xmp";
+
+/// Tags of the first image file directory.
+const MAKE: u16 = 0x010f;
+const MODEL: u16 = 0x0110;
+const ORIENTATION: u16 = 0x0112;
+const SOFTWARE: u16 = 0x0131;
+const DATE_TIME: u16 = 0x0132;
+/// The offset of the directory holding the tags of the EXIF specification.
+const EXIF_IFD: u16 = 0x8769;
+
+/// Tags of the EXIF directory.
+const EXPOSURE_TIME: u16 = 0x829a;
+const F_NUMBER: u16 = 0x829d;
+const ISO_SPEED: u16 = 0x8827;
+const DATE_TIME_ORIGINAL: u16 = 0x9003;
+const DATE_TIME_DIGITIZED: u16 = 0x9004;
+const FOCAL_LENGTH: u16 = 0x920a;
+const LENS_MODEL: u16 = 0xa434;
+
+/// The types of the values of directory entries, and their sizes.
+const ASCII: u16 = 2;
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const RATIONAL: u16 = 5;
+const IFD: u16 = 13;
+
+/// The longest value that is read, longer ones are skipped.
+const MAX_VALUE_LEN: usize = 1 << 16;
+
+/// How an image is rotated and flipped from the orientation it is stored in to the one it is
+/// displayed in, as recorded by the EXIF orientation tag.
+///
+/// Transforms that rotate and flip first rotate the image clockwise, then flip it horizontally.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Orientation {
+    /// The image is displayed as it is stored, the EXIF value 1.
+    NoTransforms,
+    /// Flip horizontally, the EXIF value 2.
+    FlipHorizontal,
+    /// Rotate by 180 degrees, the EXIF value 3.
+    Rotate180,
+    /// Flip vertically, the EXIF value 4.
+    FlipVertical,
+    /// Rotate by 90 degrees and flip horizontally, the EXIF value 5.
+    Rotate90FlipH,
+    /// Rotate by 90 degrees clockwise, the EXIF value 6.
+    Rotate90,
+    /// Rotate by 270 degrees and flip horizontally, the EXIF value 7.
+    Rotate270FlipH,
+    /// Rotate by 270 degrees clockwise, the EXIF value 8.
+    Rotate270,
+}
+
+impl Orientation {
+    /// Returns the orientation of an EXIF orientation value, or `None` if it is not one from 1
+    /// to 8.
+    pub fn from_exif(value: u16) -> Option<Orientation> {
+        match value {
+            1 => Some(Orientation::NoTransforms),
+            2 => Some(Orientation::FlipHorizontal),
+            3 => Some(Orientation::Rotate180),
+            4 => Some(Orientation::FlipVertical),
+            5 => Some(Orientation::Rotate90FlipH),
+            6 => Some(Orientation::Rotate90),
+            7 => Some(Orientation::Rotate270FlipH),
+            8 => Some(Orientation::Rotate270),
+            _ => None,
+        }
+    }
+
+    /// Returns the EXIF orientation value of this orientation.
+    pub fn to_exif(self) -> u16 {
+        match self {
+            Orientation::NoTransforms => 1,
+            Orientation::FlipHorizontal => 2,
+            Orientation::Rotate180 => 3,
+            Orientation::FlipVertical => 4,
+            Orientation::Rotate90FlipH => 5,
+            Orientation::Rotate90 => 6,
+            Orientation::Rotate270FlipH => 7,
+            Orientation::Rotate270 => 8,
+        }
+    }
+
+    /// Returns if this orientation swaps the width and the height of an image.
+    pub fn swaps_dimensions(self) -> bool {
+        self.to_exif() >= 5
+    }
+}
+
+/// EXIF metadata of an image.
+///
+/// Timestamps are strings of the form `YYYY:MM:DD HH:MM:SS` in the local time of the camera, as
+/// EXIF records them. Rational values are pairs of a numerator and a denominator. Fields that an
+/// image does not record are `None`, as are those that could not be read. Other tags, such as
+/// the location and the maker notes, are not read and not written.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Exif {
+    /// The orientation the image is displayed in.
+    pub orientation: Option<Orientation>,
+    /// When the image was last changed.
+    pub date_time: Option<String>,
+    /// When the photo was taken.
+    pub date_time_original: Option<String>,
+    /// When the photo was stored digitally, which differs from when it was taken for scans.
+    pub date_time_digitized: Option<String>,
+    /// The manufacturer of the camera.
+    pub make: Option<String>,
+    /// The model of the camera.
+    pub model: Option<String>,
+    /// The model of the lens.
+    pub lens_model: Option<String>,
+    /// The software that created or last changed the image.
+    pub software: Option<String>,
+    /// The exposure time in seconds.
+    pub exposure_time: Option<(u32, u32)>,
+    /// The F number, the focal length divided by the aperture diameter.
+    pub f_number: Option<(u32, u32)>,
+    /// The focal length of the lens in millimeters.
+    pub focal_length: Option<(u32, u32)>,
+    /// The sensitivity of the sensor as an ISO speed.
+    pub iso: Option<u16>,
+}
+
+impl Exif {
+    /// Read the metadata from its encoding as a TIFF structure.
+    ///
+    /// This is how JPEG, PNG and WebP images store it. The structure may be preceded by the
+    /// `Exif\0\0` identifier of JPEG segments.
+    pub fn from_bytes(data: &[u8]) -> ImageResult<Exif> {
+        let data = data.strip_prefix(EXIF_IDENTIFIER).unwrap_or(data);
+        read_exif(&mut Cursor::new(data), 0).map_err(|err| {
+            ImageError::Decoding(DecodingError::new(
+                ImageFormatHint::Name("EXIF".to_string()),
+                err,
+            ))
+        })
+    }
+
+    /// Encode the metadata as a little endian TIFF structure, without the `Exif\0\0` identifier.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ifd0 = Vec::new();
+        push_ascii(&mut ifd0, MAKE, &self.make);
+        push_ascii(&mut ifd0, MODEL, &self.model);
+        if let Some(orientation) = self.orientation {
+            ifd0.push(Entry::short(ORIENTATION, orientation.to_exif()));
+        }
+        push_ascii(&mut ifd0, SOFTWARE, &self.software);
+        push_ascii(&mut ifd0, DATE_TIME, &self.date_time);
+
+        let mut exif_ifd = Vec::new();
+        push_rational(&mut exif_ifd, EXPOSURE_TIME, self.exposure_time);
+        push_rational(&mut exif_ifd, F_NUMBER, self.f_number);
+        if let Some(iso) = self.iso {
+            exif_ifd.push(Entry::short(ISO_SPEED, iso));
+        }
+        push_ascii(&mut exif_ifd, DATE_TIME_ORIGINAL, &self.date_time_original);
+        push_ascii(
+            &mut exif_ifd,
+            DATE_TIME_DIGITIZED,
+            &self.date_time_digitized,
+        );
+        push_rational(&mut exif_ifd, FOCAL_LENGTH, self.focal_length);
+        push_ascii(&mut exif_ifd, LENS_MODEL, &self.lens_model);
+
+        if !exif_ifd.is_empty() {
+            // The EXIF directory follows the first one, which grows by the entry pointing to it.
+            let offset = 8 + directory_len(&ifd0) + 12;
+            ifd0.push(Entry::long(EXIF_IFD, offset as u32));
+        }
+
+        let mut data = b"II*\0\x08\0\0\0".to_vec();
+        write_directory(&mut data, &ifd0);
+        if !exif_ifd.is_empty() {
+            write_directory(&mut data, &exif_ifd);
+        }
+        data
+    }
+}
+
+/// Textual metadata of an image, entries of text under a keyword and an XMP packet.
+///
+/// PNG images store the entries in text chunks, under keywords such as `Title`, `Author`,
+/// `Copyright`, `Description` and `Source`. The XMP packet is an XML document with metadata of
+/// any kind, which JPEG, PNG, TIFF and WebP images embed as a whole. It is kept as it is, this
+/// crate does not parse it.
+///
+/// Each keyword appears at most once, and the entries are kept in the order they are inserted.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MetadataMap {
+    entries: Vec<(String, String)>,
+    xmp: Option<String>,
+}
+
+impl MetadataMap {
+    /// Create a map without any entries or XMP packet.
+    pub fn new() -> MetadataMap {
+        MetadataMap::default()
+    }
+
+    /// Returns the text of the entry with the keyword `keyword`.
+    pub fn get(&self, keyword: &str) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(key, _)| key == keyword)
+            .map(|(_, text)| text.as_str())
+    }
+
+    /// Set the text of the entry with the keyword `keyword`, returning the previous text.
+    pub fn insert<K, T>(&mut self, keyword: K, text: T) -> Option<String>
+    where
+        K: Into<String>,
+        T: Into<String>,
+    {
+        let keyword = keyword.into();
+        let text = text.into();
+        match self.entries.iter_mut().find(|(key, _)| *key == keyword) {
+            Some((_, previous)) => Some(std::mem::replace(previous, text)),
+            None => {
+                self.entries.push((keyword, text));
+                None
+            }
+        }
+    }
+
+    /// Remove the entry with the keyword `keyword`, returning its text.
+    pub fn remove(&mut self, keyword: &str) -> Option<String> {
+        let index = self.entries.iter().position(|(key, _)| key == keyword)?;
+        Some(self.entries.remove(index).1)
+    }
+
+    /// Iterate over the keywords and texts of the entries.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.entries
+            .iter()
+            .map(|(keyword, text)| (keyword.as_str(), text.as_str()))
+    }
+
+    /// Returns the number of entries, not counting the XMP packet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns if there are neither entries nor an XMP packet.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty() && self.xmp.is_none()
+    }
+
+    /// Returns the XMP packet.
+    pub fn xmp(&self) -> Option<&str> {
+        self.xmp.as_deref()
+    }
+
+    /// Set or remove the XMP packet.
+    pub fn set_xmp(&mut self, xmp: Option<String>) {
+        self.xmp = xmp;
+    }
+}
+
+/// Which metadata [`strip_metadata`] keeps.
+///
+/// Each preset keeps more than the one before. What an image needs to be decoded, such as its
+/// transparency and the frames of an animation, is always kept.
+///
+/// [`strip_metadata`]: ../fn.strip_metadata.html
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum StripPreset {
+    /// Remove all metadata, including the color profile. Colors are then shown as sRGB.
+    All,
+    /// Keep the color profile of the image, and how its colors are to be interpreted.
+    KeepColorProfile,
+    /// Keep the color profile and the EXIF orientation. All other EXIF fields, such as the
+    /// location and the camera, are removed.
+    KeepOrientation,
+}
+
+/// Returns EXIF metadata holding only the orientation of the metadata in `data`, encoded as a
+/// TIFF structure, or `None` if it has no orientation.
+#[allow(dead_code)] // When no format supports stripping
+pub(crate) fn orientation_only(data: &[u8]) -> Option<Vec<u8>> {
+    let orientation = Exif::from_bytes(data).ok()?.orientation?;
+    let exif = Exif {
+        orientation: Some(orientation),
+        ..Exif::default()
+    };
+    Some(exif.to_bytes())
+}
+
+/// Read the metadata from the TIFF structure starting at `base`.
+pub(crate) fn read_exif<R: Read + Seek>(r: &mut R, base: u64) -> io::Result<Exif> {
+    r.seek(SeekFrom::Start(base))?;
+    let mut header = [0; 8];
+    r.read_exact(&mut header)?;
+    let big_endian = match &header[..4] {
+        b"II*\0" => false,
+        b"MM\0*" => true,
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a TIFF structure",
+            ))
+        }
+    };
+
+    let mut parser = Parser {
+        r,
+        base,
+        big_endian,
+    };
+    let offset = parser.u32(&header[4..]);
+    let mut exif = Exif::default();
+    parser.read_directory(offset, &mut exif, true)?;
+    Ok(exif)
+}
+
+/// Reads directories of a TIFF structure.
+struct Parser<'r, R> {
+    r: &'r mut R,
+    base: u64,
+    big_endian: bool,
+}
+
+impl<R: Read + Seek> Parser<'_, R> {
+    /// Read the known tags of the directory at `offset`, and those of the EXIF directory it
+    /// points to if `follow` is set.
+    fn read_directory(&mut self, offset: u32, exif: &mut Exif, follow: bool) -> io::Result<()> {
+        self.r
+            .seek(SeekFrom::Start(self.base + u64::from(offset)))?;
+        let mut count = [0; 2];
+        self.r.read_exact(&mut count)?;
+        let mut entries = Vec::new();
+        for _ in 0..self.u16(&count) {
+            let mut entry = [0; 12];
+            self.r.read_exact(&mut entry)?;
+            entries.push(entry);
+        }
+
+        for entry in &entries {
+            match self.u16(&entry[0..]) {
+                MAKE => exif.make = self.ascii(entry)?,
+                MODEL => exif.model = self.ascii(entry)?,
+                ORIENTATION => {
+                    exif.orientation = self.short(entry)?.and_then(Orientation::from_exif)
+                }
+                SOFTWARE => exif.software = self.ascii(entry)?,
+                DATE_TIME => exif.date_time = self.ascii(entry)?,
+                EXIF_IFD if follow => {
+                    if let Some(offset) = self.long(entry)? {
+                        // A broken EXIF directory does not invalidate the first one.
+                        let _ = self.read_directory(offset, exif, false);
+                    }
+                }
+                EXPOSURE_TIME => exif.exposure_time = self.rational(entry)?,
+                F_NUMBER => exif.f_number = self.rational(entry)?,
+                ISO_SPEED => exif.iso = self.short(entry)?,
+                DATE_TIME_ORIGINAL => exif.date_time_original = self.ascii(entry)?,
+                DATE_TIME_DIGITIZED => exif.date_time_digitized = self.ascii(entry)?,
+                FOCAL_LENGTH => exif.focal_length = self.rational(entry)?,
+                LENS_MODEL => exif.lens_model = self.ascii(entry)?,
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the value of an entry if it has one of the `types`, each value being `size` bytes.
+    ///
+    /// Values that extend past the end of the data are skipped.
+    fn value(
+        &mut self,
+        entry: &[u8; 12],
+        types: &[u16],
+        size: usize,
+    ) -> io::Result<Option<Vec<u8>>> {
+        if !types.contains(&self.u16(&entry[2..])) {
+            return Ok(None);
+        }
+        let len = match usize::try_from(self.u32(&entry[4..]))
+            .ok()
+            .and_then(|count| count.checked_mul(size))
+        {
+            Some(len) if len > 0 && len <= MAX_VALUE_LEN => len,
+            _ => return Ok(None),
+        };
+
+        if len <= 4 {
+            return Ok(Some(entry[8..8 + len].to_vec()));
+        }
+        let offset = self.u32(&entry[8..]);
+        self.r
+            .seek(SeekFrom::Start(self.base + u64::from(offset)))?;
+        let mut value = vec![0; len];
+        match self.r.read_exact(&mut value) {
+            Ok(()) => Ok(Some(value)),
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    fn ascii(&mut self, entry: &[u8; 12]) -> io::Result<Option<String>> {
+        let value = match self.value(entry, &[ASCII], 1)? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let end = value.iter().position(|&b| b == 0).unwrap_or(value.len());
+        Ok(Some(String::from_utf8_lossy(&value[..end]).into_owned()))
+    }
+
+    fn short(&mut self, entry: &[u8; 12]) -> io::Result<Option<u16>> {
+        let value = self.value(entry, &[SHORT], 2)?;
+        Ok(value.map(|value| self.u16(&value)))
+    }
+
+    fn long(&mut self, entry: &[u8; 12]) -> io::Result<Option<u32>> {
+        let value = self.value(entry, &[LONG, IFD], 4)?;
+        Ok(value.map(|value| self.u32(&value)))
+    }
+
+    fn rational(&mut self, entry: &[u8; 12]) -> io::Result<Option<(u32, u32)>> {
+        let value = self.value(entry, &[RATIONAL], 8)?;
+        Ok(value.map(|value| (self.u32(&value), self.u32(&value[4..]))))
+    }
+
+    fn u16(&self, bytes: &[u8]) -> u16 {
+        let bytes = [bytes[0], bytes[1]];
+        if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        }
+    }
+
+    fn u32(&self, bytes: &[u8]) -> u32 {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        }
+    }
+}
+
+/// An entry of a directory that is written, with its little endian value.
+struct Entry {
+    tag: u16,
+    kind: u16,
+    count: u32,
+    value: Vec<u8>,
+}
+
+impl Entry {
+    fn short(tag: u16, value: u16) -> Entry {
+        Entry {
+            tag,
+            kind: SHORT,
+            count: 1,
+            value: value.to_le_bytes().to_vec(),
+        }
+    }
+
+    fn long(tag: u16, value: u32) -> Entry {
+        Entry {
+            tag,
+            kind: LONG,
+            count: 1,
+            value: value.to_le_bytes().to_vec(),
+        }
+    }
+}
+
+fn push_ascii(entries: &mut Vec<Entry>, tag: u16, value: &Option<String>) {
+    if let Some(value) = value {
+        let mut value = value.as_bytes().to_vec();
+        value.push(0);
+        entries.push(Entry {
+            tag,
+            kind: ASCII,
+            count: value.len() as u32,
+            value,
+        });
+    }
+}
+
+fn push_rational(entries: &mut Vec<Entry>, tag: u16, value: Option<(u32, u32)>) {
+    if let Some((numer, denom)) = value {
+        let mut value = numer.to_le_bytes().to_vec();
+        value.extend_from_slice(&denom.to_le_bytes());
+        entries.push(Entry {
+            tag,
+            kind: RATIONAL,
+            count: 1,
+            value,
+        });
+    }
+}
+
+/// The length of a directory and the values it does not hold inline.
+fn directory_len(entries: &[Entry]) -> usize {
+    let values: usize = entries
+        .iter()
+        .filter(|entry| entry.value.len() > 4)
+        .map(|entry| entry.value.len() + entry.value.len() % 2)
+        .sum();
+    2 + 12 * entries.len() + 4 + values
+}
+
+/// Write a directory followed by the values it does not hold inline. Entries must be sorted by
+/// their tag.
+fn write_directory(data: &mut Vec<u8>, entries: &[Entry]) {
+    let mut offset = data.len() + 2 + 12 * entries.len() + 4;
+    data.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for entry in entries {
+        data.extend_from_slice(&entry.tag.to_le_bytes());
+        data.extend_from_slice(&entry.kind.to_le_bytes());
+        data.extend_from_slice(&entry.count.to_le_bytes());
+        if entry.value.len() <= 4 {
+            let mut value = [0; 4];
+            value[..entry.value.len()].copy_from_slice(&entry.value);
+            data.extend_from_slice(&value);
+        } else {
+            data.extend_from_slice(&(offset as u32).to_le_bytes());
+            offset += entry.value.len() + entry.value.len() % 2;
+        }
+    }
+    // There is no next directory.
+    data.extend_from_slice(&[0; 4]);
+
+    for entry in entries.iter().filter(|entry| entry.value.len() > 4) {
+        data.extend_from_slice(&entry.value);
+        if entry.value.len() % 2 == 1 {
+            data.push(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn photo() -> Exif {
+        Exif {
+            orientation: Some(Orientation::Rotate90),
+            date_time_original: Some("2021:06:01 12:30:00".to_string()),
+            make: Some("Camera Co".to_string()),
+            model: Some("Model 1".to_string()),
+            exposure_time: Some((1, 250)),
+            f_number: Some((28, 10)),
+            iso: Some(400),
+            ..Exif::default()
+        }
+    }
+
+    #[test]
+    fn roundtrip() {
+        let exif = photo();
+        assert_eq!(Exif::from_bytes(&exif.to_bytes()).unwrap(), exif);
+
+        let mut prefixed = EXIF_IDENTIFIER.to_vec();
+        prefixed.extend_from_slice(&exif.to_bytes());
+        assert_eq!(Exif::from_bytes(&prefixed).unwrap(), exif);
+
+        let empty = Exif::default();
+        assert_eq!(Exif::from_bytes(&empty.to_bytes()).unwrap(), empty);
+    }
+
+    #[test]
+    fn big_endian() {
+        // A model of "AB" held inline, the orientation, and a malformed entry that is skipped.
+        let tiff = b"MM\0*\0\0\0\x08\0\x03\x01\x10\0\x02\0\0\0\x03AB\0\0\x01\x12\0\x03\0\0\0\x01\0\x03\0\0\x01\x31\0\x02\xff\xff\xff\xff\0\0\0\0";
+        let exif = Exif::from_bytes(tiff).unwrap();
+        assert_eq!(exif.model.as_deref(), Some("AB"));
+        assert_eq!(exif.orientation, Some(Orientation::Rotate180));
+        assert_eq!(exif.software, None);
+    }
+
+    #[test]
+    fn metadata_map() {
+        let mut map = MetadataMap::new();
+        assert!(map.is_empty());
+        assert_eq!(map.insert("Title", "Sunset"), None);
+        assert_eq!(map.insert("Author", "A. Photographer"), None);
+        assert_eq!(map.insert("Title", "Sunrise"), Some("Sunset".to_string()));
+        assert_eq!(map.get("Title"), Some("Sunrise"));
+        assert_eq!(
+            map.iter().collect::<Vec<_>>(),
+            [("Title", "Sunrise"), ("Author", "A. Photographer")]
+        );
+        assert_eq!(map.remove("Title"), Some("Sunrise".to_string()));
+        assert_eq!(map.remove("Title"), None);
+        assert_eq!(map.len(), 1);
+
+        map.remove("Author");
+        map.set_xmp(Some("<x:xmpmeta/>".to_string()));
+        assert_eq!((map.len(), map.is_empty()), (0, false));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Exif::from_bytes(b"").is_err());
+        assert!(Exif::from_bytes(b"not a TIFF structure").is_err());
+        // A directory that extends past the end.
+        assert!(Exif::from_bytes(b"II*\0\x08\0\0\0\x05\0").is_err());
+    }
+
+    #[test]
+    fn orientations() {
+        for value in 1..=8 {
+            let orientation = Orientation::from_exif(value).unwrap();
+            assert_eq!(orientation.to_exif(), value);
+            assert_eq!(orientation.swaps_dimensions(), value >= 5);
+        }
+        assert_eq!(Orientation::from_exif(0), None);
+        assert_eq!(Orientation::from_exif(9), None);
+    }
+}
@@ -0,0 +1,211 @@
+//! Sixteen bit packed pixel formats of embedded displays.
+//!
+//! Display controllers of microcontroller projects, such as the ILI9341 and ST7789, take pixels as
+//! 16 bit words with five or six bits per channel. [`to_packed`] converts an image into such a
+//! buffer and [`from_packed`] converts it back.
+//!
+//! ```
+//! use image::packed::{from_packed, to_packed, ByteOrder, PackedFormat};
+//! use image::{Rgb, RgbImage};
+//!
+//! let image = RgbImage::from_pixel(2, 1, Rgb([255, 0, 0]));
+//! // SPI displays usually take the high byte first.
+//! let buffer = to_packed(&image, PackedFormat::Rgb565, ByteOrder::BigEndian);
+//! assert_eq!(buffer, [0xF8, 0x00, 0xF8, 0x00]);
+//!
+//! let decoded = from_packed(&buffer, 2, 1, PackedFormat::Rgb565, ByteOrder::BigEndian).unwrap();
+//! assert_eq!(decoded.get_pixel(1, 0).0, [255, 0, 0, 255]);
+//! ```
+//!
+//! [`to_packed`]: fn.to_packed.html
+//! [`from_packed`]: fn.from_packed.html
+
+use crate::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use crate::image::GenericImageView;
+use crate::traits::Pixel;
+use crate::{ImageBuffer, Rgba, RgbaImage};
+
+/// A sixteen bit packed pixel format.
+///
+/// Channels are listed from the most to the least significant bits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PackedFormat {
+    /// Five bits of red, six of green and five of blue.
+    Rgb565,
+    /// An unused bit, then five bits each of red, green and blue.
+    Rgb555,
+    /// One bit of alpha, then five bits each of red, green and blue.
+    Argb1555,
+}
+
+/// The order of the two bytes of a packed pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ByteOrder {
+    /// The low byte first, as in the memory of most microcontrollers.
+    LittleEndian,
+    /// The high byte first, as most display controllers receive pixels over SPI.
+    BigEndian,
+}
+
+impl PackedFormat {
+    /// Packs an RGBA color. Channels are rounded to the nearest representable value and alpha is
+    /// set if it is at least half.
+    pub fn pack(self, color: Rgba<u8>) -> u16 {
+        let [r, g, b, a] = color.0;
+        match self {
+            PackedFormat::Rgb565 => (reduce(r, 5) << 11) | (reduce(g, 6) << 5) | reduce(b, 5),
+            PackedFormat::Rgb555 => (reduce(r, 5) << 10) | (reduce(g, 5) << 5) | reduce(b, 5),
+            PackedFormat::Argb1555 => {
+                let alpha = if a >= 0x80 { 0x8000 } else { 0 };
+                alpha | PackedFormat::Rgb555.pack(color)
+            }
+        }
+    }
+
+    /// Unpacks a color. Formats without alpha are opaque.
+    pub fn unpack(self, value: u16) -> Rgba<u8> {
+        match self {
+            PackedFormat::Rgb565 => Rgba([
+                expand(value >> 11, 5),
+                expand(value >> 5, 6),
+                expand(value, 5),
+                0xFF,
+            ]),
+            PackedFormat::Rgb555 => Rgba([
+                expand(value >> 10, 5),
+                expand(value >> 5, 5),
+                expand(value, 5),
+                0xFF,
+            ]),
+            PackedFormat::Argb1555 => {
+                let Rgba([r, g, b, _]) = PackedFormat::Rgb555.unpack(value);
+                Rgba([r, g, b, if value & 0x8000 != 0 { 0xFF } else { 0 }])
+            }
+        }
+    }
+}
+
+/// Convert an image into a buffer of packed pixels, row by row.
+pub fn to_packed<I, P>(image: &I, format: PackedFormat, order: ByteOrder) -> Vec<u8>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = u8>,
+{
+    let (width, height) = image.dimensions();
+    let mut buffer = Vec::with_capacity(width as usize * height as usize * 2);
+    for y in 0..height {
+        for x in 0..width {
+            let value = format.pack(image.get_pixel(x, y).to_rgba());
+            let bytes = match order {
+                ByteOrder::LittleEndian => value.to_le_bytes(),
+                ByteOrder::BigEndian => value.to_be_bytes(),
+            };
+            buffer.extend_from_slice(&bytes);
+        }
+    }
+    buffer
+}
+
+/// Convert a buffer of packed pixels, row by row, into an image.
+///
+/// Returns an `ImageError::Parameter` if the buffer does not hold exactly `width` by `height`
+/// pixels.
+pub fn from_packed(
+    buffer: &[u8],
+    width: u32,
+    height: u32,
+    format: PackedFormat,
+    order: ByteOrder,
+) -> ImageResult<RgbaImage> {
+    if buffer.len() as u64 != u64::from(width) * u64::from(height) * 2 {
+        return Err(ImageError::Parameter(ParameterError::from_kind(
+            ParameterErrorKind::DimensionMismatch,
+        )));
+    }
+    let mut pixels = buffer.chunks_exact(2).map(|bytes| {
+        let bytes = [bytes[0], bytes[1]];
+        let value = match order {
+            ByteOrder::LittleEndian => u16::from_le_bytes(bytes),
+            ByteOrder::BigEndian => u16::from_be_bytes(bytes),
+        };
+        format.unpack(value)
+    });
+    Ok(ImageBuffer::from_fn(width, height, |_, _| {
+        pixels.next().unwrap()
+    }))
+}
+
+/// Reduces an eight bit value to the nearest value of `bits` bits.
+fn reduce(value: u8, bits: u32) -> u16 {
+    let max = (1u32 << bits) - 1;
+    ((u32::from(value) * max + 127) / 255) as u16
+}
+
+/// Expands the lowest `bits` bits of a value to eight bits, repeating its high bits in the low
+/// ones so that the maximum maps to 255.
+fn expand(value: u16, bits: u32) -> u8 {
+    let value = value & ((1 << bits) - 1);
+    ((value << (8 - bits)) | (value >> (2 * bits - 8))) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayImage, Luma};
+
+    #[test]
+    fn channel_positions() {
+        let color = Rgba([0xFF, 0x00, 0xFF, 0x00]);
+        assert_eq!(PackedFormat::Rgb565.pack(color), 0xF81F);
+        assert_eq!(PackedFormat::Rgb555.pack(color), 0x7C1F);
+        assert_eq!(PackedFormat::Argb1555.pack(color), 0x7C1F);
+        assert_eq!(
+            PackedFormat::Argb1555.pack(Rgba([0, 0xFF, 0, 0x80])),
+            0x83E0
+        );
+        assert_eq!(
+            PackedFormat::Rgb565.unpack(0x07E0),
+            Rgba([0, 0xFF, 0, 0xFF])
+        );
+        assert_eq!(PackedFormat::Argb1555.unpack(0x001F), Rgba([0, 0, 0xFF, 0]));
+    }
+
+    #[test]
+    fn round_trips_representable_colors() {
+        for &format in &[
+            PackedFormat::Rgb565,
+            PackedFormat::Rgb555,
+            PackedFormat::Argb1555,
+        ] {
+            for value in 0..=u16::MAX {
+                let value = match format {
+                    PackedFormat::Rgb555 => value & 0x7FFF,
+                    _ => value,
+                };
+                assert_eq!(format.pack(format.unpack(value)), value);
+            }
+        }
+    }
+
+    #[test]
+    fn byte_orders() {
+        let image = GrayImage::from_fn(2, 1, |x, _| Luma([x as u8 * 255]));
+        let little = to_packed(&image, PackedFormat::Rgb565, ByteOrder::LittleEndian);
+        assert_eq!(little, [0x00, 0x00, 0xFF, 0xFF]);
+        let image = GrayImage::from_pixel(1, 1, Luma([0x80]));
+        let little = to_packed(&image, PackedFormat::Rgb565, ByteOrder::LittleEndian);
+        let big = to_packed(&image, PackedFormat::Rgb565, ByteOrder::BigEndian);
+        assert_eq!(little, [big[1], big[0]]);
+        let decoded = from_packed(&big, 1, 1, PackedFormat::Rgb565, ByteOrder::BigEndian).unwrap();
+        assert!(decoded.get_pixel(0, 0).0[..3]
+            .iter()
+            .all(|&c| (c as i32 - 0x80).abs() <= 4));
+    }
+
+    #[test]
+    fn wrong_buffer_length() {
+        let buffer = [0; 6];
+        assert!(from_packed(&buffer, 2, 2, PackedFormat::Rgb555, ByteOrder::LittleEndian).is_err());
+        assert!(from_packed(&buffer, 3, 1, PackedFormat::Rgb555, ByteOrder::LittleEndian).is_ok());
+    }
+}
@@ -0,0 +1,521 @@
+//! Color palettes and the palette file formats of image editors.
+//!
+//! A [`Palette`] is a [`ColorMap`], so it reduces images to its colors with [`index_colors`] and
+//! [`dither`]. Palettes can be read from and written to GIMP (`.gpl`), Adobe Color Table (`.act`)
+//! and JASC (`.pal`) files, and the colors of indexed GIF and PNG images can be exchanged with
+//! them.
+//!
+//! ```
+//! use image::imageops::{dither, index_colors};
+//! use image::palette::{Palette, PaletteFormat};
+//! use image::{Rgb, RgbImage};
+//!
+//! let file = b"GIMP Palette\nName: Duo\n#\n  0   0   0\tBlack\n255 255 255\tWhite\n";
+//! let palette = Palette::read(&file[..], PaletteFormat::Gpl).unwrap();
+//! assert_eq!(palette.colors(), &[Rgb([0, 0, 0]), Rgb([255, 255, 255])]);
+//!
+//! let mut image = RgbImage::from_pixel(4, 4, Rgb([100, 100, 100]));
+//! dither(&mut image, &palette);
+//! let indices = index_colors(&image, &palette);
+//! assert!(indices.pixels().any(|p| p[0] == 1));
+//! ```
+//!
+//! [`Palette`]: struct.Palette.html
+//! [`ColorMap`]: ../imageops/colorops/trait.ColorMap.html
+//! [`index_colors`]: ../imageops/fn.index_colors.html
+//! [`dither`]: ../imageops/fn.dither.html
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use crate::error::{
+    DecodingError, ImageError, ImageFormatHint, ImageResult, ParameterError, ParameterErrorKind,
+    UnsupportedError, UnsupportedErrorKind,
+};
+use crate::imageops::colorops::ColorMap;
+use crate::{ImageBuffer, Luma, Rgb, RgbImage};
+
+/// A file format for palettes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PaletteFormat {
+    /// The text format of GIMP and Inkscape, usually with the extension `.gpl`.
+    Gpl,
+    /// The binary Adobe Color Table of Photoshop, usually with the extension `.act`.
+    Act,
+    /// The text format of Paint Shop Pro, usually with the extension `.pal`.
+    Jasc,
+}
+
+impl PaletteFormat {
+    /// Returns the palette format of a file extension, ignoring case.
+    pub fn from_extension<S: AsRef<std::ffi::OsStr>>(ext: S) -> Option<Self> {
+        let ext = ext.as_ref().to_str()?.to_ascii_lowercase();
+        match ext.as_str() {
+            "gpl" => Some(PaletteFormat::Gpl),
+            "act" => Some(PaletteFormat::Act),
+            "pal" => Some(PaletteFormat::Jasc),
+            _ => None,
+        }
+    }
+
+    fn hint(self) -> ImageFormatHint {
+        let name = match self {
+            PaletteFormat::Gpl => "GPL",
+            PaletteFormat::Act => "ACT",
+            PaletteFormat::Jasc => "JASC-PAL",
+        };
+        ImageFormatHint::Name(name.to_string())
+    }
+}
+
+/// An ordered list of colors.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Palette {
+    colors: Vec<Rgb<u8>>,
+}
+
+impl Palette {
+    /// Creates a palette of the given colors.
+    pub fn new(colors: Vec<Rgb<u8>>) -> Self {
+        Palette { colors }
+    }
+
+    /// Finds a palette of at most `max_colors` colors that represents an image well, with the
+    /// NeuQuant algorithm.
+    pub fn quantize(image: &RgbImage, max_colors: usize) -> Self {
+        let mut pixels = Vec::with_capacity(image.len() / 3 * 4);
+        for pixel in image.pixels() {
+            pixels.extend_from_slice(&[pixel[0], pixel[1], pixel[2], 0xFF]);
+        }
+        let quantizer = color_quant::NeuQuant::new(10, max_colors, &pixels);
+        let colors = quantizer
+            .color_map_rgb()
+            .chunks_exact(3)
+            .map(|c| Rgb([c[0], c[1], c[2]]))
+            .collect();
+        Palette { colors }
+    }
+
+    /// Finds a palette of at most `max_colors` colors that represents an image well, with the
+    /// median cut algorithm.
+    ///
+    /// The colors of the image are split into boxes of about as many pixels each, always cutting
+    /// the box that spans the widest range of a channel, and each box contributes its average
+    /// color. This is faster than NeuQuant and keeps smooth gradients even, but spends few colors
+    /// on small areas of distinct color.
+    pub fn median_cut(image: &RgbImage, max_colors: usize) -> Self {
+        Palette::median_cut_of(image.pixels().copied(), max_colors)
+    }
+
+    /// Finds a palette for the given pixels with the median cut algorithm.
+    pub(crate) fn median_cut_of<I>(pixels: I, max_colors: usize) -> Self
+    where
+        I: IntoIterator<Item = Rgb<u8>>,
+    {
+        let mut counts: HashMap<Rgb<u8>, u64> = HashMap::new();
+        for pixel in pixels {
+            *counts.entry(pixel).or_insert(0) += 1;
+        }
+        Palette::median_cut_of_counts(counts.into_iter().collect(), max_colors)
+    }
+
+    /// Finds a palette for distinct colors and their number of pixels with the median cut
+    /// algorithm.
+    pub(crate) fn median_cut_of_counts(mut colors: Vec<(Rgb<u8>, u64)>, max_colors: usize) -> Self {
+        if colors.is_empty() || max_colors == 0 {
+            return Palette::default();
+        }
+        colors.sort_unstable_by_key(|(color, _)| color.0);
+
+        let mut boxes = vec![colors];
+        while boxes.len() < max_colors {
+            let widest = boxes
+                .iter()
+                .enumerate()
+                .filter(|(_, colors)| colors.len() > 1)
+                .map(|(index, colors)| {
+                    let (channel, range) = widest_channel(colors);
+                    (range, index, channel)
+                })
+                .max();
+            let (index, channel) = match widest {
+                Some((_, index, channel)) => (index, channel),
+                None => break,
+            };
+
+            let mut lower = boxes.swap_remove(index);
+            lower.sort_by_key(|(color, _)| color[channel]);
+            // Cut after the color that holds the median pixel, leaving both halves non-empty.
+            let total: u64 = lower.iter().map(|&(_, count)| count).sum();
+            let mut seen = 0;
+            let median = lower
+                .iter()
+                .position(|&(_, count)| {
+                    seen += count;
+                    2 * seen >= total
+                })
+                .unwrap_or(0);
+            let upper = lower.split_off((median + 1).min(lower.len() - 1));
+            boxes.push(lower);
+            boxes.push(upper);
+        }
+
+        let colors = boxes.iter().map(|colors| average_color(colors)).collect();
+        Palette { colors }
+    }
+
+    /// The colors of the palette.
+    pub fn colors(&self) -> &[Rgb<u8>] {
+        &self.colors
+    }
+
+    /// The colors of the palette, for changing them.
+    pub fn colors_mut(&mut self) -> &mut Vec<Rgb<u8>> {
+        &mut self.colors
+    }
+
+    /// Consumes the palette and returns its colors.
+    pub fn into_colors(self) -> Vec<Rgb<u8>> {
+        self.colors
+    }
+
+    /// The number of colors.
+    pub fn len(&self) -> usize {
+        self.colors.len()
+    }
+
+    /// Whether the palette has no colors.
+    pub fn is_empty(&self) -> bool {
+        self.colors.is_empty()
+    }
+
+    /// Replaces the indices of an indexed image with the colors they refer to.
+    ///
+    /// Indices beyond the end of the palette are black.
+    pub fn apply(&self, indices: &ImageBuffer<Luma<u8>, Vec<u8>>) -> RgbImage {
+        ImageBuffer::from_fn(indices.width(), indices.height(), |x, y| {
+            let index = usize::from(indices.get_pixel(x, y)[0]);
+            self.colors.get(index).copied().unwrap_or(Rgb([0, 0, 0]))
+        })
+    }
+
+    /// Opens a palette file, with the format determined by the extension of the path.
+    pub fn open<P: AsRef<Path>>(path: P) -> ImageResult<Self> {
+        let path = path.as_ref();
+        let format = format_of_path(path)?;
+        Palette::read(BufReader::new(File::open(path)?), format)
+    }
+
+    /// Saves the palette to a file, with the format determined by the extension of the path.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> ImageResult<()> {
+        let path = path.as_ref();
+        let format = format_of_path(path)?;
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.write(&mut writer, format)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// Reads a palette in the given format.
+    pub fn read<R: BufRead>(reader: R, format: PaletteFormat) -> ImageResult<Self> {
+        match format {
+            PaletteFormat::Gpl => read_gpl(reader),
+            PaletteFormat::Act => read_act(reader),
+            PaletteFormat::Jasc => read_jasc(reader),
+        }
+    }
+
+    /// Writes the palette in the given format.
+    ///
+    /// Returns an `ImageError::Parameter` if an Adobe Color Table would have more than 256
+    /// colors.
+    pub fn write<W: Write>(&self, mut writer: W, format: PaletteFormat) -> ImageResult<()> {
+        match format {
+            PaletteFormat::Gpl => {
+                writeln!(writer, "GIMP Palette")?;
+                writeln!(writer, "#")?;
+                for color in &self.colors {
+                    writeln!(writer, "{:3} {:3} {:3}", color[0], color[1], color[2])?;
+                }
+            }
+            PaletteFormat::Act => {
+                if self.colors.len() > 256 {
+                    return Err(ImageError::Parameter(ParameterError::from_kind(
+                        ParameterErrorKind::Generic(format!(
+                            "an Adobe Color Table holds at most 256 colors, found {}",
+                            self.colors.len()
+                        )),
+                    )));
+                }
+                let mut table = [0; 772];
+                for (entry, color) in table.chunks_exact_mut(3).zip(&self.colors) {
+                    entry.copy_from_slice(&color.0);
+                }
+                // The number of colors, followed by the index of the transparent one or 0xFFFF.
+                table[768..770].copy_from_slice(&(self.colors.len() as u16).to_be_bytes());
+                table[770..].copy_from_slice(&[0xFF, 0xFF]);
+                writer.write_all(&table)?;
+            }
+            PaletteFormat::Jasc => {
+                write!(writer, "JASC-PAL\r\n0100\r\n{}\r\n", self.colors.len())?;
+                for color in &self.colors {
+                    write!(writer, "{} {} {}\r\n", color[0], color[1], color[2])?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl From<Vec<Rgb<u8>>> for Palette {
+    fn from(colors: Vec<Rgb<u8>>) -> Self {
+        Palette::new(colors)
+    }
+}
+
+impl ColorMap for Palette {
+    type Color = Rgb<u8>;
+
+    /// Returns the index of the nearest color, or zero for an empty palette.
+    fn index_of(&self, color: &Rgb<u8>) -> usize {
+        let distance = |other: &Rgb<u8>| -> i32 {
+            color
+                .0
+                .iter()
+                .zip(&other.0)
+                .map(|(&a, &b)| (i32::from(a) - i32::from(b)).pow(2))
+                .sum()
+        };
+        self.colors
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, other)| distance(other))
+            .map_or(0, |(index, _)| index)
+    }
+
+    fn lookup(&self, index: usize) -> Option<Rgb<u8>> {
+        self.colors.get(index).copied()
+    }
+
+    fn has_lookup(&self) -> bool {
+        true
+    }
+
+    fn map_color(&self, color: &mut Rgb<u8>) {
+        if let Some(&mapped) = self.colors.get(self.index_of(color)) {
+            *color = mapped;
+        }
+    }
+}
+
+/// The channel along which colors spread the most, and the range of their values in it.
+fn widest_channel(colors: &[(Rgb<u8>, u64)]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = colors.iter().map(|(color, _)| color[channel]);
+            let range = values.clone().max().unwrap() - values.min().unwrap();
+            (channel, range)
+        })
+        .max_by_key(|&(channel, range)| (range, std::cmp::Reverse(channel)))
+        .unwrap()
+}
+
+/// The average of colors weighted by the number of their pixels.
+fn average_color(colors: &[(Rgb<u8>, u64)]) -> Rgb<u8> {
+    let total: u64 = colors.iter().map(|&(_, count)| count).sum();
+    let mut sums = [0u64; 3];
+    for (color, count) in colors {
+        for (sum, &value) in sums.iter_mut().zip(&color.0) {
+            *sum += u64::from(value) * count;
+        }
+    }
+    let average = |sum: u64| ((sum + total / 2) / total) as u8;
+    Rgb([average(sums[0]), average(sums[1]), average(sums[2])])
+}
+
+fn format_of_path(path: &Path) -> ImageResult<PaletteFormat> {
+    path.extension()
+        .and_then(PaletteFormat::from_extension)
+        .ok_or_else(|| {
+            ImageError::Unsupported(UnsupportedError::from_format_and_kind(
+                ImageFormatHint::PathExtension(path.to_owned()),
+                UnsupportedErrorKind::Format(ImageFormatHint::PathExtension(path.to_owned())),
+            ))
+        })
+}
+
+fn invalid(format: PaletteFormat, message: &str) -> ImageError {
+    ImageError::Decoding(DecodingError::new(format.hint(), message.to_string()))
+}
+
+/// Parses a line of three color components separated by whitespace, ignoring what follows.
+fn parse_color(line: &str) -> Option<Rgb<u8>> {
+    let mut components = line.split_whitespace().map(|c| c.parse::<u8>().ok());
+    Some(Rgb([
+        components.next()??,
+        components.next()??,
+        components.next()??,
+    ]))
+}
+
+fn read_gpl<R: BufRead>(reader: R) -> ImageResult<Palette> {
+    let format = PaletteFormat::Gpl;
+    let mut lines = reader.lines();
+    let header = lines.next().transpose()?;
+    if header.as_ref().map(|line| line.trim()) != Some("GIMP Palette") {
+        return Err(invalid(format, "missing GIMP Palette header"));
+    }
+
+    let mut colors = Vec::new();
+    for line in lines {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty()
+            || line.starts_with('#')
+            || line.starts_with("Name:")
+            || line.starts_with("Columns:")
+        {
+            continue;
+        }
+        colors.push(parse_color(line).ok_or_else(|| invalid(format, "invalid color entry"))?);
+    }
+    Ok(Palette { colors })
+}
+
+fn read_act<R: Read>(mut reader: R) -> ImageResult<Palette> {
+    let mut table = Vec::new();
+    reader.read_to_end(&mut table)?;
+    let count = match table.len() {
+        768 => 256,
+        772 => usize::from(u16::from_be_bytes([table[768], table[769]])).min(256),
+        _ => {
+            return Err(invalid(
+                PaletteFormat::Act,
+                "an Adobe Color Table has 768 or 772 bytes",
+            ))
+        }
+    };
+    let colors = table[..count * 3]
+        .chunks_exact(3)
+        .map(|c| Rgb([c[0], c[1], c[2]]))
+        .collect();
+    Ok(Palette { colors })
+}
+
+fn read_jasc<R: BufRead>(reader: R) -> ImageResult<Palette> {
+    let format = PaletteFormat::Jasc;
+    let mut lines = reader
+        .lines()
+        .map(|line| line.map(|line| line.trim().to_string()));
+    let mut next = || -> ImageResult<String> {
+        match lines.next() {
+            Some(line) => Ok(line?),
+            None => Err(ImageError::IoError(io::ErrorKind::UnexpectedEof.into())),
+        }
+    };
+    if next()? != "JASC-PAL" || next()? != "0100" {
+        return Err(invalid(format, "missing JASC-PAL header"));
+    }
+    let count: usize = next()?
+        .parse()
+        .map_err(|_| invalid(format, "invalid color count"))?;
+
+    let mut colors = Vec::with_capacity(count.min(256));
+    for _ in 0..count {
+        colors.push(parse_color(&next()?).ok_or_else(|| invalid(format, "invalid color entry"))?);
+    }
+    Ok(Palette { colors })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn palette() -> Palette {
+        Palette::new(vec![Rgb([0, 0, 0]), Rgb([255, 0, 16]), Rgb([1, 2, 3])])
+    }
+
+    #[test]
+    fn formats_round_trip() {
+        for &format in &[PaletteFormat::Gpl, PaletteFormat::Act, PaletteFormat::Jasc] {
+            let mut file = Vec::new();
+            palette().write(&mut file, format).unwrap();
+            assert_eq!(Palette::read(&file[..], format).unwrap(), palette());
+        }
+    }
+
+    #[test]
+    fn gpl_with_names_and_columns() {
+        let file = b"GIMP Palette\r\nName: Test\r\nColumns: 4\r\n# comment\r\n\r\n255   0  16\tRed\r\n  1 2 3 Untitled\r\n";
+        let parsed = Palette::read(&file[..], PaletteFormat::Gpl).unwrap();
+        assert_eq!(parsed.colors(), &palette().colors()[1..]);
+        assert!(Palette::read(&b"GIMP Palette\n1 2\n"[..], PaletteFormat::Gpl).is_err());
+        assert!(Palette::read(&b"1 2 3\n"[..], PaletteFormat::Gpl).is_err());
+    }
+
+    #[test]
+    fn act_without_count() {
+        let mut table = vec![0; 768];
+        table[3..6].copy_from_slice(&[4, 5, 6]);
+        let parsed = Palette::read(&table[..], PaletteFormat::Act).unwrap();
+        assert_eq!(parsed.len(), 256);
+        assert_eq!(parsed.colors()[1], Rgb([4, 5, 6]));
+        assert!(Palette::read(&table[1..], PaletteFormat::Act).is_err());
+    }
+
+    #[test]
+    fn jasc_truncated() {
+        let file = b"JASC-PAL\r\n0100\r\n2\r\n1 2 3\r\n";
+        assert!(Palette::read(&file[..], PaletteFormat::Jasc).is_err());
+    }
+
+    #[test]
+    fn maps_to_nearest_color() {
+        let palette = palette();
+        assert_eq!(palette.index_of(&Rgb([250, 10, 10])), 1);
+        assert_eq!(palette.index_of(&Rgb([3, 3, 3])), 2);
+        let mut color = Rgb([200, 0, 0]);
+        palette.map_color(&mut color);
+        assert_eq!(color, Rgb([255, 0, 16]));
+
+        let indices = ImageBuffer::from_fn(3, 1, |x, _| Luma([[1, 2, 7][x as usize]]));
+        let image = palette.apply(&indices);
+        assert_eq!(image.get_pixel(0, 0), &Rgb([255, 0, 16]));
+        assert_eq!(image.get_pixel(1, 0), &Rgb([1, 2, 3]));
+        assert_eq!(image.get_pixel(2, 0), &Rgb([0, 0, 0]));
+    }
+
+    #[test]
+    fn median_cut() {
+        // A gray ramp and a few red pixels.
+        let image = RgbImage::from_fn(64, 4, |x, y| match y {
+            0 if x < 4 => Rgb([255, 0, 0]),
+            _ => Rgb([x as u8 * 4; 3]),
+        });
+        let palette = Palette::median_cut(&image, 4);
+        assert_eq!(palette.len(), 4);
+        // The ramp is cut into even parts, and the red pixels end up in one of them.
+        let mut grays: Vec<u8> = palette.colors().iter().map(|c| c[1]).collect();
+        grays.sort_unstable();
+        assert!(grays.windows(2).all(|pair| pair[1] - pair[0] > 40));
+
+        let few = RgbImage::from_fn(2, 1, |x, _| Rgb([x as u8; 3]));
+        assert_eq!(Palette::median_cut(&few, 4).len(), 2);
+        assert!(Palette::median_cut(&few, 0).is_empty());
+    }
+
+    #[test]
+    fn extensions() {
+        assert_eq!(
+            PaletteFormat::from_extension("GPL"),
+            Some(PaletteFormat::Gpl)
+        );
+        assert_eq!(
+            PaletteFormat::from_extension("pal"),
+            Some(PaletteFormat::Jasc)
+        );
+        assert_eq!(PaletteFormat::from_extension("png"), None);
+    }
+}
@@ -0,0 +1,271 @@
+//! Reading and writing animations as sequences of numbered image files.
+//!
+//! Video tools and renderers commonly exchange animations as a directory of still images named
+//! after a pattern such as `frame_0001.png`, `frame_0002.png` and so on. [`open_sequence`] reads
+//! such a sequence as [`Frames`], like the animation decoders do, and [`save_sequence`] writes
+//! frames out as one. Patterns name the frame number with `%d`, or with `%04d` to pad it with
+//! zeros to four digits, as `printf` and `ffmpeg` do. A literal `%` is written as `%%`.
+//!
+//! ```no_run
+//! use image::{AnimationOptions, Delay};
+//!
+//! // A sequence rendered at 24 frames per second.
+//! let delay = Delay::from_numer_denom_ms(1000, 24);
+//! let frames = image::sequence::open_sequence("render/frame_%04d.png", delay)?;
+//! image::save_animation("render.gif", frames.collect_frames()?, AnimationOptions::default())?;
+//! # Ok::<(), image::ImageError>(())
+//! ```
+//!
+//! [`open_sequence`]: fn.open_sequence.html
+//! [`save_sequence`]: fn.save_sequence.html
+//! [`Frames`]: ../struct.Frames.html
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::animation::{Delay, Frame, Frames};
+use crate::color::ColorType;
+use crate::dynimage::{self, DynamicImage};
+use crate::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
+
+/// The numbers that the first file of a sequence is looked up at, as `ffmpeg` does.
+const FIRST_NUMBERS: std::ops::RangeInclusive<u32> = 0..=4;
+
+/// Open the sequence of numbered images that `pattern` names as the frames of an animation.
+///
+/// The sequence starts at the lowest of the numbers 0 to 4 that a file exists for and ends before
+/// the first number without one. Each frame is shown for `delay`, the inverse of the frame rate.
+/// Images are decoded as the frames are iterated, and must all have the dimensions of the first
+/// one.
+///
+/// Returns an error if the pattern does not name the frame number exactly once, or if the
+/// sequence has no first image.
+pub fn open_sequence<P: AsRef<Path>>(pattern: P, delay: Delay) -> ImageResult<Frames<'static>> {
+    let pattern = Pattern::parse(pattern.as_ref())?;
+    let first = FIRST_NUMBERS
+        .clone()
+        .find(|&number| pattern.path(number).is_file())
+        .ok_or_else(|| {
+            ImageError::IoError(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no image sequence at {}", pattern.path(0).display()),
+            ))
+        })?;
+
+    let image = dynimage::open(pattern.path(first))?.into_rgba8();
+    let (width, height) = image.dimensions();
+    let iterator = SequenceFrames {
+        pattern,
+        next: first.checked_add(1),
+        first: Some(image),
+        dimensions: (width, height),
+        delay,
+    };
+    Ok(Frames::new(Box::new(iterator)).with_canvas(width, height))
+}
+
+/// Save `frames` as a sequence of numbered images named after `pattern`, starting at `first`.
+///
+/// The format of the images is derived from the extension of the pattern. Frames are saved as
+/// they are, without their offset, so they should be composited, as those returned by
+/// `AnimationDecoder::into_frames` are. Returns the number of images written.
+///
+/// Returns an error if the pattern does not name the frame number exactly once.
+pub fn save_sequence<P, F>(pattern: P, first: u32, frames: F) -> ImageResult<u32>
+where
+    P: AsRef<Path>,
+    F: IntoIterator<Item = Frame>,
+{
+    let pattern = Pattern::parse(pattern.as_ref())?;
+    let mut written = 0;
+    for frame in frames {
+        let number = first.checked_add(written).ok_or_else(|| {
+            ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                "frame numbers exceed u32::MAX".into(),
+            )))
+        })?;
+        let buffer = frame.into_buffer();
+        let (width, height) = buffer.dimensions();
+        dynimage::save_buffer(
+            pattern.path(number),
+            buffer.as_raw(),
+            width,
+            height,
+            ColorType::Rgba8,
+        )?;
+        written += 1;
+    }
+    Ok(written)
+}
+
+/// The frames of an image sequence, created by [`open_sequence`].
+///
+/// [`open_sequence`]: fn.open_sequence.html
+struct SequenceFrames {
+    pattern: Pattern,
+    /// The number of the next image, or `None` after the last possible one.
+    next: Option<u32>,
+    /// The first image, which is opened to check that the sequence exists.
+    first: Option<crate::RgbaImage>,
+    /// The dimensions of the first image, which all others must have.
+    dimensions: (u32, u32),
+    delay: Delay,
+}
+
+impl Iterator for SequenceFrames {
+    type Item = ImageResult<Frame>;
+
+    fn next(&mut self) -> Option<ImageResult<Frame>> {
+        if let Some(image) = self.first.take() {
+            return Some(Ok(Frame::from_parts(image, 0, 0, self.delay)));
+        }
+
+        let number = self.next?;
+        let path = self.pattern.path(number);
+        if !path.is_file() {
+            self.next = None;
+            return None;
+        }
+        self.next = number.checked_add(1);
+
+        let image = match dynimage::open(&path) {
+            Ok(image) => image,
+            Err(err) => {
+                // Do not continue after an image that fails to decode.
+                self.next = None;
+                return Some(Err(err));
+            }
+        };
+        Some(self.frame(image))
+    }
+}
+
+impl SequenceFrames {
+    fn frame(&mut self, image: DynamicImage) -> ImageResult<Frame> {
+        if (image.width(), image.height()) != self.dimensions {
+            self.next = None;
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::DimensionMismatch,
+            )));
+        }
+        Ok(Frame::from_parts(image.into_rgba8(), 0, 0, self.delay))
+    }
+}
+
+/// A file name pattern with a `printf` style placeholder for the frame number.
+struct Pattern {
+    prefix: String,
+    /// The number of digits that the frame number is padded to with zeros.
+    width: usize,
+    suffix: String,
+}
+
+impl Pattern {
+    fn parse(pattern: &Path) -> ImageResult<Pattern> {
+        let invalid = |reason: &str| {
+            ImageError::Parameter(ParameterError::from_kind(ParameterErrorKind::Generic(
+                format!("invalid image sequence pattern: {}", reason),
+            )))
+        };
+        let pattern = pattern
+            .to_str()
+            .ok_or_else(|| invalid("the path is not valid unicode"))?;
+
+        let mut prefix = String::new();
+        let mut suffix = String::new();
+        let mut width = None;
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            let part = if width.is_some() {
+                &mut suffix
+            } else {
+                &mut prefix
+            };
+            if c != '%' {
+                part.push(c);
+                continue;
+            }
+            if chars.peek() == Some(&'%') {
+                chars.next();
+                part.push('%');
+                continue;
+            }
+
+            let mut digits = String::new();
+            while let Some(&digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                digits.push(digit);
+                chars.next();
+            }
+            if chars.next() != Some('d') || (!digits.is_empty() && !digits.starts_with('0')) {
+                return Err(invalid("the frame number must be written as %d or %0Nd"));
+            }
+            if width.is_some() {
+                return Err(invalid("the frame number is named more than once"));
+            }
+            width = Some(digits.parse().unwrap_or(0));
+        }
+
+        let width = width.ok_or_else(|| invalid("the frame number is not named"))?;
+        Ok(Pattern {
+            prefix,
+            width,
+            suffix,
+        })
+    }
+
+    fn path(&self, number: u32) -> PathBuf {
+        PathBuf::from(format!(
+            "{}{:0width$}{}",
+            self.prefix,
+            number,
+            self.suffix,
+            width = self.width
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn patterns() {
+        let path =
+            |pattern: &str, number| Pattern::parse(Path::new(pattern)).map(|p| p.path(number));
+        assert_eq!(path("a_%04d.png", 7).unwrap(), Path::new("a_0007.png"));
+        assert_eq!(path("%d%%.png", 12).unwrap(), Path::new("12%.png"));
+        assert_eq!(path("a_%02d.png", 123).unwrap(), Path::new("a_123.png"));
+        assert!(path("a.png", 0).is_err());
+        assert!(path("%d_%d.png", 0).is_err());
+        assert!(path("a_%4d.png", 0).is_err());
+        assert!(path("a_%s.png", 0).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn save_and_open() {
+        use crate::{Rgba, RgbaImage};
+
+        let dir = std::env::temp_dir().join("image-sequence");
+        std::fs::create_dir_all(&dir).unwrap();
+        let pattern = dir.join("frame_%03d.ppm");
+        let frames: Vec<_> = (0..3)
+            .map(|i| Frame::new(RgbaImage::from_pixel(2, 1, Rgba([i, 0, 0, 255]))))
+            .collect();
+        let _ = std::fs::remove_file(dir.join("frame_004.ppm"));
+        assert_eq!(save_sequence(&pattern, 1, frames.clone()).unwrap(), 3);
+        assert!(dir.join("frame_003.ppm").is_file());
+
+        let delay = Delay::from_numer_denom_ms(1000, 25);
+        let opened = open_sequence(&pattern, delay).unwrap();
+        assert_eq!(opened.canvas_dimensions(), Some((2, 1)));
+        let opened = opened.collect_frames().unwrap();
+        assert_eq!(opened.len(), 3);
+        for (opened, saved) in opened.iter().zip(&frames) {
+            assert_eq!(opened.buffer(), saved.buffer());
+            assert_eq!(opened.delay(), delay);
+        }
+
+        assert!(open_sequence(dir.join("missing_%d.ppm"), delay).is_err());
+    }
+}
@@ -0,0 +1,140 @@
+//! Contains the `SharedImage` struct, a reference counted image with copy-on-write semantics.
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::buffer_::ImageBuffer;
+use crate::image::GenericImageView;
+use crate::traits::Pixel;
+
+/// A reference counted, copy-on-write handle to an [`ImageBuffer`].
+///
+/// Cloning a `SharedImage` only increments a reference count, all clones read the same pixels.
+/// The buffer is copied at most once, when [`make_mut`] is called on a handle that is not the
+/// only one. This makes it cheap to hand one decoded image to several consumers, for example to
+/// generate multiple thumbnail sizes on different threads.
+///
+/// ```
+/// use image::{imageops, RgbImage, SharedImage};
+///
+/// let source = SharedImage::new(RgbImage::new(64, 64));
+/// let thumbnails: Vec<_> = [32, 16, 8]
+///     .iter()
+///     .map(|&size| {
+///         let source = source.clone();
+///         std::thread::spawn(move || imageops::thumbnail(&source, size, size))
+///     })
+///     .map(|handle| handle.join().unwrap())
+///     .collect();
+/// assert_eq!(thumbnails[2].dimensions(), (8, 8));
+/// ```
+///
+/// [`ImageBuffer`]: struct.ImageBuffer.html
+/// [`make_mut`]: #method.make_mut
+#[derive(Debug)]
+pub struct SharedImage<P: Pixel> {
+    buffer: Arc<ImageBuffer<P, Vec<P::Subpixel>>>,
+}
+
+impl<P: Pixel> SharedImage<P> {
+    /// Moves an image buffer into a new shared handle.
+    pub fn new(buffer: ImageBuffer<P, Vec<P::Subpixel>>) -> Self {
+        SharedImage {
+            buffer: Arc::new(buffer),
+        }
+    }
+
+    /// Returns a mutable reference to the image buffer.
+    ///
+    /// If other handles to the same buffer exist then the buffer is cloned first, so that
+    /// modifications are never visible through the other handles.
+    pub fn make_mut(&mut self) -> &mut ImageBuffer<P, Vec<P::Subpixel>> {
+        Arc::make_mut(&mut self.buffer)
+    }
+
+    /// Returns the image buffer, cloning it only if other handles to it exist.
+    pub fn into_buffer(self) -> ImageBuffer<P, Vec<P::Subpixel>> {
+        Arc::try_unwrap(self.buffer).unwrap_or_else(|buffer| (*buffer).clone())
+    }
+
+    /// Returns true if both handles refer to the same buffer.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.buffer, &other.buffer)
+    }
+}
+
+impl<P: Pixel> Clone for SharedImage<P> {
+    fn clone(&self) -> Self {
+        SharedImage {
+            buffer: Arc::clone(&self.buffer),
+        }
+    }
+}
+
+impl<P: Pixel> From<ImageBuffer<P, Vec<P::Subpixel>>> for SharedImage<P> {
+    fn from(buffer: ImageBuffer<P, Vec<P::Subpixel>>) -> Self {
+        SharedImage::new(buffer)
+    }
+}
+
+impl<P: Pixel> Deref for SharedImage<P> {
+    type Target = ImageBuffer<P, Vec<P::Subpixel>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.buffer
+    }
+}
+
+impl<P: Pixel> GenericImageView for SharedImage<P> {
+    type Pixel = P;
+
+    fn dimensions(&self) -> (u32, u32) {
+        self.buffer.dimensions()
+    }
+
+    fn bounds(&self) -> (u32, u32, u32, u32) {
+        self.buffer.bounds()
+    }
+
+    fn get_pixel(&self, x: u32, y: u32) -> P {
+        *self.buffer.get_pixel(x, y)
+    }
+
+    unsafe fn unsafe_get_pixel(&self, x: u32, y: u32) -> P {
+        self.buffer.unsafe_get_pixel(x, y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedImage;
+    use crate::color::Luma;
+    use crate::image::GenericImageView;
+    use crate::GrayImage;
+
+    #[test]
+    fn clones_share_the_buffer() {
+        let image = SharedImage::new(GrayImage::new(16, 16));
+        let other = image.clone();
+        assert!(image.ptr_eq(&other));
+        assert_eq!(image.as_ptr(), other.as_ptr());
+    }
+
+    #[test]
+    fn make_mut_copies_shared_buffer() {
+        let original = SharedImage::new(GrayImage::new(2, 2));
+        let mut modified = original.clone();
+        modified.make_mut().put_pixel(0, 0, Luma([9]));
+        assert!(!original.ptr_eq(&modified));
+        assert_eq!(original.get_pixel(0, 0), Luma([0]));
+        assert_eq!(GenericImageView::get_pixel(&modified, 0, 0), Luma([9]));
+    }
+
+    #[test]
+    fn make_mut_reuses_unique_buffer() {
+        let mut image = SharedImage::new(GrayImage::new(2, 2));
+        let before = image.as_ptr();
+        image.make_mut().put_pixel(1, 1, Luma([3]));
+        assert_eq!(image.as_ptr(), before);
+        assert_eq!(image.into_buffer().as_ptr(), before);
+    }
+}
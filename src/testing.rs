@@ -0,0 +1,473 @@
+//! Generators of random and malformed images, for property-based testing and fuzzing, and
+//! assertions against golden images.
+//!
+//! These let crates built on `image` test their own pipelines against the variety of images
+//! users feed them: every color type, unusual dimensions, and files that are damaged in the ways
+//! real world files are, such as truncation and flipped bits. Rendered output can be checked
+//! against reference images with [`assert_images_match`].
+//!
+//! The generators take any random number generator implementing `rand_core::RngCore`, so they
+//! work with the generators of `rand` as well as with seeded ones for reproducible test cases.
+//!
+//! ```
+//! use image::testing::{arbitrary_image, Constraints};
+//! use image::GenericImageView;
+//! use rand_core::{impls, Error, RngCore};
+//!
+//! // A tiny generator for the example, use the ones of `rand` instead.
+//! struct Counter(u64);
+//!
+//! impl RngCore for Counter {
+//!     fn next_u32(&mut self) -> u32 { self.next_u64() as u32 }
+//!     fn next_u64(&mut self) -> u64 { self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15); self.0 >> 7 }
+//!     fn fill_bytes(&mut self, dest: &mut [u8]) { impls::fill_bytes_via_next(self, dest) }
+//!     fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> { Ok(self.fill_bytes(dest)) }
+//! }
+//!
+//! let mut constraints = Constraints::default();
+//! constraints.widths = 1..=32;
+//! let image = arbitrary_image(&mut Counter(1), &constraints);
+//! assert!((1..=32).contains(&image.width()));
+//! ```
+//!
+//! [`assert_images_match`]: fn.assert_images_match.html
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use rand_core::RngCore;
+
+use crate::buffer_::ImageBuffer;
+use crate::color::ColorType;
+use crate::dynimage::DynamicImage;
+use crate::error::ImageResult;
+use crate::image::{GenericImageView, ImageFormat};
+use crate::io::free_functions;
+use crate::traits::Pixel;
+
+/// The range of images [`arbitrary_image`] generates.
+///
+/// [`arbitrary_image`]: fn.arbitrary_image.html
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub struct Constraints {
+    /// The range of widths. The default is `1..=64`.
+    pub widths: RangeInclusive<u32>,
+    /// The range of heights. The default is `1..=64`.
+    pub heights: RangeInclusive<u32>,
+    /// The color types to choose from. The default is every color type.
+    pub color_types: Vec<ColorType>,
+}
+
+impl Default for Constraints {
+    fn default() -> Constraints {
+        Constraints {
+            widths: 1..=64,
+            heights: 1..=64,
+            color_types: vec![
+                ColorType::L8,
+                ColorType::La8,
+                ColorType::Rgb8,
+                ColorType::Rgba8,
+                ColorType::L16,
+                ColorType::La16,
+                ColorType::Rgb16,
+                ColorType::Rgba16,
+                ColorType::Rgb32F,
+                ColorType::Rgba32F,
+            ],
+        }
+    }
+}
+
+/// Generates an image of random dimensions, color type and pixels within `constraints`.
+///
+/// The samples of floating point images are in `[0, 1)`.
+///
+/// # Panics
+///
+/// Panics if a range of dimensions or the list of color types is empty.
+pub fn arbitrary_image<R>(rng: &mut R, constraints: &Constraints) -> DynamicImage
+where
+    R: RngCore + ?Sized,
+{
+    let width = random_in(rng, &constraints.widths);
+    let height = random_in(rng, &constraints.heights);
+    assert!(
+        !constraints.color_types.is_empty(),
+        "no color types to choose from"
+    );
+    let index = random_in(rng, &(0..=constraints.color_types.len() as u32 - 1));
+    let color = constraints.color_types[index as usize];
+
+    let byte = |rng: &mut R| rng.next_u32() as u8;
+    let word = |rng: &mut R| rng.next_u32() as u16;
+    let float = |rng: &mut R| (rng.next_u32() >> 8) as f32 / (1 << 24) as f32;
+    match color {
+        ColorType::L8 => DynamicImage::ImageLuma8(random_buffer(rng, width, height, byte)),
+        ColorType::La8 => DynamicImage::ImageLumaA8(random_buffer(rng, width, height, byte)),
+        ColorType::Rgb8 => DynamicImage::ImageRgb8(random_buffer(rng, width, height, byte)),
+        ColorType::Rgba8 => DynamicImage::ImageRgba8(random_buffer(rng, width, height, byte)),
+        ColorType::L16 => DynamicImage::ImageLuma16(random_buffer(rng, width, height, word)),
+        ColorType::La16 => DynamicImage::ImageLumaA16(random_buffer(rng, width, height, word)),
+        ColorType::Rgb16 => DynamicImage::ImageRgb16(random_buffer(rng, width, height, word)),
+        ColorType::Rgba16 => DynamicImage::ImageRgba16(random_buffer(rng, width, height, word)),
+        ColorType::Rgb32F => DynamicImage::ImageRgb32F(random_buffer(rng, width, height, float)),
+        ColorType::Rgba32F => DynamicImage::ImageRgba32F(random_buffer(rng, width, height, float)),
+    }
+}
+
+/// Generates a damaged file of the given format.
+///
+/// A small random image is encoded in `format` and then corrupted, by truncating it, flipping
+/// bits, inserting bytes or overwriting bytes with large values. The magic bytes the format is
+/// recognized by are kept intact, so that the file reaches the decoder of the format. Feeding
+/// such files to a pipeline should result in errors, never in panics or hangs.
+///
+/// Returns an error if `format` can not be encoded, because it is not supported or its feature
+/// is disabled.
+pub fn malformed_file<R>(rng: &mut R, format: ImageFormat) -> ImageResult<Vec<u8>>
+where
+    R: RngCore + ?Sized,
+{
+    // Formats support different color types, try them in turn until one of them is encoded.
+    let mut constraints = Constraints {
+        widths: 1..=16,
+        heights: 1..=16,
+        ..Constraints::default()
+    };
+    let mut data = Vec::new();
+    let mut result = Ok(());
+    for &color in &[
+        ColorType::Rgb8,
+        ColorType::Rgba8,
+        ColorType::L8,
+        ColorType::Rgba16,
+        ColorType::Rgba32F,
+    ] {
+        constraints.color_types = vec![color];
+        let image = arbitrary_image(rng, &constraints);
+        let mut encoded = Cursor::new(Vec::new());
+        result = image.write_to(&mut encoded, format);
+        if result.is_ok() {
+            data = encoded.into_inner();
+            break;
+        }
+    }
+    result?;
+
+    let keep = free_functions::signature_len(&data);
+    if data.len() <= keep {
+        return Ok(data);
+    }
+    let position =
+        |rng: &mut R, len: usize| random_in(rng, &(keep as u32..=len as u32 - 1)) as usize;
+    match rng.next_u32() % 4 {
+        0 => {
+            let len = position(rng, data.len());
+            data.truncate(len);
+        }
+        1 => {
+            for _ in 0..=rng.next_u32() % 4 {
+                let index = position(rng, data.len());
+                data[index] ^= (rng.next_u32() % 255 + 1) as u8;
+            }
+        }
+        2 => {
+            let index = position(rng, data.len());
+            let mut inserted = vec![0; 1 + rng.next_u32() as usize % 16];
+            rng.fill_bytes(&mut inserted);
+            data.splice(index..index, inserted);
+        }
+        _ => {
+            let index = position(rng, data.len());
+            let end = data.len().min(index + 4);
+            for byte in &mut data[index..end] {
+                *byte = 0xff;
+            }
+        }
+    }
+    Ok(data)
+}
+
+/// The environment variable that makes [`assert_images_match`] regenerate golden images.
+///
+/// [`assert_images_match`]: fn.assert_images_match.html
+pub const UPDATE_GOLDEN_VAR: &str = "IMAGE_UPDATE_GOLDEN";
+
+/// Asserts that an image matches the golden image stored at a path.
+///
+/// The images match if they have the same dimensions and no channel of their pixels, converted
+/// to 8 bit RGBA, differs by more than `tolerance`. Otherwise, the function panics after writing
+/// the actual image and an image of the differences next to the golden image, so that failures
+/// on CI can be inspected. For a golden image `render.png` these are `render.actual.png` and
+/// `render.diff.png`, in which differing pixels are red and the golden image is shown faintly
+/// everywhere else.
+///
+/// If the environment variable `IMAGE_UPDATE_GOLDEN` is set to anything but `0`, the actual image
+/// is saved as the new golden image instead, creating missing directories. This is how golden
+/// images are created in the first place, and updated after intended changes.
+///
+/// ```no_run
+/// # fn render() -> image::DynamicImage { unimplemented!() }
+/// use image::testing::assert_images_match;
+///
+/// assert_images_match(&render(), "tests/golden/render.png", 2);
+/// ```
+///
+/// # Panics
+///
+/// Panics if the images do not match, or if the golden image can not be read or written.
+#[track_caller]
+pub fn assert_images_match<P>(actual: &DynamicImage, golden: P, tolerance: u8)
+where
+    P: AsRef<Path>,
+{
+    let update = env::var_os(UPDATE_GOLDEN_VAR).map_or(false, |value| value != "0");
+    if let Err(message) = images_match(actual, golden.as_ref(), tolerance, update) {
+        panic!("{}", message);
+    }
+}
+
+/// Compares `actual` to the golden image at `golden`, or replaces it if `update` is set.
+fn images_match(
+    actual: &DynamicImage,
+    golden: &Path,
+    tolerance: u8,
+    update: bool,
+) -> Result<(), String> {
+    if update {
+        if let Some(parent) = golden.parent() {
+            fs::create_dir_all(parent).map_err(|err| {
+                format!(
+                    "Could not create the directory {}: {}",
+                    parent.display(),
+                    err
+                )
+            })?;
+        }
+        return actual.save(golden).map_err(|err| {
+            format!(
+                "Could not save the golden image {}: {}",
+                golden.display(),
+                err
+            )
+        });
+    }
+
+    let expected = crate::open(golden).map_err(|err| {
+        format!(
+            "Could not open the golden image {}: {}\n\
+             Set {}=1 to create it from the actual image.",
+            golden.display(),
+            err,
+            UPDATE_GOLDEN_VAR
+        )
+    })?;
+
+    let actual_path = sibling_path(golden, "actual");
+    let mut message = if actual.dimensions() != expected.dimensions() {
+        format!(
+            "The image has dimensions {:?}, but the golden image {} has dimensions {:?}",
+            actual.dimensions(),
+            golden.display(),
+            expected.dimensions()
+        )
+    } else {
+        let (actual_rgba, expected_rgba) = (actual.to_rgba8(), expected.to_rgba8());
+        let mut differing = 0u64;
+        let mut largest = (0, (0, 0));
+        let diff = ImageBuffer::from_fn(actual.width(), actual.height(), |x, y| {
+            let (a, e) = (actual_rgba.get_pixel(x, y), expected_rgba.get_pixel(x, y));
+            let difference =
+                a.0.iter()
+                    .zip(&e.0)
+                    .map(|(&a, &e)| a.max(e) - a.min(e))
+                    .max()
+                    .unwrap_or(0);
+            if difference > largest.0 {
+                largest = (difference, (x, y));
+            }
+            if difference > tolerance {
+                differing += 1;
+                crate::Rgb([255, 0, 0])
+            } else {
+                // A faint copy of the golden image, to give the differences context.
+                let luma = (u32::from(e[0]) + u32::from(e[1]) + u32::from(e[2])) / 3;
+                let faint = (255 - (255 - luma) / 4) as u8;
+                crate::Rgb([faint, faint, faint])
+            }
+        });
+        if differing == 0 {
+            return Ok(());
+        }
+
+        let diff_path = sibling_path(golden, "diff");
+        let mut message = format!(
+            "{} pixels differ from the golden image {} by more than {}, the largest difference \
+             is {} at {:?}",
+            differing,
+            golden.display(),
+            tolerance,
+            largest.0,
+            largest.1
+        );
+        match DynamicImage::ImageRgb8(diff).save(&diff_path) {
+            Ok(()) => message += &format!("\nThe differences are in {}", diff_path.display()),
+            Err(err) => message += &format!("\nCould not save the differences: {}", err),
+        }
+        message
+    };
+
+    match actual.save(&actual_path) {
+        Ok(()) => message += &format!("\nThe actual image is in {}", actual_path.display()),
+        Err(err) => message += &format!("\nCould not save the actual image: {}", err),
+    }
+    message += &format!(
+        "\nSet {}=1 to accept the actual image as the golden image.",
+        UPDATE_GOLDEN_VAR
+    );
+    Err(message)
+}
+
+/// The path of a file next to `path`, with `suffix` inserted before the extension.
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.file_stem().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(suffix);
+    if let Some(extension) = path.extension() {
+        name.push(".");
+        name.push(extension);
+    }
+    path.with_file_name(name)
+}
+
+/// A uniformly distributed number in `range`, up to a negligible bias.
+fn random_in<R: RngCore + ?Sized>(rng: &mut R, range: &RangeInclusive<u32>) -> u32 {
+    let (start, end) = (*range.start(), *range.end());
+    assert!(start <= end, "empty range {:?}", range);
+    let len = u64::from(end - start) + 1;
+    start + (rng.next_u64() % len) as u32
+}
+
+fn random_buffer<P, R, F>(
+    rng: &mut R,
+    width: u32,
+    height: u32,
+    mut sample: F,
+) -> ImageBuffer<P, Vec<P::Subpixel>>
+where
+    P: Pixel,
+    R: RngCore + ?Sized,
+    F: FnMut(&mut R) -> P::Subpixel,
+{
+    let len = width as usize * height as usize * usize::from(P::CHANNEL_COUNT);
+    let samples = (0..len).map(|_| sample(rng)).collect();
+    ImageBuffer::from_raw(width, height, samples).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand_core::{impls, Error};
+
+    /// A xorshift generator, to keep the tests deterministic.
+    struct XorShift(u64);
+
+    impl RngCore for XorShift {
+        fn next_u32(&mut self) -> u32 {
+            (self.next_u64() >> 32) as u32
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            impls::fill_bytes_via_next(self, dest)
+        }
+
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn images_within_constraints() {
+        let mut rng = XorShift(0x2545_f491_4f6c_dd1d);
+        let constraints = Constraints {
+            widths: 3..=5,
+            heights: 0..=2,
+            color_types: vec![ColorType::La16, ColorType::Rgba32F],
+        };
+        for _ in 0..64 {
+            let image = arbitrary_image(&mut rng, &constraints);
+            assert!((3..=5).contains(&image.width()));
+            assert!(image.height() <= 2);
+            assert!(constraints.color_types.contains(&image.color()));
+            if let DynamicImage::ImageRgba32F(buffer) = image {
+                assert!(buffer.iter().all(|&sample| (0.0..1.0).contains(&sample)));
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn malformed_files_keep_signature() {
+        let mut rng = XorShift(42);
+        for _ in 0..64 {
+            let data = malformed_file(&mut rng, ImageFormat::Pnm).unwrap();
+            assert_eq!(
+                free_functions::guess_format_impl(&data),
+                Some(ImageFormat::Pnm)
+            );
+            // Only the absence of panics matters.
+            let _ = crate::load_from_memory(&data);
+        }
+        assert!(malformed_file(&mut rng, ImageFormat::Avif).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "pnm")]
+    fn golden_images() {
+        let dir = env::temp_dir().join("image-golden-images");
+        let golden = dir.join("gradient.ppm");
+        let image = DynamicImage::ImageRgb8(ImageBuffer::from_fn(8, 4, |x, y| {
+            crate::Rgb([(x * 30) as u8, (y * 60) as u8, 100])
+        }));
+
+        assert!(images_match(&image, &golden, 0, false)
+            .unwrap_err()
+            .contains(UPDATE_GOLDEN_VAR));
+        images_match(&image, &golden, 0, true).unwrap();
+        images_match(&image, &golden, 0, false).unwrap();
+
+        let mut changed = image.to_rgb8();
+        changed.get_pixel_mut(1, 1)[0] += 3;
+        changed.get_pixel_mut(5, 2)[2] = 0;
+        let changed = DynamicImage::ImageRgb8(changed);
+        images_match(&changed, &golden, 100, false).unwrap();
+        let message = images_match(&changed, &golden, 2, false).unwrap_err();
+        assert!(message.starts_with("2 pixels differ"), "{}", message);
+        assert!(message.contains("the largest difference is 100 at (5, 2)"));
+        let diff = crate::open(dir.join("gradient.diff.ppm"))
+            .unwrap()
+            .to_rgb8();
+        assert_eq!(diff.get_pixel(1, 1), &crate::Rgb([255, 0, 0]));
+        assert_ne!(diff.get_pixel(0, 0), &crate::Rgb([255, 0, 0]));
+        let saved = crate::open(dir.join("gradient.actual.ppm")).unwrap();
+        assert_eq!(saved.as_bytes(), changed.as_bytes());
+
+        let cropped = image.crop_imm(0, 0, 4, 4);
+        let message = images_match(&cropped, &golden, 0, false).unwrap_err();
+        assert!(message.contains("dimensions (4, 4)"), "{}", message);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,328 @@
+use num_traits::NumCast;
+
+use crate::color::transfer::{linear_to_srgb, srgb_to_linear};
+use crate::image::GenericImageView;
+use crate::imageops::sample::FloatNearest;
+use crate::imageops::{resize, FilterType};
+use crate::traits::{Pixel, Primitive};
+use crate::{ImageBuffer, Rgba, Rgba32FImage};
+
+use super::has_alpha;
+use super::normal::renormalize_encoded;
+
+/// Options for generating mipmaps.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MipmapOptions {
+    /// Whether the color channels are sRGB encoded, in which case they are filtered in linear
+    /// light. Otherwise the values are filtered as they are, which is right for data such as
+    /// normal or roughness maps.
+    pub srgb: bool,
+    /// Whether the image is a normal map, whose vectors are rescaled to unit length on every level.
+    /// The normals are filtered as they are, regardless of `srgb`.
+    pub normal_map: bool,
+    /// The alpha test threshold, between 0 and 1, for which the fraction of pixels that pass the
+    /// test is kept the same on every level. Without it, alpha tested foliage or fences thin out
+    /// and vanish in the distance.
+    pub alpha_coverage: Option<f32>,
+    /// The maximum number of levels, including the full size image. All levels down to one pixel
+    /// are generated if this is `None`.
+    pub max_levels: Option<u32>,
+}
+
+impl Default for MipmapOptions {
+    fn default() -> Self {
+        MipmapOptions {
+            srgb: true,
+            normal_map: false,
+            alpha_coverage: None,
+            max_levels: None,
+        }
+    }
+}
+
+/// Generate the mipmap chain of an image.
+///
+/// The first level is a copy of `image`, every further level halves the width and height of the
+/// previous one, rounding down but to no less than one pixel, until a single pixel is left. Each
+/// level is filtered from the previous one with `filter`, at full precision and with color
+/// weighted by alpha so that transparent pixels do not bleed into their neighbours.
+///
+/// ```
+/// use image::imageops::FilterType;
+/// use image::texture::{generate_mipmaps, MipmapOptions};
+/// use image::RgbaImage;
+///
+/// let texture = RgbaImage::new(64, 16);
+/// let levels = generate_mipmaps(&texture, FilterType::Triangle, &MipmapOptions::default());
+/// assert_eq!(levels.len(), 7);
+/// assert_eq!(levels[3].dimensions(), (8, 2));
+/// assert_eq!(levels[6].dimensions(), (1, 1));
+/// ```
+pub fn generate_mipmaps<I, P, S>(
+    image: &I,
+    filter: FilterType,
+    options: &MipmapOptions,
+) -> Vec<ImageBuffer<P, Vec<S>>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let (mut width, mut height) = image.dimensions();
+    let srgb = options.srgb && !options.normal_map;
+    let mut level = to_linear(image, srgb);
+    let coverage = match options.alpha_coverage {
+        Some(threshold) if has_alpha::<P>() => Some((threshold, coverage(&level, threshold, 1.0))),
+        _ => None,
+    };
+    let max_levels = options.max_levels.unwrap_or(u32::MAX);
+
+    let mut levels = vec![ImageBuffer::from_fn(width, height, |x, y| {
+        image.get_pixel(x, y)
+    })];
+    while (width > 1 || height > 1) && (levels.len() as u32) < max_levels {
+        width = (width / 2).max(1);
+        height = (height / 2).max(1);
+        level = resize(&level, width, height, filter);
+
+        // The next level is filtered from this one before its alpha is scaled, so that the
+        // scaling does not compound from level to level.
+        let mut output = level.clone();
+        unpremultiply(&mut output);
+        if options.normal_map {
+            for pixel in output.pixels_mut() {
+                let [x, y, z] = renormalize_encoded([pixel[0], pixel[1], pixel[2]]);
+                *pixel = Rgba([x, y, z, pixel[3]]);
+            }
+        }
+        if let Some((threshold, target)) = coverage {
+            scale_alpha(&mut output, threshold, target);
+        }
+        levels.push(from_linear(&output, srgb));
+    }
+    levels
+}
+
+/// Converts an image to normalized floats with premultiplied alpha, decoding sRGB if requested.
+///
+/// The color channels take the first slots, alpha is moved to the last one and is one for images
+/// without alpha.
+fn to_linear<I, P, S>(image: &I, srgb: bool) -> Rgba32FImage
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let max: f32 = NumCast::from(S::DEFAULT_MAX_VALUE).unwrap();
+    let colors = color_channels::<P>();
+    let (width, height) = image.dimensions();
+    let mut out = ImageBuffer::from_fn(width, height, |x, y| {
+        let pixel = image.get_pixel(x, y);
+        let channels = pixel.channels();
+        let mut linear = [0.0, 0.0, 0.0, 1.0];
+        for (c, value) in linear.iter_mut().enumerate().take(colors) {
+            let encoded = channels[c].to_f32().unwrap() / max;
+            *value = if srgb {
+                srgb_to_linear(encoded as f64) as f32
+            } else {
+                encoded
+            };
+        }
+        if has_alpha::<P>() {
+            linear[3] = channels[colors].to_f32().unwrap() / max;
+        }
+        Rgba(linear)
+    });
+    premultiply(&mut out);
+    out
+}
+
+/// Converts normalized floats with straight alpha back to pixels, the inverse of `to_linear`.
+fn from_linear<P, S>(image: &Rgba32FImage, srgb: bool) -> ImageBuffer<P, Vec<S>>
+where
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let max: f32 = NumCast::from(S::DEFAULT_MAX_VALUE).unwrap();
+    let colors = color_channels::<P>();
+    let mut out: ImageBuffer<P, Vec<S>> = ImageBuffer::new(image.width(), image.height());
+    for (pixel, linear) in out.pixels_mut().zip(image.pixels()) {
+        let channels = pixel.channels_mut();
+        for (c, channel) in channels.iter_mut().enumerate() {
+            let value = if c < colors {
+                let value = linear[c].clamp(0.0, 1.0);
+                if srgb {
+                    linear_to_srgb(value as f64) as f32
+                } else {
+                    value
+                }
+            } else {
+                linear[3].clamp(0.0, 1.0)
+            };
+            *channel = NumCast::from(FloatNearest(value * max)).unwrap();
+        }
+    }
+    out
+}
+
+/// The number of channels before alpha.
+fn color_channels<P: Pixel>() -> usize {
+    P::CHANNEL_COUNT as usize - has_alpha::<P>() as usize
+}
+
+fn premultiply(image: &mut Rgba32FImage) {
+    for pixel in image.pixels_mut() {
+        let alpha = pixel[3];
+        for value in &mut pixel.0[..3] {
+            *value *= alpha;
+        }
+    }
+}
+
+fn unpremultiply(image: &mut Rgba32FImage) {
+    for pixel in image.pixels_mut() {
+        let alpha = pixel[3];
+        if alpha > 0.0 {
+            for value in &mut pixel.0[..3] {
+                *value /= alpha;
+            }
+        }
+    }
+}
+
+/// The fraction of pixels whose alpha, multiplied with `scale`, passes the alpha test.
+fn coverage(image: &Rgba32FImage, threshold: f32, scale: f32) -> f32 {
+    let covered = image
+        .pixels()
+        .filter(|pixel| pixel[3] * scale > threshold)
+        .count();
+    covered as f32 / image.pixels().len() as f32
+}
+
+/// Scales alpha so that the coverage of the alpha test comes as close as possible to `target`.
+fn scale_alpha(image: &mut Rgba32FImage, threshold: f32, target: f32) {
+    // Coverage grows with the scale, so search for the smallest scale that reaches the target.
+    let (mut low, mut high) = (0.0, 4.0);
+    for _ in 0..16 {
+        let middle = (low + high) / 2.0;
+        if coverage(image, threshold, middle) < target {
+            low = middle;
+        } else {
+            high = middle;
+        }
+    }
+    let error = |scale| (coverage(image, threshold, scale) - target).abs();
+    let scale = if error(low) < error(high) { low } else { high };
+
+    for pixel in image.pixels_mut() {
+        pixel[3] = (pixel[3] * scale).min(1.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayImage, LumaA, Rgb, RgbImage};
+
+    #[test]
+    fn chain_dimensions() {
+        let image = GrayImage::new(13, 4);
+        let sizes: Vec<_> = generate_mipmaps(&image, FilterType::Triangle, &Default::default())
+            .iter()
+            .map(|level| level.dimensions())
+            .collect();
+        assert_eq!(sizes, [(13, 4), (6, 2), (3, 1), (1, 1)]);
+
+        let options = MipmapOptions {
+            max_levels: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(
+            generate_mipmaps(&image, FilterType::Triangle, &options).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn srgb_averages_in_linear_light() {
+        // A checkerboard of black and white averages to half the light, which is 188 in sRGB.
+        let image = RgbImage::from_fn(2, 2, |x, y| Rgb([((x + y) % 2 * 255) as u8; 3]));
+        let levels = generate_mipmaps(&image, FilterType::Triangle, &Default::default());
+        assert_eq!(levels[0], image);
+        assert_eq!(levels[1].get_pixel(0, 0), &Rgb([188; 3]));
+
+        let options = MipmapOptions {
+            srgb: false,
+            ..Default::default()
+        };
+        let levels = generate_mipmaps(&image, FilterType::Triangle, &options);
+        let value = levels[1].get_pixel(0, 0)[0];
+        assert!((127..=128).contains(&value), "{}", value);
+    }
+
+    #[test]
+    fn renormalizes_normal_maps() {
+        // Normals tilted left and right in alternate columns average to one facing straight out.
+        let image = RgbImage::from_fn(2, 1, |x, _| Rgb([if x == 0 { 37 } else { 219 }, 128, 219]));
+        let options = MipmapOptions {
+            normal_map: true,
+            ..Default::default()
+        };
+        let levels = generate_mipmaps(&image, FilterType::Triangle, &options);
+        assert_eq!(levels[1].get_pixel(0, 0)[2], 255);
+    }
+
+    #[test]
+    fn transparent_pixels_do_not_bleed() {
+        let image = ImageBuffer::from_fn(2, 2, |x, _| {
+            if x == 0 {
+                LumaA([200u8, 255])
+            } else {
+                LumaA([0, 0])
+            }
+        });
+        let levels = generate_mipmaps(&image, FilterType::Triangle, &Default::default());
+        assert_eq!(levels[1].get_pixel(0, 0), &LumaA([200, 128]));
+    }
+
+    #[test]
+    fn scales_alpha_to_coverage() {
+        let mut image =
+            Rgba32FImage::from_fn(4, 1, |x, _| Rgba([0.0, 0.0, 0.0, (x + 1) as f32 / 10.0]));
+        assert_eq!(coverage(&image, 0.5, 1.0), 0.0);
+        scale_alpha(&mut image, 0.5, 0.5);
+        assert_eq!(coverage(&image, 0.5, 1.0), 0.5);
+    }
+
+    #[test]
+    fn alpha_coverage_closer_than_plain() {
+        // A diagonal band, as in the stems of a foliage texture.
+        let image = ImageBuffer::from_fn(32, 32, |x, y| {
+            LumaA([
+                255u8,
+                if (x as i32 - y as i32).abs() <= 3 {
+                    255
+                } else {
+                    0
+                },
+            ])
+        });
+        let error = |level: &ImageBuffer<LumaA<u8>, Vec<u8>>| {
+            let passing = |image: &ImageBuffer<LumaA<u8>, Vec<u8>>| {
+                image.pixels().filter(|p| p[1] > 127).count() as f32 / image.pixels().len() as f32
+            };
+            (passing(level) - passing(&image)).abs()
+        };
+
+        let plain = generate_mipmaps(&image, FilterType::Triangle, &Default::default());
+        let options = MipmapOptions {
+            alpha_coverage: Some(0.5),
+            ..Default::default()
+        };
+        let levels = generate_mipmaps(&image, FilterType::Triangle, &options);
+        assert_eq!(levels[0], image);
+        for (level, plain) in levels.iter().zip(&plain) {
+            assert!(error(level) <= error(plain));
+        }
+    }
+}
@@ -0,0 +1,42 @@
+//! Preparation of textures for 3D rendering.
+//!
+//! [`generate_mipmaps`] computes the chain of successively halved images that GPUs sample from
+//! when a texture is seen from afar.
+//!
+//! Normal maps hold vectors instead of colors and need their own operations. [`height_to_normal`]
+//! derives a normal map from a height map, and [`renormalize`] restores the unit length of normals
+//! after filtering.
+//!
+//! [`pack_channels`] combines channels of several images into one texture, as is usual for the
+//! maps of physically based materials.
+//!
+//! [`generate_sdf`] turns a mask into a signed distance field, which renders smooth outlines of
+//! text and icons at any scale. With the `msdf` feature, [`generate_msdf`] computes a field of
+//! three channels that also keeps their corners sharp.
+//!
+//! [`generate_mipmaps`]: fn.generate_mipmaps.html
+//! [`height_to_normal`]: fn.height_to_normal.html
+//! [`renormalize`]: fn.renormalize.html
+//! [`pack_channels`]: fn.pack_channels.html
+//! [`generate_sdf`]: fn.generate_sdf.html
+//! [`generate_msdf`]: fn.generate_msdf.html
+use crate::traits::Pixel;
+
+mod mipmap;
+#[cfg(feature = "msdf")]
+mod msdf;
+mod normal;
+mod pack;
+mod sdf;
+
+pub use self::mipmap::{generate_mipmaps, MipmapOptions};
+#[cfg(feature = "msdf")]
+pub use self::msdf::{generate_msdf, generate_msdf_f32};
+pub use self::normal::{height_to_normal, renormalize, resize_normal_map};
+pub use self::pack::{pack_channels, ChannelSource};
+pub use self::sdf::{generate_sdf, generate_sdf_f32};
+
+/// Whether the last channel of a pixel type is alpha.
+fn has_alpha<P: Pixel>() -> bool {
+    P::COLOR_MODEL.ends_with('A')
+}
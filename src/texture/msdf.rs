@@ -0,0 +1,423 @@
+use std::collections::HashMap;
+
+use crate::image::GenericImageView;
+use crate::traits::{Pixel, Primitive};
+use crate::{ImageBuffer, Rgb, RgbImage};
+
+use super::sdf::{inside_mask, to_u8};
+
+/// The channels of the edges of an outline without corners.
+const WHITE: u8 = 0b111;
+/// The channels of the edges between corners, cyan, magenta and yellow. Each two share a channel.
+const EDGE_COLORS: [u8; 3] = [0b110, 0b101, 0b011];
+/// The cosine of the smallest turn of the outline that is kept as a corner, 60 degrees.
+const CORNER_COS: f32 = 0.5;
+/// The largest difference of distances that are considered equal.
+const TIE: f32 = 1e-3;
+
+/// Compute an eight bit multi-channel signed distance field of a mask.
+///
+/// Which pixels are inside the shape is decided as in [`generate_sdf`], and each channel maps
+/// distances to eight bits in the same way. The median of the three channels is the signed
+/// distance to the outline near it, so rendering with `median(r, g, b)` in place of the single
+/// channel of [`generate_sdf`] reproduces the outline. Its corners stay sharp when the field is
+/// magnified, as the distances to the edges on either side of a corner are stored in different
+/// channels.
+///
+/// The outline is traced through the boundaries between inside and outside pixels. Where it
+/// turns by more than 60 degrees, or where a diagonal step cuts off the corner between a
+/// horizontal and a vertical edge, it has a corner. Corners of the mask are only known to the
+/// nearest pixel, so this is best suited to masks of shapes with straight edges, such as icons,
+/// rendered at a few times the size of the field.
+///
+/// ```
+/// use image::texture::generate_msdf;
+/// use image::{GrayImage, Luma};
+///
+/// let square = GrayImage::from_fn(16, 16, |x, y| {
+///     Luma([if (4..12).contains(&x) && (4..12).contains(&y) { 255 } else { 0 }])
+/// });
+/// let field = generate_msdf(&square, 2.0);
+/// let median = |x, y| {
+///     let mut channels = field.get_pixel(x, y).0;
+///     channels.sort_unstable();
+///     channels[1]
+/// };
+/// assert_eq!(median(8, 8), 255);
+/// assert!(median(4, 8) > 128 && median(3, 8) < 128);
+/// // Diagonally outside of the corner, as close to the outline as the neighbours of the corner.
+/// assert_eq!(median(12, 12), median(12, 11));
+/// ```
+///
+/// [`generate_sdf`]: fn.generate_sdf.html
+pub fn generate_msdf<I, P, S>(image: &I, spread: f32) -> RgbImage
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let distances = generate_msdf_f32(image);
+    let mut field = RgbImage::new(distances.width(), distances.height());
+    for (value, distance) in field.pixels_mut().zip(distances.pixels()) {
+        let [red, green, blue] = distance.0;
+        *value = Rgb([
+            to_u8(red, spread),
+            to_u8(green, spread),
+            to_u8(blue, spread),
+        ]);
+    }
+    field
+}
+
+/// Compute the multi-channel signed distance field of a mask in pixels.
+///
+/// The channels are positive inside the shape and negative outside of it, as in
+/// [`generate_sdf_f32`]. See [`generate_msdf`] for how they are combined. The border of the
+/// image is not part of the outline. Without any pixels inside, or outside, all distances are
+/// infinite.
+///
+/// [`generate_sdf_f32`]: fn.generate_sdf_f32.html
+/// [`generate_msdf`]: fn.generate_msdf.html
+pub fn generate_msdf_f32<I, P, S>(image: &I) -> ImageBuffer<Rgb<f32>, Vec<f32>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let (width, height) = image.dimensions();
+    let inside = inside_mask(image);
+    let segments: Vec<Segment> = trace_outlines(width, height, &inside)
+        .into_iter()
+        .flat_map(|outline| color_edges(sharpen_corners(&outline)))
+        // The edges along the border of the image only separate the shape from the pixels beyond
+        // it, which count as outside for tracing but not as an outline to measure to.
+        .filter(|segment| !along_border(segment, width, height))
+        .collect();
+
+    let mut field = ImageBuffer::new(width, height);
+    for ((x, y, value), &inside) in field.enumerate_pixels_mut().zip(&inside) {
+        let point = [x as f32 + 0.5, y as f32 + 0.5];
+        let far = if inside {
+            f32::INFINITY
+        } else {
+            f32::NEG_INFINITY
+        };
+        let distance = |channel| channel_distance(&segments, channel, point).unwrap_or(far);
+        *value = Rgb([distance(0b001), distance(0b010), distance(0b100)]);
+    }
+    field
+}
+
+/// A straight piece of an outline, with the inside of the shape on its left in image coordinates.
+struct Segment {
+    start: [f32; 2],
+    end: [f32; 2],
+    /// The channels whose distances this segment contributes to.
+    color: u8,
+    start_is_corner: bool,
+    end_is_corner: bool,
+}
+
+/// Whether both ends of the segment lie on the same side of the border of the image.
+fn along_border(segment: &Segment, width: u32, height: u32) -> bool {
+    let on = |axis: usize, line: f32| segment.start[axis] == line && segment.end[axis] == line;
+    on(0, 0.0) || on(0, width as f32) || on(1, 0.0) || on(1, height as f32)
+}
+
+/// Trace the outlines of the shape as closed polygons.
+///
+/// The vertices are the midpoints of the pixel sides between inside and outside pixels, in
+/// doubled coordinates to keep them integers. Collinear vertices are left out.
+fn trace_outlines(width: u32, height: u32, inside: &[bool]) -> Vec<Vec<[i64; 2]>> {
+    let is_inside = |x: i64, y: i64| {
+        x >= 0
+            && y >= 0
+            && x < i64::from(width)
+            && y < i64::from(height)
+            && inside[(y * i64::from(width) + x) as usize]
+    };
+
+    // The sides of inside pixels that face outside, from corner to corner of the pixel.
+    let mut sides = Vec::new();
+    for y in 0..i64::from(height) {
+        for x in 0..i64::from(width) {
+            if !is_inside(x, y) {
+                continue;
+            }
+            if !is_inside(x, y - 1) {
+                sides.push(([x, y], [x + 1, y]));
+            }
+            if !is_inside(x + 1, y) {
+                sides.push(([x + 1, y], [x + 1, y + 1]));
+            }
+            if !is_inside(x, y + 1) {
+                sides.push(([x + 1, y + 1], [x, y + 1]));
+            }
+            if !is_inside(x - 1, y) {
+                sides.push(([x, y + 1], [x, y]));
+            }
+        }
+    }
+    let mut starting_at: HashMap<[i64; 2], Vec<usize>> = HashMap::new();
+    for (i, side) in sides.iter().enumerate().rev() {
+        starting_at.entry(side.0).or_default().push(i);
+    }
+
+    let mut outlines = Vec::new();
+    let mut used = vec![false; sides.len()];
+    for first in 0..sides.len() {
+        if used[first] {
+            continue;
+        }
+        let mut outline = Vec::new();
+        let mut side = first;
+        loop {
+            used[side] = true;
+            let (start, end) = sides[side];
+            outline.push([start[0] + end[0], start[1] + end[1]]);
+            match starting_at
+                .get_mut(&end)
+                .and_then(|next| next.iter().position(|&i| !used[i]).map(|j| next.remove(j)))
+            {
+                Some(next) => side = next,
+                None => break,
+            }
+        }
+        outlines.push(remove_collinear(&outline));
+    }
+    outlines
+}
+
+fn remove_collinear(points: &[[i64; 2]]) -> Vec<[i64; 2]> {
+    let n = points.len();
+    (0..n)
+        .filter(|&i| {
+            let (before, after) = (
+                sub(points[i], points[(i + n - 1) % n]),
+                sub(points[(i + 1) % n], points[i]),
+            );
+            cross(before, after) != 0 || dot(before, after) <= 0
+        })
+        .map(|i| points[i])
+        .collect()
+}
+
+/// Restore the corners between horizontal and vertical edges.
+///
+/// The outline through the midpoints of the pixel sides cuts a corner of the mask off with a
+/// short diagonal. It is replaced by the intersection of the edges on either side of it.
+fn sharpen_corners(points: &[[i64; 2]]) -> Vec<[f32; 2]> {
+    let n = points.len();
+    let points: Vec<[f32; 2]> = points
+        .iter()
+        .map(|p| [p[0] as f32 / 2.0, p[1] as f32 / 2.0])
+        .collect();
+    if n < 4 {
+        return points;
+    }
+    let mut sharpened = points.clone();
+    let mut keep = vec![true; n];
+    for i in 0..n {
+        let (before, from) = (points[(i + n - 1) % n], points[i]);
+        let (to, after) = (points[(i + 1) % n], points[(i + 2) % n]);
+        let (incoming, cut, outgoing) = (subf(from, before), subf(to, from), subf(after, to));
+        if length(cut) >= 1.0 || length(incoming) < 1.0 || length(outgoing) < 1.0 {
+            continue;
+        }
+        let turns_once = crossf(incoming, cut) * crossf(cut, outgoing) > 0.0;
+        if turns_once && dotf(incoming, outgoing) <= 0.0 {
+            // The lines through the edges meet at `from + incoming * t`.
+            let t = crossf(subf(to, from), outgoing) / crossf(incoming, outgoing);
+            sharpened[i] = [from[0] + incoming[0] * t, from[1] + incoming[1] * t];
+            keep[(i + 1) % n] = false;
+        }
+    }
+    // The vertices before the cuts were moved to the corners, those after them are dropped.
+    (0..n).filter(|&i| keep[i]).map(|i| sharpened[i]).collect()
+}
+
+/// Split an outline into edges at its corners and assign the edges their channels.
+///
+/// Neighbouring edges share exactly one channel, so that the median of the channels follows
+/// both edges up to the corner. Outlines with fewer than two corners have a single edge in all
+/// channels, like a signed distance field of one channel.
+fn color_edges(points: Vec<[f32; 2]>) -> Vec<Segment> {
+    let n = points.len();
+    let is_corner: Vec<bool> = (0..n)
+        .map(|i| {
+            let incoming = subf(points[i], points[(i + n - 1) % n]);
+            let outgoing = subf(points[(i + 1) % n], points[i]);
+            dotf(incoming, outgoing) <= CORNER_COS * length(incoming) * length(outgoing)
+        })
+        .collect();
+    let corners = is_corner.iter().filter(|&&corner| corner).count();
+    let first = is_corner.iter().position(|&corner| corner).unwrap_or(0);
+
+    let mut segments = Vec::with_capacity(n);
+    let mut edge = 0;
+    for j in 0..n {
+        let (i, next) = ((first + j) % n, (first + j + 1) % n);
+        if j > 0 && is_corner[i] {
+            edge += 1;
+        }
+        let color = if corners < 2 {
+            WHITE
+        } else if corners % 3 == 1 && edge == corners - 1 {
+            // The last edge also meets the first one.
+            EDGE_COLORS[1]
+        } else {
+            EDGE_COLORS[edge % 3]
+        };
+        segments.push(Segment {
+            start: points[i],
+            end: points[next],
+            color,
+            start_is_corner: corners >= 2 && is_corner[i],
+            end_is_corner: corners >= 2 && is_corner[next],
+        });
+    }
+    segments
+}
+
+/// The signed distance of `point` to the nearest segment in `channel`.
+///
+/// Beyond a corner, the distance is measured to the line through the segment instead, which is
+/// what keeps the corner sharp in the median of the channels.
+fn channel_distance(segments: &[Segment], channel: u8, point: [f32; 2]) -> Option<f32> {
+    // The nearest segment, and how orthogonal the direction to it is, which decides between
+    // segments that meet at the nearest point.
+    let mut nearest: Option<(f32, f32, &Segment, f32)> = None;
+    for segment in segments.iter().filter(|s| s.color & channel != 0) {
+        let direction = subf(segment.end, segment.start);
+        let length_squared = dotf(direction, direction);
+        if length_squared == 0.0 {
+            continue;
+        }
+        let t = dotf(subf(point, segment.start), direction) / length_squared;
+        let closest = t.clamp(0.0, 1.0);
+        let offset = subf(
+            point,
+            [
+                segment.start[0] + direction[0] * closest,
+                segment.start[1] + direction[1] * closest,
+            ],
+        );
+        let distance = length(offset);
+        let orthogonality = if distance > 0.0 {
+            crossf(direction, offset).abs() / (length_squared.sqrt() * distance)
+        } else {
+            1.0
+        };
+        let is_nearer = match nearest {
+            None => true,
+            Some((best, best_orthogonality, _, _)) => {
+                distance < best - TIE
+                    || (distance <= best + TIE && orthogonality > best_orthogonality)
+            }
+        };
+        if is_nearer {
+            nearest = Some((distance, orthogonality, segment, t));
+        }
+    }
+
+    let (distance, _, segment, t) = nearest?;
+    let direction = subf(segment.end, segment.start);
+    let to_line = crossf(direction, subf(point, segment.start)) / length(direction);
+    if (t < 0.0 && segment.start_is_corner) || (t > 1.0 && segment.end_is_corner) {
+        Some(to_line)
+    } else if to_line < 0.0 {
+        Some(-distance)
+    } else {
+        Some(distance)
+    }
+}
+
+fn sub(a: [i64; 2], b: [i64; 2]) -> [i64; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn dot(a: [i64; 2], b: [i64; 2]) -> i64 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+fn cross(a: [i64; 2], b: [i64; 2]) -> i64 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+fn subf(a: [f32; 2], b: [f32; 2]) -> [f32; 2] {
+    [a[0] - b[0], a[1] - b[1]]
+}
+
+fn dotf(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[0] + a[1] * b[1]
+}
+
+fn crossf(a: [f32; 2], b: [f32; 2]) -> f32 {
+    a[0] * b[1] - a[1] * b[0]
+}
+
+fn length(a: [f32; 2]) -> f32 {
+    dotf(a, a).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::generate_sdf_f32;
+    use crate::{GrayImage, Luma};
+
+    fn median(pixel: &Rgb<f32>) -> f32 {
+        let [r, g, b] = pixel.0;
+        r.max(g).min(r.min(g).max(b))
+    }
+
+    #[test]
+    fn square_with_hole() {
+        let frame = GrayImage::from_fn(20, 20, |x, y| {
+            let outer = (4..16).contains(&x) && (4..16).contains(&y);
+            let hole = (8..12).contains(&x) && (8..12).contains(&y);
+            Luma([if outer && !hole { 255 } else { 0 }])
+        });
+        let field = generate_msdf_f32(&frame);
+        let sdf = generate_sdf_f32(&frame);
+        // Along the middle, away from the corners, the median is the distance to the outline.
+        for x in 0..20 {
+            let (median, distance) = (median(field.get_pixel(x, 10)), sdf.get_pixel(x, 10)[0]);
+            assert!(
+                (median - distance).abs() < 1e-4,
+                "{}: {} {}",
+                x,
+                median,
+                distance
+            );
+        }
+        // Diagonally off the corners, the median measures to the lines through the edges, so
+        // the corners stay sharp, while a single channel field is rounded.
+        assert_eq!(median(field.get_pixel(16, 16)), -0.5);
+        assert_eq!(median(field.get_pixel(3, 3)), -0.5);
+        assert!(sdf.get_pixel(16, 16)[0] < -0.9);
+        assert_eq!(median(field.get_pixel(8, 8)), -0.5);
+        assert_eq!(median(field.get_pixel(9, 9)), -1.5);
+        assert_eq!(median(field.get_pixel(7, 7)), 0.5);
+    }
+
+    #[test]
+    fn smooth_outlines_use_all_channels() {
+        let disc = GrayImage::from_fn(21, 21, |x, y| {
+            let (dx, dy) = (x as f32 - 10.0, y as f32 - 10.0);
+            Luma([if dx * dx + dy * dy <= 40.0 { 255 } else { 0 }])
+        });
+        let field = generate_msdf(&disc, 4.0);
+        assert!(field.pixels().all(|p| p[0] == p[1] && p[1] == p[2]));
+        assert_eq!(field.get_pixel(10, 10), &Rgb([255; 3]));
+        assert_eq!(field.get_pixel(0, 0), &Rgb([0; 3]));
+    }
+
+    #[test]
+    fn without_outline() {
+        let field = generate_msdf_f32(&GrayImage::new(3, 3));
+        assert!(field.pixels().all(|p| p.0 == [f32::NEG_INFINITY; 3]));
+        let field = generate_msdf_f32(&GrayImage::from_pixel(3, 2, Luma([255])));
+        assert!(field.pixels().all(|p| p.0 == [f32::INFINITY; 3]));
+    }
+}
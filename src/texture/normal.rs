@@ -0,0 +1,190 @@
+use num_traits::NumCast;
+
+use crate::image::{GenericImage, GenericImageView};
+use crate::imageops::sample::FloatNearest;
+use crate::imageops::{resize, FilterType};
+use crate::traits::{Enlargeable, Pixel, Primitive};
+use crate::{ImageBuffer, Rgb};
+
+/// Compute a tangent space normal map from a height map.
+///
+/// The height is the luminance of each pixel, with the maximum value of the subpixel type being
+/// one pixel high for a `strength` of one. Larger strengths make the bumps steeper. The slopes are
+/// estimated with a Sobel filter, repeating the edge pixels beyond the border.
+///
+/// The normals follow the OpenGL convention, red points to the right and green points up. They
+/// are encoded with `-1` mapping to zero and `1` mapping to the maximum value, so a flat surface is
+/// a light blue.
+pub fn height_to_normal<I, P, S>(heightmap: &I, strength: f32) -> ImageBuffer<Rgb<S>, Vec<S>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + Enlargeable + 'static,
+{
+    let max: f32 = NumCast::from(S::DEFAULT_MAX_VALUE).unwrap();
+    let (width, height) = heightmap.dimensions();
+    let sample = |x: i64, y: i64| {
+        let x = x.max(0).min(width as i64 - 1) as u32;
+        let y = y.max(0).min(height as i64 - 1) as u32;
+        heightmap.get_pixel(x, y).to_luma()[0].to_f32().unwrap() / max
+    };
+
+    ImageBuffer::from_fn(width, height, |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let dx = (sample(x + 1, y - 1) + 2.0 * sample(x + 1, y) + sample(x + 1, y + 1)
+            - sample(x - 1, y - 1)
+            - 2.0 * sample(x - 1, y)
+            - sample(x - 1, y + 1))
+            / 8.0;
+        // Rows go down while the normal map points up.
+        let dy = (sample(x - 1, y + 1) + 2.0 * sample(x, y + 1) + sample(x + 1, y + 1)
+            - sample(x - 1, y - 1)
+            - 2.0 * sample(x, y - 1)
+            - sample(x + 1, y - 1))
+            / 8.0;
+        let normal = normalize([-dx * strength, dy * strength, 1.0]);
+
+        let mut pixel = Rgb([S::DEFAULT_MIN_VALUE; 3]);
+        for (channel, value) in pixel.0.iter_mut().zip(&normal) {
+            *channel = NumCast::from(FloatNearest((value * 0.5 + 0.5) * max)).unwrap();
+        }
+        pixel
+    })
+}
+
+/// Rescale the normals of a normal map to unit length.
+///
+/// The first three channels of each pixel hold a vector, encoded as in [`height_to_normal`].
+/// Filtering a normal map averages its vectors, which shortens them and darkens the lighting of
+/// detailed surfaces, so this should follow every resize of a normal map. Further channels, such
+/// as alpha, are not changed. Images with fewer than three channels are left as they are.
+///
+/// [`height_to_normal`]: fn.height_to_normal.html
+pub fn renormalize<I, P, S>(image: &mut I)
+where
+    I: GenericImage<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    if P::CHANNEL_COUNT < 3 {
+        return;
+    }
+    let max: f32 = NumCast::from(S::DEFAULT_MAX_VALUE).unwrap();
+    let (width, height) = image.dimensions();
+    for y in 0..height {
+        for x in 0..width {
+            let mut pixel = image.get_pixel(x, y);
+            let channels = pixel.channels_mut();
+            let mut encoded = [0.0; 3];
+            for (value, channel) in encoded.iter_mut().zip(channels.iter()) {
+                *value = channel.to_f32().unwrap() / max;
+            }
+            for (channel, value) in channels.iter_mut().zip(&renormalize_encoded(encoded)) {
+                *channel = NumCast::from(FloatNearest(value * max)).unwrap();
+            }
+            image.put_pixel(x, y, pixel);
+        }
+    }
+}
+
+/// Resize a normal map, keeping its normals at unit length.
+///
+/// This is [`resize`] followed by [`renormalize`].
+///
+/// [`resize`]: ../imageops/fn.resize.html
+/// [`renormalize`]: fn.renormalize.html
+pub fn resize_normal_map<I, P, S>(
+    image: &I,
+    width: u32,
+    height: u32,
+    filter: FilterType,
+) -> ImageBuffer<P, Vec<S>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let mut resized = resize(image, width, height, filter);
+    renormalize(&mut resized);
+    resized
+}
+
+/// Rescales a normal encoded in the range from zero to one to unit length.
+pub(super) fn renormalize_encoded(encoded: [f32; 3]) -> [f32; 3] {
+    let normal = normalize([
+        encoded[0] * 2.0 - 1.0,
+        encoded[1] * 2.0 - 1.0,
+        encoded[2] * 2.0 - 1.0,
+    ]);
+    [
+        normal[0] * 0.5 + 0.5,
+        normal[1] * 0.5 + 0.5,
+        normal[2] * 0.5 + 0.5,
+    ]
+}
+
+/// Scales a vector to unit length, or returns a normal facing straight out for a zero vector.
+fn normalize([x, y, z]: [f32; 3]) -> [f32; 3] {
+    let length = (x * x + y * y + z * z).sqrt();
+    if length > 0.0 {
+        [x / length, y / length, z / length]
+    } else {
+        [0.0, 0.0, 1.0]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayImage, Luma, RgbImage, Rgba, RgbaImage};
+
+    #[test]
+    fn flat_height_map() {
+        let normals = height_to_normal(&GrayImage::from_pixel(3, 3, Luma([90])), 4.0);
+        assert!(normals.pixels().all(|p| *p == Rgb([128, 128, 255])));
+    }
+
+    #[test]
+    fn slope_directions() {
+        // A ramp rising to the right and to the top.
+        let heightmap = GrayImage::from_fn(5, 5, |x, y| Luma([(x * 20 + (4 - y) * 10) as u8]));
+        let normals = height_to_normal(&heightmap, 1.0);
+        let center = normals.get_pixel(2, 2);
+        assert!(center[0] < 128, "{:?}", center);
+        assert!(center[1] < 128, "{:?}", center);
+        assert!(center[0] < center[1], "{:?}", center);
+
+        let steeper = height_to_normal(&heightmap, 4.0);
+        assert!(steeper.get_pixel(2, 2)[2] < center[2]);
+    }
+
+    #[test]
+    fn renormalize_keeps_alpha() {
+        let mut image = RgbaImage::from_pixel(2, 1, Rgba([128, 128, 191, 7]));
+        renormalize(&mut image);
+        let pixel = image.get_pixel(0, 0);
+        assert!(pixel[0] >= 127 && pixel[0] <= 129, "{:?}", pixel);
+        assert_eq!((pixel[2], pixel[3]), (255, 7));
+    }
+
+    #[test]
+    fn resize_keeps_unit_length() {
+        // Normals tilted left and right alternately average to a short vector.
+        let image = RgbImage::from_fn(4, 4, |x, _| {
+            if x % 2 == 0 {
+                Rgb([37, 128, 219])
+            } else {
+                Rgb([219, 128, 219])
+            }
+        });
+        let resized = resize_normal_map(&image, 2, 2, FilterType::Triangle);
+        for pixel in resized.pixels() {
+            let length: f32 = pixel
+                .0
+                .iter()
+                .map(|&c| (c as f32 / 255.0 * 2.0 - 1.0).powi(2))
+                .sum();
+            assert!((length.sqrt() - 1.0).abs() < 0.02, "{:?}", pixel);
+        }
+    }
+}
@@ -0,0 +1,197 @@
+use crate::dynimage::DynamicImage;
+use crate::error::{ImageError, ImageResult, ParameterError, ParameterErrorKind};
+use crate::image::GenericImageView;
+use crate::{ImageBuffer, Rgba, RgbaImage};
+
+/// The source of one channel of a packed texture.
+#[derive(Clone, Copy, Debug)]
+pub enum ChannelSource<'a> {
+    /// A channel of an image, in the order of the channels of its color type. Sixteen bit and
+    /// floating point channels are converted to eight bits.
+    Image {
+        /// The image to take the channel from.
+        image: &'a DynamicImage,
+        /// The index of the channel, such as 0 for the luminance of a gray image or 3 for the
+        /// alpha of an RGBA image.
+        channel: usize,
+        /// Whether the values are inverted, such as to turn a smoothness map into roughness.
+        invert: bool,
+    },
+    /// The same value for every pixel.
+    Constant(u8),
+}
+
+impl<'a> ChannelSource<'a> {
+    /// A channel of an image, taken as it is.
+    pub fn channel(image: &'a DynamicImage, channel: usize) -> Self {
+        ChannelSource::Image {
+            image,
+            channel,
+            invert: false,
+        }
+    }
+
+    /// The same source with inverted values.
+    pub fn inverted(self) -> Self {
+        match self {
+            ChannelSource::Image {
+                image,
+                channel,
+                invert,
+            } => ChannelSource::Image {
+                image,
+                channel,
+                invert: !invert,
+            },
+            ChannelSource::Constant(value) => ChannelSource::Constant(255 - value),
+        }
+    }
+}
+
+/// Assemble an RGBA texture whose channels come from different images.
+///
+/// This is commonly used to pack the ambient occlusion, roughness and metalness maps of a
+/// physically based material into the red, green and blue channels of a single texture.
+///
+/// ```
+/// use image::texture::{pack_channels, ChannelSource};
+/// use image::{DynamicImage, GrayImage, Luma, Rgba};
+///
+/// let occlusion = DynamicImage::ImageLuma8(GrayImage::from_pixel(4, 4, Luma([200])));
+/// let smoothness = DynamicImage::ImageLuma8(GrayImage::from_pixel(4, 4, Luma([50])));
+/// let metalness = DynamicImage::ImageLuma8(GrayImage::from_pixel(4, 4, Luma([255])));
+/// let packed = pack_channels([
+///     ChannelSource::channel(&occlusion, 0),
+///     ChannelSource::channel(&smoothness, 0).inverted(),
+///     ChannelSource::channel(&metalness, 0),
+///     ChannelSource::Constant(255),
+/// ])
+/// .unwrap();
+/// assert_eq!(packed.get_pixel(0, 0), &Rgba([200, 205, 255, 255]));
+/// ```
+///
+/// Returns an `ImageError::Parameter` if no channel comes from an image, if the images differ in
+/// size, or if a channel index is beyond the channels of its image.
+pub fn pack_channels(sources: [ChannelSource<'_>; 4]) -> ImageResult<RgbaImage> {
+    let mut dimensions = None;
+    for source in &sources {
+        if let ChannelSource::Image { image, channel, .. } = *source {
+            let channel_count = usize::from(image.color().channel_count());
+            if channel >= channel_count {
+                return Err(ImageError::Parameter(ParameterError::from_kind(
+                    ParameterErrorKind::Generic(format!(
+                        "channel {} of an image with {} channels",
+                        channel, channel_count
+                    )),
+                )));
+            }
+            match dimensions {
+                None => dimensions = Some(image.dimensions()),
+                Some(dimensions) if dimensions != image.dimensions() => {
+                    return Err(ImageError::Parameter(ParameterError::from_kind(
+                        ParameterErrorKind::DimensionMismatch,
+                    )))
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    let (width, height) = match dimensions {
+        Some(dimensions) => dimensions,
+        None => {
+            return Err(ImageError::Parameter(ParameterError::from_kind(
+                ParameterErrorKind::Generic(
+                    "at least one channel has to come from an image".to_string(),
+                ),
+            )))
+        }
+    };
+
+    Ok(ImageBuffer::from_fn(width, height, |x, y| {
+        let mut pixel = Rgba([0; 4]);
+        for (value, source) in pixel.0.iter_mut().zip(&sources) {
+            *value = match *source {
+                ChannelSource::Image {
+                    image,
+                    channel,
+                    invert,
+                } => {
+                    // Pixels of a dynamic image are RGBA, with gray repeated in all color channels.
+                    let color = image.color();
+                    let index =
+                        if color.has_alpha() && channel + 1 == usize::from(color.channel_count()) {
+                            3
+                        } else {
+                            channel
+                        };
+                    let value = image.get_pixel(x, y)[index];
+                    if invert {
+                        255 - value
+                    } else {
+                        value
+                    }
+                }
+                ChannelSource::Constant(value) => value,
+            };
+        }
+        pixel
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayAlphaImage, GrayImage, Luma, LumaA, Rgb, RgbImage};
+
+    #[test]
+    fn channels_of_different_images() {
+        let rgb =
+            DynamicImage::ImageRgb8(RgbImage::from_fn(3, 2, |x, y| Rgb([x as u8, y as u8, 100])));
+        let gray_alpha =
+            DynamicImage::ImageLumaA8(GrayAlphaImage::from_pixel(3, 2, LumaA([10, 20])));
+        let packed = pack_channels([
+            ChannelSource::channel(&rgb, 2),
+            ChannelSource::channel(&gray_alpha, 1),
+            ChannelSource::channel(&rgb, 0).inverted(),
+            ChannelSource::channel(&gray_alpha, 0),
+        ])
+        .unwrap();
+        assert_eq!(packed.dimensions(), (3, 2));
+        assert_eq!(packed.get_pixel(2, 1), &Rgba([100, 20, 253, 10]));
+    }
+
+    #[test]
+    fn constants() {
+        let gray = DynamicImage::ImageLuma8(GrayImage::from_pixel(1, 1, Luma([7])));
+        let packed = pack_channels([
+            ChannelSource::Constant(1),
+            ChannelSource::Constant(2).inverted(),
+            ChannelSource::channel(&gray, 0).inverted().inverted(),
+            ChannelSource::Constant(255),
+        ])
+        .unwrap();
+        assert_eq!(packed.get_pixel(0, 0), &Rgba([1, 253, 7, 255]));
+    }
+
+    #[test]
+    fn invalid_sources() {
+        let small = DynamicImage::ImageLuma8(GrayImage::new(2, 2));
+        let large = DynamicImage::ImageLuma8(GrayImage::new(3, 2));
+        let constant = ChannelSource::Constant(0);
+        assert!(pack_channels([constant; 4]).is_err());
+        assert!(pack_channels([
+            ChannelSource::channel(&small, 0),
+            ChannelSource::channel(&large, 0),
+            constant,
+            constant
+        ])
+        .is_err());
+        assert!(pack_channels([
+            ChannelSource::channel(&small, 1),
+            constant,
+            constant,
+            constant
+        ])
+        .is_err());
+    }
+}
@@ -0,0 +1,235 @@
+use num_traits::NumCast;
+
+use crate::image::GenericImageView;
+use crate::traits::{Pixel, Primitive};
+use crate::{GrayImage, ImageBuffer, Luma};
+
+use super::has_alpha;
+
+/// Compute an eight bit signed distance field of a mask.
+///
+/// A pixel is inside the shape if its alpha, or its luminance for images without alpha, is at
+/// least half of the maximum. The result is 128 on the outline of the shape and grows to 255
+/// `spread` pixels inside it, while it falls to 0 `spread` pixels outside of it. Rendering the
+/// field with a threshold of one half and linear filtering reproduces the outline with smooth
+/// edges at any scale, as is done for text and icons.
+///
+/// Corners of the outline are rounded when the field is magnified. The multi-channel fields of
+/// [`generate_msdf`], with the `msdf` feature, keep them sharp.
+///
+/// ```
+/// use image::texture::generate_sdf;
+/// use image::{GrayImage, Luma};
+///
+/// let square = GrayImage::from_fn(16, 16, |x, y| {
+///     Luma([if (4..12).contains(&x) && (4..12).contains(&y) { 255 } else { 0 }])
+/// });
+/// let field = generate_sdf(&square, 2.0);
+/// assert_eq!(field.get_pixel(8, 8), &Luma([255]));
+/// assert_eq!(field.get_pixel(0, 8), &Luma([0]));
+/// assert!(field.get_pixel(4, 8)[0] > 128 && field.get_pixel(3, 8)[0] < 128);
+/// ```
+///
+/// [`generate_msdf`]: fn.generate_msdf.html
+pub fn generate_sdf<I, P, S>(image: &I, spread: f32) -> GrayImage
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let distances = generate_sdf_f32(image);
+    let mut field = GrayImage::new(distances.width(), distances.height());
+    for (value, distance) in field.pixels_mut().zip(distances.pixels()) {
+        *value = Luma([to_u8(distance[0], spread)]);
+    }
+    field
+}
+
+/// Compute the signed distance field of a mask in pixels.
+///
+/// The distance is positive inside the shape and negative outside of it, with the outline
+/// between the centers of neighbouring inside and outside pixels. Which pixels are inside is
+/// decided as in [`generate_sdf`]. Without any pixels inside, or outside, all distances are
+/// infinite.
+///
+/// [`generate_sdf`]: fn.generate_sdf.html
+pub fn generate_sdf_f32<I, P, S>(image: &I) -> ImageBuffer<Luma<f32>, Vec<f32>>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let (width, height) = image.dimensions();
+    let inside = inside_mask(image);
+
+    let to_inside = squared_distances(width, height, |i| inside[i]);
+    let to_outside = squared_distances(width, height, |i| !inside[i]);
+    let mut field = ImageBuffer::new(width, height);
+    for (i, value) in field.pixels_mut().enumerate() {
+        let distance = if inside[i] {
+            to_outside[i].sqrt() - 0.5
+        } else {
+            0.5 - to_inside[i].sqrt()
+        };
+        *value = Luma([distance]);
+    }
+    field
+}
+
+/// Map a distance to eight bits, with the outline at 128 and `spread` pixels at either end.
+pub(super) fn to_u8(distance: f32, spread: f32) -> u8 {
+    let normalized = (distance / spread * 0.5 + 0.5).clamp(0.0, 1.0);
+    (normalized * 255.0).round() as u8
+}
+
+/// Decide which pixels of a mask are inside the shape, row by row.
+pub(super) fn inside_mask<I, P, S>(image: &I) -> Vec<bool>
+where
+    I: GenericImageView<Pixel = P>,
+    P: Pixel<Subpixel = S> + 'static,
+    S: Primitive + 'static,
+{
+    let (width, height) = image.dimensions();
+    let half: f32 = NumCast::from(S::DEFAULT_MAX_VALUE).unwrap();
+    let half = half / 2.0;
+    let mut inside = Vec::with_capacity(width as usize * height as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let pixel = image.get_pixel(x, y);
+            let value = if has_alpha::<P>() {
+                *pixel.channels().last().unwrap()
+            } else {
+                pixel.to_luma()[0]
+            };
+            inside.push(value.to_f32().unwrap() >= half);
+        }
+    }
+    inside
+}
+
+/// The squared euclidean distance of every pixel to the nearest seed pixel.
+///
+/// This is the separable algorithm of Felzenszwalb and Huttenlocher, which transforms columns and
+/// then rows with lower envelopes of parabolas.
+fn squared_distances<F: Fn(usize) -> bool>(width: u32, height: u32, seed: F) -> Vec<f32> {
+    let (width, height) = (width as usize, height as usize);
+    let mut distances: Vec<f32> = (0..width * height)
+        .map(|i| if seed(i) { 0.0 } else { f32::INFINITY })
+        .collect();
+
+    let mut line = Vec::new();
+    for x in 0..width {
+        line.clear();
+        line.extend((0..height).map(|y| distances[y * width + x]));
+        for (y, distance) in transform_line(&line).into_iter().enumerate() {
+            distances[y * width + x] = distance;
+        }
+    }
+    for row in distances.chunks_mut(width.max(1)) {
+        let transformed = transform_line(row);
+        row.copy_from_slice(&transformed);
+    }
+    distances
+}
+
+/// The one dimensional squared distance transform of sampled values.
+fn transform_line(values: &[f32]) -> Vec<f32> {
+    let n = values.len();
+    let mut result = vec![f32::INFINITY; n];
+    // The positions of the parabolas in the lower envelope, and where each starts to be lowest.
+    let mut parabolas = Vec::with_capacity(n);
+    let mut starts: Vec<f32> = Vec::with_capacity(n + 1);
+
+    for (q, &value) in values.iter().enumerate() {
+        if value.is_infinite() {
+            continue;
+        }
+        let q_f = q as f32;
+        loop {
+            let (p, start) = match (parabolas.last(), starts.last()) {
+                (Some(&p), Some(&start)) => (p, start),
+                _ => break,
+            };
+            let p_f = p as f32;
+            let intersection =
+                ((value + q_f * q_f) - (values[p] + p_f * p_f)) / (2.0 * (q_f - p_f));
+            if intersection <= start {
+                parabolas.pop();
+                starts.pop();
+            } else {
+                break;
+            }
+        }
+        let start = match parabolas.last() {
+            Some(&p) => {
+                let p_f = p as f32;
+                ((value + q_f * q_f) - (values[p] + p_f * p_f)) / (2.0 * (q_f - p_f))
+            }
+            None => f32::NEG_INFINITY,
+        };
+        parabolas.push(q);
+        starts.push(start);
+    }
+
+    let mut k = 0;
+    for (q, distance) in result.iter_mut().enumerate() {
+        if parabolas.is_empty() {
+            break;
+        }
+        let q_f = q as f32;
+        while k + 1 < parabolas.len() && starts[k + 1] < q_f {
+            k += 1;
+        }
+        let p = parabolas[k];
+        let offset = q_f - p as f32;
+        *distance = offset * offset + values[p];
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{GrayAlphaImage, LumaA};
+
+    #[test]
+    fn distances_of_a_disc() {
+        let disc = GrayImage::from_fn(41, 41, |x, y| {
+            let (dx, dy) = (x as f32 - 20.0, y as f32 - 20.0);
+            Luma([if dx * dx + dy * dy <= 100.0 { 255 } else { 0 }])
+        });
+        let field = generate_sdf_f32(&disc);
+        // The nearest pixels outside of the disc are at offsets like (10, 1).
+        assert!((field.get_pixel(20, 20)[0] - (101f32.sqrt() - 0.5)).abs() < 1e-4);
+        assert!((field.get_pixel(0, 20)[0] + 9.5).abs() < 0.6);
+        assert!((field.get_pixel(0, 0)[0] + (20.0 * 2f32.sqrt() - 10.5)).abs() < 0.6);
+    }
+
+    #[test]
+    fn edges_between_pixels() {
+        let half = GrayImage::from_fn(6, 1, |x, _| Luma([if x < 3 { 255 } else { 0 }]));
+        let field = generate_sdf_f32(&half);
+        let values: Vec<f32> = field.pixels().map(|p| p[0]).collect();
+        assert_eq!(values, [2.5, 1.5, 0.5, -0.5, -1.5, -2.5]);
+
+        let field = generate_sdf(&half, 2.0);
+        let values: Vec<u8> = field.pixels().map(|p| p[0]).collect();
+        assert_eq!(values, [255, 223, 159, 96, 32, 0]);
+    }
+
+    #[test]
+    fn uses_alpha() {
+        let image = GrayAlphaImage::from_fn(2, 1, |x, _| LumaA([0, if x == 0 { 255 } else { 0 }]));
+        let field = generate_sdf_f32(&image);
+        assert_eq!(field.get_pixel(0, 0)[0], 0.5);
+        assert_eq!(field.get_pixel(1, 0)[0], -0.5);
+    }
+
+    #[test]
+    fn without_outline() {
+        let field = generate_sdf_f32(&GrayImage::new(3, 3));
+        assert!(field.pixels().all(|p| p[0] == f32::NEG_INFINITY));
+        let field = generate_sdf(&GrayImage::new(3, 3), 4.0);
+        assert!(field.pixels().all(|p| p[0] == 0));
+    }
+}
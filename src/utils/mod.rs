@@ -3,16 +3,7 @@
 use num_iter::range_step;
 use std::iter::repeat;
 
-#[cfg(feature = "async")]
-pub enum ReaderUnion{
-    Reader(std::io::Read),
-    AsyncRead(tokio::io::AsyncRead),
-}
-
-#[cfg(not(feature = "async"))]
-pub enum ReaderUnion<R: std::io::Read> {
-    Reader(std::io::Read),
-}
+pub(crate) mod simd;
 
 #[inline(always)]
 pub(crate) fn expand_packed<F>(buf: &mut [u8], channels: usize, bit_depth: u8, mut func: F)
@@ -0,0 +1,268 @@
+//! Vectorized fast paths of hot loops, selected at runtime by the features of the CPU.
+//!
+//! Every function has a portable implementation that handles other CPUs and the pixels left over
+//! by the vectorized loops. The fast paths produce exactly the same results.
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+use crate::color_::{SRGB_LUMA, SRGB_LUMA_DIV};
+
+/// Expand RGB pixels to RGBA pixels with an opaque alpha channel.
+pub(crate) fn rgb_to_rgba(rgb: &[u8], rgba: &mut [u8]) {
+    let mut done = 0;
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("ssse3") {
+            done = unsafe { rgb_to_rgba_ssse3(rgb, rgba) };
+        }
+    }
+
+    let pixels = rgb[done * 3..].chunks_exact(3);
+    for (rgb, rgba) in pixels.zip(rgba[done * 4..].chunks_exact_mut(4)) {
+        rgba[..3].copy_from_slice(rgb);
+        rgba[3] = u8::MAX;
+    }
+}
+
+/// Drop the alpha channel of RGBA pixels.
+pub(crate) fn rgba_to_rgb(rgba: &[u8], rgb: &mut [u8]) {
+    let mut done = 0;
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("ssse3") {
+            done = unsafe { rgba_to_rgb_ssse3(rgba, rgb) };
+        }
+    }
+
+    let pixels = rgba[done * 4..].chunks_exact(4);
+    for (rgba, rgb) in pixels.zip(rgb[done * 3..].chunks_exact_mut(3)) {
+        rgb.copy_from_slice(&rgba[..3]);
+    }
+}
+
+/// Convert pixels of `channels` samples, which start with red, green and blue, to their luma
+/// with the coefficients of `rgb_to_luma` in the color module.
+pub(crate) fn rgb_to_luma(pixels: &[u8], channels: usize, luma: &mut [u8]) {
+    debug_assert!(channels == 3 || channels == 4);
+    let mut done = 0;
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("ssse3") {
+            done = unsafe { rgb_to_luma_ssse3(pixels, channels, luma) };
+        }
+    }
+
+    let pixels = pixels[done * channels..].chunks_exact(channels);
+    for (pixel, luma) in pixels.zip(&mut luma[done..]) {
+        let l = SRGB_LUMA[0] * u32::from(pixel[0])
+            + SRGB_LUMA[1] * u32::from(pixel[1])
+            + SRGB_LUMA[2] * u32::from(pixel[2]);
+        *luma = (l / SRGB_LUMA_DIV) as u8;
+    }
+}
+
+/// Add `weight` times `samples` to `sums`, like a tap of a separable filter applied to a row.
+pub(crate) fn multiply_add(sums: &mut [f32], samples: &[f32], weight: f32) {
+    let mut done = 0;
+    #[cfg(target_arch = "x86_64")]
+    {
+        if is_x86_feature_detected!("avx") {
+            done = unsafe { multiply_add_avx(sums, samples, weight) };
+        }
+    }
+
+    for (sum, &sample) in sums[done..].iter_mut().zip(&samples[done..]) {
+        *sum += sample * weight;
+    }
+}
+
+/// View pixels of `C` samples as their samples.
+pub(crate) fn flatten<const C: usize>(pixels: &[[f32; C]]) -> &[f32] {
+    // Arrays have no padding, so the pixels are contiguous samples.
+    unsafe { std::slice::from_raw_parts(pixels.as_ptr() as *const f32, pixels.len() * C) }
+}
+
+/// View pixels of `C` samples as their samples, mutably.
+pub(crate) fn flatten_mut<const C: usize>(pixels: &mut [[f32; C]]) -> &mut [f32] {
+    unsafe { std::slice::from_raw_parts_mut(pixels.as_mut_ptr() as *mut f32, pixels.len() * C) }
+}
+
+// The vectorized loops return the number of pixels or samples that they handled.
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn rgb_to_rgba_ssse3(rgb: &[u8], rgba: &mut [u8]) -> usize {
+    let pixels = (rgb.len() / 3).min(rgba.len() / 4);
+    let shuffle = _mm_setr_epi8(0, 1, 2, -1, 3, 4, 5, -1, 6, 7, 8, -1, 9, 10, 11, -1);
+    let alpha = _mm_set1_epi32(0xff00_0000_u32 as i32);
+
+    // Each step reads 16 bytes for the 12 bytes of 4 pixels.
+    let mut i = 0;
+    while i + 6 <= pixels {
+        let source = _mm_loadu_si128(rgb.as_ptr().add(i * 3) as *const __m128i);
+        let expanded = _mm_or_si128(_mm_shuffle_epi8(source, shuffle), alpha);
+        _mm_storeu_si128(rgba.as_mut_ptr().add(i * 4) as *mut __m128i, expanded);
+        i += 4;
+    }
+    i
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn rgba_to_rgb_ssse3(rgba: &[u8], rgb: &mut [u8]) -> usize {
+    let pixels = (rgba.len() / 4).min(rgb.len() / 3);
+    let shuffle = _mm_setr_epi8(0, 1, 2, 4, 5, 6, 8, 9, 10, 12, 13, 14, -1, -1, -1, -1);
+
+    // Each step writes 16 bytes for the 12 bytes of 4 pixels, the rest is overwritten by the
+    // next pixels.
+    let mut i = 0;
+    while i + 6 <= pixels {
+        let source = _mm_loadu_si128(rgba.as_ptr().add(i * 4) as *const __m128i);
+        let packed = _mm_shuffle_epi8(source, shuffle);
+        _mm_storeu_si128(rgb.as_mut_ptr().add(i * 3) as *mut __m128i, packed);
+        i += 4;
+    }
+    i
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "ssse3")]
+unsafe fn rgb_to_luma_ssse3(pixels: &[u8], channels: usize, luma: &mut [u8]) -> usize {
+    let len = (pixels.len() / channels).min(luma.len());
+    // Spread a channel of 4 pixels into 32-bit lanes.
+    let spread = |c: i8| {
+        let s = channels as i8;
+        _mm_setr_epi8(
+            c,
+            -1,
+            -1,
+            -1,
+            s + c,
+            -1,
+            -1,
+            -1,
+            2 * s + c,
+            -1,
+            -1,
+            -1,
+            3 * s + c,
+            -1,
+            -1,
+            -1,
+        )
+    };
+    let (red, green, blue) = (spread(0), spread(1), spread(2));
+    let coefficients = (
+        _mm_set1_ps(SRGB_LUMA[0] as f32),
+        _mm_set1_ps(SRGB_LUMA[1] as f32),
+        _mm_set1_ps(SRGB_LUMA[2] as f32),
+    );
+    let divisor = _mm_set1_ps(SRGB_LUMA_DIV as f32);
+
+    // Each step reads 16 bytes, of which the 4 pixels use 12 or all.
+    let mut i = 0;
+    while i + 4 <= len && i * channels + 16 <= pixels.len() {
+        let source = _mm_loadu_si128(pixels.as_ptr().add(i * channels) as *const __m128i);
+        let r = _mm_cvtepi32_ps(_mm_shuffle_epi8(source, red));
+        let g = _mm_cvtepi32_ps(_mm_shuffle_epi8(source, green));
+        let b = _mm_cvtepi32_ps(_mm_shuffle_epi8(source, blue));
+        // The weighted sums are integers below 2^22, so they are exact. Their quotients are
+        // correctly rounded and lie further from the next integer than the precision of floats
+        // below 256, so truncating them matches the integer division.
+        let sum = _mm_add_ps(
+            _mm_add_ps(_mm_mul_ps(r, coefficients.0), _mm_mul_ps(g, coefficients.1)),
+            _mm_mul_ps(b, coefficients.2),
+        );
+        let l = _mm_cvttps_epi32(_mm_div_ps(sum, divisor));
+        let l = _mm_packs_epi32(l, l);
+        let l = _mm_packus_epi16(l, l);
+        let bytes = _mm_cvtsi128_si32(l).to_le_bytes();
+        luma[i..i + 4].copy_from_slice(&bytes);
+        i += 4;
+    }
+    i
+}
+
+#[cfg(target_arch = "x86_64")]
+#[target_feature(enable = "avx")]
+unsafe fn multiply_add_avx(sums: &mut [f32], samples: &[f32], weight: f32) -> usize {
+    let len = sums.len().min(samples.len());
+    let weight = _mm256_set1_ps(weight);
+
+    // Multiply and add separately like the portable loop, rather than fused, to round the same.
+    let mut i = 0;
+    while i + 8 <= len {
+        let sample = _mm256_loadu_ps(samples.as_ptr().add(i));
+        let sum = _mm256_loadu_ps(sums.as_ptr().add(i));
+        let sum = _mm256_add_ps(sum, _mm256_mul_ps(sample, weight));
+        _mm256_storeu_ps(sums.as_mut_ptr().add(i), sum);
+        i += 8;
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Bytes that cover all values and differ between neighboring samples.
+    fn samples(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 97 + i / 7) as u8).collect()
+    }
+
+    #[test]
+    fn rgb_and_rgba() {
+        for pixels in 0..40 {
+            let rgb = samples(pixels * 3);
+            let mut rgba = vec![0; pixels * 4];
+            rgb_to_rgba(&rgb, &mut rgba);
+            for (rgb, rgba) in rgb.chunks(3).zip(rgba.chunks(4)) {
+                assert_eq!(rgb, &rgba[..3]);
+                assert_eq!(rgba[3], 255);
+            }
+
+            let mut back = vec![0; pixels * 3];
+            rgba_to_rgb(&rgba, &mut back);
+            assert_eq!(back, rgb);
+        }
+    }
+
+    #[test]
+    fn luma() {
+        for &channels in &[3, 4] {
+            for pixels in 0..40 {
+                let source = samples(pixels * channels);
+                let mut luma = vec![0; pixels];
+                rgb_to_luma(&source, channels, &mut luma);
+                for (pixel, &l) in source.chunks(channels).zip(&luma) {
+                    let expected = (SRGB_LUMA[0] * u32::from(pixel[0])
+                        + SRGB_LUMA[1] * u32::from(pixel[1])
+                        + SRGB_LUMA[2] * u32::from(pixel[2]))
+                        / SRGB_LUMA_DIV;
+                    assert_eq!(u32::from(l), expected, "{:?}", pixel);
+                }
+            }
+        }
+
+        // Sums just below a multiple of the divisor are truncated.
+        let mut luma = [0; 8];
+        let white = [255; 32];
+        rgb_to_luma(&white, 4, &mut luma);
+        assert_eq!(luma, [255; 8]);
+    }
+
+    #[test]
+    fn multiply_adds() {
+        for len in 0..30 {
+            let samples: Vec<f32> = (0..len).map(|i| i as f32 * 0.37 - 3.0).collect();
+            let mut sums: Vec<f32> = (0..len).map(|i| i as f32 / 7.0).collect();
+            let expected: Vec<f32> = sums
+                .iter()
+                .zip(&samples)
+                .map(|(sum, sample)| sum + sample * 0.3)
+                .collect();
+            multiply_add(&mut sums, &samples, 0.3);
+            assert_eq!(sums, expected);
+        }
+    }
+}